@@ -1,14 +1,6 @@
 //! Future related code
 
-pub trait Future {
-    type Output;
-    fn poll(&mut self) -> PollState<Self::Output>;
-}
-
-pub enum PollState<T> {
-    Ready(T),
-    NotReady,
-}
+pub use async_core::{simple::Future, PollState};
 
 pub struct JoinAll<F: Future> {
     futures: Vec<(bool, F)>,
@@ -1,5 +1,8 @@
 //! initial simply experiment using Coroutines from `a-runtime`
-use reactor_executor::prelude::*;
+use async_core::{waker::Future, PollState};
+// the runtime, and its HTTP client, of the maintained `reactor-executor` library
+use reactor_executor::coroutine::{waker as runtime, Http};
+use runtime::Waker;
 
 fn main() {
     // initialise ExecutorCore and Runtime
@@ -15,9 +18,9 @@ fn main() {
 // coroutine fn async_main() {
 //     println!("Program starting");
 //
-//     let txt = http::Http::get("/600/HelloAsyncAwait").wait;
+//     let txt = Http::get("/600/HelloAsyncAwait").wait;
 //     println!("{txt}");
-//     let txt = http::Http::get("/400/HelloAsyncAwait").wait;
+//     let txt = Http::get("/400/HelloAsyncAwait").wait;
 //     println!("{txt}");
 
 // }
@@ -26,14 +29,14 @@ fn main() {
 // Into this:
 // =================================
 
-fn async_main() -> impl Future<Output = String> {
+fn async_main() -> impl Future<Output = String, Waker = Waker> {
     Coroutine0::new()
 }
 
 enum State0 {
     Start,
-    Wait1(Box<dyn Future<Output = String>>),
-    Wait2(Box<dyn Future<Output = String>>),
+    Wait1(Box<dyn Future<Output = String, Waker = Waker>>),
+    Wait2(Box<dyn Future<Output = String, Waker = Waker>>),
     Resolved,
 }
 
@@ -51,6 +54,7 @@ impl Coroutine0 {
 
 impl Future for Coroutine0 {
     type Output = String;
+    type Waker = Waker;
 
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
@@ -60,7 +64,7 @@ impl Future for Coroutine0 {
                     println!("Program starting");
 
                     // ---------------------------------
-                    let fut1 = Box::new(http::Http::get("/600/HelloAsyncAwait"));
+                    let fut1 = Box::new(Http::get("/600/HelloAsyncAwait"));
                     self.state = State0::Wait1(fut1);
                 }
 
@@ -71,7 +75,7 @@ impl Future for Coroutine0 {
                             println!("{txt}");
 
                             // ---------------------------------
-                            let fut2 = Box::new(http::Http::get("/400/HelloAsyncAwait"));
+                            let fut2 = Box::new(Http::get("/400/HelloAsyncAwait"));
                             self.state = State0::Wait2(fut2);
                         }
                         PollState::NotReady => break PollState::NotReady,
@@ -1,6 +1,9 @@
 use std::thread::Builder;
 
-use reactor_executor::prelude::*;
+use async_core::{waker::Future, PollState};
+// the runtime, and its HTTP client, of the maintained `reactor-executor` library
+use reactor_executor::coroutine::{waker as runtime, Http};
+use runtime::{Executor, Waker};
 
 fn main() {
     let mut executor = runtime::init();
@@ -39,13 +42,13 @@ fn main() {
 // Into this:
 // =================================
 
-fn request(i: usize) -> impl Future<Output = String> {
+fn request(i: usize) -> impl Future<Output = String, Waker = Waker> {
     Coroutine0::new(i)
 }
 
 enum State0 {
     Start(usize),
-    Wait1(Box<dyn Future<Output = String>>),
+    Wait1(Box<dyn Future<Output = String, Waker = Waker>>),
     Resolved,
 }
 
@@ -63,6 +66,7 @@ impl Coroutine0 {
 
 impl Future for Coroutine0 {
     type Output = String;
+    type Waker = Waker;
 
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
@@ -115,7 +119,7 @@ impl Future for Coroutine0 {
 // Into this:
 // =================================
 
-fn async_main() -> impl Future<Output = String> {
+fn async_main() -> impl Future<Output = String, Waker = Waker> {
     Coroutine1::new()
 }
 
@@ -138,6 +142,7 @@ impl Coroutine1 {
 
 impl Future for Coroutine1 {
     type Output = String;
+    type Waker = Waker;
 
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
@@ -1,9 +0,0 @@
-mod future;
-mod http;
-pub mod runtime;
-
-pub mod prelude {
-    pub use crate::future::{Future, PollState};
-    pub use crate::http::Http;
-    pub use crate::runtime::{self, Executor, Waker};
-}
@@ -0,0 +1,52 @@
+//! The `Future` trait and `PollState` shared by the binaries and the runtime of this
+//! repository, rather than each defining their own.
+//!
+//! The trait went through a few variants while working through the book, each behind a
+//! feature:
+//! - [`simple`]: polled through `&mut self`, without a waker
+//! - [`waker`]: polled through `&mut self`, with a waker
+//! - [`pin`]: polled through `Pin<&mut Self>`, with a waker
+//!
+//! Every binary brings its own waker, so the traits that take one leave its type to the
+//! implementor, see [`waker::Future::Waker`]. Futures that only wrap others, e.g. a `JoinAll`,
+//! take the type of the futures they wrap, and so work with any waker.
+//!
+//! With the `task` feature, [`task::Waker`] is a waker that works with any executor, which
+//! decides what waking does when creating it.
+
+use std::fmt;
+
+#[cfg(feature = "pin")]
+pub mod pin;
+#[cfg(feature = "simple")]
+pub mod simple;
+#[cfg(feature = "task")]
+pub mod task;
+#[cfg(feature = "waker")]
+pub mod waker;
+
+/// PollState is an enum that represents the state of a future.
+/// It is either Ready or NotReady. The value returned when ready is of type T
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollState<T> {
+    Ready(T),
+    NotReady,
+}
+
+/// How far a future that is not ready yet got, e.g. bytes of a response read so far, as
+/// reported by [`pin::Future::progress`]. What is counted is up to the future.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub done: usize,
+    /// What `done` counts up to, if known up front
+    pub total: Option<usize>,
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.total {
+            Some(total) => write!(f, "{}/{total}", self.done),
+            None => write!(f, "{}", self.done),
+        }
+    }
+}
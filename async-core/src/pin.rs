@@ -0,0 +1,115 @@
+//! Futures that are polled with a waker through a `Pin<&mut Self>`, so they may hold
+//! references into themselves, e.g. coroutines that keep their variables across waits.
+use std::{ops::DerefMut, pin::Pin};
+
+use crate::{PollState, Progress};
+
+/// Represents some operation that will complete in the future
+/// and return a value of type `Future::Output`.
+pub trait Future {
+    type Output;
+    /// Type of the waker the future is polled with, provided by the runtime that polls it
+    type Waker: ?Sized;
+    fn poll(self: Pin<&mut Self>, waker: &Self::Waker) -> PollState<Self::Output>;
+
+    /// Progress made so far, while not ready yet. For debugging only, so futures without a
+    /// meaningful measure of progress do not have to report any.
+    fn progress(&self) -> Option<Progress> {
+        None
+    }
+}
+
+/// A pinned pointer to a future is a future too, so a `Pin<Box<dyn Future>>` can be waited on
+/// like any other.
+impl<P> Future for Pin<P>
+where
+    P: DerefMut + Unpin,
+    P::Target: Future,
+{
+    type Output = <P::Target as Future>::Output;
+    type Waker = <P::Target as Future>::Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &Self::Waker) -> PollState<Self::Output> {
+        self.get_mut().as_mut().poll(waker)
+    }
+
+    fn progress(&self) -> Option<Progress> {
+        self.as_ref().get_ref().progress()
+    }
+}
+
+/// Same for a box, as long as the future in it does not need to stay pinned.
+impl<F: Future + Unpin + ?Sized> Future for Box<F> {
+    type Output = F::Output;
+    type Waker = F::Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &Self::Waker) -> PollState<Self::Output> {
+        Pin::new(&mut **self.get_mut()).poll(waker)
+    }
+
+    fn progress(&self) -> Option<Progress> {
+        (**self).progress()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// Ready on the second poll, counted by the "waker" it is polled with
+    struct Twice;
+
+    impl Future for Twice {
+        type Output = &'static str;
+        type Waker = Cell<usize>;
+
+        fn poll(self: Pin<&mut Self>, polls: &Cell<usize>) -> PollState<Self::Output> {
+            polls.set(polls.get() + 1);
+            match polls.get() {
+                2 => PollState::Ready("done"),
+                _ => PollState::NotReady,
+            }
+        }
+    }
+
+    /// Reports how many of the bytes it was asked for it has, without ever resolving
+    struct Partial(usize);
+
+    impl Future for Partial {
+        type Output = ();
+        type Waker = ();
+
+        fn poll(self: Pin<&mut Self>, _: &()) -> PollState<()> {
+            PollState::NotReady
+        }
+
+        fn progress(&self) -> Option<Progress> {
+            Some(Progress {
+                done: self.0 / 2,
+                total: Some(self.0),
+            })
+        }
+    }
+
+    #[test]
+    fn boxed_futures_are_polled_with_the_waker_of_the_inner_one() {
+        let polls = Cell::new(0);
+        let mut future: Pin<Box<dyn Future<Output = &str, Waker = Cell<usize>>>> = Box::pin(Twice);
+
+        assert_eq!(future.as_mut().poll(&polls), PollState::NotReady);
+        assert_eq!(Pin::new(&mut future).poll(&polls), PollState::Ready("done"));
+        assert_eq!(polls.get(), 2);
+        assert_eq!(future.progress(), None);
+    }
+
+    #[test]
+    fn progress_is_reported_through_boxes_and_pins() {
+        let future: Pin<Box<dyn Future<Output = (), Waker = ()>>> = Box::pin(Partial(10));
+        let progress = future.progress().unwrap();
+
+        assert_eq!(progress.to_string(), "5/10");
+        assert_eq!(Box::new(Partial(4)).progress().unwrap().to_string(), "2/4");
+    }
+}
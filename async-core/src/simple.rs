@@ -0,0 +1,19 @@
+//! Futures that are polled without a waker, as in the first examples of the book: whoever
+//! polls them has no way to tell when to poll again, so it keeps polling.
+use crate::PollState;
+
+/// Represents some operation that will complete in the future
+/// and return a value of type `Future::Output`.
+pub trait Future {
+    type Output;
+    fn poll(&mut self) -> PollState<Self::Output>;
+}
+
+/// A boxed future is a future too, so it can be waited on like any other.
+impl<F: Future + ?Sized> Future for Box<F> {
+    type Output = F::Output;
+
+    fn poll(&mut self) -> PollState<Self::Output> {
+        (**self).poll()
+    }
+}
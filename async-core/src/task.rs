@@ -0,0 +1,333 @@
+//! A waker that does not know about the executor it wakes, a small version of std's
+//! `RawWaker`: a data pointer, and a table of functions that know what it points at.
+//!
+//! Leaf futures and reactors only ever clone, store and wake a [`Waker`]. What waking does,
+//! e.g. queueing a task and unparking the executor's thread, is up to the executor that made
+//! it, so a different executor can be dropped in without changing any of them.
+//!
+//! Most executors do not need the raw parts, and implement [`Wake`] instead:
+//!
+//! ```
+//! use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+//! use async_core::task::{Wake, Waker};
+//!
+//! struct CountWakes(AtomicUsize);
+//!
+//! impl Wake for CountWakes {
+//!     fn wake(&self) {
+//!         self.0.fetch_add(1, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let count = Arc::new(CountWakes(AtomicUsize::new(0)));
+//! let waker = Waker::from(count.clone());
+//! waker.clone().wake();
+//! assert_eq!(count.0.load(Ordering::Relaxed), 1);
+//! ```
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Data pointer of a waker, along with the functions that operate on it.
+#[derive(Debug)]
+pub struct RawWaker {
+    data: *const (),
+    vtable: &'static RawWakerVTable,
+}
+
+impl RawWaker {
+    pub const fn new(data: *const (), vtable: &'static RawWakerVTable) -> Self {
+        Self { data, vtable }
+    }
+}
+
+/// Functions called with the data pointer of a [`RawWaker`].
+#[derive(Debug)]
+pub struct RawWakerVTable {
+    /// Called when the waker is cloned, returning a waker that wakes the same task
+    clone: unsafe fn(*const ()) -> RawWaker,
+    /// Called when the waker is woken, without consuming it
+    wake: unsafe fn(*const ()),
+    /// Called when the waker is dropped
+    drop: unsafe fn(*const ()),
+}
+
+impl RawWakerVTable {
+    pub const fn new(
+        clone: unsafe fn(*const ()) -> RawWaker,
+        wake: unsafe fn(*const ()),
+        drop: unsafe fn(*const ()),
+    ) -> Self {
+        Self { clone, wake, drop }
+    }
+}
+
+/// Handle for waking up a task, passed to futures when they are polled.
+pub struct Waker {
+    raw: RawWaker,
+}
+
+/// Wakers are sent to the thread of the reactor, see the safety requirements of
+/// [`Waker::from_raw`].
+unsafe impl Send for Waker {}
+unsafe impl Sync for Waker {}
+
+impl Waker {
+    /// Create a waker from its raw parts.
+    ///
+    /// # Safety
+    ///
+    /// The functions of the vtable have to be safe to call with `data` from any thread, and
+    /// `clone` has to return a raw waker upholding the same, see std's `RawWaker`.
+    pub const unsafe fn from_raw(raw: RawWaker) -> Self {
+        Self { raw }
+    }
+
+    /// Wake up the task this waker belongs to.
+    pub fn wake(&self) {
+        // SAFETY: upheld by the creator of the waker, see `from_raw`
+        unsafe { (self.raw.vtable.wake)(self.raw.data) }
+    }
+
+    /// True if both wakers wake the same task, as long as they were made the same way.
+    pub fn will_wake(&self, other: &Waker) -> bool {
+        self.raw.data == other.raw.data && std::ptr::eq(self.raw.vtable, other.raw.vtable)
+    }
+}
+
+impl Clone for Waker {
+    fn clone(&self) -> Self {
+        // SAFETY: upheld by the creator of the waker, see `from_raw`
+        Self {
+            raw: unsafe { (self.raw.vtable.clone)(self.raw.data) },
+        }
+    }
+}
+
+impl Drop for Waker {
+    fn drop(&mut self) {
+        // SAFETY: upheld by the creator of the waker, see `from_raw`
+        unsafe { (self.raw.vtable.drop)(self.raw.data) }
+    }
+}
+
+impl fmt::Debug for Waker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Waker")
+            .field("data", &self.raw.data)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What an executor does when a task is woken, turned into a [`Waker`] via `From<Arc<W>>`.
+pub trait Wake: Send + Sync + 'static {
+    fn wake(&self);
+}
+
+impl<W: Wake> From<Arc<W>> for Waker {
+    fn from(wake: Arc<W>) -> Self {
+        // SAFETY: the data is an `Arc<W>`, which the vtable of `W` expects, and `W` is
+        // `Send + Sync`
+        unsafe { Waker::from_raw(RawWaker::new(Arc::into_raw(wake).cast(), vtable::<W>())) }
+    }
+}
+
+/// The functions of a [`Waker`] made from an `Arc<W>`, one table per type.
+fn vtable<W: Wake>() -> &'static RawWakerVTable {
+    unsafe fn clone<W: Wake>(data: *const ()) -> RawWaker {
+        Arc::increment_strong_count(data.cast::<W>());
+        RawWaker::new(data, vtable::<W>())
+    }
+
+    unsafe fn wake<W: Wake>(data: *const ()) {
+        (*data.cast::<W>()).wake();
+    }
+
+    unsafe fn drop<W: Wake>(data: *const ()) {
+        Arc::decrement_strong_count(data.cast::<W>());
+    }
+
+    struct Table<W>(W);
+
+    impl<W: Wake> Table<W> {
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(clone::<W>, wake::<W>, drop::<W>);
+    }
+
+    &Table::<W>::VTABLE
+}
+
+/// Asks tasks to stop early. Clones share the same state, so cancelling one cancels all.
+///
+/// Cancellation is cooperative: nothing is stopped by force. Tasks check
+/// [`CancellationToken::is_cancelled`], or wait on [`CancellationToken::cancelled`], and
+/// executors wake the tasks they were given a token for, see
+/// [`CancellationToken::wake_on_cancel`].
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<TokenState>,
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    /// Woken once cancelled, then dropped
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the token and its clones, waking everyone waiting on it. Cancelling again does
+    /// nothing.
+    pub fn cancel(&self) {
+        let wakers = {
+            let mut wakers = self.inner.wakers.lock().unwrap();
+            // set while holding the lock, so `wake_on_cancel` either sees it or its waker is
+            // taken here
+            if self.inner.cancelled.swap(true, Ordering::AcqRel) {
+                return;
+            }
+            std::mem::take(&mut *wakers)
+        };
+
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Wake `waker` once the token is cancelled, right away if it is already. A waker that
+    /// wakes the same task as one given before is only kept once.
+    pub fn wake_on_cancel(&self, waker: &Waker) {
+        let mut wakers = self.inner.wakers.lock().unwrap();
+        if self.is_cancelled() {
+            drop(wakers);
+            waker.wake();
+        } else if !wakers.iter().any(|kept| kept.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// Returns a future that resolves once the token is cancelled.
+    #[cfg(feature = "pin")]
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            token: self.clone(),
+        }
+    }
+}
+
+/// Future returned by [`CancellationToken::cancelled`].
+#[cfg(feature = "pin")]
+#[must_use = "futures do nothing unless polled"]
+pub struct Cancelled {
+    token: CancellationToken,
+}
+
+#[cfg(feature = "pin")]
+impl crate::pin::Future for Cancelled {
+    type Output = ();
+    type Waker = Waker;
+
+    fn poll(self: std::pin::Pin<&mut Self>, waker: &Waker) -> crate::PollState<()> {
+        if self.token.is_cancelled() {
+            return crate::PollState::Ready(());
+        }
+        self.token.wake_on_cancel(waker);
+        crate::PollState::NotReady
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the ids of woken tasks, like the ready queue of an executor
+    struct Queue {
+        id: usize,
+        woken: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Wake for Queue {
+        fn wake(&self) {
+            self.woken.lock().unwrap().push(self.id);
+        }
+    }
+
+    #[test]
+    fn clones_wake_the_same_task_and_are_released() {
+        let woken = Arc::new(Mutex::new(Vec::new()));
+        let queue = Arc::new(Queue {
+            id: 7,
+            woken: woken.clone(),
+        });
+        let waker = Waker::from(queue.clone());
+
+        let clone = waker.clone();
+        assert!(clone.will_wake(&waker));
+        assert_eq!(Arc::strong_count(&queue), 3);
+
+        std::thread::spawn(move || clone.wake()).join().unwrap();
+        waker.wake();
+        assert_eq!(*woken.lock().unwrap(), [7, 7]);
+
+        drop(waker);
+        assert_eq!(Arc::strong_count(&queue), 1);
+    }
+
+    #[test]
+    fn cancelling_wakes_each_task_waiting_once() {
+        let woken = Arc::new(Mutex::new(Vec::new()));
+        let waker = |id| {
+            Waker::from(Arc::new(Queue {
+                id,
+                woken: woken.clone(),
+            }))
+        };
+        let token = CancellationToken::new();
+        let (first, second) = (waker(1), waker(2));
+
+        token.wake_on_cancel(&first);
+        token.wake_on_cancel(&first.clone());
+        token.clone().wake_on_cancel(&second);
+        assert!(!token.is_cancelled());
+        assert!(woken.lock().unwrap().is_empty());
+
+        token.clone().cancel();
+        token.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(*woken.lock().unwrap(), [1, 2]);
+
+        // too late to wait, so woken right away
+        token.wake_on_cancel(&waker(3));
+        assert_eq!(*woken.lock().unwrap(), [1, 2, 3]);
+    }
+
+    #[cfg(feature = "pin")]
+    #[test]
+    fn cancelled_resolves_once_cancelled() {
+        use crate::{pin::Future, PollState};
+
+        let woken = Arc::new(Mutex::new(Vec::new()));
+        let waker = Waker::from(Arc::new(Queue {
+            id: 1,
+            woken: woken.clone(),
+        }));
+        let token = CancellationToken::new();
+        let mut cancelled = Box::pin(token.cancelled());
+
+        assert_eq!(cancelled.as_mut().poll(&waker), PollState::NotReady);
+        token.cancel();
+        assert_eq!(*woken.lock().unwrap(), [1]);
+        assert_eq!(cancelled.as_mut().poll(&waker), PollState::Ready(()));
+    }
+}
@@ -0,0 +1,21 @@
+//! Futures that are polled with a waker, which they wake once they can make progress.
+use crate::PollState;
+
+/// Represents some operation that will complete in the future
+/// and return a value of type `Future::Output`.
+pub trait Future {
+    type Output;
+    /// Type of the waker the future is polled with, provided by the runtime that polls it
+    type Waker: ?Sized;
+    fn poll(&mut self, waker: &Self::Waker) -> PollState<Self::Output>;
+}
+
+/// A boxed future is a future too, so it can be waited on like any other.
+impl<F: Future + ?Sized> Future for Box<F> {
+    type Output = F::Output;
+    type Waker = F::Waker;
+
+    fn poll(&mut self, waker: &Self::Waker) -> PollState<Self::Output> {
+        (**self).poll(waker)
+    }
+}
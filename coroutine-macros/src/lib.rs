@@ -0,0 +1,1344 @@
+//! `#[coroutine]`, rewriting a function with `wait` points into a state machine at compile
+//! time. It stands in for the `corofy` binary the build script of `stackless-coroutine` runs,
+//! and generates the same `State`/`Stack`/`Coroutine` types the hand edited copies of its
+//! output use.
+//!
+//! The attribute is used by its path, as a bare `#[coroutine]` is taken to be the unstable
+//! builtin attribute of the same name.
+//!
+//! ```ignore
+//! #[coroutine_macros::coroutine]
+//! fn async_main() {
+//!     let mut counter: usize = 0;
+//!
+//!     let txt = Http::get("/600/HelloAsyncAwait").wait;
+//!     counter += 1;
+//!     println!("{txt}, {counter}");
+//! }
+//! ```
+//!
+//! Like with corofy:
+//! - a wait is a statement of its own, `let txt = fut.wait;` or `fut.wait;`. `wait!(fut)` may
+//!   be used in place of `fut.wait`.
+//! - the futures waited on resolve to a `String`, as does the coroutine itself.
+//! - the binary provides `crate::future::{Future, PollState}` and `crate::runtime::Waker`, with
+//!   futures polled through a `Pin<&mut Self>`, i.e. the `pin` variant of `async-core`.
+//!   `#[coroutine(waker)]` generates a coroutine of the `waker` variant instead, polled through
+//!   `&mut self`.
+//!
+//! Unlike corofy, waits may also be within the body of a `loop`, `while` or `for` loop, or of
+//! an `if`/`else`, at any depth. Each of those gets states of its own, e.g. for the top of the
+//! loop, and the iterator of a `for` loop is kept on the coroutine's stack along with its
+//! variable. `break` and `continue` of such a loop are statements of their own too, without a
+//! label.
+//!
+//! ```ignore
+//! #[coroutine_macros::coroutine]
+//! fn async_main() {
+//!     let requests: RangeInclusive<usize> = 0..=5;
+//!     for i in requests {
+//!         if i % 2 == 0 {
+//!             let txt = Http::get(&format!("/{i}00/Hello")).wait;
+//!             println!("{txt}");
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Also unlike corofy, the coroutine checks `crate::runtime::is_cancelled()` on every state
+//! transition. Once it returns true, the coroutine drops whatever it waits on and resolves
+//! with an empty String, so a task whose cancellation token was cancelled stops early.
+//!
+//! Variables used across a wait are kept on the coroutine's stack, which needs their type, so
+//! they must be declared with one, e.g. `let counter: usize = 0;`. The parameters of the
+//! function are kept there too, as is the iterable of a `for` loop with a wait, which is why it
+//! is declared first, as `requests` is above. References are kept as raw pointers, so `let
+//! writer: &mut String = &mut buffer;` stays usable for as long as `buffer` is kept. Within
+//! macros such as `println!`, a kept variable is a `&mut` to its value.
+//!
+//! Those raw pointers point into the coroutine itself, so the generated coroutines are
+//! `!Unpin`, with futures they wait on pinned on the heap. Once pinned and polled, a coroutine
+//! never moves, which keeps its references valid. A coroutine of the `waker` variant may move
+//! between polls, so it can not keep references.
+use std::collections::HashSet;
+
+use proc_macro2::{Span, TokenStream, TokenTree};
+use quote::{format_ident, quote};
+use syn::{
+    parse_quote,
+    spanned::Spanned,
+    visit::{self, Visit},
+    visit_mut::{self, VisitMut},
+    Expr, FieldValue, FnArg, Ident, ItemFn, Label, Local, Macro, Member, Pat, Stmt, Type,
+};
+
+/// Rewrites the function into a state machine, see the crate docs.
+#[proc_macro_attribute]
+pub fn coroutine(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    expand(attr.into(), item.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// A variable kept on the coroutine's stack
+struct Var {
+    ident: Ident,
+    ty: Type,
+}
+
+impl Var {
+    /// Type of its field on the stack, references are kept as raw pointers
+    fn field_type(&self) -> TokenStream {
+        match &self.ty {
+            Type::Reference(r) if r.mutability.is_some() => {
+                let elem = &r.elem;
+                quote!(*mut #elem)
+            }
+            Type::Reference(r) => {
+                let elem = &r.elem;
+                quote!(*const #elem)
+            }
+            ty => quote!(#ty),
+        }
+    }
+
+    fn is_reference(&self) -> bool {
+        matches!(self.ty, Type::Reference(_))
+    }
+
+    /// Bind the variable to its value on the stack, at the start of a block
+    fn restore(&self) -> TokenStream {
+        let Var { ident, ty } = self;
+        match ty {
+            Type::Reference(r) if r.mutability.is_some() => {
+                quote!(let #ident: #ty = unsafe { &mut *coroutine.stack.#ident.unwrap() };)
+            }
+            Type::Reference(_) => {
+                quote!(let #ident: #ty = unsafe { &*coroutine.stack.#ident.unwrap() };)
+            }
+            _ => quote!(let #ident = coroutine.stack.#ident.as_mut().unwrap();),
+        }
+    }
+}
+
+/// Code run in one go, between two transitions of the state machine. Each block is a state.
+struct Block {
+    /// Binds the result of the wait the block resumes from, None for the other blocks
+    binding: Option<Pat>,
+    stmts: Vec<Stmt>,
+    /// Kept variables in scope at the start of the block, as indices into `Lower::vars`
+    scope: Vec<usize>,
+    end: End,
+}
+
+/// Where a block continues once its statements ran
+enum End {
+    /// Wait on the future, then resume with the block
+    Wait(Expr, usize),
+    /// Continue with the block, e.g. the top of a loop
+    Goto(usize),
+    /// Continue with the first block if the condition holds, with the second one otherwise
+    If(Expr, usize, usize),
+    /// Continue with `body` for the next item of the iterator kept in `iter`, binding it to
+    /// `var`, or with `exit` once there is none
+    Next {
+        iter: usize,
+        var: Option<usize>,
+        body: usize,
+        exit: usize,
+    },
+    /// Resolve the coroutine
+    Return,
+}
+
+/// A loop with a wait, which `break` and `continue` within it jump out of
+struct Loop {
+    /// Block at the top of the loop
+    head: usize,
+    /// Block after the loop, made once needed as a `loop` may never be left
+    exit: Option<usize>,
+    /// Kept variables in scope after the loop
+    scope: Vec<usize>,
+}
+
+/// Lowers the body of the function into blocks
+#[derive(Default)]
+struct Lower {
+    blocks: Vec<Block>,
+    vars: Vec<Var>,
+    /// Kept variables in scope where lowering is at
+    scope: Vec<usize>,
+    /// Loops with a wait lowering is within, innermost last
+    loops: Vec<Loop>,
+    /// Number of `for` loops lowered, naming the fields their iterators are kept in
+    iterators: usize,
+}
+
+fn expand(attr: TokenStream, item: TokenStream) -> syn::Result<TokenStream> {
+    let waker = match syn::parse2::<Option<Ident>>(attr.clone()) {
+        Ok(None) => false,
+        Ok(Some(ident)) if ident == "waker" => true,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "#[coroutine] takes no arguments, or `waker` for the `waker` variant",
+            ))
+        }
+    };
+
+    let func: ItemFn = syn::parse2(item)?;
+    let sig = &func.sig;
+    if !sig.generics.params.is_empty() || sig.asyncness.is_some() {
+        return Err(syn::Error::new_spanned(
+            sig,
+            "coroutines can not be generic or async",
+        ));
+    }
+    if let syn::ReturnType::Type(..) = sig.output {
+        return Err(syn::Error::new_spanned(
+            &sig.output,
+            "coroutines resolve to a String, leave out the return type",
+        ));
+    }
+
+    let mut lower = Lower::default();
+    for arg in &sig.inputs {
+        let FnArg::Typed(arg) = arg else {
+            return Err(syn::Error::new_spanned(arg, "coroutines can not take self"));
+        };
+        let Pat::Ident(pat) = &*arg.pat else {
+            return Err(syn::Error::new_spanned(
+                &arg.pat,
+                "parameters of coroutines must be plain identifiers",
+            ));
+        };
+        if let Type::Reference(_) = &*arg.ty {
+            return Err(syn::Error::new_spanned(
+                &arg.ty,
+                "parameters of coroutines must be owned, they outlive the call",
+            ));
+        }
+        lower.keep(Var {
+            ident: pat.ident.clone(),
+            ty: (*arg.ty).clone(),
+        });
+    }
+    let params: Vec<_> = lower.vars.iter().map(|var| var.ident.clone()).collect();
+
+    let start = lower.block(None);
+    if let Some(last) = lower.lower_stmts(start, func.block.stmts.clone())? {
+        lower.blocks[last].end = End::Return;
+    }
+    let Lower { blocks, vars, .. } = lower;
+
+    if waker {
+        if let Some(var) = vars.iter().find(|var| var.is_reference()) {
+            return Err(syn::Error::new_spanned(
+                &var.ident,
+                "references kept across a wait point into the coroutine, which only a pinned \
+                 coroutine keeps in place, leave out `waker`",
+            ));
+        }
+    }
+    check_unkept(&blocks, &vars)?;
+
+    // blocks resumed from a wait hold the future they wait on, the others are jumped to
+    let (mut waits, mut jumps) = (0, 0);
+    let states: Vec<Ident> = blocks
+        .iter()
+        .enumerate()
+        .map(|(index, block)| match (index, &block.binding) {
+            (0, _) => format_ident!("Start"),
+            (_, Some(_)) => {
+                waits += 1;
+                format_ident!("Wait{waits}")
+            }
+            (_, None) => {
+                jumps += 1;
+                format_ident!("Block{jumps}")
+            }
+        })
+        .collect();
+
+    let future_type = quote! {
+        dyn crate::future::Future<Output = String, Waker = crate::runtime::Waker>
+    };
+    let (boxed_type, boxed, poll_future) = if waker {
+        (
+            quote!(Box<#future_type>),
+            quote!(Box::new),
+            quote!(future.poll(waker)),
+        )
+    } else {
+        (
+            quote!(Pin<Box<#future_type>>),
+            quote!(Box::pin),
+            quote!(future.as_mut().poll(waker)),
+        )
+    };
+
+    let variants: Vec<_> = blocks
+        .iter()
+        .zip(&states)
+        .skip(1)
+        .map(|(block, state)| match block.binding {
+            Some(_) => quote!(#state(#boxed_type),),
+            None => quote!(#state,),
+        })
+        .collect();
+    let arms = blocks
+        .into_iter()
+        .zip(&states)
+        .map(|(block, state)| match block.binding {
+            Some(_) => {
+                let body = block.generate(&vars, &states, &boxed);
+                quote! {
+                    State::#state(ref mut future) => match #poll_future {
+                        crate::future::PollState::Ready(__ready) => { #body }
+                        crate::future::PollState::NotReady => {
+                            break crate::future::PollState::NotReady
+                        }
+                    },
+                }
+            }
+            None => {
+                let body = block.generate(&vars, &states, &boxed);
+                quote!(State::#state => { #body })
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let fields = vars.iter().map(|var| {
+        let (ident, ty) = (&var.ident, var.field_type());
+        quote!(#ident: Option<#ty>,)
+    });
+    let (attrs, vis, name, inputs) = (&func.attrs, &func.vis, &sig.ident, &sig.inputs);
+
+    let (imports, pin_field, pin_value, receiver, coroutine) = if waker {
+        (
+            quote!(),
+            quote!(),
+            quote!(),
+            quote!(&mut self),
+            quote!(let coroutine = self;),
+        )
+    } else {
+        (
+            quote!(
+                use std::{marker::PhantomPinned, pin::Pin};
+            ),
+            quote! {
+                // references on the stack point into the coroutine itself, so it must not
+                // move once polled
+                _pin: PhantomPinned,
+            },
+            quote!(_pin: PhantomPinned,),
+            quote!(self: Pin<&mut Self>),
+            quote! {
+                // Safety: the coroutine is only ever accessed through this reference, and
+                // neither it nor anything on its stack is moved out of it
+                let coroutine = unsafe { self.get_unchecked_mut() };
+            },
+        )
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis fn #name(#inputs) -> impl crate::future::Future<Output = String, Waker = crate::runtime::Waker> {
+            #imports
+
+            /// Holds the various states that the coroutine will transition between
+            enum State {
+                Start,
+                #(#variants)*
+                Resolved,
+            }
+
+            /// Variables kept across waits
+            #[derive(Default)]
+            struct Stack {
+                #(#fields)*
+            }
+
+            struct Coroutine {
+                state: State,
+                stack: Stack,
+                #pin_field
+            }
+
+            impl crate::future::Future for Coroutine {
+                type Output = String;
+                type Waker = crate::runtime::Waker;
+
+                #[allow(unused_variables, unused_mut)]
+                fn poll(
+                    #receiver,
+                    waker: &crate::runtime::Waker,
+                ) -> crate::future::PollState<Self::Output> {
+                    #coroutine
+                    loop {
+                        // checked on every transition, so a cancelled task stops at the next
+                        // wait, dropping the future it waits on
+                        if crate::runtime::is_cancelled() {
+                            coroutine.state = State::Resolved;
+                            break crate::future::PollState::Ready(String::new());
+                        }
+                        match coroutine.state {
+                            #(#arms)*
+                            State::Resolved => panic!("Polled a resolved future"),
+                        }
+                    }
+                }
+            }
+
+            Coroutine {
+                state: State::Start,
+                stack: Stack {
+                    #(#params: Some(#params),)*
+                    ..Stack::default()
+                },
+                #pin_value
+            }
+        }
+    })
+}
+
+impl Lower {
+    /// A new block, with the kept variables in scope now
+    fn block(&mut self, binding: Option<Pat>) -> usize {
+        let scope = self.scope.clone();
+        self.block_in(binding, scope)
+    }
+
+    fn block_in(&mut self, binding: Option<Pat>, scope: Vec<usize>) -> usize {
+        self.blocks.push(Block {
+            binding,
+            stmts: Vec::new(),
+            scope,
+            end: End::Return,
+        });
+        self.blocks.len() - 1
+    }
+
+    /// Keep `var` on the stack, in scope from here on
+    fn keep(&mut self, var: Var) -> usize {
+        self.vars.push(var);
+        self.scope.push(self.vars.len() - 1);
+        self.vars.len() - 1
+    }
+
+    /// Keep the variable declared by `local` if it has a type. Variables are kept in a field
+    /// of their name, so none may shadow a kept one.
+    fn declare(&mut self, local: &Local) -> syn::Result<()> {
+        let ident = match &local.pat {
+            Pat::Type(typed) => match &*typed.pat {
+                Pat::Ident(pat) => &pat.ident,
+                _ => return Ok(()),
+            },
+            Pat::Ident(pat) => &pat.ident,
+            _ => return Ok(()),
+        };
+        if self.vars.iter().any(|var| &var.ident == ident) {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "variables kept across a wait can not be shadowed",
+            ));
+        }
+        if let Some(var) = kept(local) {
+            self.keep(var);
+        }
+        Ok(())
+    }
+
+    /// Run `lower` with the scope it starts with restored afterwards
+    fn scoped<T>(&mut self, lower: impl FnOnce(&mut Self) -> T) -> T {
+        let scope = self.scope.clone();
+        let result = lower(self);
+        self.scope = scope;
+        result
+    }
+
+    /// Lower `stmts`, starting out in block `current`. Returns the block control continues in
+    /// afterwards, None if it never gets there, e.g. past a `break`.
+    fn lower_stmts(&mut self, mut current: usize, stmts: Vec<Stmt>) -> syn::Result<Option<usize>> {
+        for stmt in stmts {
+            if let Some((binding, future)) = wait_stmt(&stmt)? {
+                no_wait(|find| find.visit_expr(&future))?;
+                let next = self.block(Some(binding.clone()));
+                self.declare(&binding_local(binding))?;
+                self.blocks[current].end = End::Wait(future, next);
+                current = next;
+                continue;
+            }
+
+            let (wait, jump) = self.transitions(&stmt);
+            if wait.is_none() && jump.is_none() {
+                if let Stmt::Local(local) = &stmt {
+                    self.declare(local)?;
+                }
+                self.blocks[current].stmts.push(stmt);
+                continue;
+            }
+
+            let Stmt::Expr(expr, _) = stmt else {
+                return Err(unsupported(wait, jump));
+            };
+            match self.lower_expr(current, expr, wait, jump)? {
+                Some(next) => current = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(current))
+    }
+
+    /// Lower a statement with a wait or a jump out of a loop with a wait within, which
+    /// `wait` and `jump` point at.
+    fn lower_expr(
+        &mut self,
+        current: usize,
+        expr: Expr,
+        wait: Option<Span>,
+        jump: Option<Span>,
+    ) -> syn::Result<Option<usize>> {
+        match expr {
+            Expr::If(expr) => {
+                let cond = condition(*expr.cond)?;
+                let then = self.block(None);
+                let then_end =
+                    self.scoped(|this| this.lower_stmts(then, expr.then_branch.stmts))?;
+
+                let (otherwise, else_end) = match expr.else_branch {
+                    Some((_, branch)) => {
+                        let otherwise = self.block(None);
+                        let stmts = vec![Stmt::Expr(*branch, None)];
+                        let end = self.scoped(|this| this.lower_stmts(otherwise, stmts))?;
+                        (Some(otherwise), end)
+                    }
+                    None => (None, None),
+                };
+
+                // the block after the `if`, unless neither branch gets there
+                let join = (otherwise.is_none() || then_end.is_some() || else_end.is_some())
+                    .then(|| self.block(None));
+                if let Some(join) = join {
+                    for end in [then_end, else_end].into_iter().flatten() {
+                        self.blocks[end].end = End::Goto(join);
+                    }
+                }
+                let otherwise = otherwise.or(join).unwrap();
+                self.blocks[current].end = End::If(cond, then, otherwise);
+                Ok(join)
+            }
+            Expr::Loop(expr) => {
+                no_label(expr.label.as_ref())?;
+                let head = self.block(None);
+                self.blocks[current].end = End::Goto(head);
+
+                let end =
+                    self.in_loop(head, None, |this| this.lower_stmts(head, expr.body.stmts))?;
+                let (end, exit) = end;
+                if let Some(end) = end {
+                    self.blocks[end].end = End::Goto(head);
+                }
+                Ok(exit)
+            }
+            Expr::While(expr) => {
+                no_label(expr.label.as_ref())?;
+                let cond = condition(*expr.cond)?;
+                let head = self.block(None);
+                let body = self.block(None);
+                let exit = self.block(None);
+                self.blocks[current].end = End::Goto(head);
+                self.blocks[head].end = End::If(cond, body, exit);
+
+                let (end, _) = self.in_loop(head, Some(exit), |this| {
+                    this.lower_stmts(body, expr.body.stmts)
+                })?;
+                if let Some(end) = end {
+                    self.blocks[end].end = End::Goto(head);
+                }
+                Ok(Some(exit))
+            }
+            Expr::ForLoop(expr) => {
+                no_label(expr.label.as_ref())?;
+                let iterable = self.iterable(&expr.expr)?;
+
+                // the iterable is moved into the iterator, kept on the stack for the loop
+                let ty = &self.vars[iterable].ty;
+                let iter = format_ident!("__iter{}", self.iterators);
+                self.iterators += 1;
+                let item: Type = parse_quote!(<#ty as IntoIterator>::Item);
+                let iterator: Type = parse_quote!(<#ty as IntoIterator>::IntoIter);
+                let source = &self.vars[iterable].ident;
+                self.blocks[current].stmts.push(parse_quote! {
+                    coroutine.stack.#iter = Some(IntoIterator::into_iter(
+                        coroutine.stack.#source.take().unwrap(),
+                    ));
+                });
+                self.scope.retain(|var| *var != iterable);
+                let iter = self.keep(Var {
+                    ident: iter,
+                    ty: iterator,
+                });
+                self.scope.pop();
+
+                let head = self.block(None);
+                let exit = self.block(None);
+                self.blocks[current].end = End::Goto(head);
+
+                let (end, var, body) = self.scoped(|this| -> syn::Result<_> {
+                    let var = match &*expr.pat {
+                        Pat::Ident(pat) => {
+                            let Stmt::Local(local) = parse_quote!(let #pat: #item;) else {
+                                unreachable!("parsed a let statement")
+                            };
+                            this.declare(&local)?;
+                            Some(this.vars.len() - 1)
+                        }
+                        Pat::Wild(_) => None,
+                        pat => {
+                            return Err(syn::Error::new_spanned(
+                                pat,
+                                "the variable of a `for` loop with a wait is kept on the \
+                                 coroutine's stack, so it must be a plain identifier",
+                            ))
+                        }
+                    };
+                    let body = this.block(None);
+                    let (end, _) = this.in_loop(head, Some(exit), |this| {
+                        this.lower_stmts(body, expr.body.stmts)
+                    })?;
+                    Ok((end, var, body))
+                })?;
+                if let Some(end) = end {
+                    self.blocks[end].end = End::Goto(head);
+                }
+                self.blocks[head].end = End::Next {
+                    iter,
+                    var,
+                    body,
+                    exit,
+                };
+                Ok(Some(exit))
+            }
+            Expr::Block(expr) if expr.label.is_none() => {
+                self.scoped(|this| this.lower_stmts(current, expr.block.stmts))
+            }
+            Expr::Break(expr) if jump.is_some() && expr.label.is_none() && expr.expr.is_none() => {
+                let exit = self.exit();
+                self.blocks[current].end = End::Goto(exit);
+                Ok(None)
+            }
+            Expr::Continue(expr) if jump.is_some() && expr.label.is_none() => {
+                let head = self.loops.last().unwrap().head;
+                self.blocks[current].end = End::Goto(head);
+                Ok(None)
+            }
+            _ => Err(unsupported(wait, jump)),
+        }
+    }
+
+    /// Lower the body of a loop with `lower`, returning the block it ends in along with the
+    /// block after the loop, if it is ever left.
+    fn in_loop(
+        &mut self,
+        head: usize,
+        exit: Option<usize>,
+        lower: impl FnOnce(&mut Self) -> syn::Result<Option<usize>>,
+    ) -> syn::Result<(Option<usize>, Option<usize>)> {
+        self.loops.push(Loop {
+            head,
+            exit,
+            scope: self.scope.clone(),
+        });
+        let end = self.scoped(lower);
+        let exit = self.loops.pop().unwrap().exit;
+        Ok((end?, exit))
+    }
+
+    /// Block after the innermost loop, made on the first `break` of a `loop`
+    fn exit(&mut self) -> usize {
+        let innermost = self.loops.last().unwrap();
+        if let Some(exit) = innermost.exit {
+            return exit;
+        }
+        let scope = innermost.scope.clone();
+        let exit = self.block_in(None, scope);
+        self.loops.last_mut().unwrap().exit = Some(exit);
+        exit
+    }
+
+    /// The kept variable a `for` loop with a wait iterates over
+    fn iterable(&self, expr: &Expr) -> syn::Result<usize> {
+        let var = match expr {
+            Expr::Path(path) => path.path.get_ident().and_then(|ident| {
+                self.scope
+                    .iter()
+                    .copied()
+                    .find(|var| &self.vars[*var].ident == ident)
+            }),
+            _ => None,
+        };
+        match var {
+            Some(var) if !self.vars[var].is_reference() => Ok(var),
+            _ => Err(syn::Error::new_spanned(
+                expr,
+                "the iterable of a `for` loop with a wait is kept on the coroutine's stack, \
+                 which needs its type, so it must be an owned variable declared with one: \
+                 `let items: Type = ..; for item in items { .. }`",
+            )),
+        }
+    }
+
+    /// Where `stmt` has a wait, and a `break` or `continue` of a loop with a wait, if any.
+    fn transitions(&self, stmt: &Stmt) -> (Option<Span>, Option<Span>) {
+        let mut find = FindWait::default();
+        find.visit_stmt(stmt);
+        let jump = find.jump.filter(|_| !self.loops.is_empty());
+        (find.wait, jump)
+    }
+}
+
+/// The binding and future of `let binding = fut.wait;`, `fut.wait;` or `wait!(fut);`
+fn wait_stmt(stmt: &Stmt) -> syn::Result<Option<(Pat, Expr)>> {
+    let waited = match stmt {
+        Stmt::Local(Local {
+            pat,
+            init: Some(init),
+            ..
+        }) if init.diverge.is_none() => waited_on(&init.expr)?.map(|future| (pat.clone(), future)),
+        Stmt::Expr(expr, Some(_)) => waited_on(expr)?.map(|future| (parse_quote!(_), future)),
+        Stmt::Macro(stmt_macro) if stmt_macro.mac.path.is_ident("wait") => {
+            Some((parse_quote!(_), stmt_macro.mac.parse_body()?))
+        }
+        _ => None,
+    };
+    Ok(waited)
+}
+
+/// The future of `fut.wait` or `wait!(fut)`
+fn waited_on(expr: &Expr) -> syn::Result<Option<Expr>> {
+    match expr {
+        Expr::Field(field) if is_wait(&field.member) => Ok(Some((*field.base).clone())),
+        Expr::Macro(expr) if expr.mac.path.is_ident("wait") => expr.mac.parse_body().map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn is_wait(member: &Member) -> bool {
+    matches!(member, Member::Named(ident) if ident == "wait")
+}
+
+/// `let binding;`, declaring the binding of a wait
+fn binding_local(pat: Pat) -> Local {
+    Local {
+        attrs: Vec::new(),
+        let_token: Default::default(),
+        pat,
+        init: None,
+        semi_token: Default::default(),
+    }
+}
+
+/// The condition of an `if` or `while` with a wait within, which can not bind variables or
+/// wait itself
+fn condition(cond: Expr) -> syn::Result<Expr> {
+    if let Expr::Let(expr) = &cond {
+        return Err(syn::Error::new_spanned(
+            expr.let_token,
+            "`if let` and `while let` are not supported with a wait within",
+        ));
+    }
+    no_wait(|find| find.visit_expr(&cond))?;
+    Ok(cond)
+}
+
+fn no_label(label: Option<&Label>) -> syn::Result<()> {
+    match label {
+        Some(label) => Err(syn::Error::new_spanned(
+            label,
+            "loops with a wait within can not have a label",
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Error for a statement that has a wait, or a jump out of a loop with a wait, which can not
+/// be lowered
+fn unsupported(wait: Option<Span>, jump: Option<Span>) -> syn::Error {
+    match (wait, jump) {
+        (Some(span), _) => syn::Error::new(
+            span,
+            "a wait must be a statement of its own, within the coroutine or the body of a \
+             loop, `if`/`else` or block",
+        ),
+        (None, Some(span)) => syn::Error::new(
+            span,
+            "`break` and `continue` of a loop with a wait must be statements of their own, \
+             without a label or value, within the loop or an `if`/`else` or block in it",
+        ),
+        (None, None) => unreachable!("statement without a transition"),
+    }
+}
+
+/// Fails on any wait `visit` comes across, e.g. in the future waited on
+fn no_wait(visit: impl FnOnce(&mut FindWait)) -> syn::Result<()> {
+    let mut find = FindWait::default();
+    visit(&mut find);
+    match find.wait {
+        Some(span) => Err(unsupported(Some(span), None)),
+        None => Ok(()),
+    }
+}
+
+/// Finds the first wait, and the first `break` or `continue` not within a loop or closure of
+/// its own
+#[derive(Default)]
+struct FindWait {
+    wait: Option<Span>,
+    jump: Option<Span>,
+    /// Loops and closures the visitor is within
+    depth: usize,
+}
+
+impl FindWait {
+    fn nested(&mut self, visit: impl FnOnce(&mut Self)) {
+        self.depth += 1;
+        visit(self);
+        self.depth -= 1;
+    }
+}
+
+impl<'ast> Visit<'ast> for FindWait {
+    fn visit_expr_field(&mut self, field: &'ast syn::ExprField) {
+        if is_wait(&field.member) {
+            self.wait.get_or_insert(field.member.span());
+        }
+        visit::visit_expr_field(self, field);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        if mac.path.is_ident("wait") {
+            self.wait.get_or_insert(mac.path.span());
+        }
+    }
+
+    fn visit_expr_break(&mut self, expr: &'ast syn::ExprBreak) {
+        if self.depth == 0 {
+            self.jump.get_or_insert(expr.break_token.span);
+        }
+        visit::visit_expr_break(self, expr);
+    }
+
+    fn visit_expr_continue(&mut self, expr: &'ast syn::ExprContinue) {
+        if self.depth == 0 {
+            self.jump.get_or_insert(expr.continue_token.span);
+        }
+    }
+
+    fn visit_expr_loop(&mut self, expr: &'ast syn::ExprLoop) {
+        self.nested(|this| visit::visit_expr_loop(this, expr));
+    }
+
+    fn visit_expr_while(&mut self, expr: &'ast syn::ExprWhile) {
+        self.nested(|this| visit::visit_expr_while(this, expr));
+    }
+
+    fn visit_expr_for_loop(&mut self, expr: &'ast syn::ExprForLoop) {
+        self.nested(|this| visit::visit_expr_for_loop(this, expr));
+    }
+
+    fn visit_expr_closure(&mut self, expr: &'ast syn::ExprClosure) {
+        self.nested(|this| visit::visit_expr_closure(this, expr));
+    }
+
+    fn visit_expr_async(&mut self, expr: &'ast syn::ExprAsync) {
+        self.nested(|this| visit::visit_expr_async(this, expr));
+    }
+}
+
+/// The variable declared by `local`, if it is to be kept on the stack
+fn kept(local: &Local) -> Option<Var> {
+    let Pat::Type(typed) = &local.pat else {
+        return None;
+    };
+    let Pat::Ident(pat) = &*typed.pat else {
+        return None;
+    };
+    Some(Var {
+        ident: pat.ident.clone(),
+        ty: (*typed.ty).clone(),
+    })
+}
+
+/// Variables declared without a type can not be kept, so fail on those used in another block,
+/// i.e. after a wait or on another iteration of a loop with one
+fn check_unkept(blocks: &[Block], vars: &[Var]) -> syn::Result<()> {
+    for (index, block) in blocks.iter().enumerate() {
+        for local in block.locals() {
+            let Pat::Ident(pat) = &local.pat else {
+                continue;
+            };
+            if vars.iter().any(|var| var.ident == pat.ident) {
+                continue;
+            }
+
+            let used_elsewhere = blocks.iter().enumerate().any(|(other, block)| {
+                let redeclared = block.locals().any(
+                    |local| matches!(&local.pat, Pat::Ident(other) if other.ident == pat.ident),
+                );
+                other != index && !redeclared && block.idents().contains(&pat.ident.to_string())
+            });
+            if used_elsewhere {
+                return Err(syn::Error::new_spanned(
+                    &pat.ident,
+                    format!(
+                        "`{}` is used after a wait, so it is kept on the coroutine's stack, \
+                         which needs its type: `let {}: Type = ..`",
+                        pat.ident, pat.ident
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Block {
+    /// Top level `let` statements, including the binding of the wait it resumes from
+    fn locals(&self) -> impl Iterator<Item = Local> + '_ {
+        let binding = self.binding.clone().map(binding_local);
+        binding
+            .into_iter()
+            .chain(self.stmts.iter().filter_map(|stmt| match stmt {
+                Stmt::Local(local) => Some(local.clone()),
+                _ => None,
+            }))
+    }
+
+    /// Identifiers used within the block, including those within macros
+    fn idents(&self) -> HashSet<String> {
+        let mut idents = Idents::default();
+        for stmt in &self.stmts {
+            idents.visit_stmt(stmt);
+        }
+        match &self.end {
+            End::Wait(expr, _) | End::If(expr, ..) => idents.visit_expr(expr),
+            End::Goto(_) | End::Next { .. } | End::Return => {}
+        }
+        idents.0
+    }
+
+    /// Code of the block, `states` being the names of the states of all blocks, and `boxed`
+    /// what boxes the futures waited on
+    fn generate(self, vars: &[Var], states: &[Ident], boxed: &TokenStream) -> TokenStream {
+        let mut in_scope = KeptValues(HashSet::new());
+        let mut body = Vec::new();
+
+        for var in self.scope.iter().map(|var| &vars[*var]) {
+            body.push(var.restore());
+            if !var.is_reference() {
+                in_scope.0.insert(var.ident.clone());
+            }
+        }
+
+        let binding = self
+            .binding
+            .map(|pat| -> Stmt { parse_quote!(let #pat = __ready;) });
+        for mut stmt in binding.into_iter().chain(self.stmts) {
+            in_scope.visit_stmt_mut(&mut stmt);
+
+            let var = match &stmt {
+                Stmt::Local(local) => kept(local)
+                    .and_then(|var| vars.iter().find(|v| v.ident == var.ident))
+                    .map(|var| (var, local.init.as_ref().map(|init| &init.expr))),
+                _ => None,
+            };
+            let Some((var, init)) = var else {
+                body.push(quote!(#stmt));
+                continue;
+            };
+
+            let ident = &var.ident;
+            let Some(init) = init else {
+                body.push(
+                    syn::Error::new_spanned(ident, "variables kept across a wait need a value")
+                        .into_compile_error(),
+                );
+                continue;
+            };
+            match &var.ty {
+                Type::Reference(r) if r.mutability.is_some() => {
+                    let elem = &r.elem;
+                    body.push(quote! {
+                        let #ident: &mut #elem = #init;
+                        coroutine.stack.#ident = Some(&mut *#ident as *mut #elem);
+                    });
+                }
+                Type::Reference(r) => {
+                    let elem = &r.elem;
+                    body.push(quote! {
+                        let #ident: &#elem = #init;
+                        coroutine.stack.#ident = Some(#ident as *const #elem);
+                    });
+                }
+                _ => {
+                    body.push(quote! {
+                        coroutine.stack.#ident = Some(#init);
+                        let #ident = coroutine.stack.#ident.as_mut().unwrap();
+                    });
+                    in_scope.0.insert(ident.clone());
+                }
+            }
+        }
+
+        let end = match self.end {
+            End::Wait(mut future, next) => {
+                in_scope.visit_expr_mut(&mut future);
+                let next = &states[next];
+                quote!(coroutine.state = State::#next(#boxed(#future));)
+            }
+            End::Goto(next) => {
+                let next = &states[next];
+                quote!(coroutine.state = State::#next;)
+            }
+            End::If(mut cond, then, otherwise) => {
+                in_scope.visit_expr_mut(&mut cond);
+                let (then, otherwise) = (&states[then], &states[otherwise]);
+                quote! {
+                    if #cond {
+                        coroutine.state = State::#then;
+                    } else {
+                        coroutine.state = State::#otherwise;
+                    }
+                }
+            }
+            End::Next {
+                iter,
+                var,
+                body: next,
+                exit,
+            } => {
+                let iter = &vars[iter].ident;
+                let keep = var.map(|var| {
+                    let var = &vars[var].ident;
+                    quote!(coroutine.stack.#var = Some(__item);)
+                });
+                let (next, exit) = (&states[next], &states[exit]);
+                quote! {
+                    match coroutine.stack.#iter.as_mut().unwrap().next() {
+                        Some(__item) => {
+                            #keep
+                            coroutine.state = State::#next;
+                        }
+                        None => {
+                            coroutine.stack.#iter = None;
+                            coroutine.state = State::#exit;
+                        }
+                    }
+                }
+            }
+            End::Return => quote! {
+                coroutine.state = State::Resolved;
+                // free the variables kept on the stack
+                coroutine.stack = Stack::default();
+                break crate::future::PollState::Ready(String::new());
+            },
+        };
+
+        quote! {
+            #(#body)*
+            #end
+        }
+    }
+}
+
+#[derive(Default)]
+struct Idents(HashSet<String>);
+
+impl<'ast> Visit<'ast> for Idents {
+    fn visit_expr_path(&mut self, path: &'ast syn::ExprPath) {
+        if let Some(ident) = path.path.get_ident() {
+            self.0.insert(ident.to_string());
+        }
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        fn walk(tokens: TokenStream, idents: &mut HashSet<String>) {
+            for token in tokens {
+                match token {
+                    TokenTree::Ident(ident) => {
+                        idents.insert(ident.to_string());
+                    }
+                    TokenTree::Group(group) => walk(group.stream(), idents),
+                    _ => {}
+                }
+            }
+        }
+        walk(mac.tokens.clone(), &mut self.0);
+    }
+}
+
+/// Rewrites uses of kept values, which are bound to a `&mut` to their value on the stack
+struct KeptValues(HashSet<Ident>);
+
+impl VisitMut for KeptValues {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        if let Expr::Path(path) = expr {
+            if let Some(ident) = path.path.get_ident() {
+                if path.qself.is_none() && self.0.contains(ident) {
+                    *expr = parse_quote!((*#ident));
+                    return;
+                }
+            }
+        }
+        visit_mut::visit_expr_mut(self, expr);
+    }
+
+    fn visit_field_value_mut(&mut self, field: &mut FieldValue) {
+        // `Foo { counter }` becomes `Foo { counter: (*counter) }`
+        if field.colon_token.is_none() {
+            field.colon_token = Some(Default::default());
+        }
+        visit_mut::visit_field_value_mut(self, field);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_ok(item: TokenStream) -> String {
+        expand(TokenStream::new(), item).unwrap().to_string()
+    }
+
+    fn expand_err(item: TokenStream) -> String {
+        expand(TokenStream::new(), item).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn a_state_per_wait_and_typed_variables_on_the_stack() {
+        let expanded = expand_ok(quote! {
+            fn async_main(id: usize) {
+                let mut counter: usize = 0;
+                let txt = Http::get("/600/HelloAsyncAwait").wait;
+                counter += 1;
+                let txt = wait!(Http::get("/400/HelloAsyncAwait"));
+                println!("{txt} {counter}");
+            }
+        });
+
+        for expected in [
+            "Wait1 (Pin < Box < dyn crate :: future :: Future < Output = String , Waker = crate :: runtime :: Waker > >>) ,",
+            "Wait2 (Pin < Box",
+            "id : Option < usize > , counter : Option < usize > ,",
+            "(* counter) += 1",
+            "if crate :: runtime :: is_cancelled () { coroutine . state = State :: Resolved ;",
+        ] {
+            assert!(expanded.contains(expected), "{expected:?} in {expanded}");
+        }
+        assert!(!expanded.contains("Wait3"));
+    }
+
+    #[test]
+    fn references_are_kept_as_raw_pointers() {
+        let expanded = expand_ok(quote! {
+            fn async_main() {
+                let mut buffer: String = String::new();
+                let writer: &mut String = &mut buffer;
+                Http::get("/600/HelloAsyncAwait").wait;
+                writer.push('!');
+            }
+        });
+
+        assert!(expanded.contains("writer : Option < * mut String >"));
+        // which are only valid as long as the coroutine does not move
+        assert!(expanded.contains("_pin : PhantomPinned"));
+        assert!(expanded.contains("self : Pin < & mut Self >"));
+        assert!(expanded.contains("& mut * coroutine . stack . writer . unwrap ()"));
+    }
+
+    #[test]
+    fn waits_within_expressions_are_rejected() {
+        let err = expand_err(quote! {
+            fn async_main() {
+                let len = count(Http::get("/600/HelloAsyncAwait").wait);
+            }
+        });
+        assert!(err.contains("statement of its own"), "{err}");
+
+        let err = expand_err(quote! {
+            fn async_main() {
+                let txt = match 0 {
+                    _ => Http::get("/600/HelloAsyncAwait").wait,
+                };
+            }
+        });
+        assert!(err.contains("statement of its own"), "{err}");
+    }
+
+    #[test]
+    fn waits_within_loops_and_branches_get_states_of_their_own() {
+        let expanded = expand_ok(quote! {
+            fn async_main() {
+                let requests: RangeInclusive<usize> = 0..=5;
+                for i in requests {
+                    if i % 2 == 0 {
+                        let txt = Http::get("/600/HelloAsyncAwait").wait;
+                        println!("{txt}");
+                    } else {
+                        continue;
+                    }
+                    let mut retries: usize = 0;
+                    while retries < 3 {
+                        Http::get("/400/HelloAsyncAwait").wait;
+                        retries += 1;
+                    }
+                }
+            }
+        });
+
+        for expected in [
+            // the iterator replaces the iterable on the stack, along with the loop variable
+            "__iter0 : Option < < RangeInclusive < usize > as IntoIterator > :: IntoIter > ,",
+            "i : Option < < RangeInclusive < usize > as IntoIterator > :: Item > ,",
+            "coroutine . stack . __iter0 = Some (IntoIterator :: into_iter (coroutine . stack . requests . take () . unwrap () ,)) ;",
+            "match coroutine . stack . __iter0 . as_mut () . unwrap () . next () { Some (__item) => { coroutine . stack . i = Some (__item) ;",
+            "if (* i) % 2 == 0 {",
+            "if (* retries) < 3 {",
+            "Wait2 (Pin < Box",
+        ] {
+            assert!(expanded.contains(expected), "{expected:?} in {expanded}");
+        }
+        assert!(!expanded.contains("Wait3"));
+    }
+
+    #[test]
+    fn break_continues_after_loops_with_a_wait() {
+        let expanded = expand_ok(quote! {
+            fn async_main() {
+                let mut attempts: usize = 0;
+                loop {
+                    let txt: String = Http::get("/600/HelloAsyncAwait").wait;
+                    attempts += 1;
+                    if !txt.is_empty() || attempts == 3 {
+                        break;
+                    }
+                }
+                println!("{attempts}");
+            }
+        });
+        assert!(
+            expanded.contains("println ! (\"{attempts}\")"),
+            "{expanded}"
+        );
+
+        let err = expand_err(quote! {
+            fn async_main() {
+                'outer: loop {
+                    Http::get("/600/HelloAsyncAwait").wait;
+                }
+            }
+        });
+        assert!(err.contains("can not have a label"), "{err}");
+
+        let err = expand_err(quote! {
+            fn async_main() {
+                loop {
+                    Http::get("/600/HelloAsyncAwait").wait;
+                    let done = if true { break } else { false };
+                }
+            }
+        });
+        assert!(err.contains("`break` and `continue`"), "{err}");
+    }
+
+    #[test]
+    fn iterables_of_loops_with_a_wait_are_kept() {
+        let err = expand_err(quote! {
+            fn async_main() {
+                for i in 0..3 {
+                    Http::get("/600/HelloAsyncAwait").wait;
+                }
+            }
+        });
+        assert!(err.contains("iterable of a `for` loop"), "{err}");
+
+        let err = expand_err(quote! {
+            fn async_main() {
+                let requests = vec![1, 2];
+                for i in requests {
+                    Http::get("/600/HelloAsyncAwait").wait;
+                }
+            }
+        });
+        assert!(err.contains("iterable of a `for` loop"), "{err}");
+    }
+
+    #[test]
+    fn the_waker_variant_is_polled_through_mut_self() {
+        let expanded = expand(
+            quote!(waker),
+            quote! {
+                fn async_main() {
+                    Http::get("/600/HelloAsyncAwait").wait;
+                }
+            },
+        )
+        .unwrap()
+        .to_string();
+        assert!(expanded.contains("Wait1 (Box < dyn"), "{expanded}");
+        assert!(expanded.contains("fn poll (& mut self ,"), "{expanded}");
+        assert!(expanded.contains("future . poll (waker)"), "{expanded}");
+        assert!(!expanded.contains("PhantomPinned"), "{expanded}");
+
+        let err = expand(
+            quote!(waker),
+            quote! {
+                fn async_main() {
+                    let mut buffer: String = String::new();
+                    let writer: &mut String = &mut buffer;
+                    Http::get("/600/HelloAsyncAwait").wait;
+                    writer.push('!');
+                }
+            },
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("leave out `waker`"), "{err}");
+
+        let err = expand(
+            quote!(pinned),
+            quote!(
+                fn async_main() {}
+            ),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("takes no arguments"), "{err}");
+    }
+
+    #[test]
+    fn untyped_variables_used_after_a_wait_are_rejected() {
+        let err = expand_err(quote! {
+            fn async_main() {
+                let counter = 0;
+                Http::get("/600/HelloAsyncAwait").wait;
+                println!("{}", counter);
+            }
+        });
+        assert!(err.contains("`counter` is used after a wait"), "{err}");
+
+        // a binding of its own in the later segment is fine
+        expand_ok(quote! {
+            fn async_main() {
+                let txt = Http::get("/600/HelloAsyncAwait").wait;
+                let txt = Http::get("/400/HelloAsyncAwait").wait;
+                println!("{txt}");
+            }
+        });
+    }
+}
@@ -0,0 +1,285 @@
+//! Responds to `GET /<delay in ms>/<message>` with `<message>`, once the delay passed.
+//!
+//! Stands in for the delayserver of `rust-async-utils`, so the examples of the workspace run
+//! without it. It is built on the runtime of `reactor-executor`, i.e. the listener, timers and
+//! response writing all run on the executor, one task per connection. Hence, pointing many
+//! clients at it also puts the server side of the runtime under load.
+//!
+//! A SIGHUP upgrades the server without dropping a connection: the running instance execs a
+//! new one, and hands it the listening socket over a Unix socket, see [`net::fd`]. Once the new
+//! instance accepts connections, the old one stops accepting, answers the requests it has in
+//! flight and exits.
+//!
+//! ```bash
+//! cargo run -p delayserver
+//! cargo run -p delayserver -- 9090
+//! kill -HUP <pid>
+//! ```
+use std::{
+    env,
+    io::{self, Read, Write},
+    mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, RawFd},
+        unix::net::UnixStream as StdUnixStream,
+    },
+    process::{self, Command},
+    ptr,
+    time::Duration,
+};
+
+use prelude::*;
+
+/// Port listened on when none is given, the one the examples expect
+const DEFAULT_PORT: u16 = 8080;
+
+/// Environment variable for the port, used when none is given as an argument
+const PORT_ENV: &str = "DELAYSERVER_PORT";
+
+/// Environment variable the previous instance passes the fd of its end of the upgrade socket in
+const UPGRADE_FD_ENV: &str = "DELAYSERVER_UPGRADE_FD";
+
+fn main() {
+    // blocked on every thread, so it is only picked up by `sigwait` in `upgrade_on_hangup`.
+    // Threads inherit the mask, hence this goes before the runtime starts any.
+    let hangup = block_hangup();
+
+    let (listener, upgrade) = match env::var(UPGRADE_FD_ENV) {
+        Ok(fd) => {
+            let fd = fd.parse().expect("Upgrade fd must be a number");
+            take_over(fd).expect("Failed to take over the listener")
+        }
+        Err(_) => (bind(), None),
+    };
+
+    let mut executor = runtime::init();
+    let mut listener = TcpListener::from_std_with_priority(listener, Priority::High);
+    println!(
+        "delayserver {} listening on {}",
+        process::id(),
+        listener.local_addr().unwrap()
+    );
+
+    // let the previous instance know it can stop accepting
+    if let Some(mut upgrade) = upgrade {
+        upgrade
+            .write_all(&[1])
+            .expect("Failed to confirm the upgrade");
+    }
+
+    executor.block_on(async move {
+        let fd = listener.as_raw_fd();
+        let mut upgraded = spawn_blocking(move || upgrade_on_hangup(&hangup, fd));
+
+        loop {
+            select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((conn, addr)) => {
+                        spawn(async move {
+                            if let Err(e) = serve(conn).await {
+                                eprintln!("{addr}: {e}");
+                            }
+                        });
+                    }
+                    Err(e) => eprintln!("Failed to accept connection: {e}"),
+                },
+                _ = &mut upgraded => break,
+            }
+        }
+
+        // the executor keeps running until the connection tasks are done
+        println!("delayserver {} handed over, draining", process::id());
+    });
+}
+
+fn bind() -> std::net::TcpListener {
+    let port = env::args()
+        .nth(1)
+        .or_else(|| env::var(PORT_ENV).ok())
+        .map(|port| port.parse().expect("Port must be a number"))
+        .unwrap_or(DEFAULT_PORT);
+
+    let listener =
+        std::net::TcpListener::bind(("127.0.0.1", port)).expect("Failed to bind delayserver port");
+    listener
+        .set_nonblocking(true)
+        .expect("Failed to make the listener non-blocking");
+    listener
+}
+
+/// Receive the listener from the previous instance over the socket it left open for us at `fd`.
+/// The socket is handed back to confirm the upgrade over, once we accept connections.
+fn take_over(fd: RawFd) -> io::Result<(std::net::TcpListener, Option<StdUnixStream>)> {
+    // SAFETY: the fd was left open by the previous instance for us alone
+    let upgrade = unsafe { StdUnixStream::from_raw_fd(fd) };
+    env::remove_var(UPGRADE_FD_ENV);
+
+    let listener = net::fd::listener_from_fd(net::fd::recv_fd(&upgrade)?)?;
+    Ok((listener, Some(upgrade)))
+}
+
+/// Wait for a SIGHUP, then hand the listener at `listener` over to a new instance of this
+/// program. Returns once the new instance accepts connections, a failed upgrade is reported
+/// and waits for the next SIGHUP.
+fn upgrade_on_hangup(hangup: &libc::sigset_t, listener: RawFd) {
+    loop {
+        let mut signal = 0;
+        unsafe { libc::sigwait(hangup, &mut signal) };
+
+        match upgrade(listener) {
+            Ok(()) => return,
+            Err(e) => eprintln!("Upgrade failed: {e}"),
+        }
+    }
+}
+
+fn upgrade(listener: RawFd) -> io::Result<()> {
+    let (mut ours, theirs) = StdUnixStream::pair()?;
+    // std creates the socket with FD_CLOEXEC, the new instance needs it open across exec
+    net::fd::set_inheritable(theirs.as_raw_fd())?;
+
+    let mut child = Command::new(env::current_exe()?)
+        .args(env::args().skip(1))
+        .env(UPGRADE_FD_ENV, theirs.as_raw_fd().to_string())
+        .spawn()?;
+    drop(theirs);
+
+    net::fd::send_fd(&ours, listener)?;
+
+    // a byte once it accepts, EOF if it gave up
+    let mut ready = [0u8; 1];
+    if let Err(e) = ours.read_exact(&mut ready) {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Block SIGHUP on this thread, returning the set to wait for it with.
+fn block_hangup() -> libc::sigset_t {
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGHUP);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+        set
+    }
+}
+
+/// Read a single request off `conn`, then respond once its delay passed.
+async fn serve(mut conn: TcpStream) -> io::Result<()> {
+    let request = read_head(&mut conn).await?;
+    let Some(path) = request_path(&request) else {
+        return conn.write_all(BAD_REQUEST.as_bytes()).await;
+    };
+
+    let (delay, msg) = parse_delay_path(path);
+    sleep(delay).await;
+
+    conn.write_all(response(msg).as_bytes()).await?;
+    conn.shutdown_write()
+}
+
+/// Read until the end of the request head, or until the client stops sending.
+async fn read_head(conn: &mut TcpStream) -> io::Result<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        match conn.read(&mut buf).await? {
+            0 => break,
+            n => head.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// Path of a `GET` request, e.g. `/1000/hello` of `GET /1000/hello HTTP/1.1`.
+fn request_path(request: &str) -> Option<&str> {
+    let mut parts = request.lines().next()?.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => Some(path),
+        _ => None,
+    }
+}
+
+/// Split a path `/<delay ms>/<message>` into its delay and message. A missing or invalid
+/// delay responds straight away.
+fn parse_delay_path(path: &str) -> (Duration, &str) {
+    let mut parts = path.trim_start_matches('/').splitn(2, '/');
+    let delay = parts
+        .next()
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or_default();
+    let msg = parts.next().unwrap_or_default();
+
+    (delay, msg)
+}
+
+fn response(msg: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\n\
+         content-length: {}\r\n\
+         connection: close\r\n\
+         \r\n\
+         {msg}",
+        msg.len()
+    )
+}
+
+const BAD_REQUEST: &str = "HTTP/1.1 400 Bad Request\r\n\
+                           content-length: 0\r\n\
+                           connection: close\r\n\
+                           \r\n";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_delay_and_message() {
+        assert_eq!(
+            parse_delay_path("/1500/HelloWorld"),
+            (Duration::from_millis(1500), "HelloWorld")
+        );
+        assert_eq!(parse_delay_path("/abc/hi"), (Duration::ZERO, "hi"));
+        assert_eq!(
+            request_path("GET /0/hi HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            Some("/0/hi")
+        );
+        assert_eq!(request_path("POST /0/hi HTTP/1.1\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn responds_after_the_delay() {
+        use std::io::{Read, Write};
+        use std::time::Instant;
+
+        let mut executor = runtime::init();
+        let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let start = Instant::now();
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream.write_all(b"GET /50/hello HTTP/1.1\r\n\r\n").unwrap();
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            (reply, start.elapsed())
+        });
+
+        executor.block_on(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            serve(conn).await.unwrap();
+        });
+
+        let (reply, elapsed) = client.join().unwrap();
+        assert!(reply.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(reply.ends_with("\r\n\r\nhello"));
+        assert!(elapsed >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,84 @@
+//! Upgrading a running delayserver with SIGHUP, while it answers a request.
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpStream},
+    process::{Child, ChildStdout, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Kills the instance that took over once the test is done, whether it passed or not
+struct Instance(libc::pid_t);
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe { libc::kill(self.0, libc::SIGKILL) };
+    }
+}
+
+/// Wait for the line an instance prints once it accepts connections, resolving to its pid
+/// and address.
+fn listening(stdout: &mut BufReader<ChildStdout>) -> (libc::pid_t, SocketAddr) {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        assert!(stdout.read_line(&mut line).unwrap() > 0, "delayserver quit");
+
+        // delayserver <pid> listening on <addr>
+        let words: Vec<_> = line.split_whitespace().collect();
+        if let ["delayserver", pid, "listening", "on", addr] = words[..] {
+            return (pid.parse().unwrap(), addr.parse().unwrap());
+        }
+    }
+}
+
+fn get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {path} HTTP/1.1\r\n\r\n").unwrap();
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).unwrap();
+    reply
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status.success();
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+#[test]
+fn restart_keeps_requests_in_flight() {
+    let mut old = Command::new(env!("CARGO_BIN_EXE_delayserver"))
+        .arg("0")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let _old_instance = Instance(old.id() as _);
+    let mut stdout = BufReader::new(old.stdout.take().unwrap());
+    let (old_pid, addr) = listening(&mut stdout);
+
+    // answered by the old instance, well after the upgrade
+    let in_flight = thread::spawn(move || get(addr, "/1000/first"));
+    thread::sleep(Duration::from_millis(200));
+
+    unsafe { libc::kill(old_pid, libc::SIGHUP) };
+    let (new_pid, new_addr) = listening(&mut stdout);
+    let _new_instance = Instance(new_pid);
+    assert_ne!(new_pid, old_pid);
+    assert_eq!(new_addr, addr);
+
+    // the new instance accepts while the old one still drains
+    assert!(get(addr, "/0/second").ends_with("\r\n\r\nsecond"));
+    assert!(old.try_wait().unwrap().is_none());
+
+    assert!(in_flight.join().unwrap().ends_with("\r\n\r\nfirst"));
+    assert!(wait_with_timeout(&mut old, Duration::from_secs(5)));
+
+    // and keeps accepting once the old one is gone
+    assert!(get(addr, "/0/third").ends_with("\r\n\r\nthird"));
+}
@@ -13,6 +13,8 @@
 
 #![allow(dead_code, unused)]
 
+use crate::log;
+
 // repr(packed) forces Rust to strip any padding, and only align the type to a byte
 // note: The OS syscall expects the struct we use to be packed.
 #[derive(Debug)]
@@ -30,6 +32,11 @@ impl Event {
     pub fn token(&self) -> usize {
         self.epoll_data
     }
+
+    /// Bitmask of the events that occured, e.g. `EPOLLIN`
+    pub fn events(&self) -> u32 {
+        self.events
+    }
 }
 // ------------------------------------------------------------
 // System calls
@@ -44,8 +51,93 @@ pub const EPOLL_CTL_MOD: i32 = 3; // Change file descriptor epoll_event structur
 
 // bitflags for events we are interested in
 pub const EPOLLIN: i32 = 0x1; // read operations on the file handle
+pub const EPOLLOUT: i32 = 0x4; // write operations on the file handle
 pub const EPOLLET: i32 = 1 << 31; // edge-triggered mode
 
+// bitflags of `pollfd.events` and `pollfd.revents`, the same on Linux, macOS and the BSDs
+// taken from: /usr/include/asm-generic/poll.h
+pub const POLLIN: i16 = 0x1; // there is data to read
+pub const POLLOUT: i16 = 0x4; // writing is now possible
+pub const POLLERR: i16 = 0x8; // error condition (output only)
+pub const POLLHUP: i16 = 0x10; // hang up (output only)
+pub const POLLNVAL: i16 = 0x20; // fd not open (output only)
+
+/// Resource limit on the number of open file descriptors, which also bounds the number of fds a
+/// single call to `poll` may be given.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const RLIMIT_NOFILE: i32 = 7;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub const RLIMIT_NOFILE: i32 = 8;
+
+/// `nfds_t`, the type of the number of fds passed to `poll`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub type Nfds = std::ffi::c_ulong;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub type Nfds = std::ffi::c_uint;
+
+/// A file descriptor to watch, and the events that occured on it once `poll` returns.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct PollFd {
+    pub fd: i32,
+    // bitmask of events we are interested in
+    pub events: i16,
+    // bitmask of events that occured, filled in by the kernel
+    pub revents: i16,
+}
+
+// commands and flags of `fcntl`, the same on Linux, macOS and the BSDs
+pub const F_GETFD: i32 = 1; // get the fd flags, used to check the fd is open
+pub const F_GETFL: i32 = 3; // get the file status flags
+pub const F_SETFL: i32 = 4; // set the file status flags
+
+// taken from: /usr/include/asm-generic/fcntl.h
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const O_NONBLOCK: i32 = 0o4000;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub const O_NONBLOCK: i32 = 0x4;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const O_CLOEXEC: i32 = 0o2000000;
+
+// flags of `eventfd` and `timerfd_create`, the same as the O_* flags of the same name
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const EFD_NONBLOCK: i32 = O_NONBLOCK;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const EFD_CLOEXEC: i32 = O_CLOEXEC;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const TFD_NONBLOCK: i32 = O_NONBLOCK;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const TFD_CLOEXEC: i32 = O_CLOEXEC;
+
+/// Clock that is not affected by changes to the system time
+pub const CLOCK_MONOTONIC: i32 = 1;
+
+/// A point in time, or a duration, see `timerfd_settime`
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct Timespec {
+    pub tv_sec: std::ffi::c_long,
+    pub tv_nsec: std::ffi::c_long,
+}
+
+/// When a timer fires first, and the interval it fires at from then on, see `timerfd_settime`
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct Itimerspec {
+    // interval for periodic timers, zero for a one-shot timer
+    pub it_interval: Timespec,
+    // initial expiration, zero disarms the timer
+    pub it_value: Timespec,
+}
+
+/// Soft and hard limit of a resource, see `getrlimit`
+#[derive(Debug, Default)]
+#[repr(C)]
+pub struct Rlimit {
+    pub rlim_cur: u64,
+    pub rlim_max: u64,
+}
+
 #[cfg(target_os = "linux")]
 #[link(name = "c")] // link to C standard library / libc
 extern "C" {
@@ -105,7 +197,84 @@ extern "C" {
     pub fn epoll_wait(epfd: i32, events: *mut Event, max_events: i32, timeout: i32) -> i32;
 }
 
-pub(crate) fn check(bitmask: i32) {
+#[cfg(unix)]
+#[link(name = "c")]
+extern "C" {
+    /// wait for some event on a set of file descriptors (blocking)
+    ///
+    /// The portable fallback for platforms without epoll. Rather than keep the interest list in
+    /// the kernel, the whole list is passed in on every call, and `revents` of each entry is
+    /// filled in with the events that occured on it.
+    ///
+    /// https://man7.org/linux/man-pages/man2/poll.2.html
+    ///
+    /// #include <poll.h>
+    ///
+    /// int poll(struct pollfd *fds, nfds_t nfds, int timeout);
+    ///
+    /// Fails with EINVAL if `nfds` exceeds the RLIMIT_NOFILE resource limit.
+    pub fn poll(fds: *mut PollFd, nfds: Nfds, timeout: i32) -> i32;
+
+    /// get resource limits
+    ///
+    /// https://man7.org/linux/man-pages/man2/getrlimit.2.html
+    ///
+    /// int getrlimit(int resource, struct rlimit *rlim);
+    pub fn getrlimit(resource: i32, rlim: *mut Rlimit) -> i32;
+
+    /// create a pipe: a pair of fds, bytes written to `fds[1]` can be read from `fds[0]`
+    ///
+    /// https://man7.org/linux/man-pages/man2/pipe.2.html
+    ///
+    /// int pipe(int pipefd[2]);
+    pub fn pipe(fds: *mut i32) -> i32;
+
+    /// manipulate file descriptor
+    ///
+    /// https://man7.org/linux/man-pages/man2/fcntl.2.html
+    ///
+    /// int fcntl(int fd, int cmd, ... /* arg */ );
+    ///
+    /// Fails with EBADF if `fd` is not an open file descriptor.
+    pub fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[link(name = "c")]
+extern "C" {
+    /// create a file descriptor for event notification
+    ///
+    /// The fd holds a 64-bit counter: writes add to it, and a read returns it and resets it to
+    /// zero. It is readable while the counter is not zero.
+    ///
+    /// https://man7.org/linux/man-pages/man2/eventfd.2.html
+    ///
+    /// int eventfd(unsigned int initval, int flags);
+    pub fn eventfd(initval: u32, flags: i32) -> i32;
+
+    /// create a timer that notifies via a file descriptor
+    ///
+    /// The fd is readable once the timer expired, and a read returns the number of expirations
+    /// since the last read.
+    ///
+    /// https://man7.org/linux/man-pages/man2/timerfd_create.2.html
+    ///
+    /// int timerfd_create(int clockid, int flags);
+    pub fn timerfd_create(clockid: i32, flags: i32) -> i32;
+
+    /// arm (or disarm) the timer of a timerfd
+    ///
+    /// int timerfd_settime(int fd, int flags, const struct itimerspec *new_value,
+    ///                     struct itimerspec *_Nullable old_value);
+    pub fn timerfd_settime(
+        fd: i32,
+        flags: i32,
+        new_value: *const Itimerspec,
+        old_value: *mut Itimerspec,
+    ) -> i32;
+}
+
+pub fn check(bitmask: i32) {
     const EPOLLIN: i32 = 0x1;
     const EPOLLET: i32 = 1 << 31;
     const EPOLLONESHOT: i32 = 0x40000000;
@@ -115,7 +284,7 @@ pub(crate) fn check(bitmask: i32) {
     let et = bitmask & EPOLLET != 0;
     let oneshot = bitmask & EPOLLONESHOT != 0;
 
-    println!("read event? {read}, edge-triggered? {et}, oneshot? {oneshot}");
+    log::debug!("read event? {read}, edge-triggered? {et}, oneshot? {oneshot}");
     // println!("{EPOLLONESHOT:032b}");
     // println!("{bitmask:032b}");
     // println!("{EPOLLIN:032b}");
@@ -123,14 +292,14 @@ pub(crate) fn check(bitmask: i32) {
 }
 
 #[cfg(not(target_arch = "x86_64"))]
-pub(crate) fn print_event_debug(event: &Event) {
-    println!("Registering interest in event: {event:?}");
-    println!("event.events  (interest) : {:032b}", event.events);
-    println!("event.epoll_data (token) : {}", event.epoll_data);
+pub fn print_event_debug(event: &Event) {
+    log::debug!("Registering interest in event: {event:?}");
+    log::debug!("event.events  (interest) : {:032b}", event.events);
+    log::debug!("event.epoll_data (token) : {}", event.epoll_data);
 }
 
 #[cfg(target_arch = "x86_64")]
-pub(crate) fn print_event_debug(event: &Event) {
+pub fn print_event_debug(event: &Event) {
     // Below is due to using repr(packed) and unaligned access
-    println!("No event debug on x86_64");
+    log::debug!("No event debug on x86_64");
 }
@@ -0,0 +1,13 @@
+//! Miniature implementation of mio: a readiness based event queue over epoll, with a `poll(2)`
+//! fallback elsewhere.
+//!
+//! The `mini-mio` binary makes requests to the delayserver on top of this library. Sockets,
+//! pipes, eventfds, timerfds and any other fd can be registered, see [`source`].
+
+pub mod ffi;
+pub mod poll;
+pub mod source;
+pub mod sys;
+
+/// Leveled debug output, shared with the runtime of `reactor-executor`
+pub use runtime_log as log;
@@ -8,11 +8,11 @@ use std::{
     net::TcpStream,
 };
 
-mod ffi;
-mod poll;
-
-use ffi::Event;
-use poll::Poll;
+use mini_mio::{
+    ffi::{self, Event},
+    log,
+    poll::Poll,
+};
 
 fn main() -> Result<()> {
     // Create a new event queue
@@ -28,7 +28,7 @@ fn main() -> Result<()> {
         // first request has longest timeout, so expect
         // responses to arrive in reverse order.
         let delay = (num_events - i) * 1000;
-        println!("Delay: {} ms, for event i = {}", delay, i);
+        log::info!("Delay: {} ms, for event i = {}", delay, i);
         let url_path = format!("/{delay}/request-{i}");
         let request = get_req(&url_path);
         let mut stream = TcpStream::connect(socket_addr)?;
@@ -45,7 +45,7 @@ fn main() -> Result<()> {
 
         // register interest in being notified when steam is ready to read
 
-        println!("Registering stream {i} with epoll");
+        log::debug!("Registering stream {i} with epoll");
         poll.registry().register(
             &stream,                     // source
             i,                           // token
@@ -60,7 +60,7 @@ fn main() -> Result<()> {
         // hence Event.events = 214748364
 
         // store stream
-        println!("Storing stream...");
+        log::debug!("Storing stream...");
         streams.push(stream);
 
         println!("\n-- Completing Request {i} --\n\n");
@@ -77,20 +77,24 @@ fn main() -> Result<()> {
     // Now handle read notifications
     let mut handled_events = 0;
 
+    // Allocated once and reused for every iteration of the event loop, rather than allocating
+    // a fresh event list and read buffer each time. `poll` overwrites the events, and reads
+    // overwrite the buffer.
+    let mut events = Vec::with_capacity(10);
+    let mut data = vec![0u8; 4096]; // 4KB buffer
+
     // do below while we haven't got a response from all the requests
     while handled_events < num_events {
-        let mut events = Vec::with_capacity(10);
-
         // register interest in being notified when steam is ready to read
         poll.poll(&mut events, None)?; // block indefinitely
 
         // reach here when thread is woken up
         if events.is_empty() {
-            println!("TIMEOUT OR SPURIOUS WAKEUP EVENT NOTIFICATION");
+            log::warn!("Timeout or spurious wakeup, no events");
             continue;
         }
 
-        handled_events += handle_events(&events, &mut streams, &mut handled_ids)?;
+        handled_events += handle_events(&events, &mut streams, &mut handled_ids, &mut data)?;
     }
 
     println!("FINISHED PROGRAM");
@@ -112,17 +116,16 @@ fn handle_events(
     events: &[Event],
     streams: &mut [TcpStream],
     handled_ids: &mut HashSet<usize>,
+    data: &mut [u8],
 ) -> Result<usize> {
     let mut handled_events = 0;
 
     for event in events {
         println!("\n------------------------------------\n");
         ffi::print_event_debug(event);
-        ffi::check(event.events as i32);
+        ffi::check(event.events() as i32);
 
         let index = event.token();
-        let mut data = vec![0u8; 4096]; // 4KB buffer
-                                        // let mut data = vec![0u8; 8]; // 4KB buffer
 
         let mut i = 0_usize;
         let mut txt = String::new();
@@ -132,7 +135,7 @@ fn handle_events(
             // use a loop to ensure we drain the buffer.
             // This is important for edge-triggered mode, as if the buffer isn't
             // drained, then it will never reset to notify us of new events.
-            match streams[index].read(&mut data) {
+            match streams[index].read(data) {
                 Ok(0) => {
                     // read 0 bytes - buffer has been drained successfully
 
@@ -143,9 +146,7 @@ fn handle_events(
 
                     handled_events += 1;
 
-                    println!(
-                        "\n\nBuffer drained after {i} iteration(s), breaking out of loop...\n"
-                    );
+                    log::debug!("Buffer drained after {i} iteration(s), breaking out of loop...");
                     println!("------------------------------------\n");
                     i = 0;
                     new_response = true;
@@ -1,5 +1,5 @@
 //! This module contains the main abstraction, which is a
-//! thin layer over epoll.
+//! thin layer over the event queue of the OS, epoll where available, see [`sys`].
 #![allow(dead_code, unused)]
 
 use std::{
@@ -8,9 +8,12 @@ use std::{
     os::fd::AsRawFd,
 };
 
-use crate::ffi;
+use crate::{
+    ffi, log, source,
+    sys::{self, DefaultSelector, Selector},
+};
 
-type Events = Vec<ffi::Event>;
+type Events = sys::Events;
 
 /// Represents the event queue itself.
 pub struct Poll {
@@ -21,16 +24,12 @@ pub struct Poll {
 impl Poll {
     /// Create a new event queue
     pub fn new() -> Result<Self> {
-        let res = unsafe { ffi::epoll_create(1) };
-
-        if res < 0 {
-            return Err(io::Error::last_os_error());
-        }
-
         Ok(Self {
-            // The registry wraps the epoll file descriptor.
+            // The registry wraps the event queue of the OS, e.g. the epoll file descriptor.
             // When Poll is dropped, the registry is also dropped.
-            registry: Registry { raw_fd: res },
+            registry: Registry {
+                selector: DefaultSelector::new()?,
+            },
         })
     }
 
@@ -47,85 +46,40 @@ impl Poll {
     ///
     /// # Arguments
     ///
-    /// `events`: buffer for epoll to populate with Events from it's ready_list. timout: the maximum
-    /// amount of time to block on epoll_wait. A timeout of None means block until an event is
-    /// ready or a signal interrupts the call.
-    ///
-    /// # Other Information
-    ///
-    /// `maxevents`: the maximum number of events to return from epoll_wait, for now this is the
-    /// capacity of the events Vec. If there are more events in epoll's ready list than maxevents,
-    /// epoll will use a round-robin approach to return events. This prevents startvation of events
-    /// in the ready list, if only a few events were continually being returned.
+    /// `events`: buffer for the event queue to populate with the events that are ready, at most
+    /// as many as it has capacity for. timout: the maximum amount of time to block. A timeout of
+    /// None means block until an event is ready or a signal interrupts the call.
     pub fn poll(&mut self, events: &mut Events, timeout: Option<i32>) -> Result<()> {
-        let epfd = self.registry.raw_fd;
-
-        // a timeout of -1 means block indefinitely
-        let timeout = timeout.unwrap_or(-1);
-        let max_events = events.capacity() as i32;
-
-        // Catch case where no buffer space has been allocated
-        if max_events == 0 {
-            events.reserve(10);
-        }
-
-        // block on epoll_wait
-        let res = unsafe { ffi::epoll_wait(epfd, events.as_mut_ptr(), max_events, timeout) };
-
-        // we would get a res of 0 if a timeout occurs before an event has happened
-        if res < 0 {
-            return Err(io::Error::last_os_error());
-        }
-
-        // On notification, `events` should be populated with at most max_events
-        // so we must set the length of `events`, which epoll would not have done when populating
-        // the buffer.
-        unsafe { events.set_len(res as usize) };
-        Ok(())
+        self.registry.selector.select(events, timeout)
     }
 }
 
 /// A handle that allows us to register interest in new events
 pub struct Registry {
-    raw_fd: i32, // 4 bytes
+    selector: DefaultSelector,
 }
 
 impl Registry {
-    /// interests: indicates what kind of event we are interested in
+    /// interests: indicates what kind of event we are interested in, as an epoll bitmask
+    ///
+    /// Any open fd can be registered, see [`source`] for sources other than sockets. Fails with
+    /// EBADF if the fd is not open.
     pub fn register<T>(&self, source: &T, token: usize, interests: i32) -> Result<()>
     where
         T: AsRawFd,
     {
-        // create a new event (dropped at end of this method)
-        let mut event = ffi::Event {
-            events: interests as u32, // bitmask for events we are interested in
-            epoll_data: token,
-        };
-
-        // only use the `add` flag
-        let op = ffi::EPOLL_CTL_ADD;
-        ffi::print_event_debug(&event);
-        ffi::check(event.events as i32);
-
-        let res = unsafe { ffi::epoll_ctl(self.raw_fd, op, source.as_raw_fd(), &mut event) };
-
-        if res < 0 {
-            return Err(io::Error::last_os_error());
-        }
-
-        Ok(())
+        let fd = source.as_raw_fd();
+        source::check_fd(fd)?;
+        self.selector.register(fd, token, interests)
     }
-}
-
-impl Drop for Registry {
-    /// Close the epoll file descriptor
-    fn drop(&mut self) {
-        let res = unsafe { ffi::close(self.raw_fd) };
 
-        if res < 0 {
-            let err = io::Error::last_os_error();
-            eprintln!("error closing epoll file descriptor: {err:?}");
-        }
+    /// Stop watching `source`, e.g. before closing it or to register it again with other
+    /// interests. Fails with `NotFound` if it is not registered.
+    pub fn deregister<T>(&self, source: &T) -> Result<()>
+    where
+        T: AsRawFd,
+    {
+        self.selector.deregister(source.as_raw_fd())
     }
 }
 
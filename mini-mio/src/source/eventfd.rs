@@ -0,0 +1,44 @@
+//! Event counters, see `eventfd(2)`.
+use std::{
+    fs::File,
+    io::{self, Read, Result, Write},
+    os::fd::{AsRawFd, FromRawFd, RawFd},
+};
+
+use crate::ffi;
+
+/// A 64-bit counter that is readable while it is not zero, e.g. to wake an event loop from
+/// another thread with a single fd rather than a [`pipe`](super::pipe). Closed on drop.
+#[derive(Debug)]
+pub struct EventFd(File);
+
+impl EventFd {
+    /// Create a counter, starting at zero.
+    pub fn new() -> Result<Self> {
+        let fd = unsafe { ffi::eventfd(0, ffi::EFD_NONBLOCK | ffi::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: the fd was just created, and is owned by nothing else
+        Ok(Self(unsafe { File::from_raw_fd(fd) }))
+    }
+
+    /// Add `value` to the counter.
+    pub fn write(&self, value: u64) -> Result<()> {
+        (&self.0).write_all(&value.to_ne_bytes())
+    }
+
+    /// Take the counter, resetting it to zero. Fails with `WouldBlock` while it is zero.
+    pub fn read(&self) -> Result<u64> {
+        let mut value = [0u8; 8];
+        (&self.0).read_exact(&mut value)?;
+        Ok(u64::from_ne_bytes(value))
+    }
+}
+
+impl AsRawFd for EventFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
@@ -0,0 +1,160 @@
+//! Event sources other than sockets.
+//!
+//! [`Registry::register`](crate::poll::Registry::register) takes anything with a file
+//! descriptor. [`SourceFd`] registers a bare [`RawFd`] that is owned elsewhere, and the
+//! wrappers here create the fds most often waited on besides sockets, in non-blocking mode:
+//! - [`pipe`], a pair of connected fds, e.g. to wake an event loop from another thread
+//! - [`EventFd`], a counter that is readable while non-zero (Linux only)
+//! - [`TimerFd`], a timer that is readable once expired (Linux only)
+//!
+//! ```ignore
+//! let timer = TimerFd::new()?;
+//! timer.set(Duration::from_millis(100))?;
+//! poll.registry().register(&timer, 0, ffi::EPOLLIN)?;
+//! ```
+use std::{
+    io::{self, Result},
+    os::fd::{AsRawFd, RawFd},
+};
+
+use crate::ffi;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod eventfd;
+mod pipe;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod timerfd;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use eventfd::EventFd;
+pub use pipe::{pipe, Receiver, Sender};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use timerfd::TimerFd;
+
+/// Registers a raw fd it does not own, e.g. one handed over by another library.
+///
+/// The fd has to stay open while registered, and is not closed once this is dropped.
+#[derive(Debug)]
+pub struct SourceFd<'a>(pub &'a RawFd);
+
+impl AsRawFd for SourceFd<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        *self.0
+    }
+}
+
+/// Fail with EBADF unless `fd` is open, rather than leave it to each backend: `poll` would
+/// otherwise report a bad fd on every call, rather than once when registering it.
+pub(crate) fn check_fd(fd: RawFd) -> Result<()> {
+    if fd < 0 || unsafe { ffi::fcntl(fd, ffi::F_GETFD) } < 0 {
+        return Err(io::Error::from_raw_os_error(EBADF));
+    }
+    Ok(())
+}
+
+/// Bad file descriptor, the same on Linux, macOS and the BSDs
+const EBADF: i32 = 9;
+
+/// Put `fd` in non-blocking mode, so reads and writes fail with `WouldBlock` instead.
+pub(crate) fn set_nonblocking(fd: RawFd) -> Result<()> {
+    let flags = unsafe { ffi::fcntl(fd, ffi::F_GETFL) };
+    if flags < 0 || unsafe { ffi::fcntl(fd, ffi::F_SETFL, flags | ffi::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, time::Duration};
+
+    use super::*;
+    use crate::poll::Poll;
+
+    /// Tokens of the events that are ready within `timeout` milliseconds
+    fn ready(poll: &mut Poll, timeout: i32) -> Vec<usize> {
+        let mut events = Vec::with_capacity(10);
+        poll.poll(&mut events, Some(timeout)).unwrap();
+        events.iter().map(ffi::Event::token).collect()
+    }
+
+    #[test]
+    fn pipe_is_readable_once_written_to() {
+        let mut poll = Poll::new().unwrap();
+        let (mut sender, receiver) = pipe().unwrap();
+        poll.registry()
+            .register(&receiver, 1, ffi::EPOLLIN)
+            .unwrap();
+        assert!(ready(&mut poll, 0).is_empty());
+
+        sender.write_all(b"wake up").unwrap();
+        assert_eq!(ready(&mut poll, 1000), [1]);
+    }
+
+    #[test]
+    fn deregistered_pipe_is_no_longer_watched() {
+        let mut poll = Poll::new().unwrap();
+        let (mut sender, receiver) = pipe().unwrap();
+        poll.registry()
+            .register(&receiver, 1, ffi::EPOLLIN)
+            .unwrap();
+        poll.registry().deregister(&receiver).unwrap();
+
+        sender.write_all(b"wake up").unwrap();
+        assert!(ready(&mut poll, 0).is_empty());
+        let err = poll.registry().deregister(&receiver).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        // and may be registered again
+        poll.registry()
+            .register(&receiver, 2, ffi::EPOLLIN)
+            .unwrap();
+        assert_eq!(ready(&mut poll, 1000), [2]);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn eventfd_is_readable_while_non_zero() {
+        let mut poll = Poll::new().unwrap();
+        let counter = EventFd::new().unwrap();
+        poll.registry().register(&counter, 2, ffi::EPOLLIN).unwrap();
+        assert!(ready(&mut poll, 0).is_empty());
+
+        counter.write(2).unwrap();
+        counter.write(3).unwrap();
+        assert_eq!(ready(&mut poll, 1000), [2]);
+        assert_eq!(counter.read().unwrap(), 5);
+        let err = counter.read().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn timerfd_is_readable_once_expired() {
+        let mut poll = Poll::new().unwrap();
+        let timer = TimerFd::new().unwrap();
+        poll.registry().register(&timer, 3, ffi::EPOLLIN).unwrap();
+
+        timer.set(Duration::from_millis(20)).unwrap();
+        assert!(ready(&mut poll, 0).is_empty());
+        assert_eq!(ready(&mut poll, 1000), [3]);
+        assert_eq!(timer.read().unwrap(), 1);
+    }
+
+    #[test]
+    fn raw_fds_are_checked_before_registering() {
+        let poll = Poll::new().unwrap();
+        let (_sender, receiver) = pipe().unwrap();
+        let fd = receiver.as_raw_fd();
+        poll.registry()
+            .register(&SourceFd(&fd), 4, ffi::EPOLLIN)
+            .unwrap();
+
+        drop(receiver);
+        let err = poll
+            .registry()
+            .register(&SourceFd(&fd), 5, ffi::EPOLLIN)
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(EBADF));
+    }
+}
@@ -0,0 +1,61 @@
+//! Unix pipes in non-blocking mode.
+use std::{
+    fs::File,
+    io::{self, Read, Result, Write},
+    os::fd::{AsRawFd, FromRawFd, RawFd},
+};
+
+use super::set_nonblocking;
+use crate::ffi;
+
+/// Create a pipe: bytes written to the [`Sender`] can be read from the [`Receiver`], which is
+/// readable while there are bytes to read, or once the sender is dropped.
+pub fn pipe() -> Result<(Sender, Receiver)> {
+    let mut fds = [-1; 2];
+    if unsafe { ffi::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: both fds were just created, and are owned by nothing else
+    let (receiver, sender) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    set_nonblocking(fds[0])?;
+    set_nonblocking(fds[1])?;
+
+    Ok((Sender(sender), Receiver(receiver)))
+}
+
+/// Writing end of a [`pipe`], closed on drop.
+#[derive(Debug)]
+pub struct Sender(File);
+
+/// Reading end of a [`pipe`], closed on drop.
+#[derive(Debug)]
+pub struct Receiver(File);
+
+impl Write for Sender {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for Receiver {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl AsRawFd for Sender {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsRawFd for Receiver {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
@@ -0,0 +1,73 @@
+//! Timers delivered via a file descriptor, see `timerfd_create(2)`.
+use std::{
+    fs::File,
+    io::{self, Read, Result},
+    os::fd::{AsRawFd, FromRawFd, RawFd},
+    ptr,
+    time::Duration,
+};
+
+use crate::ffi;
+
+/// A timer on the monotonic clock, readable once it expired. Closed on drop.
+#[derive(Debug)]
+pub struct TimerFd(File);
+
+impl TimerFd {
+    /// Create a timer, disarmed until [`TimerFd::set`] is called.
+    pub fn new() -> Result<Self> {
+        let fd = unsafe {
+            ffi::timerfd_create(ffi::CLOCK_MONOTONIC, ffi::TFD_NONBLOCK | ffi::TFD_CLOEXEC)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: the fd was just created, and is owned by nothing else
+        Ok(Self(unsafe { File::from_raw_fd(fd) }))
+    }
+
+    /// Expire once, after `after`. A zero duration disarms the timer.
+    pub fn set(&self, after: Duration) -> Result<()> {
+        self.settime(after, Duration::ZERO)
+    }
+
+    /// Expire after `every`, and every `every` from then on.
+    pub fn set_interval(&self, every: Duration) -> Result<()> {
+        self.settime(every, every)
+    }
+
+    /// Take the number of expirations since the last read. Fails with `WouldBlock` while there
+    /// were none.
+    pub fn read(&self) -> Result<u64> {
+        let mut expirations = [0u8; 8];
+        (&self.0).read_exact(&mut expirations)?;
+        Ok(u64::from_ne_bytes(expirations))
+    }
+
+    fn settime(&self, value: Duration, interval: Duration) -> Result<()> {
+        let spec = ffi::Itimerspec {
+            it_interval: timespec(interval),
+            it_value: timespec(value),
+        };
+
+        let res = unsafe { ffi::timerfd_settime(self.as_raw_fd(), 0, &spec, ptr::null_mut()) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+fn timespec(duration: Duration) -> ffi::Timespec {
+    ffi::Timespec {
+        tv_sec: duration.as_secs() as _,
+        tv_nsec: duration.subsec_nanos() as _,
+    }
+}
+
+impl AsRawFd for TimerFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
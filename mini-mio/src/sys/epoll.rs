@@ -0,0 +1,101 @@
+//! The epoll backend: the interest list is kept by the kernel, which hands back only the events
+//! that are ready.
+use std::{io, io::Result, os::fd::RawFd};
+
+use super::{Events, Selector};
+use crate::{ffi, log};
+
+/// Wraps the epoll file descriptor, which is closed on drop.
+pub struct Epoll {
+    raw_fd: i32, // 4 bytes
+}
+
+impl Selector for Epoll {
+    fn new() -> Result<Self> {
+        let res = unsafe { ffi::epoll_create(1) };
+
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { raw_fd: res })
+    }
+
+    fn register(&self, fd: RawFd, token: usize, interests: i32) -> Result<()> {
+        // create a new event (dropped at end of this method)
+        let mut event = ffi::Event {
+            events: interests as u32, // bitmask for events we are interested in
+            epoll_data: token,
+        };
+
+        // only use the `add` flag
+        let op = ffi::EPOLL_CTL_ADD;
+        ffi::print_event_debug(&event);
+        ffi::check(event.events as i32);
+
+        let res = unsafe { ffi::epoll_ctl(self.raw_fd, op, fd, &mut event) };
+
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    fn deregister(&self, fd: RawFd) -> Result<()> {
+        // ignored, but kernels before 2.6.9 require a non-null event
+        let mut event = ffi::Event {
+            events: 0,
+            epoll_data: 0,
+        };
+
+        let res = unsafe { ffi::epoll_ctl(self.raw_fd, ffi::EPOLL_CTL_DEL, fd, &mut event) };
+
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// `maxevents`: the maximum number of events to return from epoll_wait, for now this is the
+    /// capacity of the events Vec. If there are more events in epoll's ready list than maxevents,
+    /// epoll will use a round-robin approach to return events. This prevents startvation of events
+    /// in the ready list, if only a few events were continually being returned.
+    fn select(&self, events: &mut Events, timeout: Option<i32>) -> Result<()> {
+        // a timeout of -1 means block indefinitely
+        let timeout = timeout.unwrap_or(-1);
+
+        // Catch case where no buffer space has been allocated
+        if events.capacity() == 0 {
+            events.reserve(10);
+        }
+        let max_events = events.capacity() as i32;
+
+        // block on epoll_wait
+        let res = unsafe { ffi::epoll_wait(self.raw_fd, events.as_mut_ptr(), max_events, timeout) };
+
+        // we would get a res of 0 if a timeout occurs before an event has happened
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // On notification, `events` should be populated with at most max_events
+        // so we must set the length of `events`, which epoll would not have done when populating
+        // the buffer.
+        unsafe { events.set_len(res as usize) };
+        Ok(())
+    }
+}
+
+impl Drop for Epoll {
+    /// Close the epoll file descriptor
+    fn drop(&mut self) {
+        let res = unsafe { ffi::close(self.raw_fd) };
+
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            log::error!("error closing epoll file descriptor: {err:?}");
+        }
+    }
+}
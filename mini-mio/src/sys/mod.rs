@@ -0,0 +1,46 @@
+//! The OS facilities a [`Poll`](crate::poll::Poll) can be built on, behind a shared trait.
+//!
+//! Which one is used is decided at compile time:
+//! - epoll on Linux and Android, see [`epoll`]
+//! - `poll(2)` everywhere else, see [`poll`]. That includes the platforms with kqueue (macOS and
+//!   the BSDs) until there is a kqueue backend, which would implement [`Selector`] as well.
+//!
+//! Interests and events are epoll bitmasks (`ffi::EPOLLIN` and friends) for all of them, so
+//! callers do not need to know which one they run on.
+#![allow(dead_code, unused)]
+
+use std::{io::Result, os::fd::RawFd};
+
+use crate::ffi;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod epoll;
+pub mod poll;
+
+/// Buffer a selector fills in with the events that are ready
+pub type Events = Vec<ffi::Event>;
+
+/// Backend of the event queue of the current platform
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub type DefaultSelector = epoll::Epoll;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub type DefaultSelector = poll::PollSelector;
+
+/// An event queue provided by the OS.
+pub trait Selector: Sized + Send + Sync {
+    /// Create a new, empty event queue.
+    fn new() -> Result<Self>;
+
+    /// Register interest in `interests` (an epoll bitmask) for `fd`, to be reported with `token`.
+    fn register(&self, fd: RawFd, token: usize, interests: i32) -> Result<()>;
+
+    /// Stop watching `fd`, so it may be registered again. Fails with `NotFound` if it is not
+    /// registered.
+    fn deregister(&self, fd: RawFd) -> Result<()>;
+
+    /// Block until an event is ready or `timeout` milliseconds passed, whichever occurs first.
+    /// A timeout of None blocks until an event is ready or a signal interrupts the call.
+    ///
+    /// `events` is overwritten with at most as many events as it has capacity for.
+    fn select(&self, events: &mut Events, timeout: Option<i32>) -> Result<()>;
+}
@@ -0,0 +1,249 @@
+//! The portable `poll(2)` backend, for platforms without epoll.
+//!
+//! The interest list lives in user space: every call to [`Selector::select`] hands all
+//! registered fds to the kernel, and scans them for the ones that are ready. That is linear in
+//! the number of fds, but works on any Unix.
+//!
+//! Unlike epoll, it differs in a few ways callers should know about:
+//! - readiness is level-triggered, `EPOLLET` is ignored. An fd that is ready stays ready, and is
+//!   reported on every call, until it is drained.
+//! - a single call may only be given as many fds as the `RLIMIT_NOFILE` limit allows, or it
+//!   fails with EINVAL. Registering beyond that limit fails instead, see [`PollSelector::limit`].
+//!   `select(2)` has a harder limit yet, `FD_SETSIZE` (1024), and can not watch fds numbered
+//!   above it at all, which is why it is not used.
+//! - fds registered while another thread is blocked in `select` are only watched from the
+//!   next call on.
+//! - fds closed while registered are reported by the kernel as invalid. They are dropped from
+//!   the interest list, as there is no way to deregister them.
+use std::{
+    io::{self, ErrorKind, Result},
+    os::fd::RawFd,
+    sync::Mutex,
+};
+
+use super::{Events, Selector};
+use crate::{ffi, log};
+
+/// The registered fds, and where to start reporting events from
+#[derive(Debug, Default)]
+struct Interests {
+    fds: Vec<ffi::PollFd>,
+    /// Token of each of `fds`, at the same index
+    tokens: Vec<usize>,
+    /// Index of the fd to report first on the next call. Rotated, so that when more fds are ready
+    /// than `events` has room for, the ones at the end are not starved.
+    next: usize,
+}
+
+/// Event queue kept in user space, see the module docs.
+pub struct PollSelector {
+    interests: Mutex<Interests>,
+    /// Most fds that may be registered at once
+    limit: usize,
+}
+
+impl PollSelector {
+    /// Most fds that may be registered at once: the number `poll` accepts in a single call.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl Selector for PollSelector {
+    fn new() -> Result<Self> {
+        let mut rlimit = ffi::Rlimit::default();
+        let res = unsafe { ffi::getrlimit(ffi::RLIMIT_NOFILE, &mut rlimit) };
+
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            interests: Mutex::new(Interests::default()),
+            // RLIM_INFINITY is all bits set
+            limit: rlimit.rlim_cur.min(i32::MAX as u64) as usize,
+        })
+    }
+
+    fn register(&self, fd: RawFd, token: usize, interests: i32) -> Result<()> {
+        if fd < 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid file descriptor: {fd}"),
+            ));
+        }
+
+        let mut registered = self.interests.lock().unwrap();
+        if registered.fds.iter().any(|pollfd| pollfd.fd == fd) {
+            // same as EEXIST from epoll_ctl
+            return Err(ErrorKind::AlreadyExists.into());
+        }
+        if registered.fds.len() >= self.limit {
+            return Err(io::Error::other(format!(
+                "poll can not watch more than {} fds at once",
+                self.limit
+            )));
+        }
+
+        let mut events = 0;
+        if interests & ffi::EPOLLIN != 0 {
+            events |= ffi::POLLIN;
+        }
+        if interests & ffi::EPOLLOUT != 0 {
+            events |= ffi::POLLOUT;
+        }
+        log::debug!("Registering fd {fd} for poll events {events:#x}");
+
+        registered.fds.push(ffi::PollFd {
+            fd,
+            events,
+            revents: 0,
+        });
+        registered.tokens.push(token);
+        Ok(())
+    }
+
+    fn deregister(&self, fd: RawFd) -> Result<()> {
+        let mut registered = self.interests.lock().unwrap();
+        if registered.token(fd).is_none() {
+            // same as ENOENT from epoll_ctl
+            return Err(ErrorKind::NotFound.into());
+        }
+
+        log::debug!("Deregistering fd {fd}");
+        registered.remove(fd);
+        Ok(())
+    }
+
+    fn select(&self, events: &mut Events, timeout: Option<i32>) -> Result<()> {
+        // a timeout of -1 means block indefinitely
+        let timeout = timeout.unwrap_or(-1);
+
+        // Catch case where no buffer space has been allocated
+        if events.capacity() == 0 {
+            events.reserve(10);
+        }
+
+        // poll on a copy, so other threads can register while we block
+        let mut fds = self.interests.lock().unwrap().fds.clone();
+
+        // block on poll
+        let res = unsafe { ffi::poll(fds.as_mut_ptr(), fds.len() as ffi::Nfds, timeout) };
+
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        events.clear();
+        let mut registered = self.interests.lock().unwrap();
+        let start = registered.next;
+        for i in (0..fds.len()).map(|i| (start + i) % fds.len()) {
+            let pollfd = fds[i];
+            if pollfd.revents == 0 {
+                continue;
+            }
+
+            if pollfd.revents & ffi::POLLNVAL != 0 {
+                log::warn!("fd {} was closed while registered, dropping it", pollfd.fd);
+                registered.remove(pollfd.fd);
+                continue;
+            }
+            if events.len() == events.capacity() {
+                // report this one first next time
+                registered.next = i;
+                return Ok(());
+            }
+
+            // errors and hang ups are reported as readable, the read then returns them
+            let mut ready = 0;
+            if pollfd.revents & (ffi::POLLIN | ffi::POLLERR | ffi::POLLHUP) != 0 {
+                ready |= ffi::EPOLLIN;
+            }
+            if pollfd.revents & ffi::POLLOUT != 0 {
+                ready |= ffi::EPOLLOUT;
+            }
+            events.push(ffi::Event {
+                events: ready as u32,
+                epoll_data: registered.token(pollfd.fd).unwrap_or_default(),
+            });
+        }
+
+        registered.next = 0;
+        Ok(())
+    }
+}
+
+impl Interests {
+    /// Token `fd` was registered with, if it still is
+    fn token(&self, fd: RawFd) -> Option<usize> {
+        let index = self.fds.iter().position(|pollfd| pollfd.fd == fd)?;
+        Some(self.tokens[index])
+    }
+
+    fn remove(&mut self, fd: RawFd) {
+        if let Some(index) = self.fds.iter().position(|pollfd| pollfd.fd == fd) {
+            self.fds.swap_remove(index);
+            self.tokens.swap_remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, os::fd::AsRawFd, os::unix::net::UnixStream};
+
+    use super::*;
+
+    /// Tokens of the events in `events`
+    fn tokens(events: &Events) -> Vec<usize> {
+        events.iter().map(ffi::Event::token).collect()
+    }
+
+    #[test]
+    fn readable_sockets_are_reported_with_their_token() {
+        let selector = PollSelector::new().unwrap();
+        let (mut a, b) = UnixStream::pair().unwrap();
+        let (_c, d) = UnixStream::pair().unwrap();
+        selector.register(b.as_raw_fd(), 1, ffi::EPOLLIN).unwrap();
+        selector.register(d.as_raw_fd(), 2, ffi::EPOLLIN).unwrap();
+
+        let mut events = Vec::with_capacity(10);
+        selector.select(&mut events, Some(0)).unwrap();
+        assert!(events.is_empty());
+
+        a.write_all(b"hello").unwrap();
+        selector.select(&mut events, Some(1000)).unwrap();
+        assert_eq!(tokens(&events), [1]);
+        assert_eq!(events[0].events as i32, ffi::EPOLLIN);
+
+        let err = selector
+            .register(b.as_raw_fd(), 3, ffi::EPOLLIN)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        selector.deregister(b.as_raw_fd()).unwrap();
+        selector.select(&mut events, Some(0)).unwrap();
+        assert!(events.is_empty(), "still readable, but no longer watched");
+        selector.register(b.as_raw_fd(), 3, ffi::EPOLLIN).unwrap();
+        let err = selector.deregister(a.as_raw_fd()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn full_event_buffer_does_not_starve_later_fds() {
+        let selector = PollSelector::new().unwrap();
+        // writable straight away, and stay so
+        let pairs: Vec<_> = (0..3).map(|_| UnixStream::pair().unwrap()).collect();
+        for (token, (a, _)) in pairs.iter().enumerate() {
+            selector
+                .register(a.as_raw_fd(), token, ffi::EPOLLOUT)
+                .unwrap();
+        }
+
+        let mut events = Vec::with_capacity(2);
+        selector.select(&mut events, Some(0)).unwrap();
+        assert_eq!(tokens(&events), [0, 1]);
+        selector.select(&mut events, Some(0)).unwrap();
+        assert_eq!(tokens(&events), [2, 0]);
+    }
+}
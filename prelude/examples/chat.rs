@@ -0,0 +1,107 @@
+//! A chat server over TCP: each connection is split into halves owned by a task reading the
+//! client's lines and a task writing everyone else's, with a hub task passing lines between
+//! them. Two blocking clients on threads of their own say hello to each other.
+//!
+//! ```bash
+//! cargo run -p prelude --example chat
+//! ```
+use std::{
+    io::{BufRead, BufReader as StdBufReader, Write},
+    net::SocketAddr,
+    sync::{Arc, Barrier},
+    thread,
+};
+
+use prelude::*;
+
+const CLIENTS: usize = 2;
+
+/// What the connection tasks tell the hub
+enum Event {
+    Join(usize, sync::mpsc::Sender<String>),
+    Line(usize, String),
+    Leave(usize),
+}
+
+fn main() {
+    let mut executor = runtime::init();
+    let mut listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind");
+    let addr = listener.local_addr().unwrap();
+
+    let barrier = Arc::new(Barrier::new(CLIENTS));
+    let clients: Vec<_> = (0..CLIENTS)
+        .map(|id| {
+            let barrier = barrier.clone();
+            thread::spawn(move || client(id, addr, &barrier))
+        })
+        .collect();
+
+    executor.block_on(async move {
+        let (events, hub_events) = sync::mpsc::channel();
+        spawn(hub(hub_events));
+
+        for id in 0..CLIENTS {
+            let (conn, _) = listener.accept().await.expect("Failed to accept");
+            let (reader, mut writer) = conn.into_split();
+            let (tx, mut rx) = sync::mpsc::channel::<String>();
+            events.send(Event::Join(id, tx)).unwrap();
+
+            let events = events.clone();
+            spawn(async move {
+                let mut reader = BufReader::new(reader);
+                let mut line = String::new();
+                while reader.read_line(&mut line).await.unwrap_or(0) > 0 {
+                    events.send(Event::Line(id, line.clone())).unwrap();
+                    line.clear();
+                }
+                events.send(Event::Leave(id)).unwrap();
+            });
+
+            // closes the connection, along with the reader above, once the hub lets go
+            spawn(async move {
+                while let Some(line) = rx.recv().await {
+                    if writer.write_all(line.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    for client in clients {
+        println!("{}", client.join().unwrap());
+    }
+}
+
+/// Sends each line to every client but the one it came from, until all clients left.
+async fn hub(mut events: sync::mpsc::Receiver<Event>) {
+    let mut clients = Vec::new();
+    while let Some(event) = events.recv().await {
+        match event {
+            Event::Join(id, tx) => {
+                let _ = tx.send(format!("welcome client {id}\n"));
+                clients.push((id, tx));
+            }
+            Event::Line(from, line) => {
+                for (_, tx) in clients.iter().filter(|(id, _)| *id != from) {
+                    let _ = tx.send(format!("client {from}: {line}"));
+                }
+            }
+            Event::Leave(id) => clients.retain(|(other, _)| *other != id),
+        }
+    }
+}
+
+/// Says hello once every client joined, then leaves after hearing from another client.
+fn client(id: usize, addr: SocketAddr, barrier: &Barrier) -> String {
+    let mut stream = std::net::TcpStream::connect(addr).expect("Failed to connect");
+    let mut lines = StdBufReader::new(stream.try_clone().unwrap()).lines();
+    let welcome = lines.next().unwrap().unwrap();
+
+    barrier.wait();
+    writeln!(stream, "hello from {id}").unwrap();
+    let heard = lines.next().unwrap().unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+    format!("{welcome}, heard \"{heard}\"")
+}
@@ -0,0 +1,34 @@
+//! Echo a message off a server task over a Unix socket, with nothing but the prelude.
+//!
+//! ```bash
+//! cargo run -p prelude --example echo
+//! ```
+use prelude::*;
+
+fn main() {
+    let path = std::env::temp_dir().join(format!("prelude-echo-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut executor = runtime::init();
+    let mut listener = UnixListener::bind(&path).expect("Failed to bind socket path");
+    let client_path = path.clone();
+
+    executor.block_on(async move {
+        spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut msg = Vec::new();
+            conn.read_to_end(&mut msg).await.unwrap();
+            conn.write_all(&msg).await.unwrap();
+        });
+
+        let mut stream = UnixStream::connect(&client_path).expect("Failed to connect");
+        stream.write_all(b"hello prelude").await.unwrap();
+        stream.shutdown_write().unwrap();
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await.unwrap();
+        println!("Echoed: {}", String::from_utf8_lossy(&reply));
+    });
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,140 @@
+//! A TLS server for two sites on one port: the hostname a client asks for picks both the
+//! certificate the handshake is done with and the task handling the connection. Blocking
+//! clients on threads of their own visit each site.
+//!
+//! ```bash
+//! cargo run -p prelude --example sni_server --features tls
+//! ```
+use std::{
+    io::{Read, Write},
+    net::SocketAddr,
+    sync::Arc,
+    thread,
+};
+
+use prelude::*;
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName},
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig, StreamOwned,
+};
+
+/// The test certificates of `reactor_executor::tls`, signed by a CA of their own
+const CA_CERT: &[u8] = include_bytes!("../../reactor-executor/src/tls/testdata/ca.der");
+const ALPHA_CERT: &[u8] = include_bytes!("../../reactor-executor/src/tls/testdata/alpha.der");
+const ALPHA_KEY: &[u8] = include_bytes!("../../reactor-executor/src/tls/testdata/alpha.key.der");
+const BETA_CERT: &[u8] = include_bytes!("../../reactor-executor/src/tls/testdata/beta.der");
+const BETA_KEY: &[u8] = include_bytes!("../../reactor-executor/src/tls/testdata/beta.key.der");
+
+/// Hostnames the clients ask for, `*.beta.test` covers the last two
+const VISITS: [&str; 3] = ["alpha.test", "www.beta.test", "api.beta.test"];
+
+/// Handlers the hostnames are routed to
+#[derive(Clone, Copy)]
+enum Site {
+    /// Greets the client once it is done sending
+    Alpha,
+    /// Echoes what the client sent, in upper case
+    Beta,
+}
+
+fn main() {
+    let mut executor = runtime::init();
+    let mut listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind");
+    let addr = listener.local_addr().unwrap();
+
+    let acceptor = Arc::new(SniAcceptor::new(
+        SniRouter::new()
+            .route("alpha.test", server_config(ALPHA_CERT, ALPHA_KEY))
+            .route("*.beta.test", server_config(BETA_CERT, BETA_KEY)),
+    ));
+    let sites = Arc::new(
+        SniRouter::new()
+            .route("alpha.test", Site::Alpha)
+            .route("*.beta.test", Site::Beta),
+    );
+
+    let clients: Vec<_> = VISITS
+        .into_iter()
+        .map(|hostname| thread::spawn(move || visit(addr, hostname)))
+        .collect();
+
+    executor.block_on(async move {
+        for _ in 0..VISITS.len() {
+            let (conn, _) = listener.accept().await.unwrap();
+            let (acceptor, sites) = (acceptor.clone(), sites.clone());
+
+            spawn(async move {
+                let (hostname, stream) = match acceptor.accept(conn).await {
+                    Ok(accepted) => accepted,
+                    Err(e) => return eprintln!("Handshake failed: {e}"),
+                };
+
+                let result = match sites.lookup(hostname.as_deref()) {
+                    Some(Site::Alpha) => alpha(stream, hostname.unwrap()).await,
+                    Some(Site::Beta) => beta(stream).await,
+                    None => Ok(()),
+                };
+                if let Err(e) = result {
+                    eprintln!("Connection failed: {e}");
+                }
+            });
+        }
+    });
+
+    for client in clients {
+        client.join().unwrap();
+    }
+}
+
+async fn alpha(mut stream: TlsServerStream, hostname: String) -> std::io::Result<()> {
+    // whatever the client sends is ignored, but read, so closing does not reset the connection
+    let mut buf = [0u8; 1024];
+    while stream.read(&mut buf).await? > 0 {}
+
+    let greeting = format!("Welcome to {hostname}");
+    stream.write_all(greeting.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+async fn beta(mut stream: TlsServerStream) -> std::io::Result<()> {
+    let mut buf = [0u8; 1024];
+    loop {
+        match stream.read(&mut buf).await? {
+            0 => break,
+            n => stream.write_all(&buf[..n].to_ascii_uppercase()).await?,
+        }
+    }
+    stream.shutdown().await
+}
+
+fn server_config(cert: &'static [u8], key: &'static [u8]) -> Arc<ServerConfig> {
+    let key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(key));
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![CertificateDer::from(cert)], key)
+        .expect("Invalid certificate");
+    Arc::new(config)
+}
+
+/// Connect asking for `hostname`, say hello and print what the site sent back.
+fn visit(addr: SocketAddr, hostname: &'static str) {
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from(CA_CERT)).unwrap();
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(hostname).unwrap();
+
+    let conn = ClientConnection::new(Arc::new(config), name).unwrap();
+    let socket = std::net::TcpStream::connect(addr).expect("Failed to connect");
+    let mut stream = StreamOwned::new(conn, socket);
+
+    stream.write_all(b"hello").unwrap();
+    // the close_notify lets the site know we are done sending
+    stream.conn.send_close_notify();
+    stream.flush().unwrap();
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).unwrap();
+    println!("{hostname}: {reply}");
+}
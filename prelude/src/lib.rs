@@ -0,0 +1,73 @@
+//! The runtime's API for examples and experiments, in one import:
+//!
+//! ```ignore
+//! use prelude::*;
+//! ```
+//!
+//! Rather than copying modules of `reactor-executor` into every binary, depend on this crate.
+//! The executor, the reactor, the stream adaptors and the `join!`/`select!` macros are always
+//! exported. The rest comes in sections, one per cargo feature, all enabled by default:
+//!
+//! - `net`: TCP, Unix and UDP sockets driven by the reactor, and the io_uring sockets
+//! - `http`: the HTTP client
+//! - `sim`: the simulated network and its virtual clock
+//!
+//! `tls` adds `Http::get_https` and friends to the HTTP client, and `SniAcceptor` for TLS
+//! servers with a certificate per hostname. It is off by default, as it pulls in rustls.
+//!
+//! The `Future` trait is the one from std. The runtime's own `future::Future` predates the
+//! move to std futures, and is left out so it does not shadow it.
+
+pub use reactor_executor::{self, future, runtime};
+
+pub use reactor_executor::future::{join, join_all, select, FuturesUnordered, Stream, StreamExt};
+pub use reactor_executor::runtime::{
+    reactor, reserve, scope, sleep, sleep_until, spawn, spawn_blocking, spawn_local,
+    spawn_with_priority, sync, task_local, try_spawn, yield_now, Backend, Backpressure, Executor,
+    ExitPolicy, Handle, LocalSet, Priority, Sleep, TaskLocal,
+};
+
+#[cfg(feature = "net")]
+pub use reactor_executor::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter,
+};
+
+#[cfg(feature = "net")]
+pub use reactor_executor::net::{
+    self, TcpListener, TcpStream, UdpSocket, UnixListener, UnixStream,
+};
+
+#[cfg(feature = "http")]
+pub use reactor_executor::http::{self, BodyStream, Http, HttpError, Method, Pipeline, Response};
+
+#[cfg(feature = "tls")]
+pub use reactor_executor::tls::{self, SniAcceptor, SniRouter, TlsServerStream};
+
+#[cfg(feature = "sim")]
+pub use reactor_executor::sim::{self, Link, Network};
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    #[test]
+    fn prelude_is_enough_to_run_tasks() {
+        let mut executor = runtime::init();
+        let done = Rc::new(Cell::new(0));
+        let counter = done.clone();
+
+        executor.block_on(async move {
+            let (tx, rx) = sync::oneshot::channel();
+            spawn(async move {
+                tx.send(21);
+            });
+
+            let doubled = join_all(vec![async { 2 }]).await;
+            counter.set(rx.await.unwrap() * doubled[0]);
+        });
+
+        assert_eq!(done.get(), 42);
+    }
+}
@@ -0,0 +1,209 @@
+//! End to end comparison of the runtime's scheduling models.
+//!
+//! Each model makes `ROUNDS` rounds of `REQUESTS` concurrent requests to the in-process
+//! [`MockServer`], which answers every request after `DELAY_MS`:
+//!
+//! - busy-poll: the naive runtime of the early chapters. Every pending request is polled in a
+//!   loop until all are done, with a waker that does nothing, so the thread never sleeps.
+//! - single: one [`Executor`], parked until the reactor wakes one of its tasks.
+//! - multi: `THREADS` executors, one per thread, sharing the reactor, each making an equal
+//!   share of the requests.
+//!
+//! Reported per model are the throughput, how often requests were polled and woken, how often
+//! executor threads were unparked, and the syscalls made by the reactor. Micro benchmarks of
+//! single parts of the runtime live in `src/bench.rs`, and are run from the binary.
+//!
+//! ```bash
+//! cargo bench -p reactor-executor --bench scheduling
+//! ```
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use reactor_executor::{
+    http::{Client, HttpError, Response},
+    mock::MockServer,
+    runtime::{self, spawn, DispatchStats, Executor},
+};
+
+const REQUESTS: usize = 200;
+const ROUNDS: usize = 5;
+const THREADS: usize = 4;
+/// Time the server takes to answer, during which the requests are pending
+const DELAY_MS: u64 = 10;
+
+/// A scheduling model, making all requests of a run and returning the polls and wakes it counted
+type Model = fn(&Client, &str) -> (usize, usize);
+
+/// Counters of a run, as differences of the runtime's counters before and after
+#[derive(Debug, Default)]
+struct Run {
+    elapsed: Duration,
+    polls: usize,
+    wakes: usize,
+    dispatch: DispatchStats,
+    ctl_calls: usize,
+}
+
+fn main() {
+    // the reactor can only be started once per process, all models share it
+    runtime::init();
+    let server = MockServer::start();
+    let path = format!("/{DELAY_MS}/ok");
+
+    println!(
+        "== scheduling: {ROUNDS} rounds of {REQUESTS} concurrent requests, {DELAY_MS} ms each =="
+    );
+    println!(
+        "{:<10} {:>12} {:>10} {:>10} {:>10} {:>10} {:>12} {:>10}",
+        "", "time", "req/s", "polls", "wakes", "unparks", "epoll_wait", "epoll_ctl"
+    );
+
+    let models: [(&str, Model); 3] = [
+        ("busy-poll", busy_poll),
+        ("single", single),
+        ("multi", multi),
+    ];
+    for (name, model) in models {
+        let run = measure(|| model(&server.client(), &path));
+        let requests = ROUNDS * REQUESTS;
+
+        println!(
+            "{name:<10} {:>12?} {:>10.0} {:>10} {:>10} {:>10} {:>12} {:>10}",
+            run.elapsed,
+            requests as f64 / run.elapsed.as_secs_f64(),
+            run.polls,
+            run.wakes,
+            run.dispatch.unparks,
+            run.dispatch.iterations,
+            run.ctl_calls,
+        );
+    }
+}
+
+/// Run `model`, which returns the polls and wakes it counted, along with the reactor's
+/// counters over the run.
+fn measure(model: impl FnOnce() -> (usize, usize)) -> Run {
+    let reactor = runtime::reactor();
+    let (dispatch, ctl_calls) = (reactor.dispatch_stats(), reactor.ctl_calls());
+    let start = Instant::now();
+
+    let (polls, wakes) = model();
+
+    let elapsed = start.elapsed();
+    let after = reactor.dispatch_stats();
+    Run {
+        elapsed,
+        polls,
+        wakes,
+        dispatch: DispatchStats {
+            iterations: after.iterations - dispatch.iterations,
+            ticks: after.ticks - dispatch.ticks,
+            events: after.events - dispatch.events,
+            woken: after.woken - dispatch.woken,
+            unparks: after.unparks - dispatch.unparks,
+            stale: after.stale - dispatch.stale,
+            full_batches: after.full_batches - dispatch.full_batches,
+            batch_sizes: std::array::from_fn(|bucket| {
+                after.batch_sizes[bucket] - dispatch.batch_sizes[bucket]
+            }),
+        },
+        ctl_calls: reactor.ctl_calls() - ctl_calls,
+    }
+}
+
+type Request = Pin<Box<dyn Future<Output = Result<Response, HttpError>>>>;
+
+/// Counts wakes, without waking anyone: the busy-poll loop polls again regardless.
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn busy_poll(client: &Client, path: &str) -> (usize, usize) {
+    let counter = Arc::new(CountingWaker::default());
+    let waker = Waker::from(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut polls = 0;
+
+    for _ in 0..ROUNDS {
+        let mut pending: Vec<Option<Request>> = (0..REQUESTS)
+            .map(|_| Some(Box::pin(client.get(path)) as Request))
+            .collect();
+        let mut left = REQUESTS;
+
+        while left > 0 {
+            for slot in pending.iter_mut() {
+                let Some(request) = slot else {
+                    continue;
+                };
+                polls += 1;
+                if let Poll::Ready(response) = request.as_mut().poll(&mut cx) {
+                    response.expect("request failed");
+                    *slot = None;
+                    left -= 1;
+                }
+            }
+        }
+    }
+
+    (polls, counter.0.load(Ordering::Relaxed))
+}
+
+fn single(client: &Client, path: &str) -> (usize, usize) {
+    run_executor(client, path, REQUESTS)
+}
+
+fn multi(client: &Client, path: &str) -> (usize, usize) {
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let (client, path) = (client.clone(), path.to_string());
+            thread::spawn(move || run_executor(&client, &path, REQUESTS / THREADS))
+        })
+        .collect();
+
+    threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap())
+        .fold((0, 0), |(polls, wakes), (p, w)| (polls + p, wakes + w))
+}
+
+/// Make `ROUNDS` rounds of `requests` concurrent requests on this thread's executor, a task
+/// each. Returns the polls and wakes of the executor over the run.
+fn run_executor(client: &Client, path: &str, requests: usize) -> (usize, usize) {
+    let mut executor = Executor::new();
+    let before = executor.metrics();
+
+    for _ in 0..ROUNDS {
+        executor.block_on({
+            let (client, path) = (client.clone(), path.to_string());
+            async move {
+                for _ in 0..requests {
+                    let request = client.get(&path);
+                    spawn(async move {
+                        request.await.expect("request failed");
+                    });
+                }
+            }
+        });
+    }
+
+    let after = executor.metrics();
+    (after.polls - before.polls, after.wakes - before.wakes)
+}
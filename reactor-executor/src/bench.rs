@@ -0,0 +1,515 @@
+//! Micro benchmarks for the runtime.
+//!
+//! These are run from the binary rather than through `cargo bench`, which installs the
+//! allocator they count allocations with. Use a release build for meaningful numbers:
+//!
+//! ```bash
+//! cargo run --release -p reactor-executor -- --bench
+//! ```
+//!
+//! The scheduling models as a whole, i.e. busy polling against one or several executors, are
+//! compared end to end by `benches/scheduling.rs`, which needs no allocator of its own.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    collections::HashMap,
+    future::Future,
+    hint::{self, black_box},
+    io::{Read, Write},
+    mem::{size_of, size_of_val},
+    net::TcpListener,
+    os::unix::net as std_net,
+    pin::Pin,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use mio::Interest;
+
+use crate::{
+    future::{join_all, join_all_budgeted},
+    http::Http,
+    net::unix::UnixListener,
+    runtime::{
+        self, spawn, spawn_with_priority, Executor, Priority, ReadyQueue, ReadyQueueKind,
+        TypedExecutor, WakerSlab,
+    },
+    sim,
+};
+
+const TASKS: usize = 100_000;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts allocations, so benchmarks can report them next to timings. Costs every allocation
+/// in the binary one relaxed atomic increment.
+///
+/// Installed as the global allocator by the binary, not the library, so code depending on the
+/// library keeps its own. Without it, allocation counts come out as 0.
+pub struct CountingAlloc;
+
+// SAFETY: defers to the system allocator for the actual work
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Run all benchmarks and print the results
+pub fn run() {
+    // the reactor can only be started once, and hands out the ids of the simulated requests
+    // of `join_strategies` as well as the sockets of the benchmarks after it
+    runtime::init();
+
+    task_storage();
+    ready_queue();
+    ready_queue_contention();
+    join_strategies();
+    accept_latency();
+    waker_storage();
+    connection_churn();
+}
+
+/// The single task type used by both executors, so the only difference is how it is stored.
+async fn task(i: usize) {
+    black_box(i);
+}
+
+/// Compare the boxed, type erased task storage of [`Executor`] against the inline storage of
+/// [`TypedExecutor`], for spawn throughput, total run time and memory used per task.
+fn task_storage() {
+    println!("== task storage: {TASKS} tasks ==");
+
+    // Boxed: every spawn allocates a `Pin<Box<dyn Future>>` and inserts it into a HashMap
+    let mut executor = Executor::new();
+    let start = Instant::now();
+    for i in 0..TASKS {
+        spawn(task(i));
+    }
+    let boxed_spawn = start.elapsed();
+    executor.block_on(async {});
+    let boxed_total = start.elapsed();
+
+    // Inline: tasks live in a `Vec<Option<F>>`, no allocation per task and no dyn dispatch
+    let mut typed = TypedExecutor::with_capacity(TASKS);
+    let start = Instant::now();
+    for i in 0..TASKS {
+        typed.spawn(task(i));
+    }
+    let typed_spawn = start.elapsed();
+    typed.run();
+    let typed_total = start.elapsed();
+
+    // Per task memory: the boxed executor pays for the heap allocation holding the future,
+    // plus the fat pointer to it and the task id used as the key in the HashMap.
+    let future = task(0);
+    let boxed_bytes = size_of_val(&future) + size_of::<BoxedTask>() + size_of::<usize>();
+    let typed_bytes = size_of_option(&future);
+
+    println!(
+        "{:<8} {:>14} {:>14} {:>12}",
+        "", "spawn", "spawn + run", "bytes/task"
+    );
+    println!(
+        "{:<8} {boxed_spawn:>14?} {boxed_total:>14?} {boxed_bytes:>12}",
+        "boxed"
+    );
+    println!(
+        "{:<8} {typed_spawn:>14?} {typed_total:>14?} {typed_bytes:>12}",
+        "typed"
+    );
+}
+
+type BoxedTask = Pin<Box<dyn Future<Output = ()>>>;
+
+fn size_of_option<F>(_: &F) -> usize {
+    size_of::<Option<F>>()
+}
+
+/// Compare the Mutex based ready queue against the lock-free ones, with tasks that wake
+/// themselves a number of times so every poll goes through a push and a pop.
+fn ready_queue() {
+    const WAKES: usize = 10;
+    println!("== ready queue: {TASKS} tasks, {WAKES} wakes each ==");
+
+    for kind in [
+        ReadyQueueKind::Mutex,
+        ReadyQueueKind::Atomic,
+        ReadyQueueKind::Segmented,
+    ] {
+        let mut executor = Executor::with_ready_queue(kind);
+        let start = Instant::now();
+        for _ in 0..TASKS {
+            spawn(YieldN(WAKES));
+        }
+        executor.block_on(async {});
+
+        println!("{:<8} {:>14?}", format!("{kind:?}"), start.elapsed());
+    }
+
+    // leave the default queue in place for anything running after us
+    Executor::with_ready_queue(ReadyQueueKind::default());
+}
+
+/// Compare the ready queues under contention, with threads standing in for reactor threads
+/// waking tasks while the executor pops them, and count the allocations made per wake up.
+///
+/// NOTE: as with `waker_storage`, the contention itself only shows with several cores.
+fn ready_queue_contention() {
+    const THREADS: usize = 4;
+    const WAKES: usize = 100_000;
+    println!("== ready queue contention: {THREADS} threads, {WAKES} wakes each ==");
+
+    println!("{:<10} {:>14} {:>14}", "queue", "time", "allocs/wake");
+    for kind in [
+        ReadyQueueKind::Mutex,
+        ReadyQueueKind::Atomic,
+        ReadyQueueKind::Segmented,
+    ] {
+        let queue = ReadyQueue::new(kind);
+        let done = AtomicBool::new(false);
+
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        thread::scope(|scope| {
+            // the executor: pops until every wake up was seen
+            scope.spawn(|| {
+                let mut popped = 0;
+                while popped < THREADS * WAKES {
+                    match queue.pop() {
+                        Some(id) => {
+                            black_box(id);
+                            popped += 1;
+                        }
+                        None => hint::spin_loop(),
+                    }
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+
+            for _ in 0..THREADS {
+                scope.spawn(|| (0..WAKES).for_each(|id| queue.push(id)));
+            }
+        });
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+        assert!(done.load(Ordering::Relaxed));
+
+        println!(
+            "{:<10} {:>14?} {:>14.2}",
+            format!("{kind:?}"),
+            start.elapsed(),
+            allocations as f64 / (THREADS * WAKES) as f64
+        );
+    }
+}
+
+/// Wakes itself and returns Pending `n` times before completing.
+struct YieldN(usize);
+
+impl Future for YieldN {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 == 0 {
+            return Poll::Ready(());
+        }
+        self.0 -= 1;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Compare [`join_all`] against [`join_all_budgeted`] on simulated requests that complete
+/// over 100 distinct virtual deadlines. The former polls every pending child on each of those
+/// wake ups, the latter only the children that completed.
+fn join_strategies() {
+    const REQUESTS: usize = 10_000;
+    println!("== join: {REQUESTS} simulated requests ==");
+
+    let net = sim::Network::new(1);
+    net.add_endpoint("127.0.0.1:8080", sim::Link::new(Duration::from_millis(1)));
+    net.install();
+
+    println!("{:<10} {:>14} {:>14}", "", "time", "child polls");
+    for name in ["join_all", "budgeted"] {
+        let polls = Rc::new(Cell::new(0));
+        let requests: Vec<_> = (0..REQUESTS)
+            .map(|i| {
+                let request =
+                    Http::with_addr("127.0.0.1:8080").get(&format!("/{}/r{i}", (i % 100) * 10));
+                CountPolls::new(request, polls.clone())
+            })
+            .collect();
+
+        let start = Instant::now();
+        match name {
+            "join_all" => Executor::new().block_on(async move {
+                join_all(requests).await;
+            }),
+            _ => Executor::new().block_on(async move {
+                join_all_budgeted(requests, 1024).await;
+            }),
+        }
+
+        println!("{name:<10} {:>14?} {:>14}", start.elapsed(), polls.get());
+    }
+}
+
+/// Counts how often the wrapped future is polled.
+struct CountPolls<F> {
+    inner: Pin<Box<F>>,
+    polls: Rc<Cell<usize>>,
+}
+
+impl<F> CountPolls<F> {
+    fn new(inner: F, polls: Rc<Cell<usize>>) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            polls,
+        }
+    }
+}
+
+impl<F: Future> Future for CountPolls<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        self.polls.set(self.polls.get() + 1);
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+/// Measure how long it takes to get a connection accepted and answered while the executor is
+/// busy with bulk data, with the listener and its task at normal and at high priority.
+///
+/// `CONNS` connections each get a 64 byte chunk every millisecond, and every chunk costs its
+/// task some CPU time. Meanwhile a client connects every few milliseconds and times how long
+/// it takes until the accepting task has written a byte back.
+fn accept_latency() {
+    const CONNS: usize = 32;
+    const PROBES: usize = 20;
+    println!("== accept latency: {CONNS} busy connections, {PROBES} connects ==");
+
+    for priority in [Priority::Normal, Priority::High] {
+        let path = std::env::temp_dir().join(format!("bench-accept-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut listener = UnixListener::bind_with_priority(&path, priority).unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+
+        // data plane: connected up front, so they are the first to be accepted
+        let feeders: Vec<_> = (0..CONNS)
+            .map(|_| {
+                let mut stream = std_net::UnixStream::connect(&path).unwrap();
+                let done = done.clone();
+                thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        stream.write_all(&[0u8; 64]).unwrap();
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                })
+            })
+            .collect();
+
+        let latencies = Arc::new(Mutex::new(Vec::new()));
+        let probes = {
+            let (path, done, latencies) = (path.clone(), done.clone(), latencies.clone());
+            thread::spawn(move || {
+                // let the data plane get going first
+                thread::sleep(Duration::from_millis(50));
+
+                for _ in 0..PROBES {
+                    thread::sleep(Duration::from_millis(5));
+                    let start = Instant::now();
+                    let mut stream = std_net::UnixStream::connect(&path).unwrap();
+                    stream.read_exact(&mut [0u8; 1]).unwrap();
+                    latencies.lock().unwrap().push(start.elapsed());
+                }
+                done.store(true, Ordering::Relaxed);
+            })
+        };
+
+        Executor::new().block_on(async move {
+            for _ in 0..CONNS {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                spawn(async move {
+                    let mut buf = [0u8; 64];
+                    while stream.read(&mut buf).await.unwrap() > 0 {
+                        spin(Duration::from_micros(20));
+                    }
+                });
+            }
+
+            spawn_with_priority(priority, async move {
+                for _ in 0..PROBES {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    stream.write(b"x").await.unwrap();
+                }
+            });
+        });
+
+        probes.join().unwrap();
+        for feeder in feeders {
+            feeder.join().unwrap();
+        }
+        let _ = std::fs::remove_file(&path);
+
+        let mut latencies = latencies.lock().unwrap();
+        latencies.sort();
+        println!(
+            "{:<8} median {:>12?} max {:>12?}",
+            format!("{priority:?}"),
+            latencies[PROBES / 2],
+            latencies[PROBES - 1]
+        );
+    }
+
+    // wakes are coalesced per tick of the event loop, see `Reactor::dispatch_stats`
+    let stats = runtime::reactor().dispatch_stats();
+    println!(
+        "reactor: {} ticks, {:.2} events/tick, {:.2} events/unpark, {} full batches",
+        stats.ticks,
+        stats.events_per_tick(),
+        stats.events_per_unpark(),
+        stats.full_batches
+    );
+}
+
+/// Compare the single `Mutex<HashMap>` the reactor used to keep its wakers in against the
+/// striped [`WakerSlab`], with threads standing in for executors that each register a
+/// source, store and look up its waker, and deregister it again.
+///
+/// NOTE: the difference in contention only shows with several cores. On a single core what
+/// remains is the cost of hashing and of growing the map.
+fn waker_storage() {
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 100_000;
+    println!("== waker storage: {THREADS} threads, {ROUNDS} registrations each ==");
+
+    let map: Mutex<HashMap<usize, Waker>> = Mutex::default();
+    let next_id = AtomicUsize::new(1);
+    let elapsed = contend(THREADS, || {
+        for _ in 0..ROUNDS {
+            let id = next_id.fetch_add(1, Ordering::Relaxed);
+            map.lock().unwrap().insert(id, Waker::noop().clone());
+            black_box(map.lock().unwrap().get(&id).cloned());
+            map.lock().unwrap().remove(&id);
+        }
+    });
+    println!("{:<8} {:>14?}", "HashMap", elapsed);
+
+    let slab = WakerSlab::default();
+    let elapsed = contend(THREADS, || {
+        for _ in 0..ROUNDS {
+            let key = slab.insert();
+            slab.set(key, Waker::noop(), Interest::READABLE, None);
+            black_box(slab.get(key));
+            slab.remove(key);
+        }
+    });
+    println!("{:<8} {:>14?}", "slab", elapsed);
+}
+
+/// Make requests over fresh connections to a local server, with and without pooling, and
+/// count the allocations made per request. Every request is a connection of its own, the
+/// worst case for allocator pressure.
+fn connection_churn() {
+    const ROUNDS: usize = 20;
+    const CONCURRENT: usize = 50;
+    println!("== connection churn: {ROUNDS} rounds of {CONCURRENT} concurrent requests ==");
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || serve_fixed_response(listener));
+
+    println!("{:<8} {:>14} {:>14}", "pooling", "time", "allocs/req");
+    for pooling in [false, true] {
+        runtime::set_pooling(pooling);
+        let client = Http::with_addr(&addr);
+        let mut executor = Executor::new();
+
+        let start = Instant::now();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        executor.block_on(async move {
+            for _ in 0..ROUNDS {
+                let requests: Vec<_> = (0..CONCURRENT).map(|_| client.get("/")).collect();
+                black_box(join_all_budgeted(requests, CONCURRENT).await);
+            }
+        });
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+        println!(
+            "{:<8} {:>14?} {:>14.1}",
+            pooling,
+            start.elapsed(),
+            allocations as f64 / (ROUNDS * CONCURRENT) as f64
+        );
+    }
+
+    for stats in runtime::pool_stats() {
+        println!(
+            "pool {}: {} shared, {} cached, {:.1}% hits",
+            stats.name,
+            stats.shared,
+            stats.cached,
+            stats.hit_rate() * 100.0
+        );
+    }
+}
+
+/// Answer every connection with the same small response and close it.
+fn serve_fixed_response(listener: TcpListener) {
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+
+        // read up to the end of the request head, we do not expect a body
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            match stream.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => request.extend_from_slice(&buf[..n]),
+            }
+        }
+        let _ = stream.write_all(RESPONSE);
+    }
+}
+
+/// Run `f` on `threads` threads at once, returning how long it took all of them.
+fn contend(threads: usize, f: impl Fn() + Sync) -> Duration {
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(&f);
+        }
+    });
+    start.elapsed()
+}
+
+/// Keep the CPU busy for `duration`, standing in for work done on each chunk of data.
+fn spin(duration: Duration) {
+    let until = Instant::now() + duration;
+    while Instant::now() < until {
+        hint::spin_loop();
+    }
+}
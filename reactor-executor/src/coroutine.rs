@@ -0,0 +1,305 @@
+//! Run the hand-written coroutines of `stackless-coroutine` and `archived` on this runtime,
+//! rather than on a copy of a runtime of their own.
+//!
+//! Those coroutines implement the `waker` or `pin` variant of the `Future` trait of
+//! `async-core`, and are polled with its executor agnostic [`Waker`], while this runtime runs
+//! std futures. The module of the same name as the variant stands in for the `runtime` module
+//! the binaries used to have:
+//!
+//! ```ignore
+//! use reactor_executor::coroutine::{waker as runtime, Http};
+//!
+//! let mut executor = runtime::init();
+//! executor.block_on(async_main());
+//! ```
+//!
+//! Each task is polled with a [`Waker`] made from the std waker of its poll, which the leaf
+//! futures turn back into a std one, see [`from_std`]. [`Http`] is the HTTP client of the
+//! runtime, wrapped that way.
+use std::{
+    cell::RefCell,
+    future::Future as StdFuture,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_core::{pin::Future as PinFuture, waker::Future as WakerFuture, PollState};
+
+pub use async_core::task::{CancellationToken, Waker};
+
+pub mod pin;
+pub mod waker;
+
+thread_local! {
+    /// Token of the task being polled, if it was spawned with one
+    static CURRENT_TOKEN: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+/// True if the task being polled was spawned with a token, which has been cancelled. The
+/// coroutines generated by `#[coroutine]` check this on every state transition.
+pub fn is_cancelled() -> bool {
+    CURRENT_TOKEN.with(|token| {
+        token
+            .borrow()
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    })
+}
+
+/// The HTTP client of the runtime, for coroutines.
+pub struct Http;
+
+impl Http {
+    /// Returns a future that yields the text of the response to a GET request for `path`,
+    /// see [`http::Http::get`](crate::http::Http::get).
+    pub fn get(path: &str) -> FromStd<impl StdFuture<Output = String>> {
+        let request = crate::http::Http::get(path);
+        from_std(async move {
+            match request.await {
+                Ok(response) => response.into_string(),
+                Err(e) => panic!("Request failed: {e}"),
+            }
+        })
+    }
+}
+
+/// Wrap a std `future`, e.g. one of the sockets or the HTTP client, so a coroutine can wait on
+/// it.
+pub fn from_std<F: StdFuture>(future: F) -> FromStd<F> {
+    FromStd {
+        future: Box::pin(future),
+    }
+}
+
+/// Future returned by [`from_std`], of both the `waker` and the `pin` variant.
+#[must_use = "futures do nothing unless polled"]
+pub struct FromStd<F> {
+    // pinned on the heap, so the coroutine can hold it through `&mut self`
+    future: Pin<Box<F>>,
+}
+
+impl<F: StdFuture> FromStd<F> {
+    fn poll_std(&mut self, waker: &Waker) -> PollState<F::Output> {
+        let waker = std::task::Waker::from(Arc::new(IntoStd(waker.clone())));
+
+        match self.future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(output) => PollState::Ready(output),
+            Poll::Pending => PollState::NotReady,
+        }
+    }
+}
+
+impl<F: StdFuture> WakerFuture for FromStd<F> {
+    type Output = F::Output;
+    type Waker = Waker;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<F::Output> {
+        self.poll_std(waker)
+    }
+}
+
+impl<F: StdFuture> PinFuture for FromStd<F> {
+    type Output = F::Output;
+    type Waker = Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &Waker) -> PollState<F::Output> {
+        self.get_mut().poll_std(waker)
+    }
+}
+
+/// A coroutine [`Waker`] as a std one, for the leaf futures of the runtime
+struct IntoStd(Waker);
+
+impl std::task::Wake for IntoStd {
+    fn wake(self: Arc<Self>) {
+        self.0.wake();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.wake();
+    }
+}
+
+/// A std waker as a coroutine [`Waker`], for the tasks of the executor
+struct FromStdWaker(std::task::Waker);
+
+impl async_core::task::Wake for FromStdWaker {
+    fn wake(&self) {
+        self.0.wake_by_ref();
+    }
+}
+
+/// A coroutine of the `pin` variant as a task of the executor, its output is dropped.
+struct Task<F> {
+    future: Pin<Box<F>>,
+    /// Asks the coroutine to stop, see [`is_cancelled`]
+    token: Option<CancellationToken>,
+}
+
+impl<F: PinFuture<Waker = Waker>> Task<F> {
+    fn new(future: F, token: Option<CancellationToken>) -> Self {
+        Self {
+            future: Box::pin(future),
+            token,
+        }
+    }
+}
+
+impl<F: PinFuture<Waker = Waker>> StdFuture for Task<F> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let waker = Waker::from(Arc::new(FromStdWaker(cx.waker().clone())));
+
+        // woken on cancellation, so it gets to see it without waiting on anything else
+        if let Some(token) = &self.token {
+            token.wake_on_cancel(&waker);
+        }
+
+        let previous = CURRENT_TOKEN.with(|current| current.replace(self.token.clone()));
+        let state = self.future.as_mut().poll(&waker);
+        CURRENT_TOKEN.with(|current| *current.borrow_mut() = previous);
+
+        match state {
+            PollState::Ready(_) => Poll::Ready(()),
+            PollState::NotReady => Poll::Pending,
+        }
+    }
+}
+
+/// A coroutine of the `waker` variant, polled through `&mut self`, as one of the `pin` variant.
+struct Unpinned<F>(F);
+
+impl<F: WakerFuture + Unpin> PinFuture for Unpinned<F> {
+    type Output = F::Output;
+    type Waker = F::Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &F::Waker) -> PollState<F::Output> {
+        self.get_mut().0.poll(waker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        rc::Rc,
+        sync::{atomic::AtomicUsize, atomic::Ordering},
+    };
+
+    use super::*;
+    use crate::runtime::{sync::oneshot, yield_now};
+
+    /// Counts the wakes of a coroutine [`Waker`]
+    struct CountWakes(AtomicUsize);
+
+    impl async_core::task::Wake for CountWakes {
+        fn wake(&self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn std_futures_wake_the_coroutine_waker() {
+        let wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+
+        let (tx, rx) = oneshot::channel();
+        let mut future = from_std(async move { rx.await.unwrap() * 2 });
+
+        assert!(matches!(future.poll_std(&waker), PollState::NotReady));
+        tx.send(21);
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 1);
+        assert!(matches!(future.poll_std(&waker), PollState::Ready(42)));
+    }
+
+    /// Waits on a std future twice, then adds to `done`
+    struct TwoYields {
+        waiting: Option<FromStd<crate::runtime::YieldNow>>,
+        yields: usize,
+        done: Rc<Cell<usize>>,
+    }
+
+    impl TwoYields {
+        fn new(done: &Rc<Cell<usize>>) -> Self {
+            Self {
+                waiting: None,
+                yields: 0,
+                done: done.clone(),
+            }
+        }
+    }
+
+    impl WakerFuture for TwoYields {
+        type Output = String;
+        type Waker = Waker;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<String> {
+            loop {
+                let future = self.waiting.get_or_insert_with(|| from_std(yield_now()));
+                match future.poll(waker) {
+                    PollState::Ready(()) => {
+                        self.waiting = None;
+                        self.yields += 1;
+                    }
+                    PollState::NotReady => break PollState::NotReady,
+                }
+
+                if self.yields == 2 {
+                    self.done.set(self.done.get() + 1);
+                    break PollState::Ready(String::new());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn coroutines_run_on_the_executor() {
+        let done = Rc::new(Cell::new(0));
+
+        let mut executor = waker::Executor::new();
+        waker::spawn(TwoYields::new(&done));
+        executor.block_on(TwoYields::new(&done));
+
+        assert_eq!(done.get(), 2, "spawned tasks are run too");
+    }
+
+    /// Never resolves, and checks for cancellation each time it is polled
+    struct UntilCancelled(Rc<Cell<bool>>);
+
+    impl PinFuture for UntilCancelled {
+        type Output = String;
+        type Waker = Waker;
+
+        fn poll(self: Pin<&mut Self>, _: &Waker) -> PollState<String> {
+            if is_cancelled() {
+                self.0.set(true);
+                return PollState::Ready(String::new());
+            }
+            PollState::NotReady
+        }
+    }
+
+    #[test]
+    fn cancelled_tasks_are_woken_to_see_it() {
+        let token = CancellationToken::new();
+        let stopped = Rc::new(Cell::new(false));
+
+        let mut executor = pin::Executor::new();
+        pin::spawn_with_token(token.clone(), UntilCancelled(stopped.clone()));
+        executor.block_on(from_std({
+            let token = token.clone();
+            async move {
+                yield_now().await;
+                token.cancel();
+            }
+        }));
+
+        assert!(stopped.get());
+        assert!(
+            !is_cancelled(),
+            "the token is only seen while its task is polled"
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! The runtime for coroutines of the `waker` variant, polled through `&mut self`, e.g. the ones
+//! written out by `corofy_waker`.
+use async_core::waker::Future;
+
+use super::{Task, Unpinned};
+use crate::runtime;
+
+pub use super::{is_cancelled, CancellationToken, Waker};
+
+/// Start the runtime, see [`runtime::init`], returning the executor of this thread.
+pub fn init() -> Executor {
+    Executor(runtime::init())
+}
+
+/// Runs coroutines on the executor of the runtime.
+pub struct Executor(runtime::Executor);
+
+impl Executor {
+    /// Executor of the current thread, for threads other than the one the runtime was started
+    /// on.
+    pub fn new() -> Self {
+        Self(runtime::Executor::new())
+    }
+
+    /// Run `future` to completion, along with the tasks spawned meanwhile.
+    pub fn block_on<F>(&mut self, future: F)
+    where
+        F: Future<Waker = Waker> + Unpin + 'static,
+    {
+        self.0.block_on(Task::new(Unpinned(future), None));
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spawn `future` as a task of the executor of the current thread, its output is dropped.
+pub fn spawn<F>(future: F)
+where
+    F: Future<Waker = Waker> + Unpin + 'static,
+{
+    runtime::spawn_local(Task::new(Unpinned(future), None));
+}
+
+/// Same as [`spawn`], the task is asked to stop once `token` is cancelled, see
+/// [`is_cancelled`]. It is woken on cancellation, to see it straight away.
+pub fn spawn_with_token<F>(token: CancellationToken, future: F)
+where
+    F: Future<Waker = Waker> + Unpin + 'static,
+{
+    runtime::spawn_local(Task::new(Unpinned(future), Some(token)));
+}
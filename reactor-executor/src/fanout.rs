@@ -0,0 +1,318 @@
+//! Capstone example: a query endpoint that fans out to several upstream services.
+//!
+//! Per request to `/query`, the server resolves three fields concurrently, each by calling an
+//! upstream service, and answers with a GraphQL style JSON document: whatever resolved goes
+//! under `data`, fields that failed are `null` with an entry under `errors`, and `timings`
+//! holds how long each stage took.
+//!
+//! - Each upstream call is retried on failure, with a timeout per attempt.
+//! - The whole request has a deadline. Attempts never run past it, so a slow upstream costs
+//!   us its field rather than the whole response.
+//! - The fields are joined with [`join_all_budgeted`].
+//!
+//! The server is served over a Unix domain socket, while the upstreams live on a simulated
+//! network, so timeouts, retries and latencies all run on its virtual clock:
+//!
+//! ```bash
+//! cargo run -p reactor-executor -- --fanout
+//! ```
+//!
+//! NOTE: there is no tracing or metrics pipeline in this crate, so per stage latencies are
+//! reported in the response, and logged at info level as the stages complete (see
+//! [`runtime::log`](crate::runtime::log)).
+use std::{
+    io::{self, ErrorKind},
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    future::join_all_budgeted,
+    http::Http,
+    net::unix::{UnixListener, UnixStream},
+    runtime::{log, spawn_local},
+    sim,
+};
+
+/// Time allowed for a single attempt at an upstream call
+const CALL_TIMEOUT: Duration = Duration::from_millis(100);
+/// Attempts made after the first one failed
+const RETRIES: usize = 2;
+/// Time allowed for resolving a whole query
+const DEADLINE: Duration = Duration::from_millis(250);
+
+/// Fields of a query: name, upstream address and the delayserver style path to request.
+const FIELDS: [(&str, &str, &str); 3] = [
+    ("user", "users:80", r#"/20/{"id":1,"name":"Alice"}"#),
+    ("orders", "orders:80", r#"/40/[{"id":7,"total":12.5}]"#),
+    (
+        "recommendations",
+        "recommendations:80",
+        r#"/400/["book","lamp"]"#,
+    ),
+];
+
+/// Simulated network with the upstreams of [`FIELDS`]: orders drops some requests, and
+/// recommendations is too slow to ever answer within [`CALL_TIMEOUT`].
+pub fn network(seed: u64) -> sim::Network {
+    let net = sim::Network::new(seed);
+    net.add_endpoint("users:80", sim::Link::new(Duration::from_millis(5)));
+    net.add_endpoint(
+        "orders:80",
+        sim::Link::new(Duration::from_millis(10)).drop_rate(0.5),
+    );
+    net.add_endpoint(
+        "recommendations:80",
+        sim::Link::new(Duration::from_millis(20)),
+    );
+    net
+}
+
+/// Accept `requests` connections on `listener`, handling each in its own task.
+pub async fn serve(mut listener: UnixListener, net: sim::Network, requests: usize) {
+    for _ in 0..requests {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .expect("Failed to accept connection");
+        let net = net.clone();
+        // the simulated network is not Send
+        spawn_local(async move {
+            if let Err(e) = handle(stream, net).await {
+                log::warn!("failed to handle request: {e}");
+            }
+        });
+    }
+}
+
+/// Send a query to the server at `path`, returning the raw response.
+pub async fn query(path: impl AsRef<Path>) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    write_all(
+        &mut stream,
+        b"GET /query HTTP/1.1\r\nhost: localhost\r\n\r\n",
+    )
+    .await?;
+    stream.shutdown_write()?;
+
+    let mut response = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        match stream.read(&mut buf).await? {
+            0 => break,
+            n => response.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&response).to_string())
+}
+
+async fn handle(mut stream: UnixStream, net: sim::Network) -> io::Result<()> {
+    // read up to the end of the request head, we do not expect a body
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut buf).await? {
+            0 => break,
+            n => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let response = match request.lines().next() {
+        Some("GET /query HTTP/1.1") => {
+            let body = resolve(&net).await;
+            format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\
+                 connection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".into(),
+    };
+
+    write_all(&mut stream, response.as_bytes()).await
+}
+
+/// Outcome of resolving a single field.
+struct Stage {
+    field: &'static str,
+    /// Body of the upstream response
+    result: io::Result<String>,
+    attempts: usize,
+    latency: Duration,
+}
+
+/// Resolve all fields concurrently and render the response document.
+async fn resolve(net: &sim::Network) -> String {
+    let start = net.now();
+    let deadline = start + DEADLINE;
+
+    let calls = FIELDS
+        .map(|(field, addr, path)| call(net.clone(), deadline, field, addr, path))
+        .into_iter();
+    let stages = join_all_budgeted(calls, FIELDS.len()).await;
+
+    let total = net.now() - start;
+    log::info!("query resolved in {total:?}");
+    render(&stages, total)
+}
+
+/// Call an upstream, retrying failed attempts until [`RETRIES`] or the deadline runs out.
+async fn call(
+    net: sim::Network,
+    deadline: Duration,
+    field: &'static str,
+    addr: &'static str,
+    path: &'static str,
+) -> Stage {
+    let start = net.now();
+    let mut attempts = 0;
+
+    let result = loop {
+        let remaining = deadline.saturating_sub(net.now());
+        if remaining.is_zero() {
+            break Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "request deadline exceeded",
+            ));
+        }
+
+        attempts += 1;
+        let request = Http::with_addr(addr).get(path);
+        let attempt = net.timeout(CALL_TIMEOUT.min(remaining), request);
+
+        match attempt.await.and_then(|response| Ok(response?)) {
+            Ok(response) => break Ok(response.body().to_string()),
+            Err(e) if attempts > RETRIES => break Err(e),
+            // dropped by the link or timed out, try again
+            Err(_) => continue,
+        }
+    };
+
+    let latency = net.now() - start;
+    log::info!("stage {field} took {latency:?} over {attempts} attempt(s)");
+
+    Stage {
+        field,
+        result,
+        attempts,
+        latency,
+    }
+}
+
+/// Everything after the response head.
+fn body_of(response: &str) -> &str {
+    response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body)
+        .unwrap_or_default()
+}
+
+fn render(stages: &[Stage], total: Duration) -> String {
+    let data: Vec<String> = stages
+        .iter()
+        .map(|stage| match &stage.result {
+            // upstreams answer with JSON, so their bodies are embedded as is
+            Ok(body) => format!(r#""{}":{body}"#, stage.field),
+            Err(_) => format!(r#""{}":null"#, stage.field),
+        })
+        .collect();
+
+    let errors: Vec<String> = stages
+        .iter()
+        .filter_map(|stage| {
+            let e = stage.result.as_ref().err()?;
+            Some(format!(
+                r#"{{"field":"{}","message":"{}"}}"#,
+                stage.field,
+                escape(&e.to_string())
+            ))
+        })
+        .collect();
+
+    let timings: Vec<String> = stages
+        .iter()
+        .map(|stage| {
+            format!(
+                r#""{}":{{"ms":{},"attempts":{}}}"#,
+                stage.field,
+                stage.latency.as_millis(),
+                stage.attempts
+            )
+        })
+        .chain([format!(r#""total":{{"ms":{}}}"#, total.as_millis())])
+        .collect();
+
+    format!(
+        r#"{{"data":{{{}}},"errors":[{}],"timings":{{{}}}}}"#,
+        data.join(","),
+        errors.join(","),
+        timings.join(",")
+    )
+}
+
+/// Escape `s` for use in a JSON string.
+fn escape(s: &str) -> String {
+    s.replace('\\', r"\\").replace('"', r#"\""#)
+}
+
+async fn write_all(stream: &mut UnixStream, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match stream.write(buf).await? {
+            0 => return Err(ErrorKind::WriteZero.into()),
+            n => buf = &buf[n..],
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::runtime;
+
+    #[test]
+    fn slow_field_is_null_and_the_rest_resolve_within_deadline() {
+        let path = std::env::temp_dir().join(format!("fanout-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut executor = runtime::init_for_tests();
+        let net = network(1);
+        net.install();
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let response = Rc::new(RefCell::new(String::new()));
+        let (client, out) = (path.clone(), response.clone());
+        executor.block_on(async move {
+            spawn_local(serve(listener, net, 1));
+            *out.borrow_mut() = query(&client).await.unwrap();
+        });
+        std::fs::remove_file(&path).unwrap();
+
+        let response = response.borrow();
+        let body = body_of(&response);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+
+        assert!(body.contains(r#""user":{"id":1,"name":"Alice"}"#), "{body}");
+        assert!(body.contains(r#""recommendations":null"#), "{body}");
+        assert!(
+            body.contains(r#"{"field":"recommendations","message":"#),
+            "{body}"
+        );
+        // seed 1 drops the first request to orders, which goes unanswered until the attempt
+        // timed out, and the retry succeeds
+        assert!(
+            body.contains(r#""orders":{"ms":160,"attempts":2}"#),
+            "{body}"
+        );
+        // the deadline cut the third attempt at recommendations short
+        assert!(
+            body.contains(r#""recommendations":{"ms":250,"attempts":3}"#),
+            "{body}"
+        );
+        assert!(body.contains(r#""total":{"ms":250}"#), "{body}");
+    }
+}
@@ -1,20 +1,102 @@
 //! future related code
 #![allow(unused)]
-use std::pin::Pin;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
 
 use crate::runtime::MyWaker;
 
-/// Represents some operation that will complete in the future
-/// and return a value of type `Future::Output`.
-pub trait Future {
-    type Output;
-    // NEW: When we poll a future, we must now supply a Waker
-    fn poll(self: Pin<&mut Self>, waker: &MyWaker) -> PollState<Self::Output>;
+mod compat;
+mod join;
+mod macros;
+mod unordered;
+mod wake_set;
+
+pub use crate::{join, select};
+pub use compat::{compat, Compat};
+#[doc(hidden)]
+pub use join::Child;
+pub use join::{join_all, join_all_budgeted, BudgetedJoinAll, JoinAll};
+pub use unordered::FuturesUnordered;
+pub use wake_set::WakeSet;
+
+/// The waker based trait shared with the binaries, implemented with `Waker = MyWaker` by the
+/// futures of this crate, see `async-core`.
+pub use async_core::{pin::Future, PollState, Progress};
+
+/// An asynchronous series of values, the async version of `Iterator`.
+///
+/// Unlike the `Future` trait above, which is polled with a [`MyWaker`], this uses the standard
+/// library `Context`, so streams can be driven by the runtime executor.
+pub trait Stream {
+    type Item;
+
+    /// Attempt to pull out the next value. `Ready(None)` means the stream is exhausted.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>;
+}
+
+/// Adaptors for consuming a [`Stream`] with async/await.
+pub trait StreamExt: Stream {
+    /// Returns a future that yields the next item, or None once the stream is exhausted.
+    fn next(&mut self) -> Next<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Next { stream: self }
+    }
+
+    /// Returns a future that drains the stream into a collection.
+    fn collect<C>(self) -> Collect<Self, C>
+    where
+        Self: Sized + Unpin,
+        C: Default + Extend<Self::Item>,
+    {
+        Collect {
+            stream: self,
+            items: C::default(),
+        }
+    }
+}
+
+impl<S: Stream + ?Sized> StreamExt for S {}
+
+/// Future returned by [`StreamExt::next`].
+pub struct Next<'a, S: ?Sized> {
+    stream: &'a mut S,
+}
+
+impl<S> std::future::Future for Next<'_, S>
+where
+    S: Stream + Unpin + ?Sized,
+{
+    type Output = Option<S::Item>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.stream).poll_next(cx)
+    }
+}
+
+/// Future returned by [`StreamExt::collect`].
+pub struct Collect<S, C> {
+    stream: S,
+    items: C,
 }
 
-/// PollState is an enum that represents the state of a future.
-/// It is either Ready or NotReady. The value returned when ready is of type T
-pub enum PollState<T> {
-    Ready(T),
-    NotReady,
+impl<S, C> std::future::Future for Collect<S, C>
+where
+    S: Stream + Unpin,
+    C: Default + Extend<S::Item> + Unpin,
+{
+    type Output = C;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<C> {
+        loop {
+            match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => self.items.extend(Some(item)),
+                Poll::Ready(None) => return Poll::Ready(std::mem::take(&mut self.items)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
 }
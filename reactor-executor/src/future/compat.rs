@@ -0,0 +1,180 @@
+//! Run std futures, e.g. from `async fn`s or other crates, through the runtime's own
+//! [`Future`](super::Future) trait.
+//!
+//! That trait hands futures a [`MyWaker`] rather than a std [`Context`]. [`compat`] wraps a std
+//! future and builds the `Context` it needs from the `MyWaker` it is polled with:
+//!
+//! ```ignore
+//! let mut answer = compat(async { 6 * 7 });
+//! match Pin::new(&mut answer).poll(&waker) {
+//!     PollState::Ready(n) => assert_eq!(n, 42),
+//!     PollState::NotReady => {}
+//! }
+//! ```
+//!
+//! The wrapper is a std future as well, so it can still be spawned, e.g.
+//! `spawn(compat(some_async_fn()))`.
+//!
+//! No allocation is made per poll: the std waker borrows the `MyWaker` for the duration of the
+//! poll. Only once a future clones the waker to keep it, e.g. to leave it with the reactor, is
+//! the `MyWaker` cloned into an `Arc` that the clone owns.
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use super::{Future, PollState};
+use crate::runtime::MyWaker;
+
+/// Wrap a std `future` to poll it through the runtime's [`Future`] trait, see the module docs.
+pub fn compat<F: std::future::Future>(future: F) -> Compat<F> {
+    Compat { future }
+}
+
+/// Future returned by [`compat`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Compat<F> {
+    future: F,
+}
+
+impl<F> Compat<F> {
+    /// Unwrap the std future.
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+
+    fn future(self: Pin<&mut Self>) -> Pin<&mut F> {
+        // SAFETY: `future` is structurally pinned, it is never moved out while pinned
+        unsafe { self.map_unchecked_mut(|this| &mut this.future) }
+    }
+}
+
+impl<F: std::future::Future> Future for Compat<F> {
+    type Output = F::Output;
+    type Waker = MyWaker;
+
+    fn poll(self: Pin<&mut Self>, waker: &MyWaker) -> PollState<F::Output> {
+        // SAFETY: the waker borrows `waker`, and is only used within this call. Clones own an
+        // `Arc` of their own, see `BORROWED`.
+        let waker = unsafe { Waker::from_raw(borrowed(waker)) };
+
+        match self.future().poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(output) => PollState::Ready(output),
+            Poll::Pending => PollState::NotReady,
+        }
+    }
+}
+
+impl<F: std::future::Future> std::future::Future for Compat<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        self.future().poll(cx)
+    }
+}
+
+/// Waker data is a `*const MyWaker` borrowed for the duration of a poll. Waking goes straight
+/// to the borrowed `MyWaker`, and nothing is dropped.
+static BORROWED: RawWakerVTable =
+    RawWakerVTable::new(clone_borrowed, wake_borrowed, wake_borrowed, drop_borrowed);
+
+/// Waker data is an `Arc<MyWaker>` turned into a raw pointer, owned by the waker.
+static OWNED: RawWakerVTable =
+    RawWakerVTable::new(clone_owned, wake_owned, wake_by_ref_owned, drop_owned);
+
+fn borrowed(waker: &MyWaker) -> RawWaker {
+    RawWaker::new(waker as *const MyWaker as *const (), &BORROWED)
+}
+
+unsafe fn clone_borrowed(data: *const ()) -> RawWaker {
+    let waker = Arc::new(unsafe { &*(data as *const MyWaker) }.clone());
+    RawWaker::new(Arc::into_raw(waker) as *const (), &OWNED)
+}
+
+unsafe fn wake_borrowed(data: *const ()) {
+    unsafe { &*(data as *const MyWaker) }.wake_task();
+}
+
+unsafe fn drop_borrowed(_: *const ()) {}
+
+unsafe fn clone_owned(data: *const ()) -> RawWaker {
+    unsafe { Arc::increment_strong_count(data as *const MyWaker) };
+    RawWaker::new(data, &OWNED)
+}
+
+unsafe fn wake_owned(data: *const ()) {
+    let waker = unsafe { Arc::from_raw(data as *const MyWaker) };
+    waker.wake_task();
+}
+
+unsafe fn wake_by_ref_owned(data: *const ()) {
+    unsafe { &*(data as *const MyWaker) }.wake_task();
+}
+
+unsafe fn drop_owned(data: *const ()) {
+    drop(unsafe { Arc::from_raw(data as *const MyWaker) });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::runtime::{sync::oneshot, ReadyQueue};
+
+    #[test]
+    fn kept_waker_queues_the_task_once_woken() {
+        let queue = Arc::default();
+        let waker = MyWaker::new(7, &queue);
+        let kept = Rc::new(RefCell::new(None));
+
+        let stash = kept.clone();
+        let (tx, rx) = oneshot::channel();
+        let mut future = Box::pin(compat(async move {
+            // keeps a clone of the waker, which outlives the poll
+            std::future::poll_fn(|cx| {
+                stash.borrow_mut().get_or_insert_with(|| cx.waker().clone());
+                Poll::Ready(())
+            })
+            .await;
+            rx.await.unwrap() * 2
+        }));
+
+        assert!(matches!(future.as_mut().poll(&waker), PollState::NotReady));
+        assert!(queue.is_empty());
+
+        tx.send(21);
+        assert_eq!(queue.snapshot(), [7], "woken by the receiver");
+        kept.borrow().as_ref().unwrap().wake_by_ref();
+        assert_eq!(queue.snapshot(), [7, 7], "woken by the kept clone");
+
+        assert!(matches!(future.as_mut().poll(&waker), PollState::Ready(42)));
+    }
+
+    #[test]
+    fn waking_the_borrowed_waker_queues_the_task() {
+        let queue: Arc<ReadyQueue> = Arc::default();
+        let waker = MyWaker::new(3, &queue);
+
+        let mut yielded = false;
+        let mut future = compat(std::future::poll_fn(move |cx| {
+            if yielded {
+                return Poll::Ready(());
+            }
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }));
+
+        assert!(matches!(
+            Pin::new(&mut future).poll(&waker),
+            PollState::NotReady
+        ));
+        assert_eq!(queue.snapshot(), [3]);
+        assert!(matches!(
+            Pin::new(&mut future).poll(&waker),
+            PollState::Ready(())
+        ));
+    }
+}
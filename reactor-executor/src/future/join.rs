@@ -0,0 +1,282 @@
+//! Combinators that drive a collection of futures to completion.
+//!
+//! [`join_all`] polls every unfinished child whenever it is woken, which costs O(n) per wake
+//! even if only one child can make progress. [`join_all_budgeted`] gives each child its own
+//! waker (see [`WakeSet`]), so only woken children are polled. They are polled in the order
+//! they were woken, and at most `budget` of them per poll, after which the combinator yields
+//! to the executor so a flood of ready children can not starve other tasks.
+use std::{
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::WakeSet;
+
+/// Future joined on, along with its output once done. Public for [`join!`](crate::join).
+#[doc(hidden)]
+pub enum Child<F: Future> {
+    Pending(Pin<Box<F>>),
+    Done(F::Output),
+    Taken,
+}
+
+impl<F: Future> Child<F> {
+    pub fn new(future: F) -> Self {
+        Child::Pending(Box::pin(future))
+    }
+
+    /// Poll the child if it is still pending. Returns true if it completed just now.
+    pub fn poll(&mut self, cx: &mut Context<'_>) -> bool {
+        let Child::Pending(future) = self else {
+            return false;
+        };
+
+        match future.as_mut().poll(cx) {
+            Poll::Ready(output) => {
+                *self = Child::Done(output);
+                true
+            }
+            Poll::Pending => false,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Child::Pending(_))
+    }
+
+    pub fn take_output(&mut self) -> F::Output {
+        match mem::replace(self, Child::Taken) {
+            Child::Done(output) => output,
+            _ => panic!("child of join polled after completion"),
+        }
+    }
+}
+
+fn children<F: Future>(futures: impl IntoIterator<Item = F>) -> Vec<Child<F>> {
+    futures.into_iter().map(Child::new).collect()
+}
+
+/// Wait for all `futures`, resolving to their outputs in the same order.
+///
+/// Every unfinished child is polled on every wake, see [`join_all_budgeted`] for a version
+/// that scales with the number of children woken instead.
+pub fn join_all<F: Future>(futures: impl IntoIterator<Item = F>) -> JoinAll<F> {
+    let children = children(futures);
+    JoinAll {
+        pending: children.len(),
+        children,
+    }
+}
+
+/// Future returned by [`join_all`].
+pub struct JoinAll<F: Future> {
+    children: Vec<Child<F>>,
+    /// Number of children that have not completed yet
+    pending: usize,
+}
+
+// Children are pinned in their own boxes and outputs are never pinned, so moving the join
+// itself is fine.
+impl<F: Future> Unpin for JoinAll<F> {}
+
+impl<F: Future> Future for JoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        for child in this.children.iter_mut() {
+            if child.poll(cx) {
+                this.pending -= 1;
+            }
+        }
+
+        if this.pending > 0 {
+            return Poll::Pending;
+        }
+        Poll::Ready(this.children.iter_mut().map(Child::take_output).collect())
+    }
+}
+
+/// Same as [`join_all`], but only polls children that were woken, round-robin in the order
+/// they were woken, and at most `budget` of them per poll.
+///
+/// Panics if `budget` is 0.
+pub fn join_all_budgeted<F: Future>(
+    futures: impl IntoIterator<Item = F>,
+    budget: usize,
+) -> BudgetedJoinAll<F> {
+    assert!(budget > 0, "budget must allow polling at least one child");
+
+    let children = children(futures);
+    BudgetedJoinAll {
+        wakers: WakeSet::new(children.len()),
+        pending: children.len(),
+        children,
+        budget,
+    }
+}
+
+/// Future returned by [`join_all_budgeted`].
+pub struct BudgetedJoinAll<F: Future> {
+    children: Vec<Child<F>>,
+    wakers: WakeSet,
+    /// Number of children that have not completed yet
+    pending: usize,
+    /// Maximum number of children polled per poll of the join
+    budget: usize,
+}
+
+// Same as for `JoinAll`
+impl<F: Future> Unpin for BudgetedJoinAll<F> {}
+
+impl<F: Future> Future for BudgetedJoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.wakers.register(cx.waker());
+
+        for _ in 0..this.budget {
+            let Some(index) = this.wakers.pop() else {
+                break;
+            };
+
+            let mut child_cx = Context::from_waker(this.wakers.waker(index));
+            if this.children[index].poll(&mut child_cx) {
+                this.pending -= 1;
+            }
+        }
+
+        if this.pending == 0 {
+            return Poll::Ready(this.children.iter_mut().map(Child::take_output).collect());
+        }
+
+        // Out of budget with children still to poll: yield, and carry on when polled next
+        if this.wakers.has_woken() {
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, sync::Arc, task::Waker};
+
+    use super::*;
+    use crate::runtime::{sync::oneshot, MyWaker};
+
+    /// Records its id in `log` every time it is polled, completes after `yields` self wakes.
+    struct Logged {
+        id: usize,
+        yields: usize,
+        log: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl Future for Logged {
+        type Output = usize;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<usize> {
+            self.log.borrow_mut().push(self.id);
+            if self.yields == 0 {
+                return Poll::Ready(self.id);
+            }
+            self.yields -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    fn parent_waker() -> Waker {
+        let queue = Arc::default();
+        Arc::new(MyWaker::new(0, &queue)).into()
+    }
+
+    #[test]
+    fn only_woken_children_are_polled() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..5).map(|_| oneshot::channel()).unzip();
+
+        let children = receivers.into_iter().enumerate().map(|(id, mut rx)| {
+            let log = log.clone();
+            std::future::poll_fn(move |cx| {
+                log.borrow_mut().push(id);
+                Pin::new(&mut rx).poll(cx).map(Result::unwrap)
+            })
+        });
+        let mut join = Box::pin(join_all_budgeted(children, 10));
+
+        let waker = parent_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(join.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(log.take(), [0, 1, 2, 3, 4]);
+
+        let mut senders: Vec<_> = senders.into_iter().map(Some).collect();
+        senders[3].take().unwrap().send(30);
+        assert!(join.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(log.take(), [3]);
+
+        for (id, sender) in senders.iter_mut().enumerate() {
+            if let Some(sender) = sender.take() {
+                sender.send(id * 10);
+            }
+        }
+        let Poll::Ready(outputs) = join.as_mut().poll(&mut cx) else {
+            panic!("all children completed");
+        };
+        assert_eq!(outputs, [0, 10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn children_are_polled_round_robin_within_budget() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let children = (0..4).map(|id| Logged {
+            id,
+            yields: 1,
+            log: log.clone(),
+        });
+        let mut join = Box::pin(join_all_budgeted(children, 3));
+
+        let waker = parent_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut polls = Vec::new();
+        let outputs = loop {
+            let poll = join.as_mut().poll(&mut cx);
+            polls.push(log.take());
+            if let Poll::Ready(outputs) = poll {
+                break outputs;
+            }
+        };
+
+        assert_eq!(polls, [vec![0, 1, 2], vec![3, 0, 1], vec![2, 3]]);
+        assert_eq!(outputs, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn join_all_polls_every_pending_child_on_each_wake() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let children = (0..3).map(|id| Logged {
+            id,
+            yields: id,
+            log: log.clone(),
+        });
+        let mut join = Box::pin(join_all(children));
+
+        let waker = parent_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(join.as_mut().poll(&mut cx).is_pending());
+        assert!(join.as_mut().poll(&mut cx).is_pending());
+        let Poll::Ready(outputs) = join.as_mut().poll(&mut cx) else {
+            panic!("all children completed");
+        };
+
+        assert_eq!(*log.borrow(), [0, 1, 2, 1, 2, 2]);
+        assert_eq!(outputs, [0, 1, 2]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Macros to wait on a fixed set of futures, of different types, from within a single task.
+//!
+//! [`join_all`](super::join_all) takes any number of futures, but all of one type, and hands
+//! back a `Vec`. [`join!`](crate::join) and [`select!`](crate::select) take up to 16 futures
+//! of any types, and expand in place to a future polling each of them in turn, which is
+//! awaited right away. Hence they can only be used in async code.
+//!
+//! ```ignore
+//! let (user, orders) = join!(fetch_user(id), fetch_orders(id));
+//!
+//! let body = select! {
+//!     response = client.get("/0/fast") => response?.body().to_string(),
+//!     _ = sleep(Duration::from_secs(1)) => String::from("timed out"),
+//! };
+//! ```
+
+/// Wait for all of the futures, resolving to a tuple of their outputs in the same order.
+///
+/// Every unfinished future is polled whenever the task is woken, as with
+/// [`join_all`](crate::future::join_all).
+#[macro_export]
+macro_rules! join {
+    // give each future a name of its own, taken from the list of names
+    (@name [$($named:tt)*] [$name:ident $($names:ident)*] $future:expr, $($rest:tt)*) => {
+        $crate::join!(@name [$($named)* ($name, $future)] [$($names)*] $($rest)*)
+    };
+    (@name [$(($name:ident, $future:expr))*] [$($names:ident)*]) => {{
+        $(let mut $name = $crate::future::Child::new($future);)*
+
+        ::std::future::poll_fn(|cx| {
+            let mut pending = false;
+            $(
+                $name.poll(cx);
+                pending |= $name.is_pending();
+            )*
+
+            if pending {
+                ::std::task::Poll::Pending
+            } else {
+                ::std::task::Poll::Ready(())
+            }
+        })
+        .await;
+
+        ($($name.take_output(),)*)
+    }};
+    (@name $($rest:tt)*) => {
+        compile_error!("join! takes up to 16 futures")
+    };
+    ($($future:expr),+ $(,)?) => {
+        $crate::join!(
+            @name [] [_0 _1 _2 _3 _4 _5 _6 _7 _8 _9 _10 _11 _12 _13 _14 _15] $($future,)+
+        )
+    };
+}
+
+/// Wait for the first of the futures to complete, then evaluate its branch with the output
+/// bound to the pattern. The other futures are dropped, i.e. cancelled.
+///
+/// ```ignore
+/// select! {
+///     pattern = future => expression,
+///     ...
+/// }
+/// ```
+///
+/// Futures are polled in the order of their branches, so if several are ready at once the
+/// first one wins. Patterns must be irrefutable, and branches separated by commas.
+#[macro_export]
+macro_rules! select {
+    // give each branch a name of its own, taken from the list of names
+    (
+        @branch [$($named:tt)*] [$name:ident $($names:ident)*]
+        $pattern:pat = $future:expr => $body:expr, $($rest:tt)*
+    ) => {
+        $crate::select!(
+            @branch [$($named)* ($name, $pattern, $future, $body)] [$($names)*] $($rest)*
+        )
+    };
+    // last branch, without a trailing comma
+    (@branch [$($named:tt)*] [$($names:ident)*] $pattern:pat = $future:expr => $body:expr) => {
+        $crate::select!(@branch [$($named)*] [$($names)*] $pattern = $future => $body,)
+    };
+    (
+        @branch [$(($name:ident, $pattern:pat, $future:expr, $body:expr))*] [$($names:ident)*]
+    ) => {{
+        // one variant per branch, holding the output of its future
+        #[allow(non_camel_case_types)]
+        enum Selected<$($name),*> {
+            $($name($name),)*
+        }
+
+        $(let mut $name = ::std::boxed::Box::pin($future);)*
+
+        let selected = ::std::future::poll_fn(|cx| {
+            $(
+                if let ::std::task::Poll::Ready(output) =
+                    ::std::future::Future::poll($name.as_mut(), cx)
+                {
+                    return ::std::task::Poll::Ready(Selected::$name(output));
+                }
+            )*
+            ::std::task::Poll::Pending
+        })
+        .await;
+
+        match selected {
+            $(Selected::$name($pattern) => $body,)*
+        }
+    }};
+    (@branch $($rest:tt)*) => {
+        compile_error!("select! takes up to 16 branches, as `pattern = future => expression`")
+    };
+    ($($branches:tt)+) => {
+        $crate::select!(
+            @branch [] [_0 _1 _2 _3 _4 _5 _6 _7 _8 _9 _10 _11 _12 _13 _14 _15] $($branches)+
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::runtime::{sync::oneshot, yield_now, Executor};
+
+    #[test]
+    fn join_resolves_to_outputs_of_any_type() {
+        let outputs = Rc::new(Cell::new(None));
+
+        Executor::new().block_on({
+            let outputs = outputs.clone();
+            async move {
+                let (tx, rx) = oneshot::channel();
+                let sender = async move {
+                    // the receiver is polled first, so it has to wait for this
+                    yield_now().await;
+                    tx.send("sent");
+                };
+
+                let ((), received, number) = crate::join!(sender, rx, async { 42 });
+                outputs.set(Some((received.unwrap(), number)));
+            }
+        });
+
+        assert_eq!(outputs.get(), Some(("sent", 42)));
+    }
+
+    #[test]
+    fn select_takes_the_first_future_ready_and_drops_the_others() {
+        let selected = Rc::new(Cell::new(""));
+        let alive = Rc::new(());
+
+        Executor::new().block_on({
+            let selected = selected.clone();
+            let kept = alive.clone();
+            async move {
+                let never = async move {
+                    let _kept = kept;
+                    std::future::pending::<()>().await
+                };
+
+                let branch = crate::select! {
+                    _ = never => "never ready",
+                    _ = yield_now() => "yielded",
+                    n = async { 1 } => if n == 1 { "ready" } else { "wrong output" },
+                };
+                selected.set(branch);
+            }
+        });
+
+        assert_eq!(selected.get(), "ready");
+        // the future that was never ready went away along with its branch
+        assert_eq!(Rc::strong_count(&alive), 1);
+    }
+
+    #[test]
+    fn select_waits_for_a_pending_branch() {
+        let selected = Rc::new(Cell::new(0));
+
+        Executor::new().block_on({
+            let selected = selected.clone();
+            async move {
+                let (tx, rx) = oneshot::channel();
+                crate::runtime::spawn_local(async move { tx.send(7) });
+
+                crate::select! {
+                    value = rx => selected.set(value.unwrap())
+                }
+            }
+        });
+
+        assert_eq!(selected.get(), 7);
+    }
+}
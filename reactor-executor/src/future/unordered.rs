@@ -0,0 +1,232 @@
+//! A set of futures that can grow while it is polled, yielding outputs as the futures complete.
+//!
+//! [`join_all`](super::join_all) takes all of its children up front, and resolves once every
+//! one of them did. [`FuturesUnordered`] takes more at any time, and is a [`Stream`] of their
+//! outputs in the order they complete, e.g. to start a request per result of an earlier one
+//! within a single task, rather than spawning a task per request.
+//!
+//! Like [`join_all_budgeted`](super::join_all_budgeted), each future is polled with a waker of
+//! its own (see [`WakeSet`]), so only woken futures are polled. Slots of completed futures are
+//! reused by the ones pushed after, along with their wakers.
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::{Stream, WakeSet};
+
+/// Set of futures driven together, see the module docs.
+///
+/// ```ignore
+/// let mut requests: FuturesUnordered<_> = paths.map(|path| client.get(path)).collect();
+/// while let Some(response) = requests.next().await {
+///     for link in links(&response?) {
+///         requests.push(client.get(link));
+///     }
+/// }
+/// ```
+pub struct FuturesUnordered<F> {
+    /// Futures by waker index, None once completed
+    slots: Vec<Option<Pin<Box<F>>>>,
+    /// Indices of empty slots, taken by the next futures pushed
+    free: Vec<usize>,
+    wakers: WakeSet,
+}
+
+impl<F> FuturesUnordered<F> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            wakers: WakeSet::new(0),
+        }
+    }
+
+    /// Add `future` to the set. It is first polled on the next poll of the set.
+    pub fn push(&mut self, future: F) {
+        let future = Some(Box::pin(future));
+
+        match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = future;
+                self.wakers.reset(index);
+            }
+            None => {
+                let index = self.wakers.push();
+                debug_assert_eq!(index, self.slots.len());
+                self.slots.push(future);
+            }
+        }
+    }
+
+    /// Number of futures that have not completed yet.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<F> Default for FuturesUnordered<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F> FromIterator<F> for FuturesUnordered<F> {
+    fn from_iter<I: IntoIterator<Item = F>>(futures: I) -> Self {
+        let mut set = Self::new();
+        for future in futures {
+            set.push(future);
+        }
+        set
+    }
+}
+
+// Futures are pinned in their own boxes, so moving the set itself is fine.
+impl<F> Unpin for FuturesUnordered<F> {}
+
+impl<F: Future> Stream for FuturesUnordered<F> {
+    type Item = F::Output;
+
+    /// Poll the woken futures until one completes, yielding its output. `Ready(None)` while
+    /// the set is empty, though futures pushed after are polled as usual.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<F::Output>> {
+        let this = &mut *self;
+        if this.is_empty() {
+            return Poll::Ready(None);
+        }
+        this.wakers.register(cx.waker());
+
+        // each future at most once per poll, so futures that keep waking themselves can not
+        // keep us from returning to the executor
+        for _ in 0..this.slots.len() {
+            let Some(index) = this.wakers.pop() else {
+                return Poll::Pending;
+            };
+            // woken after it completed
+            let Some(future) = &mut this.slots[index] else {
+                continue;
+            };
+
+            let mut child_cx = Context::from_waker(this.wakers.waker(index));
+            if let Poll::Ready(output) = future.as_mut().poll(&mut child_cx) {
+                this.slots[index] = None;
+                this.free.push(index);
+                return Poll::Ready(Some(output));
+            }
+        }
+
+        // out of budget with futures still to poll: yield, and carry on when polled next
+        if this.wakers.has_woken() {
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, sync::Arc, task::Waker};
+
+    use super::*;
+    use crate::{
+        future::StreamExt,
+        runtime::{sync::oneshot, yield_now, Executor, MyWaker},
+    };
+
+    fn parent_waker() -> Waker {
+        let queue = Arc::default();
+        Arc::new(MyWaker::new(0, &queue)).into()
+    }
+
+    /// Yields `n` times, then resolves to `n`.
+    async fn countdown(n: usize) -> usize {
+        for _ in 0..n {
+            yield_now().await;
+        }
+        n
+    }
+
+    #[test]
+    fn outputs_come_in_the_order_futures_complete() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..3).map(|_| oneshot::channel()).unzip();
+
+        let mut set: FuturesUnordered<_> = receivers
+            .into_iter()
+            .enumerate()
+            .map(|(id, mut rx)| {
+                let log = log.clone();
+                std::future::poll_fn(move |cx| {
+                    log.borrow_mut().push(id);
+                    Pin::new(&mut rx).poll(cx).map(Result::unwrap)
+                })
+            })
+            .collect();
+
+        let waker = parent_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut poll = |set: &mut FuturesUnordered<_>| Pin::new(set).poll_next(&mut cx);
+
+        assert!(poll(&mut set).is_pending());
+        assert_eq!(log.take(), [0, 1, 2]);
+
+        let mut senders: Vec<_> = senders.into_iter().map(Some).collect();
+        for id in [2, 0, 1] {
+            senders[id].take().unwrap().send(id * 10);
+            assert_eq!(poll(&mut set), Poll::Ready(Some(id * 10)));
+            // only the woken future was polled
+            assert_eq!(log.take(), [id]);
+        }
+
+        assert_eq!(poll(&mut set), Poll::Ready(None));
+    }
+
+    #[test]
+    fn futures_pushed_while_draining_are_polled() {
+        let outputs = Rc::new(RefCell::new(Vec::new()));
+        let seen = outputs.clone();
+
+        Executor::new().block_on(async move {
+            let mut set: FuturesUnordered<_> = [3, 1].map(countdown).into_iter().collect();
+
+            while let Some(n) = set.next().await {
+                seen.borrow_mut().push(n);
+                if n > 0 {
+                    set.push(countdown(n - 1));
+                }
+            }
+        });
+
+        let mut outputs = outputs.take();
+        outputs.sort();
+        assert_eq!(outputs, [0, 0, 1, 1, 2, 3]);
+    }
+
+    #[test]
+    fn completed_slots_are_reused() {
+        let waker = parent_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut set = FuturesUnordered::new();
+
+        for round in 0..3 {
+            set.push(std::future::ready(round));
+            set.push(std::future::ready(round));
+
+            let mut set = Pin::new(&mut set);
+            assert_eq!(set.as_mut().poll_next(&mut cx), Poll::Ready(Some(round)));
+            assert_eq!(set.as_mut().poll_next(&mut cx), Poll::Ready(Some(round)));
+        }
+
+        assert!(set.is_empty());
+        assert_eq!(
+            set.wakers.len(),
+            2,
+            "a waker per slot, however many futures passed"
+        );
+    }
+}
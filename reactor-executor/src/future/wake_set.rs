@@ -0,0 +1,144 @@
+//! Waker composition: a waker per child of a combinator, so the combinator knows which of its
+//! children were woken, rather than polling all of them whenever one of them is.
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    task::{Wake, Waker},
+};
+
+/// Hands out a [`Waker`] for each of `n` children. Waking a child queues its index and wakes
+/// the parent task, see [`WakeSet::register`].
+pub struct WakeSet {
+    shared: Arc<Mutex<State>>,
+    wakers: Vec<Waker>,
+}
+
+struct State {
+    /// Indices of woken children, in the order they were woken
+    woken: VecDeque<usize>,
+    /// `queued[i]` is set while child `i` is in `woken`, so waking it twice queues it once
+    queued: Vec<bool>,
+    /// Waker of the task polling the parent
+    parent: Option<Waker>,
+}
+
+impl WakeSet {
+    /// Wakers for `n` children. All of them start out woken, so each is polled once.
+    pub fn new(n: usize) -> Self {
+        let mut set = Self {
+            shared: Arc::new(Mutex::new(State {
+                woken: VecDeque::new(),
+                queued: Vec::new(),
+                parent: None,
+            })),
+            wakers: Vec::new(),
+        };
+
+        for _ in 0..n {
+            set.push();
+        }
+        set
+    }
+
+    /// Add a waker for one more child, returning its index. Like the first `n`, the child
+    /// starts out woken.
+    pub fn push(&mut self) -> usize {
+        let index = self.wakers.len();
+        {
+            let mut state = self.shared.lock().unwrap();
+            state.queued.push(true);
+            state.woken.push_back(index);
+        }
+
+        self.wakers.push(
+            Arc::new(ChildWaker {
+                index,
+                shared: self.shared.clone(),
+            })
+            .into(),
+        );
+        index
+    }
+
+    /// Queue child `index` to be polled, without waking the parent, e.g. for a new child
+    /// taking over the index of one that completed.
+    pub fn reset(&self, index: usize) {
+        let mut state = self.shared.lock().unwrap();
+        if !state.queued[index] {
+            state.queued[index] = true;
+            state.woken.push_back(index);
+        }
+    }
+
+    /// Number of children wakers were handed out for.
+    pub fn len(&self) -> usize {
+        self.wakers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wakers.is_empty()
+    }
+
+    /// The waker to poll child `index` with.
+    pub fn waker(&self, index: usize) -> &Waker {
+        &self.wakers[index]
+    }
+
+    /// Store the waker of the task polling the parent. Call on every poll of the parent, it
+    /// may have moved to another task since the last one.
+    pub fn register(&self, parent: &Waker) {
+        let mut state = self.shared.lock().unwrap();
+        match &state.parent {
+            Some(current) if current.will_wake(parent) => {}
+            _ => state.parent = Some(parent.clone()),
+        }
+    }
+
+    /// Take the child that was woken the longest ago.
+    pub fn pop(&self) -> Option<usize> {
+        let mut state = self.shared.lock().unwrap();
+        let index = state.woken.pop_front()?;
+        state.queued[index] = false;
+        Some(index)
+    }
+
+    /// True if any child is waiting to be polled.
+    pub fn has_woken(&self) -> bool {
+        !self.shared.lock().unwrap().woken.is_empty()
+    }
+}
+
+struct ChildWaker {
+    index: usize,
+    shared: Arc<Mutex<State>>,
+}
+
+impl Wake for ChildWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let parent = {
+            let mut state = self.shared.lock().unwrap();
+            if state.queued[self.index] {
+                return;
+            }
+            state.queued[self.index] = true;
+            state.woken.push_back(self.index);
+
+            // The parent only needs waking when the queue stops being empty. Otherwise it was
+            // woken before, and gets to this child before returning Pending, or wakes itself
+            // to carry on later.
+            if state.woken.len() > 1 {
+                return;
+            }
+            state.parent.clone()
+        };
+
+        // wake outside the lock, the parent may be polled on another thread
+        if let Some(parent) = parent {
+            parent.wake();
+        }
+    }
+}
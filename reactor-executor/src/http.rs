@@ -1,19 +1,58 @@
 //! Code related to http client
 //!
-//! Makes only GET requests to the delayserver in `rust-async-utils`
+//! Makes requests to the delayserver in `rust-async-utils`
 #![allow(unused)]
 use std::{
     future::Future,
-    io::{ErrorKind, Read, Write},
+    io::{self, ErrorKind, Read, Write},
     pin::Pin,
+    sync::OnceLock,
     task::{Context, Poll},
+    time::Duration,
 };
 
-use mio::Interest;
+use mio::{event::Source, net::TcpStream, Interest, Registry, Token};
 
-use crate::runtime::{self, reactor, MyWaker};
+use crate::{
+    future::Progress,
+    io::{AsyncRead, AsyncWrite},
+    runtime::{self, log, reactor, MyWaker, PooledBuffer, Sleep, StoredWaker},
+    sim,
+};
+
+mod body;
+mod cassette;
+mod chunked;
+mod error;
+mod pipeline;
+mod request;
+mod response;
+#[cfg(feature = "tls")]
+mod tls;
+
+pub use body::BodyStream;
+pub use cassette::Cassette;
+use chunked::ChunkedDecoder;
+pub use error::HttpError;
+pub use pipeline::{Pipeline, PipelinedRequest};
+pub use request::{Method, RequestBuilder};
+pub use response::Response;
+
+/// Address of the delayserver used when none is given explicitly
+static DEFAULT_DELAYSERVER: &str = "127.0.0.1:8080";
 
-static DELAYSERVER: &str = "127.0.0.1:8080";
+/// Environment variable that overrides [`DEFAULT_DELAYSERVER`]
+static DELAYSERVER_ENV: &str = "DELAYSERVER_ADDR";
+
+/// Address requests go to unless given one via [`Http::with_addr`] or
+/// [`RequestBuilder::addr`]. The environment is only read once.
+fn default_addr() -> &'static str {
+    static ADDR: OnceLock<String> = OnceLock::new();
+
+    ADDR.get_or_init(|| {
+        std::env::var(DELAYSERVER_ENV).unwrap_or_else(|_| DEFAULT_DELAYSERVER.to_string())
+    })
+}
 
 // traits and types from reading from a IO source
 
@@ -24,147 +63,981 @@ static DELAYSERVER: &str = "127.0.0.1:8080";
 pub struct Http;
 
 impl Http {
+    /// Returns a future that yields the response of the HTTP request, or the error it failed
+    /// with, e.g. [`HttpError::Connect`] if the server refused the connection.
+    pub fn get(path: &str) -> impl Future<Output = Result<Response, HttpError>> {
+        HttpGetFuture::new(Self::request().path(path))
+    }
+
+    /// Same as [`Http::get`], but fails with [`HttpError::Timeout`] if the response did not
+    /// arrive within `timeout`, see [`RequestBuilder::timeout`].
+    pub fn get_with_timeout(
+        path: &str,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Response, HttpError>> {
+        Self::request().path(path).timeout(timeout).send()
+    }
+
+    /// Returns a future that yields the response of a GET request to `host` over HTTPS, e.g.
+    /// `Http::get_https("example.com", "/")`. The port defaults to 443, and the server's
+    /// certificate has to be signed by one of the Mozilla root certificate authorities.
+    ///
+    /// See [`RequestBuilder::tls`] to trust other certificates.
+    #[cfg(feature = "tls")]
+    pub fn get_https(host: &str, path: &str) -> impl Future<Output = Result<Response, HttpError>> {
+        let addr = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:{}", tls::HTTPS_PORT)
+        };
+
+        Self::request()
+            .addr(&addr)
+            .header("Host", host)
+            .tls(tls::default_config())
+            .path(path)
+            .send()
+    }
+
+    /// Returns a future that yields the response of a POST request with the given body
+    pub fn post(
+        path: &str,
+        body: impl Into<Vec<u8>>,
+    ) -> impl Future<Output = Result<Response, HttpError>> {
+        Self::request()
+            .method(Method::Post)
+            .path(path)
+            .body(body)
+            .send()
+    }
+
+    /// Start building a request with a custom method, headers and body.
+    pub fn request() -> RequestBuilder {
+        RequestBuilder::new()
+    }
+
+    /// Same as [`Http::get`], but the response body is handed out in chunks as they arrive,
+    /// see [`BodyStream`].
+    pub fn get_streaming(path: &str) -> BodyStream {
+        Self::request().path(path).send_streaming()
+    }
+
+    /// Same as [`Http::get`], but the first poll speculatively reads from the stream before
+    /// registering interest with the reactor.
+    ///
+    /// If the response is already buffered by the time we read, the future resolves without
+    /// ever touching `epoll_ctl`. We only fall back to registering with the reactor once a
+    /// read returns `WouldBlock`.
+    pub fn get_speculative(path: &str) -> impl Future<Output = Result<Response, HttpError>> {
+        let mut future = HttpGetFuture::new(Self::request().path(path));
+        future.socket.speculative = true;
+        future
+    }
+
+    /// Returns a client that sends requests to `addr` (`host:port`) rather than the default
+    /// delayserver address.
+    pub fn with_addr(addr: &str) -> Client {
+        Client {
+            addr: addr.to_string(),
+        }
+    }
+}
+
+/// Http client bound to a specific server address, see [`Http::with_addr`].
+#[derive(Debug, Clone)]
+pub struct Client {
+    addr: String,
+}
+
+impl Client {
     /// Returns a future that yields the response of the HTTP request
-    pub fn get(path: &str) -> impl Future<Output = String> {
-        HttpGetFuture::new(path)
+    pub fn get(&self, path: &str) -> impl Future<Output = Result<Response, HttpError>> {
+        self.request().path(path).send()
+    }
+
+    /// Returns a future that yields the response of a POST request with the given body
+    pub fn post(
+        &self,
+        path: &str,
+        body: impl Into<Vec<u8>>,
+    ) -> impl Future<Output = Result<Response, HttpError>> {
+        self.request()
+            .method(Method::Post)
+            .path(path)
+            .body(body)
+            .send()
+    }
+
+    /// Start building a request to this client's address.
+    pub fn request(&self) -> RequestBuilder {
+        RequestBuilder::new().addr(&self.addr)
+    }
+
+    /// Send requests to this client's address over a single kept-alive connection, without
+    /// waiting for a response before sending the next request. See [`Pipeline`].
+    pub fn pipeline(&self) -> Pipeline {
+        Pipeline::new(&self.addr)
+    }
+}
+
+/// Connection of a request, over plain TCP or TLS, or to an endpoint of the installed
+/// simulated network.
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<tls::TlsStream>),
+    /// Woken by the virtual clock of the network rather than the reactor, so it is never
+    /// registered with it, see [`Socket`]
+    Sim(sim::DuplexStream),
+}
+
+impl Stream {
+    /// Events to wait for once a read would block.
+    fn interest(&self) -> Interest {
+        match self {
+            Self::Tcp(_) | Self::Sim(_) => Interest::READABLE,
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.interest(),
+        }
+    }
+
+    fn is_handshaking(&self) -> bool {
+        match self {
+            Self::Tcp(_) | Self::Sim(_) => false,
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.is_handshaking(),
+        }
+    }
+
+    /// True if the server closed a TLS stream without a close_notify alert, so the response
+    /// may have been cut short by an attacker, see [`HttpGetFuture::is_whole`].
+    fn closed_without_notify(&self) -> bool {
+        match self {
+            Self::Tcp(_) | Self::Sim(_) => false,
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.closed_without_notify(),
+        }
+    }
+
+    fn tcp_mut(&mut self) -> &mut TcpStream {
+        match self {
+            Self::Tcp(stream) => stream,
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.tcp_mut(),
+            Self::Sim(_) => unreachable!("simulated streams are not registered with the reactor"),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.read(buf),
+            Self::Sim(stream) => stream.try_read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.write(buf),
+            Self::Sim(stream) => stream.try_write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.flush(),
+            Self::Sim(_) => Ok(()),
+        }
+    }
+}
+
+/// Events are those of the underlying TCP stream, whatever runs on top of it.
+impl Source for Stream {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.tcp_mut().register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.tcp_mut().reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.tcp_mut().deregister(registry)
+    }
+}
+
+/// Connection of a request, registered with the reactor once a read or write would block.
+///
+/// Reads and writes go through [`AsyncRead`] and [`AsyncWrite`], which leave the latest waker
+/// with the reactor whenever the stream is not ready.
+struct Socket {
+    /// Optional since we do not connect on instantiation of HttpGetFuture
+    stream: Option<Stream>,
+    /// NEW: id retrieved from reactor for our source we want to track events on.
+    id: usize,
+    /// If true, attempt a read before registering the stream with the reactor.
+    speculative: bool,
+    /// Tracks if the stream is currently registered with the reactor, so that we only
+    /// deregister sources we actually registered.
+    registered: bool,
+    /// Events the stream is registered for, see `Stream::interest`
+    interest: Interest,
+    /// Waker left with the reactor for `id`, see `Reactor::update_waker`
+    waker: StoredWaker,
+    /// Set once `id` has been handed back to the reactor, which reuses it for other sources.
+    released: bool,
+}
+
+impl Socket {
+    fn new() -> Self {
+        Self {
+            stream: None,
+            id: reactor().next_id(),
+            speculative: false,
+            registered: false,
+            interest: Interest::READABLE,
+            waker: StoredWaker::default(),
+            released: false,
+        }
+    }
+
+    /// Register interest in `interest` events for our stream with the reactor.
+    fn register(&mut self, interest: Interest) {
+        // implements the mio `Source` trait by way of the TcpStream it wraps
+        let stream = self.stream.as_mut().unwrap();
+
+        // NEW: register interest with event queue
+        reactor().register(stream, interest, self.id);
+        self.registered = true;
+        self.interest = interest;
+    }
+
+    /// Called once a read or write would block: make sure the reactor wakes us for the events
+    /// the stream waits for now, with the latest waker. That is WRITABLE as well while
+    /// `writing`, and whatever the stream waits for otherwise.
+    fn wait_for_io(&mut self, cx: &mut Context, writing: bool) {
+        let mut interest = self.stream.as_ref().unwrap().interest();
+        if writing {
+            interest |= Interest::WRITABLE;
+        }
+
+        if !self.registered {
+            self.register(interest);
+        } else if interest != self.interest {
+            // the request may have been sent since, or a TLS handshake have records left to send
+            let stream = self.stream.as_mut().unwrap();
+            reactor().set_interest(stream, interest, self.id);
+            self.interest = interest;
+        }
+
+        reactor().update_waker(cx, self.id, &mut self.waker);
+    }
+
+    /// Run `op` on the stream, retrying if interrupted, and waiting for the reactor if it
+    /// would block.
+    fn poll_op<T>(
+        &mut self,
+        cx: &mut Context,
+        writing: bool,
+        mut op: impl FnMut(&mut Stream) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            match op(self.stream.as_mut().unwrap()) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    // NOTE: we can reach here via been polled the first or subsequent times. We
+                    // must ensure that we always register the latest waker with the Reactor if
+                    // we are still waiting to be notified. This is because the future may have
+                    // been polled on a different executor between polls. So the piror waker
+                    // stored in reactor may be associated with the previous executor it was on.
+                    //
+                    // A speculative read that would block is where we first register the stream.
+                    self.wait_for_io(cx, writing);
+                    return Poll::Pending;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+
+    /// Error for a failed read, which fails the handshake if it is not done yet.
+    fn read_error(&self, e: io::Error) -> HttpError {
+        if self.stream.as_ref().is_some_and(Stream::is_handshaking) {
+            HttpError::Tls(e)
+        } else {
+            HttpError::Read(e)
+        }
+    }
+
+    /// Deregister the stream and drop its waker if we registered it, handing back our id
+    /// either way.
+    fn release(&mut self) {
+        if self.released {
+            return;
+        }
+        self.released = true;
+
+        // NEW: No longer interested in notifications for this event source
+        if self.registered {
+            reactor().deregister(Box::new(self.stream.take().unwrap()), self.id);
+            self.registered = false;
+            self.waker.clear();
+        } else {
+            reactor().release_id(self.id);
+        }
+    }
+}
+
+impl AsyncRead for Socket {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        // a simulated stream leaves our waker with the virtual clock itself
+        if let Some(Stream::Sim(stream)) = this.stream.as_mut() {
+            return Pin::new(stream).poll_read(cx, buf);
+        }
+
+        // Fast path: a speculative socket skips registration for now, and lets the read find
+        // out if the response is already available. It registers only on `WouldBlock`.
+        if !this.registered && !this.speculative {
+            let interest = this.stream.as_ref().unwrap().interest();
+            this.register(interest);
+
+            // NEW: rather than pass in `waker`, we now pass in the full Context `cx`
+            reactor().update_waker(cx, this.id, &mut this.waker);
+        }
+
+        this.poll_op(cx, false, |stream| stream.read(buf))
+    }
+}
+
+impl AsyncWrite for Socket {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if let Some(Stream::Sim(stream)) = this.stream.as_mut() {
+            return Pin::new(stream).poll_write(cx, buf);
+        }
+        this.poll_op(cx, true, |stream| stream.write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(Stream::Sim(stream)) = this.stream.as_mut() {
+            return Pin::new(stream).poll_flush(cx);
+        }
+        this.poll_op(cx, true, Stream::flush)
+    }
+}
+
+/// A future dropped before completing, e.g. cancelled by a timeout, would otherwise leave its
+/// stream registered and its waker behind in the reactor.
+impl Drop for Socket {
+    fn drop(&mut self) {
+        self.release();
     }
 }
 
 /// A Leaf Future
 ///
+/// Despite the name, this drives any request built via [`RequestBuilder`], not only GETs.
+///
 /// This future is !Unpin, as there is nothing that makes it unsafe
 /// to move it around. Only futures created via async/await are self-referential.
 struct HttpGetFuture {
-    /// Optional since we do not connect on instantiation of HttpGetFuture
-    stream: Option<mio::net::TcpStream>,
-    /// data read from TCP stream is placed here
-    buffer: Vec<u8>,
+    /// Connection to the server, not made until the first poll
+    socket: Socket,
+    /// Config to connect over TLS with, if the request is sent over HTTPS
+    #[cfg(feature = "tls")]
+    tls: Option<std::sync::Arc<rustls::ClientConfig>>,
+    /// data read from TCP stream is placed here. Pooled, as is the scratch buffer reads go
+    /// through, since a connection is made for every request.
+    buffer: PooledBuffer,
+    /// request is serialized into bytes when the builder is turned into a future
+    request: Vec<u8>,
+    /// How much of `request` the socket took so far. We only start reading the response once
+    /// all of it is written.
+    written: usize,
+    /// `host:port` of the server we connect to
+    addr: String,
     path: String,
-    /// NEW: id retrieved from reactor for our source we want to track events on.
-    id: usize,
+    /// Set once the end of the response headers has been found in `buffer`.
+    head_parsed: bool,
+    /// Present if the response uses `Transfer-Encoding: chunked`. From then on `buffer` holds
+    /// the response head followed by the decoded payload only.
+    chunked: Option<ChunkedDecoder>,
+    /// Length of the response, head included, once the head declared a `Content-Length`.
+    /// Only used to report progress.
+    length: Option<usize>,
+    /// Bytes read from the socket at once, see [`RequestBuilder::read_size`]
+    read_size: usize,
+    /// Bytes of the response buffered at most, see [`RequestBuilder::max_response_size`]
+    max_response: Option<usize>,
+    /// See [`RequestBuilder::timeout`]
+    timeout: Option<Duration>,
+    /// Timer for `timeout`, started on the first poll
+    deadline: Option<Sleep>,
 }
 
 impl HttpGetFuture {
-    fn new(path: &str) -> Self {
-        let id = reactor().next_id();
-
+    fn new(request: RequestBuilder) -> Self {
         Self {
             // do not connect yet, only on first poll
-            stream: None,
-            buffer: Vec::new(),
-            path: path.to_string(),
-            id,
+            socket: Socket::new(),
+            #[cfg(feature = "tls")]
+            tls: request.tls_config(),
+            buffer: PooledBuffer::new(),
+            request: request.to_bytes(),
+            written: 0,
+            addr: request.addr_str().to_string(),
+            path: request.path_str().to_string(),
+            head_parsed: false,
+            chunked: None,
+            length: None,
+            read_size: request.read_size_bytes(),
+            max_response: request.max_response_bytes(),
+            timeout: request.timeout_duration(),
+            deadline: None,
         }
     }
 
-    /// Makes a non-blocking write request to the delayserver
-    /// and stores the created stream on the future.
-    fn write_request(&mut self) {
-        // Create a standard library stream first and wrap it in mio stream
-        let stream = std::net::TcpStream::connect(DELAYSERVER).unwrap();
-        stream.set_nonblocking(true).unwrap();
-        let mut stream = mio::net::TcpStream::from_std(stream);
+    /// Connects to the server and stores the created stream on the future. The endpoints of
+    /// the installed simulated network are connected to in memory, see [`sim::Network`].
+    fn connect(&mut self) -> Result<(), HttpError> {
+        if let Some(stream) = sim::connect(&self.addr) {
+            #[cfg(feature = "tls")]
+            if self.tls.is_some() {
+                let msg = "TLS is not supported over a simulated network";
+                return Err(HttpError::Tls(io::Error::new(ErrorKind::Unsupported, msg)));
+            }
+
+            self.socket.stream = Some(Stream::Sim(stream));
+            return Ok(());
+        }
 
-        let req = get_req(&self.path);
+        // Create a standard library stream first and wrap it in mio stream
+        let stream = std::net::TcpStream::connect(&self.addr).map_err(HttpError::Connect)?;
+        stream.set_nonblocking(true).map_err(HttpError::Connect)?;
+        let stream = TcpStream::from_std(stream);
 
-        // non-blocking IO operation
-        stream.write_all(&req).unwrap();
+        #[cfg(feature = "tls")]
+        let mut stream = match self.tls.clone() {
+            Some(config) => tls::TlsStream::new(stream, config, &self.addr)
+                .map(|stream| Stream::Tls(Box::new(stream)))
+                .map_err(HttpError::Tls)?,
+            None => Stream::Tcp(stream),
+        };
+        #[cfg(not(feature = "tls"))]
+        let stream = Stream::Tcp(stream);
 
         // store stream on future
-        self.stream = Some(stream);
+        self.socket.stream = Some(stream);
+        Ok(())
+    }
+
+    /// Connect on first poll, then write the request, resuming from where the last poll left
+    /// off. Ready once all of it is written.
+    ///
+    /// A request larger than the socket's send buffer, e.g. one with a large body, is only
+    /// taken in part. We then wait for the socket to become WRITABLE, rather than failing with
+    /// `WouldBlock`. Over TLS the request is buffered by rustls as a whole, and sent once the
+    /// handshake is done, from within the reads made while polling.
+    fn poll_write_request(&mut self, cx: &mut Context) -> Poll<Result<(), HttpError>> {
+        if self.socket.stream.is_none() {
+            log::debug!("First poll, sending request to {}{}", self.addr, self.path);
+            self.connect()?;
+        }
+
+        while self.written < self.request.len() {
+            let remaining = &self.request[self.written..];
+            match Pin::new(&mut self.socket).poll_write(cx, remaining) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(HttpError::Write(ErrorKind::WriteZero.into())))
+                }
+                Poll::Ready(Ok(n)) => self.written += n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(HttpError::Write(e))),
+                Poll::Pending => {
+                    log::debug!(
+                        "Sent {} of {} request bytes, waiting for the socket",
+                        self.written,
+                        self.request.len()
+                    );
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Ready with [`HttpError::Timeout`] once the timeout expired, if one was set. Otherwise the
+    /// timer is left with the waker of `cx`, next to the waker left with the reactor.
+    fn poll_deadline(&mut self, cx: &mut Context) -> Poll<HttpError> {
+        let Some(timeout) = self.timeout else {
+            return Poll::Pending;
+        };
+        let deadline = self.deadline.get_or_insert_with(|| runtime::sleep(timeout));
+
+        match Pin::new(deadline).poll(cx) {
+            Poll::Ready(()) => {
+                log::debug!("No response from {} within {timeout:?}", self.addr);
+                Poll::Ready(HttpError::Timeout(timeout))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Handle bytes read from the stream.
+    ///
+    /// Until the response head is complete, bytes are buffered as is. If the head declares a
+    /// chunked body, everything after it is passed through the chunked decoder.
+    fn on_read(&mut self, data: &[u8]) -> Result<(), HttpError> {
+        if let Some(decoder) = self.chunked.as_mut() {
+            decoder
+                .feed(data, &mut self.buffer)
+                .map_err(invalid_chunk)?;
+            return Ok(());
+        }
+
+        self.buffer.extend_from_slice(data);
+
+        if self.head_parsed {
+            return Ok(());
+        }
+
+        let Some(end) = find_head_end(&self.buffer) else {
+            return Ok(());
+        };
+        self.head_parsed = true;
+        self.length = content_length(&self.buffer[..end]).map(|length| end + length);
+
+        if is_chunked(&self.buffer[..end]) {
+            let body = self.buffer.split_off(end);
+            let mut decoder = ChunkedDecoder::new();
+            decoder
+                .feed(&body, &mut self.buffer)
+                .map_err(invalid_chunk)?;
+            self.chunked = Some(decoder);
+        }
+
+        Ok(())
+    }
+
+    /// True if a chunked body has been fully received, so there is no need to wait for the
+    /// server to close the connection.
+    fn is_complete(&self) -> bool {
+        self.chunked
+            .as_ref()
+            .is_some_and(|decoder| decoder.is_done())
+    }
+
+    /// True if all of the response was received, as far as its framing tells. A response
+    /// without a `Content-Length` or chunked body ends wherever the server closes.
+    fn is_whole(&self) -> bool {
+        if !self.head_parsed {
+            return false;
+        }
+        match (&self.chunked, self.length) {
+            (Some(decoder), _) => decoder.is_done(),
+            (None, Some(length)) => self.buffer.len() >= length,
+            (None, None) => true,
+        }
+    }
+
+    /// Bytes of the response received so far, out of its length if the server declared one.
+    /// For a chunked response only the decoded payload is counted after the head.
+    fn progress(&self) -> Progress {
+        Progress {
+            done: self.buffer.len(),
+            total: self.length,
+        }
+    }
+
+    /// Deregister from the reactor and return the response read so far.
+    fn finish(&mut self) -> Result<Response, HttpError> {
+        self.socket.release();
+        Response::parse(String::from_utf8_lossy(&self.buffer).to_string())
+    }
+
+    /// Deregister from the reactor and fail with `err`.
+    fn fail(&mut self, err: HttpError) -> Poll<Result<Response, HttpError>> {
+        self.socket.release();
+        Poll::Ready(Err(err))
     }
 }
 
 impl Future for HttpGetFuture {
-    type Output = String;
-    /// Below can be viewed as a simple state machine with 3 possible states.
+    type Output = Result<Response, HttpError>;
+    /// Below can be viewed as a simple state machine with 4 possible states.
     ///
     /// 1. Not Started: indicated by self.stream being None.
-    /// 2. Pending: indicatd by self.stream being Some and a read to `stream.read`
+    /// 2. Writing: indicated by self.stream being Some and part of the request not written
+    ///    yet, as a write to `stream.write` returned `ErrorKind::WouldBlock`.
+    /// 3. Pending: indicatd by the request being written and a read to `stream.read`
     ///    returning `ErrorKind::WouldBlock`.
-    /// 3. Resolved, indicated by self.stream being Some and `stream.read`
+    /// 4. Resolved, indicated by self.stream being Some and `stream.read`
     ///    returning 0 bytes.
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         // If stream is none, this is first time we are polling the future, so
         // "progressing" the future, means making a request to the delayserver.
 
-        let id = self.id;
-
-        if self.stream.is_none() {
-            // Send GET request and store created stream on future.
-            println!("FIRST POLL - STARTING OPERATION - Make GET REQUEST");
-            self.write_request();
-
-            // It should be a mio::net::TcpStream, hence
-            // already implements the mio `Source` trait.
-            let stream = self.stream.as_mut().unwrap();
-
-            // NEW: register interest with event queue
-            reactor().register(stream, Interest::READABLE, id);
-
-            // NEW: rather than pass in `waker`, we now pass in the full Context `cx`
-            reactor().set_waker(cx, id);
+        if let Poll::Ready(err) = self.poll_deadline(cx) {
+            return self.fail(err);
+        }
 
-            // below was removed to enable us immediately poll the TcpStream.
-            // This means we will not return control to the scheduler if we happen
-            // to get the response immediately.
+        // Send request, or what is left of it, and store created stream on future.
+        match self.poll_write_request(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return self.fail(e),
+            Poll::Pending => return Poll::Pending,
         }
 
         // Reach here if this is not first poll on the future.
         // "Progressing" the future means waiting / checking if response is ready.
-        let mut buff = vec![0u8; 4096]; // 4Kb buffer
+        let mut buff = PooledBuffer::zeroed(self.read_size);
+        // bytes we may still read in this poll, see `runtime::set_read_budget`
+        let mut budget = runtime::read_budget();
 
-        // we keep trying to read from stream until we reach end
-        // or if operation would block
+        // we keep trying to read from stream until we reach end, if operation would block,
+        // or once we are out of budget
         loop {
-            match self.stream.as_mut().unwrap().read(&mut buff) {
-                Ok(0) => {
-                    // we have reached end of buffer
-                    let response = String::from_utf8_lossy(&self.buffer).to_string();
-
-                    // NEW: No longer interested in notifications for this event source
-                    reactor().deregister(self.stream.as_mut().unwrap(), id);
+            if budget == 0 {
+                // The rest is still buffered by the socket and there may not be another
+                // event for it, so we wake ourselves to read on once others had their turn.
+                runtime::wake_after_others(cx.waker());
+                break Poll::Pending;
+            }
 
-                    return Poll::Ready(response);
+            match Pin::new(&mut self.socket).poll_read(cx, &mut buff) {
+                Poll::Ready(Ok(0)) => {
+                    // we have reached end of buffer. Over TLS, a close without a close_notify may
+                    // come from an attacker rather than the server, so the response has to be
+                    // whole.
+                    let closed_without_notify = self
+                        .socket
+                        .stream
+                        .as_ref()
+                        .is_some_and(Stream::closed_without_notify);
+                    if closed_without_notify && !self.is_whole() {
+                        let msg = "closed without a close_notify before the end of the response";
+                        let e = io::Error::new(ErrorKind::UnexpectedEof, msg);
+                        return self.fail(HttpError::Read(e));
+                    }
+                    return Poll::Ready(self.finish());
                 }
-                Ok(n) => {
+                Poll::Ready(Ok(n)) => {
                     // we have read N bytes, extend buffer on future with temporary buffer.
+                    if let Err(e) = self.on_read(&buff[..n]) {
+                        return self.fail(e);
+                    }
+                    if let Some(limit) =
+                        self.max_response.filter(|limit| self.buffer.len() > *limit)
+                    {
+                        return self.fail(HttpError::TooLarge(limit));
+                    }
 
-                    self.buffer.extend_from_slice(&buff[..n]);
-                    continue;
+                    if self.is_complete() {
+                        return Poll::Ready(self.finish());
+                    }
+                    runtime::report_progress(self.progress());
+                    budget = budget.saturating_sub(n);
                 }
-                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    // we would block, return NotReady
-                    // also reach here if we are interrupted
-                    // return PollState::NotReady;
-
-                    // NOTE: we can reach here via been polled the first or subsequent times. We
-                    // must ensure that we always register the latest waker with the Reactor if we
-                    // are still waiting to be notified. This is because the future may have been
-                    // polled on a different executor between polls. So the piror waker stored in
-                    // reactor may be associated with the previous executor it was on.
-                    reactor().set_waker(cx, id);
-                    break Poll::Pending; // break and retun value from `loop`
+                Poll::Ready(Err(e)) => {
+                    let err = self.socket.read_error(e);
+                    return self.fail(err);
                 }
-                Err(e) if e.kind() == ErrorKind::Interrupted => {
-                    // try reading again
-                    continue;
-                }
-                // We do no error handling, so all we do is panic in below situation.
-                Err(e) => panic!("IO Error: {e:?}"),
+                // the socket left our waker with the reactor
+                Poll::Pending => break Poll::Pending,
             }
         }
     }
 }
 
-/// Helper function to write actual GET request as a stream of bytes
-fn get_req(path: &str) -> Vec<u8> {
-    let req = format!(
-        "GET {path} HTTP/1.1\r\n\
-             Host: localhost\r\n\
-             Connection: close\r\n\
-             \r\n"
-    );
+fn invalid_chunk(e: std::io::Error) -> HttpError {
+    HttpError::Parse(format!("invalid chunked body: {e}"))
+}
+
+/// Returns the index just past the `\r\n\r\n` that ends the response head.
+fn find_head_end(buffer: &[u8]) -> Option<usize> {
+    buffer
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+/// Checks the response head for a `Transfer-Encoding` header ending in `chunked`.
+fn is_chunked(head: &[u8]) -> bool {
+    String::from_utf8_lossy(head).lines().any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding")
+                && value.trim().to_ascii_lowercase().ends_with("chunked")
+        })
+    })
+}
+
+/// Value of the `Content-Length` header in `head`, if it has a valid one
+fn content_length(head: &[u8]) -> Option<usize> {
+    String::from_utf8_lossy(head).lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if !name.trim().eq_ignore_ascii_case("content-length") {
+            return None;
+        }
+        value.trim().parse().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn refused_connection_is_an_error() {
+        // nothing listens on the port once the listener is dropped
+        let addr = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+
+        runtime::init_for_tests().block_on(async move {
+            let err = Http::with_addr(&addr).get("/").await.unwrap_err();
+            match err {
+                HttpError::Connect(e) => assert_eq!(e.kind(), ErrorKind::ConnectionRefused),
+                other => panic!("expected a connect error, got {other}"),
+            }
+        });
+    }
+
+    #[test]
+    fn request_larger_than_the_send_buffer_is_written_in_parts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        // far more than the socket buffers on both ends hold
+        let body = vec![b'x'; 16 * 1024 * 1024];
+
+        std::thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            // let the client run into a full send buffer first
+            std::thread::sleep(Duration::from_millis(100));
+
+            let mut reader = std::io::BufReader::new(socket);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                std::io::BufRead::read_line(&mut reader, &mut line).unwrap();
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut received = vec![0; length];
+            reader.read_exact(&mut received).unwrap();
+
+            let reply = received.iter().filter(|b| **b == b'x').count().to_string();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{reply}",
+                reply.len()
+            )
+            .unwrap();
+        });
 
-    req.into_bytes()
+        runtime::init_for_tests().block_on(async move {
+            let mut request = Box::pin(Http::with_addr(&addr).post("/", body));
+            assert!(is_pending(&mut request).await, "waiting for room to write");
+
+            let response = request.await.unwrap();
+            assert_eq!(response.body(), (16 * 1024 * 1024).to_string());
+        });
+    }
+
+    /// Poll `future` once, returning whether it is still pending.
+    async fn is_pending<F: Future + Unpin>(future: &mut F) -> bool {
+        std::future::poll_fn(|cx| Poll::Ready(Pin::new(&mut *future).poll(cx).is_pending())).await
+    }
+
+    #[test]
+    fn read_loop_yields_once_out_of_budget() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).unwrap();
+
+            let body = vec![b'x'; 64 * 1024];
+            write!(
+                socket,
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            socket.write_all(&body).unwrap();
+        });
+
+        // other tests only yield more often while this is set
+        let budget = runtime::read_budget();
+        runtime::set_read_budget(4096);
+        runtime::init_for_tests().block_on(async move {
+            let mut request = Box::pin(HttpGetFuture::new(
+                Http::with_addr(&addr).request().path("/"),
+            ));
+            assert!(is_pending(&mut request).await, "sent the request");
+            // let the whole response arrive, so reading is never cut short by the socket
+            std::thread::sleep(Duration::from_millis(100));
+            assert!(is_pending(&mut request).await, "out of budget");
+
+            let progress = request.progress();
+            let head = "HTTP/1.1 200 OK\r\ncontent-length: 65536\r\n\r\n";
+            assert_eq!(progress.total, Some(head.len() + 64 * 1024));
+            assert!(progress.done < head.len() + 64 * 1024, "{progress}");
+            // reported to the executor for the task polling the request
+            let dump = runtime::Executor::new().dump_tasks();
+            assert_eq!(dump[0].progress, Some(progress));
+
+            let response = request.await.unwrap();
+            assert_eq!(response.body().len(), 64 * 1024);
+        });
+        runtime::set_read_budget(budget);
+    }
+
+    #[test]
+    fn response_over_the_size_limit_is_an_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).unwrap();
+
+            let body = vec![b'x'; 64 * 1024];
+            write!(
+                socket,
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            // the client may hang up before all of it is sent
+            let _ = socket.write_all(&body);
+        });
+
+        runtime::init_for_tests().block_on(async move {
+            let err = Http::with_addr(&addr)
+                .request()
+                .read_size(512)
+                .max_response_size(16 * 1024)
+                .send()
+                .await
+                .unwrap_err();
+            assert!(matches!(err, HttpError::TooLarge(16384)), "got {err}");
+        });
+    }
+
+    #[test]
+    fn unanswered_request_times_out_and_is_deregistered() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // accept, but never respond
+        let server = std::thread::spawn(move || listener.accept().unwrap());
+
+        runtime::init_for_tests().block_on(async move {
+            let timeout = Duration::from_millis(50);
+            let request = Http::with_addr(&addr).request().timeout(timeout);
+            let mut request = Box::pin(HttpGetFuture::new(request));
+            assert!(is_pending(&mut request).await, "waiting for the response");
+            let id = request.socket.id;
+            assert!(reactor().has_waker(id));
+
+            let err = (&mut request).await.unwrap_err();
+            assert!(
+                matches!(err, HttpError::Timeout(t) if t == timeout),
+                "got {err}"
+            );
+            assert!(!reactor().has_waker(id));
+        });
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn request_is_driven_by_a_mock_reactor() {
+        use runtime::mock::{self, Call};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mock = mock::install();
+
+        let mut request = Box::pin(HttpGetFuture::new(Http::with_addr(&addr).request()));
+        // unlike `Waker::noop`, clones of it are known to wake the same task
+        struct Noop;
+        impl std::task::Wake for Noop {
+            fn wake(self: std::sync::Arc<Self>) {}
+        }
+        let waker = std::task::Waker::from(std::sync::Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        assert!(request.as_mut().poll(&mut cx).is_pending());
+        let id = request.socket.id;
+        assert_eq!(
+            mock.take_calls(),
+            [
+                Call::Register {
+                    id,
+                    interest: Interest::READABLE,
+                    priority: runtime::Priority::Normal,
+                },
+                Call::SetWaker { id },
+            ]
+        );
+
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut received = Vec::new();
+        while !received.ends_with(b"\r\n\r\n") {
+            let mut buf = [0u8; 1024];
+            let n = socket.read(&mut buf).unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+            .unwrap();
+        drop(socket);
+
+        assert!(mock.ready(id));
+        let Poll::Ready(response) = request.as_mut().poll(&mut cx) else {
+            panic!("response not read once ready");
+        };
+        assert_eq!(response.unwrap().body(), "ok");
+        assert_eq!(mock.take_calls(), [Call::Deregister { id }]);
+    }
 }
@@ -0,0 +1,226 @@
+//! Response bodies exposed as a stream of chunks, rather than buffered into one `String`.
+//!
+//! Each chunk is handed out as soon as it has been read from the socket, so a large response
+//! can be processed with memory bounded by the read buffer instead of the full body.
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::{
+    find_head_end, invalid_chunk, is_chunked, ChunkedDecoder, HttpError, HttpGetFuture,
+    RequestBuilder,
+};
+use crate::{
+    future::Stream,
+    io::AsyncRead,
+    runtime::{reactor, PooledBuffer},
+};
+
+/// Stream of body chunks of an HTTP response, see [`RequestBuilder::send_streaming`].
+///
+/// The response head is not part of the stream, it is available from [`BodyStream::head`]
+/// once the first chunk has been received. Chunked transfer encoding is decoded, so items are
+/// always payload bytes. A failed request yields a single error, and then ends the stream.
+pub struct BodyStream {
+    /// Connection handling. Its buffer only ever holds the response head.
+    conn: HttpGetFuture,
+    /// Set once the server closed the connection or the last chunk was decoded
+    done: bool,
+}
+
+impl BodyStream {
+    pub(super) fn new(request: RequestBuilder) -> Self {
+        Self {
+            conn: HttpGetFuture::new(request),
+            done: false,
+        }
+    }
+
+    /// The status line and headers, None until they have been received.
+    pub fn head(&self) -> Option<&str> {
+        if !self.conn.head_parsed {
+            return None;
+        }
+        std::str::from_utf8(&self.conn.buffer).ok()
+    }
+
+    /// Turn bytes read from the socket into payload bytes, buffering them as long as the
+    /// response head is incomplete.
+    fn on_read(&mut self, data: &[u8]) -> Result<Vec<u8>, HttpError> {
+        let conn = &mut self.conn;
+
+        let body = if conn.head_parsed {
+            data.to_vec()
+        } else {
+            conn.buffer.extend_from_slice(data);
+
+            let Some(end) = find_head_end(&conn.buffer) else {
+                return Ok(Vec::new());
+            };
+            conn.head_parsed = true;
+
+            if is_chunked(&conn.buffer[..end]) {
+                conn.chunked = Some(ChunkedDecoder::new());
+            }
+            conn.buffer.split_off(end)
+        };
+
+        let Some(decoder) = conn.chunked.as_mut() else {
+            return Ok(body);
+        };
+
+        let mut payload = Vec::new();
+        decoder.feed(&body, &mut payload).map_err(invalid_chunk)?;
+        Ok(payload)
+    }
+
+    /// Deregister from the reactor. The stream yields None from now on.
+    fn finish(&mut self) {
+        self.done = true;
+        self.conn.socket.release();
+    }
+
+    /// Deregister from the reactor, yielding `err` as the last item.
+    fn fail(&mut self, err: HttpError) -> Poll<Option<Result<Vec<u8>, HttpError>>> {
+        self.finish();
+        Poll::Ready(Some(Err(err)))
+    }
+}
+
+impl Stream for BodyStream {
+    type Item = Result<Vec<u8>, HttpError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        match self.conn.poll_write_request(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return self.fail(e),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        let mut buff = PooledBuffer::zeroed(self.conn.read_size);
+
+        loop {
+            // always leaves the most recent waker with the reactor if pending
+            match Pin::new(&mut self.conn.socket).poll_read(cx, &mut buff) {
+                Poll::Ready(Ok(0)) => {
+                    self.finish();
+                    return Poll::Ready(None);
+                }
+                Poll::Ready(Ok(n)) => {
+                    let chunk = match self.on_read(&buff[..n]) {
+                        Ok(chunk) => chunk,
+                        Err(e) => return self.fail(e),
+                    };
+
+                    if self.conn.is_complete() {
+                        self.finish();
+                    }
+
+                    if !chunk.is_empty() {
+                        return Poll::Ready(Some(Ok(chunk)));
+                    }
+                    if self.done {
+                        return Poll::Ready(None);
+                    }
+                }
+                Poll::Ready(Err(e)) => {
+                    let err = self.conn.socket.read_error(e);
+                    return self.fail(err);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{future::StreamExt, http::Http, runtime};
+
+    /// Serve a single chunked response, sending each chunk separately.
+    fn serve_chunked(chunks: &'static [&'static str]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+
+            // read the request first: closing with it unread resets the connection, which can
+            // hit the client before it read the end of the response
+            let mut request = BufReader::new(&socket);
+            let mut line = String::new();
+            while request.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n")
+                .unwrap();
+
+            for chunk in chunks {
+                thread::sleep(Duration::from_millis(20));
+                write!(socket, "{:x}\r\n{chunk}\r\n", chunk.len()).unwrap();
+            }
+            socket.write_all(b"0\r\n\r\n").unwrap();
+        });
+
+        addr
+    }
+
+    #[test]
+    fn chunks_are_yielded_as_they_arrive() {
+        let addr = serve_chunked(&["Hello", ", ", "stream"]);
+
+        runtime::init_for_tests().block_on(async move {
+            let mut body = Http::with_addr(&addr).request().send_streaming();
+
+            let first = body.next().await.unwrap().unwrap();
+            assert_eq!(first, b"Hello");
+            assert!(body.head().unwrap().starts_with("HTTP/1.1 200 OK"));
+
+            let rest: Vec<Result<Vec<u8>, HttpError>> = body.collect().await;
+            let rest: Vec<Vec<u8>> = rest.into_iter().collect::<Result<_, _>>().unwrap();
+            assert_eq!(rest.concat(), b", stream");
+        });
+    }
+
+    #[test]
+    fn dropping_a_pending_stream_deregisters_it() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // accept, but never respond
+        let server = thread::spawn(move || listener.accept().unwrap());
+
+        runtime::init_for_tests().block_on(async move {
+            let mut body = Http::with_addr(&addr).request().send_streaming();
+            let id = body.conn.socket.id;
+
+            std::future::poll_fn(|cx| {
+                assert!(Pin::new(&mut body).poll_next(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+            assert!(reactor().has_waker(id));
+
+            // e.g. cancelled by a timeout
+            drop(body);
+            assert!(!reactor().has_waker(id));
+        });
+
+        server.join().unwrap();
+    }
+}
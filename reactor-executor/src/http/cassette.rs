@@ -0,0 +1,263 @@
+//! Record responses from the network once, then replay them in tests without a server.
+//!
+//! In record mode a [`Cassette`] sends requests as usual, and saves each response together
+//! with how long it took, keyed by method and path. In replay mode no connection is made: the
+//! saved response is returned once the recorded duration has passed on the virtual clock of a
+//! [`sim::Network`], so replays are both hermetic and instant.
+//!
+//! ```ignore
+//! let cassette = Cassette::record();
+//! cassette.send(Http::request().path("/600/Hello")).await?;
+//! cassette.save("hello.cassette")?;
+//!
+//! let net = sim::Network::new(1);
+//! net.install();
+//! let cassette = Cassette::load("hello.cassette", net)?;
+//! let txt = cassette.send(Http::request().path("/600/Hello")).await?;
+//! ```
+//!
+//! NOTE: the http client has no middleware or layer system and no in-memory streams to plug
+//! into, so the cassette wraps [`RequestBuilder`] instead, and replays hand back the saved
+//! response directly.
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fs,
+    future::Future,
+    io::{self, ErrorKind},
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use super::{RequestBuilder, Response};
+use crate::sim;
+
+/// First line of a saved cassette, so we do not try to replay some unrelated file
+const HEADER: &str = "cassette v1";
+
+/// A single recorded exchange.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Recording {
+    response: Response,
+    /// Time from sending the request until the full response was received
+    elapsed: Duration,
+}
+
+enum Mode {
+    Record,
+    Replay(sim::Network),
+}
+
+/// Records or replays responses, see the module docs. Cloning gives another handle to the
+/// same cassette.
+#[derive(Clone)]
+pub struct Cassette {
+    inner: Rc<RefCell<Inner>>,
+}
+
+struct Inner {
+    mode: Mode,
+    /// Recordings per `METHOD path` key, in the order they were made. Replays take them from
+    /// the front, so repeated requests to the same path get the responses in recorded order.
+    tapes: HashMap<String, VecDeque<Recording>>,
+}
+
+impl Cassette {
+    /// Start an empty cassette that records responses from the network.
+    pub fn record() -> Self {
+        Self::new(Mode::Record, HashMap::new())
+    }
+
+    /// Load a cassette saved via [`Cassette::save`] for replay, with delays driven by `net`.
+    ///
+    /// `net` should be installed on the executor, otherwise nothing advances its clock.
+    pub fn load(path: impl AsRef<Path>, net: sim::Network) -> io::Result<Self> {
+        let tapes = parse(&fs::read(path)?)?;
+        Ok(Self::new(Mode::Replay(net), tapes))
+    }
+
+    fn new(mode: Mode, tapes: HashMap<String, VecDeque<Recording>>) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(Inner { mode, tapes })),
+        }
+    }
+
+    /// Send `request`, or replay the recorded response for it.
+    ///
+    /// When replaying, resolves with `ErrorKind::NotFound` if nothing (more) was recorded for
+    /// the request's method and path. When recording, a failed request is not recorded, and
+    /// resolves with its [`HttpError`](super::HttpError) turned into an `io::Error`.
+    pub fn send(&self, request: RequestBuilder) -> impl Future<Output = io::Result<Response>> {
+        let key = format!("{} {}", request.method_kind().as_str(), request.path_str());
+        let inner = self.inner.clone();
+
+        async move {
+            let replay = match &inner.borrow().mode {
+                Mode::Record => None,
+                Mode::Replay(net) => Some(net.clone()),
+            };
+
+            let Some(net) = replay else {
+                let start = Instant::now();
+                let response = request.send().await?;
+                let recording = Recording {
+                    response: response.clone(),
+                    elapsed: start.elapsed(),
+                };
+
+                let mut inner = inner.borrow_mut();
+                inner.tapes.entry(key).or_default().push_back(recording);
+                return Ok(response);
+            };
+
+            let recording = inner
+                .borrow_mut()
+                .tapes
+                .get_mut(&key)
+                .and_then(|tape| tape.pop_front())
+                .ok_or_else(|| {
+                    io::Error::new(ErrorKind::NotFound, format!("nothing recorded for {key}"))
+                })?;
+
+            net.sleep(recording.elapsed).await;
+            Ok(recording.response)
+        }
+    }
+
+    /// Write all recordings to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let inner = self.inner.borrow();
+
+        let mut keys: Vec<&String> = inner.tapes.keys().collect();
+        keys.sort();
+
+        let mut out = format!("{HEADER}\n").into_bytes();
+        for key in keys {
+            for recording in &inner.tapes[key] {
+                // `key` never contains a newline, the response is length prefixed
+                out.extend_from_slice(
+                    format!(
+                        "{} {} {key}\n",
+                        recording.elapsed.as_nanos(),
+                        recording.response.as_str().len()
+                    )
+                    .as_bytes(),
+                );
+                out.extend_from_slice(recording.response.as_str().as_bytes());
+                out.push(b'\n');
+            }
+        }
+
+        fs::write(path, out)
+    }
+}
+
+/// Parse the format written by [`Cassette::save`]. Each recording is a line
+/// `<elapsed ns> <response length> <METHOD path>` followed by the response and a newline.
+fn parse(data: &[u8]) -> io::Result<HashMap<String, VecDeque<Recording>>> {
+    let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_string());
+
+    let mut rest = data
+        .strip_prefix(format!("{HEADER}\n").as_bytes())
+        .ok_or_else(|| invalid("not a cassette"))?;
+    let mut tapes: HashMap<String, VecDeque<Recording>> = HashMap::new();
+
+    while !rest.is_empty() {
+        let end = rest
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| invalid("truncated recording header"))?;
+        let line = std::str::from_utf8(&rest[..end]).map_err(|_| invalid("invalid header"))?;
+        rest = &rest[end + 1..];
+
+        let mut parts = line.splitn(3, ' ');
+        let (Some(nanos), Some(len), Some(key)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid("invalid recording header"));
+        };
+        let nanos: u64 = nanos.parse().map_err(|_| invalid("invalid duration"))?;
+        let len: usize = len.parse().map_err(|_| invalid("invalid length"))?;
+
+        if rest.len() < len + 1 {
+            return Err(invalid("truncated response"));
+        }
+        let response = String::from_utf8(rest[..len].to_vec())
+            .map_err(|_| invalid("response is not utf-8"))?;
+        let response = Response::parse(response)?;
+        rest = &rest[len + 1..];
+
+        tapes
+            .entry(key.to_string())
+            .or_default()
+            .push_back(Recording {
+                response,
+                elapsed: Duration::from_nanos(nanos),
+            });
+    }
+
+    Ok(tapes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+    use crate::{http::Http, runtime};
+
+    #[test]
+    fn recorded_responses_replay_on_virtual_clock() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            for msg in ["first", "second"] {
+                let (mut socket, _) = listener.accept().unwrap();
+                let mut request = [0u8; 1024];
+                let _ = socket.read(&mut request).unwrap();
+
+                thread::sleep(Duration::from_millis(30));
+                write!(socket, "HTTP/1.1 200 OK\r\n\r\n{msg}").unwrap();
+            }
+        });
+
+        let file = std::env::temp_dir().join(format!("cassette-{}.txt", std::process::id()));
+
+        // record two responses for the same request against a real server
+        let cassette = Cassette::record();
+        let recorder = cassette.clone();
+        let client = Http::with_addr(&addr);
+        runtime::init_for_tests().block_on(async move {
+            for _ in 0..2 {
+                recorder
+                    .send(client.request().path("/hello"))
+                    .await
+                    .unwrap();
+            }
+        });
+        cassette.save(&file).unwrap();
+
+        // replay without a server, in recorded order, taking the recorded time
+        let net = sim::Network::new(1);
+        net.install();
+        let cassette = Cassette::load(&file, net.clone()).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        let clock = net.clone();
+        runtime::init_for_tests().block_on(async move {
+            let first = cassette.send(Http::request().path("/hello")).await.unwrap();
+            assert_eq!(first.body(), "first");
+            assert!(clock.now() >= Duration::from_millis(30));
+
+            let second = cassette.send(Http::request().path("/hello")).await.unwrap();
+            assert_eq!(second.body(), "second");
+
+            let missing = cassette.send(Http::request().path("/other")).await;
+            assert_eq!(missing.unwrap_err().kind(), ErrorKind::NotFound);
+        });
+    }
+}
@@ -0,0 +1,197 @@
+//! Incremental decoder for `Transfer-Encoding: chunked` bodies.
+//!
+//! Data arrives from the socket in arbitrarily sized pieces across multiple polls, so the
+//! decoder keeps track of where it is in the chunk framing between calls to
+//! [`ChunkedDecoder::feed`]. Only payload bytes are written to the output.
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc9112#section-7.1
+use std::io::{self, ErrorKind};
+
+/// Longest chunk size or trailer line taken, without its line ending. A peer that never ends
+/// the line would otherwise have us buffer it forever.
+const MAX_LINE: usize = 8 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Reading the `<hex size>[;extensions]\r\n` line of a chunk
+    Size,
+    /// Reading payload, holds the number of bytes left in the current chunk
+    Data(usize),
+    /// Reading the `\r\n` that terminates a chunk's payload
+    DataEnd,
+    /// Reading trailer fields after the last (zero sized) chunk, until an empty line
+    Trailers,
+    /// Body is complete, any further bytes are ignored
+    Done,
+}
+
+#[derive(Debug)]
+pub struct ChunkedDecoder {
+    state: State,
+    /// Partial line carried over between calls to `feed`
+    line: Vec<u8>,
+}
+
+impl ChunkedDecoder {
+    pub fn new() -> Self {
+        Self {
+            state: State::Size,
+            line: Vec::new(),
+        }
+    }
+
+    /// True once the terminating chunk and trailers have been consumed.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Decode `input`, appending payload bytes to `out`. Returns how many bytes of `input` are
+    /// part of the body, which is all of them unless it ended within `input`: the rest belongs
+    /// to whatever follows on the connection, e.g. the next response.
+    pub fn feed(&mut self, mut input: &[u8], out: &mut Vec<u8>) -> io::Result<usize> {
+        let len = input.len();
+
+        while !input.is_empty() {
+            match self.state {
+                State::Data(remaining) => {
+                    let n = remaining.min(input.len());
+                    out.extend_from_slice(&input[..n]);
+                    input = &input[n..];
+
+                    self.state = match remaining - n {
+                        0 => State::DataEnd,
+                        remaining => State::Data(remaining),
+                    };
+                }
+                State::Done => return Ok(len - input.len()),
+                _ => {
+                    // line based states, read up to and including the next `\n`
+                    let Some(pos) = input.iter().position(|b| *b == b'\n') else {
+                        self.extend_line(input)?;
+                        return Ok(len);
+                    };
+                    self.extend_line(&input[..pos])?;
+                    input = &input[pos + 1..];
+
+                    let line = std::mem::take(&mut self.line);
+                    self.on_line(line.strip_suffix(b"\r").unwrap_or(&line))?;
+                }
+            }
+        }
+
+        Ok(len)
+    }
+
+    /// Add to the partial line, failing once it grows beyond [`MAX_LINE`]. The `\r` of the
+    /// line ending may still be part of it.
+    fn extend_line(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.line.len() + bytes.len() > MAX_LINE + 1 {
+            return Err(invalid("chunk size or trailer line too long"));
+        }
+        self.line.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn on_line(&mut self, line: &[u8]) -> io::Result<()> {
+        self.state = match self.state {
+            State::Size => {
+                // ignore chunk extensions
+                let size = line.split(|b| *b == b';').next().unwrap_or_default();
+                let size = std::str::from_utf8(size)
+                    .ok()
+                    .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+                    .ok_or_else(|| invalid("invalid chunk size"))?;
+
+                match size {
+                    0 => State::Trailers,
+                    size => State::Data(size),
+                }
+            }
+            State::DataEnd if line.is_empty() => State::Size,
+            State::DataEnd => return Err(invalid("missing CRLF after chunk data")),
+            // trailer fields are dropped, an empty line ends the body
+            State::Trailers if line.is_empty() => State::Done,
+            State::Trailers => State::Trailers,
+            State::Data(_) | State::Done => unreachable!("not a line based state"),
+        };
+
+        Ok(())
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = b"5\r\nHello\r\n7;ext=1\r\n, world\r\n0\r\nExpires: never\r\n\r\n";
+
+    #[test]
+    fn decodes_whole_body() {
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = Vec::new();
+
+        decoder.feed(BODY, &mut out).unwrap();
+
+        assert_eq!(out, b"Hello, world");
+        assert!(decoder.is_done());
+    }
+
+    #[test]
+    fn decodes_body_split_at_every_byte() {
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = Vec::new();
+
+        for byte in BODY.chunks(1) {
+            assert!(!decoder.is_done());
+            decoder.feed(byte, &mut out).unwrap();
+        }
+
+        assert_eq!(out, b"Hello, world");
+        assert!(decoder.is_done());
+    }
+
+    #[test]
+    fn stops_at_the_end_of_the_body() {
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = Vec::new();
+        let input = [BODY, b"HTTP/1.1 200 OK\r\n"].concat();
+
+        assert_eq!(decoder.feed(&input, &mut out).unwrap(), BODY.len());
+        assert_eq!(out, b"Hello, world");
+        assert_eq!(decoder.feed(b"more", &mut out).unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_invalid_size() {
+        let mut decoder = ChunkedDecoder::new();
+        let err = decoder.feed(b"zz\r\n", &mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_lines_that_never_end() {
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = Vec::new();
+        let endless = [b'0'; 1024];
+
+        let err = (0..MAX_LINE)
+            .find_map(|_| decoder.feed(&endless, &mut out).err())
+            .expect("line is capped");
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(decoder.line.len() <= MAX_LINE + 1);
+    }
+
+    #[test]
+    fn rejects_trailers_that_never_end() {
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = Vec::new();
+        decoder.feed(b"0\r\nX-Trailer: ", &mut out).unwrap();
+
+        let err = decoder.feed(&[b'a'; MAX_LINE], &mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
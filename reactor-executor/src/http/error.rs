@@ -0,0 +1,63 @@
+//! Errors of http requests.
+use std::{fmt, io, time::Duration};
+
+/// Why an http request failed, by the stage it failed at.
+#[derive(Debug)]
+pub enum HttpError {
+    /// Connecting to the server failed, e.g. the connection was refused
+    Connect(io::Error),
+    /// The TLS handshake failed, e.g. the server's certificate is not trusted. Only returned
+    /// for HTTPS requests, see the `tls` feature
+    Tls(io::Error),
+    /// Sending the request failed
+    Write(io::Error),
+    /// Receiving the response failed
+    Read(io::Error),
+    /// The response is not valid HTTP, e.g. it has a malformed chunked body
+    Parse(String),
+    /// The response is larger than the limit in bytes set via
+    /// [`RequestBuilder::max_response_size`](super::RequestBuilder::max_response_size)
+    TooLarge(usize),
+    /// No response within the timeout set via
+    /// [`RequestBuilder::timeout`](super::RequestBuilder::timeout)
+    Timeout(Duration),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect(e) => write!(f, "failed to connect: {e}"),
+            Self::Tls(e) => write!(f, "tls handshake failed: {e}"),
+            Self::Write(e) => write!(f, "failed to send request: {e}"),
+            Self::Read(e) => write!(f, "failed to read response: {e}"),
+            Self::Parse(msg) => write!(f, "invalid response: {msg}"),
+            Self::TooLarge(limit) => write!(f, "response larger than {limit} bytes"),
+            Self::Timeout(timeout) => write!(f, "no response within {timeout:?}"),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Connect(e) | Self::Tls(e) | Self::Write(e) | Self::Read(e) => Some(e),
+            Self::Parse(_) | Self::TooLarge(_) | Self::Timeout(_) => None,
+        }
+    }
+}
+
+/// For callers that deal in `io::Error`s, e.g. [`Cassette`](super::Cassette). The kind of
+/// the underlying error is kept.
+impl From<HttpError> for io::Error {
+    fn from(err: HttpError) -> Self {
+        let kind = match &err {
+            HttpError::Connect(e)
+            | HttpError::Tls(e)
+            | HttpError::Write(e)
+            | HttpError::Read(e) => e.kind(),
+            HttpError::Parse(_) | HttpError::TooLarge(_) => io::ErrorKind::InvalidData,
+            HttpError::Timeout(_) => io::ErrorKind::TimedOut,
+        };
+        io::Error::new(kind, err)
+    }
+}
@@ -0,0 +1,635 @@
+//! Several requests to one server over a single kept-alive connection, i.e. HTTP/1.1
+//! pipelining.
+//!
+//! A [`Pipeline`] writes each request as soon as its future is first polled, without waiting
+//! for the responses to the ones before it. The server answers in the order requests arrived,
+//! so responses are split off the connection in that order, and handed to the future of their
+//! request, which resolves as soon as its own response is complete.
+//!
+//! ```ignore
+//! let pipeline = Http::with_addr("127.0.0.1:8080").pipeline();
+//! let (slow, fast) = join!(pipeline.get("/400/slow"), pipeline.get("/0/fast"));
+//! ```
+//!
+//! There is one connection, registered with the reactor under a single id, but any number of
+//! futures waiting on it. The reactor is left a waker of the pipeline's own, which wakes every
+//! waiting future (see [`Waiters`]). Whichever of them is polled first reads what arrived,
+//! completing responses for the others on the way.
+//!
+//! Responses have to be delimited, by `Content-Length` or a chunked body. One that ends with
+//! the connection can only be the last. If the connection fails, or the server closes it, all
+//! requests in flight fail with it, and the next request opens a new connection.
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io::{self, ErrorKind, Read, Write},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+};
+
+use mio::{net::TcpStream, Interest};
+
+use super::{
+    find_head_end, invalid_chunk, is_chunked, ChunkedDecoder, HttpError, Method, RequestBuilder,
+    Response,
+};
+use crate::runtime::{log, reactor, PooledBuffer, StoredWaker};
+
+/// Sends requests to one server over a single connection, see the module docs.
+///
+/// Created via [`Client::pipeline`](super::Client::pipeline). Clones share the connection.
+#[derive(Clone)]
+pub struct Pipeline {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Pipeline {
+    pub(super) fn new(addr: &str) -> Self {
+        Self {
+            conn: Arc::new(Mutex::new(Connection::new(addr))),
+        }
+    }
+
+    /// Returns a future that yields the response of a GET request for `path`.
+    pub fn get(&self, path: &str) -> PipelinedRequest {
+        self.send(RequestBuilder::new().path(path))
+    }
+
+    /// Returns a future that yields the response of `request`, sent over the pipeline's
+    /// connection whatever address the request was built with.
+    pub fn send(&self, request: RequestBuilder) -> PipelinedRequest {
+        let addr = self.conn.lock().unwrap().addr.clone();
+
+        PipelinedRequest {
+            conn: self.conn.clone(),
+            request: Some(request.addr(&addr)),
+            seq: None,
+        }
+    }
+}
+
+/// Future of a request sent via a [`Pipeline`].
+pub struct PipelinedRequest {
+    conn: Arc<Mutex<Connection>>,
+    /// Taken once the request is written, on first poll
+    request: Option<RequestBuilder>,
+    /// Position of the request on the connection, once written
+    seq: Option<u64>,
+}
+
+impl Future for PipelinedRequest {
+    type Output = Result<Response, HttpError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut conn = this.conn.lock().unwrap();
+
+        let seq = match (this.seq, this.request.take()) {
+            (Some(seq), _) => seq,
+            (None, Some(request)) => match conn.send(&request) {
+                Ok(seq) => *this.seq.insert(seq),
+                Err(e) => return Poll::Ready(Err(e)),
+            },
+            (None, None) => panic!("PipelinedRequest polled after completion"),
+        };
+
+        // read what arrived since, which may complete other requests' responses too
+        conn.drive();
+
+        match conn.done.remove(&seq) {
+            Some(result) => {
+                this.seq = None;
+                Poll::Ready(result)
+            }
+            None => {
+                conn.wait(seq, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A request dropped before its response arrived leaves the response to be read, and thrown
+/// away, by whoever reads next.
+impl Drop for PipelinedRequest {
+    fn drop(&mut self) {
+        if let Some(seq) = self.seq {
+            // a poisoned lock means the connection is gone, along with the request
+            if let Ok(mut conn) = self.conn.lock() {
+                conn.abandon(seq);
+            }
+        }
+    }
+}
+
+/// Wakers of the futures waiting on a connection, woken all at once by the reactor.
+#[derive(Default)]
+struct Waiters {
+    wakers: Mutex<HashMap<u64, Waker>>,
+}
+
+impl Waiters {
+    fn insert(&self, seq: u64, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        match wakers.get(&seq) {
+            Some(current) if current.will_wake(waker) => {}
+            _ => {
+                wakers.insert(seq, waker.clone());
+            }
+        }
+    }
+
+    fn remove(&self, seq: u64) -> Option<Waker> {
+        self.wakers.lock().unwrap().remove(&seq)
+    }
+}
+
+/// Futures still pending after being woken store their waker again, so all are taken.
+impl Wake for Waiters {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let wakers = std::mem::take(&mut *self.wakers.lock().unwrap());
+        for waker in wakers.into_values() {
+            waker.wake();
+        }
+    }
+}
+
+/// Request written to the connection, waiting for its response
+struct InFlight {
+    seq: u64,
+    method: Method,
+    /// Set once its future is dropped, the response is thrown away
+    abandoned: bool,
+}
+
+/// How the body of the response being read ends
+enum Body {
+    /// After this many more bytes
+    Length(usize),
+    Chunked(ChunkedDecoder),
+    /// Once the server closes the connection
+    UntilClose,
+}
+
+/// State of the connection shared by the futures of a [`Pipeline`]
+struct Connection {
+    /// `host:port` of the server
+    addr: String,
+    /// Connected on the first request, and again on the first after a failure
+    stream: Option<TcpStream>,
+    /// Id of `stream` with the reactor, while connected
+    id: usize,
+    /// Events `stream` is registered for: WRITABLE as well while `outgoing` is not empty
+    interest: Interest,
+    waiters: Arc<Waiters>,
+    /// Waker for `waiters`, left with the reactor
+    waker: Waker,
+    stored: StoredWaker,
+    /// Requests the socket did not take yet
+    outgoing: Vec<u8>,
+    /// In the order they were written, which is the order the responses arrive in
+    in_flight: VecDeque<InFlight>,
+    next_seq: u64,
+    /// Results not yet picked up by their futures
+    done: HashMap<u64, Result<Response, HttpError>>,
+    /// Bytes read, but not yet part of a response
+    buffer: Vec<u8>,
+    /// Head and (decoded) body of the response being read so far
+    response: Vec<u8>,
+    /// Set once the head of the response being read is complete
+    body: Option<Body>,
+}
+
+impl Connection {
+    fn new(addr: &str) -> Self {
+        let waiters = Arc::new(Waiters::default());
+
+        Self {
+            addr: addr.to_string(),
+            stream: None,
+            id: 0,
+            interest: Interest::READABLE,
+            waker: waiters.clone().into(),
+            waiters,
+            stored: StoredWaker::default(),
+            outgoing: Vec::new(),
+            in_flight: VecDeque::new(),
+            next_seq: 0,
+            done: HashMap::new(),
+            buffer: Vec::new(),
+            response: Vec::new(),
+            body: None,
+        }
+    }
+
+    /// Write `request`, connecting first if need be. Returns its position on the connection.
+    fn send(&mut self, request: &RequestBuilder) -> Result<u64, HttpError> {
+        if self.stream.is_none() {
+            self.connect()?;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.in_flight.push_back(InFlight {
+            seq,
+            method: request.method_kind(),
+            abandoned: false,
+        });
+
+        self.outgoing
+            .extend_from_slice(&request.to_bytes_keep_alive());
+        if let Err(e) = self.flush() {
+            self.fail(HttpError::Write(e));
+        }
+        Ok(seq)
+    }
+
+    fn connect(&mut self) -> Result<(), HttpError> {
+        log::debug!("Connecting pipeline to {}", self.addr);
+        let stream = std::net::TcpStream::connect(&self.addr).map_err(HttpError::Connect)?;
+        stream.set_nonblocking(true).map_err(HttpError::Connect)?;
+        let mut stream = TcpStream::from_std(stream);
+
+        self.id = reactor().next_id();
+        self.interest = Interest::READABLE;
+        reactor().register(&mut stream, self.interest, self.id);
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    /// Write as much of `outgoing` as the socket takes, and wait for it to become writable if
+    /// it does not take all of it.
+    fn flush(&mut self) -> io::Result<()> {
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(());
+        };
+
+        while !self.outgoing.is_empty() {
+            match stream.write(&self.outgoing) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.outgoing.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let interest = if self.outgoing.is_empty() {
+            Interest::READABLE
+        } else {
+            Interest::READABLE | Interest::WRITABLE
+        };
+        if interest != self.interest {
+            reactor().set_interest(stream, interest, self.id);
+            self.interest = interest;
+        }
+        Ok(())
+    }
+
+    /// Write what is left of the requests, and read until the socket would block, completing
+    /// the responses on the way.
+    fn drive(&mut self) {
+        if let Err(e) = self.flush() {
+            return self.fail(HttpError::Write(e));
+        }
+
+        let mut buff = PooledBuffer::zeroed(4096);
+        while let Some(stream) = self.stream.as_mut() {
+            match stream.read(&mut buff) {
+                Ok(0) => return self.on_closed(),
+                Ok(n) => {
+                    self.buffer.extend_from_slice(&buff[..n]);
+                    if let Err(e) = self.read_responses() {
+                        return self.fail(e);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return self.fail(HttpError::Read(e)),
+            }
+        }
+    }
+
+    /// Split complete responses off `buffer`, in order.
+    fn read_responses(&mut self) -> Result<(), HttpError> {
+        loop {
+            let Some(method) = self.in_flight.front().map(|request| request.method) else {
+                if self.buffer.is_empty() {
+                    return Ok(());
+                }
+                return Err(HttpError::Parse("response to no request".to_string()));
+            };
+
+            if self.body.is_none() {
+                let Some(end) = find_head_end(&self.buffer) else {
+                    return Ok(());
+                };
+                self.response = self.buffer.drain(..end).collect();
+                self.body = Some(body_of(&self.response, method)?);
+            }
+
+            let complete = match self.body.as_mut().unwrap() {
+                Body::Length(remaining) => {
+                    let n = (*remaining).min(self.buffer.len());
+                    self.response.extend(self.buffer.drain(..n));
+                    *remaining -= n;
+                    *remaining == 0
+                }
+                Body::Chunked(decoder) => {
+                    let n = decoder
+                        .feed(&self.buffer, &mut self.response)
+                        .map_err(invalid_chunk)?;
+                    self.buffer.drain(..n);
+                    decoder.is_done()
+                }
+                Body::UntilClose => {
+                    self.response.append(&mut self.buffer);
+                    false
+                }
+            };
+
+            if !complete {
+                return Ok(());
+            }
+            self.complete_next();
+        }
+    }
+
+    /// Hand the response read so far to the request at the front.
+    fn complete_next(&mut self) {
+        self.body = None;
+        let response = std::mem::take(&mut self.response);
+        let request = self.in_flight.pop_front().unwrap();
+
+        if !request.abandoned {
+            let result = Response::parse(String::from_utf8_lossy(&response).into_owned());
+            self.done.insert(request.seq, result);
+            if let Some(waker) = self.waiters.remove(request.seq) {
+                waker.wake();
+            }
+        }
+    }
+
+    /// The server closed the connection, which ends a response without a length.
+    fn on_closed(&mut self) {
+        if matches!(self.body, Some(Body::UntilClose)) {
+            self.complete_next();
+        }
+
+        if self.in_flight.is_empty() {
+            self.disconnect();
+        } else {
+            let closed = io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "connection closed with requests in flight",
+            );
+            self.fail(HttpError::Read(closed));
+        }
+    }
+
+    /// Fail every request in flight with `err`, and drop the connection.
+    fn fail(&mut self, err: HttpError) {
+        log::debug!("Pipeline to {} failed: {err}", self.addr);
+
+        for request in std::mem::take(&mut self.in_flight) {
+            if request.abandoned {
+                continue;
+            }
+            self.done.insert(request.seq, Err(duplicate(&err)));
+            if let Some(waker) = self.waiters.remove(request.seq) {
+                waker.wake();
+            }
+        }
+        self.disconnect();
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            reactor().deregister(Box::new(stream), self.id);
+            self.stored.clear();
+        }
+        self.outgoing.clear();
+        self.buffer.clear();
+        self.response.clear();
+        self.body = None;
+    }
+
+    /// Wake the future of request `seq` once there is something to read, or room to write.
+    fn wait(&mut self, seq: u64, waker: &Waker) {
+        self.waiters.insert(seq, waker);
+
+        if self.stream.is_some() {
+            let cx = Context::from_waker(&self.waker);
+            reactor().update_waker(&cx, self.id, &mut self.stored);
+        }
+    }
+
+    fn abandon(&mut self, seq: u64) {
+        self.waiters.remove(seq);
+        if self.done.remove(&seq).is_some() {
+            return;
+        }
+        if let Some(request) = self.in_flight.iter_mut().find(|request| request.seq == seq) {
+            request.abandoned = true;
+        }
+    }
+}
+
+/// Once the connection is gone, so is the pipeline's last future.
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+/// How the body of a response with `head` ends, for a request with `method`.
+fn body_of(head: &[u8], method: Method) -> Result<Body, HttpError> {
+    let head = String::from_utf8_lossy(head);
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .unwrap_or_default();
+
+    // see: https://datatracker.ietf.org/doc/html/rfc9112#section-6.3
+    if method == Method::Head || (100..200).contains(&status) || status == 204 || status == 304 {
+        return Ok(Body::Length(0));
+    }
+    if is_chunked(head.as_bytes()) {
+        return Ok(Body::Chunked(ChunkedDecoder::new()));
+    }
+
+    let length = head.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-length")
+            .then(|| value.trim().to_string())
+    });
+    match length {
+        Some(length) => length
+            .parse()
+            .map(Body::Length)
+            .map_err(|_| HttpError::Parse(format!("invalid content-length {length:?}"))),
+        None => Ok(Body::UntilClose),
+    }
+}
+
+/// Copy of `err` for each request failing with it, io errors keep their kind and message.
+fn duplicate(err: &HttpError) -> HttpError {
+    let copy = |e: &io::Error| io::Error::new(e.kind(), e.to_string());
+
+    match err {
+        HttpError::Connect(e) => HttpError::Connect(copy(e)),
+        HttpError::Tls(e) => HttpError::Tls(copy(e)),
+        HttpError::Write(e) => HttpError::Write(copy(e)),
+        HttpError::Read(e) => HttpError::Read(copy(e)),
+        HttpError::Parse(msg) => HttpError::Parse(msg.clone()),
+        HttpError::TooLarge(limit) => HttpError::TooLarge(*limit),
+        HttpError::Timeout(timeout) => HttpError::Timeout(*timeout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        http::Http,
+        runtime::{self, yield_now},
+    };
+
+    /// Serve a single connection: wait for `count` requests, or a second without any, then
+    /// answer each with its path (or `replies[path]`) and close. Sends back the request paths
+    /// seen before the first response went out, and whether a second connection came in.
+    fn serve_pipelined(
+        count: usize,
+        replies: HashMap<&'static str, &'static str>,
+    ) -> (String, mpsc::Receiver<(Vec<String>, bool)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            socket
+                .set_read_timeout(Some(Duration::from_secs(1)))
+                .unwrap();
+            let mut reader = BufReader::new(socket.try_clone().unwrap());
+
+            let mut paths = Vec::new();
+            while paths.len() < count {
+                let mut line = String::new();
+                let Ok(n) = reader.read_line(&mut line) else {
+                    break;
+                };
+                if n == 0 {
+                    break;
+                }
+                if let Some(path) = line.strip_prefix("GET ") {
+                    paths.push(path.split_whitespace().next().unwrap().to_string());
+                }
+            }
+
+            let mut socket = socket;
+            for path in &paths {
+                let reply = replies
+                    .get(path.as_str())
+                    .map(|reply| reply.to_string())
+                    .unwrap_or_else(|| {
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{path}",
+                            path.len()
+                        )
+                    });
+                socket.write_all(reply.as_bytes()).unwrap();
+            }
+            // read what is left before closing, unread requests would reset the connection
+            socket.shutdown(std::net::Shutdown::Write).unwrap();
+            let _ = io::copy(&mut reader, &mut io::sink());
+
+            listener.set_nonblocking(true).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            let reconnected = listener.accept().is_ok();
+            tx.send((paths, reconnected)).unwrap();
+        });
+
+        (addr, rx)
+    }
+
+    #[test]
+    fn requests_share_one_connection_and_resolve_in_order() {
+        let chunked = "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n\
+                       3\r\n/ch\r\n5\r\nunked\r\n0\r\n\r\n";
+        let (addr, served) = serve_pipelined(3, HashMap::from([("/chunked", chunked)]));
+
+        runtime::init_for_tests().block_on(async move {
+            let pipeline = Http::with_addr(&addr).pipeline();
+            let (first, second, third) = crate::join!(
+                pipeline.get("/first"),
+                pipeline.get("/chunked"),
+                pipeline.get("/third"),
+            );
+
+            assert_eq!(first.unwrap().body(), "/first");
+            assert_eq!(second.unwrap().body(), "/chunked");
+            assert_eq!(third.unwrap().body(), "/third");
+        });
+
+        let (paths, reconnected) = served.recv().unwrap();
+        // all were written before the first response came back
+        assert_eq!(paths, ["/first", "/chunked", "/third"]);
+        assert!(!reconnected);
+    }
+
+    #[test]
+    fn dropped_request_does_not_hold_up_the_rest() {
+        let (addr, served) = serve_pipelined(2, HashMap::new());
+
+        runtime::init_for_tests().block_on(async move {
+            let pipeline = Http::with_addr(&addr).pipeline();
+
+            let mut dropped = Box::pin(pipeline.get("/dropped"));
+            let polled = std::future::poll_fn(|cx| Poll::Ready(dropped.as_mut().poll(cx))).await;
+            assert!(polled.is_pending(), "response is not there yet");
+            drop(dropped);
+            yield_now().await;
+
+            let response = pipeline.get("/kept").await.unwrap();
+            assert_eq!(response.body(), "/kept");
+        });
+
+        let (paths, _) = served.recv().unwrap();
+        assert_eq!(paths, ["/dropped", "/kept"]);
+    }
+
+    #[test]
+    fn closed_connection_fails_requests_in_flight() {
+        // reads both requests, so both are in flight, but answers only the first, then closes
+        let (addr, _served) = serve_pipelined(2, HashMap::from([("/second", "")]));
+
+        runtime::init_for_tests().block_on(async move {
+            let pipeline = Http::with_addr(&addr).pipeline();
+            let (first, second) = crate::join!(pipeline.get("/first"), pipeline.get("/second"));
+
+            assert_eq!(first.unwrap().body(), "/first");
+            match second {
+                Err(HttpError::Read(e)) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+                other => panic!("expected the request to fail, got {other:?}"),
+            }
+        });
+    }
+}
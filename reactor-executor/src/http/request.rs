@@ -0,0 +1,278 @@
+//! Builder for http requests with arbitrary methods, headers and bodies.
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::{future::Future, time::Duration};
+
+use super::{default_addr, BodyStream, HttpError, HttpGetFuture, Response};
+
+/// Bytes read from the socket at once, unless set via [`RequestBuilder::read_size`]
+const DEFAULT_READ_SIZE: usize = 4096;
+
+/// Http request methods supported by [`RequestBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// Describes a request to send to the delayserver, or any other server given via
+/// [`RequestBuilder::addr`].
+///
+/// Created via [`Http::request`](super::Http::request). Nothing is sent until
+/// [`RequestBuilder::send`] is called and the returned future is polled.
+///
+/// ```ignore
+/// let txt = Http::request()
+///     .method(Method::Post)
+///     .path("/400/HelloPost")
+///     .header("Content-Type", "text/plain")
+///     .body("some data")
+///     .send()
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequestBuilder {
+    /// `host:port` to connect to, also sent as the `Host` header
+    addr: String,
+    method: Method,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    /// Bytes read from the socket at once
+    read_size: usize,
+    /// Bytes of the response buffered at most, None for no limit
+    max_response: Option<usize>,
+    /// Time the response has to be received in, None to wait for as long as it takes
+    timeout: Option<Duration>,
+    /// Set for requests sent over TLS
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl RequestBuilder {
+    pub(super) fn new() -> Self {
+        Self {
+            addr: default_addr().to_string(),
+            method: Method::Get,
+            path: String::from("/"),
+            headers: Vec::new(),
+            body: Vec::new(),
+            read_size: DEFAULT_READ_SIZE,
+            max_response: None,
+            timeout: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Send the request to `addr` (`host:port`) instead of the default address.
+    pub fn addr(mut self, addr: &str) -> Self {
+        self.addr = addr.to_string();
+        self
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// Add a header. Headers are sent in the order they are added.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Read the response in chunks of up to `bytes`, 4KiB by default. Each poll takes a
+    /// buffer of this size from the pool, see [`set_pooling`](crate::runtime::set_pooling).
+    ///
+    /// Panics if `bytes` is 0.
+    pub fn read_size(mut self, bytes: usize) -> Self {
+        assert!(bytes > 0, "read size must not be 0");
+        self.read_size = bytes;
+        self
+    }
+
+    /// Fail with [`HttpError::TooLarge`] once more than `bytes` of the response, head included,
+    /// have been received, rather than buffering it all. There is no limit by default.
+    ///
+    /// Only applies to [`RequestBuilder::send`], a [`BodyStream`] does not buffer the body.
+    pub fn max_response_size(mut self, bytes: usize) -> Self {
+        self.max_response = Some(bytes);
+        self
+    }
+
+    /// Fail with [`HttpError::Timeout`] if the response has not been received in full within
+    /// `timeout` of the first poll, e.g. as the server never responds. The stream is
+    /// deregistered from the reactor when it expires. There is no timeout by default.
+    ///
+    /// Only applies to [`RequestBuilder::send`], a [`BodyStream`] waits as long as it takes.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Send the request over TLS, verifying the server's certificate per `config`. The
+    /// certificate has to be valid for the host part of the address.
+    ///
+    /// See [`Http::get_https`](super::Http::get_https) for requests to servers with certificates
+    /// signed by the usual certificate authorities.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: Arc<rustls::ClientConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Returns a future that yields the response of the HTTP request, see [`Http::get`](super::Http::get).
+    pub fn send(self) -> impl Future<Output = Result<Response, HttpError>> {
+        HttpGetFuture::new(self)
+    }
+
+    /// Send the request, exposing the response body as a stream of chunks rather than
+    /// buffering it, see [`BodyStream`].
+    pub fn send_streaming(self) -> BodyStream {
+        BodyStream::new(self)
+    }
+
+    pub(super) fn addr_str(&self) -> &str {
+        &self.addr
+    }
+
+    pub(super) fn path_str(&self) -> &str {
+        &self.path
+    }
+
+    pub(super) fn method_kind(&self) -> Method {
+        self.method
+    }
+
+    pub(super) fn read_size_bytes(&self) -> usize {
+        self.read_size
+    }
+
+    pub(super) fn max_response_bytes(&self) -> Option<usize> {
+        self.max_response
+    }
+
+    pub(super) fn timeout_duration(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    #[cfg(feature = "tls")]
+    pub(super) fn tls_config(&self) -> Option<Arc<rustls::ClientConfig>> {
+        self.tls.clone()
+    }
+
+    /// Write out the request as a stream of bytes.
+    ///
+    /// `Host` (taken from the address unless set explicitly) and `Connection` headers are
+    /// always sent, the latter since we read the response
+    /// until the server closes the connection. `Content-Length` is added for non-empty bodies
+    /// unless it was set explicitly.
+    pub(super) fn to_bytes(&self) -> Vec<u8> {
+        self.serialize("close")
+    }
+
+    /// Same as [`RequestBuilder::to_bytes`], asking the server to keep the connection open for
+    /// further requests, see [`Pipeline`](super::Pipeline).
+    pub(super) fn to_bytes_keep_alive(&self) -> Vec<u8> {
+        self.serialize("keep-alive")
+    }
+
+    fn serialize(&self, connection: &str) -> Vec<u8> {
+        let mut req = format!("{} {} HTTP/1.1\r\n", self.method.as_str(), self.path);
+
+        let has_header = |name: &str| {
+            self.headers
+                .iter()
+                .any(|(header, _)| header.eq_ignore_ascii_case(name))
+        };
+
+        if !has_header("host") {
+            req.push_str(&format!("Host: {}\r\n", self.addr));
+        }
+
+        for (name, value) in &self.headers {
+            req.push_str(&format!("{name}: {value}\r\n"));
+        }
+
+        if !self.body.is_empty() && !has_header("content-length") {
+            req.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+
+        req.push_str(&format!("Connection: {connection}\r\n\r\n"));
+
+        let mut req = req.into_bytes();
+        req.extend_from_slice(&self.body);
+        req
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_get_without_body() {
+        let req = RequestBuilder::new()
+            .addr("localhost:8080")
+            .path("/600/HelloAsyncAwait")
+            .to_bytes();
+
+        assert_eq!(
+            req,
+            b"GET /600/HelloAsyncAwait HTTP/1.1\r\n\
+              Host: localhost:8080\r\n\
+              Connection: close\r\n\
+              \r\n"
+        );
+    }
+
+    #[test]
+    fn serializes_post_with_headers_and_body() {
+        let req = RequestBuilder::new()
+            .addr("example.com:80")
+            .method(Method::Post)
+            .path("/200/echo")
+            .header("Content-Type", "text/plain")
+            .body("hello")
+            .to_bytes();
+
+        assert_eq!(
+            req,
+            b"POST /200/echo HTTP/1.1\r\n\
+              Host: example.com:80\r\n\
+              Content-Type: text/plain\r\n\
+              Content-Length: 5\r\n\
+              Connection: close\r\n\
+              \r\n\
+              hello"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Responses of http requests.
+use std::fmt;
+
+use super::{find_head_end, HttpError};
+
+/// Response to an http request, as received. A chunked body is decoded already.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    text: String,
+    /// Index just past the blank line that ends the head
+    head_end: usize,
+    status: u16,
+}
+
+impl Response {
+    /// Check that `text` starts with a complete head, with a status line such as
+    /// `HTTP/1.1 200 OK`.
+    pub(super) fn parse(text: String) -> Result<Self, HttpError> {
+        let head_end = find_head_end(text.as_bytes())
+            .ok_or_else(|| HttpError::Parse("response ended within its head".to_string()))?;
+
+        let status_line = text.lines().next().unwrap_or_default();
+        let status = match status_line.split_whitespace().collect::<Vec<_>>()[..] {
+            [version, code, ..] if version.starts_with("HTTP/") => code.parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| HttpError::Parse(format!("invalid status line {status_line:?}")))?;
+
+        Ok(Self {
+            text,
+            head_end,
+            status,
+        })
+    }
+
+    /// Status code, e.g. 200.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The status line and headers, up to and including the blank line that ends them.
+    pub fn head(&self) -> &str {
+        &self.text[..self.head_end]
+    }
+
+    pub fn body(&self) -> &str {
+        &self.text[self.head_end..]
+    }
+
+    /// The whole response, head and body.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn into_string(self) -> String {
+        self.text
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_head_and_body() {
+        let text = "HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\n\r\ngone";
+        let response = Response::parse(text.to_string()).unwrap();
+
+        assert_eq!(response.status(), 404);
+        assert!(response.head().ends_with("Content-Length: 4\r\n\r\n"));
+        assert_eq!(response.body(), "gone");
+        assert_eq!(response.to_string(), text);
+    }
+
+    #[test]
+    fn incomplete_or_invalid_heads_are_rejected() {
+        for text in ["HTTP/1.1 200 OK\r\nContent-Len", "hello\r\n\r\nworld"] {
+            let err = Response::parse(text.to_string()).unwrap_err();
+            assert!(matches!(err, HttpError::Parse(_)), "{text:?}: {err}");
+        }
+    }
+}
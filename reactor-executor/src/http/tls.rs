@@ -0,0 +1,312 @@
+//! TLS for the http client, so requests can be sent over HTTPS. Only built with the `tls`
+//! feature.
+//!
+//! [`TlsStream`] wraps a non-blocking TCP stream in a rustls client connection. rustls does no
+//! I/O of its own: it buffers the records it wants to send, and the ones we read for it, so the
+//! handshake is driven by the same readiness events as any other read:
+//!
+//! - WANT_WRITE: records are waiting to be sent. We send what the socket takes, and wait for it
+//!   to become writable for the rest, see [`TlsStream::interest`].
+//! - WANT_READ: no plaintext is buffered yet, we read records from the socket until it would
+//!   block, and wait for it to become readable again.
+//!
+//! The request is written to rustls before the handshake is done. It is buffered, and sent once
+//! the handshake completes, all within [`TlsStream::read`] calls made by the future.
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    sync::{Arc, OnceLock},
+};
+
+use mio::{net::TcpStream, Interest};
+use rustls::{pki_types::ServerName, ClientConfig, ClientConnection, RootCertStore};
+
+/// Default port of HTTPS, used if the host given to [`Http::get_https`](super::Http::get_https)
+/// has none
+pub(super) const HTTPS_PORT: u16 = 443;
+
+/// Config trusting the Mozilla root certificates, shared by all requests not given their own
+/// via [`RequestBuilder::tls`](super::RequestBuilder::tls).
+pub(super) fn default_config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+
+    CONFIG
+        .get_or_init(|| {
+            let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            let config = ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            Arc::new(config)
+        })
+        .clone()
+}
+
+/// Client side of a TLS connection over a non-blocking TCP stream, see the module docs.
+pub(super) struct TlsStream {
+    tcp: TcpStream,
+    conn: ClientConnection,
+    /// Set once the server closed without sending a close_notify alert first
+    closed_without_notify: bool,
+}
+
+impl TlsStream {
+    /// Start a handshake with the server at `addr` (`host:port`), whose certificate has to be
+    /// valid for `host`. Nothing is sent until the stream is first read from or written to.
+    pub(super) fn new(tcp: TcpStream, config: Arc<ClientConfig>, addr: &str) -> io::Result<Self> {
+        let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+
+        let mut conn = ClientConnection::new(config, name).map_err(invalid_data)?;
+        // the request is in memory anyway, do not refuse part of it while handshaking
+        conn.set_buffer_limit(None);
+
+        Ok(Self {
+            tcp,
+            conn,
+            closed_without_notify: false,
+        })
+    }
+
+    pub(super) fn is_handshaking(&self) -> bool {
+        self.conn.is_handshaking()
+    }
+
+    /// Events to wait for: always READABLE, for records from the server, and WRITABLE while
+    /// records are waiting for room in the socket.
+    pub(super) fn interest(&self) -> Interest {
+        if self.conn.wants_write() {
+            Interest::READABLE | Interest::WRITABLE
+        } else {
+            Interest::READABLE
+        }
+    }
+
+    /// True once the server closed without a close_notify alert, so an attacker may have cut
+    /// the response short. Only a response whose end is known can tell, see
+    /// [`Stream::closed_without_notify`](super::Stream::closed_without_notify).
+    pub(super) fn closed_without_notify(&self) -> bool {
+        self.closed_without_notify
+    }
+
+    pub(super) fn tcp_mut(&mut self) -> &mut TcpStream {
+        &mut self.tcp
+    }
+
+    /// Send buffered records until there are none left, or the socket would block.
+    fn send_records(&mut self) -> io::Result<()> {
+        while self.conn.wants_write() {
+            match self.conn.write_tls(&mut self.tcp) {
+                Ok(_) => {}
+                // the rest is sent on a later call, once the socket is writable
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for TlsStream {
+    /// Read plaintext, progressing the handshake on the way. Fails with `WouldBlock` once the
+    /// socket has nothing left for us.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            self.send_records()?;
+
+            match self.conn.reader().read(buf) {
+                Ok(n) => return Ok(n),
+                // WANT_READ, nothing to hand out until more records arrive
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                // Servers often close without sending a close_notify alert first. Requests are
+                // sent with `Connection: close` and read until the server closes, so that is
+                // taken as the end of the stream, like it is over plain TCP. Whether the
+                // response is complete is up to its framing, see `closed_without_notify`.
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    self.closed_without_notify = true;
+                    return Ok(0);
+                }
+                Err(e) => return Err(e),
+            }
+
+            match self.conn.read_tls(&mut self.tcp) {
+                // 0 at the end of stream, which the reader reports once it handed out the rest
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+
+            if let Err(e) = self.conn.process_new_packets() {
+                // send the alert telling the server why we give up, if the socket takes it
+                let _ = self.send_records();
+                return Err(invalid_data(e));
+            }
+        }
+    }
+}
+
+impl Write for TlsStream {
+    /// Buffers all of `buf` with rustls, sending what the socket takes right away.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.conn.writer().write(buf)?;
+        self.send_records()?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_records()
+    }
+}
+
+fn invalid_data(err: rustls::Error) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+        ServerConfig, ServerConnection, StreamOwned,
+    };
+
+    use super::*;
+    use crate::{
+        http::{Http, HttpError},
+        runtime,
+    };
+
+    /// Signs `LOCALHOST_CERT`, valid for `localhost` and `127.0.0.1`
+    const CA_CERT: &[u8] = include_bytes!("testdata/ca.der");
+    const LOCALHOST_CERT: &[u8] = include_bytes!("testdata/localhost.der");
+    const LOCALHOST_KEY: &[u8] = include_bytes!("testdata/localhost.key.der");
+
+    /// Client config trusting the test CA only
+    fn test_config() -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from(CA_CERT)).unwrap();
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Arc::new(config)
+    }
+
+    /// Serve a single HTTPS connection with `body`, returning the address to connect to.
+    fn serve_once(body: Vec<u8>) -> String {
+        let mut response = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", body.len());
+        response.push_str(&String::from_utf8(body).unwrap());
+        serve_raw(response, true)
+    }
+
+    /// Serve a single HTTPS connection with `response` as is, closing with a close_notify alert
+    /// only if `close_notify` is set.
+    fn serve_raw(response: String, close_notify: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(LOCALHOST_KEY));
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![CertificateDer::from(LOCALHOST_CERT)], key)
+            .unwrap();
+
+        thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let conn = ServerConnection::new(Arc::new(config)).unwrap();
+            let mut stream = StreamOwned::new(conn, socket);
+
+            // a handshake the client gives up on ends here
+            let mut request = [0u8; 1024];
+            let Ok(_) = stream.read(&mut request) else {
+                return;
+            };
+
+            stream.write_all(response.as_bytes()).unwrap();
+            if close_notify {
+                stream.conn.send_close_notify();
+            }
+            stream.flush().unwrap();
+        });
+
+        addr
+    }
+
+    #[test]
+    fn https_request_completes_the_handshake() {
+        // spans several TLS records, of at most 16 KB each
+        let body = vec![b'x'; 64 * 1024];
+        let addr = serve_once(body.clone());
+
+        runtime::init_for_tests().block_on(async move {
+            let response = Http::request()
+                .addr(&addr)
+                .tls(test_config())
+                .path("/")
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.body().as_bytes(), body);
+        });
+    }
+
+    #[test]
+    fn untrusted_certificate_fails_the_handshake() {
+        let addr = serve_once(Vec::new());
+
+        runtime::init_for_tests().block_on(async move {
+            // the test CA is not among the default roots
+            let err = Http::request()
+                .addr(&addr)
+                .tls(default_config())
+                .send()
+                .await
+                .unwrap_err();
+            match err {
+                HttpError::Tls(e) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+                other => panic!("expected a tls error, got {other}"),
+            }
+        });
+    }
+
+    #[test]
+    fn truncated_response_fails_without_close_notify() {
+        let truncated = [
+            "HTTP/1.1 200 OK\r\ncontent-length: 100\r\n\r\nonly part",
+            "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n",
+            "HTTP/1.1 200 OK\r\ncontent-le",
+        ];
+
+        for response in truncated {
+            let addr = serve_raw(response.to_string(), false);
+
+            runtime::init_for_tests().block_on(async move {
+                let err = Http::request()
+                    .addr(&addr)
+                    .tls(test_config())
+                    .send()
+                    .await
+                    .unwrap_err();
+                match err {
+                    HttpError::Read(e) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+                    other => panic!("expected a read error, got {other}"),
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn response_read_until_close_does_not_need_close_notify() {
+        let addr = serve_raw("HTTP/1.1 200 OK\r\n\r\nuntil close".to_string(), false);
+
+        runtime::init_for_tests().block_on(async move {
+            let response = Http::request()
+                .addr(&addr)
+                .tls(test_config())
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.body(), "until close");
+        });
+    }
+}
@@ -0,0 +1,675 @@
+//! Traits for reading and writing bytes without blocking, and buffered adapters on top of them.
+//!
+//! [`AsyncRead`] and [`AsyncWrite`] are the counterparts of [`std::io::Read`] and
+//! [`std::io::Write`] for the runtime: rather than block, or fail with `WouldBlock`, an
+//! operation that can not make progress yet returns `Pending`, and leaves the waker of `cx`
+//! wherever it is woken from once it can, e.g. the reactor. Sockets and leaf futures implement
+//! the `poll_*` methods, and get the futures of [`AsyncReadExt`] and [`AsyncWriteExt`] on top,
+//! rather than each writing read and write loops of their own.
+//!
+//! [`BufReader`] adds [`AsyncBufRead`], and with it [`AsyncBufReadExt::read_until`] and
+//! [`AsyncBufReadExt::read_line`] for line based protocols. [`BufWriter`] batches small
+//! writes into fewer syscalls.
+//!
+//! ```ignore
+//! let (conn, _) = listener.accept().await?;
+//! let mut conn = BufReader::new(conn);
+//! let mut line = String::new();
+//! while conn.read_line(&mut line).await? > 0 {
+//!     conn.write_all(line.to_uppercase().as_bytes()).await?;
+//!     line.clear();
+//! }
+//! ```
+use std::{
+    future::Future,
+    io::{self, ErrorKind},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+/// Size of the buffers of [`BufReader`] and [`BufWriter`], unless given one
+const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+
+/// Source of bytes that can be read without blocking, see the module docs.
+pub trait AsyncRead {
+    /// Read into `buf`, resolving to the number of bytes read. 0 means the end of the stream,
+    /// or that `buf` is empty.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>;
+}
+
+/// Sink of bytes that can be written without blocking, see the module docs.
+pub trait AsyncWrite {
+    /// Write from `buf`, resolving to the number of bytes taken.
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>;
+
+    /// Write out whatever was buffered along the way, e.g. by a [`BufWriter`].
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+}
+
+/// An [`AsyncRead`] with a buffer of its own, which can be looked into before consuming it.
+pub trait AsyncBufRead: AsyncRead {
+    /// Resolve to the bytes buffered, reading more first if there are none. Empty means the
+    /// end of the stream.
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>>;
+
+    /// Mark `amt` of the buffered bytes as read, so they are not handed out again.
+    fn consume(self: Pin<&mut Self>, amt: usize);
+}
+
+impl<T: AsyncRead + Unpin + ?Sized> AsyncRead for &mut T {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncRead + Unpin + ?Sized> AsyncRead for Box<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin + ?Sized> AsyncWrite for &mut T {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+}
+
+impl<T: AsyncWrite + Unpin + ?Sized> AsyncWrite for Box<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+}
+
+impl<T: AsyncBufRead + Unpin + ?Sized> AsyncBufRead for &mut T {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut **self.get_mut()).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut **self).consume(amt)
+    }
+}
+
+/// Futures for reading from an [`AsyncRead`] with async/await.
+pub trait AsyncReadExt: AsyncRead {
+    /// Returns a future that reads into `buf`, resolving to the number of bytes read.
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> Read<'a, Self>
+    where
+        Self: Unpin,
+    {
+        Read { reader: self, buf }
+    }
+}
+
+impl<R: AsyncRead + ?Sized> AsyncReadExt for R {}
+
+/// Futures for writing to an [`AsyncWrite`] with async/await.
+pub trait AsyncWriteExt: AsyncWrite {
+    /// Returns a future that writes from `buf`, resolving to the number of bytes taken.
+    fn write<'a>(&'a mut self, buf: &'a [u8]) -> Write<'a, Self>
+    where
+        Self: Unpin,
+    {
+        Write { writer: self, buf }
+    }
+
+    /// Returns a future that writes all of `buf`, waiting for room as needed.
+    fn write_all<'a>(&'a mut self, buf: &'a [u8]) -> WriteAll<'a, Self>
+    where
+        Self: Unpin,
+    {
+        WriteAll { writer: self, buf }
+    }
+
+    /// Returns a future that writes out whatever was buffered, see [`AsyncWrite::poll_flush`].
+    fn flush(&mut self) -> Flush<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Flush { writer: self }
+    }
+}
+
+impl<W: AsyncWrite + ?Sized> AsyncWriteExt for W {}
+
+/// Futures for reading delimited data from an [`AsyncBufRead`] with async/await.
+pub trait AsyncBufReadExt: AsyncBufRead {
+    /// Returns a future that appends bytes to `buf` up to and including `byte`, or up to the
+    /// end of the stream. Resolves to the number of bytes appended, 0 only at the end of the
+    /// stream.
+    fn read_until<'a>(&'a mut self, byte: u8, buf: &'a mut Vec<u8>) -> ReadUntil<'a, Self>
+    where
+        Self: Unpin,
+    {
+        ReadUntil {
+            reader: self,
+            byte,
+            buf,
+            read: 0,
+        }
+    }
+
+    /// Same as [`AsyncBufReadExt::read_until`] a newline, appending to a `String`. Fails with
+    /// [`ErrorKind::InvalidData`] if the line is not UTF-8, leaving `buf` as it was.
+    fn read_line<'a>(&'a mut self, buf: &'a mut String) -> ReadLine<'a, Self>
+    where
+        Self: Unpin,
+    {
+        ReadLine {
+            reader: self,
+            buf,
+            bytes: Vec::new(),
+        }
+    }
+}
+
+impl<R: AsyncBufRead + ?Sized> AsyncBufReadExt for R {}
+
+/// Future returned by [`AsyncReadExt::read`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Read<'a, R: ?Sized> {
+    reader: &'a mut R,
+    buf: &'a mut [u8],
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> Future for Read<'_, R> {
+    type Output = io::Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        Pin::new(&mut *this.reader).poll_read(cx, this.buf)
+    }
+}
+
+/// Future returned by [`AsyncWriteExt::write`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Write<'a, W: ?Sized> {
+    writer: &'a mut W,
+    buf: &'a [u8],
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for Write<'_, W> {
+    type Output = io::Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        Pin::new(&mut *this.writer).poll_write(cx, this.buf)
+    }
+}
+
+/// Future returned by [`AsyncWriteExt::write_all`].
+#[must_use = "futures do nothing unless polled"]
+pub struct WriteAll<'a, W: ?Sized> {
+    writer: &'a mut W,
+    /// What is left to write
+    buf: &'a [u8],
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for WriteAll<'_, W> {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        while !this.buf.is_empty() {
+            match ready!(Pin::new(&mut *this.writer).poll_write(cx, this.buf))? {
+                0 => return Poll::Ready(Err(ErrorKind::WriteZero.into())),
+                n => this.buf = &this.buf[n..],
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Future returned by [`AsyncWriteExt::flush`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Flush<'a, W: ?Sized> {
+    writer: &'a mut W,
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for Flush<'_, W> {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.writer).poll_flush(cx)
+    }
+}
+
+/// Future returned by [`AsyncBufReadExt::read_until`].
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadUntil<'a, R: ?Sized> {
+    reader: &'a mut R,
+    byte: u8,
+    buf: &'a mut Vec<u8>,
+    /// Bytes appended to `buf` by earlier polls
+    read: usize,
+}
+
+impl<R: AsyncBufRead + Unpin + ?Sized> Future for ReadUntil<'_, R> {
+    type Output = io::Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        poll_read_until(&mut *this.reader, cx, this.byte, this.buf, &mut this.read)
+    }
+}
+
+/// Future returned by [`AsyncBufReadExt::read_line`].
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadLine<'a, R: ?Sized> {
+    reader: &'a mut R,
+    buf: &'a mut String,
+    /// The line read so far, appended to `buf` once complete
+    bytes: Vec<u8>,
+}
+
+impl<R: AsyncBufRead + Unpin + ?Sized> Future for ReadLine<'_, R> {
+    type Output = io::Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut read = this.bytes.len();
+        let n = ready!(poll_read_until(
+            &mut *this.reader,
+            cx,
+            b'\n',
+            &mut this.bytes,
+            &mut read
+        ))?;
+
+        let line = std::str::from_utf8(&this.bytes)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        this.buf.push_str(line);
+        this.bytes.clear();
+        Poll::Ready(Ok(n))
+    }
+}
+
+/// Move buffered bytes into `buf` until `byte` or the end of the stream, see
+/// [`AsyncBufReadExt::read_until`]. `read` carries the count over between polls.
+fn poll_read_until<R: AsyncBufRead + Unpin + ?Sized>(
+    reader: &mut R,
+    cx: &mut Context<'_>,
+    byte: u8,
+    buf: &mut Vec<u8>,
+    read: &mut usize,
+) -> Poll<io::Result<usize>> {
+    loop {
+        let (done, used) = {
+            let available = ready!(Pin::new(&mut *reader).poll_fill_buf(cx))?;
+            match available.iter().position(|&b| b == byte) {
+                Some(i) => {
+                    buf.extend_from_slice(&available[..=i]);
+                    (true, i + 1)
+                }
+                // nothing buffered means the end of the stream
+                None => {
+                    buf.extend_from_slice(available);
+                    (available.is_empty(), available.len())
+                }
+            }
+        };
+
+        Pin::new(&mut *reader).consume(used);
+        *read += used;
+        if done {
+            return Poll::Ready(Ok(std::mem::take(read)));
+        }
+    }
+}
+
+/// Adds a buffer to an [`AsyncRead`], so small reads do not each cost a syscall, and to
+/// implement [`AsyncBufRead`].
+///
+/// Writes go straight through to the wrapped writer, if it is one, e.g. to answer lines read
+/// from a socket on the same socket.
+pub struct BufReader<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    /// Start of the bytes not consumed yet
+    pos: usize,
+    /// End of the bytes read into `buf`
+    filled: usize,
+}
+
+impl<R> BufReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![0; capacity].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// The wrapped reader. Reading from it directly skips what is buffered.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Bytes read but not consumed yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// The wrapped reader. Whatever is buffered is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BufReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        // nothing buffered and a read at least as large as our buffer: skip the copy
+        if self.pos == self.filled && buf.len() >= self.buf.len() {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+
+        let available = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufRead for BufReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+
+        if this.pos == this.filled {
+            let n = ready!(Pin::new(&mut this.inner).poll_read(cx, &mut this.buf))?;
+            this.pos = 0;
+            this.filled = n;
+        }
+        Poll::Ready(Ok(&this.buf[this.pos..this.filled]))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        self.pos = (self.pos + amt).min(self.filled);
+    }
+}
+
+impl<R: AsyncWrite + Unpin> AsyncWrite for BufReader<R> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+}
+
+/// Adds a buffer to an [`AsyncWrite`], so small writes are sent in batches.
+///
+/// Bytes are only written out once the buffer is full, or on [`AsyncWriteExt::flush`]. There
+/// is no flushing on drop, as that would have to block: flush before dropping, or whatever is
+/// still buffered is lost.
+pub struct BufWriter<W> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W> BufWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: W) -> Self {
+        Self {
+            inner,
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// The wrapped writer. Writing to it directly skips ahead of what is buffered.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Bytes written but not sent yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// The wrapped writer. Whatever is buffered is lost.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin> BufWriter<W> {
+    /// Write out the buffer, keeping what is left of it if the writer has no room.
+    fn poll_flush_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut written = 0;
+        let result = loop {
+            if written == self.buf.len() {
+                break Poll::Ready(Ok(()));
+            }
+
+            match Pin::new(&mut self.inner).poll_write(cx, &self.buf[written..]) {
+                Poll::Ready(Ok(0)) => break Poll::Ready(Err(ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(n)) => written += n,
+                Poll::Ready(Err(e)) => break Poll::Ready(Err(e)),
+                Poll::Pending => break Poll::Pending,
+            }
+        };
+
+        self.buf.drain(..written);
+        result
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for BufWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.buf.len() + buf.len() > self.buf.capacity() {
+            ready!(self.poll_flush_buf(cx))?;
+        }
+
+        // too large to be worth buffering
+        if buf.len() >= self.buf.capacity() {
+            return Pin::new(&mut self.inner).poll_write(cx, buf);
+        }
+
+        self.buf.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_flush_buf(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+}
+
+impl<W: AsyncRead + Unpin> AsyncRead for BufWriter<W> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::runtime::Executor;
+
+    /// Hands out its chunks one read at a time, returning Pending before each of them.
+    struct Chunks {
+        chunks: VecDeque<&'static [u8]>,
+        /// Set once Pending was returned for the next chunk
+        ready: bool,
+    }
+
+    impl Chunks {
+        fn new(chunks: &[&'static [u8]]) -> Self {
+            Self {
+                chunks: chunks.iter().copied().collect(),
+                ready: false,
+            }
+        }
+    }
+
+    impl AsyncRead for Chunks {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            if !std::mem::replace(&mut self.ready, false) {
+                self.ready = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+
+            let Some(chunk) = self.chunks.pop_front() else {
+                return Poll::Ready(Ok(0));
+            };
+            // hand back what does not fit
+            let n = chunk.len().min(buf.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            if n < chunk.len() {
+                self.chunks.push_front(&chunk[n..]);
+            }
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    /// Records every write it gets.
+    #[derive(Default)]
+    struct Recorder {
+        writes: Vec<Vec<u8>>,
+    }
+
+    impl AsyncWrite for Recorder {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.writes.push(buf.to_vec());
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn lines_are_read_across_chunks() {
+        Executor::new().block_on(async {
+            let chunks = Chunks::new(&[b"first li", b"ne\nsecond\nth", b"ird"]);
+            // smaller than the chunks, so lines also span refills of the buffer
+            let mut reader = BufReader::with_capacity(4, chunks);
+            let mut lines = Vec::new();
+
+            loop {
+                let mut line = String::new();
+                match reader.read_line(&mut line).await.unwrap() {
+                    0 => break,
+                    n => assert_eq!(n, line.len()),
+                }
+                lines.push(line);
+            }
+
+            assert_eq!(lines, ["first line\n", "second\n", "third"]);
+        });
+    }
+
+    #[test]
+    fn read_line_rejects_invalid_utf8() {
+        Executor::new().block_on(async {
+            let mut reader = BufReader::new(Chunks::new(&[b"caf\xe9\n", b"ok\n"]));
+            let mut line = String::new();
+
+            let err = reader.read_line(&mut line).await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert!(line.is_empty());
+
+            // the invalid line is consumed, the next one reads as usual
+            reader.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "ok\n");
+        });
+    }
+
+    #[test]
+    fn buffered_writes_go_out_in_batches() {
+        Executor::new().block_on(async {
+            let mut writer = BufWriter::with_capacity(8, Recorder::default());
+
+            for word in ["ab", "cd", "ef", "gh", "ij"] {
+                writer.write_all(word.as_bytes()).await.unwrap();
+            }
+            // a full buffer was sent once the fifth word did not fit
+            assert_eq!(writer.get_ref().writes, [b"abcdefgh"]);
+
+            writer.write_all(b"larger than the buffer").await.unwrap();
+            writer.flush().await.unwrap();
+            assert_eq!(
+                writer.into_inner().writes,
+                [&b"abcdefgh"[..], b"ij", b"larger than the buffer"]
+            );
+        });
+    }
+}
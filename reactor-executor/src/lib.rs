@@ -0,0 +1,20 @@
+//! Runtime built around a reactor and an executor, along with the leaf futures, networking
+//! types and HTTP client driven by them.
+//!
+//! The `reactor-executor` binary runs the examples and benchmarks on top of this library.
+//! Other crates in the workspace can use it too, most conveniently through the `prelude`
+//! crate. The hand-written coroutines of the book's binaries run on it through
+//! [`coroutine`].
+#![allow(unused)]
+
+pub mod bench;
+pub mod coroutine;
+pub mod fanout;
+pub mod future;
+pub mod http;
+pub mod io;
+pub mod mock;
+pub mod net;
+pub mod runtime;
+pub mod sim;
+pub mod tls;
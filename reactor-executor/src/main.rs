@@ -11,14 +11,101 @@ use std::{
     task::{Context, Poll},
 };
 
-mod future;
-mod http;
-mod runtime;
+use reactor_executor::future::StreamExt;
+use reactor_executor::http::{Cassette, Http, HttpError, Method, Response};
+use reactor_executor::runtime::{reactor, Executor};
+use reactor_executor::{bench, fanout, net, runtime, sim};
 
-use crate::http::Http;
-use crate::runtime::{reactor, Executor};
+/// Counts allocations for the benchmarks, see [`bench::CountingAlloc`].
+#[global_allocator]
+static ALLOCATOR: bench::CountingAlloc = bench::CountingAlloc;
 
 pub fn main() {
+    if std::env::args().any(|arg| arg == "--bench") {
+        bench::run();
+        return;
+    }
+
+    // Run against a simulated network instead of the delayserver
+    if std::env::args().any(|arg| arg == "--sim") {
+        let mut executor = runtime::init();
+        executor.block_on(async_main_sim());
+        return;
+    }
+
+    // Record the responses of the delayserver to a cassette, or replay them without it
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, file] = args.as_slice() {
+        if flag == "--record" {
+            let mut executor = runtime::init();
+            let cassette = Cassette::record();
+            executor.block_on(async_main_cassette(cassette.clone()));
+            cassette.save(file).expect("Failed to save cassette");
+            return;
+        }
+
+        if flag == "--replay" {
+            let net = sim::Network::new(1);
+            net.install();
+            let cassette = Cassette::load(file, net).expect("Failed to load cassette");
+
+            let mut executor = runtime::init();
+            executor.block_on(async_main_cassette(cassette));
+            return;
+        }
+
+        // Serve clients in other processes over a socket path, until killed
+        if flag == "--ipc-server" {
+            let mut executor = runtime::init();
+            executor.block_on(async_main_ipc_server(file.clone()));
+            return;
+        }
+    }
+
+    if let [_, flag, path, msg] = args.as_slice() {
+        if flag == "--ipc-client" {
+            let mut executor = runtime::init();
+            executor.block_on(async_main_ipc_client(path.clone(), msg.clone()));
+            return;
+        }
+    }
+
+    // Talk to a local daemon over a Unix domain socket instead of the delayserver
+    if std::env::args().any(|arg| arg == "--unix") {
+        let mut executor = runtime::init();
+        executor.block_on(async_main_unix());
+        return;
+    }
+
+    // Echo datagrams between two UDP sockets
+    if std::env::args().any(|arg| arg == "--udp") {
+        let mut executor = runtime::init();
+        executor.block_on(async_main_udp());
+        return;
+    }
+
+    // Same as `--unix`, with the io_uring backend instead of the reactor
+    if std::env::args().any(|arg| arg == "--uring") {
+        let mut executor = runtime::init_with_backend(runtime::Backend::IoUring);
+        executor.block_on(async_main_uring());
+        return;
+    }
+
+    // Serve a query that fans out to simulated upstreams, and send it one request
+    if std::env::args().any(|arg| arg == "--fanout") {
+        // show how long each stage of the query took
+        let log = runtime::LogConfig::default().module("fanout", runtime::log::Level::Info);
+        let mut executor = runtime::init_with_log(log);
+        executor.block_on(async_main_fanout());
+        return;
+    }
+
+    // Spread requests over an executor per core
+    if std::env::args().any(|arg| arg == "--per-core") {
+        main_per_core();
+        return;
+    }
+
     // initialise the runtime
     let mut executor = runtime::init();
 
@@ -34,9 +121,283 @@ async fn async_main() {
 
     println!("Program starting");
 
-    let txt = Http::get("/600/HelloAsyncAwait").await;
-    println!("{txt}");
+    print_response(Http::get("/600/HelloAsyncAwait").await);
+    print_response(Http::get("/400/HelloAsyncAwait").await);
+
+    let response = Http::request()
+        .method(Method::Post)
+        .path("/200/HelloPost")
+        .header("Content-Type", "text/plain")
+        .body("Hello from the request builder")
+        .send()
+        .await;
+    print_response(response);
+
+    let before = reactor().ctl_calls();
+    print_response(Http::get_speculative("/0/HelloSpeculative").await);
+    println!(
+        "epoll_ctl calls for speculative request: {}",
+        reactor().ctl_calls() - before
+    );
+
+    let mut body = Http::get_streaming("/200/HelloStreaming");
+    while let Some(chunk) = body.next().await {
+        match chunk {
+            Ok(chunk) => println!("body chunk: {}", String::from_utf8_lossy(&chunk)),
+            Err(e) => eprintln!("Streaming request failed: {e}"),
+        }
+    }
+}
+
+/// Print the response, or why the request failed, e.g. because the delayserver is not running.
+fn print_response(result: Result<Response, HttpError>) {
+    match result {
+        Ok(response) => println!("{response}"),
+        Err(e) => eprintln!("Request failed: {e}"),
+    }
+}
+
+/// Start an executor per core, then hand requests to them round-robin via
+/// [`runtime::spawn_global`], printing which core's thread served each.
+///
+/// ```bash
+/// cargo run -p reactor-executor -- --per-core
+/// ```
+fn main_per_core() {
+    let cores = runtime::available_cores();
+    let builder = runtime::Builder::new().cores(cores.clone());
+    let _executor = builder.build();
+
+    // stops the executors once all requests are served
+    let stop: std::sync::Arc<Vec<_>> =
+        std::sync::Arc::new(cores.iter().map(|_| runtime::sync::Notify::new()).collect());
+    let stopped = stop.clone();
+    let workers = builder.spawn_executors(move |i| {
+        let stop = stopped.clone();
+        async move { stop[i].notified().await }
+    });
+
+    println!("Program starting, {} executors", runtime::core_count());
+    let (done, finished) = std::sync::mpsc::channel();
+    for i in 0..2 * runtime::core_count() {
+        let done = done.clone();
+        runtime::spawn_global(async move {
+            let response = Http::get(&format!("/{}/HelloCore{i}", 100 * (i % 3))).await;
+            let thread = std::thread::current()
+                .name()
+                .unwrap_or_default()
+                .to_string();
+            let _ = done.send((thread, response));
+        });
+    }
+    drop(done);
+
+    for (thread, response) in finished {
+        println!("served on {thread}:");
+        print_response(response);
+    }
+
+    for notify in stop.iter() {
+        notify.notify_one();
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
+
+/// Run the fan-out server of [`fanout`] and query it once.
+///
+/// ```bash
+/// cargo run -p reactor-executor -- --fanout
+/// ```
+async fn async_main_fanout() {
+    let path = std::env::temp_dir().join(format!("fanout-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let net = fanout::network(1);
+    net.install();
+    let listener = net::unix::UnixListener::bind(&path).expect("Failed to bind socket");
+    runtime::spawn_local(fanout::serve(listener, net, 1));
+
+    let response = fanout::query(&path).await.expect("Failed to query server");
+    println!("{response}");
+
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Same requests as `async_main`, sent by the same client, but served by a simulated
+/// delayserver.
+///
+/// ```bash
+/// cargo run -p reactor-executor -- --sim
+/// ```
+async fn async_main_sim() {
+    let net = sim::Network::new(1);
+    net.add_endpoint(
+        "127.0.0.1:8080",
+        sim::Link::new(std::time::Duration::from_millis(20)),
+    );
+    net.install();
+
+    println!("Program starting (simulated network)");
+
+    // the http client connects to the endpoints of the installed network in memory
+    let client = Http::with_addr("127.0.0.1:8080");
+    print_response(client.get("/600/HelloAsyncAwait").await);
+    print_response(client.get("/400/HelloAsyncAwait").await);
+
+    println!("Virtual time elapsed: {:?}", net.now());
+}
+
+/// Same requests as `async_main`, sent through a cassette that either records the responses
+/// or replays earlier recordings.
+///
+/// ```bash
+/// cargo run -p reactor-executor -- --record requests.cassette
+/// cargo run -p reactor-executor -- --replay requests.cassette
+/// ```
+async fn async_main_cassette(cassette: Cassette) {
+    println!("Program starting (cassette)");
+
+    let txt = cassette.send(Http::request().path("/600/HelloAsyncAwait"));
+    println!("{}", txt.await.unwrap());
+
+    let txt = cassette.send(Http::request().path("/400/HelloAsyncAwait"));
+    println!("{}", txt.await.unwrap());
+
+    let request = Http::request()
+        .method(Method::Post)
+        .path("/200/HelloPost")
+        .header("Content-Type", "text/plain")
+        .body("Hello from the request builder");
+    println!("{}", cassette.send(request).await.unwrap());
+}
+
+/// Runs a small daemon task that upper-cases whatever it receives over a socket path, and a
+/// client that talks to it.
+///
+/// ```bash
+/// cargo run -p reactor-executor -- --unix
+/// ```
+async fn async_main_unix() {
+    let path = std::env::temp_dir().join("reactor-executor-daemon.sock");
+    let _ = std::fs::remove_file(&path);
+
+    let mut listener = net::UnixListener::bind(&path).expect("Failed to bind socket path");
+    println!("Daemon listening on {}", path.display());
+
+    runtime::spawn(async move {
+        let (mut conn, _) = listener.accept().await.unwrap();
+        let mut msg = Vec::new();
+        conn.read_to_end(&mut msg).await.unwrap();
+        conn.write_all(&msg.to_ascii_uppercase()).await.unwrap();
+    });
+
+    let mut stream = net::UnixStream::connect(&path).expect("Failed to connect to daemon");
+    stream.write_all(b"hello over a unix socket").await.unwrap();
+    stream.shutdown_write().unwrap();
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.unwrap();
+    println!("Daemon replied: {}", String::from_utf8_lossy(&reply));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Server half of a local IPC example across two processes: upper-cases whatever each client
+/// sends, serving every connection in a task of its own.
+///
+/// ```bash
+/// cargo run -p reactor-executor -- --ipc-server /tmp/reactor-executor-ipc.sock
+/// cargo run -p reactor-executor -- --ipc-client /tmp/reactor-executor-ipc.sock "hello ipc"
+/// ```
+async fn async_main_ipc_server(path: String) {
+    let _ = std::fs::remove_file(&path);
+    let mut listener = net::UnixListener::bind(&path).expect("Failed to bind socket path");
+    println!("Serving on {path}, stop with Ctrl-C");
+
+    loop {
+        let (mut conn, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {e}");
+                continue;
+            }
+        };
+
+        runtime::spawn(async move {
+            let mut msg = Vec::new();
+            if let Err(e) = conn.read_to_end(&mut msg).await {
+                eprintln!("Failed to read request: {e}");
+                return;
+            }
+            println!("Received: {}", String::from_utf8_lossy(&msg));
+
+            if let Err(e) = conn.write_all(&msg.to_ascii_uppercase()).await {
+                eprintln!("Failed to write reply: {e}");
+            }
+        });
+    }
+}
+
+/// Client half of the IPC example, see [`async_main_ipc_server`].
+async fn async_main_ipc_client(path: String, msg: String) {
+    let mut stream = net::UnixStream::connect(&path).expect("Failed to connect to server");
+    stream.write_all(msg.as_bytes()).await.unwrap();
+    stream.shutdown_write().unwrap();
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.unwrap();
+    println!("Server replied: {}", String::from_utf8_lossy(&reply));
+}
+
+async fn async_main_udp() {
+    let server = net::UdpSocket::bind("127.0.0.1:0").expect("Failed to bind server socket");
+    let server_addr = server.local_addr().unwrap();
+    println!("Echo server listening on udp://{server_addr}");
+
+    runtime::spawn(async move {
+        let mut buf = [0u8; 512];
+        let (n, peer) = server.recv_from(&mut buf).await.unwrap();
+        server.send_to(&buf[..n], peer).await.unwrap();
+    });
+
+    let client = net::UdpSocket::bind("127.0.0.1:0").expect("Failed to bind client socket");
+    client
+        .send_to(b"hello over udp", server_addr)
+        .await
+        .unwrap();
+
+    let mut buf = [0u8; 512];
+    let (n, _) = client.recv_from(&mut buf).await.unwrap();
+    println!(
+        "Echo server replied: {}",
+        String::from_utf8_lossy(&buf[..n])
+    );
+}
+
+async fn async_main_uring() {
+    let path = std::env::temp_dir().join("reactor-executor-uring.sock");
+    let _ = std::fs::remove_file(&path);
+
+    let listener = net::uring::UnixListener::bind(&path).expect("Failed to bind socket path");
+    println!("Daemon listening on {} via io_uring", path.display());
+
+    runtime::spawn_local(async move {
+        let conn = listener.accept().await.unwrap();
+        let (res, msg) = conn.read_to_end(Vec::new()).await;
+        res.unwrap();
+        conn.write_all(msg.to_ascii_uppercase()).await.0.unwrap();
+    });
+
+    let stream = net::uring::UnixStream::connect(&path).expect("Failed to connect to daemon");
+    let msg = b"hello over io_uring".to_vec();
+    stream.write_all(msg).await.0.unwrap();
+    stream.shutdown_write().unwrap();
+
+    let (res, reply) = stream.read_to_end(Vec::new()).await;
+    res.unwrap();
+    println!("Daemon replied: {}", String::from_utf8_lossy(&reply));
 
-    let txt = Http::get("/400/HelloAsyncAwait").await;
-    println!("{txt}");
+    let _ = std::fs::remove_file(&path);
 }
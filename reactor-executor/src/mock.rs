@@ -0,0 +1,232 @@
+//! In-process HTTP server for tests, so they run without the delayserver.
+//!
+//! A [`MockServer`] listens on an ephemeral port of `127.0.0.1` and serves every connection on
+//! a std thread of its own, so slow responses overlap like they do with the delayserver. By
+//! default it speaks the delayserver protocol, i.e. `GET /<delay ms>/<message>` is answered with
+//! `<message>` once the delay passed. Other replies, including failures, can be set per path
+//! via [`MockServer::route`].
+//!
+//! ```ignore
+//! let server = MockServer::start();
+//! server.route("/broken", Reply::close());
+//!
+//! let response = server.client().get("/100/hello").await?;
+//! assert_eq!(response.body(), "hello");
+//! ```
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::http::{Client, Http};
+
+/// What the server does with a request, see [`MockServer::route`].
+#[derive(Debug, Clone)]
+pub struct Reply {
+    status: u16,
+    body: String,
+    delay: Duration,
+    /// Close the connection without responding at all
+    close: bool,
+}
+
+impl Reply {
+    /// `200 OK` with `body`.
+    pub fn ok(body: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            body: body.into(),
+            delay: Duration::ZERO,
+            close: false,
+        }
+    }
+
+    /// Close the connection after reading the request, without sending a response.
+    pub fn close() -> Self {
+        Self {
+            close: true,
+            ..Self::ok("")
+        }
+    }
+
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Wait for `delay` before replying.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    fn write_to(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\n\
+             content-length: {}\r\n\
+             connection: close\r\n\
+             \r\n\
+             {}",
+            self.status,
+            reason(self.status),
+            self.body.len(),
+            self.body
+        )
+    }
+}
+
+/// Shared between the server handle and its threads
+#[derive(Default)]
+struct State {
+    routes: Mutex<HashMap<String, Reply>>,
+    /// Request lines received so far, e.g. `GET /100/hello HTTP/1.1`
+    requests: Mutex<Vec<String>>,
+    stopped: AtomicBool,
+}
+
+/// HTTP server on a std thread, stopped once dropped. See the module docs.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<State>,
+    /// Owns the listener, joined on drop so the port is closed once the server is dropped
+    accepting: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Start listening on an ephemeral port.
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind mock server");
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(State::default());
+
+        let shared = state.clone();
+        let accepting = thread::Builder::new()
+            .name("mock-server".to_string())
+            .spawn(move || accept(listener, shared))
+            .expect("Failed to spawn mock server thread");
+
+        Self {
+            addr,
+            state,
+            accepting: Some(accepting),
+        }
+    }
+
+    /// Reply to requests for `path` with `reply`, rather than per the delayserver protocol.
+    pub fn route(&self, path: &str, reply: Reply) -> &Self {
+        self.state
+            .routes
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), reply);
+        self
+    }
+
+    /// Address to send requests to, as `host:port`.
+    pub fn addr(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// Http client bound to this server, see [`Http::with_addr`].
+    pub fn client(&self) -> Client {
+        Http::with_addr(&self.addr())
+    }
+
+    /// Request lines received so far, in the order they arrived.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::Relaxed);
+        // wake up the accepting thread, so it sees that it is stopped
+        let _ = TcpStream::connect(self.addr);
+        if let Some(accepting) = self.accepting.take() {
+            let _ = accepting.join();
+        }
+    }
+}
+
+fn accept(listener: TcpListener, state: Arc<State>) {
+    for stream in listener.incoming() {
+        if state.stopped.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+
+        let state = state.clone();
+        thread::spawn(move || {
+            // the client going away early is not the server's problem
+            let _ = serve(stream, &state);
+        });
+    }
+}
+
+fn serve(mut stream: TcpStream, state: &State) -> std::io::Result<()> {
+    // read the whole request head first: closing with it unread resets the connection, which
+    // can hit the client before it read the end of the response
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let request_line = request_line.trim_end().to_string();
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+    state.requests.lock().unwrap().push(request_line);
+
+    let reply = state
+        .routes
+        .lock()
+        .unwrap()
+        .get(&path)
+        .cloned()
+        .unwrap_or_else(|| delayserver_reply(&path));
+
+    thread::sleep(reply.delay);
+    if reply.close {
+        return Ok(());
+    }
+    reply.write_to(&mut stream)
+}
+
+/// Reply of the delayserver to a path `/<delay ms>/<message>`.
+fn delayserver_reply(path: &str) -> Reply {
+    let mut parts = path.trim_start_matches('/').splitn(2, '/');
+    let delay = parts
+        .next()
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or_default();
+
+    Reply::ok(parts.next().unwrap_or_default()).delay(delay)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,228 @@
+//! Passing file descriptors between processes over Unix domain sockets.
+//!
+//! This is the building block for graceful upgrades: a running server sends its listening
+//! socket to a freshly started instance as `SCM_RIGHTS` ancillary data, the new instance
+//! rebuilds the listener from the received fd and starts accepting, while the old instance
+//! stops accepting and drains its open connections.
+//!
+//! The delayserver does such an upgrade on SIGHUP: it execs a new instance of itself, hands
+//! over its listener with [`send_fd`], and exits once its in-flight requests are answered.
+//!
+//! See: https://man7.org/linux/man-pages/man7/unix.7.html (SCM_RIGHTS)
+use std::{
+    io, mem,
+    net::TcpListener,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
+    ptr,
+};
+
+/// Clear `FD_CLOEXEC` on `fd`, so it stays open in a program this process execs, e.g. the
+/// socket a new instance receives the listener over. Fds created by std, and those received
+/// with [`recv_fd`], are closed on exec otherwise.
+pub fn set_inheritable(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Send `fd` to the process on the other end of `socket`.
+///
+/// A single byte of regular data is sent along with the fd, since some platforms do not
+/// deliver ancillary data on its own.
+pub fn send_fd(socket: &UnixStream, fd: RawFd) -> io::Result<()> {
+    let mut data = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: data.len(),
+    };
+
+    // buffer for a single control message holding one fd, aligned for `cmsghdr`
+    let mut control = ControlBuffer::new();
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr();
+    msg.msg_controllen = control.len() as _;
+
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>(), fd);
+    }
+
+    let res = unsafe { libc::sendmsg(socket.as_raw_fd(), &msg, 0) };
+
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Receive a fd sent via [`send_fd`] from the process on the other end of `socket`.
+///
+/// The received fd is owned by this process from here on and is closed when dropped.
+pub fn recv_fd(socket: &UnixStream) -> io::Result<OwnedFd> {
+    let mut data = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: data.len(),
+    };
+
+    let mut control = ControlBuffer::new();
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr();
+    msg.msg_controllen = control.len() as _;
+
+    // the received fd is closed once this process execs another program, so it does not leak
+    // into it. To hand it down on purpose, clear the flag with `set_inheritable` before exec.
+    let res = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if res == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    let fd = unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+
+        if cmsg.is_null()
+            || (*cmsg).cmsg_level != libc::SOL_SOCKET
+            || (*cmsg).cmsg_type != libc::SCM_RIGHTS
+        {
+            None
+        } else {
+            let fd = ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>());
+            Some(OwnedFd::from_raw_fd(fd))
+        }
+    };
+
+    // the kernel dropped fds that did not fit into the buffer, so what arrived is not all the
+    // sender meant to pass along. The fd we did get is closed on return.
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "control message truncated",
+        ));
+    }
+
+    fd.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no file descriptor received"))
+}
+
+/// Rebuild a listening socket from a fd received via [`recv_fd`].
+///
+/// The listener is put in non-blocking mode so it can be registered with the reactor.
+pub fn listener_from_fd(fd: OwnedFd) -> io::Result<TcpListener> {
+    let listener = TcpListener::from(fd);
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Control message buffer with room for exactly one fd.
+struct ControlBuffer {
+    // u64 to get the alignment `cmsghdr` needs
+    buf: [u64; 4],
+}
+
+impl ControlBuffer {
+    fn new() -> Self {
+        Self { buf: [0; 4] }
+    }
+
+    fn len(&self) -> usize {
+        let len = unsafe { libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) } as usize;
+        debug_assert!(len <= mem::size_of_val(&self.buf));
+        len
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut libc::c_void {
+        self.buf.as_mut_ptr().cast()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listener_survives_being_passed_over_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (old, new) = UnixStream::pair().unwrap();
+        send_fd(&old, listener.as_raw_fd()).unwrap();
+        // the old instance can close its copy, the new one holds its own reference
+        drop(listener);
+
+        let listener = listener_from_fd(recv_fd(&new).unwrap()).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+
+        // and connections are accepted on the received listener
+        let _client = std::net::TcpStream::connect(addr).unwrap();
+        listener.set_nonblocking(false).unwrap();
+        assert!(listener.accept().is_ok());
+    }
+
+    #[test]
+    fn more_fds_than_expected_is_an_error() {
+        let (old, new) = UnixStream::pair().unwrap();
+        let (spare, _) = UnixStream::pair().unwrap();
+        let fds = [old.as_raw_fd(), new.as_raw_fd(), spare.as_raw_fd()];
+
+        // a single message carrying three fds, where `recv_fd` only has room for one, give or
+        // take the padding of the control message
+        let mut data = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+        let mut control = [0u64; 8];
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(mem::size_of_val(&fds) as u32) } as _;
+
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of_val(&fds) as u32) as _;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<[RawFd; 3]>(), fds);
+            assert!(libc::sendmsg(old.as_raw_fd(), &msg, 0) > 0);
+        }
+
+        let err = recv_fd(&new).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn inheritable_fds_stay_open_on_exec() {
+        let (socket, _) = UnixStream::pair().unwrap();
+        let cloexec =
+            || unsafe { libc::fcntl(socket.as_raw_fd(), libc::F_GETFD) } & libc::FD_CLOEXEC;
+        assert_ne!(cloexec(), 0);
+
+        set_inheritable(socket.as_raw_fd()).unwrap();
+        assert_eq!(cloexec(), 0);
+    }
+}
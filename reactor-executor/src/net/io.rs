@@ -0,0 +1,131 @@
+//! Leaf futures shared by the socket types driven by the reactor.
+use std::{
+    future::Future,
+    io::{self, ErrorKind},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use mio::Interest;
+
+use crate::runtime::{reactor, StoredWaker};
+
+/// Leaf future that retries a non-blocking operation until it stops returning `WouldBlock`.
+pub(super) struct IoFuture<F> {
+    id: usize,
+    /// Events the operation waits for, e.g. readability for a read
+    interest: Interest,
+    op: F,
+    /// Set while our waker is stored with the reactor
+    waker: StoredWaker,
+}
+
+impl<F> IoFuture<F> {
+    pub(super) fn new(id: usize, interest: Interest, op: F) -> Self {
+        Self {
+            id,
+            interest,
+            op,
+            waker: StoredWaker::default(),
+        }
+    }
+}
+
+impl<F, T> Future for IoFuture<F>
+where
+    F: FnMut() -> io::Result<T> + Unpin,
+{
+    type Output = io::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        poll_io(this.id, this.interest, &mut this.waker, cx, &mut this.op)
+    }
+}
+
+/// Try the non-blocking operation `op` on source `id` until it stops returning `WouldBlock`,
+/// leaving the waker of `cx` with the reactor for the events of `interest` in the meantime.
+/// Shared by [`IoFuture`] and the [`AsyncRead`](crate::io::AsyncRead) and
+/// [`AsyncWrite`](crate::io::AsyncWrite) impls of the socket types.
+///
+/// Operations waiting for different events keep their own waker, so a read and a write on the
+/// same socket may be in flight at once, see [`ReactorApi::set_waker_for`](crate::runtime::ReactorApi::set_waker_for).
+pub(super) fn poll_io<T>(
+    id: usize,
+    interest: Interest,
+    waker: &mut StoredWaker,
+    cx: &mut Context<'_>,
+    mut op: impl FnMut() -> io::Result<T>,
+) -> Poll<io::Result<T>> {
+    // Store the waker *before* trying the operation. Events are edge-triggered, so if the
+    // socket became ready between a failed attempt and storing the waker, we would never
+    // be notified.
+    reactor().update_waker_for(cx, id, interest, waker);
+
+    loop {
+        match op() {
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Poll::Pending,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            res => {
+                reactor().clear_waker_for(id, interest);
+                waker.clear();
+                return Poll::Ready(res);
+            }
+        }
+    }
+}
+
+impl<F> Drop for IoFuture<F> {
+    fn drop(&mut self) {
+        // cancelled while waiting, the socket itself stays registered for the next operation
+        if self.waker.is_stored() {
+            reactor().clear_waker_for(self.id, self.interest);
+        }
+    }
+}
+
+/// Leaf future that resolves once the source had one of the events of `interest`, without
+/// doing any IO itself.
+///
+/// Readiness may be spurious, so an operation that follows can still return `WouldBlock`.
+pub(super) struct Readiness {
+    id: usize,
+    interest: Interest,
+    /// Set once our waker is stored with the reactor
+    waiting: bool,
+}
+
+impl Readiness {
+    pub(super) fn new(id: usize, interest: Interest) -> Self {
+        Self {
+            id,
+            interest,
+            waiting: false,
+        }
+    }
+}
+
+impl Future for Readiness {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.waiting {
+            // only the reactor wakes us, be it for an event or one it replays
+            reactor().clear_waker_for(self.id, self.interest);
+            self.waiting = false;
+            return Poll::Ready(());
+        }
+
+        reactor().set_waker_for(cx, self.id, self.interest);
+        self.waiting = true;
+        Poll::Pending
+    }
+}
+
+impl Drop for Readiness {
+    fn drop(&mut self) {
+        if self.waiting {
+            reactor().clear_waker_for(self.id, self.interest);
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//! Networking types and helpers for the runtime.
+
+pub mod fd;
+mod io;
+pub mod tcp;
+pub mod udp;
+pub mod unix;
+pub mod uring;
+
+pub use tcp::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, TcpListener, TcpStream, WriteHalf};
+pub use udp::UdpSocket;
+pub use unix::{UnixListener, UnixStream};
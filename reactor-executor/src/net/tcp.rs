@@ -0,0 +1,590 @@
+//! TCP sockets driven by the reactor, for servers such as the `delayserver`.
+//!
+//! Works the same as [`unix`](super::unix): sockets are registered with the reactor when they
+//! are created and deregistered when dropped, and each operation is a leaf future that waits
+//! on the reactor whenever the syscall would block.
+//!
+//! NOTE: a socket has a slot for a waker in the reactor per direction, so a read and a write
+//! may be in flight at once, but only one of each on a given socket.
+use std::{
+    future::Future,
+    io::{self, ErrorKind, Read, Write},
+    mem::ManuallyDrop,
+    net::{Shutdown, SocketAddr, ToSocketAddrs},
+    os::fd::{AsRawFd, RawFd},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use mio::{net, Interest};
+
+use super::io::{poll_io, IoFuture};
+use crate::{
+    io::{AsyncRead, AsyncWrite},
+    runtime::{reactor, Priority, StoredWaker},
+    tls::{self, SniError},
+};
+
+/// Listens for TCP connections.
+pub struct TcpListener {
+    /// Handed to the reactor on drop, which closes it once deregistered
+    inner: ManuallyDrop<net::TcpListener>,
+    /// id of the source with the reactor
+    id: usize,
+}
+
+impl TcpListener {
+    /// Bind to `addr`, e.g. `127.0.0.1:8080`. Port 0 binds to any free port, see
+    /// [`TcpListener::local_addr`].
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::bind_with_priority(addr, Priority::Normal)
+    }
+
+    /// Same as [`TcpListener::bind`], with a priority for readiness of the listener, e.g.
+    /// [`Priority::High`] to keep accepting connections promptly under load.
+    pub fn bind_with_priority(addr: impl ToSocketAddrs, priority: Priority) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::from_std_with_priority(listener, priority))
+    }
+
+    /// Register a listener bound elsewhere with the reactor, e.g. one received from the
+    /// previous instance of a server, see [`fd::listener_from_fd`](super::fd::listener_from_fd).
+    /// It must be in non-blocking mode already.
+    pub fn from_std(listener: std::net::TcpListener) -> Self {
+        Self::from_std_with_priority(listener, Priority::Normal)
+    }
+
+    /// Same as [`TcpListener::from_std`], with a priority for readiness of the listener.
+    pub fn from_std_with_priority(listener: std::net::TcpListener, priority: Priority) -> Self {
+        let mut inner = net::TcpListener::from_std(listener);
+        let id = reactor().next_id();
+        reactor().register_with_priority(&mut inner, Interest::READABLE, id, priority);
+
+        Self {
+            inner: ManuallyDrop::new(inner),
+            id,
+        }
+    }
+
+    /// Returns a future that yields the next incoming connection.
+    pub fn accept(&mut self) -> impl Future<Output = io::Result<(TcpStream, SocketAddr)>> + '_ {
+        IoFuture::new(self.id, Interest::READABLE, move || {
+            let (stream, addr) = self.inner.accept()?;
+            Ok((TcpStream::from_mio(stream), addr))
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl AsRawFd for TcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        // SAFETY: `inner` is not used again after drop
+        let inner = unsafe { ManuallyDrop::take(&mut self.inner) };
+        reactor().deregister(Box::new(inner), self.id);
+    }
+}
+
+/// Connection accepted by a [`TcpListener`].
+pub struct TcpStream {
+    /// Handed to the reactor on drop, which closes it once deregistered
+    inner: ManuallyDrop<net::TcpStream>,
+    /// id of the source with the reactor
+    id: usize,
+    /// Waker left with the reactor by `poll_read`
+    read_waker: StoredWaker,
+    /// Waker left with the reactor by `poll_write`
+    write_waker: StoredWaker,
+}
+
+impl TcpStream {
+    fn from_mio(mut inner: net::TcpStream) -> Self {
+        let id = reactor().next_id();
+        reactor().register(&mut inner, Interest::READABLE | Interest::WRITABLE, id);
+
+        Self {
+            inner: ManuallyDrop::new(inner),
+            id,
+            read_waker: StoredWaker::default(),
+            write_waker: StoredWaker::default(),
+        }
+    }
+
+    /// Read into `buf`, resolving to the number of bytes read. 0 means the peer closed.
+    pub fn read<'a>(
+        &'a mut self,
+        buf: &'a mut [u8],
+    ) -> impl Future<Output = io::Result<usize>> + 'a {
+        IoFuture::new(self.id, Interest::READABLE, move || self.inner.read(buf))
+    }
+
+    /// Write from `buf`, resolving to the number of bytes written.
+    pub fn write<'a>(&'a mut self, buf: &'a [u8]) -> impl Future<Output = io::Result<usize>> + 'a {
+        IoFuture::new(self.id, Interest::WRITABLE, move || self.inner.write(buf))
+    }
+
+    /// Write all of `buf`, waiting for the socket to become writable as needed.
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    /// Returns a future that resolves to the hostname the client asks for in its TLS
+    /// ClientHello, once all of the hello arrived, see [`tls::peek_sni`]. Nothing is consumed,
+    /// the handshake still starts at the ClientHello.
+    pub fn peek_sni(&mut self) -> impl Future<Output = io::Result<Option<String>>> + '_ {
+        IoFuture::new(self.id, Interest::READABLE, move || {
+            match tls::peek_sni(&self.inner)? {
+                Ok(hostname) => Ok(hostname),
+                // the rest of the hello wakes us up once it arrives
+                Err(SniError::Incomplete) => Err(ErrorKind::WouldBlock.into()),
+                Err(e) => Err(io::Error::new(ErrorKind::InvalidData, format!("{e:?}"))),
+            }
+        })
+    }
+
+    /// Shut down the write half, letting the peer know we are done sending.
+    pub fn shutdown_write(&self) -> io::Result<()> {
+        self.inner.shutdown(Shutdown::Write)
+    }
+
+    /// Split the stream into a read and a write half, to read and write at the same time,
+    /// e.g. from two futures joined together. Each half leaves its own waker with the reactor.
+    pub fn split(&mut self) -> (ReadHalf<'_>, WriteHalf<'_>) {
+        let stream = &*self;
+        (
+            ReadHalf {
+                stream,
+                waker: StoredWaker::default(),
+            },
+            WriteHalf {
+                stream,
+                waker: StoredWaker::default(),
+            },
+        )
+    }
+
+    /// Split the stream into a read and a write half owning it together, to read and write
+    /// from separate tasks, e.g. a chat server forwarding messages to a client while reading
+    /// the client's. The stream stays registered with the reactor, and open, until both
+    /// halves are dropped.
+    pub fn into_split(self) -> (OwnedReadHalf, OwnedWriteHalf) {
+        let stream = Arc::new(self);
+        (
+            OwnedReadHalf {
+                stream: stream.clone(),
+                waker: StoredWaker::default(),
+            },
+            OwnedWriteHalf {
+                stream,
+                waker: StoredWaker::default(),
+            },
+        )
+    }
+
+    /// Read through a shared reference, leaving `waker` of the half reading with the reactor.
+    fn poll_read_shared(
+        &self,
+        waker: &mut StoredWaker,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut inner = &*self.inner;
+        poll_io(self.id, Interest::READABLE, waker, cx, || inner.read(buf))
+    }
+
+    /// Write through a shared reference, leaving `waker` of the half writing with the reactor.
+    fn poll_write_shared(
+        &self,
+        waker: &mut StoredWaker,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut inner = &*self.inner;
+        poll_io(self.id, Interest::WRITABLE, waker, cx, || inner.write(buf))
+    }
+
+    /// Take the waker a half left with the reactor when the half is dropped.
+    fn clear_shared_waker(&self, waker: &StoredWaker, interest: Interest) {
+        if waker.is_stored() {
+            reactor().clear_waker_for(self.id, interest);
+        }
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        poll_io(
+            this.id,
+            Interest::READABLE,
+            &mut this.read_waker,
+            cx,
+            || this.inner.read(buf),
+        )
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        poll_io(
+            this.id,
+            Interest::WRITABLE,
+            &mut this.write_waker,
+            cx,
+            || this.inner.write(buf),
+        )
+    }
+
+    /// Nothing to do, writes go straight to the socket.
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Read half of a [`TcpStream`], see [`TcpStream::split`].
+pub struct ReadHalf<'a> {
+    stream: &'a TcpStream,
+    waker: StoredWaker,
+}
+
+/// Write half of a [`TcpStream`], see [`TcpStream::split`].
+pub struct WriteHalf<'a> {
+    stream: &'a TcpStream,
+    waker: StoredWaker,
+}
+
+impl AsyncRead for ReadHalf<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.stream.poll_read_shared(&mut this.waker, cx, buf)
+    }
+}
+
+impl AsyncWrite for WriteHalf<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.stream.poll_write_shared(&mut this.waker, cx, buf)
+    }
+
+    /// Nothing to do, writes go straight to the socket.
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// A half dropped while waiting takes its waker with it, the stream itself stays registered.
+impl Drop for ReadHalf<'_> {
+    fn drop(&mut self) {
+        self.stream
+            .clear_shared_waker(&self.waker, Interest::READABLE);
+    }
+}
+
+impl Drop for WriteHalf<'_> {
+    fn drop(&mut self) {
+        self.stream
+            .clear_shared_waker(&self.waker, Interest::WRITABLE);
+    }
+}
+
+/// Read half of a [`TcpStream`] that can be moved to another task, see
+/// [`TcpStream::into_split`].
+pub struct OwnedReadHalf {
+    stream: Arc<TcpStream>,
+    waker: StoredWaker,
+}
+
+/// Write half of a [`TcpStream`] that can be moved to another task, see
+/// [`TcpStream::into_split`].
+pub struct OwnedWriteHalf {
+    stream: Arc<TcpStream>,
+    waker: StoredWaker,
+}
+
+impl OwnedReadHalf {
+    /// Read into `buf`, resolving to the number of bytes read. 0 means the peer closed.
+    pub fn read<'a>(
+        &'a mut self,
+        buf: &'a mut [u8],
+    ) -> impl Future<Output = io::Result<usize>> + 'a {
+        let mut inner = &*self.stream.inner;
+        IoFuture::new(self.stream.id, Interest::READABLE, move || inner.read(buf))
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+}
+
+impl OwnedWriteHalf {
+    /// Write from `buf`, resolving to the number of bytes written.
+    pub fn write<'a>(&'a mut self, buf: &'a [u8]) -> impl Future<Output = io::Result<usize>> + 'a {
+        let mut inner = &*self.stream.inner;
+        IoFuture::new(self.stream.id, Interest::WRITABLE, move || inner.write(buf))
+    }
+
+    /// Write all of `buf`, waiting for the socket to become writable as needed.
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    /// Shut down the write half, letting the peer know we are done sending. The read half
+    /// keeps receiving.
+    pub fn shutdown_write(&self) -> io::Result<()> {
+        self.stream.shutdown_write()
+    }
+}
+
+impl AsyncRead for OwnedReadHalf {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.stream.poll_read_shared(&mut this.waker, cx, buf)
+    }
+}
+
+impl AsyncWrite for OwnedWriteHalf {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.stream.poll_write_shared(&mut this.waker, cx, buf)
+    }
+
+    /// Nothing to do, writes go straight to the socket.
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Same as the borrowed halves: the stream is deregistered once the last half is dropped.
+impl Drop for OwnedReadHalf {
+    fn drop(&mut self) {
+        self.stream
+            .clear_shared_waker(&self.waker, Interest::READABLE);
+    }
+}
+
+impl Drop for OwnedWriteHalf {
+    fn drop(&mut self) {
+        self.stream
+            .clear_shared_waker(&self.waker, Interest::WRITABLE);
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        // SAFETY: `inner` is not used again after drop
+        let inner = unsafe { ManuallyDrop::take(&mut self.inner) };
+        reactor().deregister(Box::new(inner), self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+        runtime::{self, spawn, sync::mpsc},
+    };
+
+    #[test]
+    fn accepts_and_echoes() {
+        let mut executor = runtime::init_for_tests();
+        let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // a blocking client, so the executor only drives the server side
+        let client = thread::spawn(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream.write_all(b"hello server").unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            reply
+        });
+
+        executor.block_on(async move {
+            spawn(async move {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 64];
+                let mut msg = Vec::new();
+                loop {
+                    match conn.read(&mut buf).await.unwrap() {
+                        0 => break,
+                        n => msg.extend_from_slice(&buf[..n]),
+                    }
+                }
+                conn.write_all(&msg.to_ascii_uppercase()).await.unwrap();
+            });
+        });
+
+        assert_eq!(client.join().unwrap(), "HELLO SERVER");
+    }
+
+    #[test]
+    fn lines_are_echoed_through_buffered_adapters() {
+        let mut executor = runtime::init_for_tests();
+        let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream.write_all(b"first\nsec").unwrap();
+            thread::sleep(std::time::Duration::from_millis(20));
+            stream.write_all(b"ond\n").unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            reply
+        });
+
+        executor.block_on(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            let mut conn = BufReader::new(BufWriter::new(conn));
+            let mut line = String::new();
+            while conn.read_line(&mut line).await.unwrap() > 0 {
+                conn.write_all(line.to_uppercase().as_bytes())
+                    .await
+                    .unwrap();
+                line.clear();
+            }
+            conn.flush().await.unwrap();
+        });
+
+        assert_eq!(client.join().unwrap(), "FIRST\nSECOND\n");
+    }
+
+    #[test]
+    fn split_halves_read_and_write_at_once() {
+        // more than the socket buffers take, so the write waits on the client reading it
+        const LEN: usize = 4 << 20;
+
+        let mut executor = runtime::init_for_tests();
+        let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // only talks once it received everything, while the server is waiting to read
+        let client = thread::spawn(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            let mut received = vec![0u8; LEN];
+            stream.read_exact(&mut received).unwrap();
+            stream.write_all(b"got it").unwrap();
+            received.iter().all(|byte| *byte == 7)
+        });
+
+        executor.block_on(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = conn.split();
+            let payload = vec![7u8; LEN];
+            let mut reply = [0u8; 16];
+
+            let (read, written) = crate::join!(reader.read(&mut reply), writer.write_all(&payload));
+            written.unwrap();
+            assert_eq!(&reply[..read.unwrap()], b"got it");
+        });
+
+        assert!(client.join().unwrap());
+    }
+
+    #[test]
+    fn owned_halves_are_used_from_separate_tasks() {
+        let mut executor = runtime::init_for_tests();
+        let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream.write_all(b"hello ").unwrap();
+            thread::sleep(std::time::Duration::from_millis(20));
+            stream.write_all(b"halves").unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            reply
+        });
+
+        executor.block_on(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = conn.into_split();
+            let (tx, mut rx) = mpsc::channel::<Vec<u8>>();
+
+            spawn(async move {
+                let mut buf = [0u8; 64];
+                loop {
+                    match reader.read(&mut buf).await.unwrap() {
+                        0 => break,
+                        n => tx.send(buf[..n].to_vec()).unwrap(),
+                    }
+                }
+            });
+            // the stream closes once the reader is done and this drops the write half
+            spawn(async move {
+                while let Some(msg) = rx.recv().await {
+                    writer.write_all(&msg.to_ascii_uppercase()).await.unwrap();
+                }
+            });
+        });
+
+        assert_eq!(client.join().unwrap(), "HELLO HALVES");
+    }
+}
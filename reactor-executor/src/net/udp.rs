@@ -0,0 +1,116 @@
+//! UDP sockets driven by the reactor.
+//!
+//! Same model as [`net::unix`](super::unix): the socket is registered with the reactor for
+//! both READABLE and WRITABLE events when it is bound, and deregistered when dropped. Each
+//! operation is a leaf future that retries the syscall whenever the socket becomes ready.
+//!
+//! NOTE: a socket has a slot for a waker in the reactor per direction, so a send and a receive
+//! may be in flight at once, but only one of each on a given socket.
+use std::{
+    future::Future,
+    io,
+    mem::ManuallyDrop,
+    net::{SocketAddr, ToSocketAddrs},
+};
+
+use mio::{net, Interest};
+
+use super::io::IoFuture;
+use crate::runtime::reactor;
+
+/// Sends and receives datagrams.
+pub struct UdpSocket {
+    /// Handed to the reactor on drop, which closes it once deregistered
+    inner: ManuallyDrop<net::UdpSocket>,
+    /// id of the source with the reactor
+    id: usize,
+}
+
+impl UdpSocket {
+    /// Bind to `addr`, e.g. `127.0.0.1:0` for any free port.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to bind"))?;
+
+        let mut inner = net::UdpSocket::bind(addr)?;
+        let id = reactor().next_id();
+        reactor().register(&mut inner, Interest::READABLE | Interest::WRITABLE, id);
+
+        Ok(Self {
+            inner: ManuallyDrop::new(inner),
+            id,
+        })
+    }
+
+    /// Send `buf` as a single datagram to `target`, resolving to the number of bytes sent.
+    pub fn send_to<'a>(
+        &'a self,
+        buf: &'a [u8],
+        target: SocketAddr,
+    ) -> impl Future<Output = io::Result<usize>> + 'a {
+        IoFuture::new(self.id, Interest::WRITABLE, move || {
+            self.inner.send_to(buf, target)
+        })
+    }
+
+    /// Receive a single datagram into `buf`, resolving to its length and the sender.
+    ///
+    /// If `buf` is too small for the datagram, the rest of it is discarded.
+    pub fn recv_from<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+    ) -> impl Future<Output = io::Result<(usize, SocketAddr)>> + 'a {
+        IoFuture::new(self.id, Interest::READABLE, move || {
+            self.inner.recv_from(buf)
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        // SAFETY: `inner` is not used again after drop
+        let inner = unsafe { ManuallyDrop::take(&mut self.inner) };
+        reactor().deregister(Box::new(inner), self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{self, spawn};
+
+    #[test]
+    fn echo_datagrams() {
+        let mut executor = runtime::init_for_tests();
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        executor.block_on(async move {
+            spawn(async move {
+                let mut buf = [0u8; 64];
+                for _ in 0..2 {
+                    let (n, peer) = server.recv_from(&mut buf).await.unwrap();
+                    let reply = buf[..n].to_ascii_uppercase();
+                    server.send_to(&reply, peer).await.unwrap();
+                }
+            });
+
+            let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let mut buf = [0u8; 64];
+
+            for msg in [&b"ping"[..], b"pong"] {
+                client.send_to(msg, server_addr).await.unwrap();
+                let (n, from) = client.recv_from(&mut buf).await.unwrap();
+
+                assert_eq!(from, server_addr);
+                assert_eq!(&buf[..n], msg.to_ascii_uppercase());
+            }
+        });
+    }
+}
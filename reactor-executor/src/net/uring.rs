@@ -0,0 +1,158 @@
+//! Unix domain sockets driven by io_uring, see [`runtime::uring`](crate::runtime::uring).
+//!
+//! Same sockets as in [`net::unix`](super::unix), but operations are completion based:
+//! buffers are handed to an operation by value and returned along with its result, since
+//! the kernel uses them while the operation is in flight.
+//!
+//! Sockets stay in blocking mode, io_uring takes care of not blocking the executor thread.
+use std::{
+    io::{self, ErrorKind},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::net,
+    },
+    path::Path,
+};
+
+use crate::runtime::uring;
+
+/// Listens for connections on a socket path.
+pub struct UnixListener {
+    inner: net::UnixListener,
+}
+
+impl UnixListener {
+    /// Bind to `path`. The socket file must not exist yet.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: net::UnixListener::bind(path)?,
+        })
+    }
+
+    /// Wait for the next incoming connection.
+    pub async fn accept(&self) -> io::Result<UnixStream> {
+        let fd = uring::accept(self.inner.as_raw_fd()).await?;
+        Ok(UnixStream {
+            inner: net::UnixStream::from(fd),
+        })
+    }
+}
+
+impl AsRawFd for UnixListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+/// Connection over a Unix domain socket.
+pub struct UnixStream {
+    inner: net::UnixStream,
+}
+
+impl UnixStream {
+    /// Connect to the socket at `path`.
+    ///
+    /// Connecting to a Unix socket completes immediately unless the listener's backlog is
+    /// full, so as with [`net::unix`](super::unix) this is not an operation of its own.
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner: net::UnixStream::connect(path)?,
+        })
+    }
+
+    /// Read into the spare capacity of `buf`, resolving to the number of bytes read and `buf`.
+    /// 0 means the peer closed.
+    pub async fn read(&self, buf: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+        uring::read(self.inner.as_raw_fd(), buf).await
+    }
+
+    /// Write from `buf`, resolving to the number of bytes written and `buf`.
+    pub async fn write(&self, buf: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+        uring::write(self.inner.as_raw_fd(), buf).await
+    }
+
+    /// Write all of `buf`, resolving to `buf` emptied once done, so it can be reused.
+    pub async fn write_all(&self, mut buf: Vec<u8>) -> (io::Result<()>, Vec<u8>) {
+        while !buf.is_empty() {
+            let (res, written) = self.write(buf).await;
+            buf = written;
+
+            match res {
+                Ok(0) => return (Err(ErrorKind::WriteZero.into()), buf),
+                // operations always start at the front of the buffer, so move the rest there
+                Ok(n) => drop(buf.drain(..n)),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return (Err(e), buf),
+            }
+        }
+
+        (Ok(()), buf)
+    }
+
+    /// Read until the peer closes the connection, appending to `buf`.
+    pub async fn read_to_end(&self, mut buf: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+        let start = buf.len();
+
+        loop {
+            buf.reserve(4096);
+            let (res, read) = self.read(buf).await;
+            buf = read;
+
+            match res {
+                Ok(0) => return (Ok(buf.len() - start), buf),
+                Ok(_) => continue,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return (Err(e), buf),
+            }
+        }
+    }
+
+    /// Shut down the write half, letting the peer know we are done sending.
+    pub fn shutdown_write(&self) -> io::Result<()> {
+        self.inner.shutdown(std::net::Shutdown::Write)
+    }
+}
+
+impl AsRawFd for UnixStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{self, spawn_local};
+
+    #[test]
+    fn echo_over_socket_path() {
+        let mut executor = runtime::init_for_tests();
+        if !uring::is_started() {
+            return;
+        }
+
+        let path = std::env::temp_dir().join(format!("uring-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let client_path = path.clone();
+
+        executor.block_on(async move {
+            spawn_local(async move {
+                let conn = listener.accept().await.unwrap();
+                let (res, msg) = conn.read_to_end(Vec::new()).await;
+                res.unwrap();
+                conn.write_all(msg.to_ascii_uppercase()).await.0.unwrap();
+            });
+
+            let stream = UnixStream::connect(&client_path).unwrap();
+            stream.write_all(b"hello ring".to_vec()).await.0.unwrap();
+            stream.shutdown_write().unwrap();
+
+            let (res, reply) = stream.read_to_end(Vec::new()).await;
+            res.unwrap();
+            assert_eq!(reply, b"HELLO RING");
+        });
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
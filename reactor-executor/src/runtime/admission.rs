@@ -0,0 +1,211 @@
+//! Admission control: a limit on the tasks of an executor, and what spawning does once it is
+//! reached, see [`Executor::with_task_limit`](super::Executor::with_task_limit).
+//!
+//! Without a limit, a server spawning a task per connection takes on every connection that
+//! comes in, however far behind it is. With one, [`spawn`](super::spawn) follows the executor's
+//! [`Backpressure`] policy once the executor is full, [`try_spawn`] hands the task back, and
+//! [`reserve`] lets the spawning task wait for room, e.g. before accepting the next connection.
+//!
+//! ```ignore
+//! loop {
+//!     let permit = runtime::reserve().await;
+//!     let (conn, _) = listener.accept().await?;
+//!     permit.spawn(serve(conn));
+//! }
+//! ```
+//!
+//! Children of a [`scope`](super::scope) are not held to the limit, their scope waits for them
+//! either way.
+use std::{
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use super::executor;
+
+/// What [`spawn`](super::spawn) does once the executor is at its task limit, see
+/// [`Executor::with_task_limit`](super::Executor::with_task_limit).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Panic, the limit is not expected to be reached
+    #[default]
+    Panic,
+    /// Drop the task, logging a warning and counting it in
+    /// [`ExecutorMetrics::rejected`](super::ExecutorMetrics::rejected)
+    Reject,
+    /// Hold the task back, unpolled, and spawn it once a task finished. Spawners that keep on
+    /// spawning should wait for room via [`reserve`] instead, so the tasks held back do not
+    /// pile up either.
+    Wait,
+}
+
+/// Error returned by [`try_spawn`] if the executor is at its task limit. Holds the future that
+/// was not spawned.
+#[derive(PartialEq, Eq)]
+pub struct SpawnError<F>(pub F);
+
+impl<F> fmt::Debug for SpawnError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SpawnError(..)")
+    }
+}
+
+impl<F> fmt::Display for SpawnError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("executor is at its task limit")
+    }
+}
+
+impl<F> std::error::Error for SpawnError<F> {}
+
+/// Same as [`spawn`](super::spawn), but hands the future back rather than follow the
+/// executor's [`Backpressure`] policy if the executor is at its task limit.
+pub fn try_spawn<F>(future: F) -> Result<(), SpawnError<F>>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    if !executor::has_room() {
+        return Err(SpawnError(future));
+    }
+
+    super::spawn(future);
+    Ok(())
+}
+
+/// Returns a future that resolves to a [`Permit`] once the executor of this thread has room
+/// for another task, holding that room until the permit is used or dropped.
+pub fn reserve() -> Reserve {
+    Reserve {
+        _thread_bound: PhantomData,
+    }
+}
+
+/// Future returned by [`reserve`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Reserve {
+    // the room is reserved with the executor of the thread polling it
+    _thread_bound: PhantomData<Rc<()>>,
+}
+
+impl Future for Reserve {
+    type Output = Permit;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit> {
+        if executor::reserve_slot(cx.waker()) {
+            Poll::Ready(Permit {
+                _thread_bound: PhantomData,
+            })
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Room for a task on the executor of this thread, see [`reserve`]. Dropping the permit
+/// without spawning gives the room back.
+#[must_use = "the room is given back once the permit is dropped"]
+pub struct Permit {
+    _thread_bound: PhantomData<Rc<()>>,
+}
+
+impl Permit {
+    /// Same as [`spawn`](super::spawn), in the room reserved by the permit.
+    pub fn spawn<F>(self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        // spawned before the permit is dropped, so the room can not be taken in between
+        executor::spawn_reserved(Box::pin(future));
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        executor::release_slot();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use super::*;
+    use crate::runtime::{spawn, yield_now, Executor};
+
+    #[test]
+    fn try_spawn_hands_the_task_back_at_the_limit() {
+        let mut executor = Executor::with_task_limit(2, Backpressure::Reject);
+
+        executor.block_on(async {
+            // the future handed to `block_on` takes the first of the two
+            assert!(try_spawn(yield_now()).is_ok());
+            assert!(try_spawn(async {}).is_err());
+
+            spawn(async {});
+        });
+
+        assert_eq!(executor.metrics().rejected, 1);
+        assert_eq!(executor.metrics().completed, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "at its limit of 1 tasks")]
+    fn spawning_beyond_the_limit_panics_by_default() {
+        Executor::with_task_limit(1, Backpressure::Panic).block_on(async {
+            spawn(async {});
+        });
+    }
+
+    #[test]
+    fn tasks_held_back_are_spawned_in_order_as_room_frees_up() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let seen = order.clone();
+
+        Executor::with_task_limit(2, Backpressure::Wait).block_on(async move {
+            for i in 0..3 {
+                let order = seen.clone();
+                spawn(async move {
+                    yield_now().await;
+                    order.lock().unwrap().push(i);
+                });
+            }
+        });
+
+        assert_eq!(*order.lock().unwrap(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn reserve_waits_for_room() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (seen_running, seen_most) = (running.clone(), most.clone());
+
+        Executor::with_task_limit(3, Backpressure::Panic).block_on(async move {
+            for _ in 0..6 {
+                let permit = reserve().await;
+                let (running, most) = (seen_running.clone(), seen_most.clone());
+                permit.spawn(async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    yield_now().await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+
+            // a permit that is not used gives its room back
+            drop(reserve().await);
+            drop(reserve().await);
+        });
+
+        // room for two besides the spawning task, which never had to panic
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+        assert_eq!(running.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,193 @@
+//! Thread pool for running blocking or CPU heavy work off the executor thread.
+//!
+//! The executor runs every task on a single thread, so a task that blocks stops all other
+//! tasks from making progress. [`spawn_blocking`] instead hands the work to a small pool of
+//! dedicated threads and returns a future that is woken up once the work is done.
+use std::{
+    collections::VecDeque,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, OnceLock,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use super::{next_resource_id, wait_on, Resource};
+
+/// Number of threads in the blocking pool, unless configured otherwise via
+/// [`Builder::blocking_threads`](super::Builder::blocking_threads)
+const DEFAULT_POOL_SIZE: usize = 4;
+
+static POOL_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_POOL_SIZE);
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Started on first use of [`spawn_blocking`]
+static POOL: OnceLock<Pool> = OnceLock::new();
+
+struct Pool {
+    jobs: Mutex<VecDeque<Job>>,
+    /// Signalled whenever a job is pushed onto `jobs`
+    available: Condvar,
+}
+
+impl Pool {
+    fn start() -> Self {
+        for i in 0..POOL_SIZE.load(Ordering::Relaxed) {
+            thread::Builder::new()
+                .name(format!("blocking-{i}"))
+                .spawn(worker)
+                .expect("Failed to spawn blocking pool thread");
+        }
+
+        Self {
+            jobs: Mutex::new(VecDeque::new()),
+            available: Condvar::new(),
+        }
+    }
+
+    fn push(&self, job: Job) {
+        self.jobs.lock().unwrap().push_back(job);
+        self.available.notify_one();
+    }
+}
+
+/// Set the number of threads the pool starts with. Returns false, changing nothing, if the
+/// pool was started already.
+pub(super) fn set_pool_size(threads: usize) -> bool {
+    assert!(threads > 0, "blocking pool needs at least one thread");
+    if POOL.get().is_some() {
+        return false;
+    }
+
+    POOL_SIZE.store(threads, Ordering::Relaxed);
+    true
+}
+
+/// Main loop of every pool thread: wait for a job, run it, repeat.
+fn worker() {
+    let pool = POOL.get_or_init(Pool::start);
+
+    loop {
+        let job = {
+            let mut jobs = pool.jobs.lock().unwrap();
+            loop {
+                match jobs.pop_front() {
+                    Some(job) => break job,
+                    None => jobs = pool.available.wait(jobs).unwrap(),
+                }
+            }
+        };
+
+        job();
+    }
+}
+
+/// Run `f` on the blocking pool, returning a future that resolves to its result.
+///
+/// If `f` panics, the panic is resumed in the task awaiting the returned future.
+pub fn spawn_blocking<F, T>(f: F) -> BlockingTask<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+
+    let job_shared = shared.clone();
+    let job = Box::new(move || {
+        // catch the panic so the pool thread survives it
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+
+        let waker = {
+            let mut shared = job_shared.lock().unwrap();
+            shared.result = Some(result);
+            shared.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    });
+
+    POOL.get_or_init(Pool::start).push(job);
+
+    BlockingTask {
+        shared,
+        id: next_resource_id(),
+    }
+}
+
+struct Shared<T> {
+    result: Option<thread::Result<T>>,
+    /// Waker of the task awaiting the result
+    waker: Option<Waker>,
+}
+
+/// Future returned by [`spawn_blocking`].
+pub struct BlockingTask<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+    /// Identifies this job when reporting on waiting tasks
+    id: usize,
+}
+
+impl<T> Future for BlockingTask<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.shared.lock().unwrap();
+
+        match shared.result.take() {
+            Some(Ok(value)) => Poll::Ready(value),
+            Some(Err(panic)) => panic::resume_unwind(panic),
+            None => {
+                // IMPORTANT: always store the most recent waker
+                shared.waker = Some(cx.waker().clone());
+
+                wait_on(Resource {
+                    kind: "spawn_blocking",
+                    id: self.id,
+                    holder: None,
+                    external: true,
+                });
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::runtime::Executor;
+
+    #[test]
+    fn result_is_returned_to_task() {
+        Executor::new().block_on(async {
+            let value = spawn_blocking(|| {
+                thread::sleep(Duration::from_millis(20));
+                thread::current().name().map(str::to_string)
+            })
+            .await;
+
+            assert!(value.unwrap().starts_with("blocking-"));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn panic_is_resumed_in_task() {
+        Executor::new().block_on(async {
+            spawn_blocking(|| panic!("boom")).await;
+        });
+    }
+}
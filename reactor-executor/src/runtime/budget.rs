@@ -0,0 +1,23 @@
+//! Limits on how much work a leaf future does in a single poll.
+//!
+//! A leaf future that keeps reading until its source would block holds on to the executor's
+//! thread for as long as a fast peer keeps sending. Once out of budget, it wakes itself and
+//! returns `Pending` instead, so other tasks get to run in between, see
+//! [`wake_after_others`](super::wake_after_others).
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Bytes read per poll by default, a few reads worth
+const DEFAULT_READ_BUDGET: usize = 64 * 1024;
+
+static READ_BUDGET: AtomicUsize = AtomicUsize::new(DEFAULT_READ_BUDGET);
+
+/// Set how many bytes a future reading a response may read in a single poll, for the whole
+/// process. A read is never cut short, so a poll may go over by up to one read buffer.
+/// `usize::MAX` lifts the limit.
+pub fn set_read_budget(bytes: usize) {
+    READ_BUDGET.store(bytes, Ordering::Relaxed);
+}
+
+pub fn read_budget() -> usize {
+    READ_BUDGET.load(Ordering::Relaxed)
+}
@@ -0,0 +1,447 @@
+//! Configuration of the runtime in one place.
+//!
+//! [`init`](super::init) and friends start the runtime with the defaults, apart from the one
+//! knob each of them takes. A [`Builder`] takes all of them: the reactor's shards and their
+//! event buffers, the I/O backend, diagnostics, budgets, the blocking pool, and how each
+//! executor queues and waits for its tasks. Knobs that are not set keep their defaults.
+//!
+//! With [`Builder::thread_per_core`], there is an executor per CPU core instead, pinned to it
+//! and with a shard of its own, see [`cores`](super::cores).
+//!
+//! With [`Builder::single_threaded`], the reactor gets no thread at all: the executor runs its
+//! event loop whenever it would otherwise park.
+//!
+//! ```ignore
+//! let builder = runtime::Builder::new()
+//!     .shards(2)
+//!     .event_capacity(1024)
+//!     .park_strategy(ParkStrategy::Adaptive(1000))
+//!     .executor_threads(4);
+//!
+//! let mut executor = builder.build();
+//! let workers = builder.spawn_executors(|i| serve(i));
+//! executor.block_on(async_main());
+//! ```
+use std::{
+    future::Future,
+    sync::{mpsc, Arc, Barrier},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use super::{
+    blocking, budget, cores, executor, log, reactor, uring, Backend, Backpressure, Executor,
+    Handle, LogConfig, ParkStrategy, ReadyQueueKind, Routing,
+};
+
+/// How often executors validate runtime invariants in debug builds, see `self_check`
+const SELF_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Options to start the runtime with, see the module docs.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    shards: usize,
+    routing: Routing,
+    event_capacity: usize,
+    backend: Backend,
+    log: Option<LogConfig>,
+    read_budget: Option<usize>,
+    blocking_threads: Option<usize>,
+    ready_queue: Option<ReadyQueueKind>,
+    park_strategy: ParkStrategy,
+    /// Most tasks per executor, see `Executor::with_task_limit`
+    task_limit: Option<(usize, Backpressure)>,
+    executor_threads: usize,
+    thread_name: String,
+    /// Run executors deterministically, see `Executor::with_seed`
+    seed: Option<u64>,
+    /// CPUs to pin an executor to each, in thread-per-core mode
+    cores: Option<Vec<usize>>,
+    /// Run the reactor on the executor's thread, see `Builder::single_threaded`
+    single_threaded: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            shards: 1,
+            routing: Routing::default(),
+            event_capacity: reactor::DEFAULT_EVENT_CAPACITY,
+            backend: Backend::default(),
+            log: None,
+            read_budget: None,
+            blocking_threads: None,
+            ready_queue: None,
+            park_strategy: ParkStrategy::default(),
+            task_limit: None,
+            executor_threads: 1,
+            thread_name: "executor".to_string(),
+            seed: None,
+            cores: None,
+            single_threaded: false,
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of event loops, each on its own thread. Defaults to 1.
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.shards = shards;
+        self
+    }
+
+    /// How sources are spread over the shards, see [`Routing`].
+    pub fn routing(mut self, routing: Routing) -> Self {
+        self.routing = routing;
+        self
+    }
+
+    /// Number of events each event loop takes per call to `poll`. Defaults to 100. Events
+    /// that do not fit are taken by the next `poll`, which does not block, see
+    /// [`DispatchStats::full_batches`](super::DispatchStats::full_batches).
+    pub fn event_capacity(mut self, events: usize) -> Self {
+        self.event_capacity = events;
+        self
+    }
+
+    /// I/O model to drive sockets with, see [`Backend`]. Defaults to [`Backend::Epoll`].
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Levels of runtime diagnostics to show, rather than those of the `RUNTIME_LOG`
+    /// environment variable, see [`log`].
+    pub fn log(mut self, config: LogConfig) -> Self {
+        self.log = Some(config);
+        self
+    }
+
+    /// Bytes a future reading a response may read in a single poll, see
+    /// [`set_read_budget`](super::set_read_budget).
+    pub fn read_budget(mut self, bytes: usize) -> Self {
+        self.read_budget = Some(bytes);
+        self
+    }
+
+    /// Number of threads in the pool of [`spawn_blocking`](super::spawn_blocking). Defaults
+    /// to 4.
+    pub fn blocking_threads(mut self, threads: usize) -> Self {
+        self.blocking_threads = Some(threads);
+        self
+    }
+
+    /// Ready queue implementation of the executors, see [`ReadyQueueKind`].
+    pub fn ready_queue(mut self, kind: ReadyQueueKind) -> Self {
+        self.ready_queue = Some(kind);
+        self
+    }
+
+    /// How executors wait for a wake up once no task is ready, see [`ParkStrategy`].
+    pub fn park_strategy(mut self, strategy: ParkStrategy) -> Self {
+        self.park_strategy = strategy;
+        self
+    }
+
+    /// Most tasks each executor takes at once, and what spawning does beyond them, see
+    /// [`Executor::with_task_limit`]. Unlimited by default.
+    pub fn task_limit(mut self, limit: usize, policy: Backpressure) -> Self {
+        self.task_limit = Some((limit, policy));
+        self
+    }
+
+    /// Run executors deterministically, polling ready tasks in an order drawn from `seed` and
+    /// with sleeps on a virtual clock, see [`Executor::with_seed`].
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Number of threads [`Builder::spawn_executors`] runs an executor on. Defaults to 1.
+    pub fn executor_threads(mut self, threads: usize) -> Self {
+        self.executor_threads = threads;
+        self
+    }
+
+    /// Name of the threads of [`Builder::spawn_executors`], suffixed with their index.
+    /// Defaults to `executor`.
+    pub fn thread_name(mut self, name: &str) -> Self {
+        self.thread_name = name.to_string();
+        self
+    }
+
+    /// Run an executor per CPU core this process may run on, each pinned to its core and
+    /// with a reactor shard of its own, see [`cores`](super::cores). Takes the place of
+    /// [`Builder::shards`], [`Builder::routing`] and [`Builder::executor_threads`].
+    pub fn thread_per_core(self) -> Self {
+        self.cores(cores::available_cores())
+    }
+
+    /// Same as [`Builder::thread_per_core`], on the given CPUs only.
+    pub fn cores(mut self, cpus: Vec<usize>) -> Self {
+        assert!(
+            !cpus.is_empty(),
+            "thread-per-core mode needs at least one core"
+        );
+        self.shards = cpus.len();
+        self.routing = Routing::Executor;
+        self.executor_threads = cpus.len();
+        self.cores = Some(cpus);
+        self
+    }
+
+    /// Run the reactor's event loop on the executor's thread rather than on a thread of its
+    /// own: once no task is ready, the executor polls for IO events itself instead of parking,
+    /// and wakes from other threads (timers, [`Handle`]s, the blocking pool) interrupt the
+    /// poll. Saves a thread, and the hand off of every IO event between two of them.
+    ///
+    /// Meant for a single executor. Others may still run, taking turns with the event loop:
+    /// their IO is dispatched while any one of them is parked. Takes the place of
+    /// [`Builder::shards`].
+    pub fn single_threaded(mut self) -> Self {
+        self.shards = 1;
+        self.single_threaded = true;
+        self
+    }
+
+    /// Start the runtime, returning the executor of this thread.
+    ///
+    /// Panics if the runtime was started before, e.g. via [`init`](super::init), or if
+    /// [`Backend::IoUring`] is chosen but io_uring is not available.
+    pub fn build(&self) -> Executor {
+        if let Some(config) = &self.log {
+            log::configure(config.clone());
+        }
+
+        // Start reactor and event_loop
+        // NOTE: event looops are spawned in different threads, unless single-threaded,
+        // and reactor is initialised as a global static variable.
+        reactor::start_with(
+            self.shards,
+            self.routing,
+            self.event_capacity,
+            self.single_threaded,
+        );
+
+        if self.backend == Backend::IoUring {
+            uring::start().expect("io_uring is not available");
+        }
+
+        if let Some(bytes) = self.read_budget {
+            budget::set_read_budget(bytes);
+        }
+
+        if let Some(threads) = self.blocking_threads {
+            if !blocking::set_pool_size(threads) {
+                log::warn!("blocking pool already running, its size is left as is");
+            }
+        }
+
+        self.executor()
+    }
+
+    /// The executor of this thread, configured with the executor options of this builder, for
+    /// threads started by hand once the runtime runs.
+    pub fn executor(&self) -> Executor {
+        if let Some(kind) = self.ready_queue {
+            Executor::with_ready_queue(kind);
+        }
+        if let Some(seed) = self.seed {
+            Executor::with_seed(seed);
+        }
+        if let Some((limit, policy)) = self.task_limit {
+            Executor::with_task_limit(limit, policy);
+        }
+
+        // Validate runtime invariants every so often while developing, see `self_check`
+        if cfg!(debug_assertions) {
+            executor::enable_self_check(SELF_CHECK_INTERVAL);
+        }
+
+        Executor::with_park_strategy(self.park_strategy)
+    }
+
+    /// Run `main(i)` to completion on each of the executor threads, named `{name}-{i}`, with
+    /// executors configured as by [`Builder::executor`]. The runtime must be running, see
+    /// [`Builder::build`].
+    ///
+    /// In thread-per-core mode, thread `i` is pinned to the `i`th core and registers its
+    /// sources with shard `i`. [`spawn_global`](super::spawn_global) spreads tasks over these
+    /// executors before any `main` starts.
+    pub fn spawn_executors<F, Fut>(&self, main: F) -> Vec<JoinHandle<()>>
+    where
+        F: Fn(usize) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let main = Arc::new(main);
+        let (handles, started) = mpsc::channel();
+        // so every executor is known before any main runs, see `spawn_global`
+        let registered = Arc::new(Barrier::new(self.executor_threads + 1));
+
+        let threads = (0..self.executor_threads)
+            .map(|i| {
+                let (builder, main) = (self.clone(), main.clone());
+                let (handles, registered) = (handles.clone(), registered.clone());
+                thread::Builder::new()
+                    .name(format!("{}-{i}", self.thread_name))
+                    .spawn(move || {
+                        let mut executor = builder.executor();
+                        if let Some(cpus) = &builder.cores {
+                            if let Err(e) = cores::pin_to_core(cpus[i]) {
+                                log::warn!("executor {i} not pinned to CPU {}: {e}", cpus[i]);
+                            }
+                            reactor::assign_shard(i);
+                            let _ = handles.send((i, executor.handle()));
+                            registered.wait();
+                        }
+                        executor.block_on(main(i))
+                    })
+                    .expect("Failed to spawn executor thread")
+            })
+            .collect();
+
+        if self.cores.is_some() {
+            let mut handles: Vec<(usize, Handle)> =
+                started.iter().take(self.executor_threads).collect();
+            handles.sort_by_key(|(i, _)| *i);
+            cores::set_cores(handles.into_iter().map(|(_, handle)| handle).collect());
+            registered.wait();
+        }
+        threads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::Poll,
+    };
+
+    use super::*;
+    use crate::runtime::{self, sleep, spawn};
+
+    #[test]
+    fn executors_run_on_named_threads() {
+        runtime::init_for_tests();
+        let done = Arc::new(AtomicUsize::new(0));
+        let seen = done.clone();
+
+        let workers = Builder::new()
+            .executor_threads(3)
+            .thread_name("worker")
+            .spawn_executors(move |i| {
+                let done = seen.clone();
+                async move {
+                    let name = thread::current().name().map(str::to_string);
+                    assert_eq!(name, Some(format!("worker-{i}")));
+
+                    spawn(async move {
+                        sleep(Duration::from_millis(5)).await;
+                        done.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            });
+
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(done.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn global_spawn_round_robins_over_pinned_executors() {
+        runtime::init_for_tests();
+        let cpus = runtime::available_cores();
+        let (done, finished) = std::sync::mpsc::channel();
+
+        let builder = Builder::new().cores(vec![cpus[0], cpus[cpus.len() - 1]]);
+        let workers = builder.spawn_executors(move |i| {
+            let done = done.clone();
+            async move {
+                // the first core spreads the tasks, they run on either
+                if i == 0 {
+                    for _ in 0..4 {
+                        let done = done.clone();
+                        runtime::spawn_global(async move {
+                            let _ = done.send(thread::current().name().map(str::to_string));
+                        });
+                    }
+                }
+                // keep the other core running until the tasks are handed over
+                sleep(Duration::from_millis(50)).await;
+            }
+        });
+        assert_eq!(runtime::core_count(), 2);
+
+        let mut names: Vec<_> = finished.iter().take(4).flatten().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["executor-0", "executor-0", "executor-1", "executor-1"]
+        );
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn spinning_executor_is_still_woken() {
+        runtime::init_for_tests();
+        let mut executor = Builder::new()
+            .park_strategy(ParkStrategy::Spin(100))
+            .executor();
+
+        executor.block_on(async {
+            // longer than the spins take, so the executor ends up parked
+            sleep(Duration::from_millis(20)).await;
+        });
+        assert_eq!(executor.metrics().pending, 0);
+    }
+
+    #[test]
+    fn adaptive_spinning_skips_parks_for_quick_wakes() {
+        runtime::init_for_tests();
+        let mut executor = Builder::new()
+            .park_strategy(ParkStrategy::Adaptive(100_000))
+            .executor();
+
+        executor.block_on(async {
+            // a thread answering each request right away, so replies come in quickly
+            let (requests, incoming) = mpsc::channel::<(usize, std::task::Waker)>();
+            let (replies, outgoing) = mpsc::channel();
+            let echo = thread::spawn(move || {
+                for (request, waker) in incoming {
+                    replies.send(request).unwrap();
+                    waker.wake();
+                }
+            });
+
+            for i in 0..500 {
+                let mut sent = false;
+                let reply = std::future::poll_fn(|cx| {
+                    if !sent {
+                        requests.send((i, cx.waker().clone())).unwrap();
+                        sent = true;
+                    }
+                    outgoing.try_recv().map_or(Poll::Pending, Poll::Ready)
+                })
+                .await;
+                assert_eq!(reply, i);
+            }
+            drop(requests);
+            echo.join().unwrap();
+        });
+
+        let metrics = executor.metrics();
+        assert!(metrics.parks_avoided > 0, "{metrics:?}");
+        assert!(metrics.parks_avoided > metrics.parks, "{metrics:?}");
+    }
+}
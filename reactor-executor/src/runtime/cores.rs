@@ -0,0 +1,138 @@
+//! Thread-per-core mode: an executor per CPU core, pinned to it, each with a reactor shard of
+//! its own.
+//!
+//! [`Builder::thread_per_core`](super::Builder::thread_per_core) starts as many reactor shards
+//! as there are cores this process may run on, and [`Builder::spawn_executors`] one executor
+//! thread per core. Each of them is pinned to its core via `sched_setaffinity` (Linux only,
+//! elsewhere they are left to the scheduler), and registers its sources with the shard of the
+//! same index, so a connection is polled, woken and handled on a single core.
+//!
+//! Tasks spawned via [`spawn`](super::spawn) stay on the core that spawned them.
+//! [`spawn_global`] instead hands each task to the next core in turn, e.g. to spread
+//! connections accepted on one core over all of them.
+use std::{
+    future::Future,
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+    thread,
+};
+
+use super::{executor, Handle};
+
+/// Handles of the executors in thread-per-core mode, by core
+static CORES: RwLock<Vec<Handle>> = RwLock::new(Vec::new());
+
+/// Core the next task of [`spawn_global`] goes to
+static NEXT_CORE: AtomicUsize = AtomicUsize::new(0);
+
+/// CPUs this process may run on, in ascending order.
+#[cfg(target_os = "linux")]
+pub fn available_cores() -> Vec<usize> {
+    // SAFETY: cpu_set_t is plain data, and filled in by the kernel on success
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of_val(&set), &mut set) == 0 {
+            return (0..libc::CPU_SETSIZE as usize)
+                .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+                .collect();
+        }
+    }
+    fallback_cores()
+}
+
+/// CPUs this process may run on, in ascending order.
+#[cfg(not(target_os = "linux"))]
+pub fn available_cores() -> Vec<usize> {
+    fallback_cores()
+}
+
+/// As many cores as std reports parallelism, numbered from 0
+fn fallback_cores() -> Vec<usize> {
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    (0..cores).collect()
+}
+
+/// Pin the calling thread to `cpu`.
+#[cfg(target_os = "linux")]
+pub fn pin_to_core(cpu: usize) -> io::Result<()> {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("no such CPU: {cpu}"),
+        ));
+    }
+
+    // SAFETY: cpu_set_t is plain data, and `cpu` is within its bounds
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set)
+    };
+
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Pin the calling thread to `cpu`.
+#[cfg(not(target_os = "linux"))]
+pub fn pin_to_core(cpu: usize) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "pinning threads is only supported on Linux",
+    ))
+}
+
+/// Replace the executors that [`spawn_global`] spreads tasks over.
+pub(super) fn set_cores(handles: Vec<Handle>) {
+    *CORES.write().unwrap() = handles;
+}
+
+/// Number of executors in thread-per-core mode, 0 unless started, see the module docs.
+pub fn core_count() -> usize {
+    CORES.read().unwrap().len()
+}
+
+/// Spawn a task on the next executor in thread-per-core mode, round-robin, from any thread.
+///
+/// Without executors in thread-per-core mode, the task is spawned on the executor of the
+/// calling thread, same as [`spawn`](super::spawn).
+pub fn spawn_global<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let cores = CORES.read().unwrap();
+    if cores.is_empty() {
+        drop(cores);
+        return executor::spawn(future);
+    }
+
+    let core = NEXT_CORE.fetch_add(1, Ordering::Relaxed) % cores.len();
+    cores[core].spawn(future);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_thread_runs_on_its_core() {
+        let cores = available_cores();
+        assert!(!cores.is_empty());
+        let last = *cores.last().unwrap();
+
+        let pinned = thread::spawn(move || pin_to_core(last).map(|()| available_cores()));
+
+        match pinned.join().unwrap() {
+            Ok(allowed) => assert_eq!(allowed, [last]),
+            Err(e) if !cfg!(target_os = "linux") => {
+                assert_eq!(e.kind(), io::ErrorKind::Unsupported)
+            }
+            Err(e) => panic!("Failed to pin thread: {e}"),
+        }
+    }
+}
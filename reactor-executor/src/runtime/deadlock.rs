@@ -0,0 +1,151 @@
+//! Wait-for tracking between tasks, used to report deadlocks instead of hanging.
+//!
+//! When a synchronisation primitive (channel, semaphore, mutex, ...) returns `Pending`, it
+//! records what the current task is now waiting on via [`wait_on`]. If the primitive knows
+//! which task has to make progress before the wait can end, e.g. the task holding a lock,
+//! it records that task as the `holder`, which gives us an edge in the wait-for graph.
+//!
+//! The executor clears a task's record before every poll, so a record is only present while
+//! the task is parked on that resource.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT_RESOURCE_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// A resource a task can be blocked on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource {
+    /// What kind of primitive this is, used in reports only
+    pub kind: &'static str,
+    /// Unique id of the primitive, see [`next_resource_id`]
+    pub id: usize,
+    /// Task that must make progress for the wait to end, if known
+    pub holder: Option<usize>,
+    /// The wait is ended by something outside the executor, e.g. another thread. Tasks
+    /// waiting on such a resource can never be part of a deadlock.
+    pub external: bool,
+}
+
+/// Hands out ids that identify primitives in deadlock reports.
+pub fn next_resource_id() -> usize {
+    NEXT_RESOURCE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Record that the task currently being polled is blocked on `resource`.
+///
+/// Does nothing when called outside of a task.
+pub fn wait_on(resource: Resource) {
+    super::executor::set_waiting_on(resource);
+}
+
+/// Build a report for a set of tasks that are all blocked on each other.
+///
+/// `waits` maps each blocked task to the resource it is waiting on, and `names` maps task ids
+/// to the names given when spawning them.
+pub(super) fn report(waits: &HashMap<usize, Resource>, names: &HashMap<usize, String>) -> String {
+    let name = |id: &usize| match names.get(id) {
+        Some(name) => format!("'{name}' (task {id})"),
+        None => format!("task {id}"),
+    };
+
+    let mut report = format!(
+        "deadlock detected: all {} pending tasks are blocked and nothing can wake them",
+        waits.len()
+    );
+
+    if let Some(cycle) = find_cycle(waits) {
+        let cycle: Vec<String> = cycle.iter().chain(cycle.first()).map(name).collect();
+        let _ = write!(report, "\n  cycle: {}", cycle.join(" -> "));
+    }
+
+    let mut ids: Vec<&usize> = waits.keys().collect();
+    ids.sort();
+
+    for id in ids {
+        let resource = &waits[id];
+        let _ = write!(
+            report,
+            "\n  {} waiting on {}#{}",
+            name(id),
+            resource.kind,
+            resource.id
+        );
+
+        if let Some(holder) = resource.holder {
+            let _ = write!(report, " held by {}", name(&holder));
+        }
+    }
+
+    report
+}
+
+/// Find a cycle in the wait-for graph, returning the tasks that form it.
+///
+/// Each task waits on at most one resource, so every node has at most one outgoing edge and
+/// we can find a cycle by simply following edges until we either leave the graph or revisit
+/// a task.
+fn find_cycle(waits: &HashMap<usize, Resource>) -> Option<Vec<usize>> {
+    let mut visited = HashSet::new();
+
+    let mut starts: Vec<&usize> = waits.keys().collect();
+    starts.sort();
+
+    for &start in starts {
+        let mut path = Vec::new();
+        let mut current = start;
+
+        while !visited.contains(&current) {
+            visited.insert(current);
+            path.push(current);
+
+            match waits.get(&current).and_then(|resource| resource.holder) {
+                Some(holder) => current = holder,
+                None => break,
+            }
+        }
+
+        // only a cycle if we came back to a task on the path we just walked
+        if let Some(pos) = path.iter().position(|id| *id == current) {
+            if waits.get(path.last()?).and_then(|r| r.holder) == Some(current) {
+                return Some(path.split_off(pos));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waiting(holder: Option<usize>) -> Resource {
+        Resource {
+            kind: "mutex",
+            id: next_resource_id(),
+            holder,
+            external: false,
+        }
+    }
+
+    #[test]
+    fn finds_cycle_between_holders() {
+        let waits = HashMap::from([
+            (1, waiting(Some(2))),
+            (2, waiting(Some(3))),
+            (3, waiting(Some(2))),
+        ]);
+
+        assert_eq!(find_cycle(&waits), Some(vec![2, 3]));
+    }
+
+    #[test]
+    fn no_cycle_without_holders() {
+        let waits = HashMap::from([(1, waiting(Some(2))), (2, waiting(None))]);
+
+        assert_eq!(find_cycle(&waits), None);
+    }
+}
@@ -0,0 +1,526 @@
+//! Deterministic runs, so tests reproduce the same interleaving of tasks every time.
+//!
+//! Which task runs next otherwise depends on when wakes come in, from the reactor, the timer
+//! thread, or other threads. [`Executor::with_seed`](super::Executor::with_seed) takes all of
+//! that out of the picture for the executor of its thread:
+//! - tasks that are ready at the same time are polled in an order drawn from the seed, rather
+//!   than the order of their wakes. Runs with the same seed poll tasks in the same order,
+//!   other seeds try other interleavings.
+//! - [`sleep`](super::sleep) runs on a virtual clock, see [`now`]. No time actually passes:
+//!   once no task is ready, the clock jumps to the next deadline.
+//! - a [`MockReactor`] stands in for the reactor: rather than events from epoll, it replays a
+//!   script of readiness events for [`MockSource`]s, one step each time no task is ready.
+//!
+//! ```ignore
+//! let reactor = MockReactor::new()
+//!     .ready(1, Interest::READABLE)
+//!     .advance(Duration::from_millis(10))
+//!     .ready(2, Interest::READABLE);
+//! reactor.install();
+//!
+//! Executor::with_seed(42).block_on(async move {
+//!     spawn_local(handle(reactor.source(1)));
+//!     spawn_local(handle(reactor.source(2)));
+//! });
+//! ```
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    future::Future,
+    mem,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use mio::Interest;
+
+use super::{
+    executor,
+    ready_queue::{Entry, ReadyQueue},
+    timer::Key,
+};
+use crate::sim::XorShift;
+
+thread_local! {
+    /// Clock `sleep` runs on, set by `Executor::with_seed`
+    static CLOCK: RefCell<Option<VirtualClock>> = const { RefCell::new(None) };
+
+    /// Reactor the executor replays events of once idle, see `MockReactor::install`
+    static REACTOR: RefCell<Option<MockReactor>> = const { RefCell::new(None) };
+}
+
+/// Current time: on the virtual clock in deterministic runs, see the module docs, and on the
+/// wall clock otherwise.
+pub fn now() -> Instant {
+    virtual_now().unwrap_or_else(Instant::now)
+}
+
+/// Current time on the virtual clock of this thread, if there is one.
+pub(super) fn virtual_now() -> Option<Instant> {
+    CLOCK.with(|clock| clock.borrow().as_ref().map(|clock| clock.now))
+}
+
+/// Put a virtual clock in place for this thread, starting at the current time.
+pub(super) fn install_clock() {
+    CLOCK.with(|clock| *clock.borrow_mut() = Some(VirtualClock::new()));
+}
+
+/// Wake `waker` once the virtual clock reaches `deadline`, replacing the waker of `key` if
+/// there is one.
+pub(super) fn schedule(key: Option<Key>, deadline: Instant, waker: &Waker) -> Key {
+    CLOCK.with(|clock| {
+        let mut clock = clock.borrow_mut();
+        let clock = clock
+            .as_mut()
+            .expect("no virtual clock on this thread, see Executor::with_seed");
+        clock.schedule(key, deadline, waker)
+    })
+}
+
+pub(super) fn cancel(key: Key) {
+    // `try_with`, as sleeps may be dropped while thread locals are torn down
+    let _ = CLOCK.try_with(|clock| {
+        if let Some(clock) = clock.borrow_mut().as_mut() {
+            clock.timers.remove(&key);
+        }
+    });
+}
+
+/// Make progress once no task is ready: replay the next step of the installed reactor, or
+/// else move the virtual clock to the next deadline.
+///
+/// Returns false if there is nothing to replay and no one waiting on the virtual clock, in
+/// which case the caller should fall back to parking the thread.
+pub(super) fn advance() -> bool {
+    let reactor = REACTOR.with(|reactor| reactor.borrow().clone());
+    if reactor.is_some_and(|reactor| reactor.replay_next()) {
+        return true;
+    }
+
+    let expired = CLOCK.with(|clock| match clock.borrow_mut().as_mut() {
+        Some(clock) => clock.advance_to_next(),
+        None => Vec::new(),
+    });
+    let woken = !expired.is_empty();
+    // woken without holding the clock, so the tasks can be polled and schedule again. Urgent,
+    // as they would be if woken by the timer thread.
+    executor::wake_urgent(|| expired.into_iter().for_each(Waker::wake));
+    woken
+}
+
+/// Ids of tasks on this thread waiting on the virtual clock or a mock source.
+pub(super) fn waiting_tasks() -> HashSet<usize> {
+    let timers = CLOCK.with(|clock| match clock.borrow().as_ref() {
+        Some(clock) => clock
+            .timers
+            .values()
+            .filter_map(|(_, owner)| *owner)
+            .collect(),
+        None => HashSet::new(),
+    });
+    let sources = REACTOR.with(|reactor| match reactor.borrow().as_ref() {
+        Some(reactor) => reactor.waiting_tasks(),
+        None => HashSet::new(),
+    });
+
+    timers.into_iter().chain(sources).collect()
+}
+
+/// Time that only moves when told to, see [`advance`].
+struct VirtualClock {
+    now: Instant,
+    /// Waker of each sleep, and the task that sleeps
+    timers: BTreeMap<Key, (Waker, Option<usize>)>,
+    next_id: u64,
+}
+
+impl VirtualClock {
+    fn new() -> Self {
+        Self {
+            now: Instant::now(),
+            timers: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    fn schedule(&mut self, key: Option<Key>, deadline: Instant, waker: &Waker) -> Key {
+        if let Some((stored, _)) = key.and_then(|key| self.timers.get_mut(&key)) {
+            // IMPORTANT: always store the most recent waker
+            if !stored.will_wake(waker) {
+                *stored = waker.clone();
+            }
+            return key.unwrap();
+        }
+
+        let key = (deadline, self.next_id);
+        self.next_id += 1;
+        self.timers
+            .insert(key, (waker.clone(), executor::current_task()));
+        key
+    }
+
+    /// Jump to the earliest deadline, returning the wakers of the sleeps that expired.
+    fn advance_to_next(&mut self) -> Vec<Waker> {
+        match self.timers.keys().next() {
+            Some(&(deadline, _)) => self.advance_to(deadline),
+            None => Vec::new(),
+        }
+    }
+
+    /// Move forward to `time`, returning the wakers of the sleeps that expired on the way.
+    fn advance_to(&mut self, time: Instant) -> Vec<Waker> {
+        self.now = self.now.max(time);
+        let pending = self.timers.split_off(&(self.now, u64::MAX));
+        let expired = mem::replace(&mut self.timers, pending);
+
+        expired.into_values().map(|(waker, _)| waker).collect()
+    }
+}
+
+/// Picks the next task to poll among the ready ones, in an order drawn from a seed.
+///
+/// Ready tasks are taken off the executor's queues and kept here until picked, so the order
+/// does not depend on the queue implementation. High priority tasks still go first.
+pub(super) struct SeededOrder {
+    rng: XorShift,
+    urgent: Vec<Entry>,
+    normal: Vec<Entry>,
+}
+
+impl SeededOrder {
+    pub(super) fn new(seed: u64) -> Self {
+        Self {
+            rng: XorShift::new(seed),
+            urgent: Vec::new(),
+            normal: Vec::new(),
+        }
+    }
+
+    /// Take whatever was queued since the last pick, and pick one of the ready tasks.
+    pub(super) fn pick(&mut self, urgent: &ReadyQueue, normal: &ReadyQueue) -> Option<Entry> {
+        self.urgent
+            .extend(std::iter::from_fn(|| urgent.pop_entry()));
+        self.normal
+            .extend(std::iter::from_fn(|| normal.pop_entry()));
+
+        let ready = if self.urgent.is_empty() {
+            &mut self.normal
+        } else {
+            &mut self.urgent
+        };
+        if ready.is_empty() {
+            return None;
+        }
+
+        let index = (self.rng.next_u64() % ready.len() as u64) as usize;
+        Some(ready.swap_remove(index))
+    }
+
+    /// Ids of the tasks taken off the queues but not picked yet.
+    pub(super) fn ready(&self) -> impl Iterator<Item = usize> + '_ {
+        self.urgent.iter().chain(&self.normal).map(|entry| entry.id)
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.urgent.len() + self.normal.len()
+    }
+}
+
+/// One step of the script of a [`MockReactor`].
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    /// Source `token` became ready for `interest`
+    Ready(usize, Interest),
+    /// The virtual clock moves forward
+    Advance(Duration),
+}
+
+/// Readiness of a [`MockSource`], and who waits for it
+#[derive(Default)]
+struct SourceState {
+    /// Events replayed but not consumed yet
+    ready: Option<Interest>,
+    waker: Option<Waker>,
+    /// Task that waits for the source
+    owner: Option<usize>,
+}
+
+#[derive(Default)]
+struct Script {
+    steps: VecDeque<Step>,
+    sources: HashMap<usize, SourceState>,
+}
+
+/// Stands in for the reactor in deterministic runs, replaying a script of readiness events,
+/// see the module docs.
+///
+/// Each step is replayed once no task is ready, i.e. once every task had the chance to react
+/// to the step before. The virtual clock only moves on [`MockReactor::advance`] steps while
+/// the script lasts, and jumps from deadline to deadline once it ran out.
+#[derive(Clone, Default)]
+pub struct MockReactor(Rc<RefCell<Script>>);
+
+impl MockReactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a step making source `token` ready for `interest`.
+    pub fn ready(self, token: usize, interest: Interest) -> Self {
+        self.0
+            .borrow_mut()
+            .steps
+            .push_back(Step::Ready(token, interest));
+        self
+    }
+
+    /// Add a step moving the virtual clock forward by `duration`, waking the sleeps that
+    /// expire on the way.
+    pub fn advance(self, duration: Duration) -> Self {
+        self.0.borrow_mut().steps.push_back(Step::Advance(duration));
+        self
+    }
+
+    /// Replay this script on the executor of the current thread.
+    pub fn install(&self) {
+        REACTOR.with(|reactor| *reactor.borrow_mut() = Some(self.clone()));
+    }
+
+    /// Source the events of the script for `token` go to.
+    pub fn source(&self, token: usize) -> MockSource {
+        MockSource {
+            reactor: self.clone(),
+            token,
+        }
+    }
+
+    /// Number of steps not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.0.borrow().steps.len()
+    }
+
+    /// Replay the next step, returning false once the script ran out.
+    fn replay_next(&self) -> bool {
+        let Some(step) = self.0.borrow_mut().steps.pop_front() else {
+            return false;
+        };
+
+        match step {
+            Step::Ready(token, interest) => {
+                let waker = {
+                    let mut script = self.0.borrow_mut();
+                    let source = script.sources.entry(token).or_default();
+                    source.ready = Some(match source.ready {
+                        Some(ready) => ready.add(interest),
+                        None => interest,
+                    });
+                    source.waker.take()
+                };
+                // woken without holding the script, so the task can be polled straight away
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            }
+            Step::Advance(duration) => {
+                let expired = CLOCK.with(|clock| {
+                    let mut clock = clock.borrow_mut();
+                    let clock = clock
+                        .as_mut()
+                        .expect("advance steps need a virtual clock, see Executor::with_seed");
+                    let time = clock.now + duration;
+                    clock.advance_to(time)
+                });
+                executor::wake_urgent(|| expired.into_iter().for_each(Waker::wake));
+            }
+        }
+        true
+    }
+
+    fn waiting_tasks(&self) -> HashSet<usize> {
+        self.0
+            .borrow()
+            .sources
+            .values()
+            .filter(|source| source.waker.is_some())
+            .filter_map(|source| source.owner)
+            .collect()
+    }
+}
+
+/// Event source driven by the script of a [`MockReactor`], see [`MockReactor::source`].
+#[derive(Clone)]
+pub struct MockSource {
+    reactor: MockReactor,
+    token: usize,
+}
+
+impl MockSource {
+    pub fn token(&self) -> usize {
+        self.token
+    }
+
+    /// Returns a future that resolves once the script made this source ready for any of
+    /// `interest`, consuming those events. Events replayed before are kept until waited for.
+    pub fn ready(&self, interest: Interest) -> Readiness<'_> {
+        Readiness {
+            source: self,
+            interest,
+        }
+    }
+}
+
+/// Future returned by [`MockSource::ready`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Readiness<'a> {
+    source: &'a MockSource,
+    interest: Interest,
+}
+
+impl Future for Readiness<'_> {
+    type Output = Interest;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Interest> {
+        let mut script = self.source.reactor.0.borrow_mut();
+        let source = script.sources.entry(self.source.token).or_default();
+
+        let matched = source.ready.and_then(|ready| {
+            [Interest::READABLE, Interest::WRITABLE]
+                .into_iter()
+                .filter(|event| contains(ready, *event) && contains(self.interest, *event))
+                .reduce(Interest::add)
+        });
+
+        let Some(matched) = matched else {
+            source.waker = Some(cx.waker().clone());
+            source.owner = executor::current_task();
+            return Poll::Pending;
+        };
+
+        source.ready = source.ready.and_then(|ready| ready.remove(matched));
+        source.waker = None;
+        Poll::Ready(matched)
+    }
+}
+
+/// True if `interest` includes all of `event`.
+fn contains(interest: Interest, event: Interest) -> bool {
+    interest.add(event) == interest
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, thread};
+
+    use super::*;
+    use crate::runtime::{sleep, spawn_local, yield_now, Executor};
+
+    /// Tasks waiting on mock sources and sleeps, in the order they ran, on a fresh thread
+    fn interleaving(seed: u64) -> Vec<String> {
+        thread::spawn(move || {
+            let reactor = MockReactor::new()
+                .ready(1, Interest::READABLE)
+                .ready(2, Interest::READABLE)
+                .advance(Duration::from_millis(5))
+                .ready(1, Interest::READABLE);
+            reactor.install();
+            let log = Rc::new(RefCell::new(Vec::new()));
+
+            Executor::with_seed(seed).block_on({
+                let log = log.clone();
+                async move {
+                    // source 1 takes both of its events, source 2 the one
+                    for (token, events) in [(1, 2), (2, 1)] {
+                        let (source, log) = (reactor.source(token), log.clone());
+                        spawn_local(async move {
+                            for _ in 0..events {
+                                source.ready(Interest::READABLE).await;
+                                log.borrow_mut().push(format!("source {token}"));
+                            }
+                        });
+                    }
+                    for (name, ms) in [("a", 5), ("b", 5), ("c", 20)] {
+                        let log = log.clone();
+                        spawn_local(async move {
+                            sleep(Duration::from_millis(ms)).await;
+                            log.borrow_mut().push(format!("{name} slept"));
+                        });
+                    }
+                    sleep(Duration::from_millis(50)).await;
+                    assert_eq!(reactor.remaining(), 0);
+                }
+            });
+
+            Rc::try_unwrap(log).unwrap().into_inner()
+        })
+        .join()
+        .unwrap()
+    }
+
+    #[test]
+    fn same_seed_reproduces_the_same_interleaving() {
+        let first = interleaving(7);
+        assert_eq!(first, interleaving(7));
+        assert_eq!(first.len(), 6);
+
+        // the script and the clock fix what can run when, the seed which of it goes first
+        let orders: HashSet<_> = (1..20).map(interleaving).collect();
+        assert!(orders.len() > 1, "seeds should try other interleavings");
+        for order in orders {
+            assert_eq!(order[..2], ["source 1", "source 2"]);
+            let mut slept: Vec<_> = order[2..4].to_vec();
+            slept.sort();
+            assert_eq!(slept, ["a slept", "b slept"]);
+            assert_eq!(order[4..], ["source 1", "c slept"]);
+        }
+    }
+
+    #[test]
+    fn virtual_clock_jumps_to_the_next_deadline() {
+        thread::spawn(|| {
+            Executor::with_seed(1).block_on(async {
+                let (start, wall) = (now(), Instant::now());
+                sleep(Duration::from_secs(60)).await;
+
+                assert_eq!(now() - start, Duration::from_secs(60));
+                assert!(wall.elapsed() < Duration::from_secs(1));
+            });
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn yielding_tasks_share_the_executor_fairly() {
+        for seed in 0..10 {
+            let polls = thread::spawn(move || {
+                let polls = Rc::new(RefCell::new(Vec::new()));
+                Executor::with_seed(seed).block_on({
+                    let polls = polls.clone();
+                    async move {
+                        for task in 0..3 {
+                            let polls = polls.clone();
+                            spawn_local(async move {
+                                for _ in 0..5 {
+                                    polls.borrow_mut().push(task);
+                                    yield_now().await;
+                                }
+                            });
+                        }
+                    }
+                });
+                Rc::try_unwrap(polls).unwrap().into_inner()
+            })
+            .join()
+            .unwrap();
+
+            // whatever the order within a round, every task runs once per round
+            for round in polls.chunks(3) {
+                let mut round = round.to_vec();
+                round.sort();
+                assert_eq!(round, [0, 1, 2], "seed {seed}: {polls:?}");
+            }
+        }
+    }
+}
@@ -1,16 +1,77 @@
 use std::{
+    any::Any,
     cell::{Cell, RefCell},
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
     future::Future,
+    panic::{self, AssertUnwindSafe},
     pin::Pin,
-    sync::{Arc, Mutex},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
     task::{Context, Poll, Wake, Waker},
-    thread::{self, Thread},
+    thread,
+    time::{Duration, Instant},
 };
 
+use async_core::Progress;
+use mio::Interest;
+
+use super::{
+    admission::Backpressure,
+    deadlock,
+    deterministic::{self, SeededOrder},
+    handle::{Handle, Injector},
+    log,
+    parker::Parker,
+    pool::LOCAL_WAKERS,
+    reactor::{self, Priority},
+    ready_queue::{Entry, ReadyQueue, ReadyQueueKind},
+    self_check, timer, uring, Resource,
+};
+
+/// How long tasks must stay deadlocked before we report it, see `Executor::wait_out_deadlock`
+const DEADLOCK_GRACE: Duration = Duration::from_millis(500);
+
+/// Number of finished tasks remembered per executor, to explain wakes that come after, see
+/// [`Tombstones`]
+const TOMBSTONES: usize = 1024;
+
+/// Checks for a wake up before parking that busy wait, rather than yield the thread, see
+/// [`ParkStrategy::Spin`]
+const BUSY_CHECKS: u32 = 16;
+
+/// Parks shorter than this grow the checks before parking, see [`ParkStrategy::Adaptive`]
+const QUICK_WAKE: Duration = Duration::from_micros(100);
+
+/// Number of wakes ignored because the executor the waker belonged to had shut down.
+static STALE_WAKES: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of wakes of a task that was queued already, and so not queued again.
+static COALESCED_WAKES: AtomicUsize = AtomicUsize::new(0);
+
 // NOTE: Task's must now be pinned on the heap. Our top level futures
 // are expected to resolve to `()`, the unit type (aka void)
-type Task = Pin<Box<dyn Future<Output = ()>>>;
+/// A top level future. Only tasks that are Send could ever be moved to the executor of another
+/// thread, local ones are pinned to the thread they were spawned on, see [`spawn_local`].
+enum Task {
+    Send(Pin<Box<dyn Future<Output = ()> + Send>>),
+    Local(Pin<Box<dyn Future<Output = ()>>>),
+}
+
+impl Task {
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match self {
+            Self::Send(future) => future.as_mut().poll(cx),
+            Self::Local(future) => future.as_mut().poll(cx),
+        }
+    }
+}
+
+/// Values of a task's task locals, keyed by `TaskLocal`
+type TaskLocals = HashMap<usize, Rc<dyn Any>>;
 
 // thread local static variable.
 // Each OS thread will have only 1 executor running on it.
@@ -20,6 +81,12 @@ type Task = Pin<Box<dyn Future<Output = ()>>>;
 // use with `CURRENT_EXEC.with(|executor| {...})`
 thread_local! {
     static CURRENT_EXEC: ExecutorCore = ExecutorCore::default();
+
+    /// Set while wakes on this thread are being collected, see [`batch_wakes`].
+    static WAKE_BATCH: RefCell<Option<WakeBatch>> = const { RefCell::new(None) };
+
+    /// Set while wakes on this thread are latency critical, see [`wake_urgent`].
+    static URGENT_WAKES: Cell<bool> = const { Cell::new(false) };
 }
 
 /// NOTE: fields are wrapped in types that allow the static variable
@@ -42,27 +109,302 @@ struct ExecutorCore {
     /// that the executor creates and passes to a Task when polling it.
     /// The Waker will be sent to a different thread, to to keep Waker
     /// as Send + Sync, we need the ready_queue to be wrapped in an Arc.
-    ready_queue: Arc<Mutex<Vec<usize>>>,
+    ///
+    /// Wakers only hold a weak reference. On shutdown the queue is swapped for a fresh one,
+    /// so wakers that outlive it can tell their executor is gone, see `Executor::shutdown`.
+    ready_queue: RefCell<Arc<ReadyQueue>>,
+
+    /// Same as `ready_queue`, for tasks spawned with [`Priority::High`]. Drained before
+    /// `ready_queue` is looked at.
+    urgent_queue: RefCell<Arc<ReadyQueue>>,
+
+    /// Tasks spawned from other threads through a [`Handle`], until `block_on` spawns them here.
+    injector: Arc<Injector>,
+
+    /// Picks the order ready tasks are polled in, instead of the queues, if the executor runs
+    /// deterministically, see `Executor::with_seed`.
+    seeded: RefCell<Option<SeededOrder>>,
+
+    /// Priority of tasks spawned via `spawn_with_priority`, tasks not in here are normal.
+    priorities: RefCell<HashMap<usize, Priority>>,
 
     /// Counter that gives out next available task ID.
     ///
     /// It should never hand out the same ID twice for a given ExecutorCore.
     /// A Cell will suffice for giving us interior mutability needed on the ExecutorCore.
     next_id: Cell<usize>,
+
+    /// Names given to tasks via `spawn_named`, used when reporting on tasks.
+    names: RefCell<HashMap<usize, String>>,
+
+    /// Resource each parked task is blocked on, recorded by synchronisation primitives.
+    ///
+    /// Used to build the wait-for graph when looking for deadlocks.
+    waits: RefCell<HashMap<usize, Resource>>,
+
+    /// Id of the task currently being polled, None when not polling a task.
+    current: Cell<Option<usize>>,
+
+    /// Set while `block_on` drives the executor, see [`Running`].
+    running: Cell<bool>,
+
+    /// Executors handed out on this thread and not dropped yet, see [`Executor`].
+    handles: Cell<usize>,
+
+    /// What to do when polling a task panics.
+    panic_policy: Cell<PanicPolicy>,
+
+    /// How to wait for a wake up once no task is ready.
+    park_strategy: Cell<ParkStrategy>,
+
+    /// Checks for a wake up before parking, for [`ParkStrategy::Adaptive`].
+    spin_window: Cell<u32>,
+
+    /// Most tasks the executor takes, and what spawning does beyond them, see
+    /// [`Executor::with_task_limit`].
+    task_limit: Cell<Option<(usize, Backpressure)>>,
+
+    /// Room held by [`Permit`](super::Permit)s, counted against the task limit.
+    reserved: Cell<usize>,
+
+    /// Tasks held back by [`Backpressure::Wait`], spawned in order as tasks finish.
+    backlog: RefCell<VecDeque<(Option<String>, Priority, Task)>>,
+
+    /// Tasks waiting for room via [`reserve`](super::reserve), woken once there is.
+    admission: RefCell<Vec<Waker>>,
+
+    /// How long to wait for a wake up while nothing we know of can wake the pending tasks,
+    /// before giving up on them, see `Executor::with_watchdog`. None waits forever.
+    watchdog: Cell<Option<Duration>>,
+
+    /// Tasks that panicked, until taken by `Executor::take_panics`.
+    panics: RefCell<Vec<TaskPanic>>,
+
+    /// Values of task locals, per task and then per `TaskLocal` key, see `runtime::task_local`.
+    locals: RefCell<HashMap<usize, TaskLocals>>,
+
+    /// Times each pending task has been polled so far.
+    polls: RefCell<HashMap<usize, usize>>,
+
+    /// When each pending task was spawned, see [`Executor::dump_tasks`].
+    spawned_at: RefCell<HashMap<usize, Instant>>,
+
+    /// Last progress reported by each pending task, see [`report_progress`].
+    progress: RefCell<HashMap<usize, Progress>>,
+
+    /// Waker of each pending task, created on its first poll and used for every poll after.
+    ///
+    /// As the waker stays the same, leaf futures can tell via `Waker::will_wake` that the one
+    /// they left with the reactor is still current, see `Reactor::update_waker`.
+    wakers: RefCell<HashMap<usize, Arc<MyWaker>>>,
+
+    /// Wakers of tasks that yielded, woken once no other task is ready, see `defer_wake`.
+    deferred: RefCell<Vec<Waker>>,
+
+    /// Counters behind [`Executor::metrics`].
+    metrics: ExecutorCounters,
+
+    /// Tasks that finished recently, and how.
+    tombstones: RefCell<Tombstones>,
+
+    /// Scopes that are open, i.e. polled at least once but not done yet, see `runtime::scope`.
+    scopes: RefCell<HashMap<usize, ScopeState>>,
+
+    /// Scope each task spawned into a scope belongs to.
+    parents: RefCell<HashMap<usize, usize>>,
+
+    /// How often to validate runtime invariants, None if self checks are disabled.
+    self_check: Cell<Option<Duration>>,
+
+    /// When invariants were last validated.
+    last_check: Cell<Option<Instant>>,
+}
+
+impl ExecutorCore {
+    /// Whether the task limit, if any, leaves room for another task. The task being polled is
+    /// taken out of `tasks` meanwhile, but counts all the same.
+    fn has_room(&self) -> bool {
+        self.task_limit.get().is_none_or(|(limit, _)| {
+            let polled = usize::from(self.current.get().is_some());
+            self.tasks.borrow().len() + polled + self.reserved.get() < limit
+        })
+    }
+
+    /// The queue wakers of task `id` push onto, depending on its priority.
+    fn queue_for(&self, id: usize) -> Arc<ReadyQueue> {
+        match self.priorities.borrow().get(&id) {
+            Some(Priority::High) => self.urgent_queue.borrow().clone(),
+            _ => self.ready_queue.borrow().clone(),
+        }
+    }
+
+    fn has_ready(&self) -> bool {
+        !self.urgent_queue.borrow().is_empty()
+            || !self.ready_queue.borrow().is_empty()
+            || self.seeded_depth() > 0
+    }
+
+    /// Number of ids in both ready queues, and taken off them but not picked yet.
+    fn ready_depth(&self) -> usize {
+        self.urgent_queue.borrow().len() + self.ready_queue.borrow().len() + self.seeded_depth()
+    }
+
+    fn seeded_depth(&self) -> usize {
+        self.seeded.borrow().as_ref().map_or(0, SeededOrder::len)
+    }
+}
+
+/// How a task finished, see [`Tombstones`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Finish {
+    Completed,
+    Cancelled,
+    Panicked,
+}
+
+impl fmt::Display for Finish {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Completed => "completed",
+            Self::Cancelled => "was cancelled",
+            Self::Panicked => "panicked",
+        })
+    }
+}
+
+/// The last [`TOMBSTONES`] tasks to finish, and how.
+///
+/// A wake for a task that is not pending is spurious. Ids are never handed out twice, so the
+/// task either finished, or the id is not one of ours, which is a bug. The tombstone says how
+/// a task that finished recently did, for the diagnostics.
+#[derive(Default)]
+struct Tombstones {
+    /// Ids in the order their tasks finished
+    order: VecDeque<usize>,
+    finish: HashMap<usize, Finish>,
+}
+
+impl Tombstones {
+    fn bury(&mut self, id: usize, finish: Finish) {
+        if self.order.len() == TOMBSTONES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.finish.remove(&oldest);
+            }
+        }
+
+        self.order.push_back(id);
+        self.finish.insert(id, finish);
+    }
+
+    fn get(&self, id: usize) -> Option<Finish> {
+        self.finish.get(&id).copied()
+    }
+}
+
+/// Children of an open scope, see `runtime::scope`.
+#[derive(Default)]
+struct ScopeState {
+    /// Tasks spawned into the scope that have not finished yet
+    children: HashSet<usize>,
+    /// Task awaiting the scope, woken once the last child finished
+    waker: Option<Waker>,
+}
+
+/// Snapshot of the counters of a thread's executor, see [`Executor::metrics`].
+///
+/// Counts are totals since the executor on the thread was first used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecutorMetrics {
+    /// Tasks spawned, the future handed to `block_on` included
+    pub spawned: usize,
+    /// Tasks that ran to completion
+    pub completed: usize,
+    /// Tasks dropped while still pending, see [`ExitPolicy`]
+    pub cancelled: usize,
+    /// Tasks dropped because polling them panicked, see [`PanicPolicy`]
+    pub panicked: usize,
+    /// Tasks not done yet
+    pub pending: usize,
+    /// Polls of all tasks
+    pub polls: usize,
+    /// Most polls any single task took, pending tasks included
+    pub max_polls: usize,
+    /// Ids in the ready queues right now
+    pub ready_depth: usize,
+    /// Most ids in the ready queues at once, as seen when popping one
+    pub max_ready_depth: usize,
+    /// Polls of a task that was queued by a wake, or by being spawned
+    pub wakes: usize,
+    /// Time from the wakes to the polls, summed over all of them
+    pub wake_to_poll: Duration,
+    /// Longest time from a wake to the poll it led to
+    pub max_wake_to_poll: Duration,
+    /// Ids popped from the ready queues whose task was no longer pending, e.g. as it was woken
+    /// again before its last poll, or by a waker left behind after it finished
+    pub spurious_wakes: usize,
+    /// Times the executor blocked waiting for a wake up
+    pub parks: usize,
+    /// Times a wake up came in while checking for one before blocking, see [`ParkStrategy`]
+    pub parks_avoided: usize,
+    /// Tasks dropped as the executor was at its task limit, see [`Backpressure::Reject`]
+    pub rejected: usize,
+}
+
+impl ExecutorMetrics {
+    /// Average number of polls a task took.
+    pub fn polls_per_task(&self) -> f64 {
+        self.polls as f64 / self.spawned.max(1) as f64
+    }
+
+    /// Average time from a task being woken to it being polled.
+    pub fn mean_wake_to_poll(&self) -> Duration {
+        self.wake_to_poll / self.wakes.max(1) as u32
+    }
+}
+
+#[derive(Default)]
+struct ExecutorCounters {
+    spawned: Cell<usize>,
+    completed: Cell<usize>,
+    cancelled: Cell<usize>,
+    panicked: Cell<usize>,
+    polls: Cell<usize>,
+    /// Most polls of a task that is done, pending tasks are in `ExecutorCore::polls`
+    max_polls: Cell<usize>,
+    max_ready_depth: Cell<usize>,
+    wakes: Cell<usize>,
+    wake_to_poll: Cell<Duration>,
+    max_wake_to_poll: Cell<Duration>,
+    spurious_wakes: Cell<usize>,
+    parks: Cell<usize>,
+    parks_avoided: Cell<usize>,
+    rejected: Cell<usize>,
+}
+
+impl ExecutorCounters {
+    fn bump(counter: &Cell<usize>) {
+        counter.set(counter.get() + 1);
+    }
+
+    fn record_wake(&self, queued_at: Instant) {
+        let latency = queued_at.elapsed();
+        Self::bump(&self.wakes);
+        self.wake_to_poll.set(self.wake_to_poll.get() + latency);
+        self.max_wake_to_poll
+            .set(self.max_wake_to_poll.get().max(latency));
+    }
 }
 
 /// Alternative is to place this in `future` crate, since it's part of the `Future` trait.
-#[derive(Clone)]
+///
+/// The priority of its task is carried by the queue it pushes onto: the urgent queue for
+/// tasks spawned with [`Priority::High`], the normal one otherwise.
 pub struct MyWaker {
-    /// Handle to executor thread
-    ///
-    /// This enables us to park and unpark the executor's thread using the Waker.
-    /// WARNING: any other library may also be making use of getting the current thread, parking it
-    /// and unparking it. This may cause us to miss wake ups or get trapped in deadlocks. This is
-    /// only used for this simple implementation: see other asynchronous libraries for how they
-    /// implement their Wakers.
-    /// e.g. crossbeam: https://docs.rs/crossbeam/latest/crossbeam/sync/struct.Parker.html
-    thread: Thread,
+    /// Parker of the executor, unparked once the task is queued
+    ///
+    /// The executor's own rather than the thread's `thread::park` token, which other code on
+    /// the thread may use too, see [`Parker`].
+    parker: Parker,
     /// Identifies which Task this waker is associated with. Returned from event_queue ready list as
     /// part user data.
     id: usize,
@@ -70,11 +412,125 @@ pub struct MyWaker {
     ///
     /// usize: represents the id of a Task in the ready queue.
     ///
+    /// The reference is weak, as a waker may be stashed somewhere and outlive the executor.
+    /// Waking it after that is a no-op.
+    ///
     /// NOTE: Waker could also have been supplied a function via executor that would
     /// add associated Task back to it's ready queue, without the Waker itself keeping
     /// a reference to the queue directly like below.
     /// TODO: implement above method instead.
-    ready_queue: Arc<Mutex<Vec<usize>>>,
+    ready_queue: Weak<ReadyQueue>,
+    /// Queue for latency critical wakes, see [`wake_urgent`]. The same as `ready_queue` for
+    /// high priority tasks.
+    urgent_queue: Weak<ReadyQueue>,
+    /// Set once the task is queued, until the executor is about to poll it. Wakes in between
+    /// are coalesced into the one that queued it, rather than queueing the task again.
+    ///
+    /// None for wakers whose executor does not clear it, which queue the task on every wake.
+    queued: Option<AtomicBool>,
+}
+
+impl MyWaker {
+    /// Create a waker for task `id` that wakes up the executor of the current thread.
+    pub(crate) fn new(id: usize, ready_queue: &Arc<ReadyQueue>) -> Self {
+        Self {
+            parker: Parker::current(),
+            id,
+            ready_queue: Arc::downgrade(ready_queue),
+            urgent_queue: Arc::downgrade(ready_queue),
+            queued: None,
+        }
+    }
+
+    /// Same as [`MyWaker::new`], for a task of the executor of the current thread, which
+    /// coalesces wakes until the task is polled, see [`MyWaker::polling`].
+    fn for_task(id: usize, ready_queue: &Arc<ReadyQueue>, urgent_queue: &Arc<ReadyQueue>) -> Self {
+        Self {
+            urgent_queue: Arc::downgrade(urgent_queue),
+            queued: Some(AtomicBool::new(false)),
+            ..Self::new(id, ready_queue)
+        }
+    }
+
+    /// Called right before the task is polled: wakes from here on have to queue it again.
+    fn polling(&self) {
+        if let Some(queued) = &self.queued {
+            // AcqRel, so a wake that was coalesced into the queued one happens before the poll
+            queued.swap(false, Ordering::AcqRel);
+        }
+    }
+
+    /// Queue the task and unpark its executor, see [`Wake::wake_by_ref`]. Takes `&self`, so
+    /// wakers that borrow a `MyWaker` rather than own an `Arc` of it can wake it too, see
+    /// [`compat`](crate::future::compat).
+    pub(crate) fn wake_task(&self) {
+        // The executor shut down, there is no queue to add the task to and the thread may no
+        // longer exist.
+        let Some(ready_queue) = self.ready_queue.upgrade() else {
+            STALE_WAKES.fetch_add(1, Ordering::Relaxed);
+            log::warn!(
+                "waker {0} used after its executor shut down, ignoring.",
+                self.id
+            );
+            return;
+        };
+
+        // Already queued and not polled yet, that poll sees whatever this wake is about.
+        if let Some(queued) = &self.queued {
+            if queued.swap(true, Ordering::AcqRel) {
+                COALESCED_WAKES.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+
+        // A task that is queued already stays where it is, so the urgent queue only applies
+        // to tasks queued by this wake.
+        let urgent = URGENT_WAKES.try_with(Cell::get).unwrap_or(false);
+        let ready_queue = match self.urgent_queue.upgrade() {
+            Some(urgent_queue) if urgent => urgent_queue,
+            _ => ready_queue,
+        };
+
+        // Leave it to whoever is batching wakes on this thread to queue and unpark. `try_with`,
+        // since wakers may also be dropped, and so woken, while thread locals are torn down.
+        let batched = WAKE_BATCH
+            .try_with(|batch| match batch.borrow_mut().as_mut() {
+                Some(batch) => {
+                    batch.add(&ready_queue, self.id, &self.parker);
+                    true
+                }
+                None => false,
+            })
+            .unwrap_or(false);
+        if batched {
+            return;
+        }
+
+        // 1. Add wakers associated task to ready queue
+        // (let executor know it's ready to be polled)
+        //
+        // Be careful of calling unpark before the push is visible, i.e. before the
+        // MutexGuard is dropped when using the Mutex based queue.
+        ready_queue.push(self.id);
+
+        // 2.  Unpark executor if it's yielded control back to the OS scheduler / is parked.
+        self.parker.unpark();
+        log::trace!("Waker {0} woke up executor.", self.id)
+    }
+}
+
+/// A clone queues its task on every wake, as the executor only clears the flag of the waker
+/// it polls the task with.
+impl Clone for MyWaker {
+    fn clone(&self) -> Self {
+        Self {
+            parker: self.parker.clone(),
+            id: self.id,
+            ready_queue: self.ready_queue.clone(),
+            urgent_queue: self.urgent_queue.clone(),
+            queued: None,
+        }
+    }
 }
 
 // NEW: Implement the `Wake` trait from standard library on our Waker.
@@ -86,170 +542,2105 @@ impl Wake for MyWaker {
     /// The function signature of `wake`, means that `MyWaker`
     /// can only be called when wrapped within an `Arc`, i.e. heap allocated.
     fn wake(self: Arc<Self>) {
-        // 1. Add wakers associated task to ready queue
-        // (let executor know it's ready to be polled)
-        //
-        // Be careful of calling unpark before
-        // MutexGuard is dropped.
-        self.ready_queue
-            .lock()
-            .as_deref_mut()
-            .map(|queue| {
-                queue.push(self.id);
-            })
-            .unwrap();
+        self.wake_by_ref();
+    }
 
-        // 2.  Unpark executor if it's yielded control back to the OS scheduler / is parked.
-        self.thread.unpark();
-        println!("Waker {0} woke up executor.", self.id)
+    /// Wakers are shared by all polls of a task, and left with the reactor and primitives,
+    /// so waking one must not take it. Overridden, as the default clones the Arc to `wake` it.
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_task();
+    }
+}
+
+/// Wakes collected by [`batch_wakes`].
+#[derive(Default)]
+struct WakeBatch {
+    /// Ids of woken tasks per ready queue, each id once, in the order they were woken
+    queues: Vec<(Arc<ReadyQueue>, Vec<usize>)>,
+    /// Executors to unpark, each executor once
+    parkers: Vec<Parker>,
+}
+
+impl WakeBatch {
+    fn add(&mut self, ready_queue: &Arc<ReadyQueue>, id: usize, parker: &Parker) {
+        // There are only ever a handful of executors, and a few ids per tick, so linear
+        // searches beat hashing here.
+        match self
+            .queues
+            .iter_mut()
+            .find(|(queue, _)| Arc::ptr_eq(queue, ready_queue))
+        {
+            Some((_, ids)) if ids.contains(&id) => {}
+            Some((_, ids)) => ids.push(id),
+            None => self.queues.push((ready_queue.clone(), vec![id])),
+        }
+
+        if !self.parkers.iter().any(|p| p.ptr_eq(parker)) {
+            self.parkers.push(parker.clone());
+        }
+    }
+}
+
+/// Outcome of [`batch_wakes`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct BatchedWakes {
+    /// Distinct tasks pushed onto a ready queue
+    pub(crate) woken: usize,
+    /// Distinct executor threads unparked
+    pub(crate) unparks: usize,
+}
+
+/// Run `f`, collecting the wakes of [`MyWaker`]s it triggers on the calling thread rather than
+/// carrying each out straight away. Once `f` returns, every woken task is pushed onto its ready
+/// queue once, with a single push per queue, and every executor thread is unparked once.
+///
+/// Wakers that wrap a `MyWaker`, e.g. the per child wakers of a join, are batched as well,
+/// since they end up calling it on this thread.
+pub(crate) fn batch_wakes(f: impl FnOnce()) -> BatchedWakes {
+    let outer = WAKE_BATCH.with(|batch| batch.borrow_mut().replace(WakeBatch::default()));
+    f();
+    let batch = WAKE_BATCH
+        .with(|batch| std::mem::replace(&mut *batch.borrow_mut(), outer))
+        .unwrap_or_default();
+
+    let mut woken = 0;
+    for (queue, ids) in &batch.queues {
+        // push before unparking, same as in `MyWaker::wake`
+        queue.push_all(ids);
+        woken += ids.len();
+    }
+    for parker in &batch.parkers {
+        parker.unpark();
+    }
+    if woken > 0 {
+        log::trace!(
+            "Batch of {woken} wake(s) woke up {} executor(s).",
+            batch.parkers.len()
+        );
+    }
+
+    BatchedWakes {
+        woken,
+        unparks: batch.parkers.len(),
     }
 }
 
+/// Run `f`, queueing the tasks woken by [`MyWaker`]s it triggers on the calling thread as if
+/// they had [`Priority::High`], so they are polled before tasks woken for bulk IO. Used by the
+/// timer thread, since a task woken by its deadline is late already.
+pub(crate) fn wake_urgent(f: impl FnOnce()) {
+    let outer = URGENT_WAKES.with(|urgent| urgent.replace(true));
+    f();
+    URGENT_WAKES.with(|urgent| urgent.set(outer));
+}
+
 /// Allows spawning of new top-level futures (aka Tasks) from anywhere in the thread.
+///
+/// The executor is single threaded for now, but the future must be Send all the same, so it
+/// keeps working once tasks can move between threads. See [`spawn_local`] for futures that
+/// are not, e.g. ones holding an `Rc`.
 pub fn spawn<F>(future: F)
 where
-    F: Future<Output = ()> + 'static,
+    F: Future<Output = ()> + Send + 'static,
 {
-    CURRENT_EXEC.with(|executor| {
-        let next_id = executor.next_id.get();
+    spawn_limited(None, Priority::Normal, Task::Send(Box::pin(future)));
+}
 
-        // NEW: need to now pin the future befoe we can poll it.
-        let task: Task = Box::pin(future);
+/// Same as [`spawn`], but gives the task a name that is used when reporting on it,
+/// e.g. in deadlock reports.
+pub fn spawn_named<F>(name: &str, future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    spawn_limited(
+        Some(name.to_string()),
+        Priority::Normal,
+        Task::Send(Box::pin(future)),
+    );
+}
 
-        executor.tasks.borrow_mut().insert(next_id, task);
+/// Same as [`spawn`], but with a priority for the task. High priority tasks are polled before
+/// any normal task that is ready, e.g. to accept connections promptly while other tasks are
+/// busy moving data.
+pub fn spawn_with_priority<F>(priority: Priority, future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    spawn_limited(None, priority, Task::Send(Box::pin(future)));
+}
 
-        // Add task to queue to ensure it is polled at least once to start progressing it.
-        // Remember that futures are inert / lazy in Rust.
-        if let Ok(queue) = executor.ready_queue.lock().as_deref_mut() {
-            queue.push(next_id)
-        }
+/// Same as [`spawn`], for a future that is not Send. The task stays on the executor of this
+/// thread for good, see [`LocalSet`](super::LocalSet).
+pub fn spawn_local<F>(future: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    spawn_limited(None, Priority::Normal, Task::Local(Box::pin(future)));
+}
 
-        executor.next_id.set(next_id + 1);
-    });
+/// Same as [`spawn_local`], but gives the task a name, see [`spawn_named`].
+pub fn spawn_local_named<F>(name: &str, future: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    spawn_limited(
+        Some(name.to_string()),
+        Priority::Normal,
+        Task::Local(Box::pin(future)),
+    );
 }
 
-/// Requires no state of it's own. All that is in ExecutorCore, which is scoped to a thread.
-pub struct Executor;
+/// Same as [`spawn`], for a task that is boxed already, e.g. one injected through a
+/// [`Handle`].
+pub(super) fn spawn_boxed(
+    name: Option<String>,
+    priority: Priority,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+) {
+    spawn_limited(name, priority, Task::Send(future));
+}
 
-impl Executor {
-    pub fn new() -> Self {
-        Self
-    }
+/// Injection queue of the executor on this thread, see [`Handle`].
+pub(super) fn injector() -> Arc<Injector> {
+    CURRENT_EXEC.with(|executor| executor.injector.clone())
+}
 
-    /// Pop a task id from ready_queue, return None if queue is empty.
-    fn pop_ready(&self) -> Option<usize> {
-        CURRENT_EXEC.with(|executor| {
+/// Spawn the task if the executor has room for it, otherwise follow its [`Backpressure`]
+/// policy.
+fn spawn_limited(name: Option<String>, priority: Priority, task: Task) {
+    let full =
+        CURRENT_EXEC.with(|executor| executor.task_limit.get().filter(|_| !executor.has_room()));
+
+    match full {
+        None => {
+            spawn_inner(name, priority, task);
+        }
+        Some((limit, Backpressure::Panic)) => panic!("Executor is at its limit of {limit} tasks"),
+        Some((limit, Backpressure::Reject)) => {
+            log::warn!("Executor is at its limit of {limit} tasks, task rejected.");
+            Executor::CORE.count(|counters| &counters.rejected);
+            // dropped outside of the borrow, destructors may spawn tasks of their own
+            drop(task);
+        }
+        Some((_, Backpressure::Wait)) => CURRENT_EXEC.with(|executor| {
             executor
-                .ready_queue
-                .lock()
-                .as_deref_mut()
-                .map(|queue| queue.pop())
-                .unwrap()
-        })
+                .backlog
+                .borrow_mut()
+                .push_back((name, priority, task))
+        }),
     }
+}
 
-    /// WARNING: also remove tasks for hash map of (id, Task)
-    /// This is to prvent accidently trying retrieving the task and poll it even after
-    /// it has completed. Instead, we get the task from the hash map.
-    /// We then poll the Task. If it returns `NotReady`, then we add it back in to hash map.
-    fn get_future(&self, id: usize) -> Option<Task> {
-        CURRENT_EXEC.with(|executor| {
-            let task: Option<Task> = executor.tasks.borrow_mut().remove(&id);
+/// Whether the executor of this thread has room for another task, see [`try_spawn`](super::try_spawn).
+pub(super) fn has_room() -> bool {
+    CURRENT_EXEC.with(ExecutorCore::has_room)
+}
 
-            task
-        })
-    }
+/// Hold room for a task if there is any, otherwise wake `waker` once a task finished. Returns
+/// whether room was reserved, see [`reserve`](super::reserve).
+pub(super) fn reserve_slot(waker: &Waker) -> bool {
+    CURRENT_EXEC.with(|executor| {
+        if executor.has_room() && executor.backlog.borrow().is_empty() {
+            executor.reserved.set(executor.reserved.get() + 1);
+            return true;
+        }
 
-    fn get_waker(&self, id: usize) -> Arc<MyWaker> {
-        let ready_queue = CURRENT_EXEC.with(|executor| executor.ready_queue.clone());
+        let mut admission = executor.admission.borrow_mut();
+        if !admission.iter().any(|waiting| waiting.will_wake(waker)) {
+            admission.push(waker.clone());
+        }
+        false
+    })
+}
 
-        Arc::new(MyWaker {
-            id,
-            thread: thread::current(),
-            ready_queue,
-        })
-    }
+/// Spawn a task in room reserved by a [`Permit`](super::Permit), which is released after.
+pub(super) fn spawn_reserved(future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+    spawn_inner(None, Priority::Normal, Task::Send(future));
+}
 
-    /// Simply inserts the task into the hash map on ExecutorCore. It does not
-    /// queue the task onto the ready_queue.
-    fn insert_task(&self, id: usize, task: Task) {
-        CURRENT_EXEC.with(|executor| {
-            executor.tasks.borrow_mut().insert(id, task);
-        })
+/// Give back the room held by a dropped [`Permit`](super::Permit).
+pub(super) fn release_slot() {
+    // `try_with`, as permits may be dropped while thread locals are torn down
+    let released = CURRENT_EXEC
+        .try_with(|executor| executor.reserved.set(executor.reserved.get() - 1))
+        .is_ok();
+    if released {
+        admit_next();
     }
+}
 
-    fn task_count(&self) -> usize {
-        CURRENT_EXEC.with(|executor| executor.tasks.borrow().len())
+/// Hand room freed up by a task or permit to the oldest task held back, or else to the tasks
+/// waiting for room, all of them, as the first to be polled takes it.
+fn admit_next() {
+    let next = CURRENT_EXEC.with(|executor| {
+        if !executor.has_room() {
+            return None;
+        }
+        let next = executor.backlog.borrow_mut().pop_front();
+        if next.is_none() {
+            let waiting = std::mem::take(&mut *executor.admission.borrow_mut());
+            return Some(Err(waiting));
+        }
+        next.map(Ok)
+    });
+
+    match next {
+        Some(Ok((name, priority, task))) => {
+            spawn_inner(name, priority, task);
+        }
+        Some(Err(waiting)) => waiting.iter().for_each(Waker::wake_by_ref),
+        None => {}
     }
+}
 
-    /// IMPORTANT: core logic of the executor.
-    pub fn block_on<F>(&mut self, future: F)
-    where
-        F: Future<Output = ()> + 'static,
-    {
-        // NEW: there are some futures that return Ready on first poll, so we add an optimisation
-        // to poll all futures at least once.
-        //
-        // WARNING: by polling the future once here, the future is thus located within the stack
-        // frame of the `block_on` function. The act of polling it results in self.stack.writer
-        // holding a reference to buffer, i.e. a self reference. The first poll returns `NotReady`,
-        // and so we spawn it, placing it within a Box, which moves the future onto the heap.
-        // The next time the future is polled, the stack will be restored. However, the reference
-        // held by self.stack.writer will be invalid as it is pointing to the old location on the
-        // stack where the future was located.
-        // let mut waker = self.get_waker(usize::MAX);
-        // let mut future = future;
+/// Returns the id of the new task.
+fn spawn_inner(name: Option<String>, priority: Priority, task: Task) -> usize {
+    CURRENT_EXEC.with(|executor| {
+        let next_id = executor.next_id.get();
 
-        // match Box::pin(future).as_mut().poll(&waker) {
-        //     // future needs to be waited on
-        //     PollState::NotReady => {}
-        //     // future is ready, no need to block, so return
-        //     PollState::Ready(_) => return,
-        // }
+        executor.tasks.borrow_mut().insert(next_id, task);
+        executor
+            .spawned_at
+            .borrow_mut()
+            .insert(next_id, Instant::now());
+        ExecutorCounters::bump(&executor.metrics.spawned);
 
-        // spawn the future on the executor, making it a top-level task
+        if let Some(name) = name {
+            executor.names.borrow_mut().insert(next_id, name);
+        }
+
+        if priority != Priority::Normal {
+            executor.priorities.borrow_mut().insert(next_id, priority);
+        }
+
+        // Add task to queue to ensure it is polled at least once to start progressing it.
+        // Remember that futures are inert / lazy in Rust.
+        executor.queue_for(next_id).push(next_id);
+
+        executor.next_id.set(next_id + 1);
+        next_id
+    })
+}
+
+/// Id of the task currently being polled on this thread, if any.
+pub(crate) fn current_task() -> Option<usize> {
+    CURRENT_EXEC.with(|executor| executor.current.get())
+}
+
+/// Whether `block_on` is driving this thread's executor, see [`Handle`](super::Handle).
+pub(crate) fn is_running() -> bool {
+    CURRENT_EXEC.with(|executor| executor.running.get())
+}
+
+/// Hold on to `waker` until no other task on this thread's executor is ready, rather than
+/// waking it right away. Returns false, leaving the wake to the caller, outside of a task.
+///
+/// Woken right away, a task that yields would be queued on top of everything else that is
+/// ready and polled again straight after, see [`yield_now`](super::yield_now).
+pub(crate) fn defer_wake(waker: &Waker) -> bool {
+    CURRENT_EXEC.with(|executor| {
+        if executor.current.get().is_none() {
+            return false;
+        }
+
+        executor.deferred.borrow_mut().push(waker.clone());
+        true
+    })
+}
+
+/// Number of wakes that were ignored because they came after the waker's executor shut down.
+pub fn stale_wakes() -> usize {
+    STALE_WAKES.load(Ordering::Relaxed)
+}
+
+/// Number of wakes of a task that was still queued from an earlier wake, which did not queue
+/// it again. Each would otherwise have been polled as a spurious wake.
+pub fn coalesced_wakes() -> usize {
+    COALESCED_WAKES.load(Ordering::Relaxed)
+}
+
+/// Validate runtime invariants every `interval` on this thread's executor.
+pub(super) fn enable_self_check(interval: Duration) {
+    CURRENT_EXEC.with(|executor| executor.self_check.set(Some(interval)));
+}
+
+/// Record that the task currently being polled is blocked on `resource`.
+pub(super) fn set_waiting_on(resource: Resource) {
+    CURRENT_EXEC.with(|executor| {
+        if let Some(id) = executor.current.get() {
+            executor.waits.borrow_mut().insert(id, resource);
+        }
+    })
+}
+
+/// Record how far the task currently being polled got, shown by [`Executor::dump_tasks`]
+/// until the task reports again or finishes. Does nothing outside of a task.
+///
+/// The executor polls std futures, which have no way to be asked for their progress like
+/// [`crate::future::Future::progress`], so leaf futures report it from within `poll` instead.
+pub fn report_progress(progress: Progress) {
+    CURRENT_EXEC.with(|executor| {
+        if let Some(id) = executor.current.get() {
+            executor.progress.borrow_mut().insert(id, progress);
+        }
+    })
+}
+
+/// Set the value of task local `key` for the task currently being polled. Returns false,
+/// setting nothing, when called outside of a task.
+pub(super) fn set_task_local(key: usize, value: Rc<dyn Any>) -> bool {
+    let replaced = CURRENT_EXEC.with(|executor| {
+        let id = executor.current.get()?;
+        let mut locals = executor.locals.borrow_mut();
+        Some(locals.entry(id).or_default().insert(key, value))
+    });
+
+    // dropped outside of the borrow, destructors may use task locals themselves
+    replaced.is_some()
+}
+
+/// Value of task local `key` for the task currently being polled, if it has one.
+pub(super) fn task_local(key: usize) -> Option<Rc<dyn Any>> {
+    CURRENT_EXEC.with(|executor| {
+        let id = executor.current.get()?;
+        executor.locals.borrow().get(&id)?.get(&key).cloned()
+    })
+}
+
+/// Open scope `scope` on this thread's executor, see [`scope`](super::scope).
+pub(super) fn open_scope(scope: usize) {
+    CURRENT_EXEC.with(|executor| {
+        executor
+            .scopes
+            .borrow_mut()
+            .insert(scope, ScopeState::default());
+    })
+}
+
+/// Same as [`spawn`], for a child of scope `scope`.
+pub(super) fn spawn_scoped(scope: usize, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+    let id = spawn_inner(None, Priority::Normal, Task::Send(future));
+
+    CURRENT_EXEC.with(|executor| {
+        if let Some(state) = executor.scopes.borrow_mut().get_mut(&scope) {
+            state.children.insert(id);
+            executor.parents.borrow_mut().insert(id, scope);
+        }
+    })
+}
+
+/// Returns None, closing the scope, if every child of scope `scope` finished. Otherwise returns
+/// the oldest child still pending, and `waker` is woken once the last one finished.
+pub(super) fn poll_scope(scope: usize, waker: &Waker) -> Option<usize> {
+    CURRENT_EXEC.with(|executor| {
+        let mut scopes = executor.scopes.borrow_mut();
+        let state = scopes.get_mut(&scope)?;
+
+        let Some(oldest) = state.children.iter().min().copied() else {
+            scopes.remove(&scope);
+            return None;
+        };
+
+        if !state.waker.as_ref().is_some_and(|w| w.will_wake(waker)) {
+            state.waker = Some(waker.clone());
+        }
+        Some(oldest)
+    })
+}
+
+/// Close scope `scope`, cancelling the children that have not finished, most recently spawned
+/// first. Returns the number of children cancelled.
+///
+/// A child that is being polled, i.e. the one dropping the scope, can not be dropped from
+/// within its own poll. It is left to run as a task of its own.
+pub(super) fn close_scope(scope: usize) -> usize {
+    // `try_with`, as scopes may be dropped while thread locals are torn down
+    let state = CURRENT_EXEC
+        .try_with(|executor| executor.scopes.borrow_mut().remove(&scope))
+        .ok()
+        .flatten();
+    let Some(state) = state else {
+        return 0;
+    };
+
+    let mut children: Vec<_> = state.children.into_iter().collect();
+    children.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut cancelled = 0;
+    for id in children {
+        CURRENT_EXEC.with(|executor| executor.parents.borrow_mut().remove(&id));
+
+        let Some(task) = Executor::CORE.get_future(id) else {
+            continue;
+        };
+        // dropped outside of the borrow, the child may close scopes of its own
+        drop(task);
+        Executor::CORE.remove_task_info(id, Finish::Cancelled);
+        Executor::CORE.count(|counters| &counters.cancelled);
+        cancelled += 1;
+    }
+
+    cancelled
+}
+
+/// What [`Executor::block_on_with`] does with tasks still pending once its future completed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExitPolicy {
+    /// Keep running until every task completed
+    #[default]
+    WaitForAll,
+    /// Keep running until every task completed, or the deadline passed, whichever comes
+    /// first. Tasks still pending at the deadline are cancelled.
+    WaitWithDeadline(Duration),
+    /// Cancel pending tasks straight away
+    CancelRemaining,
+}
+
+impl ExitPolicy {
+    /// When to cancel pending tasks, given the future passed to `block_on` completed `now`.
+    fn cancel_at(self, now: Instant) -> Option<Instant> {
+        match self {
+            Self::WaitForAll => None,
+            Self::WaitWithDeadline(deadline) => Some(now + deadline),
+            Self::CancelRemaining => Some(now),
+        }
+    }
+}
+
+/// What [`Executor::block_on_with`] does when polling one of its tasks panics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Drop the task and keep running the others, see [`Executor::take_panics`]. A panic of
+    /// the future handed to `block_on` itself still unwinds out of `block_on`, once the other
+    /// tasks are cancelled.
+    ///
+    /// Not the default in the crate's own tests, so failed assertions in tasks fail the test.
+    #[cfg_attr(not(test), default)]
+    Isolate,
+    /// Unwind out of `block_on` straight away, leaving the other tasks behind
+    #[cfg_attr(test, default)]
+    Abort,
+}
+
+/// How the executor waits for a wake up once no task is ready, see
+/// [`Executor::with_park_strategy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParkStrategy {
+    /// Block the thread until woken
+    #[default]
+    Park,
+    /// Check for a wake up `n` times before blocking, busy waiting in between for the first
+    /// few checks and yielding the thread after. Saves blocking and being unparked when wake
+    /// ups come in close to each other, at the cost of CPU time while idle.
+    Spin(u32),
+    /// Same as [`ParkStrategy::Spin`], with the number of checks adapting to how wake ups come
+    /// in, up to the `n` given. Starts with a single check, doubling them each time a check
+    /// catches the wake up or the executor was woken soon after blocking, and halving them
+    /// each time it blocked for longer. Streams of quick completions skip the park, while an
+    /// idle executor barely spins.
+    Adaptive(u32),
+}
+
+/// A task dropped because polling it panicked, see [`PanicPolicy::Isolate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskPanic {
+    pub id: usize,
+    /// Name given via `spawn_named`
+    pub name: Option<String>,
+    /// Message of the panic, if it was a string
+    pub message: Option<String>,
+}
+
+/// A pending task and what it is waiting on, see [`Executor::dump_tasks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskDump {
+    pub id: usize,
+    /// Name given via `spawn_named`
+    pub name: Option<String>,
+    /// Times the task has been polled so far
+    pub polls: usize,
+    /// Time since the task was spawned
+    pub pending_for: Duration,
+    /// Reactor ids the task left a waker with, and the events each is registered for,
+    /// ordered by id
+    pub io: Vec<(usize, Interest)>,
+    /// Kind and id of the synchronisation primitive the task is blocked on, if any
+    pub blocked_on: Option<(&'static str, usize)>,
+    /// Last progress the task reported, see [`report_progress`]
+    pub progress: Option<Progress>,
+}
+
+impl fmt::Display for TaskDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "'{name}' (task {})", self.id)?,
+            None => write!(f, "task {}", self.id)?,
+        }
+        write!(
+            f,
+            ": polled {} times, pending for {:?}",
+            self.polls, self.pending_for
+        )?;
+        for (id, interest) in &self.io {
+            write!(f, ", io {id} {interest:?}")?;
+        }
+        if let Some((kind, id)) = self.blocked_on {
+            write!(f, ", blocked on {kind} {id}")?;
+        }
+        if let Some(progress) = self.progress {
+            write!(f, ", progress {progress}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Requires no state of it's own. All that is in ExecutorCore, which is scoped to a thread.
+///
+/// The executors of a thread share its core. Dropping the last of them cancels the tasks it
+/// left pending, e.g. once a panic unwound out of `block_on`, so an executor created on the
+/// thread later does not inherit them.
+pub struct Executor {
+    /// Whether this is one of the executors handed out, rather than [`Executor::CORE`]
+    counted: bool,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor {
+    /// Reaches the core of this thread from within the crate, without counting as one of its
+    /// executors.
+    const CORE: Executor = Executor { counted: false };
+
+    pub fn new() -> Self {
+        CURRENT_EXEC.with(|executor| executor.handles.set(executor.handles.get() + 1));
+
+        Self { counted: true }
+    }
+
+    /// Same as [`Executor::new`], but selects the ready queue implementation used by the
+    /// executor on this thread, see [`ReadyQueueKind`].
+    ///
+    /// Panics if the executor on this thread still has tasks, whose wakers point at the
+    /// current queue.
+    pub fn with_ready_queue(kind: ReadyQueueKind) -> Self {
+        CURRENT_EXEC.with(|executor| {
+            assert!(
+                executor.tasks.borrow().is_empty(),
+                "Can not switch ready queue while tasks are pending"
+            );
+            *executor.ready_queue.borrow_mut() = Arc::new(ReadyQueue::new(kind));
+            *executor.urgent_queue.borrow_mut() = Arc::new(ReadyQueue::new(kind));
+        });
+
+        Self::new()
+    }
+
+    /// Same as [`Executor::new`], but the executor on this thread runs deterministically, see
+    /// [`deterministic`](super::deterministic): tasks that are ready at the same time are
+    /// polled in an order drawn from `seed`, and [`sleep`](super::sleep) runs on a virtual
+    /// clock.
+    ///
+    /// Runs with the same seed interleave tasks the same way, as long as nothing outside of
+    /// this thread wakes them, e.g. the reactor. Use a [`MockReactor`](super::MockReactor)
+    /// instead.
+    pub fn with_seed(seed: u64) -> Self {
+        CURRENT_EXEC.with(|executor| {
+            *executor.seeded.borrow_mut() = Some(SeededOrder::new(seed));
+        });
+        deterministic::install_clock();
+
+        Self::new()
+    }
+
+    /// Same as [`Executor::new`], but selects what the executor on this thread does when
+    /// polling a task panics, see [`PanicPolicy`].
+    pub fn with_panic_policy(policy: PanicPolicy) -> Self {
+        CURRENT_EXEC.with(|executor| executor.panic_policy.set(policy));
+
+        Self::new()
+    }
+
+    /// Same as [`Executor::new`], but selects how the executor on this thread waits for a
+    /// wake up once no task is ready, see [`ParkStrategy`].
+    pub fn with_park_strategy(strategy: ParkStrategy) -> Self {
+        CURRENT_EXEC.with(|executor| executor.park_strategy.set(strategy));
+
+        Self::new()
+    }
+
+    /// Same as [`Executor::new`], but `block_on` panics rather than park forever once tasks
+    /// are pending with nothing that could wake them: no ready tasks, and no IO, timers or
+    /// io_uring operations registered by this executor. Tasks in that state may still be woken
+    /// by another thread, so they get `timeout` for it before the panic lists them.
+    pub fn with_watchdog(timeout: Duration) -> Self {
+        CURRENT_EXEC.with(|executor| executor.watchdog.set(Some(timeout)));
+
+        Self::new()
+    }
+
+    /// Same as [`Executor::new`], but the executor on this thread takes at most `limit` tasks
+    /// at once, the future handed to `block_on` included. Spawning beyond them follows
+    /// `policy`, see [`Backpressure`], and tasks can wait for room via
+    /// [`reserve`](super::reserve).
+    pub fn with_task_limit(limit: usize, policy: Backpressure) -> Self {
+        assert!(
+            limit > 0,
+            "Task limit must leave room for at least one task"
+        );
+        CURRENT_EXEC.with(|executor| executor.task_limit.set(Some((limit, policy))));
+
+        Self::new()
+    }
+
+    /// Handle to spawn tasks onto this executor from other threads, see [`Handle`].
+    pub fn handle(&self) -> Handle {
+        Handle::for_current_thread()
+    }
+
+    /// Tasks that panicked since the last call, oldest first.
+    pub fn take_panics(&self) -> Vec<TaskPanic> {
+        CURRENT_EXEC.with(|executor| executor.panics.take())
+    }
+
+    /// Counters of the executor on this thread.
+    pub fn metrics(&self) -> ExecutorMetrics {
+        CURRENT_EXEC.with(|executor| {
+            let counters = &executor.metrics;
+            let pending_polls = executor.polls.borrow().values().copied().max();
+
+            ExecutorMetrics {
+                spawned: counters.spawned.get(),
+                completed: counters.completed.get(),
+                cancelled: counters.cancelled.get(),
+                panicked: counters.panicked.get(),
+                pending: executor.tasks.borrow().len(),
+                polls: counters.polls.get(),
+                max_polls: counters.max_polls.get().max(pending_polls.unwrap_or(0)),
+                ready_depth: executor.ready_depth(),
+                max_ready_depth: counters.max_ready_depth.get(),
+                wakes: counters.wakes.get(),
+                wake_to_poll: counters.wake_to_poll.get(),
+                max_wake_to_poll: counters.max_wake_to_poll.get(),
+                spurious_wakes: counters.spurious_wakes.get(),
+                parks: counters.parks.get(),
+                parks_avoided: counters.parks_avoided.get(),
+                rejected: counters.rejected.get(),
+            }
+        })
+    }
+
+    /// Every pending task on this thread's executor, ordered by id: how often it has been
+    /// polled, how long it has been pending, and what it is waiting on.
+    ///
+    /// Meant for debugging a hung program, e.g. from a task that dumps the others on a
+    /// signal. IO is only listed for wakers left with the reactor from within the task, and
+    /// interests are those the ids are registered with when this is called.
+    pub fn dump_tasks(&self) -> Vec<TaskDump> {
+        let mut io: HashMap<usize, Vec<(usize, Interest)>> = HashMap::new();
+        for (id, task) in reactor::io_owned_by_current_thread() {
+            if let Some(interest) = reactor::reactor().interest(id) {
+                io.entry(task).or_default().push((id, interest));
+            }
+        }
+
+        CURRENT_EXEC.with(|executor| {
+            let now = Instant::now();
+            let names = executor.names.borrow();
+            let waits = executor.waits.borrow();
+            let polls = executor.polls.borrow();
+            let spawned_at = executor.spawned_at.borrow();
+            let progress = executor.progress.borrow();
+
+            let mut ids: Vec<_> = executor.tasks.borrow().keys().copied().collect();
+            // the task being polled is taken out of `tasks` for the duration
+            ids.extend(executor.current.get());
+            ids.sort();
+
+            ids.into_iter()
+                .map(|id| {
+                    let mut io = io.remove(&id).unwrap_or_default();
+                    io.sort_by_key(|(id, _)| *id);
+
+                    TaskDump {
+                        id,
+                        name: names.get(&id).cloned(),
+                        polls: polls.get(&id).copied().unwrap_or(0),
+                        pending_for: spawned_at
+                            .get(&id)
+                            .map_or(Duration::ZERO, |at| now.duration_since(*at)),
+                        io,
+                        blocked_on: waits.get(&id).map(|resource| (resource.kind, resource.id)),
+                        progress: progress.get(&id).copied(),
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// Pop a task id from ready_queue, return None if queue is empty. High priority tasks
+    /// are popped first.
+    fn pop_ready(&self) -> Option<Entry> {
+        CURRENT_EXEC.with(|executor| {
+            let depth = &executor.metrics.max_ready_depth;
+            depth.set(depth.get().max(executor.ready_depth()));
+
+            if let Some(seeded) = executor.seeded.borrow_mut().as_mut() {
+                return seeded.pick(
+                    &executor.urgent_queue.borrow(),
+                    &executor.ready_queue.borrow(),
+                );
+            }
+
+            let urgent = executor.urgent_queue.borrow().pop_entry();
+            urgent.or_else(|| executor.ready_queue.borrow().pop_entry())
+        })
+    }
+
+    /// Next task to poll, tasks that yielded only once no other task is ready.
+    fn next_ready(&self) -> Option<Entry> {
+        self.pop_ready().or_else(|| {
+            let deferred = CURRENT_EXEC.with(|executor| executor.deferred.take());
+            if deferred.is_empty() {
+                return None;
+            }
+
+            deferred.into_iter().for_each(Waker::wake);
+            self.pop_ready()
+        })
+    }
+
+    /// Bump one of the counters behind [`Executor::metrics`].
+    fn count(&self, counter: fn(&ExecutorCounters) -> &Cell<usize>) {
+        CURRENT_EXEC.with(|executor| ExecutorCounters::bump(counter(&executor.metrics)));
+    }
+
+    /// Count a poll of task `id`, which was queued at `queued_at`.
+    fn record_poll(&self, id: usize, queued_at: Instant) {
+        CURRENT_EXEC.with(|executor| {
+            ExecutorCounters::bump(&executor.metrics.polls);
+            *executor.polls.borrow_mut().entry(id).or_default() += 1;
+            executor.metrics.record_wake(queued_at);
+        })
+    }
+
+    /// WARNING: also remove tasks for hash map of (id, Task)
+    /// This is to prvent accidently trying retrieving the task and poll it even after
+    /// it has completed. Instead, we get the task from the hash map.
+    /// We then poll the Task. If it returns `NotReady`, then we add it back in to hash map.
+    fn get_future(&self, id: usize) -> Option<Task> {
+        CURRENT_EXEC.with(|executor| {
+            let task: Option<Task> = executor.tasks.borrow_mut().remove(&id);
+
+            task
+        })
+    }
+
+    /// The waker of task `id`, the same one for every poll of the task.
+    fn get_waker(&self, id: usize) -> Arc<MyWaker> {
+        if let Some(waker) =
+            CURRENT_EXEC.with(|executor| executor.wakers.borrow().get(&id).cloned())
+        {
+            return waker;
+        }
+
+        let waker = self.new_waker(id);
+        CURRENT_EXEC.with(|executor| executor.wakers.borrow_mut().insert(id, waker.clone()));
+        waker
+    }
+
+    /// Wakers come from a pool, since one is needed for every task, see `recycle_waker`.
+    fn new_waker(&self, id: usize) -> Arc<MyWaker> {
+        let waker = CURRENT_EXEC.with(|executor| {
+            MyWaker::for_task(id, &executor.queue_for(id), &executor.urgent_queue.borrow())
+        });
+
+        match LOCAL_WAKERS.with(|pool| pool.take()) {
+            Some(mut pooled) => {
+                // only unused wakers are pooled, see `recycle_waker`
+                *Arc::get_mut(&mut pooled).unwrap() = waker;
+                pooled
+            }
+            None => Arc::new(waker),
+        }
+    }
+
+    /// Hand a waker back to the pool once its task is done with it, unless a clone is still
+    /// around, e.g. left with the reactor or a primitive.
+    fn recycle_waker(&self, mut waker: Arc<MyWaker>) {
+        let Some(unused) = Arc::get_mut(&mut waker) else {
+            return;
+        };
+        // do not keep the executor's queues alive through the pool
+        unused.ready_queue = Weak::new();
+        unused.urgent_queue = Weak::new();
+
+        // `try_with`, as tasks may be polled while thread locals are torn down
+        let _ = LOCAL_WAKERS.try_with(|pool| pool.put(waker));
+    }
+
+    /// Simply inserts the task into the hash map on ExecutorCore. It does not
+    /// queue the task onto the ready_queue.
+    fn insert_task(&self, id: usize, task: Task) {
+        CURRENT_EXEC.with(|executor| {
+            executor.tasks.borrow_mut().insert(id, task);
+        })
+    }
+
+    fn task_count(&self) -> usize {
+        CURRENT_EXEC.with(|executor| executor.tasks.borrow().len())
+    }
+
+    /// Track which task is being polled. Any wait recorded for the task during a previous poll
+    /// is cleared, since being polled means it is no longer blocked on it.
+    fn set_current(&self, id: Option<usize>) {
+        CURRENT_EXEC.with(|executor| {
+            if let Some(id) = id {
+                executor.waits.borrow_mut().remove(&id);
+            }
+            executor.current.set(id);
+        })
+    }
+
+    /// Record that polling task `id` panicked with `payload`, before its bookkeeping is cleared.
+    fn record_panic(&self, id: usize, payload: &(dyn Any + Send)) {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned());
+
+        CURRENT_EXEC.with(|executor| {
+            let name = executor.names.borrow().get(&id).cloned();
+            log::error!(
+                "task {id} ({}) panicked: {}",
+                name.as_deref().unwrap_or("unnamed"),
+                message.as_deref().unwrap_or("<non-string payload>")
+            );
+
+            ExecutorCounters::bump(&executor.metrics.panicked);
+            executor
+                .panics
+                .borrow_mut()
+                .push(TaskPanic { id, name, message });
+        });
+    }
+
+    /// Count a wake of task `id`, which is not pending, see [`Tombstones`].
+    fn spurious_wake(&self, id: usize) {
+        CURRENT_EXEC.with(|executor| {
+            ExecutorCounters::bump(&executor.metrics.spurious_wakes);
+
+            if id >= executor.next_id.get() {
+                log::warn!("task {id} woken, but no task with that id was ever spawned here");
+                return;
+            }
+
+            match executor.tombstones.borrow().get(id) {
+                Some(finish) => log::debug!("task {id} woken after it {finish}"),
+                None => log::debug!("task {id} woken long after it finished"),
+            }
+        })
+    }
+
+    /// Clear all bookkeeping for a finished task
+    fn remove_task_info(&self, id: usize, finish: Finish) {
+        let (locals, scope_done) = CURRENT_EXEC.with(|executor| {
+            executor.tombstones.borrow_mut().bury(id, finish);
+            executor.names.borrow_mut().remove(&id);
+            executor.waits.borrow_mut().remove(&id);
+            executor.priorities.borrow_mut().remove(&id);
+            if let Some(waker) = executor.wakers.borrow_mut().remove(&id) {
+                self.recycle_waker(waker);
+            }
+
+            executor.spawned_at.borrow_mut().remove(&id);
+            executor.progress.borrow_mut().remove(&id);
+            let polls = executor.polls.borrow_mut().remove(&id).unwrap_or(0);
+            let max_polls = &executor.metrics.max_polls;
+            max_polls.set(max_polls.get().max(polls));
+
+            // the last child of a scope wakes the task awaiting it
+            let scope_done = executor.parents.borrow_mut().remove(&id).and_then(|scope| {
+                let mut scopes = executor.scopes.borrow_mut();
+                let state = scopes.get_mut(&scope)?;
+                state.children.remove(&id);
+                state.children.is_empty().then(|| state.waker.take())?
+            });
+
+            (executor.locals.borrow_mut().remove(&id), scope_done)
+        });
+        // dropped outside of the borrow, destructors may use task locals themselves
+        drop(locals);
+        if let Some(waker) = scope_done {
+            waker.wake();
+        }
+
+        let stale = reactor::purge_task(id);
+        if stale > 0 {
+            log::warn!("task {id} finished with {stale} IO waker(s) left behind, removed.");
+        }
+
+        admit_next();
+    }
+
+    /// Drop every pending task, most recently spawned first, so tasks are torn down before the
+    /// tasks that spawned them. Dropping a task runs the destructors of everything it holds,
+    /// e.g. deregistering its sources with the reactor. Returns the number of tasks dropped.
+    fn cancel_remaining(&self) -> usize {
+        let mut cancelled = 0;
+
+        // destructors may spawn tasks of their own, so go until none are left
+        loop {
+            let newest =
+                CURRENT_EXEC.with(|executor| executor.tasks.borrow().keys().max().copied());
+            let Some(id) = newest else {
+                break;
+            };
+
+            // dropped outside of the borrow, destructors may wake or spawn tasks
+            drop(self.get_future(id));
+            self.remove_task_info(id, Finish::Cancelled);
+            cancelled += 1;
+            self.count(|counters| &counters.cancelled);
+        }
+
+        cancelled
+    }
+
+    /// Validate runtime invariants if self checks are enabled and the interval has passed since
+    /// the last check, printing a report for any violations.
+    fn maybe_self_check(&self) {
+        let snapshot = CURRENT_EXEC.with(|executor| {
+            let interval = executor.self_check.get()?;
+            let now = Instant::now();
+
+            if executor
+                .last_check
+                .get()
+                .is_some_and(|last| now.duration_since(last) < interval)
+            {
+                return None;
+            }
+            executor.last_check.set(Some(now));
+
+            Some(self_check::Snapshot {
+                tasks: executor.tasks.borrow().keys().copied().collect(),
+                ready: [&executor.urgent_queue, &executor.ready_queue]
+                    .iter()
+                    .flat_map(|queue| queue.borrow().snapshot())
+                    .chain(executor.seeded.borrow().iter().flat_map(SeededOrder::ready))
+                    .collect(),
+                io: reactor::io_owned_by_current_thread(),
+                completions: uring::waiting_tasks(),
+                timers: crate::sim::waiting_tasks()
+                    .into_iter()
+                    .chain(timer::waiting_tasks())
+                    .chain(deterministic::waiting_tasks())
+                    .collect(),
+                primitives: executor.waits.borrow().keys().copied().collect(),
+                current: executor.current.get(),
+            })
+        });
+
+        let Some(snapshot) = snapshot else {
+            return;
+        };
+
+        let violations = self_check::check(&snapshot);
+        if !violations.is_empty() {
+            let report: String = violations
+                .iter()
+                .map(|violation| format!("\n  - {violation}"))
+                .collect();
+            log::error!("runtime self check failed:{report}");
+        }
+    }
+
+    /// Detach all wakers handed out so far, once every task has finished.
+    ///
+    /// Dropping the ready queue they point to turns any later wake into a counted no-op, rather
+    /// than a wake up for a task that no longer exists. A new queue is put in place, so the
+    /// executor on this thread can be used again.
+    fn shutdown(&self, thread_name: &str) {
+        CURRENT_EXEC.with(|executor| {
+            let kind = executor.ready_queue.borrow().kind();
+            *executor.ready_queue.borrow_mut() = Arc::new(ReadyQueue::new(kind));
+            *executor.urgent_queue.borrow_mut() = Arc::new(ReadyQueue::new(kind));
+            // left behind by tasks cancelled after yielding
+            executor.deferred.borrow_mut().clear();
+        });
+
+        let stale = stale_wakes();
+        if stale > 0 {
+            log::debug!("{thread_name}: {stale} wake(s) after shutdown ignored so far.");
+        }
+    }
+
+    /// Park until woken up. With self checks enabled we wake up at least once per interval,
+    /// so the checks keep running while the executor is idle.
+    fn park(&self) {
+        let parker = Parker::current();
+
+        let strategy = CURRENT_EXEC.with(|executor| executor.park_strategy.get());
+        let checks = match strategy {
+            ParkStrategy::Park => 0,
+            ParkStrategy::Spin(checks) => checks,
+            ParkStrategy::Adaptive(max) => {
+                CURRENT_EXEC.with(|executor| executor.spin_window.get().clamp(1, max.max(1)))
+            }
+        };
+        let woken = (0..checks).any(|check| {
+            if parker.try_park() {
+                return true;
+            }
+            if check < BUSY_CHECKS {
+                std::hint::spin_loop();
+            } else {
+                thread::yield_now();
+            }
+            false
+        });
+
+        let quick = if woken {
+            self.count(|counters| &counters.parks_avoided);
+            true
+        } else {
+            self.count(|counters| &counters.parks);
+            let parked_at = Instant::now();
+            match CURRENT_EXEC.with(|executor| executor.self_check.get()) {
+                Some(interval) => {
+                    parker.park_timeout(interval);
+                }
+                None => parker.park(),
+            }
+            parked_at.elapsed() < QUICK_WAKE
+        };
+
+        // slow start: spin for longer while wake ups come in quickly, back off once they do not
+        if let ParkStrategy::Adaptive(max) = strategy {
+            let window = if quick {
+                checks.saturating_mul(2)
+            } else {
+                checks / 2
+            };
+            CURRENT_EXEC.with(|executor| executor.spin_window.set(window.clamp(1, max.max(1))));
+        }
+    }
+
+    /// Spawn the tasks injected from other threads, returning how many there were.
+    fn drain_injected(&self) -> usize {
+        let injector = CURRENT_EXEC.with(|executor| executor.injector.clone());
+        injector.drain()
+    }
+
+    /// Returns a report if tasks are deadlocked, and still are after a grace period.
+    ///
+    /// A primitive may be released from another thread, e.g. a channel whose sender was moved
+    /// to a different executor. We can not see that, so rather than report straight away, we
+    /// give other threads [`DEADLOCK_GRACE`] to wake one of our tasks.
+    fn wait_out_deadlock(&self) -> Option<String> {
+        let mut report = self.detect_deadlock()?;
+        let deadline = Instant::now() + DEADLOCK_GRACE;
+
+        // park_timeout may return early, e.g. due to a stale unpark, so loop until the deadline
+        let parker = Parker::current();
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            parker.park_timeout(remaining);
+            report = self.detect_deadlock()?;
+        }
+
+        Some(report)
+    }
+
+    /// Returns a report of the stuck tasks if the watchdog is set and nothing woke any of them
+    /// before it ran out, see [`Executor::with_watchdog`].
+    fn wait_out_watchdog(&self, timeout: Duration) -> Option<String> {
+        let deadline = Instant::now() + timeout;
+
+        // park_timeout may return early, e.g. due to a stale unpark, so loop until the deadline
+        let parker = Parker::current();
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            parker.park_timeout(remaining);
+            self.stuck_tasks()?;
+        }
+
+        let report = self.stuck_tasks()?;
+        Some(format!(
+            "executor stuck: nothing woke the pending tasks within {timeout:?}, \
+             and no IO, timers or ready tasks are left that could:{report}"
+        ))
+    }
+
+    /// Returns a line per pending task if none of them can be woken by anything this executor
+    /// knows of, i.e. there are no ready or injected tasks, and no IO, timers or io_uring
+    /// operations registered from this thread.
+    fn stuck_tasks(&self) -> Option<String> {
+        let registered = !reactor::io_owned_by_current_thread().is_empty()
+            || !uring::waiting_tasks().is_empty()
+            || !crate::sim::waiting_tasks().is_empty()
+            || !timer::waiting_tasks().is_empty()
+            || !deterministic::waiting_tasks().is_empty();
+        if registered {
+            return None;
+        }
+
+        CURRENT_EXEC.with(|executor| {
+            if executor.has_ready() || !executor.injector.is_empty() {
+                return None;
+            }
+
+            let names = executor.names.borrow();
+            let waits = executor.waits.borrow();
+            let mut ids: Vec<_> = executor.tasks.borrow().keys().copied().collect();
+            ids.sort();
+
+            Some(
+                ids.iter()
+                    .map(|id| {
+                        let task = match names.get(id) {
+                            Some(name) => format!("'{name}' (task {id})"),
+                            None => format!("task {id}"),
+                        };
+                        match waits.get(id) {
+                            Some(resource) => {
+                                format!(
+                                    "\n  - {task}: blocked on {} {}",
+                                    resource.kind, resource.id
+                                )
+                            }
+                            None => format!("\n  - {task}: pending, without waiting on anything"),
+                        }
+                    })
+                    .collect(),
+            )
+        })
+    }
+
+    /// Returns a report if every pending task is blocked on a synchronisation primitive and
+    /// there is nothing left that could wake any of them up: no ready or injected tasks and no
+    /// IO.
+    ///
+    /// Tasks pending on anything that does not record a wait are assumed to be woken up by
+    /// something we can not see, so we only report when *all* pending tasks are accounted for.
+    fn detect_deadlock(&self) -> Option<String> {
+        if reactor::has_pending_io() {
+            return None;
+        }
+
+        CURRENT_EXEC.with(|executor| {
+            if executor.has_ready() || !executor.injector.is_empty() {
+                return None;
+            }
+
+            let tasks = executor.tasks.borrow();
+            let waits = executor.waits.borrow();
+
+            if tasks.is_empty() || !tasks.keys().all(|id| waits.contains_key(id)) {
+                return None;
+            }
+
+            if waits.values().any(|resource| resource.external) {
+                return None;
+            }
+
+            Some(deadlock::report(&waits, &executor.names.borrow()))
+        })
+    }
+
+    /// Spawn `future` on this thread's executor without waiting for it, to be driven by
+    /// [`Executor::poll_once`], [`Executor::run_until_idle`] or a later `block_on`.
+    pub fn spawn_and_forget<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        spawn_inner(None, Priority::Normal, Task::Local(Box::pin(future)));
+    }
+
+    /// Poll each task that is ready right now once, without ever parking, and return how many
+    /// were polled. Tasks woken while doing so are left for the next call.
+    ///
+    /// Meant for pumping the executor from a loop that is not ours, e.g. once per frame of a
+    /// GUI or game loop. The reactor and the timers run on threads of their own, so their
+    /// wakes arrive in between calls. A task that panics is recorded as per the
+    /// [`PanicPolicy`], there is no `block_on` future to fail instead.
+    pub fn poll_once(&mut self) -> usize {
+        let _running = Running::enter();
+        self.drain_injected();
+
+        // deferred tasks are only queued once the queue runs empty, see `next_ready`
+        let ready =
+            CURRENT_EXEC.with(|executor| executor.ready_depth() + executor.deferred.borrow().len());
+        let mut polled = 0;
+
+        for _ in 0..ready {
+            let Some(Entry { id, queued_at }) = self.next_ready() else {
+                break;
+            };
+
+            match self.poll_task(id, queued_at) {
+                Polled::Missing => continue,
+                Polled::Panicked(payload) => {
+                    self.record_panic(id, &*payload);
+                    self.remove_task_info(id, Finish::Panicked);
+                }
+                Polled::Pending | Polled::Completed => {}
+            }
+            polled += 1;
+        }
+
+        polled
+    }
+
+    /// Call [`Executor::poll_once`] until no task is ready, returning how many polls that took.
+    /// Tasks that are still waiting, e.g. on IO or a timer, are left pending.
+    pub fn run_until_idle(&mut self) -> usize {
+        let mut polled = 0;
+        loop {
+            match self.poll_once() {
+                0 => break polled,
+                n => polled += n,
+            }
+        }
+    }
+
+    /// Poll task `id` once, which was queued at `queued_at`. Bookkeeping of a task that
+    /// panicked is left to the caller.
+    fn poll_task(&self, id: usize, queued_at: Instant) -> Polled {
+        // 1. Retrieve Task from ExecutorCore
+        let mut task: Task = match self.get_future(id) {
+            Some(task) => task,
+            // Below guards agains spurious wakeups. Match arm can be reached if
+            // task has been completed already and is not in the ExecutorCore's hash map.
+            None => {
+                self.spurious_wake(id);
+                return Polled::Missing;
+            }
+        };
+
+        // 2. Creater a waker to use when polling the task
+        // NEW: we are now using a Context struct to wrap the waker.
+        // But first we convert from MyWaker to `std::task::Waker`
+        let waker = self.get_waker(id);
+        waker.polling();
+        let waker: Waker = waker.into();
+        let mut cx = Context::from_waker(&waker);
+
+        // 3. Poll future / task
+        self.record_poll(id, queued_at);
+        self.set_current(Some(id));
+        let poll = match CURRENT_EXEC.with(|executor| executor.panic_policy.get()) {
+            PanicPolicy::Abort => Ok(task.poll(&mut cx)),
+            PanicPolicy::Isolate => panic::catch_unwind(AssertUnwindSafe(|| task.poll(&mut cx))),
+        };
+        self.set_current(None);
+
+        match poll {
+            // Add future back into the hash map
+            Ok(Poll::Pending) => {
+                self.insert_task(id, task);
+                Polled::Pending
+            }
+            // task already removed from hash map, only bookkeeping left to clean up
+            Ok(Poll::Ready(_)) => {
+                self.remove_task_info(id, Finish::Completed);
+                self.count(|counters| &counters.completed);
+                Polled::Completed
+            }
+            // the future may be left in any state by the panic, so it is only dropped
+            Err(payload) => {
+                drop(task);
+                Polled::Panicked(payload)
+            }
+        }
+    }
+
+    /// Run `future` to completion, along with every task spawned until all of them completed.
+    ///
+    /// Same as [`Executor::block_on_with`] with [`ExitPolicy::WaitForAll`].
+    pub fn block_on<F>(&mut self, future: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        self.block_on_with(ExitPolicy::WaitForAll, future)
+    }
+
+    /// Run `future` to completion, then deal with tasks that are still pending according to
+    /// `policy`, e.g. background tasks that would otherwise keep us running.
+    ///
+    /// IMPORTANT: core logic of the executor.
+    pub fn block_on_with<F>(&mut self, policy: ExitPolicy, future: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        // NEW: there are some futures that return Ready on first poll, so we add an optimisation
+        // to poll all futures at least once.
+        //
+        // WARNING: by polling the future once here, the future is thus located within the stack
+        // frame of the `block_on` function. The act of polling it results in self.stack.writer
+        // holding a reference to buffer, i.e. a self reference. The first poll returns `NotReady`,
+        // and so we spawn it, placing it within a Box, which moves the future onto the heap.
+        // The next time the future is polled, the stack will be restored. However, the reference
+        // held by self.stack.writer will be invalid as it is pointing to the old location on the
+        // stack where the future was located.
+        // let mut waker = self.get_waker(usize::MAX);
+        // let mut future = future;
+
+        // match Box::pin(future).as_mut().poll(&waker) {
+        //     // future needs to be waited on
+        //     PollState::NotReady => {}
+        //     // future is ready, no need to block, so return
+        //     PollState::Ready(_) => return,
+        // }
+
+        // spawn the future on the executor, making it a top-level task
         // note that `spawn` will also move the future to the heap and pin it.
-        spawn(future);
+        // Checked before spawning, so a nested call leaves the queues of the outer one alone
+        let _running = Running::enter();
+        // the future stays on this thread, as the caller waits for it here
+        let main = spawn_inner(
+            Some("block_on".to_string()),
+            Priority::Normal,
+            Task::Local(Box::pin(future)),
+        );
+
+        // Pending tasks are cancelled once this passes, set when `main` completes
+        let mut cancel_at = None;
+        let expired = |cancel_at: Option<Instant>| cancel_at.is_some_and(|at| Instant::now() >= at);
 
         // Loop over all tasks in ready_queue and poll them once each
         'outer: loop {
-            while let Some(id) = self.pop_ready() {
-                // 1. Retrieve Task from ExecutorCore
-                let mut task: Task = match self.get_future(id) {
-                    Some(task) => task,
-                    // Below guards agains spurious wakeups. Match arm can be reached if
-                    // task has been completed already and is not in the ExecutorCore's hash map.
-                    None => continue,
-                };
-
-                // 2. Creater a waker to use when polling the task
-                // NEW: we are now using a Context struct to wrap the waker.
-                // But first we convert from MyWaker to `std::task::Waker`
-                let waker: Waker = self.get_waker(id).into();
-                let mut cx = Context::from_waker(&waker);
-
-                // 3. Poll future / task
-                match task.as_mut().poll(&mut cx) {
-                    // Add future back into the hash map
-                    Poll::Pending => self.insert_task(id, task),
-                    // nothing to do, task already removed from hash map
-                    Poll::Ready(_) => continue,
+            // tasks spawned from other threads since we last looked, see `Handle`
+            self.drain_injected();
+
+            while let Some(Entry { id, queued_at }) = self.next_ready() {
+                // the rest are cancelled without being polled again
+                if expired(cancel_at) {
+                    break;
+                }
+
+                match self.poll_task(id, queued_at) {
+                    Polled::Completed if id == main => {
+                        cancel_at = policy.cancel_at(Instant::now());
+                    }
+                    Polled::Panicked(payload) if id == main => {
+                        self.remove_task_info(id, Finish::Panicked);
+                        self.cancel_remaining();
+                        panic::resume_unwind(payload);
+                    }
+                    Polled::Panicked(payload) => {
+                        self.record_panic(id, &*payload);
+                        self.remove_task_info(id, Finish::Panicked);
+                    }
+                    Polled::Pending | Polled::Completed | Polled::Missing => {}
                 }
             } // END OF WHILE LOOP
 
+            if expired(cancel_at) && self.task_count() > 0 {
+                let cancelled = self.cancel_remaining();
+                log::info!("Cancelled {cancelled} pending task(s) on exit.");
+            }
+
             // 4. Decide wether to park or not based on current uncompleted top-level Tasks
+            if self.drain_injected() > 0 {
+                continue 'outer;
+            }
             let task_count = self.task_count();
 
             // Only used for debug purposes
-            let thread_name = thread::current().name().unwrap().to_string();
+            let thread_name = thread::current().name().unwrap_or("unnamed").to_string();
+
+            if task_count > 0 && crate::sim::advance() {
+                // Simulated network moved its virtual clock forward and woke some tasks,
+                // so there is no need to park.
+                continue 'outer;
+            } else if task_count > 0 && deterministic::advance() {
+                // Same for a deterministic run, which replayed the next scripted event or moved
+                // its virtual clock forward.
+                continue 'outer;
+            } else if task_count > 0 {
+                // Leftovers only get until the deadline, whatever they are waiting on, so
+                // there is no need to look for deadlocks among them.
+                if let Some(at) = cancel_at {
+                    self.maybe_self_check();
+
+                    log::debug!("{thread_name}: {task_count} pending tasks. Sleeping until woken up or cancelled.");
+                    Parker::current().park_timeout(at.saturating_duration_since(Instant::now()));
+                    continue 'outer;
+                }
+
+                // Rather than hang forever, fail loudly if tasks are waiting on each other
+                if self.detect_deadlock().is_some() {
+                    if let Some(report) = self.wait_out_deadlock() {
+                        panic!("{report}");
+                    }
+
+                    // another thread woke up one of our tasks during the grace period
+                    continue 'outer;
+                }
+
+                self.maybe_self_check();
+
+                // Nothing left here can wake the tasks, only another thread could
+                if let Some(timeout) = CURRENT_EXEC.with(|executor| executor.watchdog.get()) {
+                    if self.stuck_tasks().is_some() {
+                        if let Some(report) = self.wait_out_watchdog(timeout) {
+                            panic!("{report}");
+                        }
+
+                        // woken up in time
+                        continue 'outer;
+                    }
+                }
 
-            if task_count > 0 {
-                println!("{thread_name}: {task_count} pending tasks. Sleeping until woken up.");
-                thread::park()
+                log::debug!("{thread_name}: {task_count} pending tasks. Sleeping until woken up.");
+                self.park()
             } else {
-                println!("{thread_name}: All tasks finished.");
+                log::debug!("{thread_name}: All tasks finished.");
+                self.shutdown(&thread_name);
                 break 'outer;
             }
         }
     }
 }
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        if !self.counted {
+            return;
+        }
+
+        // `try_with`, since this may run while thread locals are torn down
+        let last = CURRENT_EXEC.try_with(|executor| {
+            let handles = executor.handles.get().saturating_sub(1);
+            executor.handles.set(handles);
+            handles == 0 && !executor.running.get() && !executor.tasks.borrow().is_empty()
+        });
+
+        if last == Ok(true) {
+            let thread_name = thread::current().name().unwrap_or("unnamed").to_string();
+            let cancelled = self.cancel_remaining();
+            log::warn!(
+                "{thread_name}: executor dropped with {cancelled} pending task(s), cancelled."
+            );
+            self.shutdown(&thread_name);
+        }
+    }
+}
+
+/// Outcome of [`Executor::poll_task`].
+enum Polled {
+    Pending,
+    Completed,
+    Panicked(Box<dyn Any + Send>),
+    /// Woken, but not pending, e.g. as it completed already
+    Missing,
+}
+
+/// Marks this thread's executor as running while `block_on` or `poll_once` drives it.
+///
+/// The executor's state is per thread, so a nested `block_on`, e.g. from within a task, would
+/// poll the tasks of the outer one from the middle of polling one of them.
+struct Running;
+
+impl Running {
+    fn enter() -> Self {
+        CURRENT_EXEC.with(|executor| {
+            assert!(
+                !executor.running.replace(true),
+                "Executor::block_on called while the executor on this thread is already running, \
+                 e.g. from within a task. Await the future instead, or spawn it via \
+                 `Handle::current()`."
+            );
+        });
+
+        Running
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        // Also reached when a task panics, so the executor can be used again after. `try_with`,
+        // since this may run while thread locals are torn down.
+        let _ = CURRENT_EXEC.try_with(|executor| {
+            executor.running.set(false);
+            executor.current.set(None);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        rc::Rc,
+        sync::{atomic::AtomicBool, Mutex},
+    };
+
+    use super::*;
+    use crate::{runtime::sync::mpsc, sim};
+
+    /// Stashes the waker it is polled with, then completes.
+    struct StashWaker(Arc<Mutex<Option<Waker>>>);
+
+    impl Future for StashWaker {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            *self.0.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Ready(())
+        }
+    }
+
+    /// Names of tasks in the order they were dropped, see [`OnDrop`].
+    type DropLog = Rc<RefCell<Vec<&'static str>>>;
+
+    /// Records its name when dropped, i.e. when the task holding it completes or is cancelled.
+    struct OnDrop(&'static str, DropLog);
+
+    impl Drop for OnDrop {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    /// Send a tick every 10ms of virtual time, `ticks` times or forever if None.
+    async fn interval(net: sim::Network, ticks: Option<usize>, tx: mpsc::Sender<usize>) {
+        for tick in (0..).take(ticks.unwrap_or(usize::MAX)) {
+            net.sleep(Duration::from_millis(10)).await;
+            let _ = tx.send(tick);
+        }
+    }
+
+    /// Count values received until the channel closes.
+    async fn consume(mut rx: mpsc::Receiver<usize>, received: Rc<Cell<usize>>) {
+        while rx.recv().await.is_some() {
+            received.set(received.get() + 1);
+        }
+    }
+
+    #[test]
+    fn task_is_polled_with_the_same_waker_every_time() {
+        Executor::new().block_on(async {
+            let mut first: Option<Waker> = None;
+            std::future::poll_fn(|cx| match &first {
+                Some(first) => {
+                    assert!(first.will_wake(cx.waker()));
+                    Poll::Ready(())
+                }
+                None => {
+                    first = Some(cx.waker().clone());
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await;
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "pending, without waiting on anything")]
+    fn watchdog_reports_tasks_nothing_can_wake() {
+        Executor::with_watchdog(Duration::from_millis(50)).block_on(async {
+            // never stores its waker anywhere
+            spawn_local_named("forgotten", std::future::pending::<()>());
+        });
+    }
+
+    #[test]
+    fn watchdog_leaves_tasks_woken_by_another_thread() {
+        Executor::with_watchdog(Duration::from_secs(5)).block_on(async {
+            let mut woken = false;
+            std::future::poll_fn(|cx| {
+                if woken {
+                    return Poll::Ready(());
+                }
+                woken = true;
+                let waker = cx.waker().clone();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(50));
+                    waker.wake();
+                });
+                Poll::Pending
+            })
+            .await;
+        });
+    }
+
+    #[test]
+    fn wait_for_all_runs_background_tasks_to_completion() {
+        let net = sim::Network::new(1);
+        net.install();
+        let received = Rc::new(Cell::new(0));
+
+        let count = received.clone();
+        Executor::new().block_on_with(ExitPolicy::WaitForAll, async move {
+            let (tx, rx) = mpsc::channel();
+            spawn_local(interval(net, Some(3), tx));
+            spawn_local(consume(rx, count));
+        });
+
+        assert_eq!(received.get(), 3);
+    }
+
+    #[test]
+    fn tasks_pending_at_the_deadline_are_cancelled() {
+        let net = sim::Network::new(1);
+        net.install();
+        let (received, dropped) = (Rc::new(Cell::new(0)), DropLog::default());
+
+        // a consumer waiting on a sender that is in no hurry
+        let (slow_tx, slow_rx) = mpsc::channel();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs(5));
+            let _ = slow_tx.send(0);
+        });
+
+        let (count, log) = (received.clone(), dropped.clone());
+        let start = Instant::now();
+        let policy = ExitPolicy::WaitWithDeadline(Duration::from_millis(100));
+        Executor::new().block_on_with(policy, async move {
+            let (tx, rx) = mpsc::channel();
+            let guard = OnDrop("interval", log.clone());
+            spawn_local(async move {
+                interval(net, Some(3), tx).await;
+                drop(guard);
+            });
+            spawn_local(consume(rx, count));
+
+            let guard = OnDrop("slow consumer", log);
+            spawn_local(async move {
+                consume(slow_rx, Rc::default()).await;
+                drop(guard);
+            });
+        });
+
+        assert!(start.elapsed() < Duration::from_secs(2));
+        // the interval finished within the deadline, the slow consumer did not
+        assert_eq!(received.get(), 3);
+        assert_eq!(*dropped.borrow(), ["interval", "slow consumer"]);
+    }
+
+    #[test]
+    fn cancel_remaining_drops_newest_tasks_first() {
+        let net = sim::Network::new(1);
+        net.install();
+        let (received, dropped) = (Rc::new(Cell::new(0)), DropLog::default());
+
+        let (count, log) = (received.clone(), dropped.clone());
+        Executor::new().block_on_with(ExitPolicy::CancelRemaining, async move {
+            let (tx, rx) = mpsc::channel();
+            let guard = OnDrop("interval", log.clone());
+            spawn_local(async move {
+                let _guard = guard;
+                interval(net, None, tx).await;
+            });
+
+            let guard = OnDrop("consumer", log);
+            spawn_local(async move {
+                let _guard = guard;
+                consume(rx, count).await;
+            });
+        });
+
+        assert_eq!(received.get(), 0);
+        assert_eq!(*dropped.borrow(), ["consumer", "interval"]);
+        CURRENT_EXEC.with(|executor| assert!(executor.tasks.borrow().is_empty()));
+    }
+
+    #[test]
+    fn high_priority_tasks_are_polled_first() {
+        // spawn_with_priority takes Send tasks only
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let log = order.clone();
+
+        Executor::new().block_on(async move {
+            for (name, priority) in [
+                ("urgent", Priority::High),
+                ("bulk 1", Priority::Normal),
+                ("bulk 2", Priority::Normal),
+            ] {
+                let log = log.clone();
+                spawn_with_priority(priority, async move { log.lock().unwrap().push(name) });
+            }
+        });
+
+        // without priorities, the most recently spawned task would run first
+        assert_eq!(*order.lock().unwrap(), ["urgent", "bulk 2", "bulk 1"]);
+    }
+
+    #[test]
+    fn wakes_before_the_next_poll_queue_a_task_once() {
+        let polls = Rc::new(Cell::new(0));
+        let counted = polls.clone();
+        let mut executor = Executor::new();
+
+        executor.block_on(async move {
+            spawn_local(std::future::poll_fn(move |cx| {
+                counted.set(counted.get() + 1);
+                if counted.get() > 1 {
+                    return Poll::Ready(());
+                }
+
+                let waker = cx.waker().clone();
+                spawn_local(async move { (0..3).for_each(|_| waker.wake_by_ref()) });
+                Poll::Pending
+            }));
+        });
+
+        // each wake after the first used to queue the finished task again
+        assert_eq!(polls.get(), 2);
+        assert_eq!(executor.metrics().spurious_wakes, 0);
+    }
+
+    #[test]
+    fn urgent_wakes_are_polled_before_others() {
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let log = order.clone();
+
+        Executor::new().block_on(async move {
+            let wakers = Rc::new(RefCell::new(HashMap::new()));
+            for name in ["timer", "io"] {
+                let (log, stash) = (log.clone(), wakers.clone());
+                let mut polled = false;
+                spawn_local(std::future::poll_fn(move |cx| {
+                    if polled {
+                        log.borrow_mut().push(name);
+                        return Poll::Ready(());
+                    }
+                    polled = true;
+                    stash.borrow_mut().insert(name, cx.waker().clone());
+                    Poll::Pending
+                }));
+            }
+            crate::runtime::yield_now().await;
+
+            let wakers = wakers.borrow();
+            wake_urgent(|| wakers["timer"].wake_by_ref());
+            wakers["io"].wake_by_ref();
+        });
+
+        // without the urgent queue, the task woken last would be polled first
+        assert_eq!(*order.borrow(), ["timer", "io"]);
+    }
+
+    #[test]
+    fn batched_wakes_push_each_task_once_and_unpark_once() {
+        let queue = Arc::default();
+        let wakers: Vec<Waker> = [1, 2]
+            .into_iter()
+            .map(|id| Arc::new(MyWaker::new(id, &queue)).into())
+            .collect();
+
+        let batched = batch_wakes(|| {
+            for waker in wakers.iter().chain(&wakers) {
+                waker.wake_by_ref();
+            }
+            // nothing is queued until the batch ends
+            assert!(queue.is_empty());
+        });
+
+        assert_eq!(
+            batched,
+            BatchedWakes {
+                woken: 2,
+                unparks: 1
+            }
+        );
+        assert_eq!(queue.snapshot(), vec![1, 2]);
+    }
+
+    #[test]
+    fn waker_kept_by_a_task_is_not_recycled() {
+        let stash: Arc<Mutex<Option<Waker>>> = Arc::default();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let (kept, finished) = (stash.clone(), done.clone());
+        Executor::new().block_on(async move {
+            // keeps the waker of its first poll, and completes once woken through it. High
+            // priority, so it is polled as soon as we yield.
+            let parked = kept.clone();
+            spawn_with_priority(
+                Priority::High,
+                std::future::poll_fn(move |cx| {
+                    let mut stash = parked.lock().unwrap();
+                    match *stash {
+                        Some(_) => {
+                            finished.store(true, Ordering::Relaxed);
+                            Poll::Ready(())
+                        }
+                        None => {
+                            *stash = Some(cx.waker().clone());
+                            Poll::Pending
+                        }
+                    }
+                }),
+            );
+
+            // polled a few times after that, each time with a waker that may come from the pool
+            let mut polls = 0;
+            std::future::poll_fn(|cx| {
+                if let Some(stashed) = kept.lock().unwrap().as_ref() {
+                    assert_ne!(cx.waker().data(), stashed.data(), "kept waker handed out");
+                }
+
+                polls += 1;
+                if polls == 3 {
+                    return Poll::Ready(());
+                }
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+            .await;
+
+            kept.lock().unwrap().as_ref().unwrap().wake_by_ref();
+        });
+
+        assert!(done.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn wake_after_completion_is_counted_as_spurious() {
+        let mut executor = Executor::new();
+        let before = executor.metrics().spurious_wakes;
+        let stash = Arc::new(Mutex::new(None));
+
+        executor.block_on(async move {
+            spawn(StashWaker(stash.clone()));
+            // deferred until the task above completed
+            crate::runtime::yield_now().await;
+
+            stash.lock().unwrap().take().unwrap().wake();
+            crate::runtime::yield_now().await;
+        });
+
+        assert_eq!(executor.metrics().spurious_wakes - before, 1);
+        CURRENT_EXEC.with(|executor| {
+            let tombstones = executor.tombstones.borrow();
+            assert_eq!(
+                tombstones.get(executor.next_id.get() - 2),
+                Some(Finish::Completed)
+            );
+        });
+    }
+
+    #[test]
+    fn wake_after_shutdown_is_counted_no_op() {
+        let stash = Arc::new(Mutex::new(None));
+        let before = stale_wakes();
+
+        Executor::new().block_on(StashWaker(stash.clone()));
+
+        // wake from a thread that knows nothing about the executor, as a forgotten one would
+        let waker = stash.lock().unwrap().take().unwrap();
+        thread::spawn(move || waker.wake()).join().unwrap();
+
+        assert!(stale_wakes() > before);
+        CURRENT_EXEC.with(|executor| assert!(executor.ready_queue.borrow().is_empty()));
+    }
+
+    #[test]
+    fn metrics_count_tasks_polls_and_wakes() {
+        let mut executor = Executor::new();
+
+        executor.block_on(async {
+            // woken by itself twice, so polled three times
+            let mut yields = 2;
+            spawn(std::future::poll_fn(move |cx| {
+                if yields == 0 {
+                    return Poll::Ready(());
+                }
+                yields -= 1;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }));
+        });
+
+        let metrics = executor.metrics();
+        assert_eq!(
+            (metrics.spawned, metrics.completed, metrics.pending),
+            (2, 2, 0)
+        );
+        assert_eq!((metrics.polls, metrics.max_polls), (4, 3));
+        // every poll was of a task taken off the ready queue
+        assert_eq!(metrics.wakes, 4);
+        assert!(metrics.max_wake_to_poll <= metrics.wake_to_poll);
+        assert_eq!(metrics.ready_depth, 0);
+        assert!(metrics.max_ready_depth >= 1);
+
+        executor.block_on_with(ExitPolicy::CancelRemaining, async {
+            spawn(std::future::pending());
+        });
+
+        let metrics = executor.metrics();
+        assert_eq!((metrics.spawned, metrics.completed), (4, 3));
+        assert_eq!((metrics.cancelled, metrics.pending), (1, 0));
+    }
+
+    #[test]
+    fn panicking_task_leaves_the_others_running() {
+        let mut executor = Executor::with_panic_policy(PanicPolicy::Isolate);
+        let done = Rc::new(Cell::new(false));
+
+        executor.block_on({
+            let done = done.clone();
+            async move {
+                spawn_named("doomed", async { panic!("boom") });
+                spawn_local(async move { done.set(true) });
+            }
+        });
+
+        assert!(done.get());
+        let panics = executor.take_panics();
+        assert_eq!(panics.len(), 1);
+        assert_eq!(
+            (panics[0].name.as_deref(), panics[0].message.as_deref()),
+            (Some("doomed"), Some("boom"))
+        );
+        assert!(executor.take_panics().is_empty(), "taken");
+
+        let metrics = executor.metrics();
+        assert_eq!(
+            (metrics.panicked, metrics.completed, metrics.pending),
+            (1, 2, 0)
+        );
+    }
+
+    #[test]
+    fn panic_of_the_block_on_future_unwinds_out_of_it() {
+        let mut executor = Executor::with_panic_policy(PanicPolicy::Isolate);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            executor.block_on(async {
+                spawn(std::future::pending());
+                panic!("main failed");
+            })
+        }));
+
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"main failed"));
+        // the other task is cancelled rather than left behind for the next block_on
+        let metrics = executor.metrics();
+        assert_eq!((metrics.cancelled, metrics.pending), (1, 0));
+    }
+
+    #[test]
+    fn dropping_an_executor_left_mid_run_cancels_its_tasks() {
+        struct SetOnDrop(Rc<Cell<bool>>);
+
+        impl Drop for SetOnDrop {
+            fn drop(&mut self) {
+                self.0.set(true);
+            }
+        }
+
+        let dropped = Rc::new(Cell::new(false));
+        let guard = SetOnDrop(dropped.clone());
+        let mut executor = Executor::with_panic_policy(PanicPolicy::Abort);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(move || {
+            executor.block_on(async move {
+                spawn_local(async move {
+                    let _guard = guard;
+                    std::future::pending::<()>().await;
+                });
+                spawn(async { panic!("boom") });
+                std::future::pending::<()>().await;
+            })
+        }));
+
+        // the executor was dropped while unwinding, along with the tasks it left behind
+        assert!(result.is_err());
+        assert!(dropped.get());
+        assert_eq!(Executor::new().metrics().pending, 0);
+    }
+
+    #[test]
+    fn dump_lists_what_pending_tasks_wait_on() {
+        let _mock = reactor::mock::install();
+        let dump = Rc::new(RefCell::new(Vec::new()));
+
+        let stash = dump.clone();
+        Executor::new().block_on_with(ExitPolicy::CancelRemaining, async move {
+            spawn_local_named("reader", async {
+                let (mut ours, _peer) = mio::net::UnixStream::pair().unwrap();
+                let id = reactor::reactor().next_id();
+                reactor::reactor().register(&mut ours, Interest::READABLE, id);
+                std::future::poll_fn(|cx| {
+                    reactor::reactor().set_waker(cx, id);
+                    report_progress(Progress {
+                        done: 3,
+                        total: Some(8),
+                    });
+                    Poll::<()>::Pending
+                })
+                .await;
+            });
+            // lets the reader run first
+            crate::runtime::yield_now().await;
+            *stash.borrow_mut() = Executor::new().dump_tasks();
+        });
+
+        let dump = dump.borrow();
+        let [block_on, reader] = &dump[..] else {
+            panic!("expected the block_on future and the reader, got {dump:?}");
+        };
+        assert_eq!(block_on.polls, 2);
+        assert_eq!(reader.polls, 1);
+        assert_eq!(reader.io, [(1, Interest::READABLE)]);
+        assert_eq!(reader.blocked_on, None);
+        assert_eq!(block_on.progress, None);
+        assert!(reader
+            .to_string()
+            .starts_with("'reader' (task 1): polled 1 times, pending for "));
+        assert!(reader
+            .to_string()
+            .ends_with(", io 1 READABLE, progress 3/8"));
+    }
+
+    #[test]
+    fn poll_once_only_polls_tasks_ready_when_called() {
+        let mut executor = Executor::new();
+        let steps = Rc::new(Cell::new(0));
+
+        let counter = steps.clone();
+        executor.spawn_and_forget(async move {
+            counter.set(1);
+            crate::runtime::yield_now().await;
+            counter.set(2);
+        });
+
+        assert_eq!(executor.poll_once(), 1);
+        assert_eq!(
+            steps.get(),
+            1,
+            "woken by the yield, but left for the next call"
+        );
+        assert_eq!(executor.poll_once(), 1);
+        assert_eq!(steps.get(), 2);
+        assert_eq!(executor.poll_once(), 0);
+    }
+
+    #[test]
+    fn run_until_idle_leaves_waiting_tasks_pending() {
+        let mut executor = Executor::new();
+        let (tx, rx) = crate::runtime::sync::oneshot::channel();
+        let received = Rc::new(Cell::new(None));
+
+        let stash = received.clone();
+        executor.spawn_and_forget(async move {
+            crate::runtime::yield_now().await;
+            stash.set(Some(rx.await.unwrap()));
+        });
+
+        assert_eq!(executor.run_until_idle(), 2);
+        assert_eq!(received.get(), None);
+        assert_eq!(executor.task_count(), 1);
+
+        tx.send(7);
+        assert_eq!(executor.run_until_idle(), 1);
+        assert_eq!(received.get(), Some(7));
+        assert_eq!(executor.task_count(), 0);
+    }
+}
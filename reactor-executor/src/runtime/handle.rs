@@ -0,0 +1,266 @@
+//! Explicit access to a given executor, from its own thread or any other.
+//!
+//! [`spawn`](super::spawn) and friends queue a task on whatever executor belongs to the calling
+//! thread, whether or not one is running. A [`Handle`] names the executor instead: get one via
+//! [`Executor::handle`](super::Executor::handle) and hand it to other threads, e.g. library
+//! code running on a thread pool, to spawn work onto that executor.
+//!
+//! The executor's state is per thread, so tasks spawned from another thread go through an
+//! [`Injector`]: a queue the executor's thread drains from within `block_on`, after its
+//! executor is unparked for every task that is pushed.
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    thread::{self, ThreadId},
+};
+
+use super::{executor, parker::Parker, Priority};
+
+/// Task spawned from another thread, until the executor's thread picks it up
+struct Injected {
+    name: Option<String>,
+    priority: Priority,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+/// Tasks spawned onto an executor from other threads, see the module docs.
+pub(super) struct Injector {
+    tasks: Mutex<Vec<Injected>>,
+    /// Thread of the executor
+    thread: ThreadId,
+    /// Parker of the executor, unparked for every task pushed
+    parker: Parker,
+}
+
+impl Default for Injector {
+    /// Injector for the executor of the calling thread.
+    fn default() -> Self {
+        Self {
+            tasks: Mutex::default(),
+            thread: thread::current().id(),
+            parker: Parker::current(),
+        }
+    }
+}
+
+impl Injector {
+    fn push(&self, task: Injected) {
+        // Be careful of unparking before the push is visible, i.e. before the lock is released
+        self.tasks.lock().unwrap().push(task);
+        self.parker.unpark();
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.tasks.lock().unwrap().is_empty()
+    }
+
+    /// Spawn the injected tasks onto the executor of this thread, in the order they were
+    /// pushed. Returns how many there were.
+    pub(super) fn drain(&self) -> usize {
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        let count = tasks.len();
+
+        for task in tasks {
+            executor::spawn_boxed(task.name, task.priority, task.future);
+        }
+        count
+    }
+
+    fn is_own_thread(&self) -> bool {
+        thread::current().id() == self.thread
+    }
+}
+
+/// Handle to an executor, which can be sent to and spawn tasks from other threads.
+///
+/// Tasks spawned through a handle run once the executor's thread is driving it via `block_on`.
+/// If that thread is done with the executor for good, they never run.
+#[derive(Clone)]
+pub struct Handle {
+    injector: Arc<Injector>,
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("thread", &self.injector.thread)
+            .finish()
+    }
+}
+
+impl Handle {
+    /// Handle to the executor running on this thread.
+    ///
+    /// Panics if no executor is running, see [`Handle::try_current`].
+    pub fn current() -> Self {
+        Self::try_current().expect("Handle::current called outside of a running executor")
+    }
+
+    /// Same as [`Handle::current`], returning None if no executor is running on this thread.
+    pub fn try_current() -> Option<Self> {
+        executor::is_running().then(Self::for_current_thread)
+    }
+
+    /// Handle to the executor of this thread, whether or not it is running.
+    pub(super) fn for_current_thread() -> Self {
+        Self {
+            injector: executor::injector(),
+        }
+    }
+
+    /// Spawn a task, see [`spawn`](super::spawn).
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_inner(None, Priority::Normal, future)
+    }
+
+    /// Spawn a named task, see [`spawn_named`](super::spawn_named).
+    pub fn spawn_named<F>(&self, name: &str, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_inner(Some(name.to_string()), Priority::Normal, future)
+    }
+
+    /// Spawn a task with a priority, see [`spawn_with_priority`](super::spawn_with_priority).
+    pub fn spawn_with_priority<F>(&self, priority: Priority, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_inner(None, priority, future)
+    }
+
+    /// Spawn a task that is not Send, see [`spawn_local`](super::spawn_local).
+    ///
+    /// Panics if called from a thread other than the executor's.
+    pub fn spawn_local<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        assert!(
+            self.injector.is_own_thread(),
+            "Handle::spawn_local called from a thread other than the executor's, \
+             use Handle::spawn for futures that are Send"
+        );
+        executor::spawn_local(future)
+    }
+
+    fn spawn_inner<F>(&self, name: Option<String>, priority: Priority, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        // on the executor's own thread there is no need to go through the queue
+        if self.injector.is_own_thread() {
+            executor::spawn_boxed(name, priority, Box::pin(future));
+            return;
+        }
+
+        self.injector.push(Injected {
+            name,
+            priority,
+            future: Box::pin(future),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        rc::Rc,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use super::*;
+    use crate::runtime::{sync::oneshot, Executor};
+
+    #[test]
+    fn spawns_onto_the_running_executor() {
+        assert!(Handle::try_current().is_none(), "nothing running yet");
+        let ran = Rc::new(Cell::new(false));
+
+        Executor::new().block_on({
+            let ran = ran.clone();
+            async move {
+                Handle::current().spawn_local(async move { ran.set(true) });
+            }
+        });
+
+        assert!(ran.get());
+        assert!(Handle::try_current().is_none(), "block_on returned");
+    }
+
+    #[test]
+    fn spawns_from_another_thread_while_running() {
+        let executor = Executor::new();
+        let handle = executor.handle();
+        let (tx, rx) = oneshot::channel();
+
+        let other = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(50));
+            handle.spawn(async move { tx.send(thread::current().id()) });
+        });
+
+        let ran_on = Rc::new(Cell::new(None));
+        let mut executor = executor;
+        executor.block_on({
+            let ran_on = ran_on.clone();
+            async move { ran_on.set(rx.await.ok()) }
+        });
+        other.join().unwrap();
+
+        assert_eq!(ran_on.get(), Some(thread::current().id()));
+    }
+
+    #[test]
+    fn tasks_spawned_before_running_wait_for_block_on() {
+        let handle = Executor::new().handle();
+        let ran = Arc::new(AtomicBool::new(false));
+
+        let spawned = ran.clone();
+        thread::spawn(move || handle.spawn(async move { spawned.store(true, Ordering::SeqCst) }))
+            .join()
+            .unwrap();
+        assert!(!ran.load(Ordering::SeqCst));
+
+        Executor::new().block_on(async {});
+        assert!(ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    #[should_panic(expected = "Handle::spawn_local called from a thread other than")]
+    fn spawn_local_from_another_thread_panics() {
+        let handle = Executor::new().handle();
+        let result = thread::spawn(move || handle.spawn_local(async {})).join();
+        std::panic::resume_unwind(result.unwrap_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Executor::block_on called while the executor on this thread")]
+    fn nested_block_on_panics() {
+        Executor::new().block_on(async {
+            Executor::new().block_on(async {});
+        });
+    }
+
+    #[test]
+    fn executor_is_usable_after_a_nested_block_on() {
+        let nested = std::panic::catch_unwind(|| {
+            Executor::new().block_on(async {
+                Executor::new().block_on(async {});
+            })
+        });
+        assert!(nested.is_err());
+
+        let ran = Rc::new(Cell::new(false));
+        Executor::new().block_on({
+            let ran = ran.clone();
+            async move { ran.set(true) }
+        });
+        assert!(ran.get());
+    }
+}
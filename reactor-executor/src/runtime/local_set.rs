@@ -0,0 +1,73 @@
+//! Tasks that are not Send, pinned to the thread that runs them.
+//!
+//! Every thread's executor keeps the tasks spawned on it via [`spawn_local`] to itself, so
+//! from within a task they can be spawned directly. A [`LocalSet`] collects such tasks before
+//! any executor runs, e.g. while setting up a server, and hands them to the executor of the
+//! thread it is run on.
+use std::{cell::RefCell, future::Future, marker::PhantomData, pin::Pin, rc::Rc};
+
+use super::{executor::spawn_local, Executor};
+
+/// Set of tasks that are not Send, see the module docs.
+#[derive(Default)]
+pub struct LocalSet {
+    tasks: RefCell<Vec<Pin<Box<dyn Future<Output = ()>>>>>,
+    _thread_bound: PhantomData<Rc<()>>,
+}
+
+impl LocalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a task to the set. It is spawned once the set is run, in the order added.
+    pub fn spawn_local<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        self.tasks.borrow_mut().push(Box::pin(future));
+    }
+
+    /// Spawn the tasks of the set onto `executor`, then run `future` to completion along with
+    /// them, see [`Executor::block_on`].
+    pub fn block_on<F>(self, executor: &mut Executor, future: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        for task in self.tasks.into_inner() {
+            spawn_local(task);
+        }
+
+        executor.block_on(future);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::runtime::yield_now;
+
+    #[test]
+    fn tasks_run_along_with_the_future() {
+        let local = LocalSet::new();
+        // not Send, so only `spawn_local` takes it
+        let count = Rc::new(Cell::new(0));
+
+        for _ in 0..2 {
+            let count = count.clone();
+            local.spawn_local(async move {
+                yield_now().await;
+                count.set(count.get() + 1);
+            });
+        }
+        assert_eq!(count.get(), 0, "nothing runs before the set does");
+
+        let seen = count.clone();
+        local.block_on(&mut Executor::new(), async move {
+            spawn_local(async move { seen.set(seen.get() + 10) });
+        });
+        assert_eq!(count.get(), 12);
+    }
+}
@@ -0,0 +1,17 @@
+//! Leveled diagnostics of the runtime, in place of printing unconditionally, see the
+//! `runtime-log` crate.
+//!
+//! The configuration is set once for the whole process via
+//! [`init_with_log`](super::init_with_log), or else read from the `RUNTIME_LOG` environment
+//! variable the first time anything is logged, e.g.
+//!
+//! ```bash
+//! RUNTIME_LOG=warn,runtime::executor=debug,http=trace cargo run -p reactor-executor
+//! ```
+//!
+//! Messages are logged with the `error!`, `warn!`, `info!`, `debug!` and `trace!` macros,
+//! e.g. `log::debug!("...")` after `use crate::runtime::log`.
+pub use runtime_log::{enabled, Level, LogConfig};
+
+pub(super) use runtime_log::configure;
+pub(crate) use runtime_log::{debug, error, info, trace, warn};
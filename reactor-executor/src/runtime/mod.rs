@@ -9,17 +9,112 @@ use mio::{Events, Poll, Registry};
 
 use crate::future::{Future, PollState};
 
+mod admission;
+mod blocking;
+mod budget;
+mod builder;
+mod cores;
+mod deadlock;
+pub mod deterministic;
 mod executor;
+mod handle;
+mod local_set;
+pub mod log;
+mod parker;
+mod pool;
 mod reactor;
+mod ready_queue;
+mod scope;
+mod self_check;
+pub mod sync;
+mod task_local;
+mod timer;
+mod typed;
+pub mod uring;
+mod waker_slab;
+mod yield_now;
 
-pub use executor::{spawn, Executor, MyWaker};
-pub use reactor::reactor;
+pub use crate::task_local;
+pub use admission::{reserve, try_spawn, Backpressure, Permit, Reserve, SpawnError};
+pub use blocking::{spawn_blocking, BlockingTask};
+pub use budget::{read_budget, set_read_budget};
+pub use builder::Builder;
+pub use cores::{available_cores, core_count, pin_to_core, spawn_global};
+pub use deadlock::{next_resource_id, wait_on, Resource};
+pub use deterministic::{now, MockReactor, MockSource};
+pub(crate) use executor::current_task;
+pub use executor::{
+    coalesced_wakes, report_progress, spawn, spawn_local, spawn_local_named, spawn_named,
+    spawn_with_priority, stale_wakes, Executor, ExecutorMetrics, ExitPolicy, MyWaker, PanicPolicy,
+    ParkStrategy, TaskDump, TaskPanic,
+};
+pub use handle::Handle;
+pub use local_set::LocalSet;
+pub use log::LogConfig;
+pub(crate) use pool::PooledBuffer;
+pub use pool::{pool_stats, pooling_enabled, set_pooling, PoolStats};
+pub use reactor::{
+    mock, reactor, set_reactor, DeregisterStats, DispatchStats, Priority, ReactorApi,
+    ReactorMetrics, Routing, StoredWaker, BATCH_BUCKETS,
+};
+pub(crate) use ready_queue::ReadyQueue;
+pub use ready_queue::ReadyQueueKind;
+pub use scope::{scope, Scope, Scoped};
+pub use task_local::TaskLocal;
+pub use timer::{sleep, sleep_until, Sleep};
+pub use typed::TypedExecutor;
+pub(crate) use waker_slab::WakerSlab;
+pub(crate) use yield_now::wake_after_others;
+pub use yield_now::{yield_now, YieldNow};
 
+/// I/O model the runtime drives sockets with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Readiness based, via the reactor and epoll. Used by [`net::unix`](crate::net::unix)
+    #[default]
+    Epoll,
+    /// Completion based, via io_uring, in addition to the reactor. Used by
+    /// [`net::uring`](crate::net::uring), see [`uring`]
+    IoUring,
+}
+
+/// Start the runtime with the defaults, see [`Builder`] for the options.
 pub fn init() -> Executor {
-    // Start reactor and event_loop
-    // NOTE: event looop is spawned in different thread,
-    // and reactor is initialised as a global static variable.
-    reactor::start();
-    // create executor and return it to caller
+    Builder::new().build()
+}
+
+/// Same as [`init`], with the levels of runtime diagnostics to show, rather than those of the
+/// `RUNTIME_LOG` environment variable. See [`log`].
+pub fn init_with_log(config: LogConfig) -> Executor {
+    Builder::new().log(config).build()
+}
+
+/// Same as [`init`], with a choice of I/O backend.
+///
+/// Panics if [`Backend::IoUring`] is chosen, but io_uring is not available.
+pub fn init_with_backend(backend: Backend) -> Executor {
+    Builder::new().backend(backend).build()
+}
+
+/// Same as [`init`], but runs `shards` event loops, with sources spread over them according
+/// to `routing`. Useful when many executor threads would otherwise share one event loop.
+pub fn init_sharded(shards: usize, routing: Routing) -> Executor {
+    Builder::new().shards(shards).routing(routing).build()
+}
+
+/// Start the reactor once for all tests in the process, since it is a global that can only be
+/// started once. Returns a fresh executor for the calling test.
+#[cfg(test)]
+pub(crate) fn init_for_tests() -> Executor {
+    static STARTED: std::sync::Once = std::sync::Once::new();
+    // more than one shard, so the tests also cover routing between them
+    STARTED.call_once(|| {
+        reactor::start_sharded(2, Routing::Executor);
+        // tests of the io_uring backend are skipped where it is not available
+        if let Err(e) = uring::start() {
+            eprintln!("io_uring is not available: {e}");
+        }
+    });
+
     Executor::new()
 }
@@ -0,0 +1,144 @@
+//! The executor's own way to sleep until woken, rather than `thread::park`.
+//!
+//! `thread::park` and `Thread::unpark` share a single token per thread with anything else that
+//! runs on it, e.g. a library blocking on a channel from within a task. An unpark meant for the
+//! executor may be consumed there, and the executor woken by unparks meant for someone else.
+//! A [`Parker`] is a token of the executor's alone: a flag and a condvar, which only its wakers
+//! and [`Handle`](super::Handle)s set.
+//!
+//! In single-threaded mode the executor does not block on the condvar, but runs the reactor's
+//! event loop until unparked, see [`reactor::park_inline`]. Unparking then also interrupts the
+//! event loop's `poll`.
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use super::reactor;
+
+thread_local! {
+    static CURRENT: Parker = Parker::default();
+}
+
+/// Parks the executor of a thread, and unparks it from any thread. Clones share the token.
+#[derive(Clone, Default)]
+pub(crate) struct Parker {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Set by `unpark`, taken by `park`
+    notified: Mutex<bool>,
+    condvar: Condvar,
+    /// Woken by `unpark` while the executor runs the event loop of the reactor
+    interrupt: Mutex<Option<&'static mio::Waker>>,
+}
+
+impl Parker {
+    /// Parker of the executor on this thread.
+    pub(crate) fn current() -> Self {
+        CURRENT.with(Parker::clone)
+    }
+
+    /// Block until unparked. Returns straight away if unparked since the last park.
+    pub(crate) fn park(&self) {
+        if reactor::park_inline(self, None).is_some() {
+            return;
+        }
+
+        let mut notified = self.inner.notified.lock().unwrap();
+        while !*notified {
+            notified = self.inner.condvar.wait(notified).unwrap();
+        }
+        *notified = false;
+    }
+
+    /// Same as [`Parker::park`], giving up after `timeout`, e.g. to run periodic checks while
+    /// idle. Returns true if unparked, false if timed out.
+    pub(crate) fn park_timeout(&self, timeout: Duration) -> bool {
+        if let Some(unparked) = reactor::park_inline(self, Some(timeout)) {
+            return unparked;
+        }
+
+        let notified = self.inner.notified.lock().unwrap();
+        let (mut notified, _) = self
+            .inner
+            .condvar
+            .wait_timeout_while(notified, timeout, |notified| !*notified)
+            .unwrap();
+        std::mem::take(&mut *notified)
+    }
+
+    /// Take the token if unparked since the last park, without blocking. Returns true if it
+    /// was.
+    pub(crate) fn try_park(&self) -> bool {
+        std::mem::take(&mut *self.inner.notified.lock().unwrap())
+    }
+
+    pub(crate) fn unpark(&self) {
+        *self.inner.notified.lock().unwrap() = true;
+        self.inner.condvar.notify_one();
+
+        // taken after setting the token, so either the event loop sees the token before it
+        // calls `poll`, or we see the waker and interrupt it
+        if let Some(waker) = *self.inner.interrupt.lock().unwrap() {
+            waker.wake().expect("Failed to wake up reactor event loop");
+        }
+    }
+
+    /// Have `unpark` wake `waker` too, while the executor blocks in the reactor's event loop
+    /// rather than on the condvar. `None` once it stopped.
+    pub(crate) fn interrupt_with(&self, waker: Option<&'static mio::Waker>) {
+        *self.inner.interrupt.lock().unwrap() = waker;
+    }
+
+    /// Whether both park the same executor.
+    pub(crate) fn ptr_eq(&self, other: &Parker) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Instant};
+
+    use super::*;
+
+    #[test]
+    fn unpark_before_park_is_not_lost() {
+        let parker = Parker::default();
+        parker.unpark();
+
+        // would hang if the unpark was lost
+        parker.park();
+        assert!(
+            !parker.park_timeout(Duration::from_millis(10)),
+            "consumed by park"
+        );
+    }
+
+    #[test]
+    fn unparks_from_another_thread() {
+        let parker = Parker::current();
+        let unparker = parker.clone();
+
+        let other = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            unparker.unpark();
+        });
+
+        assert!(parker.park_timeout(Duration::from_secs(5)));
+        other.join().unwrap();
+    }
+
+    #[test]
+    fn thread_unpark_does_not_wake_the_executor() {
+        let parker = Parker::current();
+        thread::current().unpark();
+
+        let start = Instant::now();
+        assert!(!parker.park_timeout(Duration::from_millis(50)));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,282 @@
+//! Object pools for runtime objects that are created and dropped at a high rate.
+//!
+//! Every task used to allocate a fresh waker, and every poll of an HTTP request a fresh read
+//! buffer, only to drop them again a moment later. Under connection churn that is
+//! most of the allocator traffic of the runtime. Objects taken from a pool are handed back
+//! once done with, and reused by the next taker.
+//!
+//! A pool is made of a shared [`Pool`], and a [`LocalPool`] cache per thread in front of it.
+//! Takes and puts only touch the cache. Once it runs dry it is refilled with a batch of
+//! objects from the shared pool, and once it holds too many a batch goes back, so the shared
+//! lock is taken once per [`BATCH`] operations at most.
+//!
+//! NOTE: timers of the simulated network are entries in a heap rather than allocations of
+//! their own, so there is nothing to pool for them.
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use super::MyWaker;
+
+/// Objects moved between a thread's cache and the shared pool at a time
+const BATCH: usize = 32;
+/// Objects kept by the shared pool at most, any more are dropped
+const SHARED_CAPACITY: usize = 1024;
+/// Buffers that grew beyond this are dropped rather than pooled, so a single large response
+/// does not pin its memory for good.
+const MAX_POOLED_BUFFER: usize = 64 * 1024;
+
+/// Set to false to have every take allocate, for comparison, see [`set_pooling`].
+static POOLING: AtomicBool = AtomicBool::new(true);
+
+/// Wakers of tasks, one per task for as long as it is pending
+static WAKERS: Pool<Arc<MyWaker>> = Pool::new("wakers");
+/// Buffers for reading from connections
+static BUFFERS: Pool<Vec<u8>> = Pool::new("buffers");
+
+thread_local! {
+    pub(super) static LOCAL_WAKERS: LocalPool<Arc<MyWaker>> = const { LocalPool::new(&WAKERS) };
+    static LOCAL_BUFFERS: LocalPool<Vec<u8>> = const { LocalPool::new(&BUFFERS) };
+}
+
+/// Enable or disable pooling for the whole process. With pooling disabled, takes always come
+/// up empty and puts drop the object, so objects are allocated as if there were no pools.
+pub fn set_pooling(enabled: bool) {
+    POOLING.store(enabled, Ordering::Relaxed);
+}
+
+pub fn pooling_enabled() -> bool {
+    POOLING.load(Ordering::Relaxed)
+}
+
+/// Occupancy of the runtime's pools, one entry per pool.
+pub fn pool_stats() -> Vec<PoolStats> {
+    vec![WAKERS.stats(), BUFFERS.stats()]
+}
+
+/// Buffer taken from [`BUFFERS`], handed back when dropped.
+pub(crate) struct PooledBuffer(Vec<u8>);
+
+impl PooledBuffer {
+    /// An empty buffer.
+    pub(crate) fn new() -> Self {
+        Self(LOCAL_BUFFERS.with(|pool| pool.take()).unwrap_or_default())
+    }
+
+    /// A buffer of `len` zeroes, to read into.
+    pub(crate) fn zeroed(len: usize) -> Self {
+        let mut buffer = Self::new();
+        buffer.resize(len, 0);
+        buffer
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let mut buffer = std::mem::take(&mut self.0);
+        if buffer.capacity() == 0 || buffer.capacity() > MAX_POOLED_BUFFER {
+            return;
+        }
+        buffer.clear();
+
+        // `try_with`, as buffers may be dropped while thread locals are torn down
+        let _ = LOCAL_BUFFERS.try_with(|pool| pool.put(buffer));
+    }
+}
+
+/// Occupancy of a pool, see [`pool_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub name: &'static str,
+    /// Idle objects in the shared pool
+    pub shared: usize,
+    /// Idle objects in the caches of all threads
+    pub cached: usize,
+    /// Takes served with a pooled object
+    pub hits: usize,
+    /// Takes that came up empty, so the caller allocated
+    pub misses: usize,
+}
+
+impl PoolStats {
+    /// Fraction of takes served with a pooled object.
+    pub fn hit_rate(&self) -> f64 {
+        self.hits as f64 / (self.hits + self.misses).max(1) as f64
+    }
+}
+
+/// Objects shared by the caches of all threads.
+pub(crate) struct Pool<T> {
+    name: &'static str,
+    free: Mutex<Vec<T>>,
+    /// Objects held by thread caches
+    cached: AtomicUsize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl<T> Pool<T> {
+    pub(crate) const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            free: Mutex::new(Vec::new()),
+            cached: AtomicUsize::new(0),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> PoolStats {
+        PoolStats {
+            name: self.name,
+            shared: self.free.lock().unwrap().len(),
+            cached: self.cached.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Move up to `n` objects into `cache`.
+    fn refill(&self, cache: &mut Vec<T>, n: usize) {
+        let mut free = self.free.lock().unwrap();
+        let start = free.len().saturating_sub(n);
+        cache.extend(free.drain(start..));
+    }
+
+    /// Take back `objects`, dropping any beyond [`SHARED_CAPACITY`].
+    fn release(&self, objects: impl Iterator<Item = T>) {
+        let mut free = self.free.lock().unwrap();
+        let room = SHARED_CAPACITY.saturating_sub(free.len());
+        free.extend(objects.take(room));
+    }
+}
+
+/// A thread's cache in front of a shared [`Pool`].
+pub(crate) struct LocalPool<T: 'static> {
+    shared: &'static Pool<T>,
+    cache: RefCell<Vec<T>>,
+}
+
+impl<T> LocalPool<T> {
+    pub(crate) const fn new(shared: &'static Pool<T>) -> Self {
+        Self {
+            shared,
+            cache: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Take an idle object, if there is one. The caller allocates a new one otherwise.
+    pub(crate) fn take(&self) -> Option<T> {
+        if !pooling_enabled() {
+            return None;
+        }
+
+        let mut cache = self.cache.borrow_mut();
+        if cache.is_empty() {
+            self.shared.refill(&mut cache, BATCH);
+            self.shared.cached.fetch_add(cache.len(), Ordering::Relaxed);
+        }
+
+        let object = cache.pop();
+        let counter = match object {
+            Some(_) => {
+                self.shared.cached.fetch_sub(1, Ordering::Relaxed);
+                &self.shared.hits
+            }
+            None => &self.shared.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        object
+    }
+
+    /// Hand back an object for reuse. The caller resets it first, if it needs to be.
+    pub(crate) fn put(&self, object: T) {
+        if !pooling_enabled() {
+            return;
+        }
+
+        let mut cache = self.cache.borrow_mut();
+        cache.push(object);
+        self.shared.cached.fetch_add(1, Ordering::Relaxed);
+
+        if cache.len() >= 2 * BATCH {
+            let start = cache.len() - BATCH;
+            self.shared.release(cache.drain(start..));
+            self.shared.cached.fetch_sub(BATCH, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Objects cached by a thread that exits go back to the shared pool.
+impl<T> Drop for LocalPool<T> {
+    fn drop(&mut self) {
+        let cache = self.cache.get_mut();
+        self.shared.cached.fetch_sub(cache.len(), Ordering::Relaxed);
+        self.shared.release(cache.drain(..));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn objects_move_between_threads_through_the_shared_pool() {
+        static POOL: Pool<Vec<u8>> = Pool::new("test");
+        thread_local! {
+            static LOCAL: LocalPool<Vec<u8>> = const { LocalPool::new(&POOL) };
+        }
+
+        // fill a cache beyond two batches, so one batch spills into the shared pool
+        thread::spawn(|| {
+            LOCAL.with(|pool| {
+                assert!(pool.take().is_none());
+                for _ in 0..2 * BATCH {
+                    pool.put(Vec::with_capacity(64));
+                }
+            });
+            assert_eq!(POOL.stats().shared, BATCH);
+            assert_eq!(POOL.stats().cached, BATCH);
+        })
+        .join()
+        .unwrap();
+
+        // the rest went back to the shared pool when the thread exited
+        let stats = POOL.stats();
+        assert_eq!((stats.shared, stats.cached), (2 * BATCH, 0));
+
+        // another thread takes them out a batch at a time
+        let reused = LOCAL.with(|pool| {
+            let buf = pool.take().unwrap();
+            assert_eq!(POOL.stats().shared, BATCH);
+            assert_eq!(POOL.stats().cached, BATCH - 1);
+            buf
+        });
+        assert!(reused.capacity() >= 64);
+
+        let stats = POOL.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+    }
+}
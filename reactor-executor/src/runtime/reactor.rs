@@ -1,141 +1,1342 @@
 use std::{
-    collections::HashMap,
+    cell::Cell,
+    collections::{hash_map::Entry, HashMap, HashSet},
     future::Future,
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, OnceLock,
     },
     task::{Context, Wake, Waker},
-    thread,
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
 };
 
-use mio::{net::TcpStream, Events, Interest, Poll, Registry, Token};
+use mio::{
+    event::{Event, Source},
+    net::TcpStream,
+    Events, Interest, Poll, Registry, Token,
+};
 
+use super::{
+    executor::{self, BatchedWakes},
+    log,
+    parker::Parker,
+    waker_slab::{Dispatch, WakerSlab},
+};
 use crate::runtime::MyWaker;
 
+pub mod mock;
+
 // ===================== END OF DEPENDENCIES =====================
 
-// NEW: Reactor is dependent on `std::task::Waker`
-// rather than our own custom `MyWaker`.
-type Wakers = Arc<Mutex<HashMap<usize, Waker>>>;
+/// Source waiting to be deregistered by the reactor thread, with the id it was registered
+/// under. The reactor owns the source until then, so its file descriptor can not be closed
+/// and reused in the meantime.
+type Deregistration = (usize, Box<dyn Source + Send>);
+
+/// Token used to wake up an event loop when deregistrations are queued. Slab keys are never
+/// 0, so neither are source ids, and it never clashes with a registered source.
+const DRAIN_TOKEN: Token = Token(0);
+
+/// Events taken per call to `poll` by each event loop, unless configured otherwise via
+/// [`Builder::event_capacity`](super::Builder::event_capacity)
+pub(super) const DEFAULT_EVENT_CAPACITY: usize = 100;
+
+/// Buckets of [`DispatchStats::batch_sizes`]
+pub const BATCH_BUCKETS: usize = 8;
 
 /// WARNING: This can be accessed from multiple threads.
 /// However, we use the OnceLock to ensure that we only initialise the Reactor once.
 /// Hence, there will only be a single instance of this reactor running, even if
 /// multiple threads are accessing it.
 /// It is however private to this module.
+///
+/// The reactor itself is a registry of shards, each with its own event loop thread, see
+/// [`Reactor`].
 static REACTOR: OnceLock<Reactor> = OnceLock::new();
 
-pub fn reactor() -> &'static Reactor {
-    REACTOR
-        .get()
-        .expect("Reactor called outside a runtime context")
+/// The reactor [`reactor`] hands out: the built-in [`REACTOR`] once started, or the one put
+/// in place via [`set_reactor`].
+static API: OnceLock<&'static dyn ReactorApi> = OnceLock::new();
+
+/// Hands out shards to executor threads for [`Routing::Executor`].
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+// Shard assigned to the executor running on this thread, see `Reactor::next_id`.
+thread_local! {
+    static THREAD_SHARD: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Priority hint for a registration or a task.
+///
+/// Within each tick of an event loop, wakers for high priority sources are called before
+/// those of normal ones, and executors poll high priority tasks before anything else. Meant
+/// for latency critical work like accepting connections, not for bulk data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    #[default]
+    Normal,
+    High,
+}
+
+/// The waker a leaf future last left with the reactor, see [`ReactorApi::update_waker`].
+///
+/// Reset it via [`StoredWaker::clear`] whenever the waker is removed from the reactor, e.g.
+/// through [`ReactorApi::clear_waker`].
+#[derive(Default)]
+pub struct StoredWaker(Option<(usize, Interest, Waker)>);
+
+impl StoredWaker {
+    /// True if a waker is stored, i.e. the future is waiting on its source.
+    pub fn is_stored(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = None;
+    }
+
+    /// True if the reactor holds a waker for `id` and `interest` that wakes the same task as
+    /// `waker`.
+    fn will_wake(&self, id: usize, interest: Interest, waker: &Waker) -> bool {
+        self.0
+            .as_ref()
+            .is_some_and(|(stored, for_interest, current)| {
+                *stored == id && *for_interest == interest && current.will_wake(waker)
+            })
+    }
 }
 
+/// How the reactor picks the shard a source is registered with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Routing {
+    /// Spread sources over the shards round-robin
+    #[default]
+    IdHash,
+    /// Sources created on an executor thread all go to the shard assigned to that thread, so
+    /// each executor mostly talks to a single event loop.
+    Executor,
+}
+
+/// The reactor of the runtime, or the mock installed on this thread via [`mock::install`].
+pub fn reactor() -> &'static dyn ReactorApi {
+    current().expect("Reactor called outside a runtime context")
+}
+
+/// See [`reactor`], `None` if the reactor is not running and no mock is installed.
+fn current() -> Option<&'static dyn ReactorApi> {
+    mock::installed().or_else(|| API.get().copied())
+}
+
+/// Use `reactor` in place of the built-in reactor, for every thread of the process.
+///
+/// To be called before the runtime is started, which then leaves the built-in reactor out.
+/// Panics if a reactor is in place already.
+pub fn set_reactor(reactor: impl ReactorApi + 'static) {
+    let reactor: &'static dyn ReactorApi = Box::leak(Box::new(reactor));
+    API.set(reactor).ok().expect("Reactor already running");
+}
+
+/// True if the reactor is running and has wakers registered for IO events.
+///
+/// Used by the executor to tell if a pending task may still be woken up by the reactor.
+pub fn has_pending_io() -> bool {
+    current().is_some_and(|reactor| reactor.has_pending_io())
+}
+
+/// Route the sources of this thread to `shard` with [`Routing::Executor`], rather than to the
+/// next shard in turn, e.g. for the executors of thread-per-core mode.
+pub(super) fn assign_shard(shard: usize) {
+    THREAD_SHARD.with(|assigned| assigned.set(Some(shard % reactor().shard_count())));
+}
+
+/// See [`ReactorApi::purge_task`], does nothing if the reactor is not running.
+pub(super) fn purge_task(task: usize) -> usize {
+    current().map_or(0, |reactor| reactor.purge_task(task))
+}
+
+/// See [`ReactorApi::io_owned_by_current_thread`], empty if the reactor is not running.
+pub(super) fn io_owned_by_current_thread() -> Vec<(usize, usize)> {
+    current()
+        .map(|reactor| reactor.io_owned_by_current_thread())
+        .unwrap_or_default()
+}
+
+/// Routes registrations to one of several shards, each running its own event loop.
+///
+/// A source stays with one shard for its whole life, and the shard is encoded in its id:
+/// `id % shards`. So anything holding an id, wakers included, finds the owning shard without
+/// a lookup. The rest of the id, `id / shards`, is the key of the source's slot in the
+/// shard's [`WakerSlab`].
 pub struct Reactor {
-    wakers: Wakers,
+    shards: Vec<Shard>,
+    routing: Routing,
+    /// Shard the next source goes to with [`Routing::IdHash`]
+    next_shard: AtomicUsize,
+    /// Event loop of the only shard in single-threaded mode, run by executors as they park
+    /// rather than by a thread of its own, see [`park_inline`]
+    inline: Option<Mutex<EventLoop>>,
+    /// Executors parked while another one ran the inline event loop
+    waiting: Mutex<Vec<Parker>>,
+}
+
+/// A single event loop and the sources registered with it.
+struct Shard {
+    /// Wakers per source and direction, along with the executor thread and task that stored them.
+    ///
+    /// NOTE: We are not using the task id's as tokens to mio. Every source gets a slot of its
+    /// own, and a slot that is reused gets a new generation, so we do not accidently get the
+    /// same token ID twice while events for the previous one may still be in flight.
+    wakers: WakerSlab,
+    /// Number of shards in the reactor, to turn ids into slab keys
+    stride: usize,
+    /// Ids registered with [`Priority::High`], everything else is normal priority.
+    high_priority: Mutex<HashSet<usize>>,
+    /// Events each registered id is registered for, see [`ReactorApi::interest`].
+    interests: Mutex<HashMap<usize, Interest>>,
     // used for interacting with event queue in mio
     registry: Registry,
-    /// tracks next available ID / Token, so that we can track which event occurred and
-    /// which Waker to use.
-    /// NOTE: We are not using the task id's as tokens to mio. In fact, whenever we register
-    /// interest in an event on a source, we do no reuse token ID's. This means we do not
-    /// accidently get the same token ID twice for a given source.
-    next_id: AtomicUsize,
+    /// Number of `epoll_ctl` calls (register + deregister) made through the reactor.
+    ///
+    /// Used to compare syscall counts between leaf futures that register up front and
+    /// those that speculatively read first.
+    ctl_calls: AtomicUsize,
+    /// Deregistrations to be carried out by the reactor thread between calls to `poll`.
+    ///
+    /// The std channel is lock-free on the sending side, so deregistering never blocks the
+    /// calling executor.
+    deregistrations: mpsc::Sender<Deregistration>,
+    /// Wakes the event loop, so queued deregistrations are processed without waiting for the
+    /// next IO event.
+    drain_waker: mio::Waker,
+    /// Set while the event loop has been woken to drain the queue but has not done so yet.
+    /// Deregistrations queued in the meantime share that wake up.
+    drain_scheduled: AtomicBool,
+    stats: DeregisterCounters,
+    dispatch: DispatchCounters,
 }
 
-impl Reactor {
+/// Counters for the deregistration queue, see [`ReactorApi::deregister_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeregisterStats {
+    /// Deregistrations handed to the reactor
+    pub queued: usize,
+    /// Deregistrations the reactor thread has carried out
+    pub processed: usize,
+    /// Times the event loop was woken up to drain the queue
+    pub wakeups: usize,
+}
+
+#[derive(Default)]
+struct DeregisterCounters {
+    queued: AtomicUsize,
+    processed: AtomicUsize,
+    wakeups: AtomicUsize,
+}
+
+impl DeregisterCounters {
+    fn snapshot(&self) -> DeregisterStats {
+        DeregisterStats {
+            queued: self.queued.load(Ordering::Relaxed),
+            processed: self.processed.load(Ordering::Relaxed),
+            wakeups: self.wakeups.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Snapshot of the reactor's counters, see [`ReactorApi::metrics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReactorMetrics {
+    /// Ids handed out and not released yet, i.e. sources registered or about to be
+    pub sources: usize,
+    /// See [`ReactorApi::ctl_calls`]
+    pub ctl_calls: usize,
+    /// Event loop iterations, and the events and wake ups they handled
+    pub dispatch: DispatchStats,
+    pub deregister: DeregisterStats,
+}
+
+/// Counters for how events are turned into wake ups, see [`ReactorApi::dispatch_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DispatchStats {
+    /// Times `poll` returned, be it with IO events or to drain deregistrations
+    pub iterations: usize,
+    /// Times `poll` returned with IO events
+    pub ticks: usize,
+    /// Distinct sources with events, summed over all ticks
+    pub events: usize,
+    /// Distinct tasks queued, summed over all ticks
+    pub woken: usize,
+    /// Executor threads unparked, summed over all ticks
+    pub unparks: usize,
+    /// Events ignored as their id was freed, or reused by another source since
+    pub stale: usize,
+    /// Times `poll` filled the event buffer, so the next `poll` took the events left over
+    /// without blocking, see [`Builder::event_capacity`](super::Builder::event_capacity)
+    pub full_batches: usize,
+    /// Times `poll` returned with events, by how many it returned: 1, 2 to 3, 4 to 7 and so
+    /// on, the last bucket counting 128 events and more
+    pub batch_sizes: [usize; BATCH_BUCKETS],
+}
+
+impl DispatchStats {
+    /// Average number of events handled per wake up of the event loop.
+    pub fn events_per_tick(&self) -> f64 {
+        self.events as f64 / self.ticks.max(1) as f64
+    }
+
+    /// Average number of events handled per unpark of an executor thread.
+    pub fn events_per_unpark(&self) -> f64 {
+        self.events as f64 / self.unparks.max(1) as f64
+    }
+}
+
+#[derive(Default)]
+struct DispatchCounters {
+    iterations: AtomicUsize,
+    ticks: AtomicUsize,
+    events: AtomicUsize,
+    woken: AtomicUsize,
+    unparks: AtomicUsize,
+    stale: AtomicUsize,
+    full_batches: AtomicUsize,
+    batch_sizes: [AtomicUsize; BATCH_BUCKETS],
+}
+
+impl DispatchCounters {
+    /// Count a call to `poll` that returned `events` events, `full` if they filled the buffer.
+    fn record_batch(&self, events: usize, full: bool) {
+        self.iterations.fetch_add(1, Ordering::Relaxed);
+        if full {
+            self.full_batches.fetch_add(1, Ordering::Relaxed);
+        }
+        if events > 0 {
+            let bucket = (events.ilog2() as usize).min(BATCH_BUCKETS - 1);
+            self.batch_sizes[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn record(&self, events: usize, batched: BatchedWakes) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        self.events.fetch_add(events, Ordering::Relaxed);
+        self.woken.fetch_add(batched.woken, Ordering::Relaxed);
+        self.unparks.fetch_add(batched.unparks, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> DispatchStats {
+        DispatchStats {
+            iterations: self.iterations.load(Ordering::Relaxed),
+            ticks: self.ticks.load(Ordering::Relaxed),
+            events: self.events.load(Ordering::Relaxed),
+            woken: self.woken.load(Ordering::Relaxed),
+            unparks: self.unparks.load(Ordering::Relaxed),
+            stale: self.stale.load(Ordering::Relaxed),
+            full_batches: self.full_batches.load(Ordering::Relaxed),
+            batch_sizes: std::array::from_fn(|bucket| {
+                self.batch_sizes[bucket].load(Ordering::Relaxed)
+            }),
+        }
+    }
+}
+
+/// What leaf futures need from a reactor: ids for their sources, registrations, and a place
+/// to leave their waker until the source is ready.
+///
+/// [`reactor`] hands out the one in use as a trait object, so leaf futures work the same with
+/// any of them: the built-in [`Reactor`], however many shards it has, the mock of
+/// [`mock::install`], or one put in place via [`set_reactor`].
+pub trait ReactorApi: Send + Sync {
+    /// Hand out a new id for a source.
+    ///
+    /// The id takes up a slot until the source is deregistered, or the id is released.
+    fn next_id(&self) -> usize;
+
+    /// Hand an id from [`ReactorApi::next_id`] back without registering a source with it, so
+    /// its slot can be reused. Ids of registered sources are released by
+    /// [`ReactorApi::deregister`] instead.
+    fn release_id(&self, id: usize);
+
     /// Register interest in notifications for an event source
-    pub fn register(&self, stream: &mut TcpStream, interest: Interest, id: usize) {
+    fn register(&self, source: &mut dyn Source, interest: Interest, id: usize) {
+        self.register_with_priority(source, interest, id, Priority::Normal);
+    }
+
+    /// Same as [`ReactorApi::register`], with a hint for how urgently events on the source
+    /// should be dispatched, see [`Priority`].
+    fn register_with_priority(
+        &self,
+        source: &mut dyn Source,
+        interest: Interest,
+        id: usize,
+        priority: Priority,
+    );
+
+    /// Register a source that was registered before, and detached with
+    /// [`ReactorApi::detach`], under a new id.
+    ///
+    /// Edge-triggered events that fired while the source was detached are gone, so the first
+    /// waker stored for `id` is woken straight away. The task re-polls, and sees the state the
+    /// source is in now, e.g. data that was buffered in the meantime.
+    fn reregister(&self, source: &mut dyn Source, interest: Interest, id: usize);
+
+    /// Change the events tracked for a registered source, e.g. to also be woken once it is
+    /// writable while there is data waiting to be sent.
+    ///
+    /// The id and the waker stored for it are kept, so a task waiting on the source is woken
+    /// by the new events. Not to be confused with [`ReactorApi::reregister`], for sources that
+    /// were detached.
+    fn set_interest(&self, source: &mut dyn Source, interest: Interest, id: usize);
+
+    /// Stop tracking events for `source`, keeping it open so it can be registered again with
+    /// [`ReactorApi::reregister`], e.g. for connections kept in a pool. `id` is released.
+    fn detach(&self, source: &mut dyn Source, id: usize);
+
+    /// Stop tracking events for `source`, taking ownership of it.
+    ///
+    /// No more wake ups happen for `id`. The source is closed once it is deregistered, and
+    /// the id released.
+    fn deregister(&self, source: Box<dyn Source + Send>, id: usize);
+
+    // NEW: change method to accept a Context rather than MyWaker
+    /// Store the waker of `cx` for `id`, woken by any event on the source.
+    fn set_waker(&self, cx: &Context, id: usize);
+
+    /// Same as [`ReactorApi::set_waker`], woken by the events of `interest` only, e.g. by
+    /// readability for the read half of a stream. Futures waiting on the same source for
+    /// different events, e.g. a reader and a writer in tasks of their own, each keep their
+    /// waker rather than replacing each other's.
+    ///
+    /// Reactors keeping a single waker per id store it as with `set_waker`.
+    fn set_waker_for(&self, cx: &Context, id: usize, interest: Interest) {
+        let _ = interest;
+        self.set_waker(cx, id);
+    }
+
+    /// Same as [`ReactorApi::set_waker`], for a leaf future that keeps track of the waker it
+    /// left with us in `stored`.
+    ///
+    /// Tasks are polled with the same waker every time, so usually the reactor holds on to
+    /// the right one already, and there is no need to store it again.
+    fn update_waker(&self, cx: &Context, id: usize, stored: &mut StoredWaker) {
+        self.update_waker_for(cx, id, Interest::READABLE | Interest::WRITABLE, stored);
+    }
+
+    /// Same as [`ReactorApi::update_waker`], woken by the events of `interest` only, see
+    /// [`ReactorApi::set_waker_for`].
+    fn update_waker_for(
+        &self,
+        cx: &Context,
+        id: usize,
+        interest: Interest,
+        stored: &mut StoredWaker,
+    ) {
+        if stored.will_wake(id, interest, cx.waker()) {
+            return;
+        }
+
+        self.set_waker_for(cx, id, interest);
+        stored.0 = Some((id, interest, cx.waker().clone()));
+    }
+
+    /// Remove the waker for `id` without deregistering the source, once the future that set
+    /// it is no longer waiting on the source.
+    fn clear_waker(&self, id: usize);
+
+    /// Same as [`ReactorApi::clear_waker`], for the waker stored for `interest` only, see
+    /// [`ReactorApi::set_waker_for`].
+    fn clear_waker_for(&self, id: usize, interest: Interest) {
+        let _ = interest;
+        self.clear_waker(id);
+    }
+
+    /// True if a waker is stored for `id`, i.e. some future is waiting on the source.
+    fn has_waker(&self, id: usize) -> bool;
+
+    /// True if wakers are stored for any source, i.e. tasks may still be woken by the reactor.
+    fn has_pending_io(&self) -> bool;
+
+    /// Remove the wakers that `task` on the calling thread's executor left behind, returning
+    /// how many were found. The executor calls this for every task that completes.
+    fn purge_task(&self, task: usize) -> usize;
+
+    /// Source ids with a registered waker, along with the task that registered it, for wakers
+    /// registered from the calling thread. An id shows up once per task waiting on it, e.g.
+    /// twice for a socket read from in one task and written to in another.
+    fn io_owned_by_current_thread(&self) -> Vec<(usize, usize)>;
+
+    /// Events `id` is registered for, None if it is not registered.
+    fn interest(&self, id: usize) -> Option<Interest>;
+
+    /// Number of event loops.
+    fn shard_count(&self) -> usize {
+        1
+    }
+
+    /// Index of the shard that owns `id`.
+    fn shard_of(&self, id: usize) -> usize {
+        id % self.shard_count()
+    }
+
+    /// Total number of register / deregister syscalls made so far.
+    fn ctl_calls(&self) -> usize;
+
+    /// Counters for deregistrations queued, processed and the wake ups needed to do so. None
+    /// for reactors that deregister right away.
+    fn deregister_stats(&self) -> DeregisterStats {
+        DeregisterStats::default()
+    }
+
+    /// Counters for how IO events were turned into wake ups. None for reactors without an
+    /// event loop.
+    fn dispatch_stats(&self) -> DispatchStats {
+        DispatchStats::default()
+    }
+
+    /// Snapshot of all of the reactor's counters.
+    fn metrics(&self) -> ReactorMetrics;
+}
+
+impl Reactor {
+    /// Carry out queued deregistrations straight away if the reactor is single-threaded and
+    /// no executor is running its event loop. One that is has been woken up to do so.
+    fn drain_inline(&self) {
+        if let Some(Ok(event_loop)) = self.inline.as_ref().map(Mutex::try_lock) {
+            drain_deregistrations(&event_loop.poll, &event_loop.deregistrations, 0);
+        }
+    }
+
+    /// The shard that owns `id`.
+    fn shard(&self, id: usize) -> &Shard {
+        &self.shards[id % self.shards.len()]
+    }
+}
+
+impl ReactorApi for Reactor {
+    /// Hand out a new id, which also decides the shard the source is registered with.
+    fn next_id(&self) -> usize {
+        let shards = self.shards.len();
+
+        // only care about spreading sources, so Relaxed ordering suffices.
+        let shard = match self.routing {
+            Routing::IdHash => self.next_shard.fetch_add(1, Ordering::Relaxed) % shards,
+            Routing::Executor => THREAD_SHARD.with(|shard| match shard.get() {
+                Some(shard) => shard,
+                None => {
+                    let assigned = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % shards;
+                    shard.set(Some(assigned));
+                    assigned
+                }
+            }),
+        };
+
+        self.shards[shard].wakers.insert() * shards + shard
+    }
+
+    fn release_id(&self, id: usize) {
+        let shard = self.shard(id);
+        shard.high_priority.lock().unwrap().remove(&id);
+        shard.interests.lock().unwrap().remove(&id);
+        shard.wakers.remove(shard.key(id));
+    }
+
+    fn register_with_priority(
+        &self,
+        source: &mut dyn Source,
+        interest: Interest,
+        id: usize,
+        priority: Priority,
+    ) {
+        let shard = self.shard(id);
+        if priority == Priority::High {
+            shard.high_priority.lock().unwrap().insert(id);
+        }
+        shard.register(source, interest, id);
+    }
+
+    fn reregister(&self, source: &mut dyn Source, interest: Interest, id: usize) {
+        self.register(source, interest, id);
+        let shard = self.shard(id);
+        shard.wakers.mark_missed(shard.key(id));
+    }
+
+    /// One syscall, where deregistering and registering again would take two, and could miss
+    /// events in between.
+    fn set_interest(&self, source: &mut dyn Source, interest: Interest, id: usize) {
+        let shard = self.shard(id);
+        shard
+            .registry
+            .reregister(source, Token(id), interest)
+            .expect("Failed to change interest of stream with reactor");
+        shard.ctl_calls.fetch_add(1, Ordering::Relaxed);
+        shard.interests.lock().unwrap().insert(id, interest);
+    }
+
+    /// Unlike [`ReactorApi::deregister`] the syscall is made right away. Any event still
+    /// carrying `id` finds its slot gone, or reused under a new generation.
+    fn detach(&self, source: &mut dyn Source, id: usize) {
+        let shard = self.shard(id);
+        shard
+            .registry
+            .deregister(source)
+            .expect("Failed to deregister stream with reactor");
+        shard.ctl_calls.fetch_add(1, Ordering::Relaxed);
+        self.release_id(id);
+    }
+
+    /// The waker is removed straight away. The syscall itself is queued and made by the
+    /// shard's event loop thread between two calls to `poll`, rather than on the caller's
+    /// thread while the event loop may be handling an event for the source. Once it is made,
+    /// the id's slot is freed for reuse, under a new generation, so any event still carrying
+    /// the id finds no waker. The source is dropped, and so closed, after it has been
+    /// deregistered.
+    fn deregister(&self, source: Box<dyn Source + Send>, id: usize) {
+        self.shard(id).deregister(source, id);
+        // nobody may run the inline event loop for a while, e.g. once `block_on` returned,
+        // and the source is only closed once deregistered
+        self.drain_inline();
+    }
+
+    fn set_waker(&self, cx: &Context, id: usize) {
+        self.set_waker_for(cx, id, Interest::READABLE | Interest::WRITABLE);
+    }
+
+    fn set_waker_for(&self, cx: &Context, id: usize, interest: Interest) {
+        self.shard(id).set_waker(cx, id, interest);
+    }
+
+    fn clear_waker(&self, id: usize) {
+        self.clear_waker_for(id, Interest::READABLE | Interest::WRITABLE);
+    }
+
+    fn clear_waker_for(&self, id: usize, interest: Interest) {
+        self.shard(id).clear_waker(id, interest);
+    }
+
+    fn has_waker(&self, id: usize) -> bool {
+        let shard = self.shard(id);
+        shard.wakers.get(shard.key(id)).is_some()
+    }
+
+    fn has_pending_io(&self) -> bool {
+        self.shards.iter().any(|shard| !shard.wakers.is_idle())
+    }
+
+    /// Leaf futures clean up after themselves when dropped, so for a task that has finished
+    /// there should be none. Any ids found are stale, e.g. from a future that was leaked
+    /// rather than dropped. Their sources can not be deregistered without the source itself,
+    /// but removing the wakers at least stops them from counting as pending IO, which would
+    /// keep the executor from ever reporting a deadlock.
+    fn purge_task(&self, task: usize) -> usize {
+        let owner = (thread::current().id(), task);
+
+        self.shards
+            .iter()
+            .enumerate()
+            .map(|(i, shard)| {
+                let stale: Vec<(usize, Interest)> = shard
+                    .wakers
+                    .owners()
+                    .into_iter()
+                    .filter(|(_, _, registered_by)| *registered_by == owner)
+                    .map(|(key, interest, _)| (key * shard.stride + i, interest))
+                    .collect();
+
+                for (id, interest) in &stale {
+                    shard.clear_waker(*id, *interest);
+                    shard.high_priority.lock().unwrap().remove(id);
+                }
+                stale.len()
+            })
+            .sum()
+    }
+
+    fn io_owned_by_current_thread(&self) -> Vec<(usize, usize)> {
+        self.shards
+            .iter()
+            .enumerate()
+            .flat_map(|(i, shard)| shard.io_owned_by_current_thread(i))
+            .collect()
+    }
+
+    fn interest(&self, id: usize) -> Option<Interest> {
+        self.shard(id).interests.lock().unwrap().get(&id).copied()
+    }
+
+    fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Summed over all shards.
+    fn ctl_calls(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.ctl_calls.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Summed over all shards.
+    fn deregister_stats(&self) -> DeregisterStats {
+        self.shards.iter().map(|shard| shard.stats.snapshot()).fold(
+            DeregisterStats::default(),
+            |total, stats| DeregisterStats {
+                queued: total.queued + stats.queued,
+                processed: total.processed + stats.processed,
+                wakeups: total.wakeups + stats.wakeups,
+            },
+        )
+    }
+
+    /// Summed over all shards.
+    fn dispatch_stats(&self) -> DispatchStats {
+        self.shards
+            .iter()
+            .map(|shard| shard.dispatch.snapshot())
+            .fold(DispatchStats::default(), |total, stats| DispatchStats {
+                iterations: total.iterations + stats.iterations,
+                ticks: total.ticks + stats.ticks,
+                events: total.events + stats.events,
+                woken: total.woken + stats.woken,
+                unparks: total.unparks + stats.unparks,
+                stale: total.stale + stats.stale,
+                full_batches: total.full_batches + stats.full_batches,
+                batch_sizes: std::array::from_fn(|bucket| {
+                    total.batch_sizes[bucket] + stats.batch_sizes[bucket]
+                }),
+            })
+    }
+
+    /// Summed over all shards.
+    fn metrics(&self) -> ReactorMetrics {
+        ReactorMetrics {
+            sources: self.shards.iter().map(|shard| shard.wakers.len()).sum(),
+            ctl_calls: self.ctl_calls(),
+            dispatch: self.dispatch_stats(),
+            deregister: self.deregister_stats(),
+        }
+    }
+}
+
+impl Shard {
+    /// Key of `id` in `wakers`
+    fn key(&self, id: usize) -> usize {
+        id / self.stride
+    }
+
+    fn register(&self, source: &mut dyn Source, interest: Interest, id: usize) {
         self.registry
-            .register(stream, Token(id), interest)
+            .register(source, Token(id), interest)
             .expect("Failed to register stream with reactor");
+        self.ctl_calls.fetch_add(1, Ordering::Relaxed);
+        self.interests.lock().unwrap().insert(id, interest);
     }
 
-    // NEW: change method to accept a Context rather than MyWaker
-    pub fn set_waker(&self, cx: &Context, id: usize) {
-        let waker = cx.waker();
-        let _ = self
+    fn set_waker(&self, cx: &Context, id: usize, interest: Interest) {
+        let owner = super::executor::current_task().map(|task| (thread::current().id(), task));
+
+        // an event came in while nobody was waiting, and will not come in again
+        if self.wakers.set(self.key(id), cx.waker(), interest, owner) {
+            cx.waker().wake_by_ref();
+        }
+    }
+
+    /// `shard` is our index in the reactor, needed to turn keys back into ids.
+    fn io_owned_by_current_thread(&self, shard: usize) -> Vec<(usize, usize)> {
+        let thread = thread::current().id();
+
+        let mut owned: Vec<(usize, usize)> = self
             .wakers
-            .lock()
-            .as_deref_mut()
-            // if waker was updated, old waker is returned. We discard it via using `is_none()`
-            // IMPORTANT: we always store the most recent waker for a given task.
-            .map(|w| w.insert(id, cx.waker().clone()).is_none())
-            .unwrap();
+            .owners()
+            .into_iter()
+            .filter(|(_, _, (owner, _))| *owner == thread)
+            .map(|(key, _, (_, task))| (key * self.stride + shard, task))
+            .collect();
+        // a task waiting on both directions of a source only counts once
+        owned.sort_unstable();
+        owned.dedup();
+        owned
+    }
+
+    fn clear_waker(&self, id: usize, interest: Interest) {
+        self.wakers.clear(self.key(id), interest);
     }
 
-    pub fn deregister(&self, stream: &mut TcpStream, id: usize) {
-        // 1. remove waker
+    /// See [`ReactorApi::deregister`].
+    fn deregister(&self, source: Box<dyn Source + Send>, id: usize) {
+        // 1. remove wakers, the slot itself is freed once the source is deregistered
         self.wakers
-            .lock()
-            .as_deref_mut()
-            .map(|w| w.remove(&id))
-            .unwrap();
+            .clear(self.key(id), Interest::READABLE | Interest::WRITABLE);
+        self.high_priority.lock().unwrap().remove(&id);
+        self.interests.lock().unwrap().remove(&id);
 
-        // 2. syscall to deregister `id`
-        self.registry.deregister(stream).unwrap();
-    }
+        // 2. hand the source to the reactor thread to make the syscall
+        self.deregistrations
+            .send((id, source))
+            .expect("Reactor event loop is not running");
+        self.stats.queued.fetch_add(1, Ordering::Relaxed);
 
-    pub fn next_id(&self) -> usize {
-        // only care about ensuring that we don't hand out the same value twice, so Relaxed
-        // ordering suffices.
-        self.next_id.fetch_add(1, Ordering::Relaxed)
+        // 3. wake up the event loop, unless a wake up is already on its way
+        if !self.drain_scheduled.swap(true, Ordering::AcqRel) {
+            self.stats.wakeups.fetch_add(1, Ordering::Relaxed);
+            self.drain_waker
+                .wake()
+                .expect("Failed to wake up reactor event loop");
+        }
     }
 }
 
 /// Holds logic for event loop that waits and reacts to new events
-fn event_loop(mut poll: Poll, wakers: Wakers) {
-    let mut events = Events::with_capacity(100);
+///
+/// Each shard runs its own event loop, `shard` is its index in the reactor.
+fn event_loop(
+    poll: Poll,
+    deregistrations: mpsc::Receiver<Deregistration>,
+    shard: usize,
+    capacity: usize,
+) {
+    let mut event_loop = EventLoop::new(poll, deregistrations, shard, capacity);
 
+    // events that did not fit are taken straight away, rather than after blocking again
+    let mut full = false;
     loop {
+        full = event_loop.turn(full.then_some(Duration::ZERO));
+    }
+}
+
+/// The state of a shard's event loop, turned by a thread of its own or, in single-threaded
+/// mode, by the executor whenever it parks, see [`park_inline`].
+struct EventLoop {
+    poll: Poll,
+    deregistrations: mpsc::Receiver<Deregistration>,
+    shard: usize,
+    events: Events,
+    ids: Vec<usize>,
+    /// Readiness of each id in `ids`, over all of its events in a tick
+    readiness: HashMap<usize, Interest>,
+}
+
+impl EventLoop {
+    fn new(
+        poll: Poll,
+        deregistrations: mpsc::Receiver<Deregistration>,
+        shard: usize,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            poll,
+            deregistrations,
+            shard,
+            events: Events::with_capacity(capacity),
+            ids: Vec::with_capacity(capacity),
+            readiness: HashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Wait up to `timeout` for events, forever if `None`, then wake the tasks waiting on
+    /// them and carry out queued deregistrations. Returns true if the events filled the
+    /// buffer, in which case more may be ready already.
+    fn turn(&mut self, timeout: Option<Duration>) -> bool {
+        let Self {
+            poll,
+            deregistrations,
+            shard,
+            events,
+            ids,
+            readiness,
+        } = self;
+
         // 1. Block on event queue until OS notifies us of ready events.
         //    This yields exection of current thread to OS scheduler.
-        poll.poll(&mut events, None).unwrap();
+        poll.poll(events, timeout).unwrap();
+        let this = &REACTOR.get().unwrap().shards[*shard];
+        let polled = events.iter().count();
+        let full = polled == events.capacity();
+        this.dispatch.record_batch(polled, full);
 
-        // 2. Iterate through events and match tokens with wakers.
-        //    Then call waker's `wake` method.
-        for event in events.iter() {
+        // 2. Collect ids of the sources that have events, high priority ones first, so their
+        //    wakers are called (and their tasks queued) before bulk data sources.
+        //    A source can show up more than once per tick, it only needs waking once, for
+        //    the readiness of all of its events.
+        ids.clear();
+        readiness.clear();
+        for event in events.iter().filter(|event| event.token() != DRAIN_TOKEN) {
             let Token(id) = event.token();
+            match readiness.entry(id) {
+                Entry::Occupied(mut ready) => *ready.get_mut() |= readiness_of(event),
+                Entry::Vacant(ready) => {
+                    ready.insert(readiness_of(event));
+                    ids.push(id);
+                }
+            }
+        }
+
+        prioritize(ids, &this.high_priority.lock().unwrap());
 
-            let wakers = wakers.lock().unwrap();
+        // 3. Match ids with wakers, then call their `wake` method outside of any lock. Wakes
+        //    are batched, so each task is queued once and each executor thread is unparked
+        //    once per tick, rather than once per event.
+        if !ids.is_empty() {
+            let mut to_wake = Vec::with_capacity(ids.len());
+            for id in ids.iter() {
+                match this
+                    .wakers
+                    .on_event(this.key(*id), readiness[id], &mut to_wake)
+                {
+                    Dispatch::Wake | Dispatch::Missed => {}
+                    Dispatch::Stale => {
+                        log::trace!("ignored stale event for id {id}");
+                        this.dispatch.stale.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
 
-            if let Some(waker) = wakers.get(&id) {
-                // Waker for token ID found
+            let batched = executor::batch_wakes(|| {
                 // NEW: we use `wake_by_ref`, since `wake` consumes the waker due
                 // to having a receiver of `self` vs `&self` with wake_by_ref.
-                waker.wake_by_ref()
-            }
+                to_wake.iter().for_each(Waker::wake_by_ref)
+            });
+            this.dispatch.record(ids.len(), batched);
         }
 
-        // Finished processing all events. Repeat and go back to blocking on event queue.
+        // 4. Carry out deregistrations queued since the last tick.
+        drain_deregistrations(poll, deregistrations, *shard);
+
+        // Finished processing all events. Go back to blocking on event queue, unless some
+        // were left over.
+        full
+    }
+}
+
+/// Run the event loop of a single-threaded reactor on this thread until `parker` is unparked,
+/// or `timeout` passed, instead of blocking on the parker. Returns `None` without waiting if
+/// the reactor runs on threads of its own, or another executor is running it already, in which
+/// case the caller parks as usual. Otherwise returns whether `parker` was unparked.
+///
+/// Wakes from other threads interrupt `poll` via the shard's [`mio::Waker`], which `parker`
+/// wakes on unpark while it runs the event loop, see [`Parker::interrupt_with`]. Executors
+/// that found the event loop taken are unparked once it is free again, so one of them takes
+/// over while they are parked.
+pub(super) fn park_inline(parker: &Parker, timeout: Option<Duration>) -> Option<bool> {
+    let reactor = REACTOR.get()?;
+    let inline = reactor.inline.as_ref()?;
+
+    // queued before trying the lock, so either the lock is free or whoever holds it sees us
+    // when letting go of it
+    reactor.waiting.lock().unwrap().push(parker.clone());
+    let Ok(mut event_loop) = inline.try_lock() else {
+        return None;
+    };
+    reactor
+        .waiting
+        .lock()
+        .unwrap()
+        .retain(|waiting| !waiting.ptr_eq(parker));
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    parker.interrupt_with(Some(&reactor.shards[0].drain_waker));
+    let mut full = false;
+    let unparked = loop {
+        // checked after the interrupt is in place, so an unpark either shows up here or wakes
+        // up the `poll` below
+        if parker.try_park() {
+            break true;
+        }
+        let remaining = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) => Some(remaining),
+                None => break false,
+            },
+            None => None,
+        };
+        full = event_loop.turn(if full {
+            Some(Duration::ZERO)
+        } else {
+            remaining
+        });
+    };
+    parker.interrupt_with(None);
+    drop(event_loop);
+
+    // queued after the last turn, while deregistering found the event loop taken
+    if reactor.shards[0].drain_scheduled.load(Ordering::Acquire) {
+        reactor.drain_inline();
+    }
+
+    for waiting in std::mem::take(&mut *reactor.waiting.lock().unwrap()) {
+        waiting.unpark();
+    }
+
+    Some(unparked)
+}
+
+/// Directions an event wakes the wakers of, see [`ReactorApi::set_waker_for`]. Errors and the
+/// peer hanging up wake both, so each finds out from its next operation.
+fn readiness_of(event: &Event) -> Interest {
+    let readable = event.is_readable() || event.is_read_closed();
+    let writable = event.is_writable() || event.is_write_closed();
+
+    match (readable, writable) {
+        (true, false) if !event.is_error() => Interest::READABLE,
+        (false, true) if !event.is_error() => Interest::WRITABLE,
+        _ => Interest::READABLE | Interest::WRITABLE,
+    }
+}
+
+/// Move high priority ids to the front, keeping the order of events within a priority.
+fn prioritize(ids: &mut [usize], high_priority: &HashSet<usize>) {
+    if !high_priority.is_empty() {
+        ids.sort_by_key(|id| !high_priority.contains(id));
+    }
+}
+
+/// Deregister every queued source, then drop it.
+fn drain_deregistrations(
+    poll: &Poll,
+    deregistrations: &mpsc::Receiver<Deregistration>,
+    shard: usize,
+) {
+    let shard = &REACTOR.get().unwrap().shards[shard];
+
+    // Clear the flag before draining, so anything queued from here on wakes us up again.
+    shard.drain_scheduled.store(false, Ordering::Release);
+
+    for (id, mut source) in deregistrations.try_iter() {
+        poll.registry()
+            .deregister(&mut *source)
+            .expect("Failed to deregister source with reactor");
+        // no more events for the source from here on, its slot can be reused
+        shard.wakers.remove(shard.key(id));
+        shard.ctl_calls.fetch_add(1, Ordering::Relaxed);
+        shard.stats.processed.fetch_add(1, Ordering::Relaxed);
     }
 }
 
-/// Initialise the reactor and start the event loop.
+/// Initialise the reactor with a single event loop.
 pub fn start() {
-    let wakers: Wakers = Arc::new(Mutex::new(HashMap::new()));
-
-    // OS event queue abstraction
-    // NOTE: The reactor does not "Own" the poll instance, the event_loop does.
-    // The reactor does have access to the registry though, to enable communicating
-    // with the event queue. It's only the Poll instance though that can block on the event queue.
-    let poll = Poll::new().unwrap();
-    let registry = poll.registry().try_clone().unwrap();
-    let next_id = AtomicUsize::new(1);
+    start_sharded(1, Routing::default());
+}
+
+/// Initialise the reactor with `shards` event loops, each on its own thread.
+pub fn start_sharded(shards: usize, routing: Routing) {
+    start_with(shards, routing, DEFAULT_EVENT_CAPACITY, false);
+}
+
+/// Same as [`start_sharded`], with room for `event_capacity` events per call to `poll` in each
+/// event loop. More events than that are left for the next call.
+///
+/// With `inline`, the reactor has a single shard whose event loop is run by the executors
+/// whenever they park, rather than on a thread of its own, see [`park_inline`].
+pub(super) fn start_with(shards: usize, routing: Routing, event_capacity: usize, inline: bool) {
+    // one put in place via `set_reactor` takes the place of ours
+    if REACTOR.get().is_none() && API.get().is_some() {
+        return;
+    }
+
+    assert!(shards > 0, "Reactor needs at least one shard");
+    assert!(
+        event_capacity > 0,
+        "Reactor needs room for at least one event"
+    );
+    // ids are slab keys times the number of shards, which leave 8 bits for it
+    assert!(shards <= 256, "Reactor supports at most 256 shards");
+    assert!(
+        !inline || shards == 1,
+        "Single-threaded reactor runs a single shard"
+    );
+
+    let stride = shards;
+    let mut loops = Vec::with_capacity(shards);
+    let shards: Vec<Shard> = (0..shards)
+        .map(|_| {
+            // OS event queue abstraction
+            // NOTE: The reactor does not "Own" the poll instance, the event_loop does.
+            // The reactor does have access to the registry though, to enable communicating
+            // with the event queue. It's only the Poll instance though that can block on the
+            // event queue.
+            let poll = Poll::new().unwrap();
+            let registry = poll.registry().try_clone().unwrap();
+            let drain_waker = mio::Waker::new(poll.registry(), DRAIN_TOKEN).unwrap();
+            let (deregistrations, queued) = mpsc::channel();
+
+            loops.push((poll, queued));
+
+            Shard {
+                wakers: WakerSlab::default(),
+                stride,
+                high_priority: Mutex::new(HashSet::new()),
+                interests: Mutex::new(HashMap::new()),
+                registry,
+                ctl_calls: AtomicUsize::new(0),
+                deregistrations,
+                drain_waker,
+                drain_scheduled: AtomicBool::new(false),
+                stats: DeregisterCounters::default(),
+                dispatch: DispatchCounters::default(),
+            }
+        })
+        .collect();
+
+    let inline = inline.then(|| {
+        let (poll, queued) = loops.pop().unwrap();
+        Mutex::new(EventLoop::new(poll, queued, 0, event_capacity))
+    });
+
     let reactor = Reactor {
-        wakers: wakers.clone(),
-        registry,
-        next_id,
+        shards,
+        routing,
+        next_shard: AtomicUsize::new(0),
+        inline,
+        waiting: Mutex::new(Vec::new()),
     };
 
     // Set global reactor instance
     // From this point, the reactor is alive and running
     REACTOR.set(reactor).ok().expect("Reactor already running");
+    let reactor: &'static dyn ReactorApi = REACTOR.get().unwrap();
+    API.set(reactor).ok().expect("Reactor already running");
+
+    // spawn a new OS thread per shard that runs its event_loop, none when single-threaded.
+    // The event loop makes use of the Reactor helper methods to modify state.
+    // NOTE: the event loop finds its wakers through the global reactor.
+    for (i, (poll, queued)) in loops.into_iter().enumerate() {
+        thread::Builder::new()
+            .name(format!("reactor-{i}"))
+            .spawn(move || event_loop(poll, queued, i, event_capacity))
+            .expect("Failed to spawn reactor thread");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[test]
+    fn rapid_register_deregister_is_drained_by_reactor() {
+        crate::runtime::init_for_tests();
+        let reactor = reactor();
+
+        for _ in 0..500 {
+            let (mut a, mut b) = mio::net::UnixStream::pair().unwrap();
+            let (id_a, id_b) = (reactor.next_id(), reactor.next_id());
+            reactor.register(&mut a, Interest::READABLE, id_a);
+            reactor.register(&mut b, Interest::READABLE, id_b);
+
+            // leave an event in flight for a source that is being deregistered
+            a.write_all(b"x").unwrap();
+
+            reactor.deregister(Box::new(a), id_a);
+            reactor.deregister(Box::new(b), id_b);
+        }
+
+        // the queue is FIFO, so ours are done once the reactor caught up with this snapshot
+        let queued = reactor.deregister_stats().queued;
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while reactor.deregister_stats().processed < queued {
+            assert!(
+                Instant::now() < deadline,
+                "deregistrations were not drained"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let stats = reactor.deregister_stats();
+        assert!(stats.wakeups <= stats.queued);
+    }
+
+    #[test]
+    fn metrics_count_event_loop_iterations_and_syscalls() {
+        crate::runtime::init_for_tests();
+        let reactor = reactor();
+        let before = reactor.metrics();
+
+        let (mut a, _b) = mio::net::UnixStream::pair().unwrap();
+        let id = reactor.next_id();
+        reactor.register(&mut a, Interest::READABLE, id);
+        assert!(reactor.metrics().ctl_calls > before.ctl_calls);
+
+        // the deregistration wakes the event loop, which counts as an iteration
+        reactor.deregister(Box::new(a), id);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while reactor.metrics().deregister.processed <= before.deregister.processed {
+            assert!(Instant::now() < deadline, "deregistration was not drained");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let after = reactor.metrics();
+        assert!(after.dispatch.iterations > before.dispatch.iterations);
+        assert!(after.dispatch.iterations >= after.dispatch.ticks);
+        assert!(after.ctl_calls >= before.ctl_calls + 2);
+    }
+
+    #[test]
+    fn executor_routing_keeps_a_threads_sources_on_one_shard() {
+        crate::runtime::init_for_tests();
+        let reactor = reactor();
+
+        let ids: Vec<Vec<usize>> = (0..4)
+            .map(|_| thread::spawn(|| (0..10).map(|_| super::reactor().next_id()).collect()))
+            .map(|handle| handle.join().unwrap())
+            .collect();
+
+        for thread_ids in &ids {
+            let shard = reactor.shard_of(thread_ids[0]);
+            assert!(shard < reactor.shard_count());
+            assert!(thread_ids.iter().all(|id| reactor.shard_of(*id) == shard));
+        }
+
+        let mut all: Vec<usize> = ids.concat();
+        all.sort();
+        all.dedup();
+        assert_eq!(all.len(), 40, "ids must be unique across shards");
+
+        for id in all {
+            reactor.release_id(id);
+        }
+    }
+
+    #[test]
+    fn high_priority_ids_are_woken_first() {
+        let mut ids = vec![1, 2, 3, 4, 5];
+        prioritize(&mut ids, &HashSet::from([4, 2]));
+        assert_eq!(ids, vec![2, 4, 1, 3, 5]);
+    }
+
+    #[test]
+    fn unchanged_waker_is_not_stored_again() {
+        let mut executor = crate::runtime::init_for_tests();
+        let (mut a, _b) = mio::net::UnixStream::pair().unwrap();
+        let id = reactor().next_id();
+        reactor().register(&mut a, Interest::READABLE, id);
+
+        executor.block_on(async move {
+            let mut stored = StoredWaker::default();
+            let mut polls = 0;
+            std::future::poll_fn(|cx| {
+                reactor().update_waker(cx, id, &mut stored);
+                polls += 1;
+                if polls == 1 {
+                    // removed behind the back of `stored`, so we can tell whether it is set again
+                    reactor().clear_waker(id);
+                    cx.waker().wake_by_ref();
+                    return std::task::Poll::Pending;
+                }
+                std::task::Poll::Ready(())
+            })
+            .await;
+            assert!(!reactor().has_waker(id), "same waker, not stored again");
+
+            stored.clear();
+            std::future::poll_fn(|cx| {
+                reactor().update_waker(cx, id, &mut stored);
+                std::task::Poll::Ready(())
+            })
+            .await;
+            assert!(reactor().has_waker(id));
+            reactor().clear_waker(id);
+        });
+
+        reactor().deregister(Box::new(a), id);
+    }
+
+    #[test]
+    fn changed_interest_wakes_the_stored_waker() {
+        let (done, finished) = std::sync::mpsc::channel();
+
+        // on a thread of its own, as the task is never polled again if the waker is not woken
+        std::thread::spawn(move || {
+            let mut executor = crate::runtime::init_for_tests();
+            let (mut a, _b) = mio::net::UnixStream::pair().unwrap();
+            let id = reactor().next_id();
+            reactor().register(&mut a, Interest::READABLE, id);
 
-    // spawn a new OS thread that runs the main event_loop. The event loop
-    // makes use of the Reactor helper methods to modify state.
-    // NOTE: could have just allowed it to access reactor wakers directly without
-    // passing them in as arguments.
-    thread::spawn(move || event_loop(poll, wakers));
+            executor.block_on(async move {
+                let mut polls = 0;
+                std::future::poll_fn(|cx| {
+                    polls += 1;
+                    if polls > 1 {
+                        return std::task::Poll::Ready(());
+                    }
+
+                    // nothing to read, but writable straight away
+                    reactor().set_waker(cx, id);
+                    reactor().set_interest(&mut a, Interest::WRITABLE, id);
+                    std::task::Poll::Pending
+                })
+                .await;
+
+                reactor().deregister(Box::new(a), id);
+            });
+            done.send(()).unwrap();
+        });
+
+        assert_eq!(finished.recv_timeout(Duration::from_secs(5)), Ok(()));
+    }
+
+    #[test]
+    fn wakers_left_behind_by_a_finished_task_are_purged() {
+        let mut executor = crate::runtime::init_for_tests();
+        let (mut a, _b) = mio::net::UnixStream::pair().unwrap();
+        let id = reactor().next_id();
+        reactor().register(&mut a, Interest::READABLE, id);
+
+        // a task that stores a waker and finishes without clearing it, as a leaked future would
+        executor.block_on(std::future::poll_fn(move |cx| {
+            reactor().set_waker(cx, id);
+            std::task::Poll::Ready(())
+        }));
+
+        assert!(!reactor().has_waker(id));
+        reactor().deregister(Box::new(a), id);
+    }
+
+    #[test]
+    fn reader_and_writer_tasks_on_one_source_are_both_woken() {
+        use std::{cell::Cell, rc::Rc};
+
+        use crate::runtime::{spawn_local, yield_now};
+
+        /// Resolves once woken after leaving its waker for `interest`
+        fn wait_for(
+            id: usize,
+            interest: Interest,
+            woken: Rc<Cell<bool>>,
+        ) -> impl Future<Output = ()> {
+            let mut waiting = false;
+            std::future::poll_fn(move |cx| {
+                if waiting {
+                    reactor().clear_waker_for(id, interest);
+                    woken.set(true);
+                    return std::task::Poll::Ready(());
+                }
+                reactor().set_waker_for(cx, id, interest);
+                waiting = true;
+                std::task::Poll::Pending
+            })
+        }
+
+        let mut executor = crate::runtime::init_for_tests();
+        let (mut ours, mut peer) = mio::net::UnixStream::pair().unwrap();
+        let id = reactor().next_id();
+        reactor().register(&mut ours, Interest::READABLE | Interest::WRITABLE, id);
+        let (read, written) = (Rc::new(Cell::new(false)), Rc::new(Cell::new(false)));
+
+        let woken = (read.clone(), written.clone());
+        executor.block_on(async move {
+            // the reader stores its waker first, the writer's must not replace it
+            spawn_local(wait_for(id, Interest::READABLE, woken.0));
+            spawn_local(wait_for(id, Interest::WRITABLE, woken.1));
+            yield_now().await;
+
+            peer.write_all(b"x").unwrap();
+        });
+
+        assert!(read.get() && written.get());
+        reactor().deregister(Box::new(ours), id);
+    }
 }
@@ -0,0 +1,384 @@
+//! A reactor without an event loop, for unit tests of leaf futures.
+//!
+//! [`install`] puts a mock in place of the reactor for the calling thread: from then on
+//! [`reactor`](super::reactor) hands out the mock there, so futures run unchanged. Rather than
+//! making syscalls it records every call made to it, and it never sees any events: a test
+//! marks ids as ready itself via [`MockIo::ready`], which wakes whatever waker is stored.
+//!
+//! ```ignore
+//! let mock = mock::install();
+//! let mut stream = UnixStream::connect(&path)?;
+//! let mut read = Box::pin(stream.read(&mut buf));
+//! assert!(read.as_mut().poll(&mut cx).is_pending());
+//!
+//! let [Call::Register { id, .. }, Call::SetWaker { .. }] = mock.calls()[..] else { panic!() };
+//! peer.write_all(b"ping")?;
+//! assert!(mock.ready(id));
+//! ```
+//!
+//! Sockets are still real ones, only their readiness is up to the test. Other threads keep
+//! using the real reactor, if one is running.
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    ops::Deref,
+    sync::Mutex,
+    task::{Context, Waker},
+    thread::{self, ThreadId},
+};
+
+use mio::Interest;
+
+use mio::event::Source;
+
+use super::{DeregisterStats, DispatchStats, Priority, ReactorApi, ReactorMetrics};
+
+thread_local! {
+    /// Reactor `reactor()` hands out on this thread instead of the global one, see `install`
+    static INSTALLED: Cell<Option<&'static dyn ReactorApi>> = const { Cell::new(None) };
+}
+
+/// A call made to the reactor, as recorded by [`MockIo::calls`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Call {
+    Register {
+        id: usize,
+        interest: Interest,
+        priority: Priority,
+    },
+    Reregister {
+        id: usize,
+        interest: Interest,
+    },
+    SetInterest {
+        id: usize,
+        interest: Interest,
+    },
+    Detach {
+        id: usize,
+    },
+    /// A waker was stored for `id`. Not recorded by `Reactor::update_waker` when the same
+    /// waker is stored already.
+    SetWaker {
+        id: usize,
+    },
+    ClearWaker {
+        id: usize,
+    },
+    Deregister {
+        id: usize,
+    },
+    ReleaseId {
+        id: usize,
+    },
+}
+
+/// Put a mock in place of the reactor for the calling thread, until the returned guard is
+/// dropped.
+///
+/// Each mock is leaked, for `reactor()` to hand out a `&'static dyn ReactorApi`. That is a
+/// few bytes per call, so meant for tests only.
+///
+/// # Panics
+///
+/// If a mock is installed on this thread already.
+pub fn install() -> MockGuard {
+    let mock: &'static MockIo = Box::leak(Box::default());
+
+    INSTALLED.with(|installed| {
+        assert!(installed.get().is_none(), "mock reactor already installed");
+        installed.set(Some(mock));
+    });
+
+    MockGuard { mock }
+}
+
+/// The mock installed on this thread, if any.
+pub(super) fn installed() -> Option<&'static dyn ReactorApi> {
+    INSTALLED.with(Cell::get)
+}
+
+/// Keeps the mock installed on its thread, see [`install`]. Derefs to the mock, to mark ids
+/// as ready and look at the calls made.
+pub struct MockGuard {
+    mock: &'static MockIo,
+}
+
+impl Deref for MockGuard {
+    type Target = MockIo;
+
+    fn deref(&self) -> &MockIo {
+        self.mock
+    }
+}
+
+impl Drop for MockGuard {
+    fn drop(&mut self) {
+        INSTALLED.with(|installed| installed.set(None));
+    }
+}
+
+/// State of a mock reactor, see the module docs.
+#[derive(Default)]
+pub struct MockIo {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Last id handed out, ids start at 1 like those of the real reactor
+    last_id: usize,
+    /// Registered ids and the events they are registered for
+    sources: HashMap<usize, Interest>,
+    wakers: HashMap<usize, Waker>,
+    /// Executor thread and task that stored each waker, if stored from within a task
+    owners: HashMap<usize, (ThreadId, usize)>,
+    /// Ids marked ready while no waker was stored, woken as soon as one is
+    missed: HashSet<usize>,
+    calls: Vec<Call>,
+    /// register, reregister, set_interest, detach and deregister calls
+    ctl_calls: usize,
+}
+
+impl MockIo {
+    /// Mark `id` as ready, waking the task waiting on it. Returns false if no waker is
+    /// stored, in which case the next one stored is woken straight away, as the real reactor
+    /// does for events nobody was waiting for.
+    pub fn ready(&self, id: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.wakers.get(&id).cloned() {
+            Some(waker) => {
+                drop(state);
+                waker.wake();
+                true
+            }
+            None => {
+                state.missed.insert(id);
+                false
+            }
+        }
+    }
+
+    /// Every call made so far, oldest first.
+    pub fn calls(&self) -> Vec<Call> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Same as [`MockIo::calls`], clearing them, to only look at what happens from here on.
+    pub fn take_calls(&self) -> Vec<Call> {
+        std::mem::take(&mut self.state.lock().unwrap().calls)
+    }
+
+    /// Events `id` is registered for, `None` if it is not registered.
+    pub fn interest(&self, id: usize) -> Option<Interest> {
+        self.state.lock().unwrap().sources.get(&id).copied()
+    }
+
+    /// Number of registered sources.
+    pub fn sources(&self) -> usize {
+        self.state.lock().unwrap().sources.len()
+    }
+
+    fn record(&self, call: Call) -> std::sync::MutexGuard<'_, State> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(call);
+        state
+    }
+}
+
+impl ReactorApi for MockIo {
+    fn next_id(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.last_id += 1;
+        state.last_id
+    }
+
+    fn register_with_priority(
+        &self,
+        _: &mut dyn Source,
+        interest: Interest,
+        id: usize,
+        priority: Priority,
+    ) {
+        let mut state = self.record(Call::Register {
+            id,
+            interest,
+            priority,
+        });
+        state.sources.insert(id, interest);
+        state.ctl_calls += 1;
+    }
+
+    fn reregister(&self, _: &mut dyn Source, interest: Interest, id: usize) {
+        let mut state = self.record(Call::Reregister { id, interest });
+        state.sources.insert(id, interest);
+        state.ctl_calls += 1;
+        // events that fired while detached are gone, see `ReactorApi::reregister`
+        state.missed.insert(id);
+    }
+
+    fn set_interest(&self, _: &mut dyn Source, interest: Interest, id: usize) {
+        let mut state = self.record(Call::SetInterest { id, interest });
+        assert!(state.sources.contains_key(&id), "id {id} is not registered");
+        state.sources.insert(id, interest);
+        state.ctl_calls += 1;
+    }
+
+    fn detach(&self, _: &mut dyn Source, id: usize) {
+        let mut state = self.record(Call::Detach { id });
+        state.ctl_calls += 1;
+        state.forget(id);
+    }
+
+    fn set_waker(&self, cx: &Context, id: usize) {
+        let mut state = self.record(Call::SetWaker { id });
+        state.wakers.insert(id, cx.waker().clone());
+        match crate::runtime::executor::current_task() {
+            Some(task) => state.owners.insert(id, (thread::current().id(), task)),
+            None => state.owners.remove(&id),
+        };
+        if state.missed.remove(&id) {
+            drop(state);
+            cx.waker().wake_by_ref();
+        }
+    }
+
+    fn clear_waker(&self, id: usize) {
+        let mut state = self.record(Call::ClearWaker { id });
+        state.wakers.remove(&id);
+        state.owners.remove(&id);
+    }
+
+    fn has_waker(&self, id: usize) -> bool {
+        self.state.lock().unwrap().wakers.contains_key(&id)
+    }
+
+    fn has_pending_io(&self) -> bool {
+        !self.state.lock().unwrap().wakers.is_empty()
+    }
+
+    /// Not recorded as calls, the executor makes these for every task that completes.
+    fn purge_task(&self, task: usize) -> usize {
+        let owner = (thread::current().id(), task);
+        let mut state = self.state.lock().unwrap();
+        let stale: Vec<_> = state
+            .owners
+            .iter()
+            .filter(|(_, registered_by)| **registered_by == owner)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in &stale {
+            state.wakers.remove(id);
+            state.owners.remove(id);
+        }
+        stale.len()
+    }
+
+    fn io_owned_by_current_thread(&self) -> Vec<(usize, usize)> {
+        let thread = thread::current().id();
+
+        self.state
+            .lock()
+            .unwrap()
+            .owners
+            .iter()
+            .filter(|(_, (owner, _))| *owner == thread)
+            .map(|(id, (_, task))| (*id, *task))
+            .collect()
+    }
+
+    fn interest(&self, id: usize) -> Option<Interest> {
+        MockIo::interest(self, id)
+    }
+
+    /// The source is dropped, and so closed, right away.
+    fn deregister(&self, _: Box<dyn Source + Send>, id: usize) {
+        let mut state = self.record(Call::Deregister { id });
+        state.ctl_calls += 1;
+        state.forget(id);
+    }
+
+    fn release_id(&self, id: usize) {
+        self.record(Call::ReleaseId { id }).forget(id);
+    }
+
+    fn ctl_calls(&self) -> usize {
+        self.state.lock().unwrap().ctl_calls
+    }
+
+    fn metrics(&self) -> ReactorMetrics {
+        ReactorMetrics {
+            sources: self.sources(),
+            ctl_calls: self.ctl_calls(),
+            dispatch: DispatchStats::default(),
+            deregister: DeregisterStats::default(),
+        }
+    }
+}
+
+impl State {
+    fn forget(&mut self, id: usize) {
+        self.sources.remove(&id);
+        self.wakers.remove(&id);
+        self.owners.remove(&id);
+        self.missed.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::Wake,
+    };
+
+    use super::*;
+    use crate::runtime::reactor;
+
+    /// Counts how often it was woken
+    #[derive(Default)]
+    struct Wakes(AtomicUsize);
+
+    impl Wake for Wakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn ready_wakes_the_stored_waker() {
+        let mock = install();
+        let wakes = Arc::new(Wakes::default());
+        let waker = Waker::from(wakes.clone());
+        let cx = Context::from_waker(&waker);
+
+        let id = reactor().next_id();
+        assert!(!mock.ready(id), "nobody waiting yet");
+        reactor().set_waker(&cx, id);
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 1, "missed event replayed");
+
+        assert!(mock.ready(id));
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 2);
+        reactor().clear_waker(id);
+        assert!(!mock.ready(id));
+        assert_eq!(
+            mock.calls(),
+            [Call::SetWaker { id }, Call::ClearWaker { id },]
+        );
+    }
+
+    #[test]
+    fn only_the_installing_thread_sees_the_mock() {
+        let mock = install();
+        assert!(installed().is_some());
+        let elsewhere = std::thread::spawn(|| installed().is_none()).join().unwrap();
+        assert!(elsewhere);
+
+        drop(mock);
+        assert!(installed().is_none());
+    }
+}
@@ -0,0 +1,696 @@
+//! Queue of ids of tasks that are ready to be polled.
+//!
+//! Wakers push onto the queue from any thread, while only the executor that owns it pops.
+//! Three implementations are available, see [`ReadyQueueKind`]:
+//!
+//! - `Mutex`: a `Mutex<Vec<usize>>`. The lock hides all memory ordering concerns: everything
+//!   written before unlocking is visible to whoever locks next. But every wake up takes the
+//!   lock, so a reactor thread waking tasks contends with the executor popping them.
+//! - `Atomic`: a lock-free Treiber stack. Pushing never blocks, at the cost of an allocation
+//!   per push, and the ordering guarantees the Mutex gave us for free have to be spelled out.
+//!   The annotations on [`TreiberStack`] walk through what each fence is for.
+//! - `Segmented`: a lock-free queue of blocks of slots, after crossbeam's `SegQueue`. Pushing
+//!   never blocks either, and allocates once per block rather than once per push, see
+//!   [`SegmentedQueue`].
+//!
+//! The first two pop the most recently pushed id first, so switching between them does not
+//! change the order tasks are polled in. The segmented queue pops the oldest id first, so a
+//! task woken early is not left behind by tasks that keep waking up.
+//!
+//! Every id is queued along with the time it was pushed, so the executor can tell how long a
+//! woken task waited to be polled, see `ExecutorMetrics`.
+//!
+//! The atomic stack is checked with [loom](https://docs.rs/loom), which runs the tests below
+//! under every possible interleaving and memory ordering the model allows:
+//!
+//! ```bash
+//! RUSTFLAGS="--cfg loom" cargo test --release -p reactor-executor loom_tests
+//! ```
+use std::{mem::MaybeUninit, ptr, sync::Mutex, time::Instant};
+
+#[cfg(loom)]
+use loom::{
+    cell::UnsafeCell,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+#[cfg(not(loom))]
+use std::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// Which implementation an executor uses for its ready queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadyQueueKind {
+    #[default]
+    Mutex,
+    Atomic,
+    Segmented,
+}
+
+pub(crate) struct ReadyQueue {
+    entries: Entries,
+    /// Number of entries queued
+    len: AtomicUsize,
+}
+
+/// A task id, and when it was queued.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Entry {
+    pub(crate) id: usize,
+    pub(crate) queued_at: Instant,
+}
+
+enum Entries {
+    Mutex(Mutex<Vec<Entry>>),
+    Atomic(TreiberStack<Entry>),
+    Segmented(SegmentedQueue<Entry>),
+}
+
+impl ReadyQueue {
+    pub(crate) fn new(kind: ReadyQueueKind) -> Self {
+        let entries = match kind {
+            ReadyQueueKind::Mutex => Entries::Mutex(Mutex::new(Vec::new())),
+            ReadyQueueKind::Atomic => Entries::Atomic(TreiberStack::new()),
+            ReadyQueueKind::Segmented => Entries::Segmented(SegmentedQueue::new()),
+        };
+
+        Self {
+            entries,
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn kind(&self) -> ReadyQueueKind {
+        match self.entries {
+            Entries::Mutex(_) => ReadyQueueKind::Mutex,
+            Entries::Atomic(_) => ReadyQueueKind::Atomic,
+            Entries::Segmented(_) => ReadyQueueKind::Segmented,
+        }
+    }
+
+    pub(crate) fn push(&self, id: usize) {
+        self.push_all(&[id]);
+    }
+
+    /// Push all `ids` in order, taking the lock only once for the Mutex based queue.
+    pub(crate) fn push_all(&self, ids: &[usize]) {
+        let queued_at = Instant::now();
+        let entries = ids.iter().map(|&id| Entry { id, queued_at });
+
+        // counted before pushing, so the length never drops below 0 on a concurrent pop
+        self.len.fetch_add(ids.len(), Ordering::Relaxed);
+        match &self.entries {
+            Entries::Mutex(queue) => queue.lock().unwrap().extend(entries),
+            Entries::Atomic(stack) => entries.for_each(|entry| stack.push(entry)),
+            Entries::Segmented(queue) => entries.for_each(|entry| queue.push(entry)),
+        }
+    }
+
+    pub(crate) fn pop(&self) -> Option<usize> {
+        self.pop_entry().map(|entry| entry.id)
+    }
+
+    /// Same as [`ReadyQueue::pop`], along with when the id was queued.
+    pub(crate) fn pop_entry(&self) -> Option<Entry> {
+        let entry = match &self.entries {
+            Entries::Mutex(queue) => queue.lock().unwrap().pop(),
+            Entries::Atomic(stack) => stack.pop(),
+            Entries::Segmented(queue) => queue.pop(),
+        };
+        if entry.is_some() {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+        }
+        entry
+    }
+
+    /// Remove all ids, in the order they were pushed.
+    pub(crate) fn take_all(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = std::iter::from_fn(|| self.pop()).collect();
+        // the stacks pop the most recent id first
+        if self.kind() != ReadyQueueKind::Segmented {
+            ids.reverse();
+        }
+        ids
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        match &self.entries {
+            Entries::Mutex(queue) => queue.lock().unwrap().is_empty(),
+            Entries::Atomic(stack) => stack.is_empty(),
+            Entries::Segmented(queue) => queue.is_empty(),
+        }
+    }
+
+    /// Number of ids queued, which may be out of date by the time it is looked at if wakers
+    /// push concurrently.
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Copy of the ids currently queued, in the order they were pushed.
+    pub(crate) fn snapshot(&self) -> Vec<usize> {
+        let entries = match &self.entries {
+            Entries::Mutex(queue) => queue.lock().unwrap().clone(),
+            Entries::Atomic(stack) => stack.snapshot(),
+            Entries::Segmented(queue) => queue.snapshot(),
+        };
+        entries.iter().map(|entry| entry.id).collect()
+    }
+}
+
+impl Default for ReadyQueue {
+    fn default() -> Self {
+        Self::new(ReadyQueueKind::default())
+    }
+}
+
+struct Node<T> {
+    value: T,
+    /// Written by the pushing thread and read by the consumer. Kept in an `UnsafeCell` so loom
+    /// can check every read is ordered after the write it should see.
+    next: UnsafeCell<*mut Node<T>>,
+}
+
+/// Same API as `loom::cell::UnsafeCell`, which gives access through closures so it can track
+/// each access.
+#[cfg(not(loom))]
+struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    fn new(value: T) -> Self {
+        Self(std::cell::UnsafeCell::new(value))
+    }
+
+    fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+/// Lock-free stack for many producers and a single consumer.
+///
+/// The classic Treiber stack suffers from the ABA problem: a popping thread reads `head` and
+/// `head.next`, another thread pops and frees `head`, pushes a new node that happens to get the
+/// same address, and the first thread's compare-exchange succeeds with a stale `next`. That
+/// can only happen if two threads pop concurrently. Here only the executor owning the queue
+/// pops, while wakers only ever push, which rules it out. `consuming` enforces that: a second
+/// concurrent consumer panics rather than corrupting the stack.
+pub(crate) struct TreiberStack<T: Copy> {
+    head: AtomicPtr<Node<T>>,
+    /// Set while a thread is popping or walking the stack
+    consuming: AtomicBool,
+}
+
+// SAFETY: nodes are only reached through `head`, and handed over between threads with the
+// fences described on `push` and `pop`.
+unsafe impl<T: Copy + Send> Send for TreiberStack<T> {}
+unsafe impl<T: Copy + Send> Sync for TreiberStack<T> {}
+
+impl<T: Copy> TreiberStack<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            consuming: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn push(&self, value: T) {
+        let node = Box::into_raw(Box::new(Node {
+            value,
+            next: UnsafeCell::new(ptr::null_mut()),
+        }));
+
+        // Relaxed is enough to read `head`: we only use it as the expected value in the
+        // compare-exchange below, which fails if it is out of date.
+        let mut head = self.head.load(Ordering::Relaxed);
+
+        loop {
+            // SAFETY: the node is not published yet, we are the only ones with access.
+            unsafe { (*node).next.with_mut(|next| *next = head) };
+
+            // Release fence: the writes to `node.value` and `node.next` above must happen-before
+            // any thread that reads `node` out of `head`. Paired with the Acquire fence in
+            // `pop`. Without it, the consumer could see the new head but a stale `next`, and
+            // walk off into freed memory.
+            fence(Ordering::Release);
+
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                // another push won the race, try again on top of it
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    pub(crate) fn pop(&self) -> Option<T> {
+        let _consumer = Consumer::enter(&self.consuming);
+
+        let mut head = self.head.load(Ordering::Relaxed);
+
+        loop {
+            if head.is_null() {
+                return None;
+            }
+
+            // Acquire fence: synchronises with the Release fence of the push that stored
+            // `head`, so its writes to the node are visible before we dereference it.
+            fence(Ordering::Acquire);
+
+            // SAFETY: only the (single) consumer frees nodes, so `head` is still allocated.
+            let next = unsafe { (*head).next.with(|next| *next) };
+
+            match self
+                .head
+                .compare_exchange_weak(head, next, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    // SAFETY: the node is unlinked, nobody else can reach it any more.
+                    let node = unsafe { Box::from_raw(head) };
+                    return Some(node.value);
+                }
+                // A push moved `head`, or the weak compare-exchange failed spuriously. Pushes
+                // never remove nodes, so retrying with the current head is safe.
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Relaxed).is_null()
+    }
+
+    /// Walk the stack without removing anything, oldest value first.
+    fn snapshot(&self) -> Vec<T> {
+        let _consumer = Consumer::enter(&self.consuming);
+
+        let mut values = Vec::new();
+        let mut node = self.head.load(Ordering::Relaxed);
+        // same reasoning as in `pop`
+        fence(Ordering::Acquire);
+
+        while !node.is_null() {
+            // SAFETY: nodes are only freed by the consumer, which is us.
+            unsafe {
+                values.push((*node).value);
+                node = (*node).next.with(|next| *next);
+            }
+        }
+
+        values.reverse();
+        values
+    }
+}
+
+impl<T: Copy> Drop for TreiberStack<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// Marks the calling thread as the consumer of a stack until dropped.
+struct Consumer<'a>(&'a AtomicBool);
+
+impl<'a> Consumer<'a> {
+    fn enter(consuming: &'a AtomicBool) -> Self {
+        // Acquire / Release so one consumer's frees happen-before the next one's reads
+        let busy = consuming.swap(true, Ordering::Acquire);
+        assert!(
+            !busy,
+            "Ready queues must only be consumed by one thread at a time"
+        );
+        Self(consuming)
+    }
+}
+
+impl Drop for Consumer<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Slots per block of a [`SegmentedQueue`]. Small under loom, so the models cover moving on
+/// to the next block.
+const BLOCK_CAP: usize = if cfg!(loom) { 2 } else { 31 };
+/// Positions per block of a [`SegmentedQueue`], one more than it has slots.
+const LAP: usize = BLOCK_CAP + 1;
+
+struct Slot<T> {
+    value: UnsafeCell<MaybeUninit<T>>,
+    /// Set by the producer once `value` is written
+    written: AtomicBool,
+}
+
+struct Block<T> {
+    slots: [Slot<T>; BLOCK_CAP],
+    /// Set by the producer that claims the last slot, before it writes that slot
+    next: AtomicPtr<Block<T>>,
+}
+
+impl<T> Block<T> {
+    fn new() -> Box<Self> {
+        Box::new(Self {
+            slots: std::array::from_fn(|_| Slot {
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+                written: AtomicBool::new(false),
+            }),
+            next: AtomicPtr::new(ptr::null_mut()),
+        })
+    }
+}
+
+/// Lock-free FIFO queue for many producers and a single consumer, after crossbeam's
+/// `SegQueue`.
+///
+/// Values are kept in blocks of [`BLOCK_CAP`] slots, linked front to back. A producer claims
+/// a slot by moving `tail` on with a compare-exchange, then writes the slot and marks it
+/// written. The consumer reads slots in order, and frees a block once it read its last slot.
+/// Unlike [`TreiberStack`], that is an allocation per block rather than per push, and
+/// producers only race each other for `tail`, never the consumer.
+///
+/// Positions count [`LAP`] per block, one more than there are slots. The extra offset,
+/// `BLOCK_CAP`, means the next block is being installed: the producer that claims the last
+/// slot of a block installs the next one, while other producers wait for it rather than
+/// allocating blocks of their own.
+///
+/// A slot can be claimed but not written yet while later slots are. Popping stops there and
+/// reports nothing ready, which is fine for wakers: the producer has yet to return from
+/// `push`, and so to unpark the executor.
+pub(crate) struct SegmentedQueue<T: Copy> {
+    /// Position of the next slot to claim
+    tail: AtomicUsize,
+    /// Block `tail` points into. Moved on before `tail`, so a producer that sees the new `tail`
+    /// sees the new block too.
+    tail_block: AtomicPtr<Block<T>>,
+    /// Position of the next slot to read. Only written by the consumer
+    head: AtomicUsize,
+    /// Block `head` points into, only accessed by the consumer
+    head_block: UnsafeCell<*mut Block<T>>,
+    /// Set while a thread is popping or walking the queue
+    consuming: AtomicBool,
+}
+
+// SAFETY: blocks are only reached through `tail_block` and `head_block`, and slots are handed
+// over between threads with the orderings described on `push` and `pop`.
+unsafe impl<T: Copy + Send> Send for SegmentedQueue<T> {}
+unsafe impl<T: Copy + Send> Sync for SegmentedQueue<T> {}
+
+impl<T: Copy> SegmentedQueue<T> {
+    pub(crate) fn new() -> Self {
+        let block = Box::into_raw(Block::new());
+
+        Self {
+            tail: AtomicUsize::new(0),
+            tail_block: AtomicPtr::new(block),
+            head: AtomicUsize::new(0),
+            head_block: UnsafeCell::new(block),
+            consuming: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn push(&self, value: T) {
+        // Acquire both: `tail_block` is stored before `tail` moves into its range, so reading
+        // `tail` first gets us its block or a later one. A later one means `tail` moved on too,
+        // and the compare-exchange below fails.
+        let mut tail = self.tail.load(Ordering::Acquire);
+        let mut block = self.tail_block.load(Ordering::Acquire);
+        let mut next_block = None;
+
+        loop {
+            let offset = tail % LAP;
+
+            // the producer of the last slot is installing the next block, wait for it
+            if offset == BLOCK_CAP {
+                snooze();
+                tail = self.tail.load(Ordering::Acquire);
+                block = self.tail_block.load(Ordering::Acquire);
+                continue;
+            }
+
+            // allocated before claiming the last slot, so the others wait as briefly as possible
+            if offset + 1 == BLOCK_CAP && next_block.is_none() {
+                next_block = Some(Block::new());
+            }
+
+            match self.tail.compare_exchange_weak(
+                tail,
+                tail + 1,
+                Ordering::SeqCst,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    // SAFETY: the consumer frees a block only once it read every slot, and the
+                    // slot we claimed is not written yet.
+                    let slot = unsafe {
+                        if offset + 1 == BLOCK_CAP {
+                            let next = Box::into_raw(next_block.take().unwrap());
+                            // Release: the new block's initialisation happens-before anyone
+                            // loading it. Block first, then skip `tail` past the extra offset.
+                            self.tail_block.store(next, Ordering::Release);
+                            self.tail.store(tail + 2, Ordering::Release);
+                            (*block).next.store(next, Ordering::Release);
+                        }
+                        &(*block).slots[offset]
+                    };
+
+                    // SAFETY: claiming the slot made us its only writer
+                    slot.value.with_mut(|cell| unsafe { (*cell).write(value) });
+                    // Release: paired with the Acquire load in `pop`, so the value, and for the
+                    // last slot `next`, are visible to the consumer once it sees the flag. This
+                    // is our last access to the block, the consumer may free it from here on.
+                    slot.written.store(true, Ordering::Release);
+                    return;
+                }
+                // another push claimed the slot, or the weak compare-exchange failed spuriously
+                Err(current) => {
+                    tail = current;
+                    block = self.tail_block.load(Ordering::Acquire);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn pop(&self) -> Option<T> {
+        let _consumer = Consumer::enter(&self.consuming);
+
+        let head = self.head.load(Ordering::Relaxed);
+        let offset = head % LAP;
+
+        // SAFETY: only the (single) consumer frees blocks, so the head block is still
+        // allocated, and moves on to the next one only once all of its slots were read.
+        self.head_block.with_mut(|block| unsafe {
+            let slot = &(**block).slots[offset];
+            if !slot.written.load(Ordering::Acquire) {
+                return None;
+            }
+            let value = slot.value.with(|cell| (*cell).assume_init());
+
+            if offset + 1 == BLOCK_CAP {
+                // set by the producer of this slot before it marked the slot written
+                let next = (**block).next.load(Ordering::Acquire);
+                drop(Box::from_raw(*block));
+                *block = next;
+                self.head.store(head + 2, Ordering::Release);
+            } else {
+                self.head.store(head + 1, Ordering::Release);
+            }
+            Some(value)
+        })
+    }
+
+    /// True if no slot was claimed past the ones read. A claimed slot counts even before it is
+    /// written, see the type docs.
+    pub(crate) fn is_empty(&self) -> bool {
+        // `head` skips the extra offset of a block right away, while `tail` may still sit on it
+        self.head.load(Ordering::Acquire) >= self.tail.load(Ordering::Acquire)
+    }
+
+    /// Walk the queue without removing anything, oldest value first.
+    fn snapshot(&self) -> Vec<T> {
+        let _consumer = Consumer::enter(&self.consuming);
+
+        let mut values = Vec::new();
+        let mut position = self.head.load(Ordering::Relaxed);
+        let mut block = self.head_block.with(|block| unsafe { *block });
+
+        // SAFETY: blocks are only freed by the consumer, which is us, and the ones past the
+        // head block are linked before the last slot of the block before them is written.
+        unsafe {
+            loop {
+                let offset = position % LAP;
+                let slot = &(*block).slots[offset];
+                if !slot.written.load(Ordering::Acquire) {
+                    return values;
+                }
+                values.push(slot.value.with(|cell| (*cell).assume_init()));
+
+                if offset + 1 == BLOCK_CAP {
+                    block = (*block).next.load(Ordering::Acquire);
+                    position += 2;
+                } else {
+                    position += 1;
+                }
+            }
+        }
+    }
+}
+
+impl<T: Copy> Drop for SegmentedQueue<T> {
+    fn drop(&mut self) {
+        // every push returned, so no slot is claimed without being written, and the head
+        // block is the last one left once all values are popped
+        while self.pop().is_some() {}
+        self.head_block
+            .with_mut(|block| unsafe { drop(Box::from_raw(*block)) });
+    }
+}
+
+/// Back off while another producer installs the next block of a [`SegmentedQueue`].
+fn snooze() {
+    #[cfg(loom)]
+    loom::thread::yield_now();
+    #[cfg(not(loom))]
+    std::hint::spin_loop();
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn both_kinds_pop_most_recent_first() {
+        for kind in [ReadyQueueKind::Mutex, ReadyQueueKind::Atomic] {
+            let queue = ReadyQueue::new(kind);
+            for id in 0..3 {
+                queue.push(id);
+            }
+
+            assert_eq!(queue.snapshot(), vec![0, 1, 2]);
+            assert_eq!(queue.pop(), Some(2));
+            assert_eq!(queue.take_all(), vec![0, 1]);
+            assert!(queue.is_empty());
+        }
+    }
+
+    #[test]
+    fn segmented_queue_pops_oldest_first_across_blocks() {
+        let queue = ReadyQueue::new(ReadyQueueKind::Segmented);
+        let ids = 0..3 * BLOCK_CAP;
+        for id in ids.clone() {
+            queue.push(id);
+        }
+
+        assert_eq!(queue.snapshot(), ids.clone().collect::<Vec<_>>());
+        assert_eq!(queue.pop(), Some(0));
+        assert_eq!(queue.take_all(), ids.skip(1).collect::<Vec<_>>());
+        assert!(queue.is_empty());
+
+        // the emptied queue carries on in the block it stopped in
+        queue.push(7);
+        assert_eq!(queue.take_all(), vec![7]);
+    }
+
+    #[test]
+    fn concurrent_pushes_are_all_popped_once() {
+        for kind in [ReadyQueueKind::Atomic, ReadyQueueKind::Segmented] {
+            let queue = Arc::new(ReadyQueue::new(kind));
+
+            let producers: Vec<_> = (0..4)
+                .map(|t| {
+                    let queue = queue.clone();
+                    thread::spawn(move || {
+                        for i in 0..1000 {
+                            queue.push(t * 1000 + i);
+                        }
+                    })
+                })
+                .collect();
+
+            let mut popped = Vec::new();
+            while popped.len() < 4000 {
+                popped.extend(queue.pop());
+            }
+            for producer in producers {
+                producer.join().unwrap();
+            }
+
+            popped.sort();
+            assert_eq!(popped, (0..4000).collect::<Vec<_>>(), "{kind:?}");
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::{sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn pushes_from_two_threads_are_seen_by_consumer() {
+        loom::model(|| {
+            let stack = Arc::new(TreiberStack::new());
+
+            let producers: Vec<_> = (0..2)
+                .map(|id| {
+                    let stack = stack.clone();
+                    thread::spawn(move || stack.push(id))
+                })
+                .collect();
+
+            // pop concurrently with the pushes, as the executor would
+            let mut popped = Vec::new();
+            while popped.len() < 2 {
+                match stack.pop() {
+                    Some(id) => popped.push(id),
+                    None => thread::yield_now(),
+                }
+            }
+
+            for producer in producers {
+                producer.join().unwrap();
+            }
+
+            popped.sort();
+            assert_eq!(popped, vec![0, 1]);
+        });
+    }
+
+    #[test]
+    fn segmented_pushes_into_the_next_block_are_seen_by_consumer() {
+        loom::model(|| {
+            let queue = Arc::new(SegmentedQueue::new());
+
+            // one more value than fits in a block, so a producer installs the next one
+            let producers: Vec<_> = [vec![0, 1], vec![2]]
+                .into_iter()
+                .map(|ids| {
+                    let queue = queue.clone();
+                    thread::spawn(move || ids.into_iter().for_each(|id| queue.push(id)))
+                })
+                .collect();
+
+            let mut popped = Vec::new();
+            while popped.len() < 3 {
+                match queue.pop() {
+                    Some(id) => popped.push(id),
+                    None => thread::yield_now(),
+                }
+            }
+
+            for producer in producers {
+                producer.join().unwrap();
+            }
+
+            // values of one producer come out in the order they were pushed
+            let first: Vec<_> = popped.iter().filter(|&&id| id < 2).collect();
+            assert_eq!(first, [&0, &1]);
+        });
+    }
+}
@@ -0,0 +1,221 @@
+//! Structured concurrency: tasks that do not outlive the task that spawned them.
+//!
+//! Tasks spawned via [`spawn`](super::spawn) run on their own, whatever happens to the task
+//! that spawned them. Tasks spawned into a [`scope`] are its children instead:
+//!
+//! - awaiting the scope waits for every child to finish, successfully or not.
+//! - dropping the scope before then, e.g. as the losing branch of a `select!` or along with a
+//!   cancelled parent, cancels the children that are still pending.
+//!
+//! The executor keeps track of which scope each child belongs to, so a child finishing wakes the
+//! task awaiting its scope once no sibling is left. While it waits, the task awaiting the scope
+//! is recorded as blocked on one of the children, so deadlock reports see through scopes.
+use std::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use super::{executor, next_resource_id, wait_on, Resource};
+
+/// Returns a future that runs `spawn` with a [`Scope`] to spawn children into, then waits for
+/// all of them to finish. Dropping the future cancels the children still pending.
+///
+/// `spawn` runs on the first poll, so the children end up on the executor of the task awaiting
+/// the scope.
+///
+/// ```ignore
+/// runtime::scope(|s| {
+///     for path in paths {
+///         s.spawn(fetch(path));
+///     }
+/// })
+/// .await;
+/// ```
+pub fn scope<F>(spawn: F) -> Scoped<F>
+where
+    F: FnOnce(&Scope),
+{
+    Scoped {
+        spawn: Some(spawn),
+        id: None,
+    }
+}
+
+/// Handle to spawn the children of a scope with, see [`scope`].
+pub struct Scope {
+    id: usize,
+    // the scope belongs to the executor of this thread
+    _thread_bound: PhantomData<Rc<()>>,
+}
+
+impl Scope {
+    /// Same as [`spawn`](super::spawn), but the task is a child of this scope.
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        executor::spawn_scoped(self.id, Box::pin(future));
+    }
+}
+
+/// Future returned by [`scope`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Scoped<F> {
+    /// Taken on the first poll
+    spawn: Option<F>,
+    /// Id of the scope with the executor, and in deadlock reports. None until the first poll
+    id: Option<usize>,
+}
+
+// `spawn` is never pinned, only called
+impl<F> Unpin for Scoped<F> {}
+
+impl<F> Future for Scoped<F>
+where
+    F: FnOnce(&Scope),
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let id = match self.id {
+            Some(id) => id,
+            None => {
+                let id = next_resource_id();
+                executor::open_scope(id);
+                self.id = Some(id);
+
+                if let Some(spawn) = self.spawn.take() {
+                    spawn(&Scope {
+                        id,
+                        _thread_bound: PhantomData,
+                    });
+                }
+                id
+            }
+        };
+
+        match executor::poll_scope(id, cx.waker()) {
+            None => Poll::Ready(()),
+            Some(child) => {
+                wait_on(Resource {
+                    kind: "scope",
+                    id,
+                    holder: Some(child),
+                    external: false,
+                });
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<F> Drop for Scoped<F> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            executor::close_scope(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::{pending, poll_fn},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use super::*;
+    use crate::runtime::{yield_now, Executor};
+
+    /// Sets its flag when dropped, i.e. when the task holding it completes or is cancelled.
+    struct SetOnDrop(Arc<AtomicBool>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn scope_waits_for_every_child() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let seen = done.clone();
+
+        Executor::new().block_on(async move {
+            scope(|s| {
+                for yields in 0..3 {
+                    let done = seen.clone();
+                    s.spawn(async move {
+                        for _ in 0..yields {
+                            yield_now().await;
+                        }
+                        done.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            })
+            .await;
+
+            assert_eq!(seen.load(Ordering::SeqCst), 3);
+        });
+
+        assert_eq!(done.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn scope_without_children_is_ready_straight_away() {
+        let mut executor = Executor::new();
+        executor.block_on(scope(|_| {}));
+        assert_eq!(executor.metrics().pending, 0);
+    }
+
+    #[test]
+    fn dropping_the_scope_cancels_pending_children() {
+        let (outer, inner) = (
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+        );
+        let (outer_dropped, inner_dropped) = (outer.clone(), inner.clone());
+        let mut executor = Executor::new();
+        let cancelled = executor.metrics().cancelled;
+
+        executor.block_on(async move {
+            let mut scoped = Box::pin(scope(move |s| {
+                s.spawn(async move {
+                    let _guard = SetOnDrop(outer);
+                    // a nested scope, whose children go along with it
+                    scope(move |s| {
+                        s.spawn(async move {
+                            let _guard = SetOnDrop(inner);
+                            pending::<()>().await
+                        })
+                    })
+                    .await
+                });
+            }));
+
+            poll_fn(|cx| {
+                assert!(scoped.as_mut().poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+            // let the children start
+            for _ in 0..3 {
+                yield_now().await;
+            }
+            assert!(!outer_dropped.load(Ordering::SeqCst));
+
+            drop(scoped);
+            assert!(outer_dropped.load(Ordering::SeqCst));
+            assert!(inner_dropped.load(Ordering::SeqCst));
+        });
+
+        // block_on returned, so nothing was left pending either
+        assert_eq!(executor.metrics().cancelled - cancelled, 2);
+    }
+}
@@ -0,0 +1,94 @@
+//! Periodic validation of invariants that span the executor, reactor and primitives.
+//!
+//! Enabled by `runtime::init` in debug builds. While enabled, the executor never parks for
+//! longer than the check interval, and runs [`check`] whenever the interval has passed.
+//!
+//! The invariants checked are the ones whose violation shows up as a "lost task", i.e. a task
+//! that stays pending forever because nothing is left that will wake it:
+//!
+//! 1. every waker the reactor holds on behalf of this executor belongs to a live task.
+//! 2. every pending task is either in the ready queue, waiting on a source registered with the
+//!    reactor, waiting on an io_uring operation, waiting on a (simulated) timer, blocked on a
+//!    synchronisation primitive, or is the task currently being polled.
+use std::collections::HashSet;
+
+/// State of the runtime as seen from one executor thread.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    /// Ids of all pending tasks
+    pub tasks: HashSet<usize>,
+    /// Task ids in the ready queue
+    pub ready: Vec<usize>,
+    /// Reactor source ids, along with the id of a task whose waker is registered for it
+    pub io: Vec<(usize, usize)>,
+    /// Ids of tasks waiting on an io_uring operation to complete
+    pub completions: HashSet<usize>,
+    /// Ids of tasks waiting on a timer
+    pub timers: HashSet<usize>,
+    /// Ids of tasks blocked on a synchronisation primitive
+    pub primitives: HashSet<usize>,
+    /// Task currently being polled
+    pub current: Option<usize>,
+}
+
+/// Returns a description of every violated invariant, empty if all hold.
+pub fn check(snapshot: &Snapshot) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    let mut io: Vec<_> = snapshot.io.iter().collect();
+    io.sort();
+
+    for (source, task) in &io {
+        if !snapshot.tasks.contains(task) {
+            violations.push(format!(
+                "reactor holds a waker for source {source} on behalf of task {task}, \
+                 which is no longer alive (leaked registration)"
+            ));
+        }
+    }
+
+    let waiting_on_io: HashSet<&usize> = snapshot.io.iter().map(|(_, task)| task).collect();
+
+    let mut tasks: Vec<_> = snapshot.tasks.iter().collect();
+    tasks.sort();
+
+    for task in tasks {
+        let accounted_for = snapshot.ready.contains(task)
+            || waiting_on_io.contains(task)
+            || snapshot.completions.contains(task)
+            || snapshot.timers.contains(task)
+            || snapshot.primitives.contains(task)
+            || snapshot.current == Some(*task);
+
+        if !accounted_for {
+            violations.push(format!(
+                "task {task} is pending, but is not in the ready queue and not waiting on IO, \
+                 a timer or a primitive (lost task)"
+            ));
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_leaked_registrations_and_lost_tasks() {
+        let snapshot = Snapshot {
+            tasks: HashSet::from([1, 2, 3, 4]),
+            ready: vec![1],
+            io: vec![(10, 2), (11, 9)],
+            timers: HashSet::from([3]),
+            ..Default::default()
+        };
+
+        let violations = check(&snapshot);
+
+        assert_eq!(violations.len(), 2);
+        assert!(violations[0].contains("source 11 on behalf of task 9"));
+        assert!(violations[1].contains("task 4 is pending"));
+    }
+}
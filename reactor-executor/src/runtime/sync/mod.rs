@@ -0,0 +1,12 @@
+//! Synchronisation primitives for tasks running on the executor.
+//!
+//! Primitives that can leave a task waiting record what it waits on with the runtime, see
+//! [`runtime::wait_on`](crate::runtime::wait_on), so the executor can report deadlocks.
+
+pub mod mpsc;
+mod mutex;
+mod notify;
+pub mod oneshot;
+
+pub use mutex::{Lock, Mutex, MutexGuard};
+pub use notify::{Notified, Notify};
@@ -0,0 +1,229 @@
+//! Multi-producer, single-consumer channel for sending values between tasks.
+//!
+//! The channel is unbounded, so sending never waits. Receiving parks the task until a value
+//! arrives or every sender has been dropped. Senders can be moved to tasks running on other
+//! executor threads, the receiving task is woken up through its waker either way.
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::runtime::{self, Resource};
+
+/// Create a new channel, returning the sending and receiving halves.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let chan = Arc::new(Mutex::new(Chan {
+        queue: VecDeque::new(),
+        waker: None,
+        senders: 1,
+        receiver_alive: true,
+    }));
+
+    let sender = Sender { chan: chan.clone() };
+    let receiver = Receiver {
+        chan,
+        id: runtime::next_resource_id(),
+    };
+
+    (sender, receiver)
+}
+
+/// Error returned by [`Sender::send`] if the receiver was dropped. Holds the unsent value.
+#[derive(PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+struct Chan<T> {
+    queue: VecDeque<T>,
+    /// Waker of the task awaiting the receiver
+    waker: Option<Waker>,
+    /// Number of live senders, the channel is closed once this reaches 0
+    senders: usize,
+    receiver_alive: bool,
+}
+
+pub struct Sender<T> {
+    chan: Arc<Mutex<Chan<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Queue a value and wake up the receiving task.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let waker = {
+            let mut chan = self.chan.lock().unwrap();
+
+            if !chan.receiver_alive {
+                return Err(SendError(value));
+            }
+
+            chan.queue.push_back(value);
+            chan.waker.take()
+        };
+
+        // wake outside the lock, the receiver may be polled on another thread
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.lock().unwrap().senders += 1;
+
+        Self {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut chan = self.chan.lock().unwrap();
+            chan.senders -= 1;
+
+            match chan.senders {
+                0 => chan.waker.take(),
+                _ => None,
+            }
+        };
+
+        // last sender gone, let the receiver know no more values are coming
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    chan: Arc<Mutex<Chan<T>>>,
+    /// Identifies this channel in deadlock reports
+    id: usize,
+}
+
+impl<T> Receiver<T> {
+    /// Returns a future that resolves to the next value, or `None` once the channel is empty
+    /// and all senders have been dropped.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+
+    /// Take the next value if there is one, without waiting.
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.chan.lock().unwrap().queue.pop_front()
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut chan = self.chan.lock().unwrap();
+        chan.receiver_alive = false;
+        // values can no longer be received, drop them now rather than with the last sender
+        chan.queue.clear();
+    }
+}
+
+/// Future returned by [`Receiver::recv`].
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut chan = self.receiver.chan.lock().unwrap();
+
+        if let Some(value) = chan.queue.pop_front() {
+            return Poll::Ready(Some(value));
+        }
+
+        if chan.senders == 0 {
+            return Poll::Ready(None);
+        }
+
+        // IMPORTANT: always store the most recent waker
+        chan.waker = Some(cx.waker().clone());
+
+        runtime::wait_on(Resource {
+            kind: "mpsc",
+            id: self.receiver.id,
+            holder: None,
+            external: false,
+        });
+
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, thread};
+
+    use super::*;
+    use crate::runtime::{spawn, Executor};
+
+    #[test]
+    fn values_arrive_in_order_until_senders_dropped() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let out = received.clone();
+
+        Executor::new().block_on(async move {
+            let (tx, mut rx) = channel();
+
+            for i in 0..3 {
+                let tx = tx.clone();
+                spawn(async move {
+                    tx.send(i).unwrap();
+                });
+            }
+            drop(tx);
+
+            while let Some(value) = rx.recv().await {
+                out.borrow_mut().push(value);
+            }
+        });
+
+        let mut received = received.take();
+        received.sort();
+        assert_eq!(received, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn send_from_another_executor_thread() {
+        let (tx, mut rx) = channel();
+
+        let sender = thread::spawn(move || {
+            Executor::new().block_on(async move {
+                thread::sleep(std::time::Duration::from_millis(20));
+                tx.send("from another thread").unwrap();
+            });
+        });
+
+        Executor::new().block_on(async move {
+            assert_eq!(rx.recv().await, Some("from another thread"));
+            assert_eq!(rx.recv().await, None);
+        });
+
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn send_fails_once_receiver_dropped() {
+        let (tx, rx) = channel();
+        drop(rx);
+        assert_eq!(tx.send(1), Err(SendError(1)));
+    }
+}
@@ -0,0 +1,285 @@
+//! Async mutex that parks waiting tasks instead of blocking the executor thread.
+//!
+//! Waiting tasks are queued in FIFO order. On unlock the lock is handed directly to the task
+//! at the front of the queue, so a task that calls `lock()` later can not barge ahead of tasks
+//! that are already waiting.
+use std::{
+    cell::UnsafeCell,
+    collections::VecDeque,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::Mutex as StdMutex,
+    task::{Context, Poll, Waker},
+};
+
+use crate::runtime::{self, Resource};
+
+pub struct Mutex<T> {
+    state: StdMutex<State>,
+    value: UnsafeCell<T>,
+    /// Identifies this mutex in deadlock reports
+    id: usize,
+}
+
+// SAFETY: access to `value` is only granted through a `MutexGuard`, and `state` makes sure
+// there is at most one guard alive at a time.
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+struct State {
+    locked: bool,
+    /// Task holding the lock, used to build the wait-for graph
+    holder: Option<usize>,
+    waiters: VecDeque<Waiter>,
+    /// Ticket of the waiter the lock was handed to on unlock, which has not yet been polled
+    granted: Option<u64>,
+    next_ticket: u64,
+}
+
+struct Waiter {
+    ticket: u64,
+    waker: Waker,
+}
+
+impl<T> Mutex<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            state: StdMutex::new(State {
+                locked: false,
+                holder: None,
+                waiters: VecDeque::new(),
+                granted: None,
+                next_ticket: 0,
+            }),
+            value: UnsafeCell::new(value),
+            id: runtime::next_resource_id(),
+        }
+    }
+
+    /// Returns a future that resolves to a guard once the lock is acquired.
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock {
+            mutex: self,
+            ticket: None,
+        }
+    }
+
+    /// Acquire the lock if it is free and nobody is waiting for it.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.locked {
+            return None;
+        }
+
+        state.locked = true;
+        state.holder = runtime::current_task();
+        Some(MutexGuard { mutex: self })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// Hand the lock to the next waiter, or release it if there is none.
+    fn unlock(&self) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.holder = None;
+
+            match state.waiters.pop_front() {
+                Some(waiter) => {
+                    // lock stays locked, ownership passes to the waiter
+                    state.granted = Some(waiter.ticket);
+                    Some(waiter.waker)
+                }
+                None => {
+                    state.locked = false;
+                    None
+                }
+            }
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Future returned by [`Mutex::lock`].
+pub struct Lock<'a, T> {
+    mutex: &'a Mutex<T>,
+    /// Our place in the waiter queue, once we had to wait
+    ticket: Option<u64>,
+}
+
+impl<'a, T> Future for Lock<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mutex = self.mutex;
+        let mut state = mutex.state.lock().unwrap();
+
+        match self.ticket {
+            // lock was handed to us on unlock
+            Some(ticket) if state.granted == Some(ticket) => {
+                state.granted = None;
+                state.holder = runtime::current_task();
+                self.ticket = None;
+                return Poll::Ready(MutexGuard { mutex });
+            }
+            // still waiting, make sure we are woken with the most recent waker
+            Some(ticket) => {
+                if let Some(waiter) = state.waiters.iter_mut().find(|w| w.ticket == ticket) {
+                    waiter.waker = cx.waker().clone();
+                }
+            }
+            // first poll, take the lock if it is free and nobody is queued
+            None if !state.locked => {
+                state.locked = true;
+                state.holder = runtime::current_task();
+                return Poll::Ready(MutexGuard { mutex });
+            }
+            None => {
+                let ticket = state.next_ticket;
+                state.next_ticket += 1;
+                state.waiters.push_back(Waiter {
+                    ticket,
+                    waker: cx.waker().clone(),
+                });
+                self.ticket = Some(ticket);
+            }
+        }
+
+        runtime::wait_on(Resource {
+            kind: "mutex",
+            id: mutex.id,
+            holder: state.holder,
+            external: false,
+        });
+
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Lock<'_, T> {
+    /// A task may stop waiting for the lock, e.g. if the future is cancelled. Leave the queue,
+    /// and if the lock had already been handed to us, pass it on.
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket else {
+            return;
+        };
+
+        let granted = {
+            let mut state = self.mutex.state.lock().unwrap();
+            state.waiters.retain(|w| w.ticket != ticket);
+
+            let granted = state.granted == Some(ticket);
+            if granted {
+                state.granted = None;
+            }
+            granted
+        };
+
+        if granted {
+            self.mutex.unlock();
+        }
+    }
+}
+
+/// Gives access to the value protected by the mutex. The lock is released on drop.
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::runtime::{
+        spawn, spawn_local, spawn_local_named, spawn_named,
+        sync::{mpsc, oneshot},
+        Executor,
+    };
+
+    #[test]
+    fn waiters_acquire_lock_in_the_order_they_queued() {
+        let queued = Rc::new(RefCell::new(Vec::new()));
+        let acquired = Rc::new(RefCell::new(Vec::new()));
+        let (q, a) = (queued.clone(), acquired.clone());
+
+        Executor::new().block_on(async move {
+            let mutex = Rc::new(Mutex::new(0));
+            let guard = mutex.lock().await;
+            let (tx, mut rx) = mpsc::channel();
+
+            for i in 0..5 {
+                let (mutex, tx, acquired) = (mutex.clone(), tx.clone(), a.clone());
+                spawn_local(async move {
+                    // the lock is held, so this task is queued within the same poll
+                    tx.send(i).unwrap();
+                    *mutex.lock().await += 1;
+                    acquired.borrow_mut().push(i);
+                });
+            }
+
+            for _ in 0..5 {
+                let i = rx.recv().await.unwrap();
+                q.borrow_mut().push(i);
+            }
+
+            drop(guard);
+            // the lock went straight to the first waiter, a newcomer can not barge in
+            assert!(mutex.try_lock().is_none());
+        });
+
+        assert_eq!(acquired.borrow().len(), 5);
+        assert_eq!(*queued.borrow(), *acquired.borrow());
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle: 'a' (task 1) -> 'b' (task 2) -> 'a' (task 1)")]
+    fn lock_order_inversion_is_reported_as_cycle() {
+        Executor::new().block_on(async {
+            let first = Rc::new(Mutex::new(()));
+            let second = Rc::new(Mutex::new(()));
+            let (tx, rx) = oneshot::channel();
+
+            let (f, s) = (first.clone(), second.clone());
+            spawn_local_named("a", async move {
+                let _first = f.lock().await;
+                tx.send(());
+                let _second = s.lock().await;
+            });
+
+            spawn_local_named("b", async move {
+                let _second = second.lock().await;
+                // wait until 'a' holds the first lock
+                rx.await.unwrap();
+                let _first = first.lock().await;
+            });
+        });
+    }
+}
@@ -0,0 +1,232 @@
+//! Wake up waiting tasks without passing any data along.
+//!
+//! [`Notify::notify_one`] wakes the task that has waited longest. If no task is waiting, a
+//! single permit is stored instead, so the next call to [`Notify::notified`] completes
+//! straight away and the notification is not lost. [`Notify::notify_waiters`] wakes every
+//! task that is waiting right now, and stores no permit.
+use std::{
+    collections::{HashSet, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+};
+
+use crate::runtime::{self, Resource};
+
+pub struct Notify {
+    state: Mutex<State>,
+    /// Identifies this primitive in deadlock reports
+    id: usize,
+}
+
+#[derive(Default)]
+struct State {
+    permit: bool,
+    waiters: VecDeque<(u64, Waker)>,
+    /// Tickets of waiters that were notified, but have not been polled since
+    notified: HashSet<u64>,
+    next_ticket: u64,
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            id: runtime::next_resource_id(),
+        }
+    }
+
+    /// Returns a future that completes once this task is notified.
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            ticket: None,
+        }
+    }
+
+    /// Wake the longest waiting task, or store a permit if none are waiting.
+    pub fn notify_one(&self) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+
+            match state.waiters.pop_front() {
+                Some((ticket, waker)) => {
+                    state.notified.insert(ticket);
+                    Some(waker)
+                }
+                None => {
+                    state.permit = true;
+                    None
+                }
+            }
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Wake all tasks that are currently waiting.
+    pub fn notify_waiters(&self) {
+        let wakers: Vec<Waker> = {
+            let mut state = self.state.lock().unwrap();
+            let waiters = std::mem::take(&mut state.waiters);
+
+            waiters
+                .into_iter()
+                .map(|(ticket, waker)| {
+                    state.notified.insert(ticket);
+                    waker
+                })
+                .collect()
+        };
+
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by [`Notify::notified`].
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    /// Our place in the waiter queue, once we had to wait
+    ticket: Option<u64>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let notify = self.notify;
+        let mut state = notify.state.lock().unwrap();
+
+        match self.ticket {
+            Some(ticket) if state.notified.remove(&ticket) => {
+                self.ticket = None;
+                return Poll::Ready(());
+            }
+            Some(ticket) => {
+                if let Some((_, waker)) = state.waiters.iter_mut().find(|(t, _)| *t == ticket) {
+                    *waker = cx.waker().clone();
+                }
+            }
+            None if state.permit => {
+                state.permit = false;
+                return Poll::Ready(());
+            }
+            None => {
+                let ticket = state.next_ticket;
+                state.next_ticket += 1;
+                state.waiters.push_back((ticket, cx.waker().clone()));
+                self.ticket = Some(ticket);
+            }
+        }
+
+        runtime::wait_on(Resource {
+            kind: "notify",
+            id: notify.id,
+            holder: None,
+            external: false,
+        });
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    /// Leave the queue if we stop waiting. If we were picked by `notify_one` but never got to
+    /// see it, pass the notification on so it is not lost.
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket else {
+            return;
+        };
+
+        let pass_on = {
+            let mut state = self.notify.state.lock().unwrap();
+            state.waiters.retain(|(t, _)| *t != ticket);
+            state.notified.remove(&ticket)
+        };
+
+        if pass_on {
+            self.notify.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::runtime::{spawn_local, sync::mpsc, Executor};
+
+    /// Spawn `n` tasks that wait on `notify`. Returns the order in which they queued up, and a
+    /// receiver that yields the id of each task once it is woken.
+    async fn queue_waiters(notify: &Rc<Notify>, n: usize) -> (Vec<usize>, mpsc::Receiver<usize>) {
+        let (queued_tx, mut queued_rx) = mpsc::channel();
+        let (woken_tx, woken_rx) = mpsc::channel();
+
+        for i in 0..n {
+            let (notify, queued, woken) = (notify.clone(), queued_tx.clone(), woken_tx.clone());
+            spawn_local(async move {
+                queued.send(i).unwrap();
+                notify.notified().await;
+                woken.send(i).unwrap();
+            });
+        }
+
+        let mut queued = Vec::new();
+        for _ in 0..n {
+            queued.push(queued_rx.recv().await.unwrap());
+        }
+        (queued, woken_rx)
+    }
+
+    #[test]
+    fn notify_one_wakes_waiters_in_fifo_order() {
+        Executor::new().block_on(async {
+            let notify = Rc::new(Notify::new());
+            let (queued, mut woken) = queue_waiters(&notify, 3).await;
+
+            for i in queued {
+                notify.notify_one();
+                assert_eq!(woken.recv().await, Some(i));
+            }
+        });
+    }
+
+    #[test]
+    fn notify_one_without_waiters_stores_a_single_permit() {
+        Executor::new().block_on(async {
+            let notify = Notify::new();
+            notify.notify_one();
+            notify.notify_one();
+
+            notify.notified().await;
+            assert!(!notify.state.lock().unwrap().permit);
+        });
+    }
+
+    #[test]
+    fn notify_waiters_wakes_everyone_without_storing_permit() {
+        Executor::new().block_on(async {
+            let notify = Rc::new(Notify::new());
+            let (_, mut woken) = queue_waiters(&notify, 3).await;
+
+            notify.notify_waiters();
+            assert!(!notify.state.lock().unwrap().permit);
+
+            for _ in 0..3 {
+                assert!(woken.recv().await.is_some());
+            }
+        });
+    }
+}
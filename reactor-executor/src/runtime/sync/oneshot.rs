@@ -0,0 +1,208 @@
+//! Channel for sending a single value between tasks.
+//!
+//! The [`Receiver`] can be awaited as a std future, and also be polled through the Waker-based
+//! [`future::Future`](crate::future::Future) trait.
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    future::{self, PollState},
+    runtime::{self, MyWaker, Resource},
+};
+
+/// Create a new oneshot channel, returning the sending and receiving halves.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Mutex::new(Inner {
+        value: None,
+        waker: None,
+        sender_dropped: false,
+    }));
+
+    let sender = Sender {
+        inner: inner.clone(),
+    };
+    let receiver = Receiver {
+        inner,
+        id: runtime::next_resource_id(),
+    };
+
+    (sender, receiver)
+}
+
+/// Error returned by [`Receiver`] if the sender was dropped without sending a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+struct Inner<T> {
+    value: Option<T>,
+    /// Waker of the task awaiting the receiver
+    waker: Option<Waker>,
+    sender_dropped: bool,
+}
+
+pub struct Sender<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Send a value, waking up the receiving task.
+    pub fn send(self, value: T) {
+        let waker = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.value = Some(value);
+            inner.waker.take()
+        };
+
+        // wake outside the lock, the receiver may be polled on another thread
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.sender_dropped = true;
+            inner.waker.take()
+        };
+
+        // let the receiver know no value is coming
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Receiving half of the channel. Await it to get the value.
+pub struct Receiver<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+    /// Identifies this channel in deadlock reports
+    id: usize,
+}
+
+impl<T> Receiver<T> {
+    /// Shared by both future traits: take the value, or leave `waker` to be woken once the
+    /// sender sends or is dropped.
+    fn poll_recv(&self, waker: &Waker) -> Poll<Result<T, RecvError>> {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(value) = inner.value.take() {
+            return Poll::Ready(Ok(value));
+        }
+
+        if inner.sender_dropped {
+            return Poll::Ready(Err(RecvError));
+        }
+
+        inner.waker = Some(waker.clone());
+
+        // We can not know which task holds the sender, so no holder is recorded
+        runtime::wait_on(Resource {
+            kind: "oneshot",
+            id: self.id,
+            holder: None,
+            external: false,
+        });
+
+        Poll::Pending
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.poll_recv(cx.waker())
+    }
+}
+
+impl<T> future::Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+    type Waker = MyWaker;
+
+    fn poll(self: Pin<&mut Self>, waker: &MyWaker) -> PollState<Self::Output> {
+        let waker: Waker = Arc::new(waker.clone()).into();
+
+        match self.poll_recv(&waker) {
+            Poll::Ready(result) => PollState::Ready(result),
+            Poll::Pending => PollState::NotReady,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{spawn_named, Executor};
+
+    #[test]
+    #[should_panic(expected = "deadlock detected: all 3 pending tasks are blocked")]
+    fn tasks_awaiting_each_others_channels_deadlock() {
+        Executor::new().block_on(async {
+            let (tx_a, rx_a) = channel::<()>();
+            let (tx_b, rx_b) = channel::<()>();
+            let (_tx_done, rx_done) = channel::<()>();
+
+            spawn_named("a", async move {
+                rx_a.await.unwrap();
+                tx_b.send(());
+            });
+
+            spawn_named("b", async move {
+                rx_b.await.unwrap();
+                tx_a.send(());
+            });
+
+            // keep the top-level task around, waiting as well
+            let _ = rx_done.await;
+        });
+    }
+
+    #[test]
+    fn dropped_sender_yields_error() {
+        Executor::new().block_on(async {
+            let (tx, rx) = channel::<u32>();
+
+            crate::runtime::spawn(async move {
+                drop(tx);
+            });
+
+            assert_eq!(rx.await, Err(RecvError));
+        });
+    }
+
+    #[test]
+    fn receiver_works_with_waker_based_trait() {
+        let queue = Arc::default();
+        let waker = MyWaker::new(7, &queue);
+        let (tx, mut rx) = channel();
+
+        let poll = future::Future::poll(Pin::new(&mut rx), &waker);
+        assert!(matches!(poll, PollState::NotReady));
+
+        tx.send("done");
+        assert_eq!(queue.snapshot(), vec![7]);
+
+        let poll = future::Future::poll(Pin::new(&mut rx), &waker);
+        assert!(matches!(poll, PollState::Ready(Ok("done"))));
+    }
+
+    #[test]
+    fn value_is_received() {
+        Executor::new().block_on(async {
+            let (tx, rx) = channel();
+
+            crate::runtime::spawn(async move {
+                tx.send(42);
+            });
+
+            assert_eq!(rx.await, Ok(42));
+        });
+    }
+}
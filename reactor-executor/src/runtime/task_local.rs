@@ -0,0 +1,193 @@
+//! Values scoped to a task, the async counterpart of `thread_local!`.
+//!
+//! A task hops between polls, and with a pool of executors may hop between threads, so a
+//! thread local is no place for data that belongs to a task, e.g. the id of the request it
+//! is serving. Task locals are declared with [`task_local!`](crate::task_local), and their
+//! values kept by the executor, per task. Values are dropped once their task completes or is
+//! cancelled.
+//!
+//! ```ignore
+//! runtime::task_local! {
+//!     static REQUEST_ID: u64;
+//! }
+//!
+//! spawn(async {
+//!     REQUEST_ID.set(7);
+//!     handle().await; // REQUEST_ID.get() == Some(7) in here, and in whatever it awaits
+//! });
+//! ```
+//!
+//! NOTE: tasks spawned by a task start out without values, nothing is inherited.
+use std::{any::Any, marker::PhantomData, rc::Rc};
+
+use super::executor;
+
+/// Declare one or more [`TaskLocal`]s.
+///
+/// ```ignore
+/// runtime::task_local! {
+///     static REQUEST_ID: u64;
+///     pub static USER: String;
+/// }
+/// ```
+#[macro_export]
+macro_rules! task_local {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty; $($rest:tt)*) => {
+        $(#[$attr])*
+        $vis static $name: $crate::runtime::TaskLocal<$t> =
+            $crate::runtime::TaskLocal::new(stringify!($name));
+        $crate::task_local!($($rest)*);
+    };
+    () => {};
+}
+
+/// Key for a value per task, declared with [`task_local!`](crate::task_local).
+///
+/// The key is the address of the static, so every declaration gets values of its own.
+pub struct TaskLocal<T: 'static> {
+    /// Used in panic messages, and keeps the static from being zero sized, so no two
+    /// statics share an address
+    name: &'static str,
+    /// Values live with the executor, never in the static, which is `Sync` whatever `T` is
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> TaskLocal<T> {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            _value: PhantomData,
+        }
+    }
+
+    fn key(&'static self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Set the value for the task currently being polled, replacing any previous one.
+    ///
+    /// Panics when called outside of a task.
+    pub fn set(&'static self, value: T) {
+        let set = executor::set_task_local(self.key(), Rc::new(value));
+        assert!(set, "task local {} set outside of a task", self.name);
+    }
+
+    /// Run `f` with the value of the task currently being polled, None if it has no value or
+    /// this is called outside of a task.
+    ///
+    /// `f` may set task locals itself, including this one, the value it was handed stays
+    /// alive until it returns.
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        let value = executor::task_local(self.key())?;
+        let value = value
+            .downcast_ref::<T>()
+            .expect("task local key matches a value of another type");
+        Some(f(value))
+    }
+
+    /// Copy of the value of the task currently being polled, see [`TaskLocal::with`].
+    pub fn get(&'static self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.with(T::clone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+    use crate::runtime::{self, spawn, spawn_local, sync::oneshot, yield_now, ExitPolicy};
+
+    crate::task_local! {
+        static REQUEST_ID: u64;
+        static TRACE: Vec<&'static str>;
+    }
+
+    /// Counts drops of values, to check that task locals are cleaned up.
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    crate::task_local! {
+        static COUNTED: Counted;
+    }
+
+    #[test]
+    fn tasks_see_only_their_own_values() {
+        let mut executor = runtime::init_for_tests();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        executor.block_on({
+            let seen = seen.clone();
+            async move {
+                REQUEST_ID.set(1);
+
+                for id in [2, 3] {
+                    let seen = seen.clone();
+                    spawn_local(async move {
+                        assert_eq!(REQUEST_ID.get(), None, "values are not inherited");
+                        REQUEST_ID.set(id);
+                        // another task runs in between
+                        yield_now().await;
+                        seen.borrow_mut().push(REQUEST_ID.get().unwrap());
+                    });
+                }
+
+                yield_now().await;
+                seen.borrow_mut().push(REQUEST_ID.get().unwrap());
+                assert!(TRACE.with(Vec::len).is_none());
+            }
+        });
+
+        let mut seen = seen.take();
+        seen.sort();
+        assert_eq!(seen, [1, 2, 3]);
+        assert_eq!(REQUEST_ID.get(), None, "no value outside of a task");
+    }
+
+    #[test]
+    fn values_are_dropped_when_tasks_complete_or_are_cancelled() {
+        let mut executor = runtime::init_for_tests();
+        let drops = Rc::new(Cell::new(0));
+
+        let policy = ExitPolicy::CancelRemaining;
+        executor.block_on_with(policy, {
+            let drops = drops.clone();
+            async move {
+                let (done, completed) = oneshot::channel();
+                let completes = drops.clone();
+                spawn_local(async move {
+                    COUNTED.set(Counted(completes));
+                    done.send(());
+                });
+
+                let (set, waiting) = oneshot::channel();
+                let cancelled = drops.clone();
+                spawn_local(async move {
+                    COUNTED.set(Counted(cancelled));
+                    set.send(());
+                    std::future::pending::<()>().await;
+                });
+
+                // replacing a value drops the previous one straight away
+                COUNTED.set(Counted(drops.clone()));
+                COUNTED.set(Counted(drops.clone()));
+                assert_eq!(drops.get(), 1);
+
+                completed.await.unwrap();
+                waiting.await.unwrap();
+            }
+        });
+
+        // replaced, main's own, the completed task's and the cancelled task's
+        assert_eq!(drops.get(), 4);
+    }
+}
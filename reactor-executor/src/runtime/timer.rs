@@ -0,0 +1,280 @@
+//! Timers on the wall clock, e.g. for servers that delay their responses.
+//!
+//! Deadlines are kept by a single timer thread, started on first use of [`sleep`], which waits
+//! until the earliest one and wakes the tasks whose deadlines passed. The simulated network
+//! has timers of its own, on its virtual clock, see [`sim`](crate::sim). So do deterministic
+//! runs, see [`deterministic`](super::deterministic).
+use std::{
+    collections::{BTreeMap, HashSet},
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::{Condvar, Mutex, OnceLock},
+    task::{Context, Poll, Waker},
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+use super::{deterministic, executor};
+
+/// Started on first use of [`sleep`]
+static TIMER: OnceLock<Timer> = OnceLock::new();
+
+/// Deadline of a sleep, the id tells apart sleeps that share a deadline
+pub(super) type Key = (Instant, u64);
+
+struct Timer {
+    timers: Mutex<Timers>,
+    /// Signalled whenever a deadline earlier than all others is added
+    earlier: Condvar,
+}
+
+#[derive(Default)]
+struct Timers {
+    wakers: BTreeMap<Key, Entry>,
+    next_id: u64,
+}
+
+struct Entry {
+    waker: Waker,
+    /// Thread and id of the task that sleeps, for the runtime's self check
+    owner: Option<(ThreadId, usize)>,
+}
+
+impl Timer {
+    fn start() -> Self {
+        thread::Builder::new()
+            .name("timer".to_string())
+            .spawn(run)
+            .expect("Failed to spawn timer thread");
+
+        Self {
+            timers: Mutex::default(),
+            earlier: Condvar::new(),
+        }
+    }
+
+    /// Wake `waker` once `deadline` passed, replacing the waker of `key` if there is one.
+    fn schedule(&self, key: Option<Key>, deadline: Instant, waker: &Waker) -> Key {
+        let mut timers = self.timers.lock().unwrap();
+
+        if let Some(stored) = key.and_then(|key| timers.wakers.get_mut(&key)) {
+            // IMPORTANT: always store the most recent waker
+            if !stored.waker.will_wake(waker) {
+                stored.waker = waker.clone();
+            }
+            return key.unwrap();
+        }
+
+        let key = (deadline, timers.next_id);
+        timers.next_id += 1;
+        let earliest = timers.wakers.keys().next().is_none_or(|first| key < *first);
+        let owner = executor::current_task().map(|task| (thread::current().id(), task));
+        timers.wakers.insert(
+            key,
+            Entry {
+                waker: waker.clone(),
+                owner,
+            },
+        );
+
+        if earliest {
+            self.earlier.notify_one();
+        }
+        key
+    }
+
+    fn cancel(&self, key: Key) {
+        self.timers.lock().unwrap().wakers.remove(&key);
+    }
+}
+
+/// Main loop of the timer thread: wake the tasks whose deadlines passed, then wait for the
+/// next deadline or an earlier one to be added.
+fn run() {
+    let timer = TIMER.get_or_init(Timer::start);
+    let mut timers = timer.timers.lock().unwrap();
+
+    loop {
+        let now = Instant::now();
+        let pending = timers.wakers.split_off(&(now, u64::MAX));
+        let expired = mem::replace(&mut timers.wakers, pending);
+
+        if !expired.is_empty() {
+            // woken without holding the lock, so the tasks can schedule again straight away
+            drop(timers);
+            executor::wake_urgent(|| expired.into_values().for_each(|entry| entry.waker.wake()));
+            timers = timer.timers.lock().unwrap();
+            continue;
+        }
+
+        timers = match timers.wakers.keys().next() {
+            Some(&(deadline, _)) => {
+                let timeout = deadline.saturating_duration_since(now);
+                timer.earlier.wait_timeout(timers, timeout).unwrap().0
+            }
+            None => timer.earlier.wait(timers).unwrap(),
+        };
+    }
+}
+
+/// Ids of tasks on this thread waiting on a sleep.
+pub(crate) fn waiting_tasks() -> HashSet<usize> {
+    let Some(timer) = TIMER.get() else {
+        return HashSet::new();
+    };
+    let thread = thread::current().id();
+
+    timer
+        .timers
+        .lock()
+        .unwrap()
+        .wakers
+        .values()
+        .filter_map(|entry| entry.owner)
+        .filter(|(owner, _)| *owner == thread)
+        .map(|(_, task)| task)
+        .collect()
+}
+
+/// Returns a future that resolves once `duration` passed.
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(deterministic::now() + duration)
+}
+
+/// Returns a future that resolves once `deadline` passed.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        key: None,
+        on_virtual_clock: false,
+    }
+}
+
+/// Future returned by [`sleep`] and [`sleep_until`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep {
+    deadline: Instant,
+    /// Set once scheduled with the timer thread
+    key: Option<Key>,
+    /// Scheduled with the virtual clock of a deterministic run rather than the timer thread
+    on_virtual_clock: bool,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(now) = deterministic::virtual_now() {
+            if now >= self.deadline {
+                self.cancel();
+                return Poll::Ready(());
+            }
+
+            self.key = Some(deterministic::schedule(self.key, self.deadline, cx.waker()));
+            self.on_virtual_clock = true;
+            return Poll::Pending;
+        }
+
+        let timer = TIMER.get_or_init(Timer::start);
+
+        if Instant::now() >= self.deadline {
+            self.cancel();
+            return Poll::Ready(());
+        }
+
+        let key = timer.schedule(self.key, self.deadline, cx.waker());
+        self.key = Some(key);
+        Poll::Pending
+    }
+}
+
+impl Sleep {
+    /// Remove our waker from whichever clock it was left with.
+    fn cancel(&mut self) {
+        match self.key.take() {
+            Some(key) if self.on_virtual_clock => deterministic::cancel(key),
+            Some(key) => {
+                if let Some(timer) = TIMER.get() {
+                    timer.cancel(key);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        // e.g. cancelled by a timeout, so its waker is not kept around until the deadline
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::runtime::{spawn_local, Executor};
+
+    #[test]
+    fn sleeps_complete_in_order_of_their_deadlines() {
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let start = Instant::now();
+
+        Executor::new().block_on({
+            let order = order.clone();
+            async move {
+                for ms in [60, 20, 40] {
+                    let order = order.clone();
+                    spawn_local(async move {
+                        sleep(Duration::from_millis(ms)).await;
+                        order.borrow_mut().push(ms);
+                    });
+                }
+            }
+        });
+
+        assert_eq!(*order.borrow(), [20, 40, 60]);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn sleeping_task_is_accounted_for() {
+        Executor::new().block_on(async {
+            let mut sleep = Box::pin(sleep(Duration::from_secs(60)));
+            let waker = Waker::noop();
+            let _ = sleep.as_mut().poll(&mut Context::from_waker(waker));
+
+            let task = executor::current_task().unwrap();
+            assert!(waiting_tasks().contains(&task));
+
+            drop(sleep);
+            assert!(!waiting_tasks().contains(&task));
+        });
+    }
+
+    #[test]
+    fn dropped_sleep_is_cancelled() {
+        let mut sleep = Box::pin(sleep(Duration::from_secs(60)));
+        let waker = Waker::noop();
+        assert!(sleep
+            .as_mut()
+            .poll(&mut Context::from_waker(waker))
+            .is_pending());
+
+        let key = sleep.key.unwrap();
+        let timer = TIMER.get().unwrap();
+        assert!(timer.timers.lock().unwrap().wakers.contains_key(&key));
+
+        drop(sleep);
+        assert!(!timer.timers.lock().unwrap().wakers.contains_key(&key));
+    }
+}
@@ -0,0 +1,95 @@
+//! Executor for homogeneous workloads where every task is the same concrete future type.
+//!
+//! [`Executor`](super::Executor) stores each task as a `Pin<Box<dyn Future>>`, which costs a
+//! heap allocation per spawn and a virtual call per poll. When all tasks share a type, as in
+//! the examples making several identical requests, we can instead store them inline in a
+//! `Vec<Option<F>>` and poll them through static dispatch.
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+use super::{parker::Parker, ready_queue::ReadyQueue, MyWaker};
+
+pub struct TypedExecutor<F> {
+    /// Tasks are stored inline, indexed by task id. `None` once a task has completed.
+    tasks: Vec<Option<F>>,
+    ready_queue: Arc<ReadyQueue>,
+}
+
+impl<F> TypedExecutor<F>
+where
+    F: Future<Output = ()>,
+{
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            tasks: Vec::with_capacity(capacity),
+            ready_queue: Arc::default(),
+        }
+    }
+
+    /// Add a task to be polled on the next call to [`TypedExecutor::run`].
+    ///
+    /// Unlike `runtime::spawn`, this needs `&mut self`, so tasks can not spawn other tasks.
+    pub fn spawn(&mut self, future: F) {
+        let id = self.tasks.len();
+        self.tasks.push(Some(future));
+        self.ready_queue.push(id);
+    }
+
+    /// Number of tasks that have not yet completed.
+    pub fn task_count(&self) -> usize {
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+
+    /// Run until all spawned tasks have completed.
+    pub fn run(&mut self) {
+        let mut pending = self.task_count();
+
+        while pending > 0 {
+            let ready = self.ready_queue.take_all();
+
+            if ready.is_empty() {
+                Parker::current().park();
+                continue;
+            }
+
+            for id in ready {
+                let Some(task) = self.tasks[id].as_mut() else {
+                    // spurious wake up for a completed task
+                    continue;
+                };
+
+                let waker: Waker = Arc::new(MyWaker::new(id, &self.ready_queue)).into();
+                let mut cx = Context::from_waker(&waker);
+
+                // SAFETY: tasks are only pinned for the duration of `run`, which holds `&mut
+                // self`. The Vec can not grow (and move its elements) until `run` returns, at
+                // which point every task that was ever polled has completed and been dropped.
+                let task = unsafe { Pin::new_unchecked(task) };
+
+                if let Poll::Ready(()) = task.poll(&mut cx) {
+                    self.tasks[id] = None;
+                    pending -= 1;
+                }
+            }
+        }
+
+        self.tasks.clear();
+    }
+}
+
+impl<F> Default for TypedExecutor<F>
+where
+    F: Future<Output = ()>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,393 @@
+//! Completion based I/O on top of io_uring, as an alternative to the readiness based reactor.
+//!
+//! With the reactor, a future waits for a source to become *ready*, and then carries out the
+//! operation itself with a non-blocking system call. With io_uring the operation is handed to
+//! the kernel up front, and the future waits for it to *complete*. This is the proactor model.
+//!
+//! The proactor owns a ring and a thread that waits for completions, much like the reactor
+//! owns a `Poll` and an event loop thread. Futures submit their operation on first poll, and
+//! the completion thread wakes them once the result is in.
+//!
+//! Since the kernel reads from or writes into buffers while an operation is in flight,
+//! buffers are owned by the operation rather than borrowed: they are passed in by value and
+//! handed back along with the result. A future dropped before its operation completes leaves
+//! its buffer with the proactor, and asks the kernel to cancel the operation. The buffer is
+//! dropped once the kernel is done with it.
+//!
+//! Started via `runtime::init_with_backend(Backend::IoUring)`.
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    io,
+    os::fd::{FromRawFd, OwnedFd, RawFd},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    task::{Context, Poll, Waker},
+    thread::{self, ThreadId},
+};
+
+use super::executor;
+
+mod sys;
+
+use sys::{Cqe, Ring, Sqe};
+
+/// Operations in flight at most before submissions start failing
+const ENTRIES: u32 = 256;
+
+/// User data of cancellations, whose completions are of no interest. Operation ids are
+/// never 0, so it never clashes with an operation.
+const CANCEL_TOKEN: u64 = 0;
+
+static PROACTOR: OnceLock<Proactor> = OnceLock::new();
+
+fn proactor() -> &'static Proactor {
+    PROACTOR
+        .get()
+        .expect("io_uring backend not started, see runtime::init_with_backend")
+}
+
+/// Set up the ring and spawn the thread that waits for completions.
+///
+/// Fails if io_uring is not available, e.g. on kernels older than 5.6 or when it is disabled
+/// by a seccomp filter.
+pub(super) fn start() -> io::Result<()> {
+    let ring = Ring::new(ENTRIES)?;
+
+    let proactor = Proactor {
+        ring,
+        ops: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(1),
+    };
+    PROACTOR
+        .set(proactor)
+        .ok()
+        .expect("io_uring backend already started");
+
+    thread::Builder::new()
+        .name("uring".to_string())
+        .spawn(completion_loop)
+        .expect("Failed to spawn io_uring completion thread");
+
+    Ok(())
+}
+
+/// True if the proactor has been started.
+pub(crate) fn is_started() -> bool {
+    PROACTOR.get().is_some()
+}
+
+/// Ids of tasks on this thread waiting on an operation to complete.
+pub(crate) fn waiting_tasks() -> HashSet<usize> {
+    let Some(proactor) = PROACTOR.get() else {
+        return HashSet::new();
+    };
+    let thread = thread::current().id();
+
+    proactor
+        .ops
+        .lock()
+        .unwrap()
+        .values()
+        .filter_map(|op| match op.state {
+            State::Submitted(Some(_)) => op.owner,
+            _ => None,
+        })
+        .filter(|(owner, _)| *owner == thread)
+        .map(|(_, task)| task)
+        .collect()
+}
+
+struct Proactor {
+    ring: Ring,
+    /// Operations that were submitted and whose result was not yet taken by their future
+    ops: Mutex<HashMap<u64, Op>>,
+    next_id: AtomicU64,
+}
+
+/// Bookkeeping for an operation in flight.
+struct Op {
+    kind: Kind,
+    state: State,
+    /// Kept alive until the kernel is done with it
+    buf: Option<Vec<u8>>,
+    /// Executor thread and task that submitted the operation
+    ///
+    /// Only used to validate runtime invariants, see `runtime::self_check`.
+    owner: Option<(ThreadId, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Read,
+    Write,
+    Accept,
+}
+
+enum State {
+    /// Waiting on the kernel, with the waker of the future to wake once done
+    Submitted(Option<Waker>),
+    /// Result is in, for the future to take
+    Completed(i32),
+    /// The future was dropped, the result is discarded once it comes in
+    Cancelled,
+}
+
+impl Proactor {
+    /// Submit `sqe` under a new id, keeping `buf` alive until it completes.
+    fn submit(&self, kind: Kind, buf: Option<Vec<u8>>, sqe: impl FnOnce(u64) -> Sqe) -> u64 {
+        // only care about never handing out the same id twice, so Relaxed suffices
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let owner = executor::current_task().map(|task| (thread::current().id(), task));
+
+        // registered before submitting, so the completion always finds it
+        let op = Op {
+            kind,
+            state: State::Submitted(None),
+            buf,
+            owner,
+        };
+        self.ops.lock().unwrap().insert(id, op);
+
+        let sqe = sqe(id);
+        if let Err(e) = self.ring.submit(sqe) {
+            // resolve the operation with the error rather than leaving it waiting forever
+            let errno = e.raw_os_error().unwrap_or(libc::EAGAIN);
+            self.ops.lock().unwrap().get_mut(&id).unwrap().state = State::Completed(-errno);
+        }
+
+        id
+    }
+
+    /// Record the result of an operation, returning the waker of its future if there is one.
+    fn complete(&self, cqe: Cqe) -> Option<Waker> {
+        if cqe.user_data == CANCEL_TOKEN {
+            return None;
+        }
+
+        let mut ops = self.ops.lock().unwrap();
+        let op = ops.get_mut(&cqe.user_data)?;
+
+        match std::mem::replace(&mut op.state, State::Completed(cqe.res)) {
+            State::Submitted(waker) => waker,
+            State::Cancelled => {
+                let op = ops.remove(&cqe.user_data).unwrap();
+                discard(op.kind, cqe.res);
+                None
+            }
+            State::Completed(_) => unreachable!("operation {} completed twice", cqe.user_data),
+        }
+    }
+}
+
+/// Release whatever the result of an operation nobody is waiting for holds on to.
+fn discard(kind: Kind, res: i32) {
+    if kind == Kind::Accept && res >= 0 {
+        // SAFETY: an accepted socket nobody took ownership of
+        drop(unsafe { OwnedFd::from_raw_fd(res) });
+    }
+}
+
+/// Wait for completions and wake the futures whose operations completed.
+fn completion_loop() {
+    let proactor = proactor();
+
+    loop {
+        let mut to_wake = Vec::new();
+        proactor
+            .ring
+            .wait(|cqe| to_wake.extend(proactor.complete(cqe)))
+            .expect("Failed to wait for io_uring completions");
+
+        // same as the reactor: each task is queued once, each executor unparked once
+        executor::batch_wakes(|| to_wake.iter().for_each(Waker::wake_by_ref));
+    }
+}
+
+/// Leaf future for a single operation, resolving to its raw result and the buffer it used.
+struct OpFuture {
+    /// None until submitted on first poll
+    id: Option<u64>,
+    /// Submits the operation and returns its id, taken on first poll
+    submit: Option<Box<dyn FnOnce() -> u64>>,
+    done: bool,
+}
+
+impl OpFuture {
+    fn new(submit: impl FnOnce() -> u64 + 'static) -> Self {
+        Self {
+            id: None,
+            submit: Some(Box::new(submit)),
+            done: false,
+        }
+    }
+}
+
+impl Future for OpFuture {
+    type Output = (i32, Option<Vec<u8>>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // futures are lazy, nothing is submitted until we are first polled
+        if let Some(submit) = self.submit.take() {
+            self.id = Some(submit());
+        }
+        let id = self.id.expect("OpFuture polled after completion");
+        let mut ops = proactor().ops.lock().unwrap();
+
+        let op = ops.get_mut(&id).unwrap();
+        match &mut op.state {
+            State::Submitted(waker) => {
+                // always keep the most recent waker, as with the reactor
+                *waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            State::Completed(res) => {
+                let res = *res;
+                let op = ops.remove(&id).unwrap();
+                drop(ops);
+
+                self.done = true;
+                Poll::Ready((res, op.buf))
+            }
+            State::Cancelled => unreachable!("operation {id} polled after cancellation"),
+        }
+    }
+}
+
+impl Drop for OpFuture {
+    fn drop(&mut self) {
+        let Some(id) = self.id.filter(|_| !self.done) else {
+            return;
+        };
+        let proactor = proactor();
+
+        {
+            let mut ops = proactor.ops.lock().unwrap();
+            let op = ops.get_mut(&id).unwrap();
+
+            if let State::Completed(res) = op.state {
+                let op = ops.remove(&id).unwrap();
+                discard(op.kind, res);
+                return;
+            }
+            op.state = State::Cancelled;
+        }
+
+        // Best effort, the operation may complete before the cancellation is picked up.
+        // Either way the buffer is only dropped once its completion comes in.
+        let _ = proactor.ring.submit(Sqe::cancel(id, CANCEL_TOKEN));
+    }
+}
+
+/// Turn a raw result into an `io::Result`, negative results being `-errno`.
+fn result(res: i32) -> io::Result<usize> {
+    if res < 0 {
+        return Err(io::Error::from_raw_os_error(-res));
+    }
+    Ok(res as usize)
+}
+
+/// Read from `fd` into the spare capacity of `buf`, appending to it.
+///
+/// Resolves to the number of bytes read, 0 meaning end of file, and `buf` itself.
+pub async fn read(fd: RawFd, mut buf: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+    let (res, buf) = OpFuture::new(move || {
+        let spare = buf.spare_capacity_mut();
+        let (ptr, len) = (spare.as_mut_ptr().cast::<u8>(), spare.len() as u32);
+
+        // the heap allocation backing `buf` does not move along with it
+        proactor().submit(Kind::Read, Some(buf), |id| Sqe::read(fd, ptr, len, id))
+    })
+    .await;
+    let mut buf = buf.unwrap();
+
+    let res = result(res);
+    if let Ok(n) = res {
+        // SAFETY: the kernel initialised the `n` bytes it read
+        unsafe { buf.set_len(buf.len() + n) };
+    }
+
+    (res, buf)
+}
+
+/// Write the contents of `buf` to `fd`.
+///
+/// Resolves to the number of bytes written, which may be fewer than `buf` holds, and `buf`
+/// itself.
+pub async fn write(fd: RawFd, buf: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+    let (res, buf) = OpFuture::new(move || {
+        let (ptr, len) = (buf.as_ptr(), buf.len() as u32);
+        proactor().submit(Kind::Write, Some(buf), |id| Sqe::write(fd, ptr, len, id))
+    })
+    .await;
+
+    (result(res), buf.unwrap())
+}
+
+/// Accept a connection on the listening socket `fd`.
+pub async fn accept(fd: RawFd) -> io::Result<OwnedFd> {
+    let (res, _) =
+        OpFuture::new(move || proactor().submit(Kind::Accept, None, |id| Sqe::accept(fd, id)))
+            .await;
+
+    // SAFETY: the kernel created the socket for us, and nobody else knows about it
+    result(res).map(|fd| unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::{fd::AsRawFd, unix::net::UnixListener},
+        sync::Arc,
+        task::Wake,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::runtime;
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[test]
+    fn dropped_accept_is_cancelled() {
+        runtime::init_for_tests();
+        if !is_started() {
+            return;
+        }
+
+        let path = std::env::temp_dir().join(format!("uring-cancel-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        // submit an accept that never completes on its own, then drop it
+        let mut accept = Box::pin(accept(listener.as_raw_fd()));
+        let waker = Arc::new(Noop).into();
+        assert!(accept
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+        drop(accept);
+
+        // the cancellation completes the operation, which is then forgotten
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while proactor()
+            .ops
+            .lock()
+            .unwrap()
+            .values()
+            .any(|op| op.kind == Kind::Accept)
+        {
+            assert!(Instant::now() < deadline, "accept was not cancelled");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
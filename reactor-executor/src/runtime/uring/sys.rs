@@ -0,0 +1,383 @@
+//! Minimal wrapper around the io_uring system calls and the rings they share with the kernel.
+//!
+//! The kernel and us communicate through two ring buffers mapped into our memory:
+//!
+//! - the submission queue (SQ), where we write submission queue entries (SQEs) describing
+//!   operations, and bump the tail for the kernel to pick them up.
+//! - the completion queue (CQ), where the kernel writes a completion queue entry (CQE) with
+//!   the result of every operation, and we bump the head once we have read it.
+//!
+//! Each entry carries a `user_data` value that is handed back untouched in its completion,
+//! which is how completions are matched with the operations that caused them.
+//!
+//! See: https://man7.org/linux/man-pages/man7/io_uring.7.html
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    ptr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+// Opcodes, see `enum io_uring_op` in `linux/io_uring.h`
+const IORING_OP_ACCEPT: u8 = 13;
+const IORING_OP_ASYNC_CANCEL: u8 = 14;
+const IORING_OP_READ: u8 = 22;
+const IORING_OP_WRITE: u8 = 23;
+
+/// Flag for `io_uring_enter` to wait for completions
+const IORING_ENTER_GETEVENTS: u32 = 1;
+
+// Offsets to mmap the rings at
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x8000000;
+const IORING_OFF_SQES: libc::off_t = 0x10000000;
+
+/// `struct io_sqring_offsets`
+#[repr(C)]
+#[derive(Default)]
+struct SqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+/// `struct io_cqring_offsets`
+#[repr(C)]
+#[derive(Default)]
+struct CqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+/// `struct io_uring_params`, filled in by the kernel on setup
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqRingOffsets,
+    cq_off: CqRingOffsets,
+}
+
+/// `struct io_uring_sqe`, an operation for the kernel to carry out.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    op_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+impl Sqe {
+    /// Read up to `len` bytes from `fd` into `buf`.
+    ///
+    /// `buf` must stay valid until the operation completes.
+    pub(crate) fn read(fd: RawFd, buf: *mut u8, len: u32, user_data: u64) -> Self {
+        Self {
+            opcode: IORING_OP_READ,
+            fd,
+            addr: buf as u64,
+            len,
+            // read from the current position, as `read(2)` would
+            off: u64::MAX,
+            user_data,
+            ..Default::default()
+        }
+    }
+
+    /// Write up to `len` bytes of `buf` to `fd`.
+    ///
+    /// `buf` must stay valid until the operation completes.
+    pub(crate) fn write(fd: RawFd, buf: *const u8, len: u32, user_data: u64) -> Self {
+        Self {
+            opcode: IORING_OP_WRITE,
+            fd,
+            addr: buf as u64,
+            len,
+            off: u64::MAX,
+            user_data,
+            ..Default::default()
+        }
+    }
+
+    /// Accept a connection on the listening socket `fd`, the result is the new socket.
+    pub(crate) fn accept(fd: RawFd, user_data: u64) -> Self {
+        Self {
+            opcode: IORING_OP_ACCEPT,
+            fd,
+            op_flags: libc::SOCK_CLOEXEC as u32,
+            user_data,
+            ..Default::default()
+        }
+    }
+
+    /// Cancel the operation submitted with `target` as its user data.
+    pub(crate) fn cancel(target: u64, user_data: u64) -> Self {
+        Self {
+            opcode: IORING_OP_ASYNC_CANCEL,
+            fd: -1,
+            addr: target,
+            user_data,
+            ..Default::default()
+        }
+    }
+}
+
+/// `struct io_uring_cqe`, the result of an operation.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Cqe {
+    pub(crate) user_data: u64,
+    /// What the equivalent system call would have returned, or `-errno`
+    pub(crate) res: i32,
+    pub(crate) flags: u32,
+}
+
+/// A memory mapping, unmapped when dropped.
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    fn new(fd: RawFd, offset: libc::off_t, len: usize) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { ptr, len })
+    }
+
+    /// Pointer to the `T` at `offset` bytes into the mapping.
+    fn at<T>(&self, offset: u32) -> *mut T {
+        unsafe { self.ptr.cast::<u8>().add(offset as usize).cast() }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+struct SubmissionQueue {
+    head: *const AtomicU32,
+    tail: *const AtomicU32,
+    mask: u32,
+    entries: u32,
+    /// Indices into `sqes`, one per slot of the ring
+    array: *mut u32,
+    sqes: *mut Sqe,
+    _ring: Mmap,
+    _sqes: Mmap,
+}
+
+struct CompletionQueue {
+    head: *const AtomicU32,
+    tail: *const AtomicU32,
+    mask: u32,
+    cqes: *const Cqe,
+    _ring: Mmap,
+}
+
+// SAFETY: the pointers point into mappings owned by the queues, which the kernel keeps alive
+// for as long as they are mapped. Access from several threads is serialized by `Ring`.
+unsafe impl Send for SubmissionQueue {}
+unsafe impl Send for CompletionQueue {}
+
+/// An io_uring instance.
+///
+/// Operations may be submitted from any thread, while completions are meant to be waited
+/// for by a single thread. Each queue is behind a lock of its own, so submitting never waits
+/// on a thread that is waiting for completions.
+pub(crate) struct Ring {
+    sq: Mutex<SubmissionQueue>,
+    cq: Mutex<CompletionQueue>,
+    /// Closed last, after the rings are unmapped
+    fd: OwnedFd,
+}
+
+impl Ring {
+    /// Set up a ring with room for `entries` submissions in flight.
+    pub(crate) fn new(entries: u32) -> io::Result<Self> {
+        let mut params = Params::default();
+        let fd = unsafe { libc::syscall(libc::SYS_io_uring_setup, entries, &mut params) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+        let raw = fd.as_raw_fd();
+
+        let sq_off = &params.sq_off;
+        let sq_len = sq_off.array as usize + params.sq_entries as usize * size_of::<u32>();
+        let sq_ring = Mmap::new(raw, IORING_OFF_SQ_RING, sq_len)?;
+        let sqes = Mmap::new(
+            raw,
+            IORING_OFF_SQES,
+            params.sq_entries as usize * size_of::<Sqe>(),
+        )?;
+
+        let cq_off = &params.cq_off;
+        let cq_len = cq_off.cqes as usize + params.cq_entries as usize * size_of::<Cqe>();
+        let cq_ring = Mmap::new(raw, IORING_OFF_CQ_RING, cq_len)?;
+
+        let sq = SubmissionQueue {
+            head: sq_ring.at(sq_off.head),
+            tail: sq_ring.at(sq_off.tail),
+            mask: unsafe { *sq_ring.at::<u32>(sq_off.ring_mask) },
+            entries: unsafe { *sq_ring.at::<u32>(sq_off.ring_entries) },
+            array: sq_ring.at(sq_off.array),
+            sqes: sqes.at(0),
+            _ring: sq_ring,
+            _sqes: sqes,
+        };
+
+        let cq = CompletionQueue {
+            head: cq_ring.at(cq_off.head),
+            tail: cq_ring.at(cq_off.tail),
+            mask: unsafe { *cq_ring.at::<u32>(cq_off.ring_mask) },
+            cqes: cq_ring.at(cq_off.cqes),
+            _ring: cq_ring,
+        };
+
+        Ok(Self {
+            sq: Mutex::new(sq),
+            cq: Mutex::new(cq),
+            fd,
+        })
+    }
+
+    /// Hand `sqe` to the kernel.
+    pub(crate) fn submit(&self, sqe: Sqe) -> io::Result<()> {
+        let sq = self.sq.lock().unwrap();
+
+        unsafe {
+            let head = (*sq.head).load(Ordering::Acquire);
+            let tail = (*sq.tail).load(Ordering::Relaxed);
+
+            // entries are consumed by the kernel as part of `io_uring_enter`, which we call
+            // for every submission, so the queue only fills up if that failed before.
+            if tail.wrapping_sub(head) == sq.entries {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "io_uring submission queue is full",
+                ));
+            }
+
+            let index = tail & sq.mask;
+            sq.sqes.add(index as usize).write(sqe);
+            sq.array.add(index as usize).write(index);
+
+            // the entry must be visible to the kernel before the tail that covers it
+            (*sq.tail).store(tail.wrapping_add(1), Ordering::Release);
+        }
+
+        // submit while still holding the lock, so entries are handed over in order
+        self.enter(1, 0, 0).map(|_| ())
+    }
+
+    /// Block until at least one operation has completed, then call `f` with every completion
+    /// available.
+    pub(crate) fn wait(&self, mut f: impl FnMut(Cqe)) -> io::Result<()> {
+        let cq = self.cq.lock().unwrap();
+
+        // the number of entries submitted is of no interest, since we submitted none
+        loop {
+            match self.enter(0, 1, IORING_ENTER_GETEVENTS).map(drop) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => break res?,
+            }
+        }
+
+        unsafe {
+            let mut head = (*cq.head).load(Ordering::Relaxed);
+            let tail = (*cq.tail).load(Ordering::Acquire);
+
+            while head != tail {
+                f(cq.cqes.add((head & cq.mask) as usize).read());
+                head = head.wrapping_add(1);
+            }
+
+            // the entries were copied out, so the kernel is free to reuse their slots
+            (*cq.head).store(head, Ordering::Release);
+        }
+
+        Ok(())
+    }
+
+    fn enter(&self, to_submit: u32, min_complete: u32, flags: u32) -> io::Result<usize> {
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_enter,
+                self.fd.as_raw_fd(),
+                to_submit,
+                min_complete,
+                flags,
+                ptr::null::<libc::sigset_t>(),
+                0usize,
+            )
+        };
+
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(res as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abi_structs_match_the_kernel() {
+        assert_eq!(size_of::<Params>(), 120);
+        assert_eq!(size_of::<Sqe>(), 64);
+        assert_eq!(size_of::<Cqe>(), 16);
+    }
+}
@@ -0,0 +1,482 @@
+//! Storage for the wakers of the sources registered with a reactor shard.
+//!
+//! Every `set_waker` and deregistration from the executors, and every event dispatched by the
+//! event loop, looks up a waker. Behind a single `Mutex<HashMap>` they all contend for the same
+//! lock. Here wakers live in a slab split into stripes, each behind its own lock, so operations
+//! on different sources rarely meet.
+//!
+//! The key of a slot is the source's id with the reactor, so finding a waker takes no hashing,
+//! and freed slots are handed out again. A reused slot gets a new generation, which is part of
+//! its key: an event still carrying the key of a previous occupant finds a generation mismatch,
+//! never the new occupant's waker.
+//!
+//! A slot holds a waker per direction, one woken once the source is readable and one once it
+//! is writable, so a task reading from a socket and another writing to it do not replace each
+//! other's wakers. A waker stored for both is woken by either.
+//!
+//! Events are edge-triggered, so one that comes in while no waker is stored would be lost for
+//! good. Instead the slot remembers it, and the next waker stored for the slot is woken
+//! straight away, see [`WakerSlab::set`].
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    task::Waker,
+    thread::ThreadId,
+};
+
+use mio::Interest;
+
+/// Number of stripes used by the reactor
+pub(crate) const DEFAULT_STRIPES: usize = 16;
+
+/// Keys are `generation << INDEX_BITS | index`, with `index` counting slots over all stripes.
+const INDEX_BITS: u32 = 32;
+/// Generations wrap around after this many reuses of a slot. Together with the index this
+/// leaves 8 bits of a 64 bit id for the reactor to encode its shard in.
+const GENERATION_BITS: u32 = 24;
+
+/// Index of the waker woken once a source is readable, in the arrays of a [`Slot`]
+const READ: usize = 0;
+/// Index of the waker woken once a source is writable
+const WRITE: usize = 1;
+
+/// What an event on a key came to, see [`WakerSlab::on_event`].
+#[derive(Debug)]
+pub(crate) enum Dispatch {
+    /// Wakers stored for the key were handed out, to be woken
+    Wake,
+    /// No waker is stored, the event is replayed to the next one instead
+    Missed,
+    /// The key's slot was freed, or reused by another source since. The event was in flight
+    /// while its source was deregistered, and is ignored.
+    Stale,
+}
+
+/// Executor thread and task a waker was stored by.
+pub(crate) type Owner = (ThreadId, usize);
+
+pub(crate) struct WakerSlab {
+    stripes: Box<[Mutex<Stripe>]>,
+    /// Spreads new slots over the stripes
+    next_stripe: AtomicUsize,
+}
+
+#[derive(Default)]
+struct Stripe {
+    slots: Vec<Slot>,
+    /// Indices into `slots` that are free to be reused
+    free: Vec<usize>,
+}
+
+/// The arrays hold an entry per direction, indexed by [`READ`] and [`WRITE`].
+#[derive(Default)]
+struct Slot {
+    /// Starts at 1 and is bumped when the slot is freed, so keys are never 0
+    generation: usize,
+    occupied: bool,
+    wakers: [Option<Waker>; 2],
+    /// An event came in while no waker was stored, to be replayed to the next one
+    missed: [bool; 2],
+    /// Only used to validate runtime invariants, see `runtime::self_check`.
+    owners: [Option<Owner>; 2],
+}
+
+impl Slot {
+    /// Drop the wakers of `directions`, keeping the slot.
+    fn clear(&mut self, directions: &[usize]) {
+        for &direction in directions {
+            self.wakers[direction] = None;
+            self.owners[direction] = None;
+        }
+    }
+}
+
+/// Indices of the directions in `interest`.
+fn directions(interest: Interest) -> &'static [usize] {
+    match (interest.is_readable(), interest.is_writable()) {
+        (true, false) => &[READ],
+        (false, true) => &[WRITE],
+        _ => &[READ, WRITE],
+    }
+}
+
+/// Interest of a direction.
+fn interest_of(direction: usize) -> Interest {
+    if direction == READ {
+        Interest::READABLE
+    } else {
+        Interest::WRITABLE
+    }
+}
+
+impl WakerSlab {
+    pub(crate) fn new(stripes: usize) -> Self {
+        assert!(stripes > 0, "WakerSlab needs at least one stripe");
+
+        Self {
+            stripes: (0..stripes).map(|_| Mutex::default()).collect(),
+            next_stripe: AtomicUsize::new(0),
+        }
+    }
+
+    /// Take a free slot, returning its key. Keys are never 0.
+    pub(crate) fn insert(&self) -> usize {
+        let n = self.stripes.len();
+        let stripe = self.next_stripe.fetch_add(1, Ordering::Relaxed) % n;
+        let mut slots = self.stripes[stripe].lock().unwrap();
+
+        let local = match slots.free.pop() {
+            Some(local) => local,
+            None => {
+                slots.slots.push(Slot {
+                    generation: 1,
+                    ..Slot::default()
+                });
+                slots.slots.len() - 1
+            }
+        };
+
+        let slot = &mut slots.slots[local];
+        slot.occupied = true;
+
+        let index = local * n + stripe;
+        assert!(index < 1 << INDEX_BITS, "WakerSlab is full");
+        slot.generation << INDEX_BITS | index
+    }
+
+    /// Free the slot of `key`, dropping its waker. Does nothing if it was freed already.
+    pub(crate) fn remove(&self, key: usize) {
+        let (stripe, local, generation) = self.split(key);
+        let mut stripe = self.stripes[stripe].lock().unwrap();
+
+        let Some(slot) = stripe
+            .slots
+            .get_mut(local)
+            .filter(|slot| slot.occupied && slot.generation == generation)
+        else {
+            return;
+        };
+
+        slot.occupied = false;
+        slot.clear(&[READ, WRITE]);
+        slot.missed = [false; 2];
+        // wrap around, skipping 0
+        slot.generation = slot.generation % ((1 << GENERATION_BITS) - 1) + 1;
+
+        stripe.free.push(local);
+    }
+
+    /// Store `waker` for `key`, to be woken by the events of `interest`, replacing the
+    /// previous waker for those. Ignored for a freed key.
+    ///
+    /// Returns true if one of the events came in since the last waker for it was dropped, in
+    /// which case the caller should wake `waker` itself.
+    pub(crate) fn set(
+        &self,
+        key: usize,
+        waker: &Waker,
+        interest: Interest,
+        owner: Option<Owner>,
+    ) -> bool {
+        self.with_slot(key, |slot| {
+            let mut missed = false;
+            for &direction in directions(interest) {
+                // IMPORTANT: we always store the most recent waker for a given source.
+                match &mut slot.wakers[direction] {
+                    Some(current) => current.clone_from(waker),
+                    None => slot.wakers[direction] = Some(waker.clone()),
+                }
+                if owner.is_some() {
+                    slot.owners[direction] = owner;
+                }
+                missed |= std::mem::take(&mut slot.missed[direction]);
+            }
+            missed
+        })
+        .unwrap_or(false)
+    }
+
+    /// Drop the wakers of `key` for `interest`, keeping the slot.
+    pub(crate) fn clear(&self, key: usize, interest: Interest) {
+        self.with_slot(key, |slot| slot.clear(directions(interest)));
+    }
+
+    /// A waker stored for `key`, the one for reading if there are both.
+    pub(crate) fn get(&self, key: usize) -> Option<Waker> {
+        self.with_slot(key, |slot| slot.wakers.iter().flatten().next().cloned())
+            .flatten()
+    }
+
+    /// Push the wakers to wake for an event on `key` with `readiness` onto `to_wake`. An event
+    /// in a direction without a waker is remembered for the next waker stored for it instead.
+    /// A waker stored for both directions is only pushed once.
+    ///
+    /// Both happen under the same lock as [`WakerSlab::set`], so an event is either handed to
+    /// a waker or replayed, never lost in between.
+    pub(crate) fn on_event(
+        &self,
+        key: usize,
+        readiness: Interest,
+        to_wake: &mut Vec<Waker>,
+    ) -> Dispatch {
+        self.with_slot(key, |slot| {
+            let mut woken: Option<&Waker> = None;
+            for &direction in directions(readiness) {
+                match &slot.wakers[direction] {
+                    Some(waker) if woken.is_some_and(|woken| woken.will_wake(waker)) => {}
+                    Some(waker) => {
+                        to_wake.push(waker.clone());
+                        woken = Some(waker);
+                    }
+                    None => slot.missed[direction] = true,
+                }
+            }
+
+            match woken {
+                Some(_) => Dispatch::Wake,
+                None => Dispatch::Missed,
+            }
+        })
+        .unwrap_or(Dispatch::Stale)
+    }
+
+    /// Have the next wakers stored for `key` woken straight away, as if an event had come in.
+    pub(crate) fn mark_missed(&self, key: usize) {
+        self.with_slot(key, |slot| slot.missed = [true; 2]);
+    }
+
+    /// Number of occupied slots.
+    pub(crate) fn len(&self) -> usize {
+        self.stripes
+            .iter()
+            .map(|stripe| {
+                let stripe = stripe.lock().unwrap();
+                stripe.slots.len() - stripe.free.len()
+            })
+            .sum()
+    }
+
+    /// True if no waker is stored at all.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.stripes.iter().all(|stripe| {
+            let stripe = stripe.lock().unwrap();
+            stripe
+                .slots
+                .iter()
+                .all(|slot| slot.wakers.iter().all(Option::is_none))
+        })
+    }
+
+    /// Keys with a waker, the direction it waits for and the owner that stored it, for all
+    /// wakers with an owner. A key shows up twice if both of its wakers have one.
+    pub(crate) fn owners(&self) -> Vec<(usize, Interest, Owner)> {
+        let n = self.stripes.len();
+
+        self.stripes
+            .iter()
+            .enumerate()
+            .flat_map(|(stripe, slots)| {
+                let slots = slots.lock().unwrap();
+                slots
+                    .slots
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(local, slot)| {
+                        let key = slot.generation << INDEX_BITS | (local * n + stripe);
+                        (0..2)
+                            .filter(|&direction| slot.wakers[direction].is_some())
+                            .filter_map(move |direction| {
+                                Some((key, interest_of(direction), slot.owners[direction]?))
+                            })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Number of slots allocated so far, free or not.
+    #[cfg(test)]
+    fn capacity(&self) -> usize {
+        self.stripes
+            .iter()
+            .map(|stripe| stripe.lock().unwrap().slots.len())
+            .sum()
+    }
+
+    /// Split a key into stripe, index within the stripe and generation.
+    fn split(&self, key: usize) -> (usize, usize, usize) {
+        let n = self.stripes.len();
+        let index = key & ((1 << INDEX_BITS) - 1);
+        (index % n, index / n, key >> INDEX_BITS)
+    }
+
+    /// Run `f` on the slot of `key`, if it is occupied by the same generation.
+    fn with_slot<R>(&self, key: usize, f: impl FnOnce(&mut Slot) -> R) -> Option<R> {
+        let (stripe, local, generation) = self.split(key);
+        let mut slots = self.stripes[stripe].lock().unwrap();
+
+        match slots.slots.get_mut(local) {
+            Some(slot) if slot.occupied && slot.generation == generation => Some(f(slot)),
+            _ => None,
+        }
+    }
+}
+
+impl Default for WakerSlab {
+    fn default() -> Self {
+        Self::new(DEFAULT_STRIPES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        task::Wake,
+        thread::{self, JoinHandle},
+    };
+
+    use super::*;
+
+    const BOTH: Interest = Interest::READABLE.add(Interest::WRITABLE);
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[test]
+    fn reused_slot_does_not_match_old_key() {
+        let slab = WakerSlab::new(1);
+        let waker: Waker = Arc::new(Noop).into();
+
+        let old = slab.insert();
+        slab.set(old, &waker, BOTH, None);
+        slab.remove(old);
+
+        let new = slab.insert();
+        assert_ne!(old, new);
+        assert_eq!(slab.capacity(), 1, "slot is reused");
+
+        slab.set(new, &waker, BOTH, None);
+        assert!(slab.get(old).is_none());
+        assert!(slab.get(new).is_some());
+        // an event still carrying the old key is not taken for one of the new occupant
+        assert!(matches!(
+            slab.on_event(old, BOTH, &mut Vec::new()),
+            Dispatch::Stale
+        ));
+        assert!(matches!(
+            slab.on_event(new, BOTH, &mut Vec::new()),
+            Dispatch::Wake
+        ));
+
+        // freeing twice must not put the slot on the free list twice
+        slab.remove(old);
+        assert_ne!(slab.insert(), slab.insert());
+    }
+
+    #[test]
+    fn event_without_waker_is_replayed_once() {
+        let slab = WakerSlab::new(1);
+        let waker: Waker = Arc::new(Noop).into();
+        let key = slab.insert();
+
+        assert!(matches!(
+            slab.on_event(key, BOTH, &mut Vec::new()),
+            Dispatch::Missed
+        ));
+        assert!(
+            slab.set(key, &waker, BOTH, None),
+            "missed event is replayed"
+        );
+        assert!(!slab.set(key, &waker, BOTH, None), "only once");
+
+        // events for a waker that is stored are handed to it instead
+        assert!(matches!(
+            slab.on_event(key, BOTH, &mut Vec::new()),
+            Dispatch::Wake
+        ));
+        slab.clear(key, BOTH);
+        assert!(!slab.set(key, &waker, BOTH, None));
+    }
+
+    #[test]
+    fn readers_and_writers_keep_their_own_wakers() {
+        let slab = WakerSlab::new(1);
+        let (reader, writer): (Waker, Waker) = (Arc::new(Noop).into(), Arc::new(Noop).into());
+        let key = slab.insert();
+
+        slab.set(key, &reader, Interest::READABLE, None);
+        slab.set(key, &writer, Interest::WRITABLE, None);
+
+        let mut to_wake = Vec::new();
+        slab.on_event(key, Interest::WRITABLE, &mut to_wake);
+        assert!(to_wake.len() == 1 && to_wake[0].will_wake(&writer));
+
+        to_wake.clear();
+        slab.on_event(key, BOTH, &mut to_wake);
+        assert_eq!(to_wake.len(), 2);
+
+        // with the reader gone, readability is replayed to the next one rather than lost
+        slab.clear(key, Interest::READABLE);
+        to_wake.clear();
+        assert!(matches!(
+            slab.on_event(key, BOTH, &mut to_wake),
+            Dispatch::Wake
+        ));
+        assert!(to_wake.len() == 1 && to_wake[0].will_wake(&writer));
+        assert!(slab.set(key, &reader, Interest::READABLE, None));
+    }
+
+    #[test]
+    fn waker_stored_for_both_directions_is_woken_once() {
+        let slab = WakerSlab::new(1);
+        let waker: Waker = Arc::new(Noop).into();
+        let key = slab.insert();
+        slab.set(key, &waker, BOTH, None);
+
+        let mut to_wake = Vec::new();
+        slab.on_event(key, BOTH, &mut to_wake);
+        assert_eq!(to_wake.len(), 1);
+    }
+
+    #[test]
+    fn concurrent_registrations_only_see_their_own_wakers() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 10_000;
+
+        let slab = Arc::new(WakerSlab::default());
+
+        let workers: Vec<JoinHandle<()>> = (0..THREADS)
+            .map(|_| {
+                let slab = slab.clone();
+                thread::spawn(move || {
+                    let waker: Waker = Arc::new(Noop).into();
+                    let owner = Some((thread::current().id(), 0));
+
+                    for _ in 0..ROUNDS {
+                        let key = slab.insert();
+                        slab.set(key, &waker, BOTH, owner);
+                        assert!(slab.get(key).unwrap().will_wake(&waker));
+
+                        slab.clear(key, BOTH);
+                        assert!(slab.get(key).is_none());
+                        slab.remove(key);
+                    }
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert!(slab.is_idle());
+        // slots are reused, rather than one allocated per registration
+        assert!(slab.capacity() <= THREADS * DEFAULT_STRIPES);
+    }
+}
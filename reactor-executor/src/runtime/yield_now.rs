@@ -0,0 +1,125 @@
+//! Cooperative yielding, for tasks that run for long stretches without doing any IO.
+//!
+//! A task only gives up the executor's thread when it returns `Pending`, so a task busy with
+//! computation starves every other task until it completes. Awaiting [`yield_now`] every so
+//! often lets the other ready tasks run in between.
+//!
+//! The wake is deferred until no other task is ready, see `executor::defer_wake`. Otherwise
+//! the task would be queued on top of the others and polled again straight away.
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+use crate::future::{self, PollState};
+
+use super::{executor, MyWaker};
+
+/// Returns a future that is not ready the first time it is polled, waking its own task once
+/// the other ready tasks had their turn. Outside of the executor, e.g. when polled with a
+/// [`MyWaker`] by hand, the waker is woken straight away.
+///
+/// ```ignore
+/// for chunk in work.chunks(1024) {
+///     crunch(chunk);
+///     runtime::yield_now().await;
+/// }
+/// ```
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+/// Future returned by [`yield_now`].
+#[must_use = "futures do nothing unless polled"]
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl YieldNow {
+    fn poll_yield(&mut self, waker: &Waker) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        wake_after_others(waker);
+        Poll::Pending
+    }
+}
+
+/// Wake the task of `waker` once the other ready tasks had their turn, for leaf futures that
+/// give up the thread before they are done, e.g. once out of budget.
+pub(crate) fn wake_after_others(waker: &Waker) {
+    if !executor::defer_wake(waker) {
+        waker.wake_by_ref();
+    }
+}
+
+impl std::future::Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.poll_yield(cx.waker())
+    }
+}
+
+impl future::Future for YieldNow {
+    type Output = ();
+    type Waker = MyWaker;
+
+    fn poll(mut self: Pin<&mut Self>, waker: &MyWaker) -> PollState<()> {
+        let waker: Waker = Arc::new(waker.clone()).into();
+
+        match self.poll_yield(&waker) {
+            Poll::Ready(()) => PollState::Ready(()),
+            Poll::Pending => PollState::NotReady,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::runtime::{spawn_local, Executor};
+
+    #[test]
+    fn busy_tasks_take_turns() {
+        let order = Rc::new(RefCell::new(Vec::new()));
+
+        Executor::new().block_on({
+            let order = order.clone();
+            async move {
+                for _ in 0..2 {
+                    let order = order.clone();
+                    spawn_local(async move {
+                        for step in 0..3 {
+                            order.borrow_mut().push(step);
+                            yield_now().await;
+                        }
+                    });
+                }
+            }
+        });
+
+        // each task does a step before either does its next one
+        assert_eq!(*order.borrow(), [0, 0, 1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn works_with_waker_based_trait() {
+        let queue = Arc::default();
+        let waker = MyWaker::new(3, &queue);
+        let mut future = yield_now();
+
+        let poll = future::Future::poll(Pin::new(&mut future), &waker);
+        assert!(matches!(poll, PollState::NotReady));
+        // woke itself, so the task is queued to be polled again
+        assert_eq!(queue.snapshot(), vec![3]);
+
+        let poll = future::Future::poll(Pin::new(&mut future), &waker);
+        assert!(matches!(poll, PollState::Ready(())));
+    }
+}
@@ -0,0 +1,750 @@
+//! Simulated network for running the http examples without real sockets.
+//!
+//! A [`Network`] holds a set of simulated endpoints, each reachable over a [`Link`] with a
+//! configurable latency, bandwidth and drop rate. Each endpoint is a delayserver: the path is
+//! expected to be `/<delay in ms>/<message>`, and the response echoes back the message once the
+//! delay has passed. Once the network is installed, the http client sends requests to its
+//! endpoints over in-memory connections to a simulated delayserver, rather than over TCP.
+//!
+//! No time actually passes. The network keeps a virtual clock that is only advanced by the
+//! executor once it has run out of ready tasks, which jumps straight to the next deadline.
+//! This makes simulated runs both deterministic and instant.
+//!
+//! ```ignore
+//! let net = sim::Network::new(42);
+//! net.add_endpoint("127.0.0.1:8080", sim::Link::new(Duration::from_millis(5)));
+//! net.install();
+//!
+//! let response = Http::with_addr("127.0.0.1:8080").get("/600/HelloSim").await?;
+//! ```
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    future::Future,
+    io::{self, ErrorKind},
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use crate::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    runtime::{self, log},
+};
+
+// The network that the executor on this thread advances when it is idle.
+thread_local! {
+    static CURRENT_NET: RefCell<Option<Network>> = const { RefCell::new(None) };
+}
+
+/// Advance the virtual clock of the installed network to the next deadline, waking any
+/// futures that were waiting on it.
+///
+/// Returns false if no network is installed or nothing is waiting on the virtual clock,
+/// in which case the caller should fall back to parking the thread.
+pub fn advance() -> bool {
+    CURRENT_NET.with(|net| match net.borrow().as_ref() {
+        Some(net) => net.advance(),
+        None => false,
+    })
+}
+
+/// Ids of tasks on this thread waiting on the virtual clock of the installed network.
+pub fn waiting_tasks() -> HashSet<usize> {
+    CURRENT_NET.with(|net| match net.borrow().as_ref() {
+        Some(net) => net.waiting_tasks(),
+        None => HashSet::new(),
+    })
+}
+
+/// Connect to the endpoint at `addr` of the installed network, which the http client does for
+/// every request to it. `None` if no network is installed or it has no endpoint there.
+pub(crate) fn connect(addr: &str) -> Option<DuplexStream> {
+    let net = CURRENT_NET.with(|net| net.borrow().clone())?;
+    net.connect(addr)
+}
+
+/// Characteristics of the link between the client and a simulated endpoint.
+#[derive(Debug, Clone)]
+pub struct Link {
+    /// One-way latency, paid once for the request and once for the response.
+    pub latency: Duration,
+    /// Bytes per second. `None` means the transfer itself takes no time.
+    pub bandwidth: Option<u64>,
+    /// Probability in `[0, 1]` that a request is dropped and never answered.
+    pub drop_rate: f64,
+}
+
+impl Link {
+    pub fn new(latency: Duration) -> Self {
+        Self {
+            latency,
+            bandwidth: None,
+            drop_rate: 0.0,
+        }
+    }
+
+    pub fn bandwidth(mut self, bytes_per_sec: u64) -> Self {
+        self.bandwidth = Some(bytes_per_sec);
+        self
+    }
+
+    pub fn drop_rate(mut self, drop_rate: f64) -> Self {
+        self.drop_rate = drop_rate;
+        self
+    }
+
+    /// Time it takes to move `len` bytes across the link, excluding latency.
+    fn transfer_time(&self, len: usize) -> Duration {
+        match self.bandwidth {
+            Some(0) | None => Duration::ZERO,
+            Some(bps) => Duration::from_secs_f64(len as f64 / bps as f64),
+        }
+    }
+}
+
+/// Handle to a simulated network. Cloning it gives another handle to the same network.
+#[derive(Clone)]
+pub struct Network {
+    inner: Rc<RefCell<NetworkInner>>,
+}
+
+struct NetworkInner {
+    /// Virtual time elapsed since the network was created.
+    now: Duration,
+    /// Deadlines that futures are waiting on, earliest first.
+    ///
+    /// The second element is a sequence number that keys into `wakers`, which also keeps
+    /// ordering stable for futures that share a deadline.
+    timers: BinaryHeap<Reverse<(Duration, usize)>>,
+    /// Waker for each sequence number, with the id of the task it was registered by
+    wakers: HashMap<usize, (Waker, Option<usize>)>,
+    next_seq: usize,
+    links: HashMap<String, Link>,
+    /// Open connections, see [`DuplexStream`]
+    conns: HashMap<usize, Connection>,
+    next_conn: usize,
+    rng: XorShift,
+}
+
+impl Network {
+    /// Create a new network. The seed drives the decision of which requests get dropped.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(NetworkInner {
+                now: Duration::ZERO,
+                timers: BinaryHeap::new(),
+                wakers: HashMap::new(),
+                next_seq: 0,
+                links: HashMap::new(),
+                conns: HashMap::new(),
+                next_conn: 0,
+                rng: XorShift::new(seed),
+            })),
+        }
+    }
+
+    /// Make this the network the executor on the current thread advances when idle, and the
+    /// http client connects to the endpoints of.
+    pub fn install(&self) {
+        CURRENT_NET.with(|net| *net.borrow_mut() = Some(self.clone()));
+    }
+
+    pub fn add_endpoint(&self, addr: &str, link: Link) {
+        self.inner.borrow_mut().links.insert(addr.to_string(), link);
+    }
+
+    /// Current virtual time.
+    pub fn now(&self) -> Duration {
+        self.inner.borrow().now
+    }
+
+    /// Open a connection to the simulated delayserver at `addr`, `None` if there is no
+    /// endpoint at `addr`. The server is spawned onto the executor of the current thread.
+    fn connect(&self, addr: &str) -> Option<DuplexStream> {
+        let conn = {
+            let mut inner = self.inner.borrow_mut();
+            let link = inner.links.get(addr).cloned()?;
+            let dropped = inner.rng.next_f64() < link.drop_rate;
+
+            let conn = inner.next_conn;
+            inner.next_conn += 1;
+            inner.conns.insert(
+                conn,
+                Connection {
+                    link,
+                    dropped,
+                    pipes: Default::default(),
+                },
+            );
+            conn
+        };
+        let end = |side| DuplexStream {
+            conn,
+            side,
+            timer: None,
+        };
+
+        let (server, net) = (end(DuplexStream::SERVER), self.clone());
+        runtime::spawn_local(async move {
+            if let Err(e) = serve(server, net).await {
+                log::debug!("simulated server failed: {e}");
+            }
+        });
+
+        Some(end(DuplexStream::CLIENT))
+    }
+
+    /// Returns a future that completes once the virtual clock has moved `duration` past the
+    /// time of its first poll.
+    pub fn sleep(&self, duration: Duration) -> impl Future<Output = ()> {
+        Sleep {
+            net: self.clone(),
+            duration,
+            deadline: None,
+            timer: None,
+        }
+    }
+
+    /// Returns a future that resolves to the output of `future`, or to `ErrorKind::TimedOut`
+    /// if the virtual clock moves `duration` past its first poll before `future` completes.
+    pub fn timeout<F: Future>(&self, duration: Duration, future: F) -> Timeout<F> {
+        Timeout {
+            future: Box::pin(future),
+            sleep: Sleep {
+                net: self.clone(),
+                duration,
+                deadline: None,
+                timer: None,
+            },
+        }
+    }
+
+    /// Jump the clock to the earliest pending deadline and wake everything due by then.
+    fn advance(&self) -> bool {
+        let mut inner = self.inner.borrow_mut();
+
+        // Skip timers of futures that were dropped, so they do not move the clock
+        while let Some(Reverse((_, seq))) = inner.timers.peek().copied() {
+            if inner.wakers.contains_key(&seq) {
+                break;
+            }
+            inner.timers.pop();
+        }
+
+        let Some(Reverse((deadline, _))) = inner.timers.peek().copied() else {
+            return false;
+        };
+
+        inner.now = inner.now.max(deadline);
+
+        while let Some(Reverse((deadline, seq))) = inner.timers.peek().copied() {
+            if deadline > inner.now {
+                break;
+            }
+            inner.timers.pop();
+
+            if let Some((waker, _)) = inner.wakers.remove(&seq) {
+                waker.wake();
+            }
+        }
+
+        true
+    }
+
+    /// Register `waker` to be woken once the virtual clock reaches `deadline`.
+    ///
+    /// `timer` holds the sequence number of an earlier registration by the same future. If it
+    /// is still pending only its waker is replaced, so a future polled many times before its
+    /// deadline does not pile up timers, each waking it again.
+    fn wake_at(&self, deadline: Duration, waker: &Waker, timer: &mut Option<usize>) {
+        let mut inner = self.inner.borrow_mut();
+
+        if let Some(entry) = timer.and_then(|seq| inner.wakers.get_mut(&seq)) {
+            *entry = (waker.clone(), runtime::current_task());
+            return;
+        }
+
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        inner.timers.push(Reverse((deadline, seq)));
+        inner
+            .wakers
+            .insert(seq, (waker.clone(), runtime::current_task()));
+        *timer = Some(seq);
+    }
+
+    /// Forget the timer registered under `seq`, for a future dropped before its deadline.
+    fn cancel(&self, seq: usize) {
+        self.inner.borrow_mut().wakers.remove(&seq);
+    }
+
+    /// Ids of tasks waiting on the virtual clock
+    fn waiting_tasks(&self) -> HashSet<usize> {
+        let inner = self.inner.borrow();
+        inner
+            .wakers
+            .values()
+            .filter_map(|(_, task)| *task)
+            .collect()
+    }
+}
+
+/// One direction of a simulated connection.
+#[derive(Default)]
+struct Pipe {
+    /// Bytes sent, each readable once the virtual clock reaches the time it arrives at
+    segments: VecDeque<(Duration, Vec<u8>)>,
+    /// Arrival of the latest segment, later ones never overtake it
+    last_arrival: Duration,
+    /// Arrival of the end of the stream, once the writing end hung up
+    closed: Option<Duration>,
+    /// Reader waiting for anything to be sent
+    reader: Option<Waker>,
+    /// Set once the reading end hung up, writes fail from then on
+    reader_gone: bool,
+}
+
+impl Pipe {
+    /// Time `len` bytes sent now arrive at, behind whatever is in flight already.
+    fn arrival(&mut self, now: Duration, link: &Link, len: usize) -> Duration {
+        self.last_arrival = self
+            .last_arrival
+            .max(now + link.latency + link.transfer_time(len));
+        self.last_arrival
+    }
+
+    fn wake_reader(&mut self) {
+        if let Some(waker) = self.reader.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Both directions of a connection and the link they go over.
+struct Connection {
+    link: Link,
+    /// The link drops the request, i.e. whatever the client sends never arrives
+    dropped: bool,
+    /// Client to server, then server to client
+    pipes: [Pipe; 2],
+}
+
+/// End of a connection to a simulated endpoint, see [`connect`].
+///
+/// Bytes written arrive at the other end once the latency of the link, and the time it takes
+/// to transfer them, passed on the virtual clock. Dropping an end closes the connection: the
+/// other end reads the end of the stream, after the latency, and fails to write.
+///
+/// The connection itself is kept by the network installed on this thread, so the stream is
+/// `Send`, as the http client's futures are, but only works on the thread it was opened on.
+pub(crate) struct DuplexStream {
+    /// Key of the connection in `NetworkInner::conns`
+    conn: usize,
+    /// Index of the pipe this end reads from. It writes to the other one.
+    side: usize,
+    /// Our registration with the virtual clock, see `Network::wake_at`
+    timer: Option<usize>,
+}
+
+impl DuplexStream {
+    const SERVER: usize = 0;
+    const CLIENT: usize = 1;
+
+    /// Run `f` on our connection, and the network it is on. Fails with `NotConnected` on a
+    /// thread without it, or once the network dropped the connection.
+    fn with_conn<T>(
+        &self,
+        f: impl FnOnce(&Network, &mut Connection, Duration) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let net = CURRENT_NET
+            .with(|net| net.borrow().clone())
+            .ok_or(ErrorKind::NotConnected)?;
+
+        let mut inner = net.inner.borrow_mut();
+        let now = inner.now;
+        let mut conn = inner
+            .conns
+            .remove(&self.conn)
+            .ok_or(ErrorKind::NotConnected)?;
+        drop(inner);
+
+        // the connection is out of the network while `f` runs, which may register timers
+        let result = f(&net, &mut conn, now);
+        net.inner.borrow_mut().conns.insert(self.conn, conn);
+        result
+    }
+
+    /// Copy what arrived so far into `buf`. Fails with `WouldBlock` if nothing did yet, like
+    /// a non-blocking socket.
+    pub(crate) fn try_read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.with_conn(|_, conn, now| {
+            let pipe = &mut conn.pipes[self.side];
+            match pipe.segments.front_mut() {
+                Some((arrival, bytes)) if *arrival <= now => {
+                    let n = buf.len().min(bytes.len());
+                    buf[..n].copy_from_slice(&bytes[..n]);
+                    bytes.drain(..n);
+                    if bytes.is_empty() {
+                        pipe.segments.pop_front();
+                    }
+                    Ok(n)
+                }
+                None if pipe.closed.is_some_and(|at| at <= now) => Ok(0),
+                _ => Err(ErrorKind::WouldBlock.into()),
+            }
+        })
+    }
+
+    /// Called once a read would block: wake us when the next segment, or the end of the
+    /// stream, arrives, or else once the other end sends anything.
+    fn wait_for_read(&mut self, waker: &Waker) -> io::Result<()> {
+        let mut timer = self.timer;
+        let result = self.with_conn(|net, conn, _| {
+            let pipe = &mut conn.pipes[self.side];
+            let arrival = match pipe.segments.front() {
+                Some((arrival, _)) => Some(*arrival),
+                None => pipe.closed,
+            };
+
+            match arrival {
+                Some(arrival) => net.wake_at(arrival, waker, &mut timer),
+                None => pipe.reader = Some(waker.clone()),
+            }
+            Ok(())
+        });
+        self.timer = timer;
+        result
+    }
+
+    /// Send `buf` to the other end, taking all of it, as the link has no buffer limits.
+    pub(crate) fn try_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.with_conn(|_, conn, now| {
+            let pipe = &mut conn.pipes[1 - self.side];
+            if pipe.reader_gone {
+                return Err(ErrorKind::BrokenPipe.into());
+            }
+            if conn.dropped && self.side == Self::CLIENT {
+                return Ok(buf.len());
+            }
+
+            let arrival = pipe.arrival(now, &conn.link, buf.len());
+            pipe.segments.push_back((arrival, buf.to_vec()));
+            pipe.wake_reader();
+            Ok(buf.len())
+        })
+    }
+}
+
+impl AsyncRead for DuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        match this.try_read(buf) {
+            Err(e) if e.kind() == ErrorKind::WouldBlock => match this.wait_for_read(cx.waker()) {
+                Ok(()) => Poll::Pending,
+                Err(e) => Poll::Ready(Err(e)),
+            },
+            result => Poll::Ready(result),
+        }
+    }
+}
+
+impl AsyncWrite for DuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().try_write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Hang up: the other end reads the end of the stream once it arrives, even over a link that
+/// drops the request, and its writes fail. The network forgets the connection once both ends
+/// hung up.
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        let Some(net) = CURRENT_NET.with(|net| net.borrow().clone()) else {
+            return;
+        };
+        if let Some(seq) = self.timer {
+            net.cancel(seq);
+        }
+
+        let mut inner = net.inner.borrow_mut();
+        let now = inner.now;
+        let Some(conn) = inner.conns.get_mut(&self.conn) else {
+            return;
+        };
+
+        let pipe = &mut conn.pipes[self.side];
+        pipe.reader_gone = true;
+        pipe.segments.clear();
+
+        let pipe = &mut conn.pipes[1 - self.side];
+        let arrival = pipe.arrival(now, &conn.link, 0);
+        pipe.closed = Some(arrival);
+        let waker = pipe.reader.take();
+
+        if conn.pipes.iter().all(|pipe| pipe.reader_gone) {
+            inner.conns.remove(&self.conn);
+        }
+        drop(inner);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// The delayserver at the far end of a simulated connection: answers `GET /<delay ms>/<msg>`
+/// with `<msg>` once the delay passed on the virtual clock, then hangs up.
+async fn serve(mut stream: DuplexStream, net: Network) -> io::Result<()> {
+    // read up to the end of the request head, a body is ignored
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut buf).await? {
+            // hung up before sending all of it, e.g. as the link dropped the request
+            0 => return Ok(()),
+            n => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+    let (delay, msg) = parse_delay_path(path);
+
+    net.sleep(delay).await;
+    stream.write_all(delay_response(msg).as_bytes()).await
+}
+
+/// Leaf future waiting on the virtual clock, see [`Network::sleep`].
+struct Sleep {
+    net: Network,
+    duration: Duration,
+    /// Set on first poll
+    deadline: Option<Duration>,
+    /// Our registration with the virtual clock, see `Network::wake_at`
+    timer: Option<usize>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let now = self.net.now();
+        let duration = self.duration;
+        let deadline = *self.deadline.get_or_insert(now + duration);
+
+        if now >= deadline {
+            return Poll::Ready(());
+        }
+
+        let net = self.net.clone();
+        net.wake_at(deadline, cx.waker(), &mut self.timer);
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(seq) = self.timer {
+            self.net.cancel(seq);
+        }
+    }
+}
+
+/// Future returned by [`Network::timeout`].
+pub struct Timeout<F> {
+    future: Pin<Box<F>>,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = io::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if let Poll::Ready(output) = self.future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+
+        match Pin::new(&mut self.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(ErrorKind::TimedOut.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Split a delayserver style path `/<delay ms>/<message>` into its delay and message.
+fn parse_delay_path(path: &str) -> (Duration, &str) {
+    let mut parts = path.trim_start_matches('/').splitn(2, '/');
+    let delay = parts
+        .next()
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or_default();
+    let msg = parts.next().unwrap_or_default();
+
+    (delay, msg)
+}
+
+/// Build the raw response the delayserver would send for `msg`.
+fn delay_response(msg: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\n\
+         content-length: {}\r\n\
+         connection: close\r\n\
+         \r\n\
+         {msg}",
+        msg.len()
+    )
+}
+
+/// Small deterministic PRNG, so we do not pull in a dependency for dropping packets.
+#[derive(Debug, Clone)]
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    pub(crate) fn new(seed: u64) -> Self {
+        // state must never be zero
+        Self(seed.max(1))
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http::{Http, HttpError},
+        runtime::{current_task, init_for_tests, spawn_local},
+    };
+
+    const ADDR: &str = "sim:8080";
+
+    /// For any assignment of delays, requests complete in order of their total simulated
+    /// time, and the virtual clock ends on the slowest one.
+    #[test]
+    fn completion_order_follows_total_delay() {
+        for seed in 1..50 {
+            let mut rng = XorShift::new(seed);
+            let latency = Duration::from_millis(rng.next_u64() % 50);
+            let delays: Vec<u64> = (0..8).map(|_| rng.next_u64() % 1000).collect();
+
+            let net = Network::new(seed);
+            net.add_endpoint(ADDR, Link::new(latency));
+            net.install();
+
+            let order = Rc::new(RefCell::new(Vec::new()));
+
+            let tasks = (net.clone(), delays.clone(), order.clone());
+            init_for_tests().block_on(async move {
+                let (net, delays, order) = tasks;
+                for (i, delay) in delays.into_iter().enumerate() {
+                    let (net, order) = (net.clone(), order.clone());
+                    spawn_local(async move {
+                        let res = Http::with_addr(ADDR)
+                            .get(&format!("/{delay}/req-{i}"))
+                            .await;
+                        assert_eq!(res.unwrap().body(), format!("req-{i}"));
+                        order.borrow_mut().push((net.now(), delay));
+                    });
+                }
+            });
+
+            let order = order.borrow();
+            assert_eq!(order.len(), delays.len());
+            assert!(order.windows(2).all(|w| w[0].1 <= w[1].1), "seed {seed}");
+
+            let slowest = Duration::from_millis(*delays.iter().max().unwrap()) + latency * 2;
+            assert_eq!(net.now(), slowest);
+            assert!(
+                net.inner.borrow().conns.is_empty(),
+                "connections are closed"
+            );
+        }
+    }
+
+    #[test]
+    fn dropped_requests_time_out() {
+        let net = Network::new(7);
+        net.add_endpoint(ADDR, Link::new(Duration::from_millis(1)).drop_rate(1.0));
+        net.install();
+
+        let handle = net.clone();
+        init_for_tests().block_on(async move {
+            let request = Http::with_addr(ADDR).get("/10/dropped");
+            let err = handle.timeout(Duration::from_secs(1), request).await;
+            assert_eq!(err.unwrap_err().kind(), ErrorKind::TimedOut);
+        });
+        // the server saw the client hang up, rather than waiting on the request forever
+        assert_eq!(net.now(), Duration::from_millis(1001));
+        assert!(net.inner.borrow().conns.is_empty());
+    }
+
+    #[test]
+    fn timeout_resolves_first_and_cancels_the_other_timer() {
+        let net = Network::new(1);
+        net.add_endpoint(ADDR, Link::new(Duration::from_millis(1)));
+        net.install();
+
+        let handle = net.clone();
+        init_for_tests().block_on(async move {
+            let client = Http::with_addr(ADDR);
+            let task = current_task().unwrap();
+
+            let fast = client.get("/10/fast");
+            let res = handle.timeout(Duration::from_millis(500), fast).await;
+            assert_eq!(res.unwrap().unwrap().body(), "fast");
+            assert_eq!(handle.now(), Duration::from_millis(12));
+            // the timeout's own timer went away with it
+            assert!(!handle.waiting_tasks().contains(&task));
+
+            let slow = client.get("/1000/slow");
+            let err = handle.timeout(Duration::from_millis(50), slow).await;
+            assert_eq!(err.unwrap_err().kind(), ErrorKind::TimedOut);
+            assert_eq!(handle.now(), Duration::from_millis(62));
+            // so did the request's, only its server still sleeps
+            assert!(!handle.waiting_tasks().contains(&task));
+        });
+    }
+
+    #[test]
+    fn requests_to_unknown_endpoints_are_not_simulated() {
+        let net = Network::new(1);
+        net.add_endpoint(ADDR, Link::new(Duration::from_millis(1)));
+        net.install();
+
+        init_for_tests().block_on(async move {
+            // goes out over TCP, where nothing listens on the port
+            let addr = std::net::TcpListener::bind("127.0.0.1:0")
+                .and_then(|listener| listener.local_addr())
+                .unwrap();
+            let err = Http::with_addr(&addr.to_string()).get("/0/tcp").await;
+            assert!(matches!(err, Err(HttpError::Connect(_))));
+        });
+    }
+}
@@ -0,0 +1,290 @@
+//! Accept-time inspection of TLS connections.
+//!
+//! Lets a server find out which hostname a client wants (the SNI extension of the TLS
+//! ClientHello) before any handshake takes place, so the connection can be routed to a
+//! handler or certificate for that host. The ClientHello is read with `MSG_PEEK`, so the bytes
+//! stay in the socket buffer for whatever completes the handshake afterwards.
+//!
+//! With the `tls` feature, [`SniAcceptor`] does both for a server: it completes the handshake
+//! with the certificate routed to by the hostname, see the `sni_server` example.
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc8446#section-4.1.2 and
+//! https://datatracker.ietf.org/doc/html/rfc6066#section-3
+use std::{collections::HashMap, io};
+
+use crate::runtime::PooledBuffer;
+
+#[cfg(feature = "tls")]
+mod server;
+
+#[cfg(feature = "tls")]
+pub use server::{SniAcceptor, TlsServerStream};
+
+const RECORD_HANDSHAKE: u8 = 0x16;
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+const NAME_TYPE_HOST_NAME: u8 = 0x00;
+
+/// Largest TLS record we are prepared to peek at
+const MAX_RECORD: usize = 5 + (1 << 14);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SniError {
+    /// Not enough bytes have arrived yet, peek again once the socket is readable
+    Incomplete,
+    /// The bytes are not a TLS ClientHello
+    NotClientHello,
+    /// The ClientHello is malformed
+    Invalid,
+}
+
+/// Extract the SNI hostname from the start of a TLS connection.
+///
+/// Returns `Ok(None)` for a valid ClientHello that does not carry a hostname.
+pub fn parse_sni(buf: &[u8]) -> Result<Option<String>, SniError> {
+    let mut record = Reader::new(buf);
+
+    if record.u8()? != RECORD_HANDSHAKE {
+        return Err(SniError::NotClientHello);
+    }
+    record.skip(2)?; // legacy record version
+    let len = record.u16()? as usize;
+    let mut hello = record.take(len)?;
+
+    if hello.u8()? != HANDSHAKE_CLIENT_HELLO {
+        return Err(SniError::NotClientHello);
+    }
+    let len = hello.u24()?;
+    // We only look at the first record. A ClientHello spanning several records is possible,
+    // but does not happen with the hello sizes clients send in practice.
+    let mut hello = hello.take(len).map_err(|_| SniError::Invalid)?;
+
+    hello.skip(2 + 32)?; // legacy version + random
+    let len = hello.u8()? as usize;
+    hello.skip(len)?; // session id
+    let len = hello.u16()? as usize;
+    hello.skip(len)?; // cipher suites
+    let len = hello.u8()? as usize;
+    hello.skip(len)?; // compression methods
+
+    if hello.is_empty() {
+        // no extensions at all
+        return Ok(None);
+    }
+
+    let len = hello.u16()? as usize;
+    let mut extensions = hello.take(len)?;
+
+    while !extensions.is_empty() {
+        let kind = extensions.u16()?;
+        let len = extensions.u16()? as usize;
+        let mut extension = extensions.take(len)?;
+
+        if kind != EXTENSION_SERVER_NAME {
+            continue;
+        }
+
+        let len = extension.u16()? as usize;
+        let mut names = extension.take(len)?;
+
+        while !names.is_empty() {
+            let name_type = names.u8()?;
+            let len = names.u16()? as usize;
+            let name = names.take(len)?;
+
+            if name_type == NAME_TYPE_HOST_NAME {
+                let name = std::str::from_utf8(name.buf).map_err(|_| SniError::Invalid)?;
+                return Ok(Some(name.to_ascii_lowercase()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Peek at the ClientHello on `stream` without consuming it and extract the SNI hostname.
+///
+/// Returns `Err(SniError::Incomplete)` in the inner result if the client has not sent the full
+/// ClientHello yet. The caller should wait for the next readable event and try again, see
+/// [`TcpStream::peek_sni`](crate::net::TcpStream::peek_sni). Fails with `UnexpectedEof` if the
+/// client closed before that.
+pub fn peek_sni(stream: &mio::net::TcpStream) -> io::Result<Result<Option<String>, SniError>> {
+    // peeked again on every readable event until the hello is complete, so pooled
+    let mut buf = PooledBuffer::zeroed(MAX_RECORD);
+    let n = stream.peek(&mut buf)?;
+    if n == 0 {
+        // closed before the hello was complete, it is not going to be
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(parse_sni(&buf[..n]))
+}
+
+/// Maps SNI hostnames to routes, e.g. a handler or a certificate per host.
+///
+/// Hostnames either match exactly or via a leading wildcard label, so `*.example.com` matches
+/// `api.example.com` but not `example.com`. Connections without a (known) hostname use the
+/// default route if one is set.
+pub struct SniRouter<R> {
+    routes: HashMap<String, R>,
+    default: Option<R>,
+}
+
+impl<R> SniRouter<R> {
+    pub fn new() -> Self {
+        Self {
+            routes: HashMap::new(),
+            default: None,
+        }
+    }
+
+    pub fn route(mut self, hostname: &str, route: R) -> Self {
+        self.routes.insert(hostname.to_ascii_lowercase(), route);
+        self
+    }
+
+    pub fn default_route(mut self, route: R) -> Self {
+        self.default = Some(route);
+        self
+    }
+
+    /// Find the route for a hostname taken from [`parse_sni`] or [`peek_sni`].
+    pub fn lookup(&self, hostname: Option<&str>) -> Option<&R> {
+        let Some(hostname) = hostname else {
+            return self.default.as_ref();
+        };
+
+        let wildcard = hostname
+            .split_once('.')
+            .and_then(|(_, parent)| self.routes.get(&format!("*.{parent}")));
+
+        self.routes
+            .get(hostname)
+            .or(wildcard)
+            .or(self.default.as_ref())
+    }
+}
+
+impl<R> Default for SniRouter<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cursor over a byte slice that reports running out of bytes as `SniError::Incomplete`.
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<Reader<'a>, SniError> {
+        if self.buf.len() < len {
+            return Err(SniError::Incomplete);
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(Reader { buf: head })
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), SniError> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, SniError> {
+        Ok(self.take(1)?.buf[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SniError> {
+        let b = self.take(2)?.buf;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Result<usize, SniError> {
+        let b = self.take(3)?.buf;
+        Ok(u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a minimal ClientHello record carrying `hostname` in the SNI extension.
+    fn client_hello(hostname: &str) -> Vec<u8> {
+        let name = hostname.as_bytes();
+
+        let mut sni = Vec::new();
+        sni.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+        sni.push(NAME_TYPE_HOST_NAME);
+        sni.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        sni.extend_from_slice(name);
+
+        let mut extensions = Vec::new();
+        // an unrelated extension first (supported_versions)
+        extensions.extend_from_slice(&[0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04]);
+        extensions.extend_from_slice(&EXTENSION_SERVER_NAME.to_be_bytes());
+        extensions.extend_from_slice(&(sni.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&sni);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0u8; 32]); // random
+        body.push(0); // session id
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // one cipher suite
+        body.extend_from_slice(&[0x01, 0x00]); // null compression
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![HANDSHAKE_CLIENT_HELLO];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+
+        let mut record = vec![RECORD_HANDSHAKE, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    #[test]
+    fn extracts_hostname() {
+        let hello = client_hello("API.example.com");
+        assert_eq!(parse_sni(&hello), Ok(Some("api.example.com".to_string())));
+    }
+
+    #[test]
+    fn partial_hello_is_incomplete() {
+        let hello = client_hello("example.com");
+        assert_eq!(
+            parse_sni(&hello[..hello.len() - 1]),
+            Err(SniError::Incomplete)
+        );
+    }
+
+    #[test]
+    fn plain_http_is_not_client_hello() {
+        assert_eq!(
+            parse_sni(b"GET / HTTP/1.1\r\n"),
+            Err(SniError::NotClientHello)
+        );
+    }
+
+    #[test]
+    fn router_matches_exact_then_wildcard_then_default() {
+        let router = SniRouter::new()
+            .route("example.com", "apex")
+            .route("*.example.com", "wildcard")
+            .default_route("default");
+
+        assert_eq!(router.lookup(Some("example.com")), Some(&"apex"));
+        assert_eq!(router.lookup(Some("api.example.com")), Some(&"wildcard"));
+        assert_eq!(router.lookup(Some("other.org")), Some(&"default"));
+        assert_eq!(router.lookup(None), Some(&"default"));
+    }
+}
@@ -0,0 +1,248 @@
+//! Server side of TLS, with a certificate per hostname. Only built with the `tls` feature.
+//!
+//! [`SniAcceptor`] peeks at the ClientHello of an accepted connection, see
+//! [`TcpStream::peek_sni`], looks up the rustls config for the hostname the client asks for,
+//! and completes the handshake with it. The hostname is handed back along with the stream, so
+//! the server can pick a handler for the host as well.
+//!
+//! Unlike the TLS stream of the [`http`](crate::http) client, [`TlsServerStream`] is driven by
+//! the async methods of [`TcpStream`]: records rustls wants to send are written out with
+//! [`TcpStream::write_all`], and records from the client are read with [`TcpStream::read`].
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use rustls::{ServerConfig, ServerConnection};
+
+use super::SniRouter;
+use crate::{net::TcpStream, runtime::PooledBuffer};
+
+/// Largest read of records from the socket at once
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Completes TLS handshakes with the config routed to by the hostname the client asks for.
+pub struct SniAcceptor {
+    configs: SniRouter<Arc<ServerConfig>>,
+}
+
+impl SniAcceptor {
+    pub fn new(configs: SniRouter<Arc<ServerConfig>>) -> Self {
+        Self { configs }
+    }
+
+    /// Complete the handshake on an accepted connection, resolving to the hostname the client
+    /// asked for along with the stream. Fails with `NotFound` if there is no config for the
+    /// hostname, and no default one either.
+    pub async fn accept(
+        &self,
+        mut tcp: TcpStream,
+    ) -> io::Result<(Option<String>, TlsServerStream)> {
+        let hostname = tcp.peek_sni().await?;
+        let Some(config) = self.configs.lookup(hostname.as_deref()) else {
+            let msg = format!("no certificate for {hostname:?}");
+            return Err(io::Error::new(ErrorKind::NotFound, msg));
+        };
+
+        let mut conn = ServerConnection::new(config.clone()).map_err(invalid_data)?;
+        // what is written is in memory anyway, do not refuse part of it
+        conn.set_buffer_limit(None);
+
+        let mut stream = TlsServerStream { tcp, conn };
+        loop {
+            stream.send_records().await?;
+            if !stream.conn.is_handshaking() {
+                break;
+            }
+            if stream.receive_records().await? == 0 {
+                let msg = "client closed during the handshake";
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, msg));
+            }
+        }
+
+        Ok((hostname, stream))
+    }
+}
+
+/// Server side of a TLS connection, handed out by [`SniAcceptor::accept`].
+pub struct TlsServerStream {
+    tcp: TcpStream,
+    conn: ServerConnection,
+}
+
+impl TlsServerStream {
+    /// Read plaintext into `buf`, resolving to the number of bytes read. 0 means the client
+    /// closed after a close_notify alert. A client that closes without one fails with
+    /// `UnexpectedEof`, as the data may have been cut short.
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.conn.reader().read(buf) {
+                Ok(n) => return Ok(n),
+                // nothing to hand out until more records arrive
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+
+            self.receive_records().await?;
+            // answers to the records just read, e.g. a key update
+            self.send_records().await?;
+        }
+    }
+
+    /// Write all of `buf`, waiting for the socket to take the records carrying it.
+    pub async fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.conn.writer().write_all(buf)?;
+        self.send_records().await
+    }
+
+    /// Send a close_notify alert and shut down the write half, letting the client know we are
+    /// done sending.
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.conn.send_close_notify();
+        self.send_records().await?;
+        self.tcp.shutdown_write()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.peer_addr()
+    }
+
+    /// Send buffered records until there are none left.
+    async fn send_records(&mut self) -> io::Result<()> {
+        while self.conn.wants_write() {
+            let mut records = Vec::new();
+            self.conn.write_tls(&mut records)?;
+            self.tcp.write_all(&records).await?;
+        }
+        Ok(())
+    }
+
+    /// Read records from the socket and process them, resolving to the number of bytes read,
+    /// 0 once the client closed.
+    async fn receive_records(&mut self) -> io::Result<usize> {
+        let mut buf = PooledBuffer::zeroed(READ_CHUNK);
+        let n = self.tcp.read(&mut buf).await?;
+
+        // an empty read tells rustls the client closed
+        let mut records = &buf[..n];
+        loop {
+            self.conn.read_tls(&mut records)?;
+            if let Err(e) = self.conn.process_new_packets() {
+                // send the alert telling the client why we give up
+                let _ = self.send_records().await;
+                return Err(invalid_data(e));
+            }
+            if records.is_empty() {
+                return Ok(n);
+            }
+        }
+    }
+}
+
+fn invalid_data(err: rustls::Error) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::{self, JoinHandle};
+
+    use rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName},
+        ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+    };
+
+    use super::*;
+    use crate::{net::TcpListener, runtime};
+
+    /// Signs `ALPHA_CERT`, valid for `alpha.test`, and `BETA_CERT`, valid for `*.beta.test`
+    const CA_CERT: &[u8] = include_bytes!("testdata/ca.der");
+    const ALPHA_CERT: &[u8] = include_bytes!("testdata/alpha.der");
+    const ALPHA_KEY: &[u8] = include_bytes!("testdata/alpha.key.der");
+    const BETA_CERT: &[u8] = include_bytes!("testdata/beta.der");
+    const BETA_KEY: &[u8] = include_bytes!("testdata/beta.key.der");
+
+    fn server_config(cert: &'static [u8], key: &'static [u8]) -> Arc<ServerConfig> {
+        let key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(key));
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![CertificateDer::from(cert)], key)
+            .unwrap();
+        Arc::new(config)
+    }
+
+    fn acceptor() -> SniAcceptor {
+        SniAcceptor::new(
+            SniRouter::new()
+                .route("alpha.test", server_config(ALPHA_CERT, ALPHA_KEY))
+                .route("*.beta.test", server_config(BETA_CERT, BETA_KEY)),
+        )
+    }
+
+    /// Connect to `addr` asking for `hostname`, resolving to the certificate the server
+    /// presented and what it sent.
+    fn connect(addr: SocketAddr, hostname: &str) -> JoinHandle<io::Result<(Vec<u8>, String)>> {
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from(CA_CERT)).unwrap();
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let name = ServerName::try_from(hostname.to_string()).unwrap();
+
+        thread::spawn(move || {
+            let conn = ClientConnection::new(Arc::new(config), name).unwrap();
+            let socket = std::net::TcpStream::connect(addr)?;
+            let mut stream = StreamOwned::new(conn, socket);
+
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply)?;
+            let cert = stream.conn.peer_certificates().unwrap()[0].to_vec();
+            Ok((cert, reply))
+        })
+    }
+
+    #[test]
+    fn picks_the_certificate_by_hostname() {
+        let mut executor = runtime::init_for_tests();
+        let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let clients = [connect(addr, "alpha.test"), connect(addr, "api.beta.test")];
+
+        let (acceptor, count) = (acceptor(), clients.len());
+        executor.block_on(async move {
+            for _ in 0..count {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let (hostname, mut stream) = acceptor.accept(tcp).await.unwrap();
+                let greeting = format!("hello {}", hostname.unwrap());
+                stream.write_all(greeting.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+
+        let [alpha, beta] = clients.map(|client| client.join().unwrap().unwrap());
+        assert_eq!(alpha, (ALPHA_CERT.to_vec(), "hello alpha.test".to_string()));
+        assert_eq!(
+            beta,
+            (BETA_CERT.to_vec(), "hello api.beta.test".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_hostnames_are_refused() {
+        let mut executor = runtime::init_for_tests();
+        let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = connect(listener.local_addr().unwrap(), "gamma.test");
+
+        let acceptor = acceptor();
+        executor.block_on(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let Err(e) = acceptor.accept(tcp).await else {
+                panic!("handshake for an unknown hostname");
+            };
+            assert_eq!(e.kind(), ErrorKind::NotFound);
+        });
+
+        assert!(client.join().unwrap().is_err());
+    }
+}
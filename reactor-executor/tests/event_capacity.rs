@@ -0,0 +1,54 @@
+//! Tests of an event loop whose event buffer is smaller than the events ready at once, see
+//! `Builder::event_capacity`. The reactor is started once per process, hence a test binary of
+//! its own.
+use std::{thread, time::Duration};
+
+use reactor_executor::{
+    future::join_all,
+    net::UdpSocket,
+    runtime::{self, Builder},
+};
+
+const CAPACITY: usize = 4;
+
+#[test]
+fn events_beyond_the_capacity_are_taken_without_blocking() {
+    let mut executor = Builder::new().event_capacity(CAPACITY).build();
+    let before = runtime::reactor().dispatch_stats();
+
+    let mut sockets: Vec<_> = (0..32)
+        .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+        .collect();
+    let addrs: Vec<_> = sockets
+        .iter()
+        .map(|socket| socket.local_addr().unwrap())
+        .collect();
+
+    // once all sockets are waiting, so they become readable at about the same time
+    let sender = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        for addr in addrs {
+            sender.send_to(b"ping", addr).unwrap();
+        }
+    });
+
+    executor.block_on(async move {
+        let received = join_all(sockets.iter_mut().map(|socket| async move {
+            let mut buf = [0u8; 8];
+            let (n, _) = socket.recv_from(&mut buf).await.unwrap();
+            buf[..n].to_vec()
+        }))
+        .await;
+        assert!(received.iter().all(|datagram| datagram == b"ping"));
+    });
+    sender.join().unwrap();
+
+    let after = runtime::reactor().dispatch_stats();
+    assert!(after.full_batches > before.full_batches, "{after:?}");
+    // no poll returned more than fits, i.e. 4 events, in the bucket of 4 to 7
+    assert!(
+        after.batch_sizes[3..].iter().all(|&polls| polls == 0),
+        "{after:?}"
+    );
+}
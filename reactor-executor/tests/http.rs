@@ -0,0 +1,133 @@
+//! End to end tests of the HTTP client and the `reactor-executor` binary, against the
+//! in-process [`MockServer`] rather than the delayserver.
+use std::{
+    io::ErrorKind,
+    process::Command,
+    sync::Once,
+    time::{Duration, Instant},
+};
+
+use reactor_executor::{
+    future::join_all,
+    http::HttpError,
+    join,
+    mock::{MockServer, Reply},
+    runtime::{self, Executor},
+};
+
+/// The reactor can only be started once per process, hence once for all tests.
+fn executor() -> Executor {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        runtime::init();
+    });
+    Executor::new()
+}
+
+#[test]
+fn single_request() {
+    let server = MockServer::start();
+    let client = server.client();
+
+    executor().block_on(async move {
+        let response = client.get("/0/HelloWorld").await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body(), "HelloWorld");
+    });
+
+    assert_eq!(server.requests(), ["GET /0/HelloWorld HTTP/1.1"]);
+}
+
+#[test]
+fn concurrent_requests_overlap() {
+    let server = MockServer::start();
+    let client = server.client();
+    let start = Instant::now();
+
+    executor().block_on(async move {
+        let requests = (1..=5).map(|i| client.get(&format!("/200/Hello{i}")));
+        let bodies: Vec<String> = join_all(requests)
+            .await
+            .into_iter()
+            .map(|response| response.unwrap().body().to_string())
+            .collect();
+        assert_eq!(bodies, ["Hello1", "Hello2", "Hello3", "Hello4", "Hello5"]);
+    });
+
+    // one after the other they would take a second
+    assert!(start.elapsed() < Duration::from_millis(800));
+    assert_eq!(server.requests().len(), 5);
+}
+
+#[test]
+fn slow_response_is_waited_for() {
+    let server = MockServer::start();
+    server.route(
+        "/slow",
+        Reply::ok("finally")
+            .status(503)
+            .delay(Duration::from_millis(300)),
+    );
+    let client = server.client();
+    let start = Instant::now();
+
+    executor().block_on(async move {
+        let (slow, fast) = join!(client.get("/slow"), client.get("/0/fast"));
+        assert_eq!(fast.unwrap().body(), "fast");
+        let slow = slow.unwrap();
+        assert_eq!(slow.status(), 503);
+        assert_eq!(slow.body(), "finally");
+    });
+
+    assert!(start.elapsed() >= Duration::from_millis(300));
+}
+
+#[test]
+fn refused_connection_is_an_error() {
+    // nothing listens on its port once the server is dropped
+    let addr = MockServer::start().addr();
+
+    executor().block_on(async move {
+        let client = reactor_executor::http::Http::with_addr(&addr);
+        match client.get("/0/hello").await {
+            Err(HttpError::Connect(e)) => assert_eq!(e.kind(), ErrorKind::ConnectionRefused),
+            other => panic!("expected a connect error, got {other:?}"),
+        }
+    });
+}
+
+#[test]
+fn connection_closed_without_response_is_an_error() {
+    let server = MockServer::start();
+    server.route("/broken", Reply::close());
+    let client = server.client();
+
+    executor().block_on(async move {
+        let err = client.get("/broken").await.unwrap_err();
+        assert!(matches!(err, HttpError::Parse(_)), "got {err}");
+    });
+}
+
+/// The default run of the binary, with its requests sent to the mock server.
+#[test]
+fn binary_runs_against_mock_server() {
+    let server = MockServer::start();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_reactor-executor"))
+        .env("DELAYSERVER_ADDR", server.addr())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{stdout}");
+    for msg in [
+        "HelloAsyncAwait",
+        "HelloSpeculative",
+        "body chunk: HelloStreaming",
+    ] {
+        assert!(stdout.contains(msg), "{msg} missing from:\n{stdout}");
+    }
+    assert!(server
+        .requests()
+        .contains(&"POST /200/HelloPost HTTP/1.1".to_string()));
+}
@@ -0,0 +1,95 @@
+//! Tests of the runtime through the public API of the library, as the example binaries and
+//! the `prelude` crate use it: tasks, timers, channels and sockets driven by the reactor.
+use std::{
+    io::{Read, Write},
+    sync::Once,
+    thread,
+    time::{Duration, Instant},
+};
+
+use reactor_executor::{
+    future::join_all,
+    net::TcpListener,
+    runtime::{self, sleep, spawn, spawn_local, sync::mpsc, Executor},
+};
+
+/// The reactor can only be started once per process, hence once for all tests.
+fn executor() -> Executor {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        runtime::init();
+    });
+    Executor::new()
+}
+
+#[test]
+fn spawned_tasks_sleep_concurrently() {
+    let start = Instant::now();
+
+    executor().block_on(async {
+        let (tx, mut rx) = mpsc::channel();
+        for i in 0..5 {
+            let tx = tx.clone();
+            spawn(async move {
+                sleep(Duration::from_millis(100)).await;
+                tx.send(i).unwrap();
+            });
+        }
+        drop(tx);
+
+        let mut received = Vec::new();
+        while let Some(i) = rx.recv().await {
+            received.push(i);
+        }
+        received.sort();
+        assert_eq!(received, [0, 1, 2, 3, 4]);
+    });
+
+    // one after the other they would take half a second
+    assert!(start.elapsed() < Duration::from_millis(400));
+}
+
+#[test]
+fn join_all_resolves_in_order_of_the_futures() {
+    executor().block_on(async {
+        let delays = [30, 10, 20];
+        let slept = join_all(delays.map(|ms| async move {
+            sleep(Duration::from_millis(ms)).await;
+            ms
+        }))
+        .await;
+        assert_eq!(slept, delays);
+    });
+}
+
+#[test]
+fn tcp_echo_is_driven_by_the_reactor() {
+    let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || {
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream.write_all(b"ping").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        reply
+    });
+
+    executor().block_on(async move {
+        spawn_local(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 64];
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                stream.write_all(&buf[..n]).await.unwrap();
+            }
+        });
+    });
+
+    assert_eq!(client.join().unwrap(), "ping");
+}
@@ -0,0 +1,95 @@
+//! Tests of the single-threaded runtime, where the executor runs the reactor's event loop
+//! whenever it would park, see `Builder::single_threaded`. The reactor is started once per
+//! process, hence a test binary of its own.
+use std::{
+    fs,
+    io::{Read, Write},
+    sync::Once,
+    thread,
+    time::{Duration, Instant},
+};
+
+use reactor_executor::{
+    net::TcpListener,
+    runtime::{sleep, spawn_local, sync::mpsc, Builder, Executor},
+};
+
+fn executor() -> Executor {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        Builder::new().single_threaded().build();
+    });
+    Executor::new()
+}
+
+/// Names of the threads of this process.
+fn thread_names() -> Vec<String> {
+    fs::read_dir("/proc/self/task")
+        .unwrap()
+        .filter_map(|task| fs::read_to_string(task.ok()?.path().join("comm")).ok())
+        .map(|name| name.trim_end().to_string())
+        .collect()
+}
+
+#[test]
+fn io_is_dispatched_without_a_reactor_thread() {
+    let mut executor = executor();
+    let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || {
+        // give the executor time to block in the event loop first
+        thread::sleep(Duration::from_millis(20));
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream.write_all(b"ping").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        reply
+    });
+
+    executor.block_on(async move {
+        spawn_local(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 64];
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                stream.write_all(&buf[..n]).await.unwrap();
+            }
+        });
+    });
+
+    assert_eq!(client.join().unwrap(), "ping");
+    assert!(
+        !thread_names()
+            .iter()
+            .any(|name| name.starts_with("reactor")),
+        "reactor runs on a thread of its own: {:?}",
+        thread_names()
+    );
+}
+
+#[test]
+fn wakes_from_other_threads_interrupt_the_event_loop() {
+    let start = Instant::now();
+
+    executor().block_on(async {
+        let (tx, mut rx) = mpsc::channel();
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.send("from another thread").unwrap();
+        });
+
+        // the timer thread wakes this one up
+        sleep(Duration::from_millis(10)).await;
+        assert_eq!(rx.recv().await, Some("from another thread"));
+        sender.join().unwrap();
+    });
+
+    // rather than waiting on an event loop with nothing to report
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
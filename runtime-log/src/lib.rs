@@ -0,0 +1,261 @@
+//! Leveled diagnostics, in place of printing unconditionally. Shared by `reactor-executor`,
+//! whose runtime re-exports it as `runtime::log`, and `mini-mio`.
+//!
+//! Messages go to stderr, prefixed with their level and the module they come from, if their
+//! level is enabled for that module. The configuration is set once for the whole process via
+//! [`configure`], or else read from the `RUNTIME_LOG` environment variable the first time
+//! anything is logged, e.g.
+//!
+//! ```bash
+//! RUNTIME_LOG=warn,runtime::executor=debug,http=trace cargo run -p reactor-executor
+//! ```
+//!
+//! Modules are named by their path within their crate, and a setting for a module also covers
+//! the modules nested in it. Without any configuration only warnings and errors are shown.
+//!
+//! Messages are logged with the `error!`, `warn!`, `info!`, `debug!` and `trace!` macros,
+//! e.g. `runtime_log::debug!("...")`.
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        RwLock,
+    },
+};
+
+/// Environment variable the configuration is read from, see [`LogConfig::from_env`]
+const ENV_VAR: &str = "RUNTIME_LOG";
+/// Stored in [`MAX_LEVEL`] until the configuration is set
+const UNSET: u8 = u8::MAX;
+
+/// Configuration of the process, `None` until set
+static CONFIG: RwLock<Option<LogConfig>> = RwLock::new(None);
+/// Most verbose level enabled for any module, so messages more verbose than that are skipped
+/// without taking the lock.
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(UNSET);
+
+/// Severity of a message, from most to least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            "trace" => Some(Self::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Levels enabled per module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    /// Level of modules without a setting of their own
+    level: Level,
+    /// Module paths within their crate, e.g. `runtime::executor`, with their level
+    modules: Vec<(String, Level)>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self::new(Level::Warn)
+    }
+}
+
+impl LogConfig {
+    /// Enable messages up to `level` for every module.
+    pub fn new(level: Level) -> Self {
+        Self {
+            level,
+            modules: Vec::new(),
+        }
+    }
+
+    /// Enable messages up to `level` for `module` and the modules nested in it instead, e.g.
+    /// `http` or `runtime::executor`.
+    pub fn module(mut self, module: &str, level: Level) -> Self {
+        self.modules.push((module.to_string(), level));
+        self
+    }
+
+    /// Read from the `RUNTIME_LOG` environment variable, see [`LogConfig::parse`]. Defaults to
+    /// warnings and errors if it is not set.
+    pub fn from_env() -> Self {
+        std::env::var(ENV_VAR)
+            .map(|spec| Self::parse(&spec))
+            .unwrap_or_default()
+    }
+
+    /// Parse a comma separated list of a level for every module, and `module=level` pairs,
+    /// e.g. `warn,runtime::executor=debug`. Entries that do not parse are ignored.
+    pub fn parse(spec: &str) -> Self {
+        let mut config = Self::default();
+
+        for entry in spec.split(',').filter(|entry| !entry.trim().is_empty()) {
+            match entry.split_once('=') {
+                Some((module, level)) => {
+                    if let Some(level) = Level::parse(level) {
+                        config = config.module(module.trim(), level);
+                    }
+                }
+                None => {
+                    if let Some(level) = Level::parse(entry) {
+                        config.level = level;
+                    }
+                }
+            }
+        }
+
+        config
+    }
+
+    /// Most verbose level enabled for `module`, a path within its crate. The setting of the
+    /// most deeply nested module containing it wins.
+    fn level_for(&self, module: &str) -> Level {
+        self.modules
+            .iter()
+            .filter(|(name, _)| {
+                module == name
+                    || module
+                        .strip_prefix(name.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> Level {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, Level::max)
+    }
+}
+
+/// Replace the configuration of the process.
+pub fn configure(config: LogConfig) {
+    let max = config.max_level();
+    *CONFIG.write().unwrap() = Some(config);
+    MAX_LEVEL.store(max as u8, Ordering::Relaxed);
+}
+
+/// Whether messages at `level` from `module`, as given by `module_path!`, are shown.
+pub fn enabled(level: Level, module: &str) -> bool {
+    let max = MAX_LEVEL.load(Ordering::Relaxed);
+    if max == UNSET {
+        let mut config = CONFIG.write().unwrap();
+        // someone else may have set it in the meantime
+        if config.is_none() {
+            let from_env = LogConfig::from_env();
+            MAX_LEVEL.store(from_env.max_level() as u8, Ordering::Relaxed);
+            *config = Some(from_env);
+        }
+    } else if level as u8 > max {
+        return false;
+    }
+
+    // module paths start with the name of the crate
+    let module = module.split_once("::").map_or("", |(_, rest)| rest);
+    CONFIG
+        .read()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|config| level <= config.level_for(module))
+}
+
+#[doc(hidden)]
+pub fn emit(level: Level, module: &str, args: fmt::Arguments<'_>) {
+    if enabled(level, module) {
+        eprintln!("[{level} {module}] {args}");
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::emit($level, module_path!(), format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log!($crate::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log!($crate::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log!($crate::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log!($crate::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::log!($crate::Level::Trace, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_modules_inherit_the_closest_setting() {
+        let config = LogConfig::new(Level::Warn)
+            .module("runtime", Level::Info)
+            .module("runtime::executor", Level::Trace);
+
+        assert_eq!(config.level_for("http"), Level::Warn);
+        assert_eq!(config.level_for("runtime::reactor"), Level::Info);
+        assert_eq!(config.level_for("runtime::executor"), Level::Trace);
+        assert_eq!(config.level_for("runtime::executor::tests"), Level::Trace);
+        // a name sharing a prefix is a different module
+        assert_eq!(config.level_for("runtime_extra"), Level::Warn);
+        assert_eq!(config.max_level(), Level::Trace);
+    }
+
+    #[test]
+    fn parses_levels_and_module_pairs() {
+        let config = LogConfig::parse("error, http=DEBUG,fanout=bogus,,runtime::executor=trace");
+
+        assert_eq!(
+            config,
+            LogConfig::new(Level::Error)
+                .module("http", Level::Debug)
+                .module("runtime::executor", Level::Trace)
+        );
+        assert_eq!(LogConfig::parse(""), LogConfig::default());
+    }
+}
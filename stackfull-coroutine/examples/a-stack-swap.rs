@@ -0,0 +1,175 @@
+//! Switching to a new stack, and back again.
+//!
+//! Run `hello` on a stack of its own, which hands control back to `main` after every line it
+//! prints. `main` switches to it again until it is done, so execution ping-pongs between the
+//! two stacks:
+//!
+//! - [`switch_to`] saves the caller's context in the coroutine and loads the coroutine's.
+//! - [`switch_back`] saves the coroutine's context and loads the caller's again.
+//!
+//! A minimal symmetric coroutine: the same two switches are what the runtime in `main.rs`
+//! builds its threads on.
+use std::{
+    arch::{asm, naked_asm},
+    cell::Cell,
+};
+
+const STACK_SIZE: usize = 1024 * 64; // 64 KB, plenty for a few println!s
+
+thread_local! {
+    /// Coroutine currently running, used by `switch_back`. Null while on the main stack.
+    static CURRENT: Cell<*mut Coroutine> = const { Cell::new(std::ptr::null_mut()) };
+}
+
+/// Callee saved registers, see `ThreadContext` in `main.rs`.
+#[derive(Debug, Default)]
+#[repr(C)]
+struct ThreadContext {
+    rsp: u64,
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    rbx: u64,
+    rbp: u64,
+}
+
+/// A function running on a stack of its own
+struct Coroutine {
+    stack: Vec<u8>,
+    /// Context to resume the coroutine with
+    ctx: ThreadContext,
+    /// Context of whoever switched to the coroutine, resumed by `switch_back`
+    caller: ThreadContext,
+    finished: bool,
+}
+
+impl Coroutine {
+    fn new(f: fn()) -> Self {
+        let mut coroutine = Coroutine {
+            stack: vec![0_u8; STACK_SIZE],
+            ctx: ThreadContext::default(),
+            caller: ThreadContext::default(),
+            finished: false,
+        };
+
+        unsafe {
+            let bottom = coroutine.stack.as_mut_ptr().add(STACK_SIZE);
+            // ensure memory segment is 16byte aligned
+            let bottom = (bottom as usize & !15) as *mut u8;
+
+            // same layout as `Scheduler::spawn` in `main.rs`: `ret` into `f`, which returns
+            // into `skip`, which returns into `guard`, keeping the stack aligned for both
+            std::ptr::write(bottom.offset(-16) as *mut u64, guard as *const () as u64);
+            std::ptr::write(bottom.offset(-24) as *mut u64, skip as *const () as u64);
+            std::ptr::write(bottom.offset(-32) as *mut u64, f as usize as u64);
+
+            coroutine.ctx.rsp = bottom.offset(-32) as u64;
+        }
+
+        coroutine
+    }
+}
+
+/// Run `coroutine` until it switches back or finishes.
+fn switch_to(coroutine: &mut Coroutine) {
+    assert!(!coroutine.finished, "switched to a finished coroutine");
+
+    let previous = CURRENT.replace(coroutine);
+
+    unsafe {
+        let old_ctx: *mut ThreadContext = &mut coroutine.caller;
+        let new_ctx: *const ThreadContext = &coroutine.ctx;
+        // see `Scheduler::t_yield` in `main.rs` on why `clobber_abi("C")` is needed
+        asm!(
+            "call switch",
+            in("rdi") old_ctx,
+            in("rsi") new_ctx,
+            clobber_abi("C")
+        );
+    }
+
+    // resumed here once the coroutine switched back or finished
+    CURRENT.set(previous);
+}
+
+/// Hand control back to whoever switched to the running coroutine. Returns once it is
+/// switched to again.
+///
+/// Panics when called on the main stack.
+fn switch_back() {
+    let coroutine = CURRENT.get();
+    assert!(
+        !coroutine.is_null(),
+        "switch_back called outside of a coroutine"
+    );
+
+    unsafe {
+        let old_ctx: *mut ThreadContext = &mut (*coroutine).ctx;
+        let new_ctx: *const ThreadContext = &(*coroutine).caller;
+        asm!(
+            "call switch",
+            in("rdi") old_ctx,
+            in("rsi") new_ctx,
+            clobber_abi("C")
+        );
+    }
+}
+
+/// Returned into once the coroutine's function returns. The coroutine is never resumed, so
+/// this switches back for good.
+fn guard() {
+    unsafe { (*CURRENT.get()).finished = true };
+    switch_back();
+    unreachable!("finished coroutine was resumed");
+}
+
+/// Single instruction to skip to next instruction
+#[unsafe(naked)]
+unsafe extern "C" fn skip() {
+    naked_asm!("ret");
+}
+
+// rdi = pointer into 'old' thread context
+// rsi = pointer into 'new' thread context
+//
+// same as `switch` in `main.rs`
+#[unsafe(naked)]
+#[no_mangle]
+#[cfg_attr(target_os = "macos", export_name = "\x01switch")] // see: How-to-MacOS-M.md for explanation
+unsafe extern "C" fn switch() {
+    naked_asm!(
+        "mov [rdi + 0x00], rsp",
+        "mov [rdi + 0x08], r15",
+        "mov [rdi + 0x10], r14",
+        "mov [rdi + 0x18], r13",
+        "mov [rdi + 0x20], r12",
+        "mov [rdi + 0x28], rbx",
+        "mov [rdi + 0x30], rbp",
+        "mov rsp, [rsi + 0x00]",
+        "mov r15, [rsi + 0x08]",
+        "mov r14, [rsi + 0x10]",
+        "mov r13, [rsi + 0x18]",
+        "mov r12, [rsi + 0x20]",
+        "mov rbx, [rsi + 0x28]",
+        "mov rbp, [rsi + 0x30]",
+        "ret"
+    );
+}
+
+fn hello() {
+    for i in 0..3 {
+        println!("hello from the new stack: {i}");
+        switch_back();
+    }
+    println!("done on the new stack");
+}
+
+fn main() {
+    let mut coroutine = Coroutine::new(hello);
+
+    while !coroutine.finished {
+        switch_to(&mut coroutine);
+        println!("back on the main stack");
+    }
+}
@@ -1,5 +1,4 @@
-#![feature(naked_functions)]
-use std::arch::asm;
+use std::arch::{asm, naked_asm};
 
 const DEFAULT_STACK_SIZE: usize = 1024 * 1024 * 2;
 const MAX_THREADS: usize = 4;
@@ -123,9 +122,9 @@ impl Runtime {
         unsafe {
             let s_ptr = available.stack.as_mut_ptr().offset(size as isize);
             let s_ptr = (s_ptr as usize & !15) as *mut u8;
-            std::ptr::write(s_ptr.offset(-16) as *mut u64, guard as u64);
-            std::ptr::write(s_ptr.offset(-24) as *mut u64, skip as u64);
-            std::ptr::write(s_ptr.offset(-32) as *mut u64, f as u64);
+            std::ptr::write(s_ptr.offset(-16) as *mut u64, guard as *const () as u64);
+            std::ptr::write(s_ptr.offset(-24) as *mut u64, skip as *const () as u64);
+            std::ptr::write(s_ptr.offset(-32) as *mut u64, f as usize as u64);
             available.ctx.rsp = s_ptr.offset(-32) as u64;
         }
         available.state = State::Ready;
@@ -139,9 +138,9 @@ fn guard() {
     };
 }
 
-#[naked]
+#[unsafe(naked)]
 unsafe extern "C" fn skip() {
-    asm!("ret", options(noreturn))
+    naked_asm!("ret")
 }
 
 pub fn yield_thread() {
@@ -151,11 +150,11 @@ pub fn yield_thread() {
     };
 }
 
-#[naked]
+#[unsafe(naked)]
 #[no_mangle]
 #[cfg_attr(target_os = "macos", export_name = "\x01switch")] // see: How-to-MacOS-M.md for explanation
 unsafe extern "C" fn switch() {
-    asm!(
+    naked_asm!(
         "mov [rdi + 0x00], rsp",
         "mov [rdi + 0x08], r15",
         "mov [rdi + 0x10], r14",
@@ -170,8 +169,7 @@ unsafe extern "C" fn switch() {
         "mov r12, [rsi + 0x20]",
         "mov rbx, [rsi + 0x28]",
         "mov rbp, [rsi + 0x30]",
-        "ret",
-        options(noreturn)
+        "ret"
     );
 }
 
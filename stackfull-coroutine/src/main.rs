@@ -19,20 +19,121 @@
 //! From the OS's perspective, our OS threads are
 //! continously busy and it will avoid pre-empting them as
 //! much as possible.
-#![feature(naked_functions)]
-use std::arch::asm;
+//!
+//! # Preemption
+//!
+//! Scheduling is cooperative by default: a thread that never calls `yield_thread` starves
+//! all others. Run with `--preempt` to time slice them instead, see
+//! [`Runtime::enable_preemption`]: a SIGALRM tick counts against the time slice of the
+//! running thread, and once it is used up the signal handler sets `PREEMPT_PENDING`.
+//!
+//! That is all the handler does, as it interrupts a thread wherever it is, e.g. halfway
+//! through the scheduler or a `println!`. The switch to the next thread happens at the next
+//! safe point: [`yield_thread`], or [`check_preempt`], which a thread that runs for long
+//! without yielding calls every now and then. Code that must not be interleaved with other
+//! threads goes in a `without_preemption` section, see `safe_println!`, a thread whose slice
+//! ran out during one is preempted as it leaves it.
+//!
+//! # Sleeping
+//!
+//! [`sleep_ms`] parks a thread until a deadline rather than have it yield over and over:
+//! `t_yield` skips it until then. Once every thread is asleep, the main loop puts the OS thread
+//! to sleep until the nearest deadline.
+//!
+//! # IO
+//!
+//! [`block_on_read`] parks a thread until an fd is readable. The fd is registered with a
+//! [mini-mio](mini_mio) `Poll` owned by the base thread, which checks it every time it runs and
+//! blocks on it once nothing else is ready, much like the reactor of the reactor-executor.
+//!
+//! # Statistics
+//!
+//! Every thread counts how often it was switched to, how long it ran between switches and how
+//! deep its stack got, see [`Runtime::stats`]. They are printed once all threads are done, to
+//! compare how tasks share the OS thread, e.g. with and without `--preempt`.
+use std::{
+    arch::{asm, naked_asm},
+    cell::Cell,
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::{self, Read, Write},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::net::UnixStream,
+    },
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use mini_mio::{ffi, poll::Poll, source::SourceFd};
 
 const DEFAULT_STACK_SIZE: usize = 1024 * 1024 * 2; // 2 MB
 const MAX_THREADS: usize = 4;
 
-static mut RUNTIME: usize = 0; // pointer to our runtime
+thread_local! {
+    /// Scheduler of this OS thread, created by `Runtime::new` and never freed.
+    ///
+    /// Green threads share the OS thread they run on, so `guard`, `yield_thread` and the
+    /// signal handler find it from whichever task stack they are called on.
+    static SCHEDULER: Cell<*mut Scheduler> = const { Cell::new(std::ptr::null_mut()) };
+}
+
+/// Depth of `without_preemption` sections, no thread is preempted while > 0.
+///
+/// Threads are only switched outside of them, so this is the depth of the running thread's.
+static CRITICAL: AtomicUsize = AtomicUsize::new(0);
+
+/// Ticks a thread may run for before it is preempted, 0 while scheduling is cooperative. Read
+/// by the signal handler, see `on_tick`.
+static TIME_SLICE: AtomicUsize = AtomicUsize::new(0);
+
+/// Ticks the running thread has run for since it was switched to, counted by `on_tick`
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+
+/// The running thread used up its time slice, and is preempted at the next safe point, see
+/// `check_preempt`
+static PREEMPT_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// `println!` that is not preempted halfway, see `without_preemption`.
+macro_rules! safe_println {
+    ($($arg:tt)*) => {
+        without_preemption(|| println!($($arg)*))
+    };
+}
 
 /// Main entrypoint for our runtime
+///
+/// A handle to the scheduler of this OS thread, which lives on the heap, so the handle can be
+/// moved and copied around freely. See [`current`] to get one from within a task.
+#[derive(Clone, Copy)]
 pub struct Runtime {
+    /// Not Send, as it is a raw pointer: the scheduler belongs to this OS thread
+    scheduler: *mut Scheduler,
+}
+
+/// State of the runtime, see [`Runtime`]
+struct Scheduler {
     threads: Vec<Thread>,
 
     /// Thread we are currently running
     current: usize,
+
+    /// Ticks a thread may run for before it is preempted, None while scheduling is cooperative
+    time_slice: Option<usize>,
+
+    /// Deadlines of sleeping threads along with their ids, nearest first, see `sleep_ms`
+    timers: BinaryHeap<Reverse<(Instant, usize)>>,
+
+    /// Fds of blocked threads, registered with the id of the thread as token, see
+    /// `block_on_read`
+    poll: Poll,
+
+    /// Buffer for the events `poll` hands back
+    events: Vec<ffi::Event>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -43,6 +144,10 @@ enum State {
     Running,
     /// Thread is ready to move forward and resume execution
     Ready,
+    /// Thread waits for its deadline in `timers` to pass, then becomes Ready
+    Sleeping,
+    /// Thread waits for its fd in `poll` to become readable, then becomes Ready
+    Blocked(RawFd),
 }
 
 /// Holds data for a thread
@@ -53,6 +158,26 @@ struct Thread {
     ctx: ThreadContext,
     state: State,
     base: usize,
+    /// When the thread was last switched to, None while it is not running
+    resumed_at: Option<Instant>,
+    /// Counters across all tasks the thread ran, see [`Runtime::stats`]
+    stats: ThreadStats,
+}
+
+/// Counters of a thread, across all tasks it ran, see [`Runtime::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThreadStats {
+    /// Times the thread was switched to
+    pub switches: usize,
+    /// Time spent running, summed over every stretch between being switched to and yielding
+    pub run_time: Duration,
+    /// Longest stretch the thread ran without yielding
+    pub longest_run: Duration,
+    /// Deepest the thread's stack got, in bytes. Always 0 for the base thread, which runs on
+    /// the stack of the OS thread.
+    pub stack_high_water: usize,
+    /// Times the thread was preempted, see [`Runtime::enable_preemption`]
+    pub preempted: usize,
 }
 
 fn offset(rsp: u64, base: usize) -> usize {
@@ -93,23 +218,110 @@ impl Thread {
             ctx: ThreadContext::default(),
             state: State::Available,
             base: 0,
+            resumed_at: None,
+            stats: ThreadStats::default(),
+        }
+    }
+
+    /// Depth of the deepest byte the thread has written to its stack so far.
+    ///
+    /// Stacks start out zeroed, so this is the distance from `base` down to the lowest non-zero
+    /// byte. Zeros written at the very bottom go unnoticed, so it may come out a little low.
+    fn stack_high_water(&self) -> usize {
+        match self.stack.iter().position(|byte| *byte != 0) {
+            Some(lowest) if self.base != 0 => {
+                offset(self.stack.as_ptr() as u64 + lowest as u64, self.base)
+            }
+            _ => 0,
+        }
+    }
+
+    /// The thread was switched away from, add the stretch it ran for to its stats.
+    fn suspended(&mut self, now: Instant) {
+        if let Some(resumed_at) = self.resumed_at.take() {
+            let ran = now.duration_since(resumed_at);
+            self.stats.run_time += ran;
+            self.stats.longest_run = self.stats.longest_run.max(ran);
         }
     }
 }
 
 impl Runtime {
-    /// Create a new runtime
+    /// Create the runtime of this OS thread
     ///
     /// This runtime is created with MAX_THREADS available, one of which is the main / base thread.
     /// The base thread is always running and is the first thread to be created.
     ///
     /// This means that we do not create threads only when and as needed.
+    ///
+    /// Panics if this OS thread has a runtime already.
     pub fn new() -> Self {
+        assert!(
+            SCHEDULER.with(Cell::get).is_null(),
+            "Runtime::new called on an OS thread that has a runtime already"
+        );
+
+        // leaked, the stacks of its threads must stay put for as long as the process runs
+        let scheduler = Box::into_raw(Box::new(Scheduler::new()));
+        SCHEDULER.with(|current| current.set(scheduler));
+
+        Self { scheduler }
+    }
+
+    /// Preempt threads that ran for `time_slice` ticks of `tick` each, rather than waiting for
+    /// them to yield.
+    pub fn enable_preemption(&self, tick: Duration, time_slice: usize) {
+        unsafe { (*self.scheduler).enable_preemption(tick, time_slice) }
+    }
+
+    /// Being main program loop
+    pub fn run(&self) -> ! {
+        unsafe { (*self.scheduler).run() }
+    }
+
+    /// Spawn a new task onto an available thread
+    ///
+    /// panics if no available thread found
+    pub fn spawn(&self, f: fn()) {
+        unsafe { (*self.scheduler).spawn(f) }
+    }
+
+    /// Counters of every thread so far, indexed by thread id, 0 being the base thread.
+    ///
+    /// The running thread's current stretch is not included in its run time until it yields.
+    pub fn stats(&self) -> Vec<ThreadStats> {
+        unsafe { (*self.scheduler).stats() }
+    }
+}
+
+/// Runtime of this OS thread, e.g. to spawn tasks from within a task.
+///
+/// Panics if no runtime was created on this OS thread.
+pub fn current() -> Runtime {
+    Runtime {
+        scheduler: scheduler(),
+    }
+}
+
+/// Scheduler of this OS thread, see `SCHEDULER`.
+fn scheduler() -> *mut Scheduler {
+    let scheduler = SCHEDULER.with(Cell::get);
+    assert!(
+        !scheduler.is_null(),
+        "no runtime on this OS thread, see Runtime::new"
+    );
+    scheduler
+}
+
+impl Scheduler {
+    fn new() -> Self {
         let base_thread = Thread {
             stack: vec![0_u8; DEFAULT_STACK_SIZE],
             ctx: ThreadContext::default(),
             state: State::Running, // Set thread as running
             base: 0,
+            resumed_at: Some(Instant::now()),
+            stats: ThreadStats::default(),
         };
 
         let mut threads = vec![base_thread];
@@ -121,32 +333,107 @@ impl Runtime {
         Self {
             threads,
             current: 0,
+            time_slice: None,
+            timers: BinaryHeap::new(),
+            poll: Poll::new().expect("Failed to create event queue"),
+            events: Vec::with_capacity(MAX_THREADS),
         }
     }
 
-    /// Initialize static RUNTIME
-    ///
-    /// This allows the RUNTIME to be accessed from anywhere in our code.
-    ///
-    /// This is not safe and we must make sure that the address to &self never changes during
-    /// program execution, or else RUNTIME will contain and invalid address. i.e. we can't do
-    /// anything that will invalidate the pointer.
-    pub fn init(&self) {
+    /// See [`Runtime::enable_preemption`].
+    fn enable_preemption(&mut self, tick: Duration, time_slice: usize) {
+        assert!(time_slice > 0, "time slice must be at least one tick");
+        self.time_slice = Some(time_slice);
+        TIME_SLICE.store(time_slice, Ordering::SeqCst);
+
+        let interval = libc::timeval {
+            tv_sec: tick.as_secs() as libc::time_t,
+            tv_usec: tick.subsec_micros() as libc::suseconds_t,
+        };
+        let timer = libc::itimerval {
+            it_interval: interval,
+            it_value: interval,
+        };
+
         unsafe {
-            let ptr: *const Runtime = self;
-            RUNTIME = ptr as usize;
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_tick as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            assert_eq!(
+                libc::sigaction(libc::SIGALRM, &action, std::ptr::null_mut()),
+                0
+            );
+            assert_eq!(
+                setitimer(libc::ITIMER_REAL, &timer, std::ptr::null_mut()),
+                0
+            );
         }
     }
 
-    /// Being main program loop
-    pub fn run(&mut self) -> ! {
+    fn run(&mut self) -> ! {
         println!("Main Loop Starting");
-        while self.t_yield() {
-            println!("Main Loop Calling Yield on base thread again...")
+        loop {
+            while self.t_yield() {
+                println!("Main Loop Calling Yield on base thread again...");
+                self.poll_io(Some(0));
+            }
+
+            // nothing is ready, but threads that are asleep or blocked will be once their
+            // deadline passed or their fd is readable
+            let timeout = self
+                .timers
+                .peek()
+                .map(|Reverse((deadline, _))| deadline.saturating_duration_since(Instant::now()));
+
+            if self.is_blocked() {
+                // rounded up, so we do not wake up just before the deadline
+                self.poll_io(timeout.map(|timeout| {
+                    timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32
+                }));
+            } else if let Some(timeout) = timeout {
+                thread::sleep(timeout);
+            } else {
+                break;
+            }
+        }
+
+        for (id, stats) in self.stats().iter().enumerate() {
+            print!(
+                "thread {}: switched to {} times, ran for {:?} (longest {:?}), stack {} bytes",
+                id, stats.switches, stats.run_time, stats.longest_run, stats.stack_high_water
+            );
+            if self.time_slice.is_some() {
+                print!(", preempted {} times", stats.preempted);
+            }
+            println!();
         }
         std::process::exit(0);
     }
 
+    fn stats(&self) -> Vec<ThreadStats> {
+        self.threads
+            .iter()
+            .map(|thread| ThreadStats {
+                stack_high_water: thread.stack_high_water(),
+                ..thread.stats
+            })
+            .collect()
+    }
+
+    /// Switch to the next thread, as the running one used up its time slice, see
+    /// [`check_preempt`]. The base thread only runs the main loop, which yields right away
+    /// anyway.
+    fn preempt(&mut self) {
+        if self.current == 0 {
+            return;
+        }
+
+        self.threads[self.current].stats.preempted += 1;
+        self.t_yield();
+    }
+
     /// return function called when thread is finished
     ///
     /// user of thread does not call this. We setup stack so that it is called
@@ -159,7 +446,7 @@ impl Runtime {
     /// manually written to the threads stack as part of the custom epilogue.
     fn t_return(&mut self) {
         if self.current != 0 {
-            println!("Returning thread {} and setting to Available", self.current);
+            safe_println!("Returning thread {} and setting to Available", self.current);
             // let runtime know thread is ready to be assigned a new task
             // as it is completed Running of previous task assigned to it.
             self.threads[self.current].state = State::Available;
@@ -174,6 +461,8 @@ impl Runtime {
     #[inline(never)]
     fn t_yield(&mut self) -> bool {
         println!("Yielding thread {}", self.current);
+        self.wake_sleepers(Instant::now());
+
         // # 1. Scheduler
         let mut pos = self.current;
 
@@ -194,23 +483,30 @@ impl Runtime {
 
         // If current thread is in Available state, it has no task to even run
         // so nothing is done to it.
-        if self.threads[self.current].state != State::Available {
+        if self.threads[self.current].state == State::Running {
             // If current thread is `Running` (from `yield_thread` usage), then
             // we can simply transition from `Running` to `Ready`. This effecitevly
             // adds it back to list of threads to be scheduled for running, since they
             // have an active task still to complete and have not returned.
             //
-            // There probably isn't any instance when this branch is selected
-            // where current thread is Ready, that doesn't make sense from
-            // how all this code has been setup.
+            // A thread that is Sleeping (from `sleep_ms` usage) stays so, until
+            // its deadline has passed.
             self.threads[self.current].state = State::Ready
         }
 
         // Set new thread's state as Running (we are about to switch context into it)
         self.threads[pos].state = State::Running;
+        // with a fresh time slice, any preemption of the current thread is moot now
+        TICKS.store(0, Ordering::SeqCst);
+        PREEMPT_PENDING.store(false, Ordering::SeqCst);
         let old_pos = self.current;
         self.current = pos;
 
+        let now = Instant::now();
+        self.threads[old_pos].suspended(now);
+        self.threads[pos].resumed_at = Some(now);
+        self.threads[pos].stats.switches += 1;
+
         // # 2. Context Switch
         unsafe {
             let old_ctx: *mut ThreadContext = &mut self.threads[old_pos].ctx;
@@ -277,10 +573,96 @@ impl Runtime {
         self.threads.len() > 0
     }
 
-    /// Spawn a new task onto an available thread
-    ///
-    /// panics if no available thread found
-    pub fn spawn(&mut self, f: fn()) {
+    /// Make sleeping threads whose deadline passed by `now` Ready again.
+    fn wake_sleepers(&mut self, now: Instant) {
+        while let Some(Reverse((deadline, id))) = self.timers.peek() {
+            if *deadline > now {
+                break;
+            }
+            self.threads[*id].state = State::Ready;
+            self.timers.pop();
+        }
+    }
+
+    /// True if any thread waits for an fd to become readable.
+    fn is_blocked(&self) -> bool {
+        self.threads
+            .iter()
+            .any(|thread| matches!(thread.state, State::Blocked(_)))
+    }
+
+    /// Make blocked threads whose fd is readable Ready again, waiting up to `timeout`
+    /// milliseconds for one to be. Does nothing if no thread is blocked.
+    fn poll_io(&mut self, timeout: Option<i32>) {
+        if !self.is_blocked() {
+            return;
+        }
+
+        match self.poll.poll(&mut self.events, timeout) {
+            Ok(()) => {}
+            // a SIGALRM tick, see `enable_preemption`
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => return,
+            Err(err) => panic!("Failed to poll for events: {err}"),
+        }
+
+        for event in &self.events {
+            let id = event.token();
+            let State::Blocked(fd) = self.threads[id].state else {
+                continue;
+            };
+
+            // level-triggered, so stop watching until the thread blocks on it again
+            self.poll
+                .registry()
+                .deregister(&SourceFd(&fd))
+                .expect("Failed to deregister fd");
+            self.threads[id].state = State::Ready;
+        }
+    }
+
+    /// See [`block_on_read`].
+    fn block_on_read(&mut self, fd: RawFd) -> io::Result<()> {
+        assert_ne!(
+            self.current, 0,
+            "the base thread runs the main loop, it can not block"
+        );
+
+        without_preemption(|| {
+            self.poll
+                .registry()
+                .register(&SourceFd(&fd), self.current, ffi::EPOLLIN)?;
+            self.threads[self.current].state = State::Blocked(fd);
+            Ok::<_, io::Error>(())
+        })?;
+
+        // unless preempted while leaving the section, and woken meanwhile
+        if self.threads[self.current].state == State::Blocked(fd) {
+            self.t_yield();
+        }
+        Ok(())
+    }
+
+    /// See [`sleep_ms`].
+    fn sleep(&mut self, duration: Duration) {
+        // the base thread runs the main loop, there is nobody to switch back to it
+        if self.current == 0 {
+            thread::sleep(duration);
+            return;
+        }
+
+        let deadline = Instant::now() + duration;
+        without_preemption(|| {
+            self.threads[self.current].state = State::Sleeping;
+            self.timers.push(Reverse((deadline, self.current)));
+        });
+
+        // unless preempted while leaving the section, in which case we slept already
+        if self.threads[self.current].state == State::Sleeping {
+            self.t_yield();
+        }
+    }
+
+    fn spawn(&mut self, f: fn()) {
         // find available thread
         let available = self
             .threads
@@ -312,9 +694,11 @@ impl Runtime {
             // 1. `f` -> function to run concurrently
             // 2. `skip` -> skip to next instruction (it's just a `ret instruction`)
             // 3. `guard` -> set current thread state to Available and schedul next thread
-            std::ptr::write(s_ptr.offset(-16) as *mut u64, guard as u64);
-            std::ptr::write(s_ptr.offset(-24) as *mut u64, skip as u64);
-            std::ptr::write(s_ptr.offset(-32) as *mut u64, f as u64);
+            //
+            // `skip` keeps the stack 16 byte aligned on entry to `guard`
+            std::ptr::write(s_ptr.offset(-16) as *mut u64, guard as *const () as u64);
+            std::ptr::write(s_ptr.offset(-24) as *mut u64, skip as *const () as u64);
+            std::ptr::write(s_ptr.offset(-32) as *mut u64, f as usize as u64);
 
             // store stack pointer for thread such that it's pointing at `f`
             available.ctx.rsp = s_ptr.offset(-32) as u64;
@@ -327,20 +711,16 @@ impl Runtime {
 
 fn guard() {
     unsafe {
-        // get mutable raw pointer
-        let rt_ptr = RUNTIME as *mut Runtime;
-        // dereference raw pointer to get Runtime and call t_return
-        //
         // This will set the current thread state to Available
         // and call t_yield() to schedule next thread to run
-        (*rt_ptr).t_return();
+        (*scheduler()).t_return();
     }
 }
 
 /// Single instruction to skip to next instruction
-#[naked]
+#[unsafe(naked)]
 unsafe extern "C" fn skip() {
-    asm!("ret", options(noreturn));
+    naked_asm!("ret");
 }
 
 /// A thread can decide that it can no longer make progress an yield execution to another thread.
@@ -348,14 +728,69 @@ unsafe extern "C" fn skip() {
 /// function has not yet been called (i.e. t_return not called yet).
 pub fn yield_thread() {
     unsafe {
-        // get mutable raw pointer
-        let rt_ptr = RUNTIME as *mut Runtime;
-
-        // dereference raw pointer to get Runtime and call t_yield
-        //
-        // Let's use call t_yield on our Runtime from an arbitrary place in our code
+        // Let's use call t_yield on our scheduler from an arbitrary place in our code
         // without needing any references to it.
-        (*rt_ptr).t_yield();
+        (*scheduler()).t_yield();
+    }
+}
+
+/// Park the current thread for at least `ms` milliseconds, letting other threads run
+/// meanwhile, see [`Sleeping`](self#sleeping).
+pub fn sleep_ms(ms: u64) {
+    unsafe { (*scheduler()).sleep(Duration::from_millis(ms)) }
+}
+
+/// Park the current thread until `fd` is readable, letting other threads run meanwhile, see
+/// [`IO`](self#io). Once this returns, a read from `fd` does not block.
+///
+/// Fails if `fd` can not be registered, e.g. as it is not open or another thread is blocked on
+/// it already. Panics if called from the base thread.
+pub fn block_on_read(fd: RawFd) -> io::Result<()> {
+    unsafe { (*scheduler()).block_on_read(fd) }
+}
+
+/// Run `f` without being preempted, i.e. [`check_preempt`] does nothing within it.
+///
+/// If the thread's time slice ran out meanwhile, this is where it is preempted.
+pub fn without_preemption<T>(f: impl FnOnce() -> T) -> T {
+    CRITICAL.fetch_add(1, Ordering::SeqCst);
+    let output = f();
+
+    if CRITICAL.fetch_sub(1, Ordering::SeqCst) == 1 {
+        check_preempt();
+    }
+    output
+}
+
+/// Safe point for preemption: switches to the next thread if the running one used up its time
+/// slice, see [`Runtime::enable_preemption`]. A thread that runs for long without yielding
+/// calls this every now and then, it is cheap while there is nothing to do.
+pub fn check_preempt() {
+    if PREEMPT_PENDING.load(Ordering::Relaxed)
+        && CRITICAL.load(Ordering::SeqCst) == 0
+        && PREEMPT_PENDING.swap(false, Ordering::SeqCst)
+    {
+        unsafe { (*scheduler()).preempt() }
+    }
+}
+
+// not exported by the libc crate
+extern "C" {
+    fn setitimer(
+        which: libc::c_int,
+        new_value: *const libc::itimerval,
+        old_value: *mut libc::itimerval,
+    ) -> libc::c_int;
+}
+
+/// SIGALRM handler, see `Runtime::enable_preemption`.
+///
+/// Counts the tick against the running thread's time slice, and marks it for preemption once
+/// the slice is used up. Only touches atomics, as it runs wherever the thread was interrupted.
+extern "C" fn on_tick(_signal: libc::c_int) {
+    let ticks = TICKS.fetch_add(1, Ordering::SeqCst) + 1;
+    if ticks >= TIME_SLICE.load(Ordering::SeqCst) {
+        PREEMPT_PENDING.store(true, Ordering::SeqCst);
     }
 }
 
@@ -375,11 +810,11 @@ pub fn yield_thread() {
 //     rbx: u64,  [rdi + 0x28]
 //     rbp: u64,  [rdi + 0x30]
 // }
-#[naked]
+#[unsafe(naked)]
 #[no_mangle]
 #[cfg_attr(target_os = "macos", export_name = "\x01switch")] // see: How-to-MacOS-M.md for explanation
 unsafe extern "C" fn switch() {
-    asm!(
+    naked_asm!(
         "mov [rdi + 0x00], rsp",
         "mov [rdi + 0x08], r15",
         "mov [rdi + 0x10], r14",
@@ -394,41 +829,76 @@ unsafe extern "C" fn switch() {
         "mov r12, [rsi + 0x20]",
         "mov rbx, [rsi + 0x28]",
         "mov rbp, [rsi + 0x30]",
-        "ret",
-        options(noreturn)
+        "ret"
     );
 }
 
+/// Connects thread 2, which writes to the first socket once done, to thread 1, which waits
+/// for it on the second. Tasks are plain `fn`s, they can not capture the sockets.
+static SOCKETS: OnceLock<(UnixStream, UnixStream)> = OnceLock::new();
+
 fn main() {
-    let mut runtime = Runtime::new();
+    let runtime = Runtime::new();
+    SOCKETS.get_or_init(|| UnixStream::pair().expect("Failed to create socket pair"));
 
-    runtime.init();
+    // without preemption, thread 3 runs to completion before the others get going again
+    if std::env::args().any(|arg| arg == "--preempt") {
+        runtime.enable_preemption(Duration::from_millis(10), 5);
+    }
 
     // spawn a task onto an available thread
     runtime.spawn(|| {
         // technically speaking, we have no idea what thread this function is
         // executing on, so we can't really say it's thread 1.
-        println!("THREAD 1 STARTING");
+        safe_println!("THREAD 1 STARTING");
         let id = 1;
         // we simply print out a message and yield at end of each iteration
         for i in 0..10 {
-            println!("thread: {} counter: {}", id, i);
+            safe_println!("thread: {} counter: {}", id, i);
             yield_thread();
         }
-        println!("THREAD 1 FINISHED");
+
+        let socket = &SOCKETS.get().unwrap().1;
+        block_on_read(socket.as_raw_fd()).unwrap();
+        let mut message = [0; 64];
+        let mut reader = socket;
+        let n = reader.read(&mut message).unwrap();
+        let message = String::from_utf8_lossy(&message[..n]);
+        safe_println!("thread: {} received: {}", id, message);
+        safe_println!("THREAD 1 FINISHED");
     });
 
     runtime.spawn(|| {
-        println!("THREAD 2 STARTING");
+        safe_println!("THREAD 2 STARTING");
         // task / thread id
         let id = 2;
 
-        // we simply print out a message and yield at end of each iteration
+        // we simply print out a message and sleep at end of each iteration
         for i in 0..15 {
-            println!("thread: {} counter: {}", id, i);
-            yield_thread();
+            safe_println!("thread: {} counter: {}", id, i);
+            sleep_ms(10);
+        }
+
+        let socket = &SOCKETS.get().unwrap().0;
+        let mut writer = socket;
+        writer.write_all(b"hello from thread 2").unwrap();
+        safe_println!("THREAD 2 FINISHED");
+    });
+
+    runtime.spawn(|| {
+        safe_println!("THREAD 3 STARTING");
+        let id = 3;
+
+        // busy for a while, and never yields, but lets itself be preempted
+        let mut sum: u64 = 0;
+        for i in 0..500_000_000_u64 {
+            sum = std::hint::black_box(sum.wrapping_add(i));
+            check_preempt();
+            if i % 100_000_000 == 0 {
+                safe_println!("thread: {} progress: {}", id, i / 100_000_000);
+            }
         }
-        println!("THREAD 2 FINISHED");
+        safe_println!("THREAD 3 FINISHED, sum: {}", sum);
     });
     runtime.run();
 }
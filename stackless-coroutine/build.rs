@@ -8,6 +8,18 @@
 //! ```
 //! corofy [src_path] [optional-dest-path]
 //! ```
+//!
+//! `a-coroutines-variables`, `b-coroutines-references` and
+//! `b-reactor-executor` use the `#[coroutine]` attribute of
+//! `coroutine-macros` instead, which does the rewriting at compile time and
+//! needs no external binary.
+//!
+//! Coroutines generated by `corofy` and `corofy_waker` are polled through
+//! `&mut self` and box the futures they wait on with `Box::new`, so a
+//! reference kept across a wait dangles once the coroutine moves, see
+//! `c-coroutines-problem`. `e-coroutines-problem` pins its copy of the output
+//! by hand. Their templates live with the binaries in `rust-async-utils`, not
+//! in this repository; `#[coroutine]` generates pinned, `!Unpin` coroutines.
 use std::process::Command;
 
 fn main() {
@@ -17,33 +29,6 @@ fn main() {
         .output()
         .expect("Failed to run corofy for `a-runtime");
 
-    Command::new("corofy_waker")
-        .arg("src/bin/b-reactor-executor/main_async.rs")
-        .arg("src/bin/b-reactor-executor/main_corofy.rs")
-        .output()
-        .expect("Failed to run corofy for b-reactor-executor");
-
-    Command::new("corofy_waker")
-        .arg("src/bin/a-coroutines-variables/main_async.rs")
-        .arg("src/bin/a-coroutines-variables/main_corofy.rs")
-        .output()
-        .expect("Failed to run corofy for a-coroutines-variables");
-
-    Command::new("corofy_waker")
-        .arg("src/bin/b-coroutines-references/main_async.rs")
-        .arg("src/bin/b-coroutines-references/main_corofy.rs")
-        .output()
-        .expect("Failed to run corofy for b-coroutines-references");
-
     // Tell cargo to rerun build script of below file changes
     println!("cargo::rerun-if-changed=stackless-coroutine/src/bin/a-runtime/main_async.rs");
-    println!(
-        "cargo::rerun-if-changed=stackless-coroutine/src/bin/b-reactor-executor/main_async.rs"
-    );
-    println!(
-        "cargo::rerun-if-changed=stackless-coroutine/src/bin/a-coroutines-variables/main_async.rs"
-    );
-    println!(
-        "cargo::rerun-if-changed=stackless-coroutine/src/bin/b-coroutines-references/main_async.rs"
-    );
 }
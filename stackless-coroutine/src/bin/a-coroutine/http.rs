@@ -8,7 +8,10 @@ use std::{
 
 use crate::future::{Future, PollState};
 
-static DELAYSERVER: &str = "127.0.0.1:8080";
+/// Address of the delayserver, override with the `DELAYSERVER_ADDR` environment variable
+fn delayserver() -> String {
+    std::env::var("DELAYSERVER_ADDR").unwrap_or_else(|_| String::from("127.0.0.1:8080"))
+}
 
 /// The main http client responsible for I/O operations via kernel
 ///
@@ -50,7 +53,7 @@ impl HttpGetFuture {
     /// and stores the created stream on the future.
     fn write_request(&mut self) {
         // Create a standard library stream first and wrap it in mio stream
-        let stream = std::net::TcpStream::connect(DELAYSERVER).unwrap();
+        let stream = std::net::TcpStream::connect(delayserver()).unwrap();
         stream.set_nonblocking(true).unwrap();
         let mut stream = mio::net::TcpStream::from_std(stream);
 
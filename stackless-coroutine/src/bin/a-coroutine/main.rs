@@ -12,7 +12,7 @@ mod future;
 /// code for http client
 mod http;
 
-use future::{Future, PollState};
+use future::{BoxFuture, Future, FutureExt, PollState};
 use http::{Http, Response};
 
 /// Represents a pause-able / resumable task
@@ -78,7 +78,7 @@ enum CoroutineState {
     ///
     /// state at end (Pending): `Wait1`
     /// state at end (ready): progresses to `println` statement and then 2nd get request
-    Wait1(Box<dyn Future<Output = Response>>),
+    Wait1(BoxFuture<'static, Response>),
 
     /// 3. Waiting for the 2nd future to complete
     ///
@@ -92,7 +92,7 @@ enum CoroutineState {
     /// and transitions to Resolved.
     ///
     /// Transitions to Resolved after the 3rd poll
-    Wait2(Box<dyn Future<Output = Response>>),
+    Wait2(BoxFuture<'static, Response>),
 
     /// 4. Future is Resolved and no further useful work can be done.
     ///
@@ -140,7 +140,7 @@ impl Future for Coroutine {
                     // 2. Transition to Wait1 state
                     // store future, change state and poll future
                     // store state and poll
-                    self.state = Wait1(Box::new(future));
+                    self.state = Wait1(future.boxed());
 
                     // continue in loop
                 }
@@ -153,7 +153,7 @@ impl Future for Coroutine {
                         let future = Http::get("/500/HelloWorld");
 
                         // 3. Transition to Wait2 state
-                        self.state = Wait2(Box::new(future))
+                        self.state = Wait2(future.boxed())
 
                         // continue in loop
                     }
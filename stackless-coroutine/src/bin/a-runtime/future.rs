@@ -1,18 +1,140 @@
 //! future related code
 #![allow(unused)]
 
-/// Represents some operation that will complete in the future
-/// and return a value of type `Future::Output`.
-pub trait Future {
-    type Output;
-    fn poll(&mut self) -> PollState<Self::Output>;
+/// The trait without a waker, shared with the other binaries, see `async-core`.
+pub use async_core::{simple::Future, PollState};
+
+/// NEW: Boxed future of any type with output `T`, e.g. for the states of a coroutine that each
+/// wait on a different future, or for a coroutine that waits on a call to itself.
+pub type BoxFuture<'a, T> = Box<dyn Future<Output = T> + 'a>;
+
+/// Adapters for every future, e.g. `.boxed()`, see [`BoxFuture`].
+pub trait FutureExt: Future {
+    fn boxed<'a>(self) -> BoxFuture<'a, Self::Output>
+    where
+        Self: Sized + 'a,
+    {
+        Box::new(self)
+    }
+
+    /// Transform the output with `f` once the future is ready.
+    fn map<T, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnOnce(Self::Output) -> T,
+    {
+        Map {
+            future: self,
+            f: Some(f),
+        }
+    }
+
+    /// Continue with the future returned by `f` once this one is ready, resolving to its output.
+    fn and_then<Fut, F>(self, f: F) -> AndThen<Self, Fut, F>
+    where
+        Self: Sized,
+        Fut: Future,
+        F: FnOnce(Self::Output) -> Fut,
+    {
+        AndThen::First(self, Some(f))
+    }
+
+    /// Look at the output with `f` once the future is ready, passing it on unchanged.
+    fn inspect<F>(self, f: F) -> Inspect<Self, F>
+    where
+        Self: Sized,
+        F: FnOnce(&Self::Output),
+    {
+        Inspect {
+            future: self,
+            f: Some(f),
+        }
+    }
 }
 
-/// PollState is an enum that represents the state of a future.
-/// It is either Ready or NotReady. The value returned when ready is of type T
-pub enum PollState<T> {
-    Ready(T),
-    NotReady,
+impl<F: Future + ?Sized> FutureExt for F {}
+
+/// Future for [`FutureExt::map`].
+pub struct Map<Fut, F> {
+    future: Fut,
+    /// Taken once the future is ready
+    f: Option<F>,
+}
+
+impl<Fut, F, T> Future for Map<Fut, F>
+where
+    Fut: Future,
+    F: FnOnce(Fut::Output) -> T,
+{
+    type Output = T;
+
+    fn poll(&mut self) -> PollState<Self::Output> {
+        match self.future.poll() {
+            PollState::Ready(output) => {
+                let f = self.f.take().expect("Map polled after it was ready");
+                PollState::Ready(f(output))
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+/// Future for [`FutureExt::and_then`].
+pub enum AndThen<Fut1, Fut2, F> {
+    /// Waiting on the first future, `f` is taken once it is ready
+    First(Fut1, Option<F>),
+    /// Waiting on the future returned by `f`
+    Second(Fut2),
+}
+
+impl<Fut1, Fut2, F> Future for AndThen<Fut1, Fut2, F>
+where
+    Fut1: Future,
+    Fut2: Future,
+    F: FnOnce(Fut1::Output) -> Fut2,
+{
+    type Output = Fut2::Output;
+
+    fn poll(&mut self) -> PollState<Self::Output> {
+        loop {
+            match self {
+                AndThen::First(future, f) => match future.poll() {
+                    PollState::Ready(output) => {
+                        let f = f.take().expect("AndThen polled after it was ready");
+                        *self = AndThen::Second(f(output));
+                    }
+                    PollState::NotReady => return PollState::NotReady,
+                },
+                AndThen::Second(future) => return future.poll(),
+            }
+        }
+    }
+}
+
+/// Future for [`FutureExt::inspect`].
+pub struct Inspect<Fut, F> {
+    future: Fut,
+    /// Taken once the future is ready
+    f: Option<F>,
+}
+
+impl<Fut, F> Future for Inspect<Fut, F>
+where
+    Fut: Future,
+    F: FnOnce(&Fut::Output),
+{
+    type Output = Fut::Output;
+
+    fn poll(&mut self) -> PollState<Self::Output> {
+        match self.future.poll() {
+            PollState::Ready(output) => {
+                let f = self.f.take().expect("Inspect polled after it was ready");
+                f(&output);
+                PollState::Ready(output)
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
 }
 
 // Taking inspiration from tokio, we create a `join_all` function
@@ -10,7 +10,10 @@ use crate::future::{Future, PollState};
 // HttpGetRequest to register interest with event queue
 use crate::runtime;
 
-static DELAYSERVER: &str = "127.0.0.1:8080";
+/// Address of the delayserver, override with the `DELAYSERVER_ADDR` environment variable
+fn delayserver() -> String {
+    std::env::var("DELAYSERVER_ADDR").unwrap_or_else(|_| String::from("127.0.0.1:8080"))
+}
 
 // traits and types from reading from a IO source
 use std::{
@@ -54,7 +57,7 @@ impl HttpGetFuture {
     /// and stores the created stream on the future.
     fn write_request(&mut self) {
         // Create a standard library stream first and wrap it in mio stream
-        let stream = std::net::TcpStream::connect(DELAYSERVER).unwrap();
+        let stream = std::net::TcpStream::connect(delayserver()).unwrap();
         stream.set_nonblocking(true).unwrap();
         let mut stream = mio::net::TcpStream::from_std(stream);
 
@@ -14,6 +14,10 @@ pub fn run() {
     // unlike the a-coroutine example, rather than directly polling the future in a loop,
     // we create a runtime and pass the future to the Runtime. 
     let mut runtime = Runtime::new();
+
+    // NEW: top-level futures spawned before are polled along with it, so the two run
+    // concurrently and take as long as one of them.
+    runtime.spawn(async_main());
     runtime.block_on(future);
 }
 
@@ -6,7 +6,7 @@ use std::sync::OnceLock;
 
 use mio::{Events, Poll, Registry};
 
-use crate::future::{Future, PollState};
+use crate::future::{BoxFuture, Future, FutureExt, PollState};
 
 /// Registry is used for registering interest in events on a source.
 ///
@@ -25,9 +25,17 @@ pub fn registry() -> &'static Registry {
         .expect("Registry not initialized. Called outside a runtime context.")
 }
 
+/// A top-level future, and its output once it resolved.
+struct Task {
+    future: BoxFuture<'static, String>,
+    output: Option<String>,
+}
+
 pub struct Runtime {
     /// Abstraction over the OS'es event_queue polling / select interface
     poll: Poll,
+    /// NEW: top-level futures, in the order they were spawned
+    tasks: Vec<Task>,
 }
 
 impl Runtime {
@@ -44,21 +52,45 @@ impl Runtime {
             .set(registry)
             .expect("Failed to set REGISTRY static variable");
 
-        Self { poll }
+        Self {
+            poll,
+            tasks: Vec::new(),
+        }
     }
-    /// The `block_on` method is used to run the future to completion.
-    ///
-    /// It represents the original `main` function in the `a-coroutine` example.
-    /// NOTE: this implementation does not support multiple top-level futures.
-    pub fn block_on<F>(&mut self, mut future: F)
+
+    /// NEW: Add a top-level future, polled once the runtime runs, see [`Runtime::run`].
+    pub fn spawn<F>(&mut self, future: F)
     where
         // corofy only supports futures resolving with strings
-        F: Future<Output = String>,
+        F: Future<Output = String> + 'static,
     {
-        // Remember, out top-level future will return Ready only when all child futures have
-        // resolved and return PollState::Ready.
-        while let PollState::NotReady = future.poll() {
-            println!("\nCurrent future is not ready. Schedule other tasks.");
+        self.tasks.push(Task {
+            future: future.boxed(),
+            output: None,
+        });
+    }
+
+    /// NEW: Run all spawned futures to completion, returning their outputs in the order they
+    /// were spawned.
+    ///
+    /// This sits between the loop of `a-coroutine` and a full executor: every wake up polls
+    /// all futures that have not resolved yet, as we do not know which of them the event was
+    /// for. All sources are registered with the same token, see `HttpGetFuture`.
+    pub fn run(&mut self) -> Vec<String> {
+        loop {
+            let mut pending = 0;
+
+            for task in self.tasks.iter_mut().filter(|task| task.output.is_none()) {
+                match task.future.poll() {
+                    PollState::Ready(output) => task.output = Some(output),
+                    PollState::NotReady => pending += 1,
+                }
+            }
+
+            if pending == 0 {
+                break;
+            }
+            println!("\n{pending} future(s) not ready. Schedule other tasks.");
 
             // rather than sleep, we block on the event_queue (epoll or kqueue syscalls) with no
             // timeout specified. It is the responsibility of HttpGetRequest to ensure
@@ -67,5 +99,23 @@ impl Runtime {
             self.poll.poll(&mut events, None);
             println!("Woken up from poll. Checking for ready tasks.\n");
         }
+
+        self.tasks
+            .drain(..)
+            .filter_map(|task| task.output)
+            .collect()
+    }
+
+    /// The `block_on` method is used to run the future to completion.
+    ///
+    /// It represents the original `main` function in the `a-coroutine` example. Futures
+    /// spawned before are run along with it, see [`Runtime::run`].
+    pub fn block_on<F>(&mut self, future: F)
+    where
+        // corofy only supports futures resolving with strings
+        F: Future<Output = String> + 'static,
+    {
+        self.spawn(future);
+        self.run();
     }
 }
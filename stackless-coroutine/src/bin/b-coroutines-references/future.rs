@@ -1,21 +1,163 @@
 //! future related code
 #![allow(unused)]
+use std::{ops::DerefMut, pin::Pin};
 
 use crate::runtime::Waker;
 
-/// Represents some operation that will complete in the future
-/// and return a value of type `Future::Output`.
-pub trait Future {
-    type Output;
-    // NEW: When we poll a future, we must now supply a Waker
-    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output>;
+/// The trait polled with a waker, shared with the other binaries and implemented with the
+/// [`Waker`] of the runtime, see `async-core`.
+pub use async_core::{pin::Future, PollState};
+
+/// NEW: Boxed future of any type with output `T`, e.g. for the states of a coroutine that each
+/// wait on a different future, or for a coroutine that waits on a call to itself. Pinned, as
+/// coroutines may hold references into themselves.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T, Waker = Waker> + 'a>>;
+
+/// Adapters for every future, e.g. `.boxed()`, see [`BoxFuture`].
+pub trait FutureExt: Future {
+    fn boxed<'a>(self) -> BoxFuture<'a, Self::Output>
+    where
+        Self: Sized + Future<Waker = Waker> + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Transform the output with `f` once the future is ready.
+    fn map<T, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnOnce(Self::Output) -> T,
+    {
+        Map {
+            future: Box::pin(self),
+            f: Some(f),
+        }
+    }
+
+    /// Continue with the future returned by `f` once this one is ready, resolving to its output.
+    fn and_then<Fut, F>(self, f: F) -> AndThen<Self, Fut, F>
+    where
+        Self: Sized,
+        Fut: Future<Waker = Self::Waker>,
+        F: FnOnce(Self::Output) -> Fut,
+    {
+        AndThen::First(Box::pin(self), Some(f))
+    }
+
+    /// Look at the output with `f` once the future is ready, passing it on unchanged.
+    fn inspect<F>(self, f: F) -> Inspect<Self, F>
+    where
+        Self: Sized,
+        F: FnOnce(&Self::Output),
+    {
+        Inspect {
+            future: Box::pin(self),
+            f: Some(f),
+        }
+    }
 }
 
-/// PollState is an enum that represents the state of a future.
-/// It is either Ready or NotReady. The value returned when ready is of type T
-pub enum PollState<T> {
-    Ready(T),
-    NotReady,
+impl<F: Future + ?Sized> FutureExt for F {}
+
+/// Future for [`FutureExt::map`].
+pub struct Map<Fut, F> {
+    future: Pin<Box<Fut>>,
+    /// Taken once the future is ready
+    f: Option<F>,
+}
+
+// Unpin whatever `F` is, as it is never pinned, and the futures are pinned on the heap
+impl<Fut, F> Unpin for Map<Fut, F> {}
+
+impl<Fut, F, T> Future for Map<Fut, F>
+where
+    Fut: Future,
+    F: FnOnce(Fut::Output) -> T,
+{
+    type Output = T;
+    type Waker = Fut::Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &Self::Waker) -> PollState<Self::Output> {
+        let this = self.get_mut();
+
+        match this.future.as_mut().poll(waker) {
+            PollState::Ready(output) => {
+                let f = this.f.take().expect("Map polled after it was ready");
+                PollState::Ready(f(output))
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+/// Future for [`FutureExt::and_then`].
+pub enum AndThen<Fut1, Fut2, F> {
+    /// Waiting on the first future, `f` is taken once it is ready
+    First(Pin<Box<Fut1>>, Option<F>),
+    /// Waiting on the future returned by `f`
+    Second(Pin<Box<Fut2>>),
+}
+
+// Unpin whatever `F` is, as it is never pinned, and the futures are pinned on the heap
+impl<Fut1, Fut2, F> Unpin for AndThen<Fut1, Fut2, F> {}
+
+impl<Fut1, Fut2, F> Future for AndThen<Fut1, Fut2, F>
+where
+    Fut1: Future,
+    Fut2: Future<Waker = Fut1::Waker>,
+    F: FnOnce(Fut1::Output) -> Fut2,
+{
+    type Output = Fut2::Output;
+    type Waker = Fut1::Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &Self::Waker) -> PollState<Self::Output> {
+        let this = self.get_mut();
+
+        loop {
+            match this {
+                AndThen::First(future, f) => match future.as_mut().poll(waker) {
+                    PollState::Ready(output) => {
+                        let f = f.take().expect("AndThen polled after it was ready");
+                        *this = AndThen::Second(Box::pin(f(output)));
+                    }
+                    PollState::NotReady => return PollState::NotReady,
+                },
+                AndThen::Second(future) => return future.as_mut().poll(waker),
+            }
+        }
+    }
+}
+
+/// Future for [`FutureExt::inspect`].
+pub struct Inspect<Fut, F> {
+    future: Pin<Box<Fut>>,
+    /// Taken once the future is ready
+    f: Option<F>,
+}
+
+// Unpin whatever `F` is, as it is never pinned, and the futures are pinned on the heap
+impl<Fut, F> Unpin for Inspect<Fut, F> {}
+
+impl<Fut, F> Future for Inspect<Fut, F>
+where
+    Fut: Future,
+    F: FnOnce(&Fut::Output),
+{
+    type Output = Fut::Output;
+    type Waker = Fut::Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &Self::Waker) -> PollState<Self::Output> {
+        let this = self.get_mut();
+
+        match this.future.as_mut().poll(waker) {
+            PollState::Ready(output) => {
+                let f = this.f.take().expect("Inspect polled after it was ready");
+                f(&output);
+                PollState::Ready(output)
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
 }
 
 // Taking inspiration from tokio, we create a `join_all` function
@@ -23,7 +165,8 @@ pub enum PollState<T> {
 pub fn join_all<F: Future>(futures: Vec<F>) -> JoinAll<F> {
     // initialse all futures with a boolean flag of false to indicate they
     // are not complete / not resolved.
-    let futures = futures.into_iter().map(|f| (false, f)).collect();
+    // NEW: each future is pinned on the heap, as they may not be Unpin.
+    let futures = futures.into_iter().map(|f| (false, Box::pin(f))).collect();
 
     JoinAll {
         futures,
@@ -32,39 +175,43 @@ pub fn join_all<F: Future>(futures: Vec<F>) -> JoinAll<F> {
 }
 
 pub struct JoinAll<F: Future> {
-    futures: Vec<(bool, F)>,
+    futures: Vec<(bool, Pin<Box<F>>)>,
     finished_count: usize,
 }
 
 // The JoinAll itself is a future and can be polled to completion
 impl<F: Future> Future for JoinAll<F> {
     type Output = Vec<<F as Future>::Output>;
+    type Waker = F::Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &Self::Waker) -> PollState<Self::Output> {
+        // JoinAll itself is Unpin, as the futures it joins on are pinned on the heap
+        let this = self.get_mut();
 
-    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         // store resolved values from all futures and return them
         // when all futures are all resolved.
         let mut resolved_values = vec![];
 
-        for (finished, future) in self.futures.iter_mut() {
+        for (finished, future) in this.futures.iter_mut() {
             if *finished {
                 // don't poll completed future
                 continue;
             }
 
             // NEW: pass waker when polling the futures we are joining on
-            match future.poll(waker) {
+            match future.as_mut().poll(waker) {
                 PollState::NotReady => continue,
                 PollState::Ready(value) => {
                     // mark future as resolved
                     *finished = true;
-                    self.finished_count += 1;
+                    this.finished_count += 1;
                     resolved_values.push(value);
                 }
             }
         }
 
         // if all futures are resolved, return Ready
-        if self.finished_count == self.futures.len() {
+        if this.finished_count == this.futures.len() {
             PollState::Ready(resolved_values)
         } else {
             PollState::NotReady
@@ -8,11 +8,11 @@ use std::fmt::Write;
 use std::thread::Builder;
 
 mod future;
-mod http;
-mod runtime;
+
+// the runtime, and its HTTP client, of the `reactor-executor` library
+use reactor_executor::coroutine::{pin as runtime, Http};
 
 use crate::future::{Future, PollState};
-use crate::http::Http;
 use crate::runtime::{Executor, Waker};
 
 pub fn main() {
@@ -25,145 +25,76 @@ pub fn main() {
     executor.block_on(future);
 }
 
-// NOTE: for this particular example, we generate main_corofy.rs
-// and copy it's contents to below section. We then make remainder
-// of the alterations within this file and do not use `main_async.rs`
-// or `main_corofy.rs` further.
-
-// =================================
-// We rewrite this:
-// =================================
-
-// coroutine fn async_main(){
-//     let mut buffer = String::from("\nBUFFER:\n----\n");
-//     let writer = &mut buffer;
-//
-//     println!("Program starting");
-//
-//     let txt = Http::get("/600/HelloAsyncAwait").wait;
-//     writeln!(writer, "{txt}").unwrap();
-//
-//     let txt = Http::get("/400/HelloAsyncAwait").wait;
-//     writeln!(writer, "{txt}").unwrap();
-//
-//     println!("{}", buffer);
-//
-
-// }
-
-// =================================
-// Into this:
-// =================================
-
-fn async_main() -> impl Future<Output = String> {
-    Coroutine0::new()
-}
+// NOTE: `#[coroutine]` keeps `buffer` on the coroutine's stack, and `writer`
+// as a raw pointer to it, as a `&mut String` into the stack would make the
+// coroutine a self-referential struct. The generated coroutine is !Unpin and
+// polled through a `Pin`, so it can't move once first polled and the pointer
+// stays valid.
+#[coroutine_macros::coroutine]
+fn async_main() {
+    let mut buffer: String = String::from("\nBUFFER:\n----\n");
+    let writer: &mut String = &mut buffer;
 
-/// NEW: We create a stack to store the buffer and &mut reference to it.
-#[derive(Default)]
-struct Stack0 {
-    buffer: Option<String>,
-    /// We can't use a &mut String, since we know that lifetime is tied
-    /// to the buffer itself, so Rust wouldn't allow that to work.
-    /// Rust does allow a field to whole a reference of &self: self-referential.
-    /// Rust has no way to determine the lifetime of such self references.
-    ///
-    writer: Option<*mut String>,
-}
+    println!("Program starting");
 
-enum State0 {
-    Start,
-    Wait1(Box<dyn Future<Output = String>>),
-    Wait2(Box<dyn Future<Output = String>>),
-    Resolved,
-}
+    let txt = Http::get("/600/HelloAsyncAwait").wait;
+    writeln!(writer, "{txt}").unwrap();
+
+    let txt = Http::get("/400/HelloAsyncAwait").wait;
+    writeln!(writer, "{txt}").unwrap();
 
-struct Coroutine0 {
-    state: State0,
-    stack: Stack0,
+    println!("{}", buffer);
 }
 
-impl Coroutine0 {
-    fn new() -> Self {
-        Self {
-            state: State0::Start,
-            stack: Stack0::default(),
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, pin::Pin, rc::Rc};
+
+    use super::*;
+    use crate::runtime::{spawn_with_token, CancellationToken};
+
+    /// Cancels its token when polled, and never resolves
+    struct CancelOnPoll(CancellationToken);
+
+    impl Future for CancelOnPoll {
+        type Output = String;
+        type Waker = Waker;
+
+        fn poll(self: Pin<&mut Self>, _: &Waker) -> PollState<String> {
+            self.0.cancel();
+            PollState::NotReady
         }
     }
-}
 
-impl Future for Coroutine0 {
-    type Output = String;
-
-    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
-        loop {
-            match self.state {
-                State0::Start => {
-                    // ---- Code you actually wrote ----
-                    // NEW: we create a buffer and store a &mut reference to it in `writer`. This
-                    // will be used across yield points to write into the buffer. However, we first
-                    // need to cast into a mutable raw pointer.
-                    self.stack.buffer = Some(String::from("\nBUFFER:\n----\n"));
-
-                    self.stack.writer = self.stack.buffer.as_mut().map(|v| v as *mut _);
-
-                    println!("Program starting");
-
-                    // ---------------------------------
-                    let fut1 = Box::new(Http::get("/600/HelloAsyncAwait"));
-                    self.state = State0::Wait1(fut1);
-                }
-
-                State0::Wait1(ref mut f1) => {
-                    match f1.poll(waker) {
-                        PollState::Ready(txt) => {
-                            // NEW: restore stack and cast *mut -> &mut.
-                            // The dereference of a raw pointer requires enclosing within and
-                            // unsafe block.
-                            let writer = unsafe { &mut *self.stack.writer.take().unwrap() };
-
-                            // ---- Code you actually wrote ----
-                            writeln!(writer, "{txt}").unwrap();
-
-                            // ---------------------------------
-                            let fut2 = Box::new(Http::get("/400/HelloAsyncAwait"));
-                            self.state = State0::Wait2(fut2);
-
-                            // NEW: store stack
-                            self.stack.writer = Some(writer);
-                        }
-                        PollState::NotReady => break PollState::NotReady,
-                    }
-                }
-
-                State0::Wait2(ref mut f2) => {
-                    match f2.poll(waker) {
-                        PollState::Ready(txt) => {
-                            // NEW: restore stack and cast *mut -> &mut.
-                            // The dereference of a raw pointer requires enclosing within and
-                            // unsafe block.
-                            let buffer = self.stack.buffer.as_ref().unwrap();
-                            let writer = unsafe { &mut *self.stack.writer.take().unwrap() };
-
-                            // ---- Code you actually wrote ----
-                            writeln!(writer, "{txt}").unwrap();
-
-                            println!("{}", buffer);
-
-                            // ---------------------------------
-                            self.state = State0::Resolved;
-
-                            // NEW: free resources from the stack
-                            let _ = self.stack.buffer.take();
-
-                            break PollState::Ready(String::new());
-                        }
-                        PollState::NotReady => break PollState::NotReady,
-                    }
-                }
-
-                State0::Resolved => panic!("Polled a resolved future"),
-            }
+    struct Ready;
+
+    impl Future for Ready {
+        type Output = String;
+        type Waker = Waker;
+
+        fn poll(self: Pin<&mut Self>, _: &Waker) -> PollState<String> {
+            PollState::Ready(String::new())
         }
     }
+
+    #[coroutine_macros::coroutine]
+    fn cancelled_while_waiting(token: CancellationToken, resumed: Rc<Cell<bool>>) {
+        CancelOnPoll(token.clone()).wait;
+        resumed.set(true);
+    }
+
+    #[test]
+    fn cancelled_coroutine_stops_at_the_next_transition() {
+        let token = CancellationToken::new();
+        let resumed = Rc::new(Cell::new(false));
+
+        spawn_with_token(
+            token.clone(),
+            cancelled_while_waiting(token.clone(), resumed.clone()),
+        );
+        Executor::new().block_on(Ready);
+
+        assert!(token.is_cancelled());
+        assert!(!resumed.get());
+    }
 }
@@ -1,17 +1,76 @@
 //! Run with following
 //! ```bash
-//! cargo run -p stackless-coroutine --bin a-runtime
+//! cargo run -p stackless-coroutine --bin b-reactor-executor
 //! ```
 #![allow(unused)]
 
+use std::{ops::RangeInclusive, thread::Builder};
+
 mod future;
-mod http;
-mod main_corofy;
-mod runtime;
 
-#[cfg(test)]
-mod main_async;
+// the runtime, and its HTTP client, of the `reactor-executor` library
+use reactor_executor::coroutine::{waker as runtime, Http};
+
+use crate::future::{Future, PollState};
+use crate::runtime::{Executor, Waker};
 
 fn main() {
-    main_corofy::run();
+    // initiaise the runtime
+    let mut executor = runtime::init();
+
+    let mut handles = vec![];
+
+    for i in 1..12 {
+        let name = format!("executor-{}", i);
+        let h = Builder::new()
+            .name(name)
+            .spawn(move || {
+                let mut executor = Executor::new();
+
+                // The main top-level future we start executor with
+                let future = async_main();
+                executor.block_on(future);
+            })
+            .unwrap();
+
+        handles.push(h)
+    }
+
+    // The main top-level future we start executor with
+    let future = async_main();
+
+    executor.block_on(future);
+
+    handles.into_iter().for_each(|h| h.join().unwrap());
+}
+
+// NOTE: `#[coroutine(waker)]` rewrites the functions below into state machines
+// polled with the `Waker` of the runtime. A wait may be within a loop or a
+// branch, which get states of their own, e.g. for the top of the loop. The
+// loop's iterator is kept on the coroutine's `Stack` along with `i`, so the
+// range is declared with its type first, as is every variable kept across a
+// wait.
+#[coroutine_macros::coroutine(waker)]
+fn request(i: usize) {
+    let delay = i * 1000;
+    let path = format!("/{0}/HelloWorld{0}", delay);
+    let txt = Http::get(&path).wait;
+    println!("{txt}");
+}
+
+#[coroutine_macros::coroutine(waker)]
+fn async_main() {
+    println!("Program starting");
+
+    let requests: RangeInclusive<usize> = 0..=5;
+    for i in requests {
+        if i % 2 == 0 {
+            let delay = i * 1000;
+            let path = format!("/{0}/HelloWorld{0}", delay);
+            let txt = Http::get(&path).wait;
+            println!("{txt}");
+        } else {
+            runtime::spawn(request(i));
+        }
+    }
 }
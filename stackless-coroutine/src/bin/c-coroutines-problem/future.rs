@@ -3,19 +3,144 @@
 
 use crate::runtime::Waker;
 
-/// Represents some operation that will complete in the future
-/// and return a value of type `Future::Output`.
-pub trait Future {
-    type Output;
-    // NEW: When we poll a future, we must now supply a Waker
-    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output>;
+/// The trait polled with a waker, shared with the other binaries and implemented with the
+/// [`Waker`] of the runtime, see `async-core`.
+pub use async_core::{waker::Future, PollState};
+
+/// NEW: Boxed future of any type with output `T`, e.g. for the states of a coroutine that each
+/// wait on a different future, or for a coroutine that waits on a call to itself.
+pub type BoxFuture<'a, T> = Box<dyn Future<Output = T, Waker = Waker> + 'a>;
+
+/// Adapters for every future, e.g. `.boxed()`, see [`BoxFuture`].
+pub trait FutureExt: Future {
+    fn boxed<'a>(self) -> BoxFuture<'a, Self::Output>
+    where
+        Self: Sized + Future<Waker = Waker> + 'a,
+    {
+        Box::new(self)
+    }
+
+    /// Transform the output with `f` once the future is ready.
+    fn map<T, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnOnce(Self::Output) -> T,
+    {
+        Map {
+            future: self,
+            f: Some(f),
+        }
+    }
+
+    /// Continue with the future returned by `f` once this one is ready, resolving to its output.
+    fn and_then<Fut, F>(self, f: F) -> AndThen<Self, Fut, F>
+    where
+        Self: Sized,
+        Fut: Future<Waker = Self::Waker>,
+        F: FnOnce(Self::Output) -> Fut,
+    {
+        AndThen::First(self, Some(f))
+    }
+
+    /// Look at the output with `f` once the future is ready, passing it on unchanged.
+    fn inspect<F>(self, f: F) -> Inspect<Self, F>
+    where
+        Self: Sized,
+        F: FnOnce(&Self::Output),
+    {
+        Inspect {
+            future: self,
+            f: Some(f),
+        }
+    }
+}
+
+impl<F: Future + ?Sized> FutureExt for F {}
+
+/// Future for [`FutureExt::map`].
+pub struct Map<Fut, F> {
+    future: Fut,
+    /// Taken once the future is ready
+    f: Option<F>,
+}
+
+impl<Fut, F, T> Future for Map<Fut, F>
+where
+    Fut: Future,
+    F: FnOnce(Fut::Output) -> T,
+{
+    type Output = T;
+    type Waker = Fut::Waker;
+
+    fn poll(&mut self, waker: &Self::Waker) -> PollState<Self::Output> {
+        match self.future.poll(waker) {
+            PollState::Ready(output) => {
+                let f = self.f.take().expect("Map polled after it was ready");
+                PollState::Ready(f(output))
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+/// Future for [`FutureExt::and_then`].
+pub enum AndThen<Fut1, Fut2, F> {
+    /// Waiting on the first future, `f` is taken once it is ready
+    First(Fut1, Option<F>),
+    /// Waiting on the future returned by `f`
+    Second(Fut2),
+}
+
+impl<Fut1, Fut2, F> Future for AndThen<Fut1, Fut2, F>
+where
+    Fut1: Future,
+    Fut2: Future<Waker = Fut1::Waker>,
+    F: FnOnce(Fut1::Output) -> Fut2,
+{
+    type Output = Fut2::Output;
+    type Waker = Fut1::Waker;
+
+    fn poll(&mut self, waker: &Self::Waker) -> PollState<Self::Output> {
+        loop {
+            match self {
+                AndThen::First(future, f) => match future.poll(waker) {
+                    PollState::Ready(output) => {
+                        let f = f.take().expect("AndThen polled after it was ready");
+                        *self = AndThen::Second(f(output));
+                    }
+                    PollState::NotReady => return PollState::NotReady,
+                },
+                AndThen::Second(future) => return future.poll(waker),
+            }
+        }
+    }
+}
+
+/// Future for [`FutureExt::inspect`].
+pub struct Inspect<Fut, F> {
+    future: Fut,
+    /// Taken once the future is ready
+    f: Option<F>,
 }
 
-/// PollState is an enum that represents the state of a future.
-/// It is either Ready or NotReady. The value returned when ready is of type T
-pub enum PollState<T> {
-    Ready(T),
-    NotReady,
+impl<Fut, F> Future for Inspect<Fut, F>
+where
+    Fut: Future,
+    F: FnOnce(&Fut::Output),
+{
+    type Output = Fut::Output;
+    type Waker = Fut::Waker;
+
+    fn poll(&mut self, waker: &Self::Waker) -> PollState<Self::Output> {
+        match self.future.poll(waker) {
+            PollState::Ready(output) => {
+                let f = self.f.take().expect("Inspect polled after it was ready");
+                f(&output);
+                PollState::Ready(output)
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
 }
 
 // Taking inspiration from tokio, we create a `join_all` function
@@ -39,8 +164,9 @@ pub struct JoinAll<F: Future> {
 // The JoinAll itself is a future and can be polled to completion
 impl<F: Future> Future for JoinAll<F> {
     type Output = Vec<<F as Future>::Output>;
+    type Waker = F::Waker;
 
-    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+    fn poll(&mut self, waker: &Self::Waker) -> PollState<Self::Output> {
         // store resolved values from all futures and return them
         // when all futures are all resolved.
         let mut resolved_values = vec![];
@@ -71,3 +197,83 @@ impl<F: Future> Future for JoinAll<F> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::runtime::Executor;
+
+    /// Coroutine counting down to 0 by waiting on a call to itself. Only possible boxed, as its
+    /// state would otherwise contain itself.
+    enum Countdown {
+        Start(usize),
+        Wait(usize, BoxFuture<'static, String>),
+        Resolved,
+    }
+
+    impl Future for Countdown {
+        type Output = String;
+        type Waker = Waker;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+            loop {
+                match std::mem::replace(self, Countdown::Resolved) {
+                    Countdown::Start(0) => return PollState::Ready("0".to_string()),
+                    Countdown::Start(n) => {
+                        *self = Countdown::Wait(n, Countdown::Start(n - 1).boxed());
+                    }
+                    Countdown::Wait(n, mut rest) => match rest.poll(waker) {
+                        PollState::Ready(rest) => return PollState::Ready(format!("{n} {rest}")),
+                        PollState::NotReady => {
+                            *self = Countdown::Wait(n, rest);
+                            return PollState::NotReady;
+                        }
+                    },
+                    Countdown::Resolved => panic!("Polled a resolved future"),
+                }
+            }
+        }
+    }
+
+    /// Resolves to the output of a boxed future, once checked against the expected one.
+    struct Expect(BoxFuture<'static, String>, &'static str);
+
+    impl Future for Expect {
+        type Output = String;
+        type Waker = Waker;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+            match self.0.poll(waker) {
+                PollState::Ready(output) => {
+                    assert_eq!(output, self.1);
+                    PollState::Ready(output)
+                }
+                PollState::NotReady => PollState::NotReady,
+            }
+        }
+    }
+
+    #[test]
+    fn recursive_coroutine_waits_on_itself_boxed() {
+        Executor::new().block_on(Expect(Countdown::Start(3).boxed(), "3 2 1 0"));
+    }
+
+    #[test]
+    fn combinators_transform_the_output() {
+        let mapped = Countdown::Start(1).map(|count| format!("{count}!"));
+        Executor::new().block_on(Expect(mapped.boxed(), "1 0!"));
+
+        let chained = Countdown::Start(1).and_then(|count| Countdown::Start(count.len()));
+        Executor::new().block_on(Expect(chained.boxed(), "3 2 1 0"));
+
+        let seen = Rc::new(RefCell::new(None));
+        let inspected = Countdown::Start(2).inspect({
+            let seen = seen.clone();
+            move |count| *seen.borrow_mut() = Some(count.clone())
+        });
+        Executor::new().block_on(Expect(inspected.boxed(), "2 1 0"));
+        assert_eq!(seen.borrow().as_deref(), Some("2 1 0"));
+    }
+}
@@ -8,11 +8,11 @@ use std::fmt::Write;
 use std::thread::Builder;
 
 mod future;
-mod http;
-mod runtime;
 
-use crate::future::{Future, PollState};
-use crate::http::Http;
+// the runtime, and its HTTP client, of the `reactor-executor` library
+use reactor_executor::coroutine::{waker as runtime, Http};
+
+use crate::future::{BoxFuture, Future, FutureExt, PollState};
 use crate::runtime::{Executor, Waker};
 
 pub fn main() {
@@ -55,7 +55,7 @@ pub fn main() {
 // Into this:
 // =================================
 
-fn async_main() -> impl Future<Output = String> {
+fn async_main() -> impl Future<Output = String, Waker = Waker> {
     Coroutine0::new()
 }
 
@@ -72,8 +72,8 @@ struct Stack0 {
 
 enum State0 {
     Start,
-    Wait1(Box<dyn Future<Output = String>>),
-    Wait2(Box<dyn Future<Output = String>>),
+    Wait1(BoxFuture<'static, String>),
+    Wait2(BoxFuture<'static, String>),
     Resolved,
 }
 
@@ -93,6 +93,7 @@ impl Coroutine0 {
 
 impl Future for Coroutine0 {
     type Output = String;
+    type Waker = Waker;
 
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
@@ -106,7 +107,7 @@ impl Future for Coroutine0 {
                     println!("Program starting");
 
                     // ---------------------------------
-                    let fut1 = Box::new(Http::get("/600/HelloAsyncAwait"));
+                    let fut1 = Http::get("/600/HelloAsyncAwait").boxed();
                     self.state = State0::Wait1(fut1);
                 }
 
@@ -119,7 +120,7 @@ impl Future for Coroutine0 {
                             writeln!(writer, "{txt}").unwrap();
 
                             // ---------------------------------
-                            let fut2 = Box::new(Http::get("/400/HelloAsyncAwait"));
+                            let fut2 = Http::get("/400/HelloAsyncAwait").boxed();
                             self.state = State0::Wait2(fut2);
 
                             self.stack.writer = Some(writer);
@@ -155,3 +156,48 @@ impl Future for Coroutine0 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{Executor, Waker};
+
+    /// Takes a pointer to its own `buffer` when first polled and writes through it when polled
+    /// again, like the `writer` on the stack of a coroutine.
+    struct SelfReferential {
+        buffer: String,
+        writer: Option<*mut String>,
+    }
+
+    impl Future for SelfReferential {
+        type Output = String;
+        type Waker = Waker;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+            match self.writer {
+                None => {
+                    self.writer = Some(&mut self.buffer);
+                    waker.wake();
+                    PollState::NotReady
+                }
+                Some(writer) => {
+                    // only still points at `buffer` if the future did not move since
+                    assert_eq!(
+                        writer, &mut self.buffer as *mut String,
+                        "future moved after it was first polled"
+                    );
+                    unsafe { (*writer).push_str("written") };
+                    PollState::Ready(self.buffer.clone())
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn future_does_not_move_after_first_poll() {
+        Executor::new().block_on(SelfReferential {
+            buffer: String::new(),
+            writer: None,
+        });
+    }
+}
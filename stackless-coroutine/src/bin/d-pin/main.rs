@@ -48,7 +48,7 @@ fn main() {
     println!("{}", x.as_ref().a);
     let mut x = x;
     // let a = x.as_mut();
-    let b = unsafe { x.get_unchecked_mut() };
+    let b = unsafe { x.as_mut().get_unchecked_mut() };
     *x.as_mut().b().unwrap() = 10;
     println!("{}", x.as_ref().a);
 }
@@ -1,20 +1,161 @@
 //! future related code
 #![allow(unused)]
-use std::pin::Pin;
+use std::{ops::DerefMut, pin::Pin};
 
-use crate::runtime::MyWaker;
+use crate::runtime::Waker;
 
-/// Represents some operation that will complete in the future
-/// and return a value of type `Future::Output`.
-pub trait Future {
-    type Output;
-    // NEW: When we poll a future, we must now supply a Waker
-    fn poll(self: Pin<&mut Self>, waker: &MyWaker) -> PollState<Self::Output>;
+/// The trait polled with a waker, shared with the other binaries and implemented with the
+/// [`Waker`] of the runtime, see `async-core`.
+pub use async_core::{pin::Future, PollState};
+
+/// NEW: Boxed future of any type with output `T`, e.g. for the states of a coroutine that each
+/// wait on a different future, or for a coroutine that waits on a call to itself. Pinned, as
+/// coroutines may hold references into themselves.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T, Waker = Waker> + 'a>>;
+
+/// Adapters for every future, e.g. `.boxed()`, see [`BoxFuture`].
+pub trait FutureExt: Future {
+    fn boxed<'a>(self) -> BoxFuture<'a, Self::Output>
+    where
+        Self: Sized + Future<Waker = Waker> + 'a,
+    {
+        Box::pin(self)
+    }
+
+    /// Transform the output with `f` once the future is ready.
+    fn map<T, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnOnce(Self::Output) -> T,
+    {
+        Map {
+            future: Box::pin(self),
+            f: Some(f),
+        }
+    }
+
+    /// Continue with the future returned by `f` once this one is ready, resolving to its output.
+    fn and_then<Fut, F>(self, f: F) -> AndThen<Self, Fut, F>
+    where
+        Self: Sized,
+        Fut: Future<Waker = Self::Waker>,
+        F: FnOnce(Self::Output) -> Fut,
+    {
+        AndThen::First(Box::pin(self), Some(f))
+    }
+
+    /// Look at the output with `f` once the future is ready, passing it on unchanged.
+    fn inspect<F>(self, f: F) -> Inspect<Self, F>
+    where
+        Self: Sized,
+        F: FnOnce(&Self::Output),
+    {
+        Inspect {
+            future: Box::pin(self),
+            f: Some(f),
+        }
+    }
+}
+
+impl<F: Future + ?Sized> FutureExt for F {}
+
+/// Future for [`FutureExt::map`].
+pub struct Map<Fut, F> {
+    future: Pin<Box<Fut>>,
+    /// Taken once the future is ready
+    f: Option<F>,
+}
+
+// Unpin whatever `F` is, as it is never pinned, and the futures are pinned on the heap
+impl<Fut, F> Unpin for Map<Fut, F> {}
+
+impl<Fut, F, T> Future for Map<Fut, F>
+where
+    Fut: Future,
+    F: FnOnce(Fut::Output) -> T,
+{
+    type Output = T;
+    type Waker = Fut::Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &Self::Waker) -> PollState<Self::Output> {
+        let this = self.get_mut();
+
+        match this.future.as_mut().poll(waker) {
+            PollState::Ready(output) => {
+                let f = this.f.take().expect("Map polled after it was ready");
+                PollState::Ready(f(output))
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
 }
 
-/// PollState is an enum that represents the state of a future.
-/// It is either Ready or NotReady. The value returned when ready is of type T
-pub enum PollState<T> {
-    Ready(T),
-    NotReady,
+/// Future for [`FutureExt::and_then`].
+pub enum AndThen<Fut1, Fut2, F> {
+    /// Waiting on the first future, `f` is taken once it is ready
+    First(Pin<Box<Fut1>>, Option<F>),
+    /// Waiting on the future returned by `f`
+    Second(Pin<Box<Fut2>>),
+}
+
+// Unpin whatever `F` is, as it is never pinned, and the futures are pinned on the heap
+impl<Fut1, Fut2, F> Unpin for AndThen<Fut1, Fut2, F> {}
+
+impl<Fut1, Fut2, F> Future for AndThen<Fut1, Fut2, F>
+where
+    Fut1: Future,
+    Fut2: Future<Waker = Fut1::Waker>,
+    F: FnOnce(Fut1::Output) -> Fut2,
+{
+    type Output = Fut2::Output;
+    type Waker = Fut1::Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &Self::Waker) -> PollState<Self::Output> {
+        let this = self.get_mut();
+
+        loop {
+            match this {
+                AndThen::First(future, f) => match future.as_mut().poll(waker) {
+                    PollState::Ready(output) => {
+                        let f = f.take().expect("AndThen polled after it was ready");
+                        *this = AndThen::Second(Box::pin(f(output)));
+                    }
+                    PollState::NotReady => return PollState::NotReady,
+                },
+                AndThen::Second(future) => return future.as_mut().poll(waker),
+            }
+        }
+    }
+}
+
+/// Future for [`FutureExt::inspect`].
+pub struct Inspect<Fut, F> {
+    future: Pin<Box<Fut>>,
+    /// Taken once the future is ready
+    f: Option<F>,
+}
+
+// Unpin whatever `F` is, as it is never pinned, and the futures are pinned on the heap
+impl<Fut, F> Unpin for Inspect<Fut, F> {}
+
+impl<Fut, F> Future for Inspect<Fut, F>
+where
+    Fut: Future,
+    F: FnOnce(&Fut::Output),
+{
+    type Output = Fut::Output;
+    type Waker = Fut::Waker;
+
+    fn poll(self: Pin<&mut Self>, waker: &Self::Waker) -> PollState<Self::Output> {
+        let this = self.get_mut();
+
+        match this.future.as_mut().poll(waker) {
+            PollState::Ready(output) => {
+                let f = this.f.take().expect("Inspect polled after it was ready");
+                f(&output);
+                PollState::Ready(output)
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
 }
@@ -9,12 +9,12 @@ use std::thread::Builder;
 use std::{marker::PhantomPinned, pin::Pin};
 
 mod future;
-mod http;
-mod runtime;
 
-use crate::future::{Future, PollState};
-use crate::http::Http;
-use crate::runtime::{Executor, MyWaker};
+// the runtime, and its HTTP client, of the `reactor-executor` library
+use reactor_executor::coroutine::{pin as runtime, Http};
+
+use crate::future::{BoxFuture, Future, FutureExt, PollState};
+use crate::runtime::{Executor, Waker};
 
 pub fn main() {
     // initialise the runtime
@@ -56,7 +56,7 @@ pub fn main() {
 // Into this:
 // =================================
 
-fn async_main() -> impl Future<Output = String> {
+fn async_main() -> impl Future<Output = String, Waker = Waker> {
     Coroutine0::new()
 }
 
@@ -73,8 +73,8 @@ struct Stack0 {
 
 enum State0 {
     Start,
-    Wait1(Pin<Box<dyn Future<Output = String>>>),
-    Wait2(Pin<Box<dyn Future<Output = String>>>),
+    Wait1(BoxFuture<'static, String>),
+    Wait2(BoxFuture<'static, String>),
     Resolved,
 }
 
@@ -97,8 +97,9 @@ impl Coroutine0 {
 
 impl Future for Coroutine0 {
     type Output = String;
+    type Waker = Waker;
 
-    fn poll(self: Pin<&mut Self>, waker: &MyWaker) -> PollState<Self::Output> {
+    fn poll(self: Pin<&mut Self>, waker: &Waker) -> PollState<Self::Output> {
         // NEW: Get a mutable reference to future inside of Pin.
         // replace all instances of `self` with `coroutine` within
         // this implementation block.
@@ -114,7 +115,7 @@ impl Future for Coroutine0 {
                     println!("Program starting");
 
                     // ---------------------------------
-                    let fut1 = Box::pin(Http::get("/600/HelloAsyncAwait"));
+                    let fut1 = Http::get("/600/HelloAsyncAwait").boxed();
                     coroutine.state = State0::Wait1(fut1);
                 }
 
@@ -127,7 +128,7 @@ impl Future for Coroutine0 {
                             writeln!(writer, "{txt}").unwrap();
 
                             // ---------------------------------
-                            let fut2 = Box::pin(Http::get("/400/HelloAsyncAwait"));
+                            let fut2 = Http::get("/400/HelloAsyncAwait").boxed();
                             coroutine.state = State0::Wait2(fut2);
 
                             coroutine.stack.writer = Some(writer);
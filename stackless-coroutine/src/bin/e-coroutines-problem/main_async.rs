@@ -6,8 +6,8 @@
 use std::thread::Builder;
 
 use crate::future::{Future, PollState};
-use crate::http::{self, Http};
 use crate::runtime::{self, Executor, Waker};
+use crate::Http;
 
 pub fn run() {
     // initialise the runtime